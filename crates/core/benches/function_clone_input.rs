@@ -0,0 +1,55 @@
+//! Compares the function node taking its input message with the one cloning it, see the `cloneInput` option.
+//!
+//! Run it with `cargo bench -p edgelink-core --features testing --bench function_clone_input`.
+
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use serde::Deserialize;
+use serde_json::json;
+
+use edgelink_core::runtime::engine::Engine;
+use edgelink_core::runtime::model::{ElementId, Msg};
+use edgelink_core::runtime::registry::RegistryBuilder;
+
+const MSG_COUNT: usize = 50;
+
+/// Builds the engine outside of the measurement, an engine can only run once
+fn build_engine(clone_input: bool) -> (Engine, Vec<(ElementId, Msg)>) {
+    let flows_json = json!([
+        {"id": "100", "type": "tab"},
+        {"id": "1", "type": "function", "z": "100", "cloneInput": clone_input, "wires": [["2"]],
+            "func": "return msg;"},
+        {"id": "2", "z": "100", "type": "test-once"},
+    ]);
+    let payload: Vec<_> = (0..20_000).map(|i| json!({"index": i, "name": format!("item-{}", i)})).collect();
+    let msgs_to_inject = (0..MSG_COUNT)
+        .map(|_| (ElementId::with_u64(1), Msg::deserialize(json!({"payload": payload})).unwrap()))
+        .collect();
+
+    let registry = RegistryBuilder::default().build().unwrap();
+    (Engine::with_json(&registry, flows_json, None).unwrap(), msgs_to_inject)
+}
+
+fn bench_clone_input(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap();
+    let mut group = c.benchmark_group("function_clone_input");
+    group.sample_size(10);
+    for clone_input in [true, false] {
+        group.bench_with_input(BenchmarkId::from_parameter(clone_input), &clone_input, |b, &clone_input| {
+            b.to_async(&rt).iter_batched(
+                || build_engine(clone_input),
+                |(engine, msgs_to_inject)| async move {
+                    let msgs =
+                        engine.run_once_with_inject(MSG_COUNT, Duration::from_secs(60), msgs_to_inject).await.unwrap();
+                    assert_eq!(msgs.len(), MSG_COUNT);
+                },
+                BatchSize::PerIteration,
+            );
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_clone_input);
+criterion_main!(benches);
@@ -2,6 +2,9 @@ pub mod runtime;
 pub mod text;
 pub mod utils;
 
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
 /// The `PluginRegistrar` is defined by the application and passed to `plugin_entry`. It's used
 /// for a plugin module to register itself with the application.
 pub trait PluginRegistrar {
@@ -1 +1,279 @@
-// .keepme
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use propex::PropexSegment;
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+use super::{EdgelinkError, ElementId, Variant};
+use crate::runtime::context::*;
+use crate::{ErrorContext, Result};
+
+inventory::submit! {
+    ProviderMetadata { type_: "localfs", factory: LocalFsContextStore::build }
+}
+
+const DEFAULT_DIR: &str = "context";
+const FILE_EXT: &str = "json";
+
+/// The context store persisted in the local file system, like the `localfilesystem` store of Node-RED, e.g.:
+///
+/// ```toml
+/// [runtime.context.stores]
+/// file = { provider = "localfs", dir = "/var/lib/edgelink/context" }
+/// ```
+///
+/// Every scope is a JSON file in the directory, it is loaded on the first access and written through on every change.
+struct LocalFsContextStore {
+    name: String,
+    dir: PathBuf,
+    scopes: Mutex<HashMap<String, Variant>>,
+}
+
+impl LocalFsContextStore {
+    fn build(name: String, options: Option<&ContextStoreOptions>) -> crate::Result<Box<dyn ContextStore>> {
+        let dir = match options.and_then(|x| x.options.get("dir")) {
+            Some(dir) => dir.clone().into_string()?,
+            None => DEFAULT_DIR.to_string(),
+        };
+        let this = LocalFsContextStore { name, dir: PathBuf::from(dir), scopes: Mutex::new(HashMap::new()) };
+        Ok(Box::new(this))
+    }
+
+    fn scope_file(&self, scope: &str) -> Result<PathBuf> {
+        // The scopes are the IDs of the flows elements or `global`, anything else must not escape the directory
+        if scope.is_empty() || !scope.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            return Err(EdgelinkError::BadArgument("scope")).with_context(|| format!("Bad context scope: '{}'", scope));
+        }
+        Ok(self.dir.join(format!("{}.{}", scope, FILE_EXT)))
+    }
+
+    /// Gets the cached scope, it will be loaded from its file if it has not been accessed.
+    async fn load<'a>(&self, scopes: &'a mut HashMap<String, Variant>, scope: &str) -> Result<&'a mut Variant> {
+        if !scopes.contains_key(scope) {
+            let value = read_scope_file(&self.scope_file(scope)?).await?;
+            scopes.insert(scope.to_string(), value);
+        }
+        Ok(scopes.get_mut(scope).unwrap())
+    }
+
+    async fn save(&self, scope: &str, value: &Variant) -> Result<()> {
+        let path = self.scope_file(scope)?;
+        let json = serde_json::to_vec(value)?;
+        // Writes to a temporary file first, so a crash never leaves a truncated scope file
+        let tmp_path = path.with_extension("json.tmp");
+        tokio::fs::create_dir_all(&self.dir).await?;
+        tokio::fs::write(&tmp_path, json).await?;
+        tokio::fs::rename(&tmp_path, &path)
+            .await
+            .with_context(|| format!("Failed to write the context file: {}", path.display()))?;
+        Ok(())
+    }
+
+    async fn remove_file(&self, path: &Path) -> Result<()> {
+        match tokio::fs::remove_file(path).await {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                Err(err).with_context(|| format!("Failed to remove the context file: {}", path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+async fn read_scope_file(path: &Path) -> Result<Variant> {
+    match tokio::fs::read(path).await {
+        Ok(json) => {
+            let jv: serde_json::Value =
+                serde_json::from_slice(&json).with_context(|| format!("Bad context file: {}", path.display()))?;
+            Ok(Variant::deserialize(jv)?)
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Variant::empty_object()),
+        Err(err) => Err(err).with_context(|| format!("Failed to read the context file: {}", path.display())),
+    }
+}
+
+#[async_trait]
+impl ContextStore for LocalFsContextStore {
+    async fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn open(&self) -> Result<()> {
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .with_context(|| format!("Failed to create the context directory: {}", self.dir.display()))?;
+        Ok(())
+    }
+
+    async fn close(&self) -> Result<()> {
+        // Every change has been written through
+        self.scopes.lock().await.clear();
+        Ok(())
+    }
+
+    async fn get_one(&self, scope: &str, path: &[PropexSegment]) -> Result<Variant> {
+        let mut scopes = self.scopes.lock().await;
+        let scope_map = self.load(&mut scopes, scope).await?;
+        scope_map.get_segs(path).cloned().ok_or_else(|| EdgelinkError::OutOfRange.into())
+    }
+
+    async fn get_many(&self, scope: &str, keys: &[&str]) -> Result<Vec<Variant>> {
+        let mut scopes = self.scopes.lock().await;
+        let scope_map = self.load(&mut scopes, scope).await?;
+        Ok(keys.iter().filter_map(|key| scope_map.get_nav(key, &[]).cloned()).collect())
+    }
+
+    async fn get_keys(&self, scope: &str) -> Result<Vec<String>> {
+        let mut scopes = self.scopes.lock().await;
+        let scope_map = self.load(&mut scopes, scope).await?;
+        Ok(scope_map.as_object().map(|x| x.keys().cloned().collect()).unwrap_or_default())
+    }
+
+    async fn set_one(&self, scope: &str, path: &[PropexSegment], value: Variant) -> Result<()> {
+        let mut scopes = self.scopes.lock().await;
+        let scope_map = self.load(&mut scopes, scope).await?;
+        scope_map.set_segs_property(path, value, true)?;
+        self.save(scope, scope_map).await
+    }
+
+    async fn set_many(&self, scope: &str, pairs: Vec<(String, Variant)>) -> Result<()> {
+        let mut scopes = self.scopes.lock().await;
+        let scope_map = self.load(&mut scopes, scope).await?;
+        let object = scope_map.as_object_mut().ok_or(EdgelinkError::OutOfRange)?;
+        for (key, value) in pairs {
+            let _ = object.insert(key, value);
+        }
+        self.save(scope, scope_map).await
+    }
+
+    async fn remove_one(&self, scope: &str, path: &[PropexSegment]) -> Result<Variant> {
+        let mut scopes = self.scopes.lock().await;
+        let scope_map = self.load(&mut scopes, scope).await?;
+        let object = scope_map.as_object_mut().ok_or(EdgelinkError::OutOfRange)?;
+        let removed = object.remove_segs_property(path).ok_or(EdgelinkError::OutOfRange)?;
+        self.save(scope, scope_map).await?;
+        Ok(removed)
+    }
+
+    async fn add_to_set(&self, scope: &str, path: &[PropexSegment], value: Variant) -> Result<bool> {
+        let mut scopes = self.scopes.lock().await;
+        let scope_map = self.load(&mut scopes, scope).await?;
+        let added = if let Some(set) = scope_map.get_segs_mut(path) {
+            set_insert(set, value)?
+        } else {
+            scope_map.set_segs_property(path, Variant::Array(vec![value]), true)?;
+            true
+        };
+        if added {
+            self.save(scope, scope_map).await?;
+        }
+        Ok(added)
+    }
+
+    async fn remove_from_set(&self, scope: &str, path: &[PropexSegment], value: &Variant) -> Result<bool> {
+        let mut scopes = self.scopes.lock().await;
+        let scope_map = self.load(&mut scopes, scope).await?;
+        let removed = match scope_map.get_segs_mut(path) {
+            Some(set) => set_remove(set, value)?,
+            None => false,
+        };
+        if removed {
+            self.save(scope, scope_map).await?;
+        }
+        Ok(removed)
+    }
+
+    async fn delete(&self, scope: &str) -> Result<()> {
+        let mut scopes = self.scopes.lock().await;
+        scopes.remove(scope);
+        self.remove_file(&self.scope_file(scope)?).await
+    }
+
+    async fn clean(&self, active_nodes: &[ElementId]) -> Result<()> {
+        let mut scopes = self.scopes.lock().await;
+        // Keeps the global scope, it is not bound to any node
+        let is_active =
+            |scope: &str| scope == GLOBAL_CONTEXT_NAME || active_nodes.iter().any(|id| id.to_string() == scope);
+        scopes.retain(|scope, _| is_active(scope));
+
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().and_then(|x| x.to_str()) != Some(FILE_EXT) {
+                continue;
+            }
+            let is_stale = path.file_stem().and_then(|x| x.to_str()).is_some_and(|scope| !is_active(scope));
+            if is_stale {
+                self.remove_file(&path).await?;
+            }
+        }
+        Ok(())
+    }
+
+    fn is_async(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn build_store(dir: &Path) -> Box<dyn ContextStore> {
+        let options = ContextStoreOptions {
+            provider: "localfs".to_string(),
+            options: HashMap::from([("dir".to_string(), config::Value::from(dir.to_str().unwrap()))]),
+        };
+        LocalFsContextStore::build("file0".to_string(), Some(&options)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_it_should_persist_properties_across_instances() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = build_store(dir.path());
+        store.open().await.unwrap();
+        store.set_one("nodeX", &propex::parse("foo.bar").unwrap(), "test".into()).await.unwrap();
+        store.set_many("nodeX", vec![("baz".into(), 1.into())]).await.unwrap();
+        assert!(store.add_to_set("nodeX", &propex::parse("set").unwrap(), 2.into()).await.unwrap());
+        store.close().await.unwrap();
+
+        let store = build_store(dir.path());
+        store.open().await.unwrap();
+        assert_eq!(
+            store.get_one("nodeX", &propex::parse("foo").unwrap()).await.unwrap(),
+            json!({"bar": "test"}).into()
+        );
+        assert_eq!(store.get_many("nodeX", &["baz", "nope"]).await.unwrap(), vec![1.into()]);
+        assert!(store.is_in_set("nodeX", &propex::parse("set").unwrap(), &2.into()).await.unwrap());
+        assert_eq!(store.remove_one("nodeX", &propex::parse("foo.bar").unwrap()).await.unwrap(), "test".into());
+        assert!(store.get_one("nodeY", &propex::parse("foo").unwrap()).await.is_err());
+        assert!(store.get_one("../nodeX", &propex::parse("foo").unwrap()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_it_should_delete_and_clean_scope_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = build_store(dir.path());
+        let active = ElementId::with_u64(0x1);
+        let inactive = ElementId::with_u64(0x2);
+        store.set_one(&active.to_string(), &propex::parse("x").unwrap(), 1.into()).await.unwrap();
+        store.set_one(&inactive.to_string(), &propex::parse("x").unwrap(), 1.into()).await.unwrap();
+        store.set_one(GLOBAL_CONTEXT_NAME, &propex::parse("g").unwrap(), 1.into()).await.unwrap();
+
+        store.clean(&[active]).await.unwrap();
+        assert!(!dir.path().join(format!("{}.json", inactive)).exists());
+        assert!(dir.path().join(format!("{}.json", active)).exists());
+        assert!(store.get_keys(&inactive.to_string()).await.unwrap().is_empty());
+        assert_eq!(store.get_keys(GLOBAL_CONTEXT_NAME).await.unwrap(), vec!["g".to_string()]);
+
+        store.delete(&active.to_string()).await.unwrap();
+        assert!(!dir.path().join(format!("{}.json", active)).exists());
+        assert!(store.get_keys(&active.to_string()).await.unwrap().is_empty());
+    }
+}
@@ -100,20 +100,45 @@ impl ContextStore for MemoryContextStore {
         Err(EdgelinkError::OutOfRange.into())
     }
 
+    async fn add_to_set(&self, scope: &str, path: &[PropexSegment], value: Variant) -> Result<bool> {
+        let mut scopes = self.scopes.write().await;
+        let scope_map = scopes.entry(scope.to_string()).or_insert_with(Variant::empty_object);
+        if let Some(set) = scope_map.get_segs_mut(path) {
+            set_insert(set, value)
+        } else {
+            scope_map.set_segs_property(path, Variant::Array(vec![value]), true)?;
+            Ok(true)
+        }
+    }
+
+    async fn remove_from_set(&self, scope: &str, path: &[PropexSegment], value: &Variant) -> Result<bool> {
+        let mut scopes = self.scopes.write().await;
+        match scopes.get_mut(scope).and_then(|x| x.get_segs_mut(path)) {
+            Some(set) => set_remove(set, value),
+            None => Ok(false),
+        }
+    }
+
+    async fn is_in_set(&self, scope: &str, path: &[PropexSegment], value: &Variant) -> Result<bool> {
+        let scopes = self.scopes.read().await;
+        match scopes.get(scope).and_then(|x| x.get_segs(path)) {
+            Some(set) => set_contains(set, value),
+            None => Ok(false),
+        }
+    }
+
     async fn delete(&self, scope: &str) -> Result<()> {
         let mut scopes = self.scopes.write().await;
         scopes.remove(scope);
         Ok(())
     }
 
-    async fn clean(&self, _active_nodes: &[ElementId]) -> Result<()> {
-        /*
-        let mut items = self.items.write().await;
-        let scopes = active_nodes. scope.parse::<ElementId>();
-        items.retain(|scope, _| active_nodes.contains(&scope));
+    async fn clean(&self, active_nodes: &[ElementId]) -> Result<()> {
+        let mut scopes = self.scopes.write().await;
+        // Keeps the global scope, it is not bound to any node
+        scopes
+            .retain(|scope, _| scope == GLOBAL_CONTEXT_NAME || active_nodes.iter().any(|id| id.to_string() == *scope));
         Ok(())
-        */
-        todo!()
     }
 }
 
@@ -171,4 +196,66 @@ mod tests {
         assert_eq!(context.get_one("nodeX", &propex::parse("foo").unwrap()).await.unwrap(), "testX".into());
         assert_eq!(context.get_one("nodeY", &propex::parse("foo").unwrap()).await.unwrap(), "testY".into());
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_set_operations_should_be_atomic() {
+        let context: std::sync::Arc<dyn crate::runtime::context::ContextStore> =
+            MemoryContextStore::build("memory0".to_string(), None).unwrap().into();
+
+        let mut tasks = tokio::task::JoinSet::new();
+        for i in 0..100 {
+            let context = context.clone();
+            tasks.spawn(async move {
+                let path = propex::parse("foo.set").unwrap();
+                // Half of the tasks are trying to add the same value
+                let value = if i % 2 == 0 { Variant::from("same") } else { Variant::from(i) };
+                context.add_to_set("nodeX", &path, value).await.unwrap()
+            });
+        }
+        let mut added = 0;
+        while let Some(res) = tasks.join_next().await {
+            if res.unwrap() {
+                added += 1;
+            }
+        }
+        assert_eq!(added, 51);
+
+        let path = propex::parse("foo.set").unwrap();
+        let set = context.get_one("nodeX", &path).await.unwrap();
+        assert_eq!(set.as_array().unwrap().len(), 51);
+        assert!(context.is_in_set("nodeX", &path, &"same".into()).await.unwrap());
+
+        let mut tasks = tokio::task::JoinSet::new();
+        for _ in 0..10 {
+            let context = context.clone();
+            tasks.spawn(async move {
+                let path = propex::parse("foo.set").unwrap();
+                context.remove_from_set("nodeX", &path, &"same".into()).await.unwrap()
+            });
+        }
+        let mut removed = 0;
+        while let Some(res) = tasks.join_next().await {
+            if res.unwrap() {
+                removed += 1;
+            }
+        }
+        assert_eq!(removed, 1);
+        assert!(!context.is_in_set("nodeX", &path, &"same".into()).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_it_should_clean_inactive_scopes() {
+        let context = MemoryContextStore::build("memory0".to_string(), None).unwrap();
+        let active = ElementId::with_u64(0x1);
+        let inactive = ElementId::with_u64(0x2);
+        for scope in [active.to_string(), inactive.to_string(), "global".to_string(), "nodeX".to_string()] {
+            context.set_one(&scope, &propex::parse("x").unwrap(), 1.into()).await.unwrap();
+        }
+
+        context.clean(&[active]).await.unwrap();
+        assert_eq!(context.get_keys(&active.to_string()).await.unwrap(), vec!["x".to_string()]);
+        assert_eq!(context.get_keys("global").await.unwrap(), vec!["x".to_string()]);
+        assert!(context.get_keys(&inactive.to_string()).await.is_err());
+        assert!(context.get_keys("nodeX").await.is_err());
+    }
 } // tests
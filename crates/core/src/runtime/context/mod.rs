@@ -5,6 +5,7 @@ use std::{
 
 use async_trait::async_trait;
 use dashmap::DashMap;
+use itertools::Itertools;
 use nom::Parser;
 use propex::PropexSegment;
 use serde;
@@ -15,10 +16,19 @@ use runtime::model::*;
 mod localfs;
 mod memory;
 
+#[cfg(feature = "redis")]
+mod redis;
+
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
 pub const GLOBAL_CONTEXT_NAME: &str = "global";
 pub const DEFAULT_STORE_NAME: &str = "default";
 pub const DEFAULT_STORE_NAME_ALIAS: &str = "_";
 
+/// How long to wait for the operations in progress to release a replaced store before closing it
+const STORE_DRAIN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
 type StoreFactoryFn = fn(name: String, options: Option<&ContextStoreOptions>) -> crate::Result<Box<dyn ContextStore>>;
 
 #[derive(Debug, Clone, Copy)]
@@ -29,13 +39,57 @@ pub struct ProviderMetadata {
 
 inventory::collect!(ProviderMetadata);
 
-#[derive(Debug, Clone, serde:: Deserialize)]
+#[derive(Debug, Clone, PartialEq, serde:: Deserialize)]
 pub struct ContextStorageSettings {
     pub default: String,
     pub stores: HashMap<String, ContextStoreOptions>,
+
+    /// Optional per-scope default stores, falls back to `default` when absent.
+    #[serde(default)]
+    pub scopes: ContextScopeDefaults,
+}
+
+/// The default store names for each kind of scope, e.g.:
+///
+/// ```toml
+/// [runtime.context.scopes]
+/// global = "file"
+/// node = "memory"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize)]
+pub struct ContextScopeDefaults {
+    pub global: Option<String>,
+    pub flow: Option<String>,
+    pub node: Option<String>,
+}
+
+impl ContextScopeDefaults {
+    pub fn get(&self, kind: ContextScope) -> Option<&str> {
+        match kind {
+            ContextScope::Global => self.global.as_deref(),
+            ContextScope::Flow => self.flow.as_deref(),
+            ContextScope::Node => self.node.as_deref(),
+        }
+    }
+
+    pub fn set(&mut self, kind: ContextScope, store: Option<String>) {
+        match kind {
+            ContextScope::Global => self.global = store,
+            ContextScope::Flow => self.flow = store,
+            ContextScope::Node => self.node = store,
+        }
+    }
+}
+
+/// The kind of the flows element that a context bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContextScope {
+    Global,
+    Flow,
+    Node,
 }
 
-#[derive(Debug, Clone, serde::Deserialize)]
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
 pub struct ContextStoreOptions {
     pub provider: String,
 
@@ -49,6 +103,40 @@ pub struct ContextKey<'a> {
     pub key: &'a str,
 }
 
+/// An owned `ContextKey`, parsed once and reused, e.g. by the rules of a node instead of parsing the key for every
+/// message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContextKeyRef {
+    pub store: Option<String>,
+    pub key: String,
+}
+
+impl ContextKeyRef {
+    /// Parses the key in the form of `#:(store)::key` or `key`, see `evaluate_key()`.
+    pub fn parse(key: &str) -> crate::Result<Self> {
+        Ok(evaluate_key(key)?.into())
+    }
+
+    pub fn as_key(&self) -> ContextKey<'_> {
+        ContextKey { store: self.store.as_deref(), key: &self.key }
+    }
+}
+
+impl From<ContextKey<'_>> for ContextKeyRef {
+    fn from(value: ContextKey<'_>) -> Self {
+        Self { store: value.store.map(|x| x.to_string()), key: value.key.to_string() }
+    }
+}
+
+impl std::fmt::Display for ContextKeyRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.store {
+            Some(store) => write!(f, "#:({})::{}", store, self.key),
+            None => write!(f, "{}", self.key),
+        }
+    }
+}
+
 /// The API trait for a context storage plug-in
 #[async_trait]
 pub trait ContextStore: Send + Sync {
@@ -66,8 +154,61 @@ pub trait ContextStore: Send + Sync {
 
     async fn remove_one(&self, scope: &str, path: &[PropexSegment]) -> Result<Variant>;
 
+    /// Adds the value into the array at the path if it is not contained, the array will be created if it is missing.
+    /// Returns `true` if the value was added.
+    ///
+    /// The membership is checked by scanning the array, so the set operations take O(n) time and are meant for small
+    /// sets, e.g. the IDs of the online devices.
+    ///
+    /// The default implementation is not atomic, stores should override it if they can do better.
+    async fn add_to_set(&self, scope: &str, path: &[PropexSegment], value: Variant) -> Result<bool> {
+        let mut set = match self.get_one(scope, path).await {
+            Ok(set) => set,
+            Err(e) if is_absent_error(&e) => Variant::empty_array(),
+            Err(e) => return Err(e),
+        };
+        let added = set_insert(&mut set, value)?;
+        if added {
+            self.set_one(scope, path, set).await?;
+        }
+        Ok(added)
+    }
+
+    /// Removes the value from the array at the path, returns `true` if the value was removed.
+    ///
+    /// The default implementation is not atomic, stores should override it if they can do better.
+    async fn remove_from_set(&self, scope: &str, path: &[PropexSegment], value: &Variant) -> Result<bool> {
+        let mut set = match self.get_one(scope, path).await {
+            Ok(set) => set,
+            Err(e) if is_absent_error(&e) => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        let removed = set_remove(&mut set, value)?;
+        if removed {
+            self.set_one(scope, path, set).await?;
+        }
+        Ok(removed)
+    }
+
+    async fn is_in_set(&self, scope: &str, path: &[PropexSegment], value: &Variant) -> Result<bool> {
+        match self.get_one(scope, path).await {
+            Ok(set) => set_contains(&set, value),
+            Err(e) if is_absent_error(&e) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
     async fn delete(&self, scope: &str) -> Result<()>;
+
+    /// Removes the scopes of the nodes and flows no longer active, the global scope is always kept.
     async fn clean(&self, active_nodes: &[ElementId]) -> Result<()>;
+
+    /// Returns `true` if the store is too slow to be accessed synchronously, e.g. a file or database backed store.
+    ///
+    /// The `function` node returns Promises instead of blocking the JS event loop for such stores.
+    fn is_async(&self) -> bool {
+        false
+    }
 }
 
 /// A context instance, allowed to bind to a flows element
@@ -76,37 +217,72 @@ pub struct Context {
     pub parent: Option<Weak<Context>>,
     pub manager: Weak<ContextManager>,
     pub scope: String,
+    pub kind: ContextScope,
 }
 
 pub type ContextStoreHandle = Arc<dyn ContextStore>;
 
 pub struct ContextManager {
+    store_set: std::sync::RwLock<ContextStoreSet>,
+    contexts: DashMap<String, Arc<Context>>,
+}
+
+/// The stores of a `ContextManager` and the settings creating them, replaced as a whole by `reload_stores()`.
+struct ContextStoreSet {
+    settings: ContextStorageSettings,
     default_store: ContextStoreHandle,
+    scope_stores: HashMap<ContextScope, ContextStoreHandle>,
     stores: HashMap<String, ContextStoreHandle>,
-    contexts: DashMap<String, Arc<Context>>,
 }
 
 pub struct ContextManagerBuilder {
     stores: HashMap<String, ContextStoreHandle>,
+    options: HashMap<String, ContextStoreOptions>,
     default_store: String,
-    settings: Option<ContextStorageSettings>,
+    scope_defaults: ContextScopeDefaults,
 }
 
 impl Context {
     pub async fn get_one(&self, storage: Option<&str>, key: &str, eval_env: &[PropexEnv<'_>]) -> Option<Variant> {
         let manager = self.manager.upgrade()?;
-        let store =
-            if let Some(storage) = storage { manager.get_context_store(storage)? } else { manager.get_default_store() };
+        let store = if let Some(storage) = storage {
+            manager.get_context_store(storage)?
+        } else {
+            manager.get_scope_default_store(self.kind)
+        };
         // TODO FIXME change it to fixed length stack-allocated string
         let mut path = propex::parse(key).ok()?;
         expand_propex_segments(&mut path, eval_env).ok()?;
         store.get_one(&self.scope, &path).await.ok()
     }
 
+    /// Gets the value like `get_one()`, but only the absent value is `None`, the failures of the store are errors.
+    pub async fn try_get_one(
+        &self,
+        storage: Option<&str>,
+        key: &str,
+        eval_env: &[PropexEnv<'_>],
+    ) -> Result<Option<Variant>> {
+        let store = self.resolve_store(storage)?;
+        let mut path = propex::parse(key)?;
+        let got = match expand_propex_segments(&mut path, eval_env) {
+            Ok(()) => store.get_one(&self.scope, &path).await,
+            Err(e) => Err(e),
+        };
+        match got {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if is_absent_error(&e) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
     pub async fn keys(&self, store: Option<&str>) -> Option<Vec<String>> {
         let manager = self.manager.upgrade()?;
-        let store =
-            if let Some(storage) = store { manager.get_context_store(storage)? } else { manager.get_default_store() };
+        let store = if let Some(storage) = store {
+            manager.get_context_store(storage)?
+        } else {
+            manager.get_scope_default_store(self.kind)
+        };
         store.get_keys(&self.scope).await.ok()
     }
 
@@ -124,7 +300,7 @@ impl Context {
                 .ok_or(EdgelinkError::BadArgument("storage"))
                 .with_context(|| format!("Cannot found the storage: '{}'", storage))?
         } else {
-            manager.get_default_store()
+            manager.get_scope_default_store(self.kind)
         };
         let mut path = propex::parse(key)?;
         expand_propex_segments(&mut path, eval_env)?;
@@ -135,17 +311,95 @@ impl Context {
             Ok(())
         }
     }
+
+    pub async fn add_to_set(
+        &self,
+        storage: Option<&str>,
+        key: &str,
+        value: Variant,
+        eval_env: &[PropexEnv<'_>],
+    ) -> Result<bool> {
+        let store = self.resolve_store(storage)?;
+        let mut path = propex::parse(key)?;
+        expand_propex_segments(&mut path, eval_env)?;
+        store.add_to_set(&self.scope, &path, value).await
+    }
+
+    pub async fn remove_from_set(
+        &self,
+        storage: Option<&str>,
+        key: &str,
+        value: &Variant,
+        eval_env: &[PropexEnv<'_>],
+    ) -> Result<bool> {
+        let store = self.resolve_store(storage)?;
+        let mut path = propex::parse(key)?;
+        expand_propex_segments(&mut path, eval_env)?;
+        store.remove_from_set(&self.scope, &path, value).await
+    }
+
+    pub async fn is_in_set(
+        &self,
+        storage: Option<&str>,
+        key: &str,
+        value: &Variant,
+        eval_env: &[PropexEnv<'_>],
+    ) -> Result<bool> {
+        let store = self.resolve_store(storage)?;
+        let mut path = propex::parse(key)?;
+        expand_propex_segments(&mut path, eval_env)?;
+        store.is_in_set(&self.scope, &path, value).await
+    }
+
+    pub(crate) fn resolve_store(&self, storage: Option<&str>) -> Result<ContextStoreHandle> {
+        let manager =
+            self.manager.upgrade().ok_or(EdgelinkError::invalid_operation("The manager has been released"))?;
+        if let Some(storage) = storage {
+            manager
+                .get_context_store(storage)
+                .ok_or(EdgelinkError::BadArgument("storage"))
+                .with_context(|| format!("Cannot found the storage: '{}'", storage))
+        } else {
+            Ok(manager.get_scope_default_store(self.kind))
+        }
+    }
+}
+
+/// Tells if the error of `ContextStore::get_one()` means the property is absent rather than a failure of the store.
+fn is_absent_error(err: &anyhow::Error) -> bool {
+    matches!(err.downcast_ref::<EdgelinkError>(), Some(EdgelinkError::OutOfRange))
+}
+
+/// Inserts the value into the array if it is not contained, by a linear scan of the array.
+pub(crate) fn set_insert(set: &mut Variant, value: Variant) -> Result<bool> {
+    let arr = set.as_array_mut().ok_or(EdgelinkError::invalid_operation("The set must be an array"))?;
+    if arr.contains(&value) {
+        Ok(false)
+    } else {
+        arr.push(value);
+        Ok(true)
+    }
+}
+
+pub(crate) fn set_remove(set: &mut Variant, value: &Variant) -> Result<bool> {
+    let arr = set.as_array_mut().ok_or(EdgelinkError::invalid_operation("The set must be an array"))?;
+    let prev_len = arr.len();
+    arr.retain(|x| x != value);
+    Ok(arr.len() != prev_len)
+}
+
+pub(crate) fn set_contains(set: &Variant, value: &Variant) -> Result<bool> {
+    let arr = set.as_array().ok_or(EdgelinkError::invalid_operation("The set must be an array"))?;
+    Ok(arr.contains(value))
 }
 
 impl Default for ContextManager {
     fn default() -> Self {
-        let x = inventory::iter::<ProviderMetadata>;
-        let memory_metadata = x.into_iter().find(|x| x.type_ == "memory").unwrap();
-        let memory_store =
-            (memory_metadata.factory)("memory".into(), None).expect("Create memory storage cannot go wrong.");
-        let mut stores: HashMap<std::string::String, ContextStoreHandle> = HashMap::with_capacity(1);
-        stores.insert("memory".to_string(), Arc::from(memory_store));
-        Self { default_store: stores["memory"].clone(), contexts: DashMap::new(), stores }
+        let store_set = ContextManagerBuilder::new()
+            .load_default()
+            .build_store_set()
+            .expect("Create memory storage cannot go wrong.");
+        Self { store_set: std::sync::RwLock::new(store_set), contexts: DashMap::new() }
     }
 }
 
@@ -158,7 +412,12 @@ impl Default for ContextManagerBuilder {
 impl ContextManagerBuilder {
     pub fn new() -> Self {
         let stores = HashMap::with_capacity(inventory::iter::<ProviderMetadata>.into_iter().count());
-        Self { stores, default_store: "memory".into(), settings: None }
+        Self {
+            stores,
+            options: HashMap::new(),
+            default_store: "memory".into(),
+            scope_defaults: ContextScopeDefaults::default(),
+        }
     }
 
     pub fn load_default(&mut self) -> &mut Self {
@@ -167,11 +426,19 @@ impl ContextManagerBuilder {
             (memory_metadata.factory)("memory".into(), None).expect("Create memory storage cannot go wrong.");
         self.stores.clear();
         self.stores.insert("memory".to_string(), Arc::from(memory_store));
+        self.options.clear();
+        self.options
+            .insert("memory".to_string(), ContextStoreOptions { provider: "memory".into(), options: HashMap::new() });
+        self.default_store = "memory".into();
         self
     }
 
     pub fn with_config(&mut self, config: &config::Config) -> crate::Result<&mut Self> {
-        let settings: ContextStorageSettings = config.get("runtime.context")?;
+        let settings: ContextStorageSettings = match config.get("runtime.context") {
+            Ok(settings) => settings,
+            Err(config::ConfigError::NotFound(_)) => return Ok(self.load_default()),
+            Err(e) => return Err(e.into()),
+        };
         self.stores.clear();
         for (store_name, store_options) in settings.stores.iter() {
             log::debug!(
@@ -196,7 +463,22 @@ impl ContextManagerBuilder {
                 )
             });
         }
-        self.settings = Some(settings);
+        for kind in [ContextScope::Global, ContextScope::Flow, ContextScope::Node] {
+            if let Some(store_name) = settings.scopes.get(kind) {
+                if !settings.stores.contains_key(store_name) {
+                    use anyhow::Context;
+                    return Err(EdgelinkError::Configuration).with_context(|| {
+                        format!(
+                            "Cannot found the default context storage '{}' for scope {:?}, check your configuration file.",
+                            store_name, kind
+                        )
+                    });
+                }
+            }
+        }
+        self.options = settings.stores;
+        self.default_store = settings.default;
+        self.scope_defaults = settings.scopes;
         Ok(self)
     }
 
@@ -205,46 +487,205 @@ impl ContextManagerBuilder {
         self
     }
 
+    /// Sets the default store for the specified kind of scope, `None` to use the default store.
+    pub fn scope_default_store(&mut self, kind: ContextScope, store: Option<String>) -> &mut Self {
+        self.scope_defaults.set(kind, store);
+        self
+    }
+
     pub fn build(&self) -> crate::Result<Arc<ContextManager>> {
-        let cm = ContextManager {
-            default_store: self.stores[&self.default_store].clone(),
-            stores: self.stores.clone(),
-            contexts: DashMap::new(),
-        };
-        Ok(Arc::new(cm))
+        let store_set = self.build_store_set()?;
+        Ok(Arc::new(ContextManager { store_set: std::sync::RwLock::new(store_set), contexts: DashMap::new() }))
+    }
+
+    fn settings(&self) -> ContextStorageSettings {
+        ContextStorageSettings {
+            default: self.default_store.clone(),
+            stores: self.options.clone(),
+            scopes: self.scope_defaults.clone(),
+        }
+    }
+
+    fn build_store_set(&self) -> crate::Result<ContextStoreSet> {
+        let default_store = self
+            .stores
+            .get(&self.default_store)
+            .cloned()
+            .ok_or(EdgelinkError::Configuration)
+            .with_context(|| format!("Cannot found the default context storage '{}'", self.default_store))?;
+        let mut scope_stores = HashMap::with_capacity(3);
+        for kind in [ContextScope::Global, ContextScope::Flow, ContextScope::Node] {
+            if let Some(store_name) = self.scope_defaults.get(kind) {
+                let store =
+                    self.stores.get(store_name).cloned().ok_or(EdgelinkError::Configuration).with_context(|| {
+                        format!("Cannot found the default context storage '{}' for scope {:?}", store_name, kind)
+                    })?;
+                scope_stores.insert(kind, store);
+            }
+        }
+        Ok(ContextStoreSet { settings: self.settings(), default_store, scope_stores, stores: self.stores.clone() })
     }
 }
 
 impl ContextManager {
-    pub fn new_context(self: &Arc<Self>, parent: &Arc<Context>, scope: String) -> Arc<Context> {
+    /// Creates the context of the scope, or reuses the existing one with the same scope and parent,
+    /// so the nodes and flows keyed by the same IDs share their contexts across reloads.
+    pub fn new_context(self: &Arc<Self>, parent: &Arc<Context>, scope: String, kind: ContextScope) -> Arc<Context> {
+        if let Some(existed) = self.contexts.get(&scope) {
+            let same_parent =
+                existed.parent.as_ref().and_then(|x| x.upgrade()).is_some_and(|x| Arc::ptr_eq(&x, parent));
+            if same_parent && existed.kind == kind {
+                return existed.clone();
+            }
+        }
         let c = Arc::new(Context {
             parent: Some(Arc::downgrade(parent)),
             manager: Arc::downgrade(self),
             scope: scope.clone(),
+            kind,
         });
         self.contexts.insert(scope, c.clone());
         c
     }
 
     pub fn new_global_context(self: &Arc<Self>) -> Arc<Context> {
-        let c =
-            Arc::new(Context { parent: None, manager: Arc::downgrade(self), scope: GLOBAL_CONTEXT_NAME.to_string() });
+        let c = Arc::new(Context {
+            parent: None,
+            manager: Arc::downgrade(self),
+            scope: GLOBAL_CONTEXT_NAME.to_string(),
+            kind: ContextScope::Global,
+        });
         self.contexts.insert(GLOBAL_CONTEXT_NAME.to_string(), c.clone());
         c
     }
 
-    pub fn get_default_store(&self) -> &ContextStoreHandle {
-        &self.default_store
+    pub fn get_default_store(&self) -> ContextStoreHandle {
+        self.store_set.read().expect("`store_set` read lock").default_store.clone()
+    }
+
+    /// Gets the default store of the specified kind of scope, or the default store if it was not configured.
+    pub fn get_scope_default_store(&self, kind: ContextScope) -> ContextStoreHandle {
+        let store_set = self.store_set.read().expect("`store_set` read lock");
+        store_set.scope_stores.get(&kind).unwrap_or(&store_set.default_store).clone()
+    }
+
+    /// The current stores, the lock is released before the caller awaits on them.
+    fn stores(&self) -> HashMap<String, ContextStoreHandle> {
+        self.store_set.read().expect("`store_set` read lock").stores.clone()
+    }
+
+    /// Opens all configured stores, fails on the first store that cannot be opened.
+    pub async fn open_stores(&self) -> Result<()> {
+        for (name, store) in self.stores().iter() {
+            store.open().await.with_context(|| format!("Failed to open the context store '{}'", name))?;
+        }
+        Ok(())
+    }
+
+    pub async fn close_stores(&self) -> Result<()> {
+        for (name, store) in self.stores().iter() {
+            store.close().await.with_context(|| format!("Failed to close the context store '{}'", name))?;
+        }
+        Ok(())
+    }
+
+    /// Applies the `runtime.context` section of the configuration to the existing contexts, returns `None` if the
+    /// section is unchanged.
+    ///
+    /// The stores with unchanged options are kept along with their values. If `opened` is `true`, the new stores
+    /// are opened before replacing the old ones, and the returned `ReplacedStores` must be closed by the caller.
+    /// Nothing is changed if any new store fails to open.
+    pub async fn reload_stores(&self, config: &config::Config, opened: bool) -> Result<Option<ReplacedStores>> {
+        let mut builder = ContextManagerBuilder::new();
+        builder.with_config(config)?;
+        let (old_settings, old_stores) = {
+            let store_set = self.store_set.read().expect("`store_set` read lock");
+            (store_set.settings.clone(), store_set.stores.clone())
+        };
+        if builder.settings() == old_settings {
+            return Ok(None);
+        }
+
+        let mut new_stores = Vec::new();
+        for (name, store) in builder.stores.iter_mut() {
+            match old_stores.get(name) {
+                Some(old) if old_settings.stores.get(name) == builder.options.get(name) => *store = old.clone(),
+                _ => new_stores.push((name.clone(), store.clone())),
+            }
+        }
+        let new_set = builder.build_store_set()?;
+        drop(builder);
+
+        if opened {
+            for (i, (name, store)) in new_stores.iter().enumerate() {
+                if let Err(e) = store.open().await {
+                    for (_, opened_store) in new_stores[..i].iter() {
+                        let _ = opened_store.close().await;
+                    }
+                    return Err(e).with_context(|| format!("Failed to open the context store '{}'", name));
+                }
+            }
+        }
+        drop(new_stores);
+
+        let replaced: Vec<(String, ContextStoreHandle)> = old_stores
+            .into_iter()
+            .filter(|(name, old)| !new_set.stores.get(name).is_some_and(|x| Arc::ptr_eq(x, old)))
+            .collect();
+        *self.store_set.write().expect("`store_set` write lock") = new_set;
+        Ok(Some(ReplacedStores { stores: if opened { replaced } else { Vec::new() } }))
+    }
+
+    /// Counts the keys of every store in the scopes of the contexts created by the manager.
+    pub async fn keys_count(&self) -> Vec<(String, usize)> {
+        let scopes: Vec<String> = self.contexts.iter().map(|x| x.key().clone()).collect();
+        let stores = self.stores();
+        let mut counts = Vec::with_capacity(stores.len());
+        for (name, store) in stores.iter().sorted_by_key(|x| x.0) {
+            let mut count = 0;
+            for scope in scopes.iter() {
+                count += store.get_keys(scope).await.map(|x| x.len()).unwrap_or(0);
+            }
+            counts.push((name.clone(), count));
+        }
+        counts
     }
 
-    pub fn get_context_store<'a>(&'a self, store_name: &str) -> Option<&'a ContextStoreHandle> {
+    pub fn get_context_store(&self, store_name: &str) -> Option<ContextStoreHandle> {
+        let store_set = self.store_set.read().expect("`store_set` read lock");
         match store_name {
-            DEFAULT_STORE_NAME | DEFAULT_STORE_NAME_ALIAS | "" => Some(&self.default_store),
-            _ => self.stores.get(store_name),
+            DEFAULT_STORE_NAME | DEFAULT_STORE_NAME_ALIAS | "" => Some(store_set.default_store.clone()),
+            _ => store_set.stores.get(store_name).cloned(),
         }
     }
 }
 
+/// The opened stores replaced by `ContextManager::reload_stores()`, to close without blocking the engine.
+#[derive(Default)]
+pub struct ReplacedStores {
+    stores: Vec<(String, ContextStoreHandle)>,
+}
+
+impl ReplacedStores {
+    /// Closes the stores after the operations in progress released them, or the timeout elapsed.
+    pub async fn drain_and_close(self) {
+        for (name, store) in self.stores.into_iter() {
+            drain_and_close_store(&name, store).await;
+        }
+    }
+}
+
+/// Closes the replaced store after the operations in progress released it, or the timeout elapsed.
+async fn drain_and_close_store(name: &str, store: ContextStoreHandle) {
+    let deadline = tokio::time::Instant::now() + STORE_DRAIN_TIMEOUT;
+    while Arc::strong_count(&store) > 1 && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    if let Err(e) = store.close().await {
+        log::warn!("Failed to close the replaced context store '{}': {}", name, e);
+    }
+}
+
 fn parse_store_expr(input: &str) -> nom::IResult<&str, &str, nom::error::VerboseError<&str>> {
     use crate::text::nom_parsers::*;
     use nom::{
@@ -270,6 +711,12 @@ fn context_store_parser(input: &str) -> nom::IResult<&str, ContextKey, nom::erro
     Ok((input, ContextKey { store, key }))
 }
 
+#[cfg(test)]
+thread_local! {
+    /// How many times `evaluate_key()` was called in the current thread, to check that the keys are parsed only once
+    pub(crate) static KEY_PARSE_COUNT: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
 /// Parses a context property string, as generated by the TypedInput, to extract
 /// the store name if present.
 ///
@@ -283,6 +730,9 @@ fn context_store_parser(input: &str) -> nom::IResult<&str, ContextKey, nom::erro
 /// assert_eq!("foo.bar", res.key);
 /// ```
 pub fn evaluate_key(key: &str) -> crate::Result<ContextKey<'_>> {
+    #[cfg(test)]
+    KEY_PARSE_COUNT.with(|x| x.set(x.get() + 1));
+
     match context_store_parser(key) {
         Ok(res) => Ok(res.1),
         Err(e) => Err(EdgelinkError::BadArgument("key")).with_context(|| format!("Can not parse the key: '{0}'", e)),
@@ -306,6 +756,15 @@ mod tests {
         let res = evaluate_key("foo.bar").unwrap();
         assert_eq!(None, res.store);
         assert_eq!("foo.bar", res.key);
+
+        let key = ContextKeyRef::parse("#:(file1)::foo.bar").unwrap();
+        assert_eq!(key.as_key().store, Some("file1"));
+        assert_eq!(key.as_key().key, "foo.bar");
+        assert_eq!(key.to_string(), "#:(file1)::foo.bar");
+
+        let key = ContextKeyRef::parse("foo[msg.topic]").unwrap();
+        assert_eq!(key, ContextKeyRef { store: None, key: "foo[msg.topic]".to_string() });
+        assert_eq!(key.to_string(), "foo[msg.topic]");
     }
 
     #[tokio::test]
@@ -317,4 +776,60 @@ mod tests {
         let foo = global.get_one(None, "foo", &[]).await.unwrap();
         assert_eq!(foo, "bar".into());
     }
+
+    #[tokio::test]
+    async fn test_context_set_helpers() {
+        let ctxman = ContextManagerBuilder::new().load_default().build().unwrap();
+        let global = ctxman.new_global_context();
+
+        assert!(!global.is_in_set(None, "devices", &"a".into(), &[]).await.unwrap());
+        assert!(global.add_to_set(None, "devices", "a".into(), &[]).await.unwrap());
+        assert!(!global.add_to_set(None, "devices", "a".into(), &[]).await.unwrap());
+        assert!(global.add_to_set(None, "devices", "b".into(), &[]).await.unwrap());
+        assert!(global.is_in_set(None, "devices", &"a".into(), &[]).await.unwrap());
+
+        assert!(global.remove_from_set(None, "devices", &"a".into(), &[]).await.unwrap());
+        assert!(!global.remove_from_set(None, "devices", &"a".into(), &[]).await.unwrap());
+        assert_eq!(global.get_one(None, "devices", &[]).await.unwrap(), Variant::Array(vec!["b".into()]));
+
+        global.set_one(None, "scalar", Some(Variant::from(1)), &[]).await.unwrap();
+        assert!(global.add_to_set(None, "scalar", "a".into(), &[]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_scope_default_stores_should_be_separated() {
+        let cfg = config::Config::builder()
+            .add_source(config::File::from_str(
+                r#"
+                [runtime.context]
+                default = "memory0"
+
+                [runtime.context.stores]
+                memory0 = { provider = "memory" }
+                memory1 = { provider = "memory" }
+
+                [runtime.context.scopes]
+                global = "memory1"
+                "#,
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap();
+        let ctxman = ContextManagerBuilder::new().with_config(&cfg).unwrap().build().unwrap();
+        let global = ctxman.new_global_context();
+        let flow = ctxman.new_context(&global, "flow1".into(), ContextScope::Flow);
+        let node = ctxman.new_context(&flow, "node1".into(), ContextScope::Node);
+
+        global.set_one(None, "foo", Some(Variant::from("global")), &[]).await.unwrap();
+        node.set_one(None, "foo", Some(Variant::from("node")), &[]).await.unwrap();
+
+        // The global scope goes to `memory1`, the others use the default store `memory0`
+        assert_eq!(global.get_one(Some("memory1"), "foo", &[]).await.unwrap(), "global".into());
+        assert!(global.get_one(Some("memory0"), "foo", &[]).await.is_none());
+        assert_eq!(node.get_one(Some("memory0"), "foo", &[]).await.unwrap(), "node".into());
+        assert!(node.get_one(Some("memory1"), "foo", &[]).await.is_none());
+
+        assert!(Arc::ptr_eq(&ctxman.get_scope_default_store(ContextScope::Flow), &ctxman.get_default_store()));
+        assert_eq!(ctxman.get_scope_default_store(ContextScope::Global).name().await, "memory1");
+    }
 }
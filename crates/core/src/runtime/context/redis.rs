@@ -0,0 +1,502 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use ::redis::aio::ConnectionManager;
+use ::redis::AsyncCommands;
+use async_trait::async_trait;
+use propex::PropexSegment;
+use serde::Deserialize;
+use tokio::sync::OnceCell;
+
+use super::{EdgelinkError, ElementId, Variant};
+use crate::runtime::context::*;
+use crate::{ErrorContext, Result};
+
+inventory::submit! {
+    ProviderMetadata { type_: "redis", factory: RedisContextStore::build }
+}
+
+const DEFAULT_URL: &str = "redis://127.0.0.1:6379/";
+const DEFAULT_KEY_PREFIX: &str = "edgelink";
+
+/// The options of the redis store, e.g.:
+///
+/// ```toml
+/// [runtime.context.stores]
+/// redis = { provider = "redis", url = "redis://127.0.0.1:6379/0", pool_size = 4, tls = true }
+/// ```
+#[derive(Debug, Clone)]
+struct RedisStoreOptions {
+    url: String,
+    /// The count of the multiplexed connections, the commands are distributed over them in turn
+    pool_size: usize,
+    /// Connects by `rediss://`, the scheme of the `url` is replaced
+    tls: bool,
+    /// Skips the verification of the server certificate, only for testing
+    tls_insecure: bool,
+    key_prefix: String,
+}
+
+impl RedisStoreOptions {
+    fn parse(options: Option<&ContextStoreOptions>) -> Result<Self> {
+        let get = |name: &str| options.and_then(|x| x.options.get(name)).cloned();
+        let url = get("url").map(|x| x.into_string()).transpose()?.unwrap_or_else(|| DEFAULT_URL.to_string());
+        let pool_size = get("pool_size").map(|x| x.into_int()).transpose()?.unwrap_or(1);
+        if pool_size <= 0 {
+            return Err(EdgelinkError::BadArgument("pool_size"))
+                .with_context(|| format!("The `pool_size` of the redis store must be positive, got: {}", pool_size));
+        }
+        Ok(RedisStoreOptions {
+            url,
+            pool_size: pool_size as usize,
+            tls: get("tls").map(|x| x.into_bool()).transpose()?.unwrap_or(false),
+            tls_insecure: get("tls_insecure").map(|x| x.into_bool()).transpose()?.unwrap_or(false),
+            key_prefix: get("key_prefix")
+                .map(|x| x.into_string())
+                .transpose()?
+                .unwrap_or_else(|| DEFAULT_KEY_PREFIX.to_string()),
+        })
+    }
+
+    fn connection_url(&self) -> String {
+        let mut url = self.url.clone();
+        if self.tls {
+            if let Some(rest) = url.strip_prefix("redis://") {
+                url = format!("rediss://{}", rest);
+            }
+        }
+        if self.tls_insecure && !url.ends_with("#insecure") {
+            url.push_str("#insecure");
+        }
+        url
+    }
+}
+
+/// The context store persisted in redis.
+///
+/// A value is stored as JSON under the key `edgelink:<scope>:<path>`, the path is the propex expression the value was
+/// set by, e.g. `edgelink:<scope>:foo.bar` for `foo.bar`. The arrays are stored as a whole under the path of their
+/// owner, and a stored path never has another stored path as its ancestor: setting a path replaces the stored values
+/// under it, and setting the property of a stored value updates that value. The operations are not atomic, so the
+/// concurrent writers of the same value may overwrite each other.
+struct RedisContextStore {
+    name: String,
+    options: RedisStoreOptions,
+    client: ::redis::Client,
+    connections: OnceCell<Vec<ConnectionManager>>,
+    next_connection: AtomicUsize,
+}
+
+/// The values loaded for a path, the root object has them at their own paths.
+struct Loaded {
+    root: Variant,
+    /// The length of the path of the key the values are written back to
+    stored_len: usize,
+    /// The keys of the values stored under that path, replaced when the values are written back
+    descendant_keys: Vec<String>,
+}
+
+impl RedisContextStore {
+    fn build(name: String, options: Option<&ContextStoreOptions>) -> crate::Result<Box<dyn ContextStore>> {
+        Ok(Box::new(Self::new(name, options)?))
+    }
+
+    fn new(name: String, options: Option<&ContextStoreOptions>) -> crate::Result<Self> {
+        let options = RedisStoreOptions::parse(options)?;
+        let client = ::redis::Client::open(options.connection_url())
+            .with_context(|| format!("Bad url of the redis context store '{}': {}", name, options.url))?;
+        Ok(RedisContextStore {
+            name,
+            options,
+            client,
+            connections: OnceCell::new(),
+            next_connection: AtomicUsize::new(0),
+        })
+    }
+
+    /// Gets a connection of the pool in turn, the pool is connected on the first use.
+    async fn connection(&self) -> Result<ConnectionManager> {
+        let connections = self
+            .connections
+            .get_or_try_init(|| async {
+                let mut connections = Vec::with_capacity(self.options.pool_size);
+                for _ in 0..self.options.pool_size {
+                    connections.push(ConnectionManager::new(self.client.clone()).await?);
+                }
+                Ok::<_, ::redis::RedisError>(connections)
+            })
+            .await
+            .with_context(|| format!("Failed to connect the redis context store '{}'", self.name))?;
+        let index = self.next_connection.fetch_add(1, Ordering::Relaxed) % connections.len();
+        Ok(connections[index].clone())
+    }
+
+    fn scope_prefix(&self, scope: &str) -> String {
+        format!("{}:{}:", self.options.key_prefix, scope)
+    }
+
+    fn key_of(&self, scope: &str, path: &[PropexSegment]) -> Result<String> {
+        Ok(format!("{}{}", self.scope_prefix(scope), path_of(path)?))
+    }
+
+    /// Finds all keys matching the pattern without blocking the server like `KEYS` does.
+    async fn scan_keys(&self, pattern: &str) -> Result<Vec<String>> {
+        let mut conn = self.connection().await?;
+        let mut iter: ::redis::AsyncIter<String> = conn.scan_match(pattern).await?;
+        let mut keys = Vec::new();
+        while let Some(key) = iter.next_item().await {
+            keys.push(key);
+        }
+        Ok(keys)
+    }
+
+    /// Gets the values of the keys, always by `MGET`, the `mget()` sends `GET` for a single key and gets a value
+    /// instead of an array.
+    async fn get_values(&self, keys: &[String]) -> Result<Vec<Option<Variant>>> {
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut conn = self.connection().await?;
+        let values: Vec<Option<String>> = ::redis::cmd("MGET").arg(keys).query_async(&mut conn).await?;
+        values.into_iter().map(|x| x.map(|x| decode_value(&x)).transpose()).collect()
+    }
+
+    /// Loads the stored value containing the path, or the values stored under it, `None` if there is nothing.
+    async fn load(&self, scope: &str, path: &[PropexSegment<'_>]) -> Result<Option<Loaded>> {
+        // The arrays are stored as a whole, so only the properties before the first index can be a key
+        let key_len = path.iter().position(|x| !matches!(x, PropexSegment::Property(_))).unwrap_or(path.len());
+        if key_len == 0 {
+            return Err(EdgelinkError::BadArgument("path")).with_context(|| "Expected a property name");
+        }
+
+        let mut root = Variant::empty_object();
+        let ancestor_keys = (1..=key_len).map(|len| self.key_of(scope, &path[..len])).collect::<Result<Vec<_>>>()?;
+        let ancestors = self.get_values(&ancestor_keys).await?;
+        if let Some((index, value)) = ancestors.into_iter().enumerate().find_map(|(i, x)| x.map(|x| (i, x))) {
+            root.set_segs_property(&path[..=index], value, true)?;
+            return Ok(Some(Loaded { root, stored_len: index + 1, descendant_keys: Vec::new() }));
+        }
+
+        let key = self.key_of(scope, &path[..key_len])?;
+        let descendant_keys: Vec<String> = self
+            .scan_keys(&format!("{}*", escape_pattern(&key)))
+            .await?
+            .into_iter()
+            .filter(|x| matches!(x.as_bytes().get(key.len()), Some(b'.') | Some(b'[')))
+            .collect();
+        let prefix_len = self.scope_prefix(scope).len();
+        let mut found = false;
+        for (key, value) in descendant_keys.iter().zip(self.get_values(&descendant_keys).await?) {
+            // Skips the keys removed after the scan
+            if let Some(value) = value {
+                root.set_segs_property(&propex::parse(&key[prefix_len..])?, value, true)?;
+                found = true;
+            }
+        }
+        Ok(found.then_some(Loaded { root, stored_len: key_len, descendant_keys }))
+    }
+
+    /// Writes the value of the stored path back to its key, it is removed if the value is missing.
+    async fn save(&self, scope: &str, path: &[PropexSegment<'_>], loaded: Loaded) -> Result<()> {
+        let stored_path = &path[..loaded.stored_len];
+        let key = self.key_of(scope, stored_path)?;
+        let mut conn = self.connection().await?;
+        if !loaded.descendant_keys.is_empty() {
+            conn.del::<_, ()>(&loaded.descendant_keys).await?;
+        }
+        match loaded.root.get_segs(stored_path) {
+            Some(value) => conn.set::<_, _, ()>(key, serde_json::to_string(value)?).await?,
+            None => conn.del::<_, ()>(key).await?,
+        }
+        Ok(())
+    }
+}
+
+/// Formats the path of the properties as a propex expression, the names not a JS identifier are quoted, e.g.
+/// `foo["bar baz"][0]`.
+fn path_of(path: &[PropexSegment]) -> Result<String> {
+    let mut expr = String::new();
+    for seg in path.iter() {
+        match seg {
+            PropexSegment::Property(name) if is_identifier(name) => {
+                if !expr.is_empty() {
+                    expr.push('.');
+                }
+                expr.push_str(name);
+            }
+            PropexSegment::Property(name) if !name.is_empty() && !name.contains('"') => {
+                expr.push_str(&format!("[\"{}\"]", name))
+            }
+            PropexSegment::Property(name) if !name.is_empty() && !name.contains('\'') => {
+                expr.push_str(&format!("['{}']", name))
+            }
+            PropexSegment::Index(index) if !expr.is_empty() => expr.push_str(&format!("[{}]", index)),
+            _ => {
+                return Err(EdgelinkError::BadArgument("path"))
+                    .with_context(|| format!("Cannot store the context property: {:?}", path))
+            }
+        }
+    }
+    Ok(expr)
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Escapes the special characters of the glob-style patterns of `SCAN`.
+fn escape_pattern(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn decode_value(json: &str) -> Result<Variant> {
+    let jv: serde_json::Value = serde_json::from_str(json)?;
+    Ok(Variant::deserialize(jv)?)
+}
+
+#[async_trait]
+impl ContextStore for RedisContextStore {
+    async fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn open(&self) -> Result<()> {
+        // Fails early if the server is unreachable
+        self.connection().await?;
+        Ok(())
+    }
+
+    async fn close(&self) -> Result<()> {
+        // The connections are closed on drop
+        Ok(())
+    }
+
+    async fn get_one(&self, scope: &str, path: &[PropexSegment]) -> Result<Variant> {
+        let loaded = self.load(scope, path).await?.ok_or(EdgelinkError::OutOfRange)?;
+        loaded.root.get_segs(path).cloned().ok_or_else(|| EdgelinkError::OutOfRange.into())
+    }
+
+    async fn get_many(&self, scope: &str, keys: &[&str]) -> Result<Vec<Variant>> {
+        let mut values = Vec::with_capacity(keys.len());
+        for key in keys.iter() {
+            let path = [PropexSegment::Property((*key).into())];
+            // The missing keys are skipped like the memory store does
+            if let Some(value) = self.load(scope, &path).await?.and_then(|x| x.root.get_segs(&path).cloned()) {
+                values.push(value);
+            }
+        }
+        Ok(values)
+    }
+
+    async fn get_keys(&self, scope: &str) -> Result<Vec<String>> {
+        let prefix_len = self.scope_prefix(scope).len();
+        let mut keys = Vec::new();
+        for key in self.scan_keys(&format!("{}*", escape_pattern(&self.scope_prefix(scope)))).await? {
+            if let Some(PropexSegment::Property(name)) = propex::parse(&key[prefix_len..])?.first() {
+                keys.push(name.to_string());
+            }
+        }
+        keys.sort();
+        keys.dedup();
+        Ok(keys)
+    }
+
+    async fn set_one(&self, scope: &str, path: &[PropexSegment], value: Variant) -> Result<()> {
+        let key_len = path.iter().position(|x| !matches!(x, PropexSegment::Property(_))).unwrap_or(path.len());
+        match self.load(scope, path).await? {
+            Some(mut loaded) => {
+                loaded.root.set_segs_property(path, value, true)?;
+                self.save(scope, path, loaded).await
+            }
+            None => {
+                let mut root = Variant::empty_object();
+                root.set_segs_property(path, value, true)?;
+                self.save(scope, path, Loaded { root, stored_len: key_len, descendant_keys: Vec::new() }).await
+            }
+        }
+    }
+
+    async fn set_many(&self, scope: &str, pairs: Vec<(String, Variant)>) -> Result<()> {
+        for (key, value) in pairs.into_iter() {
+            self.set_one(scope, &[PropexSegment::Property(key.into())], value).await?;
+        }
+        Ok(())
+    }
+
+    async fn remove_one(&self, scope: &str, path: &[PropexSegment]) -> Result<Variant> {
+        let mut loaded = self.load(scope, path).await?.ok_or(EdgelinkError::OutOfRange)?;
+        let removed =
+            loaded.root.as_object_mut().unwrap().remove_segs_property(path).ok_or(EdgelinkError::OutOfRange)?;
+        self.save(scope, path, loaded).await?;
+        Ok(removed)
+    }
+
+    async fn delete(&self, scope: &str) -> Result<()> {
+        let keys = self.scan_keys(&format!("{}*", escape_pattern(&self.scope_prefix(scope)))).await?;
+        if !keys.is_empty() {
+            let mut conn = self.connection().await?;
+            conn.del::<_, ()>(keys).await?;
+        }
+        Ok(())
+    }
+
+    async fn clean(&self, active_nodes: &[ElementId]) -> Result<()> {
+        let prefix_len = self.options.key_prefix.len() + 1;
+        let keys = self.scan_keys(&format!("{}:*", escape_pattern(&self.options.key_prefix))).await?;
+        let mut inactive_keys = Vec::new();
+        for key in keys.into_iter() {
+            let scope = match key[prefix_len..].split_once(':') {
+                Some((scope, _)) => scope,
+                None => continue,
+            };
+            // Keeps the global scope, it is not bound to any node
+            let is_inactive = scope != GLOBAL_CONTEXT_NAME && !active_nodes.iter().any(|id| id.to_string() == scope);
+            if is_inactive {
+                inactive_keys.push(key);
+            }
+        }
+        if !inactive_keys.is_empty() {
+            let mut conn = self.connection().await?;
+            conn.del::<_, ()>(inactive_keys).await?;
+        }
+        Ok(())
+    }
+
+    fn is_async(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use serde_json::json;
+    use testcontainers_modules::redis::{Redis, REDIS_PORT};
+    use testcontainers_modules::testcontainers::runners::AsyncRunner;
+    use testcontainers_modules::testcontainers::ContainerAsync;
+
+    async fn start_store() -> (ContainerAsync<Redis>, RedisContextStore) {
+        let container = Redis::default().start().await.unwrap();
+        let host = container.get_host().await.unwrap();
+        let port = container.get_host_port_ipv4(REDIS_PORT).await.unwrap();
+        let options = ContextStoreOptions {
+            provider: "redis".to_string(),
+            options: HashMap::from([
+                ("url".to_string(), config::Value::from(format!("redis://{}:{}/", host, port))),
+                ("pool_size".to_string(), config::Value::from(2)),
+            ]),
+        };
+        let store = RedisContextStore::new("redis0".to_string(), Some(&options)).unwrap();
+        store.open().await.unwrap();
+        (container, store)
+    }
+
+    #[tokio::test]
+    #[ignore = "requires Docker to run the Redis container"]
+    async fn test_it_should_store_nested_properties() {
+        let (_container, store) = start_store().await;
+
+        store.set_one("nodeX", &propex::parse("foo.bar").unwrap(), "test".into()).await.unwrap();
+        store.set_one("nodeX", &propex::parse("foo.baz").unwrap(), 1.into()).await.unwrap();
+        assert_eq!(
+            store.get_one("nodeX", &propex::parse("foo").unwrap()).await.unwrap(),
+            json!({"bar": "test", "baz": 1}).into()
+        );
+        assert_eq!(store.get_one("nodeX", &propex::parse("foo.bar").unwrap()).await.unwrap(), "test".into());
+        assert!(store.get_one("nodeY", &propex::parse("foo").unwrap()).await.is_err());
+
+        let removed = store.remove_one("nodeX", &propex::parse("foo.bar").unwrap()).await.unwrap();
+        assert_eq!(removed, "test".into());
+        assert_eq!(store.get_one("nodeX", &propex::parse("foo").unwrap()).await.unwrap(), json!({"baz": 1}).into());
+        store.remove_one("nodeX", &propex::parse("foo").unwrap()).await.unwrap();
+        assert!(store.get_keys("nodeX").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires Docker to run the Redis container"]
+    async fn test_it_should_store_values_by_their_paths() {
+        let (_container, store) = start_store().await;
+        let stored_keys = || async {
+            let mut keys = store.scan_keys("edgelink:nodeX:*").await.unwrap();
+            keys.sort();
+            keys
+        };
+
+        store.set_one("nodeX", &propex::parse("foo.bar").unwrap(), "test".into()).await.unwrap();
+        store.set_one("nodeX", &propex::parse("foo['a b']").unwrap(), 1.into()).await.unwrap();
+        assert_eq!(stored_keys().await, vec!["edgelink:nodeX:foo.bar", "edgelink:nodeX:foo[\"a b\"]"]);
+        assert_eq!(
+            store.get_one("nodeX", &propex::parse("foo").unwrap()).await.unwrap(),
+            json!({"bar": "test", "a b": 1}).into()
+        );
+        assert_eq!(store.get_keys("nodeX").await.unwrap(), vec!["foo".to_string()]);
+
+        // Replaces the values stored under the path
+        store.set_one("nodeX", &propex::parse("foo").unwrap(), json!({"arr": [1, 2]}).into()).await.unwrap();
+        assert_eq!(stored_keys().await, vec!["edgelink:nodeX:foo"]);
+
+        // Updates the stored value containing the path, the arrays are stored as a whole
+        store.set_one("nodeX", &propex::parse("foo.arr[1]").unwrap(), 3.into()).await.unwrap();
+        store.set_one("nodeX", &propex::parse("foo.baz").unwrap(), true.into()).await.unwrap();
+        assert_eq!(stored_keys().await, vec!["edgelink:nodeX:foo"]);
+        assert_eq!(
+            store.get_one("nodeX", &propex::parse("foo").unwrap()).await.unwrap(),
+            json!({"arr": [1, 3], "baz": true}).into()
+        );
+        assert_eq!(store.get_one("nodeX", &propex::parse("foo.arr[1]").unwrap()).await.unwrap(), 3.into());
+
+        assert_eq!(
+            store.remove_one("nodeX", &propex::parse("foo").unwrap()).await.unwrap().as_object().unwrap().len(),
+            2
+        );
+        assert!(stored_keys().await.is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires Docker to run the Redis container"]
+    async fn test_it_should_list_delete_and_clean_scopes() {
+        let (_container, store) = start_store().await;
+        let active = ElementId::with_u64(0x1);
+        let inactive = ElementId::with_u64(0x2);
+
+        store.set_many(&active.to_string(), vec![("b".into(), 2.into()), ("a".into(), 1.into())]).await.unwrap();
+        store.set_one(&inactive.to_string(), &propex::parse("x").unwrap(), 1.into()).await.unwrap();
+        store.set_one(GLOBAL_CONTEXT_NAME, &propex::parse("g").unwrap(), 1.into()).await.unwrap();
+        store.set_one("not-a-node", &propex::parse("y").unwrap(), 1.into()).await.unwrap();
+        assert_eq!(store.get_keys(&active.to_string()).await.unwrap(), vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(store.get_many(&active.to_string(), &["a", "b"]).await.unwrap(), vec![1.into(), 2.into()]);
+
+        store.clean(&[active]).await.unwrap();
+        assert!(store.get_keys(&inactive.to_string()).await.unwrap().is_empty());
+        assert!(store.get_keys("not-a-node").await.unwrap().is_empty());
+        assert_eq!(store.get_keys(GLOBAL_CONTEXT_NAME).await.unwrap(), vec!["g".to_string()]);
+
+        store.delete(&active.to_string()).await.unwrap();
+        assert!(store.get_keys(&active.to_string()).await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_tls_options_should_change_the_url() {
+        let options = ContextStoreOptions {
+            provider: "redis".to_string(),
+            options: HashMap::from([
+                ("url".to_string(), config::Value::from("redis://example.com:6380/0")),
+                ("tls".to_string(), config::Value::from(true)),
+                ("tls_insecure".to_string(), config::Value::from(true)),
+            ]),
+        };
+        let options = RedisStoreOptions::parse(Some(&options)).unwrap();
+        assert_eq!(options.connection_url(), "rediss://example.com:6380/0#insecure");
+        assert_eq!(options.pool_size, 1);
+    }
+}
@@ -0,0 +1,358 @@
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use propex::PropexSegment;
+use serde::Deserialize;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions};
+use sqlx::{QueryBuilder, Sqlite, SqliteExecutor};
+use tokio::sync::OnceCell;
+
+use super::{EdgelinkError, ElementId, Variant};
+use crate::runtime::context::*;
+use crate::{ErrorContext, Result};
+
+inventory::submit! {
+    ProviderMetadata { type_: "sqlite", factory: SqliteContextStore::build }
+}
+
+const DEFAULT_PATH: &str = "context.db";
+const IN_MEMORY_PATH: &str = ":memory:";
+
+/// The options of the SQLite store, e.g.:
+///
+/// ```toml
+/// [runtime.context.stores]
+/// sqlite = { provider = "sqlite", path = "/var/lib/edgelink/context.db", journal_mode = "wal", cache_size = -8000 }
+/// ```
+#[derive(Debug, Clone)]
+struct SqliteStoreOptions {
+    /// The database file, created if it is missing. `:memory:` keeps the database in memory, only for testing
+    path: String,
+    /// The `PRAGMA journal_mode`, e.g. `wal`, `delete` or `memory`
+    journal_mode: SqliteJournalMode,
+    /// The `PRAGMA cache_size`, the pages if positive or the KiB if negative
+    cache_size: Option<i64>,
+}
+
+impl SqliteStoreOptions {
+    fn parse(options: Option<&ContextStoreOptions>) -> Result<Self> {
+        let get = |name: &str| options.and_then(|x| x.options.get(name)).cloned();
+        let journal_mode = match get("journal_mode").map(|x| x.into_string()).transpose()? {
+            Some(mode) => SqliteJournalMode::from_str(&mode)
+                .map_err(|_| EdgelinkError::BadArgument("journal_mode"))
+                .with_context(|| format!("Unknown `journal_mode` of the SQLite store: '{}'", mode))?,
+            None => SqliteJournalMode::Wal,
+        };
+        Ok(SqliteStoreOptions {
+            path: get("path").map(|x| x.into_string()).transpose()?.unwrap_or_else(|| DEFAULT_PATH.to_string()),
+            journal_mode,
+            cache_size: get("cache_size").map(|x| x.into_int()).transpose()?,
+        })
+    }
+
+    fn connect_options(&self) -> SqliteConnectOptions {
+        let mut options = if self.path == IN_MEMORY_PATH {
+            SqliteConnectOptions::new().in_memory(true)
+        } else {
+            SqliteConnectOptions::new().filename(&self.path).create_if_missing(true).journal_mode(self.journal_mode)
+        };
+        if let Some(cache_size) = self.cache_size {
+            options = options.pragma("cache_size", cache_size.to_string());
+        }
+        options
+    }
+}
+
+/// The context store persisted in a SQLite database, for the persistent context without a Redis server.
+///
+/// Every top-level property of a scope is a row of the table `context`, and its value is stored as JSON. The nested
+/// properties are updated by reading and writing their top-level property in a transaction.
+struct SqliteContextStore {
+    name: String,
+    options: SqliteStoreOptions,
+    pool: OnceCell<SqlitePool>,
+}
+
+impl SqliteContextStore {
+    fn build(name: String, options: Option<&ContextStoreOptions>) -> crate::Result<Box<dyn ContextStore>> {
+        let options = SqliteStoreOptions::parse(options)?;
+        let this = SqliteContextStore { name, options, pool: OnceCell::new() };
+        Ok(Box::new(this))
+    }
+
+    /// Gets the connection pool, the database is opened and the table is created on the first use.
+    async fn pool(&self) -> Result<&SqlitePool> {
+        self.pool
+            .get_or_try_init(|| async {
+                let mut pool_options = SqlitePoolOptions::new();
+                if self.options.path == IN_MEMORY_PATH {
+                    // Every connection opens its own in-memory database, keeps the only one forever
+                    pool_options = pool_options.max_connections(1).idle_timeout(None).max_lifetime(None);
+                }
+                let pool = pool_options.connect_with(self.options.connect_options()).await?;
+                sqlx::query(
+                    "CREATE TABLE IF NOT EXISTS context (
+                        scope TEXT NOT NULL,
+                        key TEXT NOT NULL,
+                        value BLOB NOT NULL,
+                        updated_at INTEGER NOT NULL,
+                        PRIMARY KEY (scope, key)
+                    )",
+                )
+                .execute(&pool)
+                .await?;
+                Ok::<_, sqlx::Error>(pool)
+            })
+            .await
+            .with_context(|| format!("Failed to open the SQLite context store '{}'", self.name))
+    }
+
+    /// Loads the top-level property of the path into an object, so the path can be resolved against it.
+    async fn load_root(
+        conn: impl SqliteExecutor<'_>,
+        scope: &str,
+        path: &[PropexSegment<'_>],
+    ) -> Result<(String, Variant)> {
+        let property = match path.first() {
+            Some(PropexSegment::Property(name)) => name.to_string(),
+            _ => return Err(EdgelinkError::BadArgument("path")).with_context(|| "Expected a property name"),
+        };
+        let mut root = Variant::empty_object();
+        if let Some(value) = get_property(conn, scope, &property).await? {
+            root.as_object_mut().unwrap().insert(property.clone(), value);
+        }
+        Ok((property, root))
+    }
+}
+
+async fn get_property(conn: impl SqliteExecutor<'_>, scope: &str, key: &str) -> Result<Option<Variant>> {
+    let value: Option<(Vec<u8>,)> = sqlx::query_as("SELECT value FROM context WHERE scope = ? AND key = ?")
+        .bind(scope)
+        .bind(key)
+        .fetch_optional(conn)
+        .await?;
+    value.map(|(json,)| decode_value(&json)).transpose()
+}
+
+async fn set_property(conn: impl SqliteExecutor<'_>, scope: &str, key: &str, value: &Variant) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO context (scope, key, value, updated_at) VALUES (?, ?, ?, ?)
+         ON CONFLICT (scope, key) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at",
+    )
+    .bind(scope)
+    .bind(key)
+    .bind(serde_json::to_vec(value)?)
+    .bind(now_millis())
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+async fn delete_property(conn: impl SqliteExecutor<'_>, scope: &str, key: &str) -> Result<()> {
+    sqlx::query("DELETE FROM context WHERE scope = ? AND key = ?").bind(scope).bind(key).execute(conn).await?;
+    Ok(())
+}
+
+fn decode_value(json: &[u8]) -> Result<Variant> {
+    let jv: serde_json::Value = serde_json::from_slice(json)?;
+    Ok(Variant::deserialize(jv)?)
+}
+
+/// The `updated_at` column, in milliseconds since the UNIX epoch
+fn now_millis() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |x| x.as_millis() as i64)
+}
+
+#[async_trait]
+impl ContextStore for SqliteContextStore {
+    async fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn open(&self) -> Result<()> {
+        // Fails early if the database cannot be opened
+        self.pool().await?;
+        Ok(())
+    }
+
+    async fn close(&self) -> Result<()> {
+        if let Some(pool) = self.pool.get() {
+            pool.close().await;
+        }
+        Ok(())
+    }
+
+    async fn get_one(&self, scope: &str, path: &[PropexSegment]) -> Result<Variant> {
+        let (_, root) = Self::load_root(self.pool().await?, scope, path).await?;
+        root.get_segs(path).cloned().ok_or_else(|| EdgelinkError::OutOfRange.into())
+    }
+
+    async fn get_many(&self, scope: &str, keys: &[&str]) -> Result<Vec<Variant>> {
+        let pool = self.pool().await?;
+        let mut values = Vec::with_capacity(keys.len());
+        for key in keys.iter() {
+            // The missing keys are skipped like the memory store does
+            if let Some(value) = get_property(pool, scope, key).await? {
+                values.push(value);
+            }
+        }
+        Ok(values)
+    }
+
+    async fn get_keys(&self, scope: &str) -> Result<Vec<String>> {
+        let keys: Vec<(String,)> = sqlx::query_as("SELECT key FROM context WHERE scope = ? ORDER BY key")
+            .bind(scope)
+            .fetch_all(self.pool().await?)
+            .await?;
+        Ok(keys.into_iter().map(|(key,)| key).collect())
+    }
+
+    async fn set_one(&self, scope: &str, path: &[PropexSegment], value: Variant) -> Result<()> {
+        let mut tx = self.pool().await?.begin().await?;
+        let (property, mut root) = Self::load_root(&mut *tx, scope, path).await?;
+        root.set_segs_property(path, value, true)?;
+        let value = root.as_object_mut().unwrap().remove(&property).unwrap_or(Variant::Null);
+        set_property(&mut *tx, scope, &property, &value).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn set_many(&self, scope: &str, pairs: Vec<(String, Variant)>) -> Result<()> {
+        let mut tx = self.pool().await?.begin().await?;
+        for (key, value) in pairs.iter() {
+            set_property(&mut *tx, scope, key, value).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn remove_one(&self, scope: &str, path: &[PropexSegment]) -> Result<Variant> {
+        let mut tx = self.pool().await?.begin().await?;
+        let (property, mut root) = Self::load_root(&mut *tx, scope, path).await?;
+        let removed = root.as_object_mut().unwrap().remove_segs_property(path).ok_or(EdgelinkError::OutOfRange)?;
+        match root.as_object_mut().unwrap().remove(&property) {
+            // A nested property was removed, writes back the rest of its top-level property
+            Some(rest) => set_property(&mut *tx, scope, &property, &rest).await?,
+            None => delete_property(&mut *tx, scope, &property).await?,
+        }
+        tx.commit().await?;
+        Ok(removed)
+    }
+
+    async fn delete(&self, scope: &str) -> Result<()> {
+        sqlx::query("DELETE FROM context WHERE scope = ?").bind(scope).execute(self.pool().await?).await?;
+        Ok(())
+    }
+
+    async fn clean(&self, active_nodes: &[ElementId]) -> Result<()> {
+        // Keeps the global scope, it is not bound to any node
+        let mut query = QueryBuilder::<Sqlite>::new("DELETE FROM context WHERE scope <> ");
+        query.push_bind(GLOBAL_CONTEXT_NAME);
+        if !active_nodes.is_empty() {
+            query.push(" AND scope NOT IN (");
+            let mut separated = query.separated(", ");
+            for id in active_nodes.iter() {
+                separated.push_bind(id.to_string());
+            }
+            separated.push_unseparated(")");
+        }
+        query.build().execute(self.pool().await?).await?;
+        Ok(())
+    }
+
+    fn is_async(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use serde_json::json;
+
+    async fn open_store() -> Box<dyn ContextStore> {
+        let options = ContextStoreOptions {
+            provider: "sqlite".to_string(),
+            options: HashMap::from([
+                ("path".to_string(), config::Value::from(IN_MEMORY_PATH)),
+                ("cache_size".to_string(), config::Value::from(-4000)),
+            ]),
+        };
+        let store = SqliteContextStore::build("sqlite0".to_string(), Some(&options)).unwrap();
+        store.open().await.unwrap();
+        store
+    }
+
+    #[tokio::test]
+    async fn test_it_should_store_nested_properties() {
+        let store = open_store().await;
+
+        store.set_one("nodeX", &propex::parse("foo.bar").unwrap(), "test".into()).await.unwrap();
+        store.set_one("nodeX", &propex::parse("foo.baz").unwrap(), 1.into()).await.unwrap();
+        assert_eq!(
+            store.get_one("nodeX", &propex::parse("foo").unwrap()).await.unwrap(),
+            json!({"bar": "test", "baz": 1}).into()
+        );
+        assert_eq!(store.get_one("nodeX", &propex::parse("foo.bar").unwrap()).await.unwrap(), "test".into());
+        assert!(store.get_one("nodeY", &propex::parse("foo").unwrap()).await.is_err());
+
+        let removed = store.remove_one("nodeX", &propex::parse("foo.bar").unwrap()).await.unwrap();
+        assert_eq!(removed, "test".into());
+        assert_eq!(store.get_one("nodeX", &propex::parse("foo").unwrap()).await.unwrap(), json!({"baz": 1}).into());
+        store.remove_one("nodeX", &propex::parse("foo").unwrap()).await.unwrap();
+        assert!(store.get_keys("nodeX").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_it_should_list_delete_and_clean_scopes() {
+        let store = open_store().await;
+        let active = ElementId::with_u64(0x1);
+        let inactive = ElementId::with_u64(0x2);
+
+        store.set_many(&active.to_string(), vec![("b".into(), 2.into()), ("a".into(), 1.into())]).await.unwrap();
+        store.set_one(&inactive.to_string(), &propex::parse("x").unwrap(), 1.into()).await.unwrap();
+        store.set_one(GLOBAL_CONTEXT_NAME, &propex::parse("g").unwrap(), 1.into()).await.unwrap();
+        assert_eq!(store.get_keys(&active.to_string()).await.unwrap(), vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(store.get_many(&active.to_string(), &["a", "nope", "b"]).await.unwrap(), vec![1.into(), 2.into()]);
+
+        store.clean(&[active]).await.unwrap();
+        assert!(store.get_keys(&inactive.to_string()).await.unwrap().is_empty());
+        assert_eq!(store.get_keys(GLOBAL_CONTEXT_NAME).await.unwrap(), vec!["g".to_string()]);
+        assert_eq!(store.get_keys(&active.to_string()).await.unwrap().len(), 2);
+
+        store.delete(&active.to_string()).await.unwrap();
+        assert!(store.get_keys(&active.to_string()).await.unwrap().is_empty());
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_set_operations_should_report_store_failures() {
+        let store = open_store().await;
+        let path = propex::parse("devices").unwrap();
+        assert!(!store.is_in_set("nodeX", &path, &"a".into()).await.unwrap());
+        assert!(!store.remove_from_set("nodeX", &path, &"a".into()).await.unwrap());
+        assert!(store.add_to_set("nodeX", &path, "a".into()).await.unwrap());
+        assert!(store.is_in_set("nodeX", &path, &"a".into()).await.unwrap());
+
+        // The failures of the closed store are not taken as an empty set
+        store.close().await.unwrap();
+        assert!(store.is_in_set("nodeX", &path, &"a".into()).await.is_err());
+        assert!(store.remove_from_set("nodeX", &path, &"a".into()).await.is_err());
+        assert!(store.add_to_set("nodeX", &path, "b".into()).await.is_err());
+    }
+
+    #[test]
+    fn test_bad_journal_mode_should_be_rejected() {
+        let options = ContextStoreOptions {
+            provider: "sqlite".to_string(),
+            options: HashMap::from([("journal_mode".to_string(), config::Value::from("nope"))]),
+        };
+        assert!(SqliteStoreOptions::parse(Some(&options)).is_err());
+        let options = SqliteStoreOptions::parse(None).unwrap();
+        assert_eq!(options.path, DEFAULT_PATH);
+        assert!(matches!(options.journal_mode, SqliteJournalMode::Wal));
+    }
+}
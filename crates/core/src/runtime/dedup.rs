@@ -0,0 +1,135 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::runtime::model::ElementId;
+
+/// The settings of the deduplication of the injected messages, configured by `runtime.engine.dedup`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct DedupArgs {
+    /// How long a `_msgid` is remembered since it was first seen
+    #[serde(default = "window_ms_default")]
+    pub window_ms: u64,
+
+    /// The most `_msgid`s remembered, the oldest ones are forgotten first even if they are still in the window
+    #[serde(default = "capacity_default")]
+    pub capacity: usize,
+}
+
+impl Default for DedupArgs {
+    fn default() -> Self {
+        Self { window_ms: window_ms_default(), capacity: capacity_default() }
+    }
+}
+
+fn window_ms_default() -> u64 {
+    60_000
+}
+
+fn capacity_default() -> usize {
+    10_000
+}
+
+/// The bounded set of the `_msgid`s seen by `Engine::inject_msg()` in the window.
+///
+/// The window of an id starts from the first time it was seen, the duplicates do not extend it.
+#[derive(Debug)]
+pub struct MsgIdDedup {
+    window: Duration,
+    capacity: usize,
+    seen: std::sync::Mutex<SeenIds>,
+}
+
+#[derive(Debug, Default)]
+struct SeenIds {
+    ids: HashMap<ElementId, Instant>,
+    /// The ids in the order they were first seen, for expiring and evicting the oldest ones
+    order: VecDeque<(ElementId, Instant)>,
+}
+
+impl MsgIdDedup {
+    pub fn new(args: &DedupArgs) -> Self {
+        Self {
+            window: Duration::from_millis(args.window_ms),
+            capacity: args.capacity.max(1),
+            seen: std::sync::Mutex::new(SeenIds::default()),
+        }
+    }
+
+    /// Remembers the id and returns `true` if it was not seen in the window.
+    pub fn check(&self, id: ElementId, now: Instant) -> bool {
+        let mut seen = self.seen.lock().expect("`seen` lock");
+        while let Some(&(oldest, first_seen)) = seen.order.front() {
+            if now.saturating_duration_since(first_seen) < self.window {
+                break;
+            }
+            seen.order.pop_front();
+            seen.ids.remove(&oldest);
+        }
+        if seen.ids.contains_key(&id) {
+            return false;
+        }
+        if seen.order.len() >= self.capacity {
+            if let Some((oldest, _)) = seen.order.pop_front() {
+                seen.ids.remove(&oldest);
+            }
+        }
+        seen.ids.insert(id, now);
+        seen.order.push_back((id, now));
+        true
+    }
+
+    /// Forgets the id, so a message with it can be injected again on purpose in the window.
+    pub fn forget(&self, id: &ElementId) -> bool {
+        let mut seen = self.seen.lock().expect("`seen` lock");
+        if seen.ids.remove(id).is_some() {
+            seen.order.retain(|x| x.0 != *id);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.seen.lock().expect("`seen` lock").ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duplicates_should_be_dropped_in_window_only() {
+        let dedup = MsgIdDedup::new(&DedupArgs { window_ms: 100, capacity: 16 });
+        let start = Instant::now();
+        let (a, b) = (ElementId::with_u64(1), ElementId::with_u64(2));
+        assert!(dedup.check(a, start));
+        assert!(dedup.check(b, start + Duration::from_millis(50)));
+        assert!(!dedup.check(a, start + Duration::from_millis(99)));
+        assert!(dedup.check(a, start + Duration::from_millis(100)));
+        assert!(!dedup.check(b, start + Duration::from_millis(120)));
+        assert_eq!(dedup.len(), 2);
+
+        assert!(dedup.forget(&b));
+        assert!(!dedup.forget(&b));
+        assert!(dedup.check(b, start + Duration::from_millis(130)));
+    }
+
+    #[test]
+    fn test_oldest_ids_should_be_evicted_over_capacity() {
+        let dedup = MsgIdDedup::new(&DedupArgs { window_ms: 60_000, capacity: 3 });
+        let now = Instant::now();
+        for id in 1..=5 {
+            assert!(dedup.check(ElementId::with_u64(id), now));
+        }
+        assert_eq!(dedup.len(), 3);
+        assert!(dedup.check(ElementId::with_u64(1), now));
+        assert!(!dedup.check(ElementId::with_u64(5), now));
+    }
+}
@@ -1,25 +1,131 @@
+use std::any::{Any, TypeId};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
 use std::io::Read;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Weak};
 
 use dashmap::DashMap;
+use itertools::Itertools;
 use runtime::flow::*;
 use runtime::registry::RegistryHandle;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use tokio_util::sync::CancellationToken;
 
 use super::context::{Context, ContextManager, ContextManagerBuilder};
+use super::dedup::{DedupArgs, MsgIdDedup};
 use super::env::*;
-use super::model::json::{RedFlowConfig, RedGlobalNodeConfig};
+use super::model::json::{RedFlowConfig, RedGlobalNodeConfig, ResolvedFlows};
 use super::model::*;
 use super::nodes::FlowNodeBehavior;
+use super::trace::TraceStep;
 use crate::runtime::model::Variant;
 use crate::runtime::nodes::{GlobalNodeBehavior, NodeFactory};
 use crate::*;
-use crate::utils::constants::SUB_FLOW_TYPE;
+use crate::utils::constants::{ID_STR, SUB_FLOW_TYPE, TAB_STR, TYPE_STR};
 
-#[derive(Debug, Clone, Deserialize, Default)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Default)]
 pub struct EngineArgs {
     //node_msg_queue_capacity: usize,
+    /// The node receiving the errors not handled by any `catch` node in any flow,
+    /// configured by `runtime.engine.uncaught_error_handler`
+    #[serde(default)]
+    pub uncaught_error_handler: Option<ElementId>,
+
+    /// The default time-to-live in milliseconds of the messages without a TTL, starting from the first node received
+    /// them, configured by `runtime.engine.msg_ttl_ms`, the messages never expire if absent
+    #[serde(default)]
+    pub msg_ttl_ms: Option<u64>,
+
+    /// Makes `Date.now()` and `Math.random()` in the function nodes deterministic for testing, configured by
+    /// `runtime.engine.test_clock`, the real clock and random numbers are used if absent
+    #[serde(default)]
+    pub test_clock: Option<TestClockArgs>,
+
+    /// The HTTP settings of the `http in` and `http request` nodes, configured by `runtime.engine.http`
+    #[serde(default)]
+    pub http: HttpArgs,
+
+    /// The server of the `websocket-listener` nodes, configured by `runtime.engine.websocket`
+    #[serde(default)]
+    pub websocket: WebSocketArgs,
+
+    /// Skips the verification of the server certificates of the outgoing TLS connections, configured by
+    /// `runtime.engine.tls_insecure` or the `--tls-insecure` option of `edgelinkd`, for testing only
+    #[serde(default)]
+    pub tls_insecure: bool,
+
+    /// The serialization format of the messages handed out by `encode_msgs()`, e.g. to the Python module, configured
+    /// by `runtime.engine.output_format`
+    #[serde(default)]
+    pub output_format: MsgsFormat,
+
+    /// Drops the messages injected by `Engine::inject_msg()` with a `_msgid` already seen in the window, configured by
+    /// `runtime.engine.dedup`, disabled if absent
+    #[serde(default)]
+    pub dedup: Option<DedupArgs>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Default)]
+pub struct TestClockArgs {
+    /// The fixed milliseconds since the UNIX epoch returned by `Date.now()`
+    #[serde(default)]
+    pub now_ms: u64,
+
+    /// The seed of the pseudo-random sequence returned by `Math.random()`
+    #[serde(default)]
+    pub random_seed: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct HttpArgs {
+    /// The address to listen on, the `http in` nodes listening on the same address share the server
+    #[serde(default = "http_listen_default")]
+    pub listen: String,
+
+    /// How long to wait for the `http response` node before replying `504 Gateway Timeout`
+    #[serde(default = "http_timeout_ms_default")]
+    pub timeout_ms: u64,
+
+    /// The default timeout of the `http request` nodes, overridden by the `msg.requestTimeout`
+    #[serde(default = "http_timeout_ms_default")]
+    pub request_timeout_ms: u64,
+}
+
+impl Default for HttpArgs {
+    fn default() -> Self {
+        Self {
+            listen: http_listen_default(),
+            timeout_ms: http_timeout_ms_default(),
+            request_timeout_ms: http_timeout_ms_default(),
+        }
+    }
+}
+
+fn http_listen_default() -> String {
+    "0.0.0.0:1880".to_string()
+}
+
+fn http_timeout_ms_default() -> u64 {
+    120_000
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct WebSocketArgs {
+    /// The address to listen on, the `websocket-listener` nodes on the same address share the server by the paths
+    #[serde(default = "websocket_listen_default")]
+    pub listen: String,
+}
+
+impl Default for WebSocketArgs {
+    fn default() -> Self {
+        Self { listen: websocket_listen_default() }
+    }
+}
+
+fn websocket_listen_default() -> String {
+    "0.0.0.0:1881".to_string()
 }
 
 impl EngineArgs {
@@ -35,6 +141,42 @@ impl EngineArgs {
     }
 }
 
+/// The changes applied by `Engine::reload_config()`, named by their keys in the configuration.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ConfigReloadReport {
+    /// The changed settings applied to the running engine
+    pub applied: Vec<String>,
+
+    /// The changed settings ignored until the engine restarted
+    pub requires_restart: Vec<String>,
+}
+
+impl ConfigReloadReport {
+    /// Returns `true` if nothing was changed.
+    pub fn is_empty(&self) -> bool {
+        self.applied.is_empty() && self.requires_restart.is_empty()
+    }
+}
+
+/// The status of a flow in the `HealthReport`.
+#[derive(Debug, Clone, Serialize)]
+pub struct FlowHealth {
+    pub id: ElementId,
+    pub label: String,
+    pub disabled: bool,
+    pub running: bool,
+}
+
+/// A snapshot of the engine status for the liveness and readiness probes.
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthReport {
+    /// `true` if the engine started and all enabled flows are running
+    pub ready: bool,
+    pub started: bool,
+    pub context_stores_opened: bool,
+    pub flows: Vec<FlowHealth>,
+}
+
 #[derive(Debug, Clone)]
 pub struct Engine {
     inner: Arc<InnerEngine>,
@@ -54,21 +196,43 @@ impl WeakEngine {
 struct InnerEngine {
     shutdown: tokio::sync::RwLock<bool>,
     stop_token: CancellationToken,
-    _args: EngineArgs,
+    args: EngineArgs,
+    /// The settings of the flows loaded afterwards, changed by `reload_config()`
+    flow_args: std::sync::RwLock<FlowArgs>,
+    /// The registry the engine was built with, for `reload_flows()` to build the new flows
+    registry: RegistryHandle,
     envs: Envs,
     context_manager: Arc<ContextManager>,
     context: Arc<Context>,
+    context_stores_opened: AtomicBool,
 
     _context: Variant,
+    /// The configurations of the loaded flows and global nodes, for `export_flows()`
+    configs: std::sync::RwLock<ResolvedFlows>,
+    /// The digests of the loaded `flows.json`, for `reload_flows()` to find out the changed flows
+    digest: std::sync::Mutex<FlowsDigest>,
     flows: DashMap<ElementId, Flow>,
     global_nodes: DashMap<ElementId, Arc<dyn GlobalNodeBehavior>>,
     all_flow_nodes: DashMap<ElementId, Arc<dyn FlowNodeBehavior>>,
-
-    #[cfg(any(test, feature = "pymod"))]
+    /// The metrics created by the flow nodes, e.g. `node.metrics.counter()` of the function node
+    #[cfg(feature = "metrics")]
+    metrics_registry: prometheus::Registry,
+    /// The `_msgid`s seen by `inject_msg()`, see `EngineArgs::dedup`
+    msg_dedup: Option<MsgIdDedup>,
+    /// The states shared by the nodes of this engine, keyed by the type, see `shared_state()`
+    shared_states: DashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+
+    #[cfg(any(test, feature = "testing"))]
     final_msgs_rx: MsgUnboundedReceiverHolder,
 
-    #[cfg(any(test, feature = "pymod"))]
+    #[cfg(any(test, feature = "testing"))]
     final_msgs_tx: MsgUnboundedSender,
+
+    #[cfg(any(test, feature = "testing"))]
+    test_failures_tx: tokio::sync::mpsc::UnboundedSender<String>,
+
+    #[cfg(any(test, feature = "testing"))]
+    test_failures_rx: tokio::sync::Mutex<tokio::sync::mpsc::UnboundedReceiver<String>>,
 }
 
 impl Engine {
@@ -81,6 +245,7 @@ impl Engine {
         json: serde_json::Value,
         elcfg: Option<&config::Config>,
     ) -> crate::Result<Engine> {
+        let digest = FlowsDigest::new(&json);
         let json_values = json::deser::load_flows_json_value(json).map_err(|e| {
             log::error!("Failed to load NodeRED JSON value: {}", e);
             e
@@ -99,9 +264,14 @@ impl Engine {
         // let context_manager = Arc::new(ContextManager::default());
         let context = context_manager.new_global_context();
 
-        #[cfg(any(test, feature = "pymod"))]
+        #[cfg(any(test, feature = "testing"))]
         let final_msgs_channel = tokio::sync::mpsc::unbounded_channel();
 
+        #[cfg(any(test, feature = "testing"))]
+        let test_failures_channel = tokio::sync::mpsc::unbounded_channel();
+
+        let args = EngineArgs::load(elcfg)?;
+        let msg_dedup = args.dedup.as_ref().map(MsgIdDedup::new);
         let engine = Self {
             inner: Arc::new(InnerEngine {
                 shutdown: tokio::sync::RwLock::new(true),
@@ -110,23 +280,39 @@ impl Engine {
                 global_nodes: DashMap::new(),
                 flows: DashMap::new(),
                 _context: Variant::empty_object(),
+                configs: std::sync::RwLock::new(json_values.clone()),
+                digest: std::sync::Mutex::new(digest),
                 envs,
-                _args: EngineArgs::load(elcfg)?,
+                args,
+                flow_args: std::sync::RwLock::new(FlowArgs::load(elcfg)?),
+                registry: reg.clone(),
                 context_manager,
                 context,
+                context_stores_opened: AtomicBool::new(false),
+                #[cfg(feature = "metrics")]
+                metrics_registry: prometheus::Registry::new(),
+                msg_dedup,
+                shared_states: DashMap::new(),
 
-                #[cfg(any(test, feature = "pymod"))]
+                #[cfg(any(test, feature = "testing"))]
                 final_msgs_rx: MsgUnboundedReceiverHolder::new(final_msgs_channel.1),
 
-                #[cfg(any(test, feature = "pymod"))]
+                #[cfg(any(test, feature = "testing"))]
                 final_msgs_tx: final_msgs_channel.0,
+
+                #[cfg(any(test, feature = "testing"))]
+                test_failures_tx: test_failures_channel.0,
+
+                #[cfg(any(test, feature = "testing"))]
+                test_failures_rx: tokio::sync::Mutex::new(test_failures_channel.1),
             }),
         };
 
-        engine.clone().load_flows(json_values.flows, reg, elcfg)?;
-
+        // The global nodes must be available before the flow nodes referencing them are built
         engine.clone().load_global_nodes(json_values.global_nodes, reg.clone())?;
 
+        engine.clone().load_flows(json_values.flows, reg)?;
+
         Ok(engine)
     }
 
@@ -150,16 +336,230 @@ impl Engine {
         Self::with_json(reg, json, elcfg)
     }
 
+    /// Applies the flows and global nodes in the JSON, the flows will be restarted if the engine is running.
+    ///
+    /// Only the changed flows are rebuilt: the removed flows are stopped, the new ones are started, and a modified
+    /// flow is stopped and started again with its new configuration. A flow is modified if its elements or the
+    /// subflows used by it are changed, the unchanged flows keep running. All flows are rebuilt if the global nodes
+    /// are changed. The context manager is kept, so the context values of flows and nodes survive the reload as long
+    /// as their IDs are not changed.
+    ///
+    /// Nothing is changed if the new flows or global nodes cannot be built, the previous flows keep running and the
+    /// error is returned. A flow failed to stop or start does not stop the reload, the other flows are still reloaded and the failures
+    /// are reported by the error. The settings changed by `reload_config()` are applied to the rebuilt flows.
+    pub async fn reload_flows(&self, new_json: serde_json::Value) -> crate::Result<()> {
+        let reg = &self.inner.registry;
+        let new_digest = FlowsDigest::new(&new_json);
+        let json_values = json::deser::load_flows_json_value(new_json).map_err(|e| {
+            log::error!("Failed to load NodeRED JSON value: {}", e);
+            e
+        })?;
+
+        let shutdown_lock = self.inner.shutdown.write().await;
+        let is_running = !(*shutdown_lock);
+
+        let (globals_changed, mut unchanged_roots) = {
+            let old_digest = self.inner.digest.lock().expect("`digest` lock");
+            let unchanged: HashSet<ElementId> = if old_digest.globals == new_digest.globals {
+                new_digest.flows.iter().filter(|(id, d)| old_digest.flows.get(id) == Some(d)).map(|x| *x.0).collect()
+            } else {
+                HashSet::new()
+            };
+            (old_digest.globals != new_digest.globals, unchanged)
+        };
+
+        let old_configs = self.inner.configs.read().expect("`configs` read lock").flows.clone();
+        let old_roots = root_flow_ids(&old_configs);
+        // The stopped flows cannot be started again, they are rebuilt even if unchanged
+        for flow in self.inner.flows.iter().filter(|x| x.is_stopped()) {
+            if let Some(root) = old_roots.get(flow.key()) {
+                unchanged_roots.remove(root);
+            }
+        }
+        let flows_to_stop: Vec<Flow> = self
+            .inner
+            .flows
+            .iter()
+            .filter(|x| !old_roots.get(x.key()).is_some_and(|root| unchanged_roots.contains(root)))
+            .map(|x| x.value().clone())
+            .collect();
+
+        // The running copies of the subflows in the unchanged flows are kept with their configurations, since the
+        // copies in the new configurations got new IDs
+        let new_roots = root_flow_ids(&json_values.flows);
+        let mut merged_configs = Vec::with_capacity(json_values.flows.len());
+        let mut flows_to_load = Vec::new();
+        for flow_config in json_values.flows.iter() {
+            let root = new_roots.get(&flow_config.id).copied().unwrap_or(flow_config.id);
+            if !unchanged_roots.contains(&root) {
+                merged_configs.push(flow_config.clone());
+                flows_to_load.push(flow_config.clone());
+            } else if root == flow_config.id {
+                merged_configs
+                    .extend(old_configs.iter().filter(|x| old_roots.get(&x.id) == Some(&root)).cloned());
+            }
+        }
+        let flow_ids_to_start: Vec<ElementId> = flows_to_load.iter().map(|x| x.id).collect();
+
+        // The new flows are built before the old ones are stopped, so the old flows keep running if the new
+        // configurations cannot be loaded, e.g. a node with a bad property
+        for flow in flows_to_stop.iter() {
+            self.unregister_flow(flow);
+        }
+        let old_global_nodes: Vec<(ElementId, Arc<dyn GlobalNodeBehavior>)> = if globals_changed {
+            let old = self.inner.global_nodes.iter().map(|x| (*x.key(), x.value().clone())).collect();
+            self.inner.global_nodes.clear();
+            old
+        } else {
+            Vec::new()
+        };
+        let loaded = if globals_changed {
+            self.load_global_nodes(json_values.global_nodes.clone(), reg.clone())
+        } else {
+            Ok(())
+        }
+        .and_then(|_| self.load_flows(flows_to_load, reg));
+        if let Err(err) = loaded {
+            log::error!("Failed to build the flows to reload, the previous flows are kept: {}", err);
+            for flow_id in flow_ids_to_start.iter() {
+                if let Some(flow) = self.get_flow(flow_id) {
+                    self.unregister_flow(&flow);
+                }
+            }
+            if globals_changed {
+                self.inner.global_nodes.clear();
+                for (id, global_node) in old_global_nodes.into_iter() {
+                    self.inner.global_nodes.insert(id, global_node);
+                }
+            }
+            for flow in flows_to_stop.into_iter() {
+                self.register_flow(flow)?;
+            }
+            return Err(err);
+        }
+
+        log::info!("-- Stopping {} flow(s) to reload...", flows_to_stop.len());
+        let mut failed_flows = Vec::new();
+        if is_running {
+            for flow in flows_to_stop.iter() {
+                // The flow is replaced anyway, the other flows are still reloaded
+                if let Err(err) = flow.stop().await {
+                    log::error!("Failed to stop the flow {} to reload: {}", flow.id(), err);
+                    failed_flows.push(flow.id());
+                }
+            }
+        }
+
+        *self.inner.configs.write().expect("`configs` write lock") =
+            ResolvedFlows { flows: merged_configs, global_nodes: json_values.global_nodes };
+        *self.inner.digest.lock().expect("`digest` lock") = new_digest;
+
+        if is_running {
+            for flow_id in flow_ids_to_start.iter() {
+                if let Some(flow) = self.get_flow(flow_id) {
+                    if let Err(err) = flow.start().await {
+                        log::error!("Failed to start the reloaded flow {}: {}", flow_id, err);
+                        failed_flows.push(*flow_id);
+                    }
+                }
+            }
+        }
+        log::info!(
+            "-- Flows reloaded: {} flow(s) kept running, {} flow(s) stopped and {} flow(s) started.",
+            self.inner.flows.len() - flow_ids_to_start.len(),
+            flows_to_stop.len(),
+            flow_ids_to_start.len()
+        );
+        if !failed_flows.is_empty() {
+            let ids = failed_flows.iter().map(|x| x.to_string()).join(", ");
+            return Err(EdgelinkError::invalid_operation(&format!("Failed to stop or start the flow(s): {}", ids)));
+        }
+        Ok(())
+    }
+
+    /// Applies the changed settings of the configuration to the engine without restarting it.
+    ///
+    /// The settings are validated before anything is applied:
+    ///
+    /// * `runtime.context`: the stores with changed options are replaced, the new ones are opened first if the
+    ///   engine is running, and the replaced ones are closed after their operations in progress finished. The
+    ///   contexts keep the values in the unchanged stores.
+    /// * `runtime.flow`: applied to the flows loaded afterwards, e.g. by `reload_flows()`, the running flows keep
+    ///   their message queues.
+    /// * `runtime.engine`: cannot be applied, the changed settings are reported in `requires_restart`.
+    pub async fn reload_config(&self, elcfg: &config::Config) -> crate::Result<ConfigReloadReport> {
+        let engine_args = EngineArgs::load(Some(elcfg))?;
+        let flow_args = FlowArgs::load(Some(elcfg))?;
+
+        let mut report = ConfigReloadReport::default();
+        let replaced_stores = {
+            // Holds the lock to prevent the engine from starting or stopping while the stores are being replaced
+            let _shutdown_lock = self.inner.shutdown.write().await;
+            let stores_opened = self.inner.context_stores_opened.load(Ordering::Acquire);
+            self.inner.context_manager.reload_stores(elcfg, stores_opened).await?
+        };
+        if let Some(replaced_stores) = replaced_stores {
+            report.applied.push("runtime.context".to_string());
+            // The stores are no longer reachable, so the engine can start or stop while they are being drained
+            replaced_stores.drain_and_close().await;
+        }
+
+        {
+            let mut current = self.inner.flow_args.write().expect("`flow_args` write lock");
+            if current.node_msg_queue_capacity != flow_args.node_msg_queue_capacity {
+                report.applied.push("runtime.flow.node_msg_queue_capacity".to_string());
+            }
+            if current.on_fatal != flow_args.on_fatal {
+                report.applied.push("runtime.flow.on_fatal".to_string());
+            }
+            *current = flow_args;
+        }
+
+        let args = &self.inner.args;
+        let engine_changes = [
+            ("uncaught_error_handler", args.uncaught_error_handler != engine_args.uncaught_error_handler),
+            ("msg_ttl_ms", args.msg_ttl_ms != engine_args.msg_ttl_ms),
+            ("test_clock", args.test_clock != engine_args.test_clock),
+            ("http", args.http != engine_args.http),
+            ("websocket", args.websocket != engine_args.websocket),
+            ("tls_insecure", args.tls_insecure != engine_args.tls_insecure),
+            ("output_format", args.output_format != engine_args.output_format),
+            ("dedup", args.dedup != engine_args.dedup),
+        ];
+        for (key, _) in engine_changes.iter().filter(|x| x.1) {
+            log::warn!("The setting `runtime.engine.{}` has been changed, restart the engine to apply it", key);
+            report.requires_restart.push(format!("runtime.engine.{}", key));
+        }
+
+        log::info!(
+            "-- Configuration reloaded: {} change(s) applied, {} change(s) require restart.",
+            report.applied.len(),
+            report.requires_restart.len()
+        );
+        Ok(report)
+    }
+
+    /// Regenerates the Node-RED flows array from the loaded configurations, e.g. for editing and redeploying.
+    ///
+    /// The ids of the subflow copies are de-mangled back to the ids in the original `flows.json`, see
+    /// `json::ser::dump_flows_json_value()` for the details.
+    pub fn export_flows(&self) -> serde_json::Value {
+        let configs = self.inner.configs.read().expect("`configs` read lock");
+        json::ser::dump_flows_json_value(&configs)
+    }
+
     pub fn get_flow(&self, id: &ElementId) -> Option<Flow> {
         self.inner.flows.get(id).map(|x| x.value().clone())
     }
 
-    fn load_flows(
-        &self,
-        flow_cfg: Vec<RedFlowConfig>,
-        reg: &RegistryHandle,
-        elcfg: Option<&config::Config>,
-    ) -> crate::Result<()> {
+    /// Gets the global configuration node, e.g. a broker referenced by the flow nodes.
+    pub fn get_global_node(&self, id: &ElementId) -> crate::Result<Arc<dyn GlobalNodeBehavior>> {
+        self.inner.global_nodes.get(id).map(|x| x.value().clone()).ok_or_else(|| {
+            EdgelinkError::BadFlowsJson(format!("Cannot find the global configuration node: id='{}'", id)).into()
+        })
+    }
+
+    fn load_flows(&self, flow_cfg: Vec<RedFlowConfig>, reg: &RegistryHandle) -> crate::Result<()> {
         // load flows
         for flow_config in flow_cfg.into_iter() {
             if &flow_config.type_name==SUB_FLOW_TYPE {
@@ -168,32 +568,37 @@ impl Engine {
                 log::debug!("---- Loading flow: (id='{}', label='{}')...", flow_config.id, flow_config.label);
             }
 
-            let flow = Flow::new(self, flow_config, reg, elcfg)?;
-            {
-                // register all nodes
-                for fnode in flow.get_all_flow_nodes().iter() {
-                    if self.inner.all_flow_nodes.contains_key(&fnode.id()) {
-                        return Err(EdgelinkError::InvalidOperation(format!(
-                            "This flow node already existed: {}",
-                            fnode
-                        ))
-                            .into());
-                    }
-                    self.inner.all_flow_nodes.insert(fnode.id(), fnode.clone());
-                }
+            let flow = Flow::new(self, flow_config, reg)?;
+            log::debug!(
+                "---- The flow (id='{}', label='{}') has been loaded successfully.",
+                flow.id(),
+                flow.name()
+            );
+            self.register_flow(flow)?;
+        }
+        Ok(())
+    }
 
-                log::debug!(
-                    "---- The flow (id='{}', label='{}') has been loaded successfully.",
-                    flow.id(),
-                    flow.name()
-                );
-                //register the flow
-                self.inner.flows.insert(flow.id(), flow);
-            }
+    /// Registers the flow and all its nodes, nothing is registered if any of the nodes already existed.
+    fn register_flow(&self, flow: Flow) -> crate::Result<()> {
+        let fnodes = flow.get_all_flow_nodes();
+        if let Some(fnode) = fnodes.iter().find(|x| self.inner.all_flow_nodes.contains_key(&x.id())) {
+            return Err(EdgelinkError::InvalidOperation(format!("This flow node already existed: {}", fnode)).into());
         }
+        for fnode in fnodes.iter() {
+            self.inner.all_flow_nodes.insert(fnode.id(), fnode.clone());
+        }
+        self.inner.flows.insert(flow.id(), flow);
         Ok(())
     }
 
+    fn unregister_flow(&self, flow: &Flow) {
+        for fnode in flow.get_all_flow_nodes().iter() {
+            self.inner.all_flow_nodes.remove(&fnode.id());
+        }
+        self.inner.flows.remove(&flow.id());
+    }
+
     fn load_global_nodes(&self, node_configs: Vec<RedGlobalNodeConfig>, reg: RegistryHandle) -> crate::Result<()> {
         for global_config in node_configs.into_iter() {
             let node_type_name = global_config.type_name.as_str();
@@ -233,8 +638,12 @@ impl Engine {
     ) -> crate::Result<()> {
         let flow = self.inner.flows.get(&flow_id).as_deref().cloned();
         if let Some(flow) = flow {
-            flow.inject_msg(msg, cancel.clone()).await?;
-            Ok(())
+            msg.write().await.ensure_id();
+            let Some(msg_id) = self.check_msg_id(&flow_id, &msg).await else {
+                return Ok(());
+            };
+            self.start_msg_ttl(&msg).await;
+            self.forget_msg_id_on_error(msg_id, flow.inject_msg(msg, cancel.clone()).await)
         } else {
             Err(EdgelinkError::BadArgument("flow_id")).with_context(|| format!("Can not found flow_id: {}", flow_id))
         }
@@ -266,6 +675,10 @@ impl Engine {
         if self.inner.flows.is_empty() {
             return Err(EdgelinkError::invalid_operation("no flows loaded in the engine."));
         }
+
+        self.inner.context_manager.open_stores().await?;
+        self.inner.context_stores_opened.store(true, Ordering::Release);
+
         for f in self.inner.flows.iter() {
             f.value().start().await?;
         }
@@ -289,19 +702,51 @@ impl Engine {
             i.value().stop().await?;
         }
 
+        self.inner.context_stores_opened.store(false, Ordering::Release);
+        self.inner.context_manager.close_stores().await?;
+
         *shutdown_lock = true;
         //drop(self.stopped_tx);
         log::info!("-- Engine flows stopped.");
         Ok(())
     }
 
-    #[cfg(any(test, feature = "pymod"))]
+    /// Returns `true` only after the engine started and all enabled flows are running.
+    pub fn is_ready(&self) -> bool {
+        self.health().ready
+    }
+
+    pub fn health(&self) -> HealthReport {
+        // The lock is held while starting, stopping or reloading, the engine is not ready then
+        let started = self.inner.shutdown.try_read().map(|x| !*x).unwrap_or(false);
+        let context_stores_opened = self.inner.context_stores_opened.load(Ordering::Acquire);
+        let flows: Vec<FlowHealth> = self
+            .inner
+            .flows
+            .iter()
+            .map(|x| FlowHealth {
+                id: x.id(),
+                label: x.name().to_string(),
+                disabled: x.is_disabled(),
+                running: x.is_running(),
+            })
+            .sorted_by_key(|x| x.id)
+            .collect();
+        let ready = started && context_stores_opened && flows.iter().all(|x| x.disabled || x.running);
+        HealthReport { ready, started, context_stores_opened, flows }
+    }
+
+    #[cfg(any(test, feature = "testing"))]
     pub async fn run_once_with_inject(
         &self,
         expected_msgs: usize,
         timeout: std::time::Duration,
         mut msgs_to_inject: Vec<(ElementId, Msg)>,
     ) -> crate::Result<Vec<Msg>> {
+        // Clear the failures reported in the previous runs before any node starts
+        let mut failures_rx = self.inner.test_failures_rx.lock().await;
+        while failures_rx.try_recv().is_ok() {}
+
         self.start().await?;
 
         let mut count = 0;
@@ -320,9 +765,15 @@ impl Engine {
 
         let result = tokio::time::timeout(timeout, async {
             while !cancel.is_cancelled() && count < expected_msgs {
-                let msg = self.inner.final_msgs_rx.recv_msg(cancel.clone()).await?;
+                let msg = tokio::select! {
+                    msg = self.inner.final_msgs_rx.recv_msg(cancel.clone()) => msg?,
+                    Some(failure) = failures_rx.recv() => {
+                        return Err(EdgelinkError::InvalidOperation(failure).into());
+                    }
+                };
                 count += 1;
-                let msg = msg.unwrap().await;
+                // The last node may still hold the msg to report its completion, so it is copied instead of unwrapped
+                let msg = msg.read().await.clone();
                 received.push(msg);
             }
             cancel.cancel();
@@ -339,15 +790,105 @@ impl Engine {
         }
     }
 
-    #[cfg(any(test, feature = "pymod"))]
+    #[cfg(any(test, feature = "testing"))]
     pub async fn run_once(&self, expected_msgs: usize, timeout: std::time::Duration) -> crate::Result<Vec<Msg>> {
         self.run_once_with_inject(expected_msgs, timeout, Vec::with_capacity(0)).await
     }
 
+    /// Starts the engine, sends the message out from the output port 0 of the specified node, then records every
+    /// step of the message (and its clones) until the timeout elapsed, the engine will be stopped at the end.
+    pub async fn trace_once(
+        &self,
+        node_id: &ElementId,
+        msg: Msg,
+        timeout: std::time::Duration,
+    ) -> crate::Result<Vec<TraceStep>> {
+        let node = self
+            .find_flow_node_by_id(node_id)
+            .ok_or(EdgelinkError::BadArgument("node_id"))
+            .with_context(|| format!("Cannot found the flow node, id='{}'", node_id))?;
+
+        let cancel = CancellationToken::new();
+        let (steps_tx, mut steps_rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut tracers = tokio::task::JoinSet::new();
+        for entry in self.inner.all_flow_nodes.iter() {
+            let traced_node = entry.value().clone();
+            let mut sent_rx = traced_node.get_node().on_sent.subscribe();
+            let steps_tx = steps_tx.clone();
+            let cancel = cancel.clone();
+            tracers.spawn(async move {
+                loop {
+                    tokio::select! {
+                        event = sent_rx.recv() => match event {
+                            Ok(event) => {
+                                let step = TraceStep::new(traced_node.as_ref(), &event).await;
+                                if steps_tx.send(step).is_err() {
+                                    break;
+                                }
+                            }
+                            Err(tokio::sync::broadcast::error::RecvError::Lagged(n)) => {
+                                log::warn!("[TRACE] {} step(s) of node '{}' have been lost", n, traced_node.id());
+                            }
+                            Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                        },
+                        _ = cancel.cancelled() => break,
+                    }
+                }
+            });
+        }
+        drop(steps_tx);
+
+        self.start().await?;
+        let sent = node.fan_out_one(Envelope { port: 0, msg: MsgHandle::new(msg) }, cancel.child_token()).await;
+        if sent.is_ok() {
+            let _ = crate::utils::async_util::delay(timeout, cancel.child_token()).await;
+        }
+        cancel.cancel();
+        while tracers.join_next().await.is_some() {}
+        self.stop().await?;
+        sent?;
+
+        let mut steps = Vec::new();
+        while let Ok(step) = steps_rx.try_recv() {
+            steps.push(step);
+        }
+        steps.sort_by_key(|x| x.at);
+        Ok(steps)
+    }
+
+    /// Routes the error msg not handled by any `catch` node to the `runtime.engine.uncaught_error_handler` node.
+    ///
+    /// Returns `false` if no handler is available, or the error was reported by the handler itself.
+    pub(crate) async fn route_uncaught_error(
+        &self,
+        reporting_node_id: &ElementId,
+        error_msg: Msg,
+        cancel: CancellationToken,
+    ) -> crate::Result<bool> {
+        let handler_id = match self.inner.args.uncaught_error_handler {
+            Some(id) if id != *reporting_node_id => id,
+            _ => return Ok(false),
+        };
+        match self.find_flow_node_by_id(&handler_id) {
+            Some(handler) => {
+                handler.inject_msg(MsgHandle::new(error_msg), cancel).await?;
+                Ok(true)
+            }
+            None => {
+                log::warn!("The uncaught error handler node does not exist: Node(id='{}')", handler_id);
+                Ok(false)
+            }
+        }
+    }
+
     pub fn find_flow_node_by_id(&self, id: &ElementId) -> Option<Arc<dyn FlowNodeBehavior>> {
         self.inner.all_flow_nodes.get(id).map(|x| x.value().clone())
     }
 
+    pub fn get_all_flow_nodes(&self) -> Vec<Arc<dyn FlowNodeBehavior>> {
+        self.inner.all_flow_nodes.iter().map(|x| x.value().clone()).collect()
+    }
+
     pub fn find_flow_node_by_name(&self, name: &str) -> crate::Result<Option<Arc<dyn FlowNodeBehavior>>> {
         for i in self.inner.flows.iter() {
             let flow = i.value();
@@ -369,7 +910,90 @@ impl Engine {
             .find_flow_node_by_id(flow_node_id)
             .ok_or(EdgelinkError::BadArgument("flow_node_id"))
             .with_context(|| format!("Cannot found the flow node, id='{}'", flow_node_id))?;
-        node.inject_msg(msg, cancel).await
+        // The injected message is given an ID like the ones entering a flow of Node-RED
+        msg.write().await.ensure_id();
+        let Some(msg_id) = self.check_msg_id(flow_node_id, &msg).await else {
+            return Ok(());
+        };
+        self.start_msg_ttl(&msg).await;
+        self.forget_msg_id_on_error(msg_id, node.inject_msg(msg, cancel).await)
+    }
+
+    /// Remembers the `_msgid` of the message injected to the node or flow by `EngineArgs::dedup`, returns `None` if
+    /// it is a duplicate to drop, or the remembered id to forget if the injection failed.
+    async fn check_msg_id(&self, target_id: &ElementId, msg: &MsgHandle) -> Option<Option<ElementId>> {
+        let Some(dedup) = self.inner.msg_dedup.as_ref() else {
+            return Some(None);
+        };
+        // The messages without `_msgid` are always new
+        let msg_id = msg.read().await.id();
+        match msg_id {
+            Some(msg_id) if !dedup.check(msg_id, std::time::Instant::now()) => {
+                log::debug!("Dropped the duplicate message injected to '{}': {}", target_id, msg_id);
+                None
+            }
+            msg_id => Some(msg_id),
+        }
+    }
+
+    /// Starts the default TTL for the injected message without a TTL, so the time queued before the first node counts.
+    async fn start_msg_ttl(&self, msg: &MsgHandle) {
+        if let Some(ttl) = self.msg_ttl() {
+            let mut msg_guard = msg.write().await;
+            if msg_guard.expires_at().is_none() {
+                msg_guard.set_ttl(ttl);
+            }
+        }
+    }
+
+    /// Forgets the `_msgid` remembered by `check_msg_id()` if the message was not injected, so it can be retried.
+    fn forget_msg_id_on_error(&self, msg_id: Option<ElementId>, result: crate::Result<()>) -> crate::Result<()> {
+        if let (Err(_), Some(msg_id)) = (&result, msg_id) {
+            self.forget_msg_id(&msg_id);
+        }
+        result
+    }
+
+    /// Forgets the `_msgid` seen by `inject_msg()` or `inject_msg_to_flow()`, so the message can be injected again on
+    /// purpose in the window of `EngineArgs::dedup`, returns `false` if the id was not seen or the deduplication is
+    /// disabled.
+    pub fn forget_msg_id(&self, msg_id: &ElementId) -> bool {
+        self.inner.msg_dedup.as_ref().is_some_and(|x| x.forget(msg_id))
+    }
+
+    /// The default TTL of the messages, see `EngineArgs::msg_ttl_ms`.
+    pub fn msg_ttl(&self) -> Option<std::time::Duration> {
+        self.inner.args.msg_ttl_ms.map(std::time::Duration::from_millis)
+    }
+
+    /// The deterministic clock for testing, see `EngineArgs::test_clock`.
+    pub fn test_clock(&self) -> Option<&TestClockArgs> {
+        self.inner.args.test_clock.as_ref()
+    }
+
+    /// The settings of the flows to load, see `reload_config()`.
+    pub fn flow_args(&self) -> FlowArgs {
+        self.inner.flow_args.read().expect("`flow_args` read lock").clone()
+    }
+
+    /// The HTTP settings of the `http in` and `http request` nodes, see `EngineArgs::http`.
+    pub fn http_args(&self) -> &HttpArgs {
+        &self.inner.args.http
+    }
+
+    /// The settings of the WebSocket server, see `EngineArgs::websocket`.
+    pub fn websocket_args(&self) -> &WebSocketArgs {
+        &self.inner.args.websocket
+    }
+
+    /// Whether to skip the verification of the server certificates, see `EngineArgs::tls_insecure`.
+    pub fn tls_insecure(&self) -> bool {
+        self.inner.args.tls_insecure
+    }
+
+    /// Encodes the messages received by `run_once_with_inject()` in the format of `EngineArgs::output_format`.
+    pub fn encode_msgs(&self, msgs: &[Msg]) -> crate::Result<EncodedMsgs> {
+        self.inner.args.output_format.encode(msgs)
     }
 
     pub fn get_envs(&self) -> Envs {
@@ -388,11 +1012,32 @@ impl Engine {
         self.inner.context.clone()
     }
 
-    #[cfg(any(test, feature = "pymod"))]
+    /// The registry of the metrics created by the flow nodes, exported with the node metrics by the `/metrics`
+    /// endpoint.
+    #[cfg(feature = "metrics")]
+    pub fn metrics_registry(&self) -> &prometheus::Registry {
+        &self.inner.metrics_registry
+    }
+
+    /// The state of the type shared by the nodes of this engine, e.g. the HTTP servers of the `http in` nodes,
+    /// it is created on the first call.
+    pub fn shared_state<T: Default + Send + Sync + 'static>(&self) -> Arc<T> {
+        let state = self.inner.shared_states.entry(TypeId::of::<T>()).or_insert_with(|| Arc::new(T::default())).clone();
+        state.downcast::<T>().expect("The shared state is keyed by its type")
+    }
+
+    #[cfg(any(test, feature = "testing"))]
     pub fn recv_final_msg(&self, msg: MsgHandle) -> crate::Result<()> {
         self.inner.final_msgs_tx.send(msg)?;
         Ok(())
     }
+
+    /// Fails the running `run_once()` or `run_once_with_inject()` immediately with the reason.
+    #[cfg(any(test, feature = "testing"))]
+    pub fn report_test_failure(&self, reason: String) -> crate::Result<()> {
+        self.inner.test_failures_tx.send(reason)?;
+        Ok(())
+    }
 }
 
 impl std::fmt::Debug for InnerEngine {
@@ -402,6 +1047,88 @@ impl std::fmt::Debug for InnerEngine {
     }
 }
 
+/// The digests of the elements in `flows.json`, grouped by the tabs and subflows containing them.
+///
+/// The digests are calculated from the original JSON, because the loader copies the subflows with new random IDs.
+#[derive(Debug, Clone, Default)]
+struct FlowsDigest {
+    /// The digest of every tab or subflow, including the subflows used by its subflow instance nodes
+    flows: HashMap<ElementId, u64>,
+    /// The digest of the global nodes
+    globals: u64,
+}
+
+impl FlowsDigest {
+    fn new(json: &serde_json::Value) -> Self {
+        let elements = json.as_array().map(|x| x.as_slice()).unwrap_or_default();
+        let mut containers: HashMap<&str, Vec<&serde_json::Value>> = HashMap::new();
+        let mut globals_hasher = DefaultHasher::new();
+        for element in elements.iter() {
+            let type_name = element.get(TYPE_STR).and_then(|x| x.as_str()).unwrap_or_default();
+            if type_name == TAB_STR || type_name == SUB_FLOW_TYPE {
+                let id = element.get(ID_STR).and_then(|x| x.as_str()).unwrap_or_default();
+                containers.entry(id).or_default().insert(0, element);
+            } else if let Some(z) = element.get("z").and_then(|x| x.as_str()) {
+                containers.entry(z).or_default().push(element);
+            } else {
+                element.to_string().hash(&mut globals_hasher);
+            }
+        }
+
+        let mut flows = HashMap::with_capacity(containers.len());
+        for id in containers.keys() {
+            if let Some(flow_id) = json::helpers::parse_red_id_str(id) {
+                flows.insert(flow_id, Self::container_digest(id, &containers, &mut HashSet::new()));
+            }
+        }
+        Self { flows, globals: globals_hasher.finish() }
+    }
+
+    fn container_digest<'a>(
+        id: &'a str,
+        containers: &HashMap<&'a str, Vec<&'a serde_json::Value>>,
+        visiting: &mut HashSet<&'a str>,
+    ) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        // Guards against the subflows using themselves
+        if visiting.insert(id) {
+            for element in containers.get(id).into_iter().flatten() {
+                element.to_string().hash(&mut hasher);
+                let type_name = element.get(TYPE_STR).and_then(|x| x.as_str()).unwrap_or_default();
+                if let Some((SUB_FLOW_TYPE, subflow_id)) = type_name.split_once(':') {
+                    Self::container_digest(subflow_id, containers, visiting).hash(&mut hasher);
+                }
+            }
+            visiting.remove(id);
+        }
+        hasher.finish()
+    }
+}
+
+/// Maps every flow to the flow it belongs to, i.e. the copy of a subflow belongs to the flow of its instance node.
+fn root_flow_ids(flows: &[RedFlowConfig]) -> HashMap<ElementId, ElementId> {
+    let node_flows: HashMap<ElementId, ElementId> =
+        flows.iter().flat_map(|f| f.nodes.iter().map(move |n| (n.id, f.id))).collect();
+    let parents: HashMap<ElementId, ElementId> = flows
+        .iter()
+        .filter_map(|f| f.subflow_node_id.and_then(|n| node_flows.get(&n)).map(|parent| (f.id, *parent)))
+        .collect();
+    flows
+        .iter()
+        .map(|f| {
+            let mut root = f.id;
+            // Bounded in case of a malformed cycle
+            for _ in 0..flows.len() {
+                match parents.get(&root) {
+                    Some(parent) if *parent != root => root = *parent,
+                    _ => break,
+                }
+            }
+            (f.id, root)
+        })
+        .collect()
+}
+
 #[cfg(test)]
 pub fn build_test_engine(flows_json: serde_json::Value) -> crate::Result<Engine> {
     let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
@@ -479,6 +1206,60 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_engine_should_be_ready_only_after_started() {
+        let flows_json = json!([
+            { "id": "100", "type": "tab", "label": "Flow 1" },
+            { "id": "200", "type": "tab", "label": "Flow 2", "disabled": true },
+            { "id": "1", "z": "100", "type": "test-once" }
+        ]);
+        let engine = build_test_engine(flows_json).unwrap();
+        assert!(!engine.is_ready());
+        let report = engine.health();
+        assert!(!report.started);
+        assert_eq!(report.flows.len(), 2);
+        assert!(report.flows.iter().all(|x| !x.running));
+
+        engine.start().await.unwrap();
+        assert!(engine.is_ready());
+        let report = engine.health();
+        assert!(report.context_stores_opened);
+        assert_eq!(report.flows[0].label, "Flow 1");
+        assert!(report.flows[0].running);
+        assert!(report.flows[1].disabled);
+
+        engine.stop().await.unwrap();
+        assert!(!engine.is_ready());
+        assert!(engine.health().flows.iter().all(|x| !x.running));
+    }
+
+    #[tokio::test]
+    async fn test_flow_node_should_read_value_from_its_config_node() {
+        // The config node is deliberately placed after the flow node referencing it
+        let flows_json = json!([
+            { "id": "100", "type": "tab", "label": "Flow 1" },
+            { "id": "1", "z": "100", "type": "test-config-reader", "config": "900", "wires": [["2"]] },
+            { "id": "2", "z": "100", "type": "test-once" },
+            { "id": "900", "type": "test-config", "value": {"host": "localhost", "port": 1883} }
+        ]);
+        let engine = build_test_engine(flows_json).unwrap();
+        assert_eq!(engine.get_global_node(&ElementId::with_u64(0x900)).unwrap().type_str(), "test-config");
+
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": null}]])).unwrap();
+        let msgs = engine.run_once_with_inject(1, Duration::from_millis(200), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0]["payload"], Variant::deserialize(json!({"host": "localhost", "port": 1883})).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_missing_config_node_should_fail_to_build() {
+        let flows_json = json!([
+            { "id": "100", "type": "tab", "label": "Flow 1" },
+            { "id": "1", "z": "100", "type": "test-config-reader", "config": "901", "wires": [] }
+        ]);
+        let err = build_test_engine(flows_json).unwrap_err();
+        assert!(format!("{:?}", err).contains("Cannot find the global configuration node: id='0000000000000901'"));
+    }
+
     #[tokio::test]
     async fn test_it_should_load_and_run_simple_json_without_configuration() {
         let flows_json = make_simple_flows_json();
@@ -499,6 +1280,571 @@ mod tests {
         assert_eq!(msg.get("payload").unwrap(), &Variant::from(123 * 2));
     }
 
+    #[tokio::test]
+    async fn test_it_should_trace_msg_through_nodes() {
+        let flows_json = json!([
+            { "id": "100", "type": "tab", "label": "Flow 1" },
+            { "id": "1", "type": "inject", "z": "100", "props": [{ "p": "payload" }],
+                "repeat": "", "once": false, "payload": "foo", "payloadType": "str", "wires": [["2"]] },
+            { "id": "2", "type": "change", "z": "100", "rules": [
+                { "t": "set", "p": "payload", "pt": "msg", "to": "bar", "tot": "str" }
+            ], "wires": [["3", "4"]] },
+            { "id": "3", "z": "100", "type": "test-once" },
+            { "id": "4", "z": "100", "type": "test-once" }
+        ]);
+        let engine = build_test_engine(flows_json).unwrap();
+        let msg = Msg::deserialize(json!({"payload": "foo"})).unwrap();
+        let inject_id = ElementId::with_u64(1);
+        let started_at = std::time::SystemTime::now();
+        let steps = engine.trace_once(&inject_id, msg, Duration::from_millis(200)).await.unwrap();
+
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[0].node_id, inject_id);
+        assert_eq!(steps[0].node_type, "inject");
+        assert_eq!(steps[0].port, 0);
+        assert_eq!(steps[0].targets, vec![ElementId::with_u64(2)]);
+        assert_eq!(steps[0].msg["payload"], Variant::from("foo"));
+        assert!(!steps[0].is_branch());
+
+        assert_eq!(steps[1].node_id, ElementId::with_u64(2));
+        assert_eq!(steps[1].node_type, "change");
+        assert_eq!(steps[1].port, 0);
+        assert_eq!(steps[1].targets, vec![ElementId::with_u64(3), ElementId::with_u64(4)]);
+        assert_eq!(steps[1].msg["payload"], Variant::from("bar"));
+        assert_eq!(steps[1].msg_id, steps[0].msg_id);
+        assert!(steps[1].is_branch());
+
+        // Every hop is stamped in the order of the path
+        assert!(started_at <= steps[0].timestamp);
+        assert!(steps[0].timestamp <= steps[1].timestamp);
+    }
+
+    #[tokio::test]
+    async fn test_ordered_node_should_preserve_end_to_end_order() {
+        // The slow wire is rate limited, the fast wire would overtake it if the node were not ordered
+        let flows_json = json!([
+            { "id": "100", "type": "tab", "label": "Flow 1" },
+            { "id": "1", "z": "100", "type": "junction", "ordered": true, "wires": [["2", "3"]] },
+            { "id": "2", "z": "100", "type": "delay", "pauseType": "rate", "rate": "1", "nbRateUnits": "0.01",
+                "rateUnits": "second", "drop": false, "wires": [["4"]] },
+            { "id": "3", "z": "100", "type": "change", "wires": [["5"]], "rules": [
+                {"t": "set", "p": "path", "pt": "msg", "to": "fast", "tot": "str"}
+            ]},
+            { "id": "4", "z": "100", "type": "change", "wires": [["5"]], "rules": [
+                {"t": "set", "p": "path", "pt": "msg", "to": "slow", "tot": "str"}
+            ]},
+            { "id": "5", "z": "100", "type": "test-once" }
+        ]);
+        let count: u64 = 30;
+        let msgs_to_inject: Vec<(ElementId, Msg)> =
+            (0..count).map(|i| (ElementId::with_u64(1), MsgBuilder::new().payload(i).build().unwrap())).collect();
+
+        let engine = build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(count as usize * 2, Duration::from_secs(3), msgs_to_inject).await.unwrap();
+        let results: Vec<(Variant, Variant)> = msgs.iter().map(|x| (x["payload"].clone(), x["path"].clone())).collect();
+        let expected: Vec<(Variant, Variant)> =
+            (0..count).flat_map(|i| [(i.into(), "slow".into()), (i.into(), "fast".into())]).collect();
+        assert_eq!(results, expected);
+    }
+
+    #[tokio::test]
+    async fn test_ordered_node_should_not_wait_for_the_msgs_kept_downstream() {
+        // The join node keeps the last msg until the group is complete, but it reports the completion of every msg
+        let flows_json = json!([
+            { "id": "100", "type": "tab", "label": "Flow 1" },
+            { "id": "1", "z": "100", "type": "junction", "ordered": true, "wires": [["2"]] },
+            { "id": "2", "z": "100", "type": "join", "mode": "custom", "build": "array", "count": "3",
+                "wires": [["3"]] },
+            { "id": "3", "z": "100", "type": "test-once" }
+        ]);
+        let msgs_to_inject: Vec<(ElementId, Msg)> =
+            (0..3).map(|i| (ElementId::with_u64(1), MsgBuilder::new().payload(i).build().unwrap())).collect();
+
+        let engine = build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(1, Duration::from_secs(1), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0]["payload"], Variant::from(json!([0, 1, 2])));
+    }
+
+    #[tokio::test]
+    async fn test_overflow_policy_should_apply_to_wires_into_node() {
+        let flows_json = json!([
+            { "id": "100", "type": "tab" },
+            { "id": "1", "z": "100", "type": "junction", "wires": [["2", "3"]] },
+            { "id": "2", "z": "100", "type": "test-once", "overflow": "drop_new" },
+            { "id": "3", "z": "100", "type": "test-once" }
+        ]);
+        let engine = build_test_engine(flows_json).unwrap();
+        let source = engine.find_flow_node_by_id(&ElementId::with_u64(1)).unwrap();
+        let wires = &source.get_node().ports[0].wires;
+        assert_eq!(wires[0].overflow, OverflowPolicy::DropNew);
+        assert_eq!(wires[1].overflow, OverflowPolicy::Block);
+
+        // The engine is not started, so nobody takes the messages from the channel
+        for i in 0..40 {
+            let msg = MsgHandle::with_payload(Variant::from(i));
+            wires[0].tx(msg, CancellationToken::new()).await.unwrap();
+        }
+        assert!(wires[0].dropped_count() > 0);
+        assert_eq!(wires[1].dropped_count(), 0);
+    }
+
+    mod test_barrier {
+        use std::sync::Arc;
+
+        use async_trait::async_trait;
+        use tokio_util::sync::CancellationToken;
+
+        use crate::runtime::context::Context;
+        use crate::runtime::flow::Flow;
+        use crate::runtime::model::json::RedFlowNodeConfig;
+        use crate::runtime::model::*;
+        use crate::runtime::nodes::*;
+        use edgelink_macro::*;
+
+        /// Holds every message until `concurrency` messages are being processed, then sends them all out, so nothing
+        /// would be sent if the messages were processed one by one.
+        #[derive(Debug)]
+        #[flow_node("test-barrier")]
+        struct TestBarrierNode {
+            base: FlowNode,
+            barrier: tokio::sync::Barrier,
+        }
+
+        impl TestBarrierNode {
+            fn build(
+                _flow: &Flow,
+                base_node: FlowNode,
+                _config: &RedFlowNodeConfig,
+            ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+                let barrier = tokio::sync::Barrier::new(base_node.concurrency);
+                Ok(Box::new(TestBarrierNode { base: base_node, barrier }))
+            }
+        }
+
+        #[async_trait]
+        impl FlowNodeBehavior for TestBarrierNode {
+            fn get_node(&self) -> &FlowNode {
+                &self.base
+            }
+
+            async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+                while !stop_token.is_cancelled() {
+                    let cancel = stop_token.clone();
+                    with_uow_concurrent(&self, cancel.child_token(), |node, msg| async move {
+                        node.barrier.wait().await;
+                        node.fan_out_one(Envelope { port: 0, msg }, cancel.child_token()).await
+                    })
+                    .await;
+                }
+                wait_uows_completed(self.as_ref()).await;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_should_process_msgs_in_parallel() {
+        let flows_json = json!([
+            { "id": "100", "type": "tab" },
+            { "id": "1", "z": "100", "type": "test-barrier", "concurrency": 4, "wires": [["2"]] },
+            { "id": "2", "z": "100", "type": "change", "concurrency": 4, "wires": [["3"]], "rules": [
+                { "t": "set", "p": "changed", "pt": "msg", "to": "true", "tot": "bool" }
+            ] },
+            { "id": "3", "z": "100", "type": "test-once" },
+            { "id": "4", "z": "100", "type": "complete", "scope": ["1", "2"], "wires": [["5"]] },
+            { "id": "5", "z": "100", "type": "test-once" }
+        ]);
+        let engine = build_test_engine(flows_json).unwrap();
+        let node = engine.find_flow_node_by_id(&ElementId::with_u64(1)).unwrap();
+        assert_eq!(node.get_node().concurrency, 4);
+
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"payload": 0}], ["1", {"payload": 1}], ["1", {"payload": 2}], ["1", {"payload": 3}]
+        ]))
+        .unwrap();
+        // Four sent messages and eight completions, the barrier would never open if they were processed serially
+        let msgs = engine.run_once_with_inject(12, Duration::from_secs(2), msgs_to_inject).await.unwrap();
+
+        // The messages of the change node and its completions, the completions of the barrier may be seen changed
+        let changed = msgs.iter().filter(|x| x.get("changed") == Some(&Variant::Bool(true))).count();
+        assert!(changed >= 8);
+        let mut payloads: Vec<i64> = msgs.iter().map(|x| x["payload"].as_i64().unwrap()).collect();
+        payloads.sort();
+        assert_eq!(payloads, vec![0, 0, 0, 1, 1, 1, 2, 2, 2, 3, 3, 3]);
+    }
+
+    #[test]
+    fn test_concurrency_should_be_positive() {
+        let flows_json = json!([
+            { "id": "100", "type": "tab" },
+            { "id": "1", "z": "100", "type": "test-once", "concurrency": 0 }
+        ]);
+        assert!(build_test_engine(flows_json).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_uncaught_error_should_reach_the_global_handler() {
+        let flows_json = json!([
+            { "id": "100", "type": "tab" },
+            { "id": "1", "z": "100", "type": "assert", "valueType": "string", "wires": [["2"]] },
+            { "id": "2", "z": "100", "type": "test-once" },
+            { "id": "200", "type": "tab" },
+            { "id": "9", "z": "200", "type": "test-once" }
+        ]);
+        let cfg = config::Config::builder()
+            .add_source(config::File::from_str(
+                "[runtime.engine]\nuncaught_error_handler = \"9\"\n",
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap();
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let engine = Engine::with_json(&registry, flows_json, Some(&cfg)).unwrap();
+        let msgs_to_inject =
+            Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": 42, "topic": "t"}]])).unwrap();
+        let msgs = engine.run_once_with_inject(1, Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        let msg = &msgs[0];
+        assert_eq!(msg["payload"], Variant::from(42));
+        assert_eq!(msg["topic"], Variant::from("t"));
+        let error_message = msg.get_nav_stripped("error.message").unwrap().as_str().unwrap();
+        assert!(error_message.starts_with("Assertion failed at 'msg.payload'"));
+        assert_eq!(msg.get_nav_stripped("error.source.id"), Some(&Variant::from("0000000000000001")));
+        assert_eq!(msg.get_nav_stripped("error.source.type"), Some(&Variant::from("assert")));
+        assert_eq!(msg.get_nav_stripped("error.source.count"), Some(&Variant::from(1)));
+    }
+
+    #[tokio::test]
+    async fn test_msg_delayed_past_its_ttl_should_be_dropped() {
+        // The second msg of the topic is delayed about 0.2s by the delay node, the change node drops it
+        let flows_json = json!([
+            { "id": "100", "type": "tab" },
+            { "id": "1", "z": "100", "type": "delay", "pauseType": "queue",
+                "rate": "1", "nbRateUnits": "0.2", "rateUnits": "second", "drop": false, "wires": [["2"]] },
+            { "id": "2", "z": "100", "type": "change", "rules": [
+                { "t": "set", "p": "passed", "pt": "msg", "to": "true", "tot": "bool" }
+            ], "wires": [["3"]] },
+            { "id": "3", "z": "100", "type": "test-once" }
+        ]);
+        let make_msgs = || {
+            Vec::<(ElementId, Msg)>::deserialize(json!([
+                ["1", {"topic": "a", "payload": "a1"}],
+                ["1", {"topic": "a", "payload": "a2"}],
+            ]))
+            .unwrap()
+        };
+
+        // The messages never expire by default
+        let engine = build_test_engine(flows_json.clone()).unwrap();
+        let msgs = engine.run_once_with_inject(2, Duration::from_secs_f64(0.6), make_msgs()).await.unwrap();
+        assert_eq!(msgs.len(), 2);
+
+        let cfg = config::Config::builder()
+            .add_source(config::File::from_str("[runtime.engine]\nmsg_ttl_ms = 100\n", config::FileFormat::Toml))
+            .build()
+            .unwrap();
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let engine = Engine::with_json(&registry, flows_json.clone(), Some(&cfg)).unwrap();
+        let msgs = engine.run_once_with_inject(1, Duration::from_secs_f64(0.6), make_msgs()).await.unwrap();
+        assert_eq!(msgs[0]["payload"], Variant::from("a1"));
+        assert_eq!(msgs[0]["passed"], Variant::from(true));
+
+        let engine = Engine::with_json(&registry, flows_json, Some(&cfg)).unwrap();
+        assert!(engine.run_once_with_inject(2, Duration::from_secs_f64(0.6), make_msgs()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_msg_dropped_past_its_ttl_should_be_completed() {
+        // Same as above, but the complete node reports both msgs, including the one dropped by the change node
+        let flows_json = json!([
+            { "id": "100", "type": "tab" },
+            { "id": "1", "z": "100", "type": "delay", "pauseType": "queue",
+                "rate": "1", "nbRateUnits": "0.2", "rateUnits": "second", "drop": false, "wires": [["2"]] },
+            { "id": "2", "z": "100", "type": "change", "rules": [
+                { "t": "set", "p": "passed", "pt": "msg", "to": "true", "tot": "bool" }
+            ], "wires": [] },
+            { "id": "3", "z": "100", "type": "complete", "scope": ["2"], "wires": [["4"]] },
+            { "id": "4", "z": "100", "type": "test-once" }
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"topic": "a", "payload": "a1"}],
+            ["1", {"topic": "a", "payload": "a2"}],
+        ]))
+        .unwrap();
+
+        let cfg = config::Config::builder()
+            .add_source(config::File::from_str("[runtime.engine]\nmsg_ttl_ms = 100\n", config::FileFormat::Toml))
+            .build()
+            .unwrap();
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let engine = Engine::with_json(&registry, flows_json, Some(&cfg)).unwrap();
+        let msgs = engine.run_once_with_inject(2, Duration::from_secs_f64(0.6), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0]["payload"], Variant::from("a1"));
+        assert_eq!(msgs[0]["passed"], Variant::from(true));
+        assert_eq!(msgs[1]["payload"], Variant::from("a2"));
+        assert!(!msgs[1].contains("passed"));
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_msgid_should_be_dropped_in_dedup_window() {
+        let flows_json = json!([
+            { "id": "100", "type": "tab" },
+            { "id": "1", "z": "100", "type": "junction", "wires": [["2"]] },
+            { "id": "2", "z": "100", "type": "test-once" }
+        ]);
+        let make_msgs = || {
+            Vec::<(ElementId, Msg)>::deserialize(json!([
+                ["1", {"_msgid": "0000000000000a01", "payload": 1}],
+                ["1", {"_msgid": "0000000000000a01", "payload": 2}],
+                ["1", {"payload": 3}],
+                ["1", {"_msgid": "0000000000000a02", "payload": 4}],
+            ]))
+            .unwrap()
+        };
+        let payloads_of = |msgs: &[Msg]| msgs.iter().map(|x| x["payload"].as_i64().unwrap()).collect::<Vec<_>>();
+
+        // Disabled by default
+        let engine = build_test_engine(flows_json.clone()).unwrap();
+        let msgs = engine.run_once_with_inject(4, Duration::from_secs_f64(0.4), make_msgs()).await.unwrap();
+        assert_eq!(payloads_of(&msgs), vec![1, 2, 3, 4]);
+        assert!(!engine.forget_msg_id(&ElementId::with_u64(0xa01)));
+
+        let cfg = config::Config::builder()
+            .add_source(config::File::from_str(
+                "[runtime.engine]\ndedup = { window_ms = 60000 }\n",
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap();
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let engine = Engine::with_json(&registry, flows_json, Some(&cfg)).unwrap();
+        let msgs = engine.run_once_with_inject(3, Duration::from_secs_f64(0.4), make_msgs()).await.unwrap();
+        assert_eq!(payloads_of(&msgs), vec![1, 3, 4]);
+        assert!(engine.forget_msg_id(&ElementId::with_u64(0xa01)));
+
+        // The id of a message failed to inject is not remembered, so the retry is not dropped
+        let msg = MsgHandle::new(Msg::deserialize(json!({"_msgid": "0000000000000a03", "payload": 5})).unwrap());
+        let res = engine.inject_msg_to_flow(ElementId::with_u64(0x100), msg, CancellationToken::new()).await;
+        assert!(res.is_err());
+        assert!(!engine.forget_msg_id(&ElementId::with_u64(0xa03)));
+    }
+
+    #[tokio::test]
+    async fn test_reload_config_should_apply_queue_capacity_to_new_nodes() {
+        let make_cfg = |toml: &str| {
+            config::Config::builder()
+                .add_source(config::File::from_str(toml, config::FileFormat::Toml))
+                .build()
+                .unwrap()
+        };
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let engine = build_test_engine(make_simple_flows_json()).unwrap();
+        let node = engine.find_flow_node_by_id(&ElementId::with_u64(0x2)).unwrap();
+        assert_eq!(node.get_node().msg_tx.max_capacity(), 32);
+
+        let cfg = make_cfg("[runtime.flow]\nnode_msg_queue_capacity = 16\n");
+        let engine = Engine::with_json(&registry, make_simple_flows_json(), Some(&cfg)).unwrap();
+        let capacity_of =
+            |id: u64| engine.find_flow_node_by_id(&ElementId::with_u64(id)).unwrap().get_node().msg_tx.max_capacity();
+        assert_eq!(capacity_of(0x2), 16);
+
+        let cfg = make_cfg(
+            "[runtime.flow]\nnode_msg_queue_capacity = 4\n\n[runtime.engine.http]\nlisten = \"127.0.0.1:18800\"\n",
+        );
+        let report = engine.reload_config(&cfg).await.unwrap();
+        assert_eq!(report.applied, vec!["runtime.flow.node_msg_queue_capacity".to_string()]);
+        assert_eq!(report.requires_restart, vec!["runtime.engine.http".to_string()]);
+        assert_eq!(engine.http_args().listen, "0.0.0.0:1880");
+
+        // The new nodes get the new capacity, the unchanged flow keeps its nodes
+        let mut flows_json = make_simple_flows_json();
+        flows_json.as_array_mut().unwrap().extend([
+            json!({ "id": "200", "type": "tab", "label": "Flow 2" }),
+            json!({ "id": "5", "z": "200", "type": "test-once" }),
+        ]);
+        engine.reload_flows(flows_json).await.unwrap();
+        assert_eq!(capacity_of(0x5), 4);
+        assert_eq!(capacity_of(0x2), 16);
+
+        // Nothing more to apply, the changed HTTP settings still wait for the restart
+        let report = engine.reload_config(&cfg).await.unwrap();
+        assert!(report.applied.is_empty());
+        assert_eq!(report.requires_restart, vec!["runtime.engine.http".to_string()]);
+        assert!(engine.reload_config(&make_cfg("[runtime.flow]\nnode_msg_queue_capacity = 0\n")).await.is_err());
+        assert_eq!(engine.flow_args().node_msg_queue_capacity, 4);
+    }
+
+    #[tokio::test]
+    async fn test_reload_config_should_replace_changed_context_stores() {
+        let make_cfg = |stores: &str| {
+            let toml = format!("[runtime.context]\ndefault = \"memory\"\n\n[runtime.context.stores]\n{}\n", stores);
+            config::Config::builder()
+                .add_source(config::File::from_str(&toml, config::FileFormat::Toml))
+                .build()
+                .unwrap()
+        };
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let cfg = make_cfg("memory = { provider = \"memory\" }");
+        let engine = Engine::with_json(&registry, make_simple_flows_json(), Some(&cfg)).unwrap();
+        engine.start().await.unwrap();
+        let context = engine.context();
+        context.set_one(None, "foo", Some(Variant::from(1)), &[]).await.unwrap();
+
+        // The unchanged store keeps its values, the new store is available to the existing contexts
+        let cfg = make_cfg("memory = { provider = \"memory\" }\nmemory2 = { provider = \"memory\" }");
+        let report = engine.reload_config(&cfg).await.unwrap();
+        assert_eq!(report.applied, vec!["runtime.context".to_string()]);
+        assert_eq!(context.get_one(None, "foo", &[]).await, Some(Variant::from(1)));
+        context.set_one(Some("memory2"), "bar", Some(Variant::from(2)), &[]).await.unwrap();
+        assert_eq!(context.get_one(Some("memory2"), "bar", &[]).await, Some(Variant::from(2)));
+
+        // The removed store is no longer available
+        let cfg = make_cfg("memory = { provider = \"memory\" }");
+        assert!(!engine.reload_config(&cfg).await.unwrap().is_empty());
+        assert_eq!(context.get_one(Some("memory2"), "bar", &[]).await, None);
+
+        // Nothing is changed by the invalid settings
+        let cfg = make_cfg("memory = { provider = \"no-such-provider\" }");
+        assert!(engine.reload_config(&cfg).await.is_err());
+        assert_eq!(context.get_one(None, "foo", &[]).await, Some(Variant::from(1)));
+
+        engine.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_exported_flows_should_reload_to_equivalent_engine() {
+        let flows_json = json!([
+            { "id": "100", "type": "tab", "label": "Flow 1", "info": "main" },
+            { "id": "a0", "z": "100", "type": "group", "name": "sinks", "nodes": ["2"] },
+            { "id": "1", "z": "100", "type": "subflow:200", "name": "sub", "x": 10, "y": 20, "wires": [["2"]] },
+            { "id": "2", "z": "100", "g": "a0", "type": "test-once" },
+            { "id": "200", "type": "subflow", "name": "Subflow", "info": "",
+                "in": [{ "x": 50, "y": 30, "wires": [{ "id": "3" }] }],
+                "out": [{ "x": 300, "y": 30, "wires": [{ "id": "4", "port": 0 }] }] },
+            { "id": "3", "z": "200", "type": "test-config-reader", "config": "900", "wires": [["4"]] },
+            { "id": "4", "z": "200", "type": "change", "rules": [
+                { "t": "set", "p": "topic", "pt": "msg", "to": "sub", "tot": "str" }
+            ], "wires": [] },
+            { "id": "900", "type": "test-config", "value": "from config" }
+        ]);
+        let engine = build_test_engine(flows_json).unwrap();
+        let exported = engine.export_flows();
+
+        let find = |flows: &serde_json::Value, id: u64| {
+            let id = ElementId::with_u64(id).to_string();
+            flows.as_array().unwrap().iter().find(|x| x["id"] == id.as_str()).cloned().unwrap()
+        };
+        // The subflow copy and its children should be exported with the original ids
+        assert_eq!(exported.as_array().unwrap().len(), 8);
+        assert_eq!(find(&exported, 0x1)["type"], "subflow:0000000000000200");
+        assert_eq!(find(&exported, 0x1)["x"], 10);
+        assert_eq!(find(&exported, 0x2)["g"], "00000000000000a0");
+        let subflow = find(&exported, 0x200);
+        assert_eq!(subflow["name"], "Subflow");
+        assert_eq!(subflow["in"][0]["wires"][0]["id"], "0000000000000003");
+        assert_eq!(subflow["in"][0]["x"], 50);
+        assert_eq!(subflow["out"][0]["wires"][0]["id"], "0000000000000004");
+        assert_eq!(find(&exported, 0x3)["z"], "0000000000000200");
+        assert_eq!(find(&exported, 0x3)["wires"], json!([["0000000000000004"]]));
+        assert_eq!(find(&exported, 0x4)["rules"][0]["to"], "sub");
+        assert_eq!(find(&exported, 0x900)["value"], "from config");
+
+        // And the export should be reloaded to an engine working the same
+        let reloaded = build_test_engine(exported.clone()).unwrap();
+        let sorted = |flows: serde_json::Value| {
+            let mut items = flows.as_array().unwrap().clone();
+            items.sort_by_key(|x| x["id"].as_str().unwrap().to_string());
+            items
+        };
+        assert_eq!(sorted(reloaded.export_flows()), sorted(exported));
+        for engine in [engine, reloaded] {
+            let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": null}]])).unwrap();
+            let msgs = engine.run_once_with_inject(1, Duration::from_millis(200), msgs_to_inject).await.unwrap();
+            assert_eq!(msgs[0]["payload"], Variant::from("from config"));
+            assert_eq!(msgs[0]["topic"], Variant::from("sub"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reload_flows_should_only_restart_changed_flows() {
+        let make_flows_json = |rule_value: &str, subflow_value: &str| {
+            json!([
+                { "id": "100", "type": "tab" },
+                { "id": "1", "z": "100", "type": "subflow:300", "wires": [["2"]] },
+                { "id": "2", "z": "100", "type": "test-once" },
+                { "id": "200", "type": "tab" },
+                { "id": "5", "z": "200", "type": "change", "wires": [["6"]], "rules": [
+                    { "t": "set", "p": "topic", "pt": "msg", "to": rule_value, "tot": "str" }
+                ] },
+                { "id": "6", "z": "200", "type": "test-once" },
+                { "id": "300", "type": "subflow", "name": "Subflow",
+                    "in": [{ "wires": [{ "id": "3" }] }], "out": [{ "wires": [{ "id": "3", "port": 0 }] }] },
+                { "id": "3", "z": "300", "type": "change", "wires": [], "rules": [
+                    { "t": "set", "p": "topic", "pt": "msg", "to": subflow_value, "tot": "str" }
+                ] }
+            ])
+        };
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let engine = Engine::with_json(&registry, make_flows_json("a", "sub"), None).unwrap();
+        engine.start().await.unwrap();
+        let flow1 = engine.get_flow(&ElementId::with_u64(0x100)).unwrap();
+        let flow2 = engine.get_flow(&ElementId::with_u64(0x200)).unwrap();
+        let subflow_ids: Vec<ElementId> =
+            engine.health().flows.iter().map(|x| x.id).filter(|x| *x != flow1.id() && *x != flow2.id()).collect();
+        assert_eq!(subflow_ids.len(), 1);
+
+        // Only the modified flow should be restarted
+        engine.reload_flows(make_flows_json("b", "sub")).await.unwrap();
+        assert!(flow1.is_running());
+        assert!(!flow2.is_running());
+        assert!(engine.get_flow(&ElementId::with_u64(0x200)).unwrap().is_running());
+        assert!(engine.get_flow(&subflow_ids[0]).unwrap().is_running());
+        assert!(engine.is_ready());
+
+        // The flow using the modified subflow should be restarted with a new copy of the subflow
+        engine.reload_flows(make_flows_json("b", "sub2")).await.unwrap();
+        assert!(!flow1.is_running());
+        assert!(engine.get_flow(&subflow_ids[0]).is_none());
+        assert!(engine.get_flow(&ElementId::with_u64(0x100)).unwrap().is_running());
+        assert_eq!(engine.health().flows.len(), 3);
+        assert!(engine.is_ready());
+
+        engine.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_failed_reload_should_keep_the_previous_flows() {
+        let make_flows_json = |schema_type: &str| {
+            json!([
+                { "id": "100", "type": "tab" },
+                { "id": "1", "z": "100", "type": "test-once" },
+                { "id": "200", "type": "tab" },
+                { "id": "2", "z": "200", "type": "json", "schema": { "type": schema_type }, "wires": [["3"]] },
+                { "id": "3", "z": "200", "type": "test-once" }
+            ])
+        };
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let engine = Engine::with_json(&registry, make_flows_json("object"), None).unwrap();
+        engine.start().await.unwrap();
+        let flow2 = engine.get_flow(&ElementId::with_u64(0x200)).unwrap();
+        let node2 = engine.find_flow_node_by_id(&ElementId::with_u64(2)).unwrap();
+        let exported = engine.export_flows();
+
+        // The JSON node with a bad schema cannot be built
+        assert!(engine.reload_flows(make_flows_json("strnig")).await.is_err());
+        assert!(flow2.is_running());
+        assert!(Arc::ptr_eq(&engine.find_flow_node_by_id(&ElementId::with_u64(2)).unwrap(), &node2));
+        assert_eq!(engine.export_flows(), exported);
+
+        // The failed reload should not be taken as applied
+        engine.reload_flows(make_flows_json("array")).await.unwrap();
+        assert!(!flow2.is_running());
+        assert!(engine.get_flow(&ElementId::with_u64(0x200)).unwrap().is_running());
+        assert!(engine.is_ready());
+
+        engine.stop().await.unwrap();
+    }
+
     #[tokio::test]
     async fn test_it_should_json_flows_multiple_times() {
         let flows_json = make_flows_json_that_contains_subflows();
@@ -10,6 +10,7 @@ use serde::Deserialize;
 use serde_json::Value as JsonValue;
 use utils::topo::TopologicalSorter;
 
+use crate::runtime::jsonata;
 use crate::runtime::model::{RedPropertyType, Variant};
 use crate::*;
 
@@ -192,7 +193,22 @@ impl EnvStoreBuilder {
                 Ok(Variant::Bytes(bytes))
             }
 
-            RedPropertyType::Jsonata => todo!(),
+            RedPropertyType::Jsonata => {
+                let expr = jsonata::Expression::parse(value)?;
+                if !expr.context_keys().is_empty() {
+                    return Err(EdgelinkError::NotSupported(format!(
+                        "The environment variable `{}` cannot read the context",
+                        value
+                    ))
+                    .into());
+                }
+                let env = |name: &str| self.get_existed(name);
+                let mut bindings = jsonata::Bindings::default();
+                bindings.env = Some(&env);
+                expr.evaluate(&Variant::Null, &bindings)?
+                    .ok_or(EdgelinkError::BadArgument("value"))
+                    .with_context(|| format!("The JSONata expression `{}` is evaluated to undefined", value))
+            }
 
             RedPropertyType::Env => match self.normalized_and_get_existed(value) {
                 Some(ev) => Ok(ev),
@@ -337,6 +353,11 @@ mod tests {
                 "name": "AGE",
                 "value": "100",
                 "type": "str"
+            },
+            {
+                "name": "UPPER_FOO",
+                "value": "$uppercase($env('FOO')) & '-' & $env('BAR')",
+                "type": "jsonata"
             }
         ]);
         let node = EnvStoreBuilder::default().with_parent(&flow).load_json(&json).build();
@@ -346,5 +367,6 @@ mod tests {
         assert_eq!(node.evalute_env("PARENT_BAR").unwrap().as_str().unwrap(), "barbar");
         assert_eq!(node.evalute_env("AGE").unwrap().as_str().unwrap(), "100");
         assert_eq!(node.evalute_env("FILE_SIZE").unwrap().as_i64().unwrap(), 123);
+        assert_eq!(node.evalute_env("UPPER_FOO").unwrap().as_str().unwrap(), "FOOFOO-barbar");
     }
 }
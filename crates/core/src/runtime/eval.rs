@@ -4,7 +4,9 @@ use regex::Regex;
 use serde::Deserialize;
 use smallvec::SmallVec;
 
+use crate::runtime::context::ContextKey;
 use crate::runtime::flow::*;
+use crate::runtime::jsonata;
 use crate::runtime::model::*;
 use crate::runtime::nodes::*;
 use crate::utils;
@@ -85,43 +87,20 @@ pub async fn evaluate_node_property(
             }
         }
 
-        RedPropertyType::Global => {
-            let ctx_prop = crate::runtime::context::evaluate_key(value)?;
-            let ctx = flow
-                .and_then(|f| f.engine())
-                .or(node.and_then(|n| n.engine()))
-                .map(|e| e.context().clone())
-                .ok_or_else(|| EdgelinkError::BadArgument("flow,node"))?;
-
-            let msg_env = msg.map(|m| SmallVec::from([PropexEnv::ExtRef("msg", m.as_variant())])).unwrap_or_default();
-            if let Some(ctx_value) = ctx.get_one(ctx_prop.store, ctx_prop.key, &msg_env).await {
-                Ok(ctx_value)
-            } else {
-                Err(EdgelinkError::BadArgument("value"))
-                    .with_context(|| format!("Cannot found the global context variable `{}`", value))
-            }
-        }
-
-        RedPropertyType::Flow => {
-            let ctx_prop = crate::runtime::context::evaluate_key(value)?;
-            let ctx = flow
-                .cloned()
-                .or(node.and_then(|n| n.flow()))
-                .map(|e| e.context().clone())
-                .ok_or_else(|| EdgelinkError::BadArgument("flow,node"))?;
-
-            let msg_env = msg.map(|m| SmallVec::from([PropexEnv::ExtRef("msg", m.as_variant())])).unwrap_or_default();
-            if let Some(ctx_value) = ctx.get_one(ctx_prop.store, ctx_prop.key, &msg_env).await {
-                Ok(ctx_value)
-            } else {
-                Err(EdgelinkError::BadArgument("value"))
-                    .with_context(|| format!("Cannot found the flow context variable `{}`", value))
-            }
+        RedPropertyType::Global | RedPropertyType::Flow => {
+            let ctx_key = crate::runtime::context::evaluate_key(value)?;
+            evaluate_context_property(ctx_key, _type, node, flow, msg).await
         }
 
         RedPropertyType::Bool => Ok(Variant::Bool(value.trim_ascii().parse::<bool>()?)),
 
-        RedPropertyType::Jsonata => todo!(),
+        RedPropertyType::Jsonata => {
+            let expr = jsonata::Expression::parse(value)?;
+            evaluate_jsonata(&expr, node, flow, msg)
+                .await?
+                .ok_or(EdgelinkError::BadArgument("value"))
+                .with_context(|| format!("The JSONata expression `{}` is evaluated to undefined", value))
+        }
 
         RedPropertyType::Env => match evaluate_env_property(value, node, flow) {
             Some(ev) => Ok(ev),
@@ -131,6 +110,77 @@ pub async fn evaluate_node_property(
     }
 }
 
+/// Evaluates a flow or global context property with the key already parsed, e.g. a `ContextKeyRef` parsed once for
+/// a rule of the node.
+pub async fn evaluate_context_property(
+    ctx_key: ContextKey<'_>,
+    _type: RedPropertyType,
+    node: Option<&dyn FlowNodeBehavior>,
+    flow: Option<&Flow>,
+    msg: Option<&Msg>,
+) -> crate::Result<Variant> {
+    let ctx = match _type {
+        RedPropertyType::Global => {
+            flow.and_then(|f| f.engine()).or(node.and_then(|n| n.engine())).map(|e| e.context().clone())
+        }
+        RedPropertyType::Flow => flow.cloned().or(node.and_then(|n| n.flow())).map(|e| e.context().clone()),
+        _ => {
+            return Err(EdgelinkError::BadArgument("_type")).with_context(|| format!("Not a context type: {:?}", _type))
+        }
+    }
+    .ok_or_else(|| EdgelinkError::BadArgument("flow,node"))?;
+
+    let msg_env = msg.map(|m| SmallVec::from([PropexEnv::ExtRef("msg", m.as_variant())])).unwrap_or_default();
+    if let Some(ctx_value) = ctx.get_one(ctx_key.store, ctx_key.key, &msg_env).await {
+        Ok(ctx_value)
+    } else {
+        let scope = if _type == RedPropertyType::Global { "global" } else { "flow" };
+        Err(EdgelinkError::BadArgument("value"))
+            .with_context(|| format!("Cannot found the {} context variable `{}`", scope, ctx_key.key))
+    }
+}
+
+/// Evaluates the JSONata expression against the `msg`, `None` is the `undefined` of JSONata.
+///
+/// The flow and global context values read by the expression are fetched before the evaluation, the missing ones are
+/// `undefined` in the expression.
+pub async fn evaluate_jsonata(
+    expr: &jsonata::Expression,
+    node: Option<&dyn FlowNodeBehavior>,
+    flow: Option<&Flow>,
+    msg: Option<&Msg>,
+) -> crate::Result<Option<Variant>> {
+    let mut contexts = Vec::with_capacity(expr.context_keys().len());
+    for (scope, key) in expr.context_keys() {
+        let _type = match scope {
+            jsonata::JsonataContextScope::Flow => RedPropertyType::Flow,
+            jsonata::JsonataContextScope::Global => RedPropertyType::Global,
+        };
+        let ctx_key = crate::runtime::context::evaluate_key(key)?;
+        if let Ok(value) = evaluate_context_property(ctx_key, _type, node, flow, msg).await {
+            contexts.push((*scope, key.clone(), value));
+        }
+    }
+    evaluate_jsonata_with(expr, contexts, node, flow, msg)
+}
+
+fn evaluate_jsonata_with(
+    expr: &jsonata::Expression,
+    contexts: Vec<(jsonata::JsonataContextScope, String, Variant)>,
+    node: Option<&dyn FlowNodeBehavior>,
+    flow: Option<&Flow>,
+    msg: Option<&Msg>,
+) -> crate::Result<Option<Variant>> {
+    let env = |name: &str| evaluate_env_property(name, node, flow);
+    let mut bindings = jsonata::Bindings::default();
+    bindings.env = Some(&env);
+    for (scope, key, value) in contexts {
+        bindings.set_context(scope, key, value);
+    }
+    let null = Variant::Null;
+    expr.evaluate(msg.map(|x| x.as_variant()).unwrap_or(&null), &bindings)
+}
+
 /// Evaluates a property variant according to its type.
 pub fn evaluate_node_property_variant<'a>(
     value: &'a Variant,
@@ -191,7 +241,24 @@ pub fn evaluate_node_property_variant<'a>(
 
         (RedPropertyType::Bool, Variant::String(s)) => Cow::Owned(Variant::Bool(s.trim_ascii().parse::<bool>()?)),
 
-        (RedPropertyType::Jsonata, _) => todo!(),
+        // The context values cannot be fetched here, see `evaluate_jsonata()`
+        (RedPropertyType::Jsonata, Variant::String(s)) => {
+            let expr = jsonata::Expression::parse(s)?;
+            if !expr.context_keys().is_empty() {
+                return Err(EdgelinkError::NotSupported(format!(
+                    "The context of the JSONata expression `{}` cannot be read synchronously",
+                    s
+                ))
+                .into());
+            }
+            match evaluate_jsonata_with(&expr, Vec::new(), node, flow, msg)? {
+                Some(v) => Cow::Owned(v),
+                None => {
+                    return Err(EdgelinkError::BadArgument("value"))
+                        .with_context(|| format!("The JSONata expression `{}` is evaluated to undefined", s));
+                }
+            }
+        }
 
         (RedPropertyType::Env, Variant::String(s)) => match evaluate_env_property(s, node, flow) {
             Some(ev) => Cow::Owned(ev),
@@ -211,5 +278,18 @@ pub fn evaluate_node_property_variant<'a>(
 
 #[cfg(test)]
 mod tests {
-    // use super::*;
+    use super::*;
+
+    #[test]
+    fn test_jsonata_property_should_be_evaluated() {
+        let msg = Msg::deserialize(serde_json::json!({"payload": {"a": 1, "b": [2, 3]}})).unwrap();
+        let value = Variant::from("payload.a + $sum(payload.b)");
+        let res = evaluate_node_property_variant(&value, &RedPropertyType::Jsonata, None, None, Some(&msg)).unwrap();
+        assert_eq!(res.as_ref(), &Variant::from(6));
+
+        let value = Variant::from("payload.missing");
+        assert!(evaluate_node_property_variant(&value, &RedPropertyType::Jsonata, None, None, Some(&msg)).is_err());
+        let value = Variant::from("$flowContext('count')");
+        assert!(evaluate_node_property_variant(&value, &RedPropertyType::Jsonata, None, None, Some(&msg)).is_err());
+    }
 }
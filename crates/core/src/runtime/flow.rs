@@ -1,4 +1,5 @@
 use std::cmp::Ordering;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
 use std::sync::{Arc, Weak};
 
 use common_nodes::catch::{CatchNode, CatchNodeScope};
@@ -9,9 +10,10 @@ use tokio::sync::{Mutex, RwLock};
 use tokio::task::JoinSet;
 use tokio_util::sync::CancellationToken;
 
-use super::context::Context;
+use super::context::{Context, ContextScope};
 use super::engine::{Engine, WeakEngine};
 use super::group::{Group, GroupParent};
+use super::metrics::NodeMetrics;
 use super::registry::RegistryHandle;
 use super::subflow::SubflowState;
 use crate::runtime::env::*;
@@ -19,34 +21,57 @@ use crate::runtime::model::json::*;
 use crate::runtime::model::*;
 use crate::runtime::nodes::*;
 use crate::runtime::registry::Registry;
-use crate::{EdgelinkError, handle_option};
+use crate::{EdgelinkError, ErrorContext, handle_option};
 use crate::utils::constants::{ENV_STR, FLOW_STR, SUB_FLOW_TYPE, SUB_FLOW_TYPE_HEAD, TAB_STR};
 
-const NODE_MSG_CHANNEL_CAPACITY: usize = 32;
+/// The maximum count of catching a message from the same node, to break the infinite error loops
+const MAX_CATCH_COUNT: u64 = 10;
 
 pub type FlowNodeTask = tokio::task::JoinHandle<()>;
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct FlowArgs {
     pub node_msg_queue_capacity: usize,
+
+    #[serde(default)]
+    pub on_fatal: FatalErrorPolicy,
+}
+
+/// What to stop when a node reports a fatal error, configured by `runtime.flow.on_fatal`.
+/// The rest of the engine keeps running in either case.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum FatalErrorPolicy {
+    /// Stop the failing node only
+    #[default]
+    #[serde(rename = "node")]
+    StopNode,
+
+    /// Stop all nodes in the flow of the failing node
+    #[serde(rename = "flow")]
+    StopFlow,
 }
 
 impl FlowArgs {
     pub fn load(cfg: Option<&config::Config>) -> crate::Result<Self> {
-        match cfg {
+        let args = match cfg {
             Some(cfg) => match cfg.get::<Self>("runtime.flow") {
-                Ok(res) => Ok(res),
-                Err(config::ConfigError::NotFound(_)) => Ok(Self::default()),
-                Err(e) => Err(e.into()),
+                Ok(res) => res,
+                Err(config::ConfigError::NotFound(_)) => Self::default(),
+                Err(e) => return Err(e.into()),
             },
-            _ => Ok(Self::default()),
+            _ => Self::default(),
+        };
+        if args.node_msg_queue_capacity == 0 {
+            return Err(EdgelinkError::Configuration)
+                .with_context(|| "The `runtime.flow.node_msg_queue_capacity` must be greater than 0");
         }
+        Ok(args)
     }
 }
 
 impl Default for FlowArgs {
     fn default() -> Self {
-        Self { node_msg_queue_capacity: 16 }
+        Self { node_msg_queue_capacity: 32, on_fatal: FatalErrorPolicy::default() }
     }
 }
 
@@ -89,13 +114,14 @@ struct InnerFlow {
     parent: Option<ElementId>,
     label: String,
     disabled: bool,
-    _args: FlowArgs,
+    args: FlowArgs,
     ordering: usize,
     type_str: &'static str,
 
     engine: WeakEngine,
 
     stop_token: CancellationToken,
+    started: AtomicBool,
 
     pub(crate) groups: DashMap<ElementId, Group>,
     pub(crate) nodes: DashMap<ElementId, Arc<dyn FlowNodeBehavior>>,
@@ -191,12 +217,7 @@ impl Flow {
         Ok(())
     }
 
-    pub(crate) fn new(
-        engine: &Engine,
-        flow_config: RedFlowConfig,
-        reg: &RegistryHandle,
-        options: Option<&config::Config>,
-    ) -> crate::Result<Flow> {
+    pub(crate) fn new(engine: &Engine, flow_config: RedFlowConfig, reg: &RegistryHandle) -> crate::Result<Flow> {
 
         let flow_kind =  handle_option!(result: FlowKind::from(&flow_config.type_name),EdgelinkError::BadFlowsJson,str:"Unsupported flow type");
 
@@ -246,8 +267,9 @@ impl Flow {
         };
         let envs = envs_builder.build();
 
-        let context = engine.get_context_manager().new_context(&engine.context(), flow_config.id.to_string());
-        let args = FlowArgs::load(options)?;
+        let context =
+            engine.get_context_manager().new_context(&engine.context(), flow_config.id.to_string(), ContextScope::Flow);
+        let args = engine.flow_args();
 
         let inner_flow = InnerFlow {
             id: flow_config.id,
@@ -256,7 +278,7 @@ impl Flow {
             label: flow_config.label.clone(),
             disabled: flow_config.disabled,
             ordering: flow_config.ordering,
-            _args: args.clone(),
+            args: args.clone(),
             type_str: match flow_kind {
                 FlowKind::GlobalFlow => FLOW_STR,
                 FlowKind::Subflow => SUB_FLOW_TYPE,
@@ -275,6 +297,7 @@ impl Flow {
             envs,
             context,
             stop_token: CancellationToken::new(),
+            started: AtomicBool::new(false),
             // groups: HashMap::new(), //   flow_config.groups.iter().map(|g| Group::new_flow_group(config, flow))
         };
         let flow = Flow { inner: Arc::new(inner_flow) };
@@ -352,7 +375,17 @@ impl Flow {
                                             subflow_state.tx_ports.read().expect("read subflow tx_ports lock");
                                         tx_ports_lock[subflow_port_index].clone()
                                     };
-                                    let node_wire = PortWire { msg_sender: subflow_tx_port.msg_tx.clone() };
+                                    // The messages will be sent out by the subflow instance node, with its policy
+                                    let overflow =
+                                        subflow_state.instance_node.as_ref().map(|x| x.get_node().overflow);
+                                    let node_wire = PortWire {
+                                        overflow: overflow.unwrap_or_default(),
+                                        target_rx: Some(subflow_tx_port.msg_rx.clone()),
+                                        ..PortWire::new(
+                                            self.inner.parent.unwrap_or_default(),
+                                            subflow_tx_port.msg_tx.clone(),
+                                        )
+                                    };
                                     node_port.wires.push(node_wire)
                                 } else {
                                     return Err(EdgelinkError::BadFlowsJson(format!(
@@ -522,9 +555,30 @@ impl Flow {
             self.start_nodes(self.inner.stop_token.clone()).await?;
         }
 
+        self.inner.started.store(true, AtomicOrdering::Release);
         Ok(())
     }
 
+    /// Returns `true` if the flow has been stopped or is stopping.
+    pub fn is_stopped(&self) -> bool {
+        self.inner.stop_token.is_cancelled()
+    }
+
+    /// Returns `true` if the flow has been started successfully and not stopped yet.
+    pub fn is_running(&self) -> bool {
+        self.inner.started.load(AtomicOrdering::Acquire) && !self.is_stopped()
+    }
+
+    /// Handles a fatal error of the node according to the `runtime.flow.on_fatal` policy.
+    /// The node itself is always stopped by its `stop_token`.
+    pub(crate) fn handle_fatal_error(&self, node: &FlowNode, log_message: &str) {
+        log::error!("[{}:{}] Fatal error! {}", node.type_str, node.name, log_message);
+        if self.inner.args.on_fatal == FatalErrorPolicy::StopFlow {
+            log::warn!("---- Stopping flow (id='{}') due to the fatal error of node (id='{}')", self.id(), node.id);
+            self.inner.stop_token.cancel();
+        }
+    }
+
     pub async fn stop(&self) -> crate::Result<()> {
         if self.is_subflow() {
             log::info!("---- Stopping Subflow (id={})...", self.id());
@@ -533,6 +587,7 @@ impl Flow {
         }
 
         self.inner.stop_token.cancel();
+        self.inner.started.store(false, AtomicOrdering::Release);
 
         // Wait all subflow senders to stop
         /*
@@ -601,7 +656,7 @@ impl Flow {
         engine: &Engine,
     ) -> crate::Result<FlowNode> {
         let mut ports = Vec::new();
-        let (tx_root, rx) = tokio::sync::mpsc::channel(NODE_MSG_CHANNEL_CAPACITY);
+        let (tx_root, rx) = tokio::sync::mpsc::channel(self.inner.args.node_msg_queue_capacity);
         // Convert the Node-RED wires elements to ours
         for red_port in node_config.wires.iter() {
             let mut wires = Vec::new();
@@ -614,11 +669,11 @@ impl Flow {
                     "[flow:{}] Referenced node not found [this_node.id='{}' this_node.name='{}', referenced_node.id='{}']",
                     self.name(), node_config.id, node_config.name, nid
                 )))?;
-                let tx = node_entry.get_node().msg_tx.to_owned();
+                let target = node_entry.get_node();
                 let pw = PortWire {
-                    // target_node_id: *nid,
-                    // target_node: Arc::downgrade(node_entry),
-                    msg_sender: tx,
+                    overflow: target.overflow,
+                    target_rx: Some(target.msg_rx.clone()),
+                    ..PortWire::new(*nid, target.msg_tx.to_owned())
                 };
                 wires.push(pw);
             }
@@ -656,7 +711,11 @@ impl Flow {
                 ("NR_NODE_PATH".into(), Variant::String(format!("{}/{}", self.get_path(), node_config.id))),
             ])
             .build();
-        let context = engine.get_context_manager().new_context(&self.inner.context, node_config.id.to_string());
+        let context = engine.get_context_manager().new_context(
+            &self.inner.context,
+            node_config.id.to_string(),
+            ContextScope::Node,
+        );
 
         Ok(FlowNode {
             id: node_config.id,
@@ -665,16 +724,24 @@ impl Flow {
             ordering: node_config.ordering,
             disabled: node_config.disabled,
             active: node_config.active.unwrap_or(true),
+            ordered: node_config.ordered,
             flow: self.downgrade(),
             msg_tx: tx_root,
-            msg_rx: MsgReceiverHolder::new(rx),
+            msg_rx: Arc::new(MsgReceiverHolder::new(rx)),
+            overflow: node_config.overflow,
+            concurrency: node_config.concurrency,
+            uow_permits: Arc::new(tokio::sync::Semaphore::new(node_config.concurrency)),
             ports,
             group: group.map(|g| g.downgrade()),
             envs,
             context,
+            metrics: NodeMetrics::default(),
+            #[cfg(feature = "metrics")]
+            metrics_registry: engine.metrics_registry().clone(),
             on_received: MsgEventSender::new(1),
             on_completed: MsgEventSender::new(1),
             on_error: MsgEventSender::new(1),
+            on_sent: MsgSentEventSender::new(NODE_EVENT_CHANNEL_CAPACITY),
         })
     }
 
@@ -688,6 +755,26 @@ impl Flow {
     ) -> crate::Result<bool> {
         let reporting_node = if let Some(rn) = reporting_node { rn } else { node };
 
+        // The message has been caught from the same node before, count the retries to break the loop
+        let mut count = 1;
+        if let Some(ref msg) = msg {
+            let msg_guard = msg.read().await;
+            let source = msg_guard.get_nav("error.source");
+            let source_id = source.and_then(|x| x.get_nav("id", &[])).and_then(|x| x.as_str());
+            if source_id == Some(node.id().to_string().as_str()) {
+                count = source.and_then(|x| x.get_nav("count", &[])).and_then(|x| x.as_u64()).unwrap_or(0) + 1;
+                if count >= MAX_CATCH_COUNT {
+                    log::warn!(
+                        "[{}:{}] Message exceeded maximum number of catches: {}",
+                        node.type_str(),
+                        node.name(),
+                        log_message
+                    );
+                    return Ok(false);
+                }
+            }
+        }
+
         // TODO: use SmallVec
         let mut candidates = Vec::new();
         {
@@ -750,27 +837,50 @@ impl Flow {
                 }
                 handled_by_uncaught = true;
             }
-            let mut error_msg = if let Some(ref msg) = msg {
-                let msg_lock = msg.read().await;
-                msg_lock.clone()
-            } else {
-                Msg::default()
-            };
-            let error_object = Variant::from(serde_json::json!({
-                "message": log_message.to_string(),
-                "source": {
-                    "id": node.id(),
-                    "type": node.type_str().to_string(),
-                    "name": node.name(),
-                    "count": 1, // TODO
+            let mut error_msg = match msg {
+                Some(ref msg) if catch_node.keep_payload => {
+                    let msg_lock = msg.read().await;
+                    msg_lock.clone()
+                }
+                Some(ref msg) => {
+                    let mut error_msg = Msg::default();
+                    if let Some(id) = msg.read().await.id() {
+                        error_msg.set_id(id);
+                    }
+                    error_msg
                 }
-            }));
-            error_msg.set("error".into(), error_object);
+                None => Msg::default(),
+            };
+            error_msg.set("error".into(), make_error_object(node, log_message, count));
             let error_msg = MsgHandle::new(error_msg);
             catch_node.inject_msg(error_msg, cancel.clone()).await?;
 
             handled = true;
         }
+
+        // Fall back to the engine-wide handler, with the whole msg kept
+        if !handled {
+            if let Some(engine) = self.engine() {
+                let mut error_msg = match msg {
+                    Some(ref msg) => msg.read().await.clone(),
+                    None => Msg::default(),
+                };
+                error_msg.set("error".into(), make_error_object(node, log_message, count));
+                handled = engine.route_uncaught_error(&node.id(), error_msg, cancel).await?;
+            }
+        }
         Ok(handled)
     }
 }
+
+fn make_error_object(node: &dyn FlowNodeBehavior, log_message: &str, count: u64) -> Variant {
+    Variant::from(serde_json::json!({
+        "message": log_message.to_string(),
+        "source": {
+            "id": node.id(),
+            "type": node.type_str().to_string(),
+            "name": node.name(),
+            "count": count,
+        }
+    }))
+}
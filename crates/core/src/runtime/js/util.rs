@@ -1,40 +1,92 @@
-use std::collections::HashMap;
-
 use rquickjs::function::{Constructor, This};
-use rquickjs::{Ctx, Function, IntoJs, Value};
+use rquickjs::{Array, ArrayBuffer, Ctx, Function, Object, Value};
 
+/// Deep clones the value like the `cloneDeep()` of lodash.
+///
+/// The shared and the cyclic references are kept in the copy, e.g. a message referencing itself is cloned to a copy
+/// referencing the copy.
 pub fn deep_clone<'js>(ctx: Ctx<'js>, obj: Value<'js>) -> rquickjs::Result<Value<'js>> {
-    if let Some(obj_ref) = obj.as_object() {
-        let globals = ctx.globals();
-        let date_ctor: Constructor = globals.get("Date")?;
-        if obj_ref.is_instance_of(&date_ctor) {
-            let get_time_fn: Function = obj_ref.get("getTime")?;
-            let time: i64 = get_time_fn.call((This(&obj),))?;
-            return date_ctor.construct((time,));
-        }
+    if !obj.is_object() {
+        return Ok(obj);
+    }
+    let map_ctor: Constructor = ctx.globals().get("Map")?;
+    let map: Object = map_ctor.construct(())?;
+    let cloned = ClonedObjects { get: map.get("get")?, set: map.get("set")?, map };
+    deep_clone_with(&ctx, obj, &cloned)
+}
+
+/// The copies keyed by the original objects, a JavaScript `Map` compares the keys by the identity.
+struct ClonedObjects<'js> {
+    map: Object<'js>,
+    get: Function<'js>,
+    set: Function<'js>,
+}
+
+impl<'js> ClonedObjects<'js> {
+    fn get(&self, obj: &Value<'js>) -> rquickjs::Result<Option<Value<'js>>> {
+        let copy: Value = self.get.call((This(self.map.clone()), obj.clone()))?;
+        Ok(if copy.is_undefined() { None } else { Some(copy) })
+    }
+
+    fn insert(&self, obj: &Value<'js>, copy: &Value<'js>) -> rquickjs::Result<()> {
+        self.set.call::<_, Value>((This(self.map.clone()), obj.clone(), copy.clone()))?;
+        Ok(())
+    }
+}
+
+fn deep_clone_with<'js>(ctx: &Ctx<'js>, obj: Value<'js>, cloned: &ClonedObjects<'js>) -> rquickjs::Result<Value<'js>> {
+    let obj_ref = match obj.as_object() {
+        Some(obj_ref) => obj_ref,
+        None => return Ok(obj),
+    };
+    if let Some(copy) = cloned.get(&obj)? {
+        return Ok(copy);
+    }
+
+    let globals = ctx.globals();
+    let date_ctor: Constructor = globals.get("Date")?;
+    if obj_ref.is_instance_of(&date_ctor) {
+        let get_time_fn: Function = obj_ref.get("getTime")?;
+        let time: i64 = get_time_fn.call((This(&obj),))?;
+        let copy: Value = date_ctor.construct((time,))?;
+        cloned.insert(&obj, &copy)?;
+        return Ok(copy);
+    }
 
-        if let Some(src_arr) = obj_ref.as_array() {
-            let mut arr_copy = Vec::with_capacity(src_arr.len());
-            for item in src_arr.iter() {
-                let cloned = deep_clone(ctx.clone(), item?)?;
-                arr_copy.push(cloned);
-            }
-            return arr_copy.into_js(&ctx);
+    let regexp_ctor: Constructor = globals.get("RegExp")?;
+    if obj_ref.is_instance_of(&regexp_ctor) {
+        let copy: Value = regexp_ctor.construct((obj.clone(),))?;
+        cloned.insert(&obj, &copy)?;
+        return Ok(copy);
+    }
+
+    // The buffers have no own properties, so they must be copied or they become empty objects
+    if let Some(buf) = obj_ref.as_array_buffer() {
+        let bytes = buf.as_bytes().map(|x| x.to_vec()).unwrap_or_default();
+        let copy = ArrayBuffer::new(ctx.clone(), bytes)?.into_value();
+        cloned.insert(&obj, &copy)?;
+        return Ok(copy);
+    }
+
+    // The copy is registered before its items, so the items referencing it get the copy
+    if let Some(src_arr) = obj_ref.as_array() {
+        let arr_copy = Array::new(ctx.clone())?;
+        cloned.insert(&obj, &arr_copy.clone().into_value())?;
+        for (i, item) in src_arr.iter().enumerate() {
+            arr_copy.set(i, deep_clone_with(ctx, item?, cloned)?)?;
         }
+        return Ok(arr_copy.into_value());
+    }
 
-        {
-            let mut obj_copy: HashMap<String, Value<'js>> = HashMap::with_capacity(obj_ref.len());
-            let has_own_property_fn: Function = obj_ref.get("hasOwnProperty")?;
-            for item in obj_ref.props::<String, Value<'js>>() {
-                let (k, v) = item?;
-                let has: bool = has_own_property_fn.call((This(&obj), k.as_str()))?;
-                if has {
-                    obj_copy.insert(k, deep_clone(ctx.clone(), v)?);
-                }
-            }
-            obj_copy.into_js(&ctx)
+    let obj_copy = Object::new(ctx.clone())?;
+    cloned.insert(&obj, &obj_copy.clone().into_value())?;
+    let has_own_property_fn: Function = obj_ref.get("hasOwnProperty")?;
+    for item in obj_ref.props::<String, Value<'js>>() {
+        let (k, v) = item?;
+        let has: bool = has_own_property_fn.call((This(&obj), k.as_str()))?;
+        if has {
+            obj_copy.set(k, deep_clone_with(ctx, v, cloned)?)?;
         }
-    } else {
-        Ok(obj)
     }
+    Ok(obj_copy.into_value())
 }
@@ -0,0 +1,373 @@
+use std::collections::HashMap;
+
+use crate::runtime::model::{Variant, VariantObjectMap};
+use crate::*;
+
+use super::parser::{BinaryOp, Node};
+use super::{context_key_of, functions, is_truthy, Bindings};
+
+/// The most items a range like `[1..n]` can expand to.
+const MAX_RANGE_ITEMS: i64 = 100_000;
+
+pub(super) struct Evaluator<'a, 'b> {
+    input: &'a Variant,
+    bindings: &'a Bindings<'b>,
+    /// The variables bound by `:=`, a frame for each block
+    frames: Vec<HashMap<String, Variant>>,
+}
+
+impl<'a, 'b> Evaluator<'a, 'b> {
+    pub fn new(input: &'a Variant, bindings: &'a Bindings<'b>) -> Self {
+        Evaluator { input, bindings, frames: vec![HashMap::new()] }
+    }
+
+    pub fn evaluate(&mut self, node: &Node) -> crate::Result<Option<Variant>> {
+        let input = self.input;
+        self.eval(node, input)
+    }
+
+    fn eval(&mut self, node: &Node, context: &Variant) -> crate::Result<Option<Variant>> {
+        match node {
+            Node::Number(n) => number(*n).map(Some),
+            Node::String(s) => Ok(Some(Variant::String(s.clone()))),
+            Node::Bool(b) => Ok(Some(Variant::Bool(*b))),
+            Node::Null => Ok(Some(Variant::Null)),
+            Node::Name(name) => Ok(field(context, name)),
+            Node::Wildcard => Ok(match context {
+                Variant::Object(map) => collapse(flatten(map.values().cloned())),
+                _ => None,
+            }),
+            Node::Variable(name) => Ok(match name.as_str() {
+                "" => Some(context.clone()),
+                "$" => Some(self.input.clone()),
+                _ => self.frames.iter().rev().find_map(|frame| frame.get(name)).cloned(),
+            }),
+            Node::Path(steps) => self.path(steps, context),
+            Node::Filter(value, predicate) => match self.eval(value, context)? {
+                Some(value) => self.filter(value, predicate),
+                None => Ok(None),
+            },
+            Node::Negate(value) => match self.eval(value, context)? {
+                Some(Variant::Number(n)) => number(-n.as_f64().unwrap_or(f64::NAN)).map(Some),
+                Some(other) => Err(type_error(&format!("Cannot negate the non-number value: {:?}", other))),
+                None => Ok(None),
+            },
+            Node::Binary(op, left, right) => self.binary(*op, left, right, context),
+            Node::Condition(condition, then, otherwise) => {
+                if self.eval(condition, context)?.as_ref().is_some_and(is_truthy) {
+                    self.eval(then, context)
+                } else if let Some(otherwise) = otherwise {
+                    self.eval(otherwise, context)
+                } else {
+                    Ok(None)
+                }
+            }
+            Node::Bind(name, value) => {
+                let value = self.eval(value, context)?;
+                let frame = self.frames.last_mut().expect("the root frame");
+                match &value {
+                    Some(v) => frame.insert(name.clone(), v.clone()),
+                    None => frame.remove(name),
+                };
+                Ok(value)
+            }
+            Node::Block(items) => {
+                self.frames.push(HashMap::new());
+                let mut result = Ok(None);
+                for item in items {
+                    result = self.eval(item, context);
+                    if result.is_err() {
+                        break;
+                    }
+                }
+                self.frames.pop();
+                result
+            }
+            Node::Array(items) => self.array(items, context),
+            Node::Range(_, _) => {
+                Err(EdgelinkError::InvalidOperation("The range operator can only be used in an array".into()).into())
+            }
+            Node::Object(pairs) => {
+                let mut map = VariantObjectMap::new();
+                for (key, value) in pairs {
+                    let key = match self.eval(key, context)? {
+                        Some(Variant::String(key)) => key,
+                        None => continue,
+                        Some(other) => {
+                            return Err(type_error(&format!("The key of an object must be a string, got: {:?}", other)))
+                        }
+                    };
+                    if let Some(value) = self.eval(value, context)? {
+                        map.insert(key, value);
+                    }
+                }
+                Ok(Some(Variant::Object(map)))
+            }
+            Node::Call(name, args) => self.call(name, args, context),
+        }
+    }
+
+    /// Evaluates every step against each item of the previous one, the arrays are flattened into the results.
+    fn path(&mut self, steps: &[Node], context: &Variant) -> crate::Result<Option<Variant>> {
+        let Some((first, rest)) = steps.split_first() else {
+            return Ok(None);
+        };
+        let mut current = self.eval(first, context)?;
+        for step in rest {
+            let items = match current.take() {
+                Some(Variant::Array(items)) => items,
+                Some(value) => vec![value],
+                None => return Ok(None),
+            };
+            let mut results = Vec::new();
+            for item in items.iter() {
+                match self.eval(step, item)? {
+                    // The array constructor in a path keeps its array
+                    Some(value @ Variant::Array(_)) if matches!(step, Node::Array(_)) => results.push(value),
+                    Some(Variant::Array(values)) => results.extend(values),
+                    Some(value) => results.push(value),
+                    None => {}
+                }
+            }
+            current = collapse(results);
+        }
+        Ok(current)
+    }
+
+    /// A number predicate is an index counted from the end if it is negative, other predicates are tested for the
+    /// truthiness against each item.
+    fn filter(&mut self, value: Variant, predicate: &Node) -> crate::Result<Option<Variant>> {
+        let items = match value {
+            Variant::Array(items) => items,
+            value => vec![value],
+        };
+        let len = items.len();
+        let mut results = Vec::new();
+        for (index, item) in items.into_iter().enumerate() {
+            let selected = match self.eval(predicate, &item)? {
+                Some(Variant::Number(n)) => is_index(n.as_f64(), index, len),
+                Some(Variant::Array(values)) if !values.is_empty() && values.iter().all(Variant::is_number) => {
+                    values.iter().any(|x| is_index(x.as_f64(), index, len))
+                }
+                Some(value) => is_truthy(&value),
+                None => false,
+            };
+            if selected {
+                results.push(item);
+            }
+        }
+        Ok(collapse(results))
+    }
+
+    fn array(&mut self, items: &[Node], context: &Variant) -> crate::Result<Option<Variant>> {
+        let mut results = Vec::with_capacity(items.len());
+        for item in items {
+            match item {
+                Node::Range(from, to) => {
+                    let (Some(from), Some(to)) = (self.eval(from, context)?, self.eval(to, context)?) else {
+                        continue;
+                    };
+                    let (from, to) = (integer(&from)?, integer(&to)?);
+                    if to < from {
+                        continue;
+                    }
+                    if to.saturating_sub(from) >= MAX_RANGE_ITEMS {
+                        return Err(EdgelinkError::OutOfRange.into());
+                    }
+                    results.extend((from..=to).map(Variant::from));
+                }
+                item => {
+                    if let Some(value) = self.eval(item, context)? {
+                        results.push(value);
+                    }
+                }
+            }
+        }
+        Ok(Some(Variant::Array(results)))
+    }
+
+    fn binary(&mut self, op: BinaryOp, left: &Node, right: &Node, context: &Variant) -> crate::Result<Option<Variant>> {
+        // `and` and `or` do not evaluate the right side if the left side decides the result
+        match op {
+            BinaryOp::And | BinaryOp::Or => {
+                let left = self.eval(left, context)?.as_ref().is_some_and(is_truthy);
+                if left == (op == BinaryOp::Or) {
+                    return Ok(Some(Variant::Bool(left)));
+                }
+                let right = self.eval(right, context)?.as_ref().is_some_and(is_truthy);
+                return Ok(Some(Variant::Bool(right)));
+            }
+            _ => {}
+        }
+
+        let left = self.eval(left, context)?;
+        let right = self.eval(right, context)?;
+        let result = match op {
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => {
+                let (Some(left), Some(right)) = (left, right) else {
+                    return Ok(None);
+                };
+                let (x, y) = match (&left, &right) {
+                    (Variant::Number(x), Variant::Number(y)) => {
+                        (x.as_f64().unwrap_or(f64::NAN), y.as_f64().unwrap_or(f64::NAN))
+                    }
+                    _ => {
+                        return Err(type_error(&format!(
+                            "The operands of the arithmetic must be numbers, got: {:?} and {:?}",
+                            left, right
+                        )))
+                    }
+                };
+                number(match op {
+                    BinaryOp::Add => x + y,
+                    BinaryOp::Sub => x - y,
+                    BinaryOp::Mul => x * y,
+                    BinaryOp::Div => x / y,
+                    _ => x % y,
+                })?
+            }
+            BinaryOp::Concat => Variant::String(format!(
+                "{}{}",
+                left.as_ref().map(to_text).unwrap_or_default(),
+                right.as_ref().map(to_text).unwrap_or_default()
+            )),
+            BinaryOp::Eq => Variant::Bool(matches!((&left, &right), (Some(x), Some(y)) if deep_eq(x, y))),
+            BinaryOp::Ne => Variant::Bool(matches!((&left, &right), (Some(x), Some(y)) if !deep_eq(x, y))),
+            BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => {
+                let (Some(left), Some(right)) = (left, right) else {
+                    return Ok(Some(Variant::Bool(false)));
+                };
+                let ordering = match (&left, &right) {
+                    (Variant::Number(x), Variant::Number(y)) => x.as_f64().partial_cmp(&y.as_f64()),
+                    (Variant::String(x), Variant::String(y)) => Some(x.cmp(y)),
+                    _ => {
+                        return Err(type_error(&format!(
+                            "The operands of the comparison must be both numbers or both strings, got: {:?} and {:?}",
+                            left, right
+                        )))
+                    }
+                };
+                Variant::Bool(ordering.is_some_and(|ordering| match op {
+                    BinaryOp::Lt => ordering.is_lt(),
+                    BinaryOp::Le => ordering.is_le(),
+                    BinaryOp::Gt => ordering.is_gt(),
+                    _ => ordering.is_ge(),
+                }))
+            }
+            BinaryOp::In => Variant::Bool(match (&left, &right) {
+                (Some(x), Some(Variant::Array(items))) => items.iter().any(|y| deep_eq(x, y)),
+                (Some(x), Some(y)) => deep_eq(x, y),
+                _ => false,
+            }),
+            BinaryOp::And | BinaryOp::Or => unreachable!(),
+        };
+        Ok(Some(result))
+    }
+
+    fn call(&mut self, name: &str, args: &[Node], context: &Variant) -> crate::Result<Option<Variant>> {
+        if let Some(key) = context_key_of(name, args)? {
+            return Ok(self.bindings.contexts.get(&key).cloned());
+        }
+        let mut values = Vec::with_capacity(args.len());
+        for arg in args {
+            values.push(self.eval(arg, context)?);
+        }
+        if name == "env" {
+            return match values.first() {
+                Some(Some(Variant::String(name))) => Ok(self.bindings.env.and_then(|env| env(name))),
+                Some(None) => Ok(None),
+                _ => Err(type_error("The argument of `$env()` must be a string")),
+            };
+        }
+        functions::call(name, values, context)
+    }
+}
+
+pub(super) fn type_error(message: &str) -> anyhow::Error {
+    EdgelinkError::InvalidOperation(format!("JSONata: {}", message)).into()
+}
+
+/// Makes the number, the integral ones are kept as integers so they are serialized without the fraction.
+pub(super) fn number(value: f64) -> crate::Result<Variant> {
+    if !value.is_finite() {
+        Err(EdgelinkError::OutOfRange.into())
+    } else if value.fract() == 0.0 && value.abs() <= 9_007_199_254_740_992.0 {
+        Ok(Variant::from(value as i64))
+    } else {
+        Ok(Variant::from(value))
+    }
+}
+
+pub(super) fn integer(value: &Variant) -> crate::Result<i64> {
+    match value.as_f64() {
+        Some(x) if x.fract() == 0.0 && x.abs() <= 9_007_199_254_740_992.0 => Ok(x as i64),
+        _ => Err(type_error(&format!("Expected an integer, got: {:?}", value))),
+    }
+}
+
+/// The result of a sequence, `undefined` if it is empty and the only item if it is a singleton.
+pub(super) fn collapse(mut items: Vec<Variant>) -> Option<Variant> {
+    match items.len() {
+        0 => None,
+        1 => items.pop(),
+        _ => Some(Variant::Array(items)),
+    }
+}
+
+fn flatten(values: impl Iterator<Item = Variant>) -> Vec<Variant> {
+    let mut results = Vec::new();
+    for value in values {
+        match value {
+            Variant::Array(items) => results.extend(items),
+            value => results.push(value),
+        }
+    }
+    results
+}
+
+fn field(context: &Variant, name: &str) -> Option<Variant> {
+    match context {
+        Variant::Object(map) => map.get(name).cloned(),
+        _ => None,
+    }
+}
+
+fn is_index(index: Option<f64>, position: usize, len: usize) -> bool {
+    let Some(index) = index else {
+        return false;
+    };
+    let index = index.floor();
+    let index = if index < 0.0 { len as f64 + index } else { index };
+    index == position as f64
+}
+
+/// The equality of JSONata, the numbers are compared by the values so `2` equals `2.0`.
+pub(super) fn deep_eq(x: &Variant, y: &Variant) -> bool {
+    match (x, y) {
+        (Variant::Number(x), Variant::Number(y)) => x.as_f64() == y.as_f64(),
+        (Variant::Array(x), Variant::Array(y)) => x.len() == y.len() && x.iter().zip(y).all(|(x, y)| deep_eq(x, y)),
+        (Variant::Object(x), Variant::Object(y)) => {
+            x.len() == y.len() && x.iter().all(|(k, v)| y.get(k).is_some_and(|w| deep_eq(v, w)))
+        }
+        _ => x == y,
+    }
+}
+
+/// The text of the value like the `$string()` of JSONata.
+pub(super) fn to_text(value: &Variant) -> String {
+    match value {
+        Variant::String(s) => s.clone(),
+        Variant::Number(n) => match n.as_i64() {
+            Some(i) => i.to_string(),
+            None => {
+                let x = n.as_f64().unwrap_or(f64::NAN);
+                if x.fract() == 0.0 && x.abs() < 1e21 {
+                    format!("{:.0}", x)
+                } else {
+                    x.to_string()
+                }
+            }
+        },
+        other => serde_json::to_string(&serde_json::Value::from(other)).unwrap_or_default(),
+    }
+}
@@ -0,0 +1,237 @@
+use crate::runtime::model::Variant;
+use crate::utils;
+use crate::*;
+
+use super::evaluator::{collapse, deep_eq, integer, number, to_text, type_error};
+use super::is_truthy;
+
+/// The functions taking the context as the argument if they are called without one, e.g. `name.$uppercase()`.
+const CONTEXT_ARG_FUNCTIONS: [&str; 8] =
+    ["string", "length", "uppercase", "lowercase", "trim", "number", "boolean", "not"];
+
+pub(super) fn call(name: &str, mut args: Vec<Option<Variant>>, context: &Variant) -> crate::Result<Option<Variant>> {
+    if args.is_empty() && CONTEXT_ARG_FUNCTIONS.contains(&name) {
+        args.push(Some(context.clone()));
+    }
+    let arg = |index: usize| args.get(index).and_then(Option::as_ref);
+
+    let result = match name {
+        "string" => arg(0).map(|x| Variant::String(to_text(x))),
+        "length" => match string_arg(name, arg(0))? {
+            Some(s) => Some(number(s.chars().count() as f64)?),
+            None => None,
+        },
+        "substring" => match string_arg(name, arg(0))? {
+            Some(s) => {
+                let chars: Vec<char> = s.chars().collect();
+                let len = chars.len() as i64;
+                let start = arg(1).map(integer).transpose()?.unwrap_or(0);
+                let start = if start < 0 { (len + start).max(0) } else { start.min(len) };
+                let end = match arg(2).map(integer).transpose()? {
+                    Some(count) => (start + count.max(0)).min(len),
+                    None => len,
+                };
+                Some(Variant::String(chars[start as usize..end as usize].iter().collect()))
+            }
+            None => None,
+        },
+        "uppercase" => string_arg(name, arg(0))?.map(|s| Variant::String(s.to_uppercase())),
+        "lowercase" => string_arg(name, arg(0))?.map(|s| Variant::String(s.to_lowercase())),
+        "trim" => {
+            string_arg(name, arg(0))?.map(|s| Variant::String(s.split_whitespace().collect::<Vec<_>>().join(" ")))
+        }
+        "contains" => match (string_arg(name, arg(0))?, string_arg(name, arg(1))?) {
+            (Some(s), Some(pattern)) => Some(Variant::Bool(s.contains(pattern))),
+            _ => None,
+        },
+        "split" => match (string_arg(name, arg(0))?, string_arg(name, arg(1))?) {
+            (Some(s), Some(separator)) => {
+                let limit = arg(2).map(integer).transpose()?.map(|x| x.max(0) as usize).unwrap_or(usize::MAX);
+                let parts: Vec<Variant> = if separator.is_empty() {
+                    s.chars().take(limit).map(|c| Variant::String(c.to_string())).collect()
+                } else {
+                    s.split(separator).take(limit).map(Variant::from).collect()
+                };
+                Some(Variant::Array(parts))
+            }
+            _ => None,
+        },
+        "join" => {
+            let separator = string_arg(name, arg(1))?.unwrap_or("");
+            match arg(0) {
+                Some(Variant::String(s)) => Some(Variant::String(s.clone())),
+                Some(Variant::Array(items)) => {
+                    let parts = items.iter().map(|x| string_arg(name, Some(x)).map(Option::unwrap_or_default));
+                    Some(Variant::String(parts.collect::<crate::Result<Vec<_>>>()?.join(separator)))
+                }
+                Some(other) => {
+                    return Err(type_error(&format!("`$join()` expects an array of strings, got: {:?}", other)))
+                }
+                None => None,
+            }
+        }
+        "number" => match arg(0) {
+            Some(Variant::Number(_)) => arg(0).cloned(),
+            Some(Variant::String(s)) => match s.trim().parse::<f64>() {
+                Ok(x) if x.is_finite() => Some(number(x)?),
+                _ => return Err(type_error(&format!("Cannot convert the string to a number: {:?}", s))),
+            },
+            Some(Variant::Bool(b)) => Some(Variant::from(*b as i64)),
+            Some(other) => return Err(type_error(&format!("Cannot convert the value to a number: {:?}", other))),
+            None => None,
+        },
+        "boolean" => arg(0).map(|x| Variant::Bool(is_truthy(x))),
+        "not" => arg(0).map(|x| Variant::Bool(!is_truthy(x))),
+        "exists" => Some(Variant::Bool(arg(0).is_some())),
+        "count" => Some(Variant::from(match arg(0) {
+            Some(Variant::Array(items)) => items.len() as i64,
+            Some(_) => 1,
+            None => 0,
+        })),
+        "sum" => Some(number(numbers_arg(name, arg(0))?.iter().sum())?),
+        "max" | "min" | "average" => {
+            let numbers = numbers_arg(name, arg(0))?;
+            if numbers.is_empty() {
+                None
+            } else {
+                let result = match name {
+                    "max" => numbers.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+                    "min" => numbers.iter().copied().fold(f64::INFINITY, f64::min),
+                    _ => numbers.iter().sum::<f64>() / numbers.len() as f64,
+                };
+                Some(number(result)?)
+            }
+        }
+        "round" => match number_arg(name, arg(0))? {
+            Some(x) => {
+                let precision = arg(1).map(integer).transpose()?.unwrap_or(0).clamp(-15, 15) as i32;
+                let scale = 10f64.powi(precision);
+                Some(number(round_half_even(x * scale) / scale)?)
+            }
+            None => None,
+        },
+        "floor" => number_arg(name, arg(0))?.map(|x| number(x.floor())).transpose()?,
+        "ceil" => number_arg(name, arg(0))?.map(|x| number(x.ceil())).transpose()?,
+        "abs" => number_arg(name, arg(0))?.map(|x| number(x.abs())).transpose()?,
+        "sqrt" => match number_arg(name, arg(0))? {
+            Some(x) if x < 0.0 => return Err(EdgelinkError::OutOfRange.into()),
+            Some(x) => Some(number(x.sqrt())?),
+            None => None,
+        },
+        "power" => match (number_arg(name, arg(0))?, number_arg(name, arg(1))?) {
+            (Some(base), Some(exponent)) => Some(number(base.powf(exponent))?),
+            _ => None,
+        },
+        "keys" => {
+            let mut keys: Vec<Variant> = Vec::new();
+            let objects = match arg(0) {
+                Some(Variant::Array(items)) => items.iter().collect(),
+                Some(value) => vec![value],
+                None => Vec::new(),
+            };
+            for key in objects.into_iter().filter_map(Variant::as_object).flat_map(|map| map.keys()) {
+                let key = Variant::from(key.as_str());
+                if !keys.contains(&key) {
+                    keys.push(key);
+                }
+            }
+            (!keys.is_empty()).then_some(Variant::Array(keys))
+        }
+        "lookup" => {
+            let key = string_arg(name, arg(1))?.unwrap_or_default();
+            match arg(0) {
+                Some(Variant::Array(items)) => {
+                    collapse(items.iter().filter_map(|x| x.as_object().and_then(|map| map.get(key)).cloned()).collect())
+                }
+                Some(Variant::Object(map)) => map.get(key).cloned(),
+                _ => None,
+            }
+        }
+        "append" => match (arg(0), arg(1)) {
+            (Some(x), None) | (None, Some(x)) => Some(x.clone()),
+            (Some(x), Some(y)) => {
+                let mut items = into_items(x.clone());
+                items.extend(into_items(y.clone()));
+                Some(Variant::Array(items))
+            }
+            (None, None) => None,
+        },
+        "reverse" => match arg(0) {
+            Some(Variant::Array(items)) => Some(Variant::Array(items.iter().rev().cloned().collect())),
+            other => other.cloned(),
+        },
+        "distinct" => match arg(0) {
+            Some(Variant::Array(items)) => {
+                let mut distinct: Vec<Variant> = Vec::with_capacity(items.len());
+                for item in items {
+                    if !distinct.iter().any(|x| deep_eq(x, item)) {
+                        distinct.push(item.clone());
+                    }
+                }
+                Some(Variant::Array(distinct))
+            }
+            other => other.cloned(),
+        },
+        "type" => arg(0).map(|x| {
+            Variant::from(match x {
+                Variant::Null => "null",
+                Variant::Number(_) => "number",
+                Variant::String(_) => "string",
+                Variant::Bool(_) => "boolean",
+                Variant::Array(_) | Variant::Bytes(_) => "array",
+                Variant::Object(_) | Variant::Date(_) | Variant::Regexp(_) => "object",
+            })
+        }),
+        "now" => Some(Variant::String(utils::time::iso_now())),
+        "millis" => Some(Variant::from(utils::time::unix_now())),
+        _ => {
+            return Err(
+                EdgelinkError::NotSupported(format!("The JSONata function `${}()` is not supported", name)).into()
+            )
+        }
+    };
+    Ok(result)
+}
+
+fn string_arg<'v>(name: &str, value: Option<&'v Variant>) -> crate::Result<Option<&'v str>> {
+    match value {
+        Some(Variant::String(s)) => Ok(Some(s.as_str())),
+        Some(other) => Err(type_error(&format!("`${}()` expects a string, got: {:?}", name, other))),
+        None => Ok(None),
+    }
+}
+
+fn number_arg(name: &str, value: Option<&Variant>) -> crate::Result<Option<f64>> {
+    match value {
+        Some(Variant::Number(n)) => Ok(n.as_f64()),
+        Some(other) => Err(type_error(&format!("`${}()` expects a number, got: {:?}", name, other))),
+        None => Ok(None),
+    }
+}
+
+/// The numbers of a number or an array of numbers.
+fn numbers_arg(name: &str, value: Option<&Variant>) -> crate::Result<Vec<f64>> {
+    let items = match value {
+        Some(Variant::Array(items)) => items.iter().collect(),
+        Some(value) => vec![value],
+        None => Vec::new(),
+    };
+    items.into_iter().map(|x| number_arg(name, Some(x)).map(|x| x.unwrap_or(f64::NAN))).collect()
+}
+
+fn into_items(value: Variant) -> Vec<Variant> {
+    match value {
+        Variant::Array(items) => items,
+        value => vec![value],
+    }
+}
+
+/// Rounds half to even like the `$round()` of JSONata.
+fn round_half_even(x: f64) -> f64 {
+    let rounded = x.round();
+    if (x - x.trunc()).abs() == 0.5 && rounded % 2.0 != 0.0 {
+        rounded - x.signum()
+    } else {
+        rounded
+    }
+}
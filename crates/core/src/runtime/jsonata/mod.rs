@@ -0,0 +1,266 @@
+//! The JSONata expressions of the `jsonata` properties, e.g. the `jsonata_exp` rules of the switch node.
+//!
+//! It covers the paths, the predicates, the operators, the conditions, the variable bindings, the array and object
+//! constructors and the common functions of JSONata. The lambdas, the higher-order functions and the regular
+//! expressions are not supported.
+
+use std::collections::HashMap;
+
+use crate::runtime::model::Variant;
+use crate::*;
+
+mod evaluator;
+mod functions;
+mod parser;
+
+use parser::{Node, Parser};
+
+/// The scope of the context read by `$flowContext()` and `$globalContext()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JsonataContextScope {
+    Flow,
+    Global,
+}
+
+/// Gets the environment variable by its name.
+pub type EnvResolver<'a> = &'a dyn Fn(&str) -> Option<Variant>;
+
+/// The values the expression can access besides its input.
+///
+/// The context stores are asynchronous, so the context values read by the expression are fetched before the
+/// evaluation, see `Expression::context_keys()`.
+#[derive(Default)]
+pub struct Bindings<'a> {
+    contexts: HashMap<(JsonataContextScope, String), Variant>,
+
+    /// Gets the environment variable for `$env()`
+    pub env: Option<EnvResolver<'a>>,
+}
+
+impl<'a> Bindings<'a> {
+    /// Binds the value of the context key, the key is the one returned by `Expression::context_keys()`.
+    pub fn set_context(&mut self, scope: JsonataContextScope, key: String, value: Variant) {
+        self.contexts.insert((scope, key), value);
+    }
+}
+
+/// A parsed JSONata expression, it can be evaluated many times.
+#[derive(Debug, Clone)]
+pub struct Expression {
+    root: Node,
+    context_keys: Vec<(JsonataContextScope, String)>,
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> crate::Result<Self> {
+        let root = Parser::parse(source)?;
+        let mut context_keys = Vec::new();
+        collect_context_keys(&root, &mut context_keys)?;
+        Ok(Expression { root, context_keys })
+    }
+
+    /// The context keys read by `$flowContext()` and `$globalContext()`, in the `#:(store)::key` form if the store
+    /// is given.
+    pub fn context_keys(&self) -> &[(JsonataContextScope, String)] {
+        &self.context_keys
+    }
+
+    /// Evaluates the expression, `None` is the `undefined` of JSONata.
+    pub fn evaluate(&self, input: &Variant, bindings: &Bindings) -> crate::Result<Option<Variant>> {
+        evaluator::Evaluator::new(input, bindings).evaluate(&self.root)
+    }
+}
+
+/// The truthiness of the value like the `$boolean()` of JSONata.
+pub fn is_truthy(value: &Variant) -> bool {
+    match value {
+        Variant::Null => false,
+        Variant::Bool(b) => *b,
+        Variant::Number(n) => n.as_f64().is_some_and(|x| x != 0.0),
+        Variant::String(s) => !s.is_empty(),
+        Variant::Array(items) => items.iter().any(is_truthy),
+        Variant::Object(map) => !map.is_empty(),
+        Variant::Bytes(bytes) => !bytes.is_empty(),
+        Variant::Date(_) | Variant::Regexp(_) => true,
+    }
+}
+
+/// The context key of the arguments of `$flowContext()` or `$globalContext()`, they must be string literals.
+fn context_key_of(name: &str, args: &[Node]) -> crate::Result<Option<(JsonataContextScope, String)>> {
+    let scope = match name {
+        "flowContext" => JsonataContextScope::Flow,
+        "globalContext" => JsonataContextScope::Global,
+        _ => return Ok(None),
+    };
+    let key = match args {
+        [Node::String(key)] => key.clone(),
+        [Node::String(key), Node::String(store)] => format!("#:({})::{}", store, key),
+        _ => {
+            return Err(EdgelinkError::InvalidOperation(format!(
+                "The arguments of `${}()` must be the key and the optional store in string literals",
+                name
+            ))
+            .into())
+        }
+    };
+    Ok(Some((scope, key)))
+}
+
+fn collect_context_keys(node: &Node, keys: &mut Vec<(JsonataContextScope, String)>) -> crate::Result<()> {
+    match node {
+        Node::Call(name, args) => {
+            for arg in args {
+                collect_context_keys(arg, keys)?;
+            }
+            if let Some(key) = context_key_of(name, args)? {
+                if !keys.contains(&key) {
+                    keys.push(key);
+                }
+            }
+        }
+        Node::Path(items) | Node::Block(items) | Node::Array(items) => {
+            for item in items {
+                collect_context_keys(item, keys)?;
+            }
+        }
+        Node::Filter(a, b) | Node::Binary(_, a, b) | Node::Range(a, b) => {
+            collect_context_keys(a, keys)?;
+            collect_context_keys(b, keys)?;
+        }
+        Node::Condition(a, b, c) => {
+            collect_context_keys(a, keys)?;
+            collect_context_keys(b, keys)?;
+            if let Some(c) = c {
+                collect_context_keys(c, keys)?;
+            }
+        }
+        Node::Negate(a) | Node::Bind(_, a) => collect_context_keys(a, keys)?,
+        Node::Object(pairs) => {
+            for (k, v) in pairs {
+                collect_context_keys(k, keys)?;
+                collect_context_keys(v, keys)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn eval(expr: &str, input: serde_json::Value) -> Option<Variant> {
+        Expression::parse(expr).unwrap().evaluate(&Variant::from(input), &Bindings::default()).unwrap()
+    }
+
+    fn eval_json(expr: &str, input: serde_json::Value) -> serde_json::Value {
+        eval(expr, input).map(|x| serde_json::Value::from(&x)).unwrap_or(serde_json::Value::Null)
+    }
+
+    #[test]
+    fn test_paths_and_predicates() {
+        let msg = json!({
+            "payload": {"items": [{"name": "a", "price": 5}, {"name": "b", "price": 20}, {"name": "c", "price": 30}]},
+            "topic": "orders"
+        });
+        assert_eq!(eval_json("topic", msg.clone()), json!("orders"));
+        assert_eq!(eval_json("payload.items.name", msg.clone()), json!(["a", "b", "c"]));
+        assert_eq!(eval_json("payload.items[0].name", msg.clone()), json!("a"));
+        assert_eq!(eval_json("payload.items[-1].price", msg.clone()), json!(30));
+        assert_eq!(eval_json("payload.items[price > 10].name", msg.clone()), json!(["b", "c"]));
+        assert_eq!(eval_json("payload.items[price > 25].name", msg.clone()), json!("c"));
+        assert_eq!(eval_json("$sum(payload.items.price)", msg.clone()), json!(55));
+        assert_eq!(eval_json("$count(payload.items[price < 0])", msg.clone()), json!(0));
+        assert_eq!(eval_json("payload.`items`[1].*", msg.clone()), json!(["b", 20]));
+        assert_eq!(eval("missing.path", msg.clone()), None);
+        assert_eq!(eval_json("$.topic & '/' & $$.payload.items[0].name", msg), json!("orders/a"));
+    }
+
+    #[test]
+    fn test_operators() {
+        let msg = json!({"payload": 42, "flag": true, "name": "edge"});
+        assert_eq!(eval_json("payload + 8 * 2 - 10 / 4", msg.clone()), json!(55.5));
+        assert_eq!(eval_json("payload % 5", msg.clone()), json!(2));
+        assert_eq!(eval_json("-payload", msg.clone()), json!(-42));
+        assert_eq!(eval_json("payload > 40 and flag", msg.clone()), json!(true));
+        assert_eq!(eval_json("payload < 40 or name = 'edge'", msg.clone()), json!(true));
+        assert_eq!(eval_json("name != 'edge'", msg.clone()), json!(false));
+        assert_eq!(eval_json("name & 'link' & payload", msg.clone()), json!("edgelink42"));
+        assert_eq!(eval_json("payload in [1, 42]", msg.clone()), json!(true));
+        assert_eq!(eval_json("payload > 100 ? 'high' : 'low'", msg.clone()), json!("low"));
+        assert_eq!(eval_json("missing = 1", msg.clone()), json!(false));
+        assert_eq!(eval("missing + 1", msg.clone()), None);
+        assert_eq!(eval_json("($x := payload * 2; $y := $x + 1; [$x, $y])", msg.clone()), json!([84, 85]));
+        assert_eq!(eval_json("[1..3, 'a']", msg.clone()), json!([1, 2, 3, "a"]));
+        assert_eq!(eval_json("{'v': payload, name: flag}", msg.clone()), json!({"v": 42, "edge": true}));
+
+        let bad = Expression::parse("name + 1").unwrap();
+        assert!(bad.evaluate(&Variant::from(msg.clone()), &Bindings::default()).is_err());
+        let bad = Expression::parse("name < 1").unwrap();
+        assert!(bad.evaluate(&Variant::from(msg), &Bindings::default()).is_err());
+    }
+
+    #[test]
+    fn test_functions() {
+        let msg = json!({"payload": [3, 1, 2], "text": "  Hello   World ", "obj": {"a": 1, "b": 2}});
+        assert_eq!(eval_json("$count(payload)", msg.clone()), json!(3));
+        assert_eq!(eval_json("[$max(payload), $min(payload), $average(payload)]", msg.clone()), json!([3, 1, 2]));
+        assert_eq!(eval_json("$trim(text)", msg.clone()), json!("Hello World"));
+        assert_eq!(eval_json("$uppercase($substring($trim(text), 0, 5))", msg.clone()), json!("HELLO"));
+        assert_eq!(eval_json("$length($trim(text))", msg.clone()), json!(11));
+        assert_eq!(eval_json("$contains(text, 'World')", msg.clone()), json!(true));
+        assert_eq!(eval_json("$join($split('a,b,c', ','), '-')", msg.clone()), json!("a-b-c"));
+        assert_eq!(eval_json("$keys(obj)", msg.clone()), json!(["a", "b"]));
+        assert_eq!(eval_json("$lookup(obj, 'b')", msg.clone()), json!(2));
+        assert_eq!(eval_json("$string(obj)", msg.clone()), json!(r#"{"a":1,"b":2}"#));
+        assert_eq!(eval_json("$number('1.5') + 1", msg.clone()), json!(2.5));
+        assert_eq!(eval_json("[$round(2.5), $round(3.5), $round(1.234, 2)]", msg.clone()), json!([2, 4, 1.23]));
+        assert_eq!(
+            eval_json("[$exists(obj.a), $exists(obj.c), $not(obj.a)]", msg.clone()),
+            json!([true, false, false])
+        );
+        assert_eq!(eval_json("$type(payload)", msg.clone()), json!("array"));
+        assert_eq!(eval_json("$append(payload, 4)", msg), json!([3, 1, 2, 4]));
+    }
+
+    #[test]
+    fn test_context_and_env_bindings() {
+        let expr =
+            Expression::parse("$flowContext('limit') < payload and $globalContext('mode', 'file') = $env('MODE')")
+                .unwrap();
+        assert_eq!(
+            expr.context_keys(),
+            &[
+                (JsonataContextScope::Flow, "limit".to_string()),
+                (JsonataContextScope::Global, "#:(file)::mode".to_string())
+            ]
+        );
+        let env = |name: &str| (name == "MODE").then(|| Variant::from("on"));
+        let mut bindings = Bindings { env: Some(&env), ..Default::default() };
+        bindings.set_context(JsonataContextScope::Flow, "limit".into(), Variant::from(10));
+        bindings.set_context(JsonataContextScope::Global, "#:(file)::mode".into(), Variant::from("on"));
+        assert_eq!(expr.evaluate(&Variant::from(json!({"payload": 11})), &bindings).unwrap(), Some(true.into()));
+        assert_eq!(expr.evaluate(&Variant::from(json!({"payload": 9})), &bindings).unwrap(), Some(false.into()));
+
+        // The keys must be known before the evaluation
+        assert!(Expression::parse("$flowContext(topic)").is_err());
+    }
+
+    #[test]
+    fn test_is_truthy() {
+        for (value, expected) in [
+            (json!(0), false),
+            (json!(""), false),
+            (json!([]), false),
+            (json!({}), false),
+            (json!(null), false),
+            (json!([0, 1]), true),
+            (json!("0"), true),
+            (json!(-1), true),
+        ] {
+            assert_eq!(is_truthy(&Variant::from(value.clone())), expected, "{}", value);
+        }
+    }
+}
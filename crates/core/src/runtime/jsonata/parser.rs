@@ -0,0 +1,446 @@
+use crate::*;
+
+/// The binary operators, see the operator table of JSONata.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Concat,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    In,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Number(f64),
+    String(String),
+    Bool(bool),
+    Null,
+
+    /// A field of the context object, e.g. `payload`
+    Name(String),
+
+    /// All the fields of the context object
+    Wildcard,
+
+    /// `$` is the context, `$$` is the input and `$name` is a bound variable
+    Variable(String),
+
+    /// The steps of a path, e.g. `payload.items.price`
+    Path(Vec<Node>),
+
+    /// A predicate or an index, e.g. `items[price > 10]` or `items[0]`
+    Filter(Box<Node>, Box<Node>),
+
+    Negate(Box<Node>),
+    Binary(BinaryOp, Box<Node>, Box<Node>),
+    Condition(Box<Node>, Box<Node>, Option<Box<Node>>),
+
+    /// `$name := value`
+    Bind(String, Box<Node>),
+
+    /// `(expr1; expr2; ...)`, the value is the last expression
+    Block(Vec<Node>),
+
+    Array(Vec<Node>),
+
+    /// `from..to`, only in the array constructors
+    Range(Box<Node>, Box<Node>),
+
+    Object(Vec<(Node, Node)>),
+
+    /// `$name(args...)`
+    Call(String, Vec<Node>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    String(String),
+    Name(String),
+    Variable(String),
+    Operator(&'static str),
+    End,
+}
+
+const OPERATORS: [&str; 27] = [
+    ":=", "!=", "<=", ">=", "..", "**", ".", "[", "]", "{", "}", "(", ")", ",", ":", ";", "?", "+", "-", "*", "/", "%",
+    "&", "=", "<", ">", "|",
+];
+
+struct Lexer<'a> {
+    source: &'a str,
+    pos: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn error(&self, message: &str) -> anyhow::Error {
+        EdgelinkError::InvalidOperation(format!("Bad JSONata expression at {}: {}", self.pos, message)).into()
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.source[self.pos..]
+    }
+
+    fn skip_spaces_and_comments(&mut self) -> crate::Result<()> {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+            if let Some(comment) = trimmed.strip_prefix("/*") {
+                match comment.find("*/") {
+                    Some(end) => self.pos += end + 4,
+                    None => return Err(self.error("the comment is not closed")),
+                }
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn next(&mut self) -> crate::Result<Token> {
+        self.skip_spaces_and_comments()?;
+        let rest = self.rest();
+        let c = match rest.chars().next() {
+            Some(c) => c,
+            None => return Ok(Token::End),
+        };
+
+        if c.is_ascii_digit() {
+            return self.number();
+        }
+        if c == '"' || c == '\'' {
+            return self.string(c).map(Token::String);
+        }
+        if c == '`' {
+            return match rest[1..].find('`') {
+                Some(end) => {
+                    self.pos += end + 2;
+                    Ok(Token::Name(rest[1..end + 1].to_string()))
+                }
+                None => Err(self.error("the quoted name is not closed")),
+            };
+        }
+        if c == '$' {
+            if rest.starts_with("$$") {
+                self.pos += 2;
+                return Ok(Token::Variable("$".to_string()));
+            }
+            self.pos += 1;
+            let name = self.name();
+            return Ok(Token::Variable(name));
+        }
+        if let Some(op) = OPERATORS.iter().find(|x| rest.starts_with(**x)) {
+            self.pos += op.len();
+            return Ok(Token::Operator(op));
+        }
+        if is_name_char(c) {
+            return Ok(Token::Name(self.name()));
+        }
+        Err(self.error(&format!("unexpected character '{}'", c)))
+    }
+
+    fn name(&mut self) -> String {
+        let rest = self.rest();
+        let end = rest.find(|c: char| !is_name_char(c)).unwrap_or(rest.len());
+        self.pos += end;
+        rest[..end].to_string()
+    }
+
+    fn number(&mut self) -> crate::Result<Token> {
+        let bytes = self.rest().as_bytes();
+        let mut end = 0;
+        while end < bytes.len() && bytes[end].is_ascii_digit() {
+            end += 1;
+        }
+        // The dot of the range operator `1..3` is not a decimal point
+        if end + 1 < bytes.len() && bytes[end] == b'.' && bytes[end + 1].is_ascii_digit() {
+            end += 1;
+            while end < bytes.len() && bytes[end].is_ascii_digit() {
+                end += 1;
+            }
+        }
+        if end < bytes.len() && (bytes[end] == b'e' || bytes[end] == b'E') {
+            let mut exp_end = end + 1;
+            if exp_end < bytes.len() && (bytes[exp_end] == b'+' || bytes[exp_end] == b'-') {
+                exp_end += 1;
+            }
+            if exp_end < bytes.len() && bytes[exp_end].is_ascii_digit() {
+                end = exp_end;
+                while end < bytes.len() && bytes[end].is_ascii_digit() {
+                    end += 1;
+                }
+            }
+        }
+        let text = &self.rest()[..end];
+        let number = text.parse::<f64>().map_err(|_| self.error(&format!("bad number '{}'", text)))?;
+        if !number.is_finite() {
+            return Err(self.error(&format!("the number '{}' is out of range", text)));
+        }
+        self.pos += end;
+        Ok(Token::Number(number))
+    }
+
+    fn string(&mut self, quote: char) -> crate::Result<String> {
+        let mut result = String::new();
+        let mut chars = self.rest().char_indices().skip(1);
+        while let Some((i, c)) = chars.next() {
+            match c {
+                c if c == quote => {
+                    self.pos += i + 1;
+                    return Ok(result);
+                }
+                '\\' => match chars.next().map(|x| x.1) {
+                    Some('n') => result.push('\n'),
+                    Some('t') => result.push('\t'),
+                    Some('r') => result.push('\r'),
+                    Some('b') => result.push('\u{8}'),
+                    Some('f') => result.push('\u{c}'),
+                    Some('u') => {
+                        let hex: String = chars.by_ref().take(4).map(|x| x.1).collect();
+                        let code = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32);
+                        result.push(code.ok_or_else(|| self.error(&format!("bad unicode escape '\\u{}'", hex)))?);
+                    }
+                    Some(c @ ('"' | '\'' | '\\' | '/')) => result.push(c),
+                    Some(c) => return Err(self.error(&format!("unsupported escape '\\{}'", c))),
+                    None => break,
+                },
+                c => result.push(c),
+            }
+        }
+        Err(self.error("the string is not closed"))
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// The left binding power of the infix operators.
+fn infix_power(token: &Token) -> u8 {
+    match token {
+        Token::Operator(op) => match *op {
+            "[" | "(" => 80,
+            "." => 75,
+            "*" | "/" | "%" => 60,
+            "+" | "-" | "&" => 50,
+            "=" | "!=" | "<" | "<=" | ">" | ">=" => 40,
+            "?" | ".." => 20,
+            ":=" => 10,
+            _ => 0,
+        },
+        Token::Name(name) => match name.as_str() {
+            "in" => 40,
+            "and" => 30,
+            "or" => 25,
+            _ => 0,
+        },
+        _ => 0,
+    }
+}
+
+pub struct Parser<'a> {
+    lexer: Lexer<'a>,
+    current: Token,
+}
+
+impl<'a> Parser<'a> {
+    pub fn parse(source: &'a str) -> crate::Result<Node> {
+        let mut lexer = Lexer { source, pos: 0 };
+        let current = lexer.next()?;
+        let mut parser = Parser { lexer, current };
+        let node = parser.expression(0)?;
+        if parser.current != Token::End {
+            return Err(parser.lexer.error(&format!("unexpected {:?}", parser.current)));
+        }
+        Ok(node)
+    }
+
+    fn advance(&mut self) -> crate::Result<Token> {
+        let next = self.lexer.next()?;
+        Ok(std::mem::replace(&mut self.current, next))
+    }
+
+    fn expect(&mut self, op: &'static str) -> crate::Result<()> {
+        if self.current == Token::Operator(op) {
+            self.advance()?;
+            Ok(())
+        } else {
+            Err(self.lexer.error(&format!("expected '{}' but got {:?}", op, self.current)))
+        }
+    }
+
+    fn expression(&mut self, rbp: u8) -> crate::Result<Node> {
+        let token = self.advance()?;
+        let mut left = self.prefix(token)?;
+        while rbp < infix_power(&self.current) {
+            let token = self.advance()?;
+            left = self.infix(token, left)?;
+        }
+        Ok(left)
+    }
+
+    /// Parses the items separated by `sep` until `close`.
+    fn items(&mut self, sep: &'static str, close: &'static str) -> crate::Result<Vec<Node>> {
+        let mut items = Vec::new();
+        if self.current != Token::Operator(close) {
+            loop {
+                items.push(self.expression(0)?);
+                if self.current != Token::Operator(sep) {
+                    break;
+                }
+                self.advance()?;
+            }
+        }
+        self.expect(close)?;
+        Ok(items)
+    }
+
+    fn prefix(&mut self, token: Token) -> crate::Result<Node> {
+        match token {
+            Token::Number(n) => Ok(Node::Number(n)),
+            Token::String(s) => Ok(Node::String(s)),
+            Token::Variable(name) => Ok(Node::Variable(name)),
+            Token::Name(name) => Ok(match name.as_str() {
+                "true" => Node::Bool(true),
+                "false" => Node::Bool(false),
+                "null" => Node::Null,
+                _ => Node::Name(name),
+            }),
+            Token::Operator("*") => Ok(Node::Wildcard),
+            Token::Operator("-") => Ok(Node::Negate(Box::new(self.expression(70)?))),
+            Token::Operator("(") => Ok(Node::Block(self.items(";", ")")?)),
+            Token::Operator("[") => Ok(Node::Array(self.items(",", "]")?)),
+            Token::Operator("{") => {
+                let mut pairs = Vec::new();
+                if self.current != Token::Operator("}") {
+                    loop {
+                        let key = self.expression(0)?;
+                        self.expect(":")?;
+                        pairs.push((key, self.expression(0)?));
+                        if self.current != Token::Operator(",") {
+                            break;
+                        }
+                        self.advance()?;
+                    }
+                }
+                self.expect("}")?;
+                Ok(Node::Object(pairs))
+            }
+            other => Err(self.lexer.error(&format!("unexpected {:?}", other))),
+        }
+    }
+
+    fn binary(&mut self, op: BinaryOp, left: Node, power: u8) -> crate::Result<Node> {
+        Ok(Node::Binary(op, Box::new(left), Box::new(self.expression(power)?)))
+    }
+
+    fn infix(&mut self, token: Token, left: Node) -> crate::Result<Node> {
+        match token {
+            Token::Operator(".") => {
+                let right = self.expression(75)?;
+                Ok(match left {
+                    Node::Path(mut steps) => {
+                        steps.push(right);
+                        Node::Path(steps)
+                    }
+                    left => Node::Path(vec![left, right]),
+                })
+            }
+            Token::Operator("[") => {
+                // `items[]` keeps the singleton array, it is the same as `items` here
+                if self.current == Token::Operator("]") {
+                    self.advance()?;
+                    return Ok(left);
+                }
+                let predicate = self.expression(0)?;
+                self.expect("]")?;
+                Ok(Node::Filter(Box::new(left), Box::new(predicate)))
+            }
+            Token::Operator("(") => match left {
+                Node::Variable(name) if !name.is_empty() && name != "$" => Ok(Node::Call(name, self.items(",", ")")?)),
+                _ => Err(self.lexer.error("only the functions can be called")),
+            },
+            Token::Operator("?") => {
+                let then = self.expression(0)?;
+                let otherwise = if self.current == Token::Operator(":") {
+                    self.advance()?;
+                    Some(Box::new(self.expression(0)?))
+                } else {
+                    None
+                };
+                Ok(Node::Condition(Box::new(left), Box::new(then), otherwise))
+            }
+            Token::Operator(":=") => match left {
+                Node::Variable(name) if !name.is_empty() && name != "$" => {
+                    Ok(Node::Bind(name, Box::new(self.expression(9)?)))
+                }
+                _ => Err(self.lexer.error("only a variable can be bound")),
+            },
+            Token::Operator("..") => Ok(Node::Range(Box::new(left), Box::new(self.expression(20)?))),
+            Token::Operator("+") => self.binary(BinaryOp::Add, left, 50),
+            Token::Operator("-") => self.binary(BinaryOp::Sub, left, 50),
+            Token::Operator("&") => self.binary(BinaryOp::Concat, left, 50),
+            Token::Operator("*") => self.binary(BinaryOp::Mul, left, 60),
+            Token::Operator("/") => self.binary(BinaryOp::Div, left, 60),
+            Token::Operator("%") => self.binary(BinaryOp::Mod, left, 60),
+            Token::Operator("=") => self.binary(BinaryOp::Eq, left, 40),
+            Token::Operator("!=") => self.binary(BinaryOp::Ne, left, 40),
+            Token::Operator("<") => self.binary(BinaryOp::Lt, left, 40),
+            Token::Operator("<=") => self.binary(BinaryOp::Le, left, 40),
+            Token::Operator(">") => self.binary(BinaryOp::Gt, left, 40),
+            Token::Operator(">=") => self.binary(BinaryOp::Ge, left, 40),
+            Token::Name(name) if name == "in" => self.binary(BinaryOp::In, left, 40),
+            Token::Name(name) if name == "and" => self.binary(BinaryOp::And, left, 30),
+            Token::Name(name) if name == "or" => self.binary(BinaryOp::Or, left, 25),
+            other => Err(self.lexer.error(&format!("unexpected {:?}", other))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_precedence_and_paths() {
+        let node = Parser::parse("a.b[0] + 2 * 3 > 1 and not_a").unwrap();
+        let expected = Node::Binary(
+            BinaryOp::And,
+            Box::new(Node::Binary(
+                BinaryOp::Gt,
+                Box::new(Node::Binary(
+                    BinaryOp::Add,
+                    Box::new(Node::Path(vec![
+                        Node::Name("a".into()),
+                        Node::Filter(Box::new(Node::Name("b".into())), Box::new(Node::Number(0.0))),
+                    ])),
+                    Box::new(Node::Binary(BinaryOp::Mul, Box::new(Node::Number(2.0)), Box::new(Node::Number(3.0)))),
+                )),
+                Box::new(Node::Number(1.0)),
+            )),
+            Box::new(Node::Name("not_a".into())),
+        );
+        assert_eq!(node, expected);
+        assert_eq!(Parser::parse("[1..3]").unwrap(), {
+            Node::Array(vec![Node::Range(Box::new(Node::Number(1.0)), Box::new(Node::Number(3.0)))])
+        });
+    }
+
+    #[test]
+    fn test_bad_expressions_should_fail() {
+        for expr in ["payload >", "(1; 2", "'abc", "a[1", "1 = = 2", "/* x", "{\"a\" 1}", "payload(1)", "#"] {
+            assert!(Parser::parse(expr).is_err(), "{}", expr);
+        }
+    }
+}
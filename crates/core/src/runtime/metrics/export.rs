@@ -0,0 +1,403 @@
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use prometheus::proto::{Bucket, Counter, Gauge, Histogram, LabelPair, Metric, MetricFamily, MetricType};
+use prometheus::{Encoder, TextEncoder};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::sync::CancellationToken;
+
+use super::{LatencyHistogram, LATENCY_BUCKETS};
+use crate::runtime::engine::Engine;
+use crate::runtime::model::{ElementId, FlowsElement};
+use crate::runtime::nodes::FlowNodeBehavior;
+use crate::EdgelinkError;
+
+/// How long a client has to send its request and read the response, a stalled client is disconnected after it
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long the counts of the context keys are reused, counting them reads every scope of every store
+const CONTEXT_KEYS_TTL: Duration = Duration::from_secs(30);
+
+/// The numbers of the context keys by the store names
+type ContextKeysCount = Vec<(String, usize)>;
+
+/// The counts of the context keys by the stores, shared by the scrapes of an engine.
+#[derive(Debug, Default)]
+struct ContextKeysCache {
+    counts: tokio::sync::Mutex<Option<(Instant, ContextKeysCount)>>,
+}
+
+/// Counts the keys of the context stores, at most once in `CONTEXT_KEYS_TTL`.
+async fn context_keys_count(engine: &Engine) -> ContextKeysCount {
+    let cache = engine.shared_state::<ContextKeysCache>();
+    // The concurrent scrapes wait for the one counting instead of counting again
+    let mut counts = cache.counts.lock().await;
+    match counts.as_ref() {
+        Some((counted_at, counts)) if counted_at.elapsed() < CONTEXT_KEYS_TTL => counts.clone(),
+        _ => {
+            let fresh = engine.get_context_manager().keys_count().await;
+            *counts = Some((Instant::now(), fresh.clone()));
+            fresh
+        }
+    }
+}
+
+/// Collects the metrics of the engine and all its flow nodes.
+pub async fn gather(engine: &Engine) -> Vec<MetricFamily> {
+    let health = engine.health();
+    let nodes = engine.get_all_flow_nodes();
+
+    let mut received = Vec::with_capacity(nodes.len());
+    let mut sent = Vec::with_capacity(nodes.len());
+    let mut errors = Vec::with_capacity(nodes.len());
+    let mut latencies = Vec::with_capacity(nodes.len());
+    let mut custom = Vec::new();
+    // The messages dropped by the overflow policies are counted by the wires, sums them up for their target nodes
+    let mut dropped_by_target: HashMap<ElementId, u64> = HashMap::new();
+    for wire in nodes.iter().flat_map(|x| x.get_node().ports.iter()).flat_map(|x| x.wires.iter()) {
+        *dropped_by_target.entry(wire.target_node_id).or_default() += wire.dropped_count();
+    }
+    let mut dropped = Vec::with_capacity(nodes.len());
+    for node in nodes.iter() {
+        let metrics = &node.get_node().metrics;
+        received.push(counter(node.as_ref(), metrics.received.load(Ordering::Relaxed)));
+        sent.push(counter(node.as_ref(), metrics.sent.load(Ordering::Relaxed)));
+        errors.push(counter(node.as_ref(), metrics.errors.load(Ordering::Relaxed)));
+        dropped.push(counter(node.as_ref(), dropped_by_target.get(&node.id()).copied().unwrap_or(0)));
+        latencies.push(histogram(node.as_ref(), &metrics.latency_histogram()));
+        for (name, value) in metrics.custom_metrics() {
+            custom.push(custom_gauge(node.as_ref(), &name, value));
+        }
+    }
+
+    let active_flows = health.flows.iter().filter(|x| x.running).count();
+    let mut context_keys = Vec::new();
+    for (store, count) in context_keys_count(engine).await {
+        context_keys.push(gauge(&[("store", &store)], count as f64));
+    }
+
+    let mut families = vec![
+        new_family("edgelink_node_messages_received_total", "Messages received", MetricType::COUNTER, received),
+        new_family("edgelink_node_messages_sent_total", "Messages sent", MetricType::COUNTER, sent),
+        new_family("edgelink_node_errors_total", "Messages failed to process", MetricType::COUNTER, errors),
+        new_family(
+            "edgelink_node_messages_dropped_total",
+            "Messages dropped by the overflow policy",
+            MetricType::COUNTER,
+            dropped,
+        ),
+        new_family(
+            "edgelink_node_processing_seconds",
+            "Time spent on processing a message",
+            MetricType::HISTOGRAM,
+            latencies,
+        ),
+        new_family("edgelink_node_custom_metric", "Values emitted by the nodes", MetricType::GAUGE, custom),
+        new_family("edgelink_active_flows", "Flows running", MetricType::GAUGE, vec![gauge(&[], active_flows as f64)]),
+        new_family(
+            "edgelink_total_nodes",
+            "Flow nodes loaded",
+            MetricType::GAUGE,
+            vec![gauge(&[], nodes.len() as f64)],
+        ),
+        new_family("edgelink_context_store_keys", "Keys in the known context scopes", MetricType::GAUGE, context_keys),
+    ];
+    families.extend(engine.metrics_registry().gather());
+    // The text encoder rejects the families without any metric, e.g. no node has emitted a custom metric yet
+    families.retain(|x| !x.get_metric().is_empty());
+    families
+}
+
+/// Renders the metrics of the engine in the Prometheus text format.
+pub async fn render(engine: &Engine) -> crate::Result<String> {
+    let mut buf = Vec::new();
+    TextEncoder::new().encode(&gather(engine).await, &mut buf)?;
+    Ok(String::from_utf8(buf)?)
+}
+
+/// Serves `GET /metrics` on the listener until cancelled.
+pub async fn serve(listener: TcpListener, engine: Engine, cancel: CancellationToken) -> crate::Result<()> {
+    log::info!("Serving the metrics on: http://{}/metrics", listener.local_addr()?);
+    loop {
+        let stream = tokio::select! {
+            accepted = listener.accept() => accepted?.0,
+            _ = cancel.cancelled() => break,
+        };
+        let engine = engine.clone();
+        tokio::spawn(async move {
+            let res = match tokio::time::timeout(REQUEST_TIMEOUT, handle_request(stream, &engine)).await {
+                Ok(res) => res,
+                Err(_) => Err(EdgelinkError::Timeout.into()),
+            };
+            if let Err(err) = res {
+                log::warn!("Failed to serve the metrics request: {}", err);
+            }
+        });
+    }
+    Ok(())
+}
+
+async fn handle_request(stream: TcpStream, engine: &Engine) -> crate::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    // Skips the headers
+    let mut header = String::new();
+    while reader.read_line(&mut header).await? > 2 {
+        header.clear();
+    }
+
+    let encoder = TextEncoder::new();
+    let mut parts = request_line.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", encoder.format_type(), render(engine).await?),
+        _ => ("404 Not Found", "text/plain", "Not Found\n".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    let mut stream = reader.into_inner();
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+fn new_family(name: &str, help: &str, field_type: MetricType, metrics: Vec<Metric>) -> MetricFamily {
+    let mut family = MetricFamily::default();
+    family.set_name(name.to_string());
+    family.set_help(help.to_string());
+    family.set_field_type(field_type);
+    family.set_metric(metrics);
+    family
+}
+
+fn new_metric(labels: &[(&str, &str)]) -> Metric {
+    let labels: Vec<LabelPair> = labels
+        .iter()
+        .map(|(name, value)| {
+            let mut label = LabelPair::default();
+            label.set_name(name.to_string());
+            label.set_value(value.to_string());
+            label
+        })
+        .collect();
+    let mut metric = Metric::default();
+    metric.set_label(labels);
+    metric
+}
+
+fn node_labels(node: &dyn FlowNodeBehavior) -> [(&'static str, String); 4] {
+    let flow_id = node.flow().map(|x| x.id().to_string()).unwrap_or_default();
+    [
+        ("flow_id", flow_id),
+        ("node_id", node.id().to_string()),
+        ("node_type", node.type_str().to_string()),
+        ("node_name", node.name().to_string()),
+    ]
+}
+
+/// The constant labels of the metrics registered by the node to `Engine::metrics_registry()`, the metrics of the same
+/// name from different nodes are told apart by them.
+pub fn node_const_labels(node: &dyn FlowNodeBehavior) -> HashMap<String, String> {
+    node_labels(node).into_iter().map(|(k, v)| (k.to_string(), v)).collect()
+}
+
+fn with_node_labels(node: &dyn FlowNodeBehavior) -> Metric {
+    let labels = node_labels(node);
+    let labels: Vec<(&str, &str)> = labels.iter().map(|(k, v)| (*k, v.as_str())).collect();
+    new_metric(&labels)
+}
+
+fn custom_gauge(node: &dyn FlowNodeBehavior, name: &str, value: f64) -> Metric {
+    let labels = node_labels(node);
+    let mut labels: Vec<(&str, &str)> = labels.iter().map(|(k, v)| (*k, v.as_str())).collect();
+    labels.push(("name", name));
+    gauge(&labels, value)
+}
+
+fn counter(node: &dyn FlowNodeBehavior, value: u64) -> Metric {
+    let mut metric = with_node_labels(node);
+    let mut counter = Counter::default();
+    counter.set_value(value as f64);
+    metric.set_counter(counter);
+    metric
+}
+
+fn gauge(labels: &[(&str, &str)], value: f64) -> Metric {
+    let mut metric = new_metric(labels);
+    let mut gauge = Gauge::default();
+    gauge.set_value(value);
+    metric.set_gauge(gauge);
+    metric
+}
+
+fn histogram(node: &dyn FlowNodeBehavior, latencies: &LatencyHistogram) -> Metric {
+    let mut metric = with_node_labels(node);
+    let mut histogram = Histogram::default();
+    histogram.set_sample_count(latencies.count);
+    histogram.set_sample_sum(latencies.sum);
+    let mut cumulative = 0;
+    let buckets: Vec<Bucket> = LATENCY_BUCKETS
+        .iter()
+        .zip(latencies.buckets.iter())
+        .map(|(bound, count)| {
+            cumulative += count;
+            let mut bucket = Bucket::default();
+            bucket.set_upper_bound(*bound);
+            bucket.set_cumulative_count(cumulative);
+            bucket
+        })
+        .collect();
+    histogram.set_bucket(buckets);
+    metric.set_histogram(histogram);
+    metric
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::model::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    /// Finds the value of the sample with the name and containing the label, e.g. `node_id="0000000000000001"`.
+    fn find_sample(text: &str, name: &str, label: &str) -> Option<f64> {
+        text.lines()
+            .filter(|x| !x.starts_with('#'))
+            .find(|x| x.starts_with(&format!("{}{{", name)) && x.contains(label))
+            .and_then(|x| x.rsplit(' ').next())
+            .and_then(|x| x.parse().ok())
+    }
+
+    #[tokio::test]
+    async fn test_metrics_should_count_msgs_and_errors() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "change", "wires": [["2"]],
+                "rules": [{"t": "set", "p": "topic", "pt": "msg", "to": "payload.name", "tot": "msg"}]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"payload": {"name": "a"}}],
+            ["1", {"payload": {"name": "b"}}],
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        engine.run_once_with_inject(2, Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        let text = render(&engine).await.unwrap();
+
+        let node1 = format!("node_id=\"{}\"", ElementId::with_u64(1));
+        let node2 = format!("node_id=\"{}\"", ElementId::with_u64(2));
+        assert_eq!(find_sample(&text, "edgelink_node_messages_received_total", &node1), Some(2.0));
+        assert_eq!(find_sample(&text, "edgelink_node_messages_sent_total", &node1), Some(2.0));
+        assert_eq!(find_sample(&text, "edgelink_node_messages_received_total", &node2), Some(2.0));
+        assert_eq!(find_sample(&text, "edgelink_node_errors_total", &node1), Some(0.0));
+        assert_eq!(find_sample(&text, "edgelink_node_processing_seconds_count", &node1), Some(2.0));
+        assert!(text.contains("edgelink_total_nodes 2"));
+    }
+
+    #[tokio::test]
+    async fn test_metrics_endpoint_should_serve_prometheus_text() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "assert", "expected": "2", "expectedType": "num", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"},
+            {"id": "3", "z": "100", "type": "catch", "wires": [["2"]]}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": 1}]])).unwrap();
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        engine.run_once_with_inject(1, Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let cancel = CancellationToken::new();
+        let server = tokio::spawn(serve(listener, engine.clone(), cancel.clone()));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        tokio::io::AsyncReadExt::read_to_string(&mut stream, &mut response).await.unwrap();
+        cancel.cancel();
+        server.await.unwrap().unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        let node1 = format!("node_id=\"{}\"", ElementId::with_u64(1));
+        assert_eq!(find_sample(&response, "edgelink_node_messages_received_total", &node1), Some(1.0));
+        assert_eq!(find_sample(&response, "edgelink_node_errors_total", &node1), Some(1.0));
+        assert!(response.contains("# TYPE edgelink_node_processing_seconds histogram"));
+    }
+
+    #[tokio::test]
+    async fn test_dropped_msgs_should_be_counted_and_completed() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "junction", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once", "overflow": "drop_new"},
+            {"id": "3", "z": "100", "type": "complete", "scope": ["2"], "wires": [["4"]]},
+            {"id": "4", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let source = engine.find_flow_node_by_id(&ElementId::with_u64(1)).unwrap();
+
+        // The engine is not started, so the channel of the node 2 is full after 32 messages
+        for i in 0..40 {
+            let envelope = Envelope { port: 0, msg: MsgHandle::with_payload(Variant::from(i)) };
+            source.fan_out_one(envelope, CancellationToken::new()).await.unwrap();
+        }
+        let text = render(&engine).await.unwrap();
+        let node2 = format!("node_id=\"{}\"", ElementId::with_u64(2));
+        assert_eq!(find_sample(&text, "edgelink_node_messages_dropped_total", &node2), Some(8.0));
+
+        // The dropped messages are completed on behalf of the node 2
+        let complete = engine.find_flow_node_by_id(&ElementId::with_u64(3)).unwrap();
+        let mut completed = Vec::new();
+        while let Some(msg) = complete.get_node().msg_rx.try_evict_oldest() {
+            completed.push(msg.read().await["payload"].as_i64().unwrap());
+        }
+        assert_eq!(completed, (32..40).collect::<Vec<i64>>());
+    }
+
+    #[tokio::test]
+    async fn test_context_keys_should_be_counted_once_in_ttl() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        engine.context().set_one(None, "a", Some(Variant::from(1)), &[]).await.unwrap();
+        let text = render(&engine).await.unwrap();
+        assert!(text.contains("edgelink_context_store_keys{store=\"memory\"} 1"));
+
+        // The cached count is served until the TTL elapsed
+        engine.context().set_one(None, "b", Some(Variant::from(2)), &[]).await.unwrap();
+        let text = render(&engine).await.unwrap();
+        assert!(text.contains("edgelink_context_store_keys{store=\"memory\"} 1"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stalled_request_should_be_timed_out() {
+        let engine = crate::runtime::engine::build_test_engine(json!([{"id": "100", "type": "tab"}])).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let cancel = CancellationToken::new();
+        let server = tokio::spawn(serve(listener, engine.clone(), cancel.clone()));
+
+        // Never sends the request, the paused clock advances to the timeout while the server is waiting for it
+        let started_at = tokio::time::Instant::now();
+        let mut stalled = TcpStream::connect(addr).await.unwrap();
+        let mut response = String::new();
+        tokio::io::AsyncReadExt::read_to_string(&mut stalled, &mut response).await.unwrap();
+        assert!(response.is_empty());
+        assert!(started_at.elapsed() >= REQUEST_TIMEOUT);
+
+        cancel.cancel();
+        server.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::EdgelinkError;
+
+#[cfg(feature = "metrics")]
+mod export;
+
+#[cfg(feature = "metrics")]
+pub use export::*;
+
+/// The upper bounds in seconds of the buckets of the processing latency histograms
+pub const LATENCY_BUCKETS: [f64; 14] =
+    [0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0];
+
+/// The most custom metrics a node can emit, a node naming the metrics by its data would grow them without bounds
+pub const MAX_CUSTOM_METRICS: usize = 64;
+
+/// The counters of a flow node, updated by the unit of work functions and exported by the `/metrics` endpoint.
+#[derive(Debug, Default)]
+pub struct NodeMetrics {
+    pub received: AtomicU64,
+    pub sent: AtomicU64,
+    pub errors: AtomicU64,
+    /// The non-cumulative counts of the latencies in the buckets of `LATENCY_BUCKETS`
+    latency_buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    latency_count: AtomicU64,
+    latency_sum_micros: AtomicU64,
+    /// The last values of the metrics emitted by the node itself, e.g. by `node.metric()` of the function node
+    custom: std::sync::Mutex<BTreeMap<String, f64>>,
+}
+
+impl NodeMetrics {
+    pub fn inc_received(&self) {
+        self.received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn inc_sent(&self) {
+        self.sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn inc_errors(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records the time spent on processing a message, it never blocks and every latency is counted.
+    pub fn record_latency(&self, latency: Duration) {
+        let seconds = latency.as_secs_f64();
+        if let Some(index) = LATENCY_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.latency_buckets[index].fetch_add(1, Ordering::Relaxed);
+        }
+        self.latency_sum_micros.fetch_add(latency.as_micros() as u64, Ordering::Relaxed);
+        self.latency_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns a snapshot of the recorded latencies.
+    pub fn latency_histogram(&self) -> LatencyHistogram {
+        let mut histogram = LatencyHistogram::default();
+        for (count, bucket) in histogram.buckets.iter_mut().zip(self.latency_buckets.iter()) {
+            *count = bucket.load(Ordering::Relaxed);
+        }
+        histogram.count = self.latency_count.load(Ordering::Relaxed);
+        histogram.sum = self.latency_sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+        histogram
+    }
+
+    /// Sets the value of a custom metric of the node, replacing the last value of the same name.
+    ///
+    /// Fails if the name is new and the node has emitted `MAX_CUSTOM_METRICS` metrics already.
+    pub fn set_custom(&self, name: &str, value: f64) -> crate::Result<()> {
+        let mut custom = self.custom.lock().expect("`custom` lock");
+        if let Some(last) = custom.get_mut(name) {
+            *last = value;
+            return Ok(());
+        }
+        if custom.len() >= MAX_CUSTOM_METRICS {
+            return Err(EdgelinkError::InvalidOperation(format!(
+                "A node can emit {} custom metrics at most",
+                MAX_CUSTOM_METRICS
+            ))
+            .into());
+        }
+        custom.insert(name.to_string(), value);
+        Ok(())
+    }
+
+    /// Returns the custom metrics of the node, ordered by the names.
+    pub fn custom_metrics(&self) -> Vec<(String, f64)> {
+        let custom = self.custom.lock().expect("`custom` lock");
+        custom.iter().map(|(name, value)| (name.clone(), *value)).collect()
+    }
+}
+
+/// The processing latencies of a node, in the buckets of `LATENCY_BUCKETS`.
+#[derive(Debug, Clone, Default)]
+pub struct LatencyHistogram {
+    /// The non-cumulative counts of the buckets, the latencies above the last bound are only counted in `count`
+    pub buckets: [u64; LATENCY_BUCKETS.len()],
+    pub count: u64,
+    /// The sum of the latencies in seconds
+    pub sum: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latency_histogram_should_count_every_latency() {
+        let metrics = NodeMetrics::default();
+        for _ in 0..1000 {
+            metrics.record_latency(Duration::from_millis(2));
+        }
+        let histogram = metrics.latency_histogram();
+        assert_eq!(histogram.count, 1000);
+        assert_eq!(histogram.buckets[LATENCY_BUCKETS.iter().position(|x| *x == 0.0025).unwrap()], 1000);
+        assert!((histogram.sum - 2.0).abs() < 1e-9);
+
+        metrics.record_latency(Duration::from_secs(10));
+        let histogram = metrics.latency_histogram();
+        assert_eq!(histogram.count, 1001);
+        assert_eq!(histogram.buckets.iter().sum::<u64>(), 1000);
+        assert!((histogram.sum - 12.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_custom_metrics_should_be_capped() {
+        let metrics = NodeMetrics::default();
+        for i in 0..MAX_CUSTOM_METRICS {
+            metrics.set_custom(&format!("m{}", i), i as f64).unwrap();
+        }
+        assert!(metrics.set_custom("one_too_many", 1.0).is_err());
+        // The existing metrics can still be updated
+        metrics.set_custom("m0", 42.0).unwrap();
+        assert_eq!(metrics.custom_metrics().len(), MAX_CUSTOM_METRICS);
+        assert!(metrics.custom_metrics().contains(&("m0".to_string(), 42.0)));
+    }
+}
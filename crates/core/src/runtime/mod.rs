@@ -1,13 +1,17 @@
 pub mod context;
+pub mod dedup;
 pub mod engine;
 pub mod env;
 pub mod eval;
 pub mod flow;
 pub mod group;
+pub mod jsonata;
+pub mod metrics;
 pub mod model;
 pub mod nodes;
 pub mod registry;
 pub mod subflow;
+pub mod trace;
 
 #[cfg(feature = "js")]
 pub mod js;
@@ -0,0 +1,214 @@
+use std::time::UNIX_EPOCH;
+
+use serde::Deserialize;
+
+use super::{wellknown, Msg, Variant};
+
+/// The property of the placeholder object of a buffer in the `attachments` format, the placeholder is
+/// `{"type": "Buffer", "attachment": index}` where `index` is the position of the bytes in the attachments.
+pub const ATTACHMENT_PROPERTY: &str = "attachment";
+
+/// The serialization format of the messages handed out of the engine, configured by `runtime.engine.output_format`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum MsgsFormat {
+    /// An array of JSON objects, the buffers are arrays of numbers
+    #[default]
+    #[serde(rename = "json")]
+    Json,
+
+    /// An array of MessagePack maps, the buffers are `bin` values
+    #[serde(rename = "msgpack")]
+    MsgPack,
+
+    /// An array of JSON objects with the buffers moved out to the attachments, see `ATTACHMENT_PROPERTY`
+    #[serde(rename = "attachments")]
+    Attachments,
+}
+
+/// The messages encoded in a `MsgsFormat`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EncodedMsgs {
+    Json(serde_json::Value),
+
+    MsgPack(Vec<u8>),
+
+    Attachments { msgs: serde_json::Value, attachments: Vec<Vec<u8>> },
+}
+
+impl MsgsFormat {
+    /// Encodes the messages with the same properties as `Msg::to_json_value()`.
+    pub fn encode(self, msgs: &[Msg]) -> crate::Result<EncodedMsgs> {
+        match self {
+            MsgsFormat::Json => {
+                Ok(EncodedMsgs::Json(serde_json::Value::Array(msgs.iter().map(|x| x.to_json_value()).collect())))
+            }
+
+            MsgsFormat::MsgPack => {
+                let items = msgs
+                    .iter()
+                    .map(|msg| {
+                        let entries = output_entries(msg)
+                            .into_iter()
+                            .map(|(k, json, orig)| {
+                                let value = orig.map(variant_to_msgpack).unwrap_or_else(|| json_to_msgpack(&json));
+                                (rmpv::Value::from(k), value)
+                            })
+                            .collect();
+                        rmpv::Value::Map(entries)
+                    })
+                    .collect();
+                let mut buf = Vec::new();
+                rmpv::encode::write_value(&mut buf, &rmpv::Value::Array(items))?;
+                Ok(EncodedMsgs::MsgPack(buf))
+            }
+
+            MsgsFormat::Attachments => {
+                let mut attachments = Vec::new();
+                let items = msgs
+                    .iter()
+                    .map(|msg| {
+                        let map = output_entries(msg)
+                            .into_iter()
+                            .map(|(k, json, orig)| match orig {
+                                Some(orig) => (k, variant_to_attached_json(orig, &mut attachments)),
+                                None => (k, json),
+                            })
+                            .collect();
+                        serde_json::Value::Object(map)
+                    })
+                    .collect();
+                Ok(EncodedMsgs::Attachments { msgs: serde_json::Value::Array(items), attachments })
+            }
+        }
+    }
+}
+
+/// The properties of `Msg::to_json_value()` and their original values in the message, the generated or converted
+/// ones like `_msgid` and `_linkSource` have no original values.
+fn output_entries(msg: &Msg) -> Vec<(String, serde_json::Value, Option<&Variant>)> {
+    let body = msg.as_variant_object();
+    match msg.to_json_value() {
+        serde_json::Value::Object(map) => map
+            .into_iter()
+            .map(|(k, json)| {
+                let orig = match k.as_str() {
+                    wellknown::LINK_SOURCE_PROPERTY | wellknown::MSG_ID_PROPERTY => None,
+                    _ => body.get(&k),
+                };
+                (k, json, orig)
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn variant_to_msgpack(value: &Variant) -> rmpv::Value {
+    match value {
+        Variant::Bytes(bytes) => rmpv::Value::Binary(bytes.clone()),
+        Variant::Array(items) => rmpv::Value::Array(items.iter().map(variant_to_msgpack).collect()),
+        Variant::Object(map) => {
+            rmpv::Value::Map(map.iter().map(|(k, v)| (rmpv::Value::from(k.as_str()), variant_to_msgpack(v))).collect())
+        }
+        Variant::Date(t) => rmpv::Value::from(t.duration_since(UNIX_EPOCH).map(|x| x.as_millis() as u64).unwrap_or(0)),
+        other => json_to_msgpack(&serde_json::Value::from(other)),
+    }
+}
+
+fn json_to_msgpack(value: &serde_json::Value) -> rmpv::Value {
+    match value {
+        serde_json::Value::Null => rmpv::Value::Nil,
+        serde_json::Value::Bool(b) => rmpv::Value::Boolean(*b),
+        serde_json::Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                rmpv::Value::from(i)
+            } else if let Some(u) = n.as_u64() {
+                rmpv::Value::from(u)
+            } else {
+                rmpv::Value::from(n.as_f64().unwrap_or(f64::NAN))
+            }
+        }
+        serde_json::Value::String(s) => rmpv::Value::from(s.as_str()),
+        serde_json::Value::Array(items) => rmpv::Value::Array(items.iter().map(json_to_msgpack).collect()),
+        serde_json::Value::Object(map) => {
+            rmpv::Value::Map(map.iter().map(|(k, v)| (rmpv::Value::from(k.as_str()), json_to_msgpack(v))).collect())
+        }
+    }
+}
+
+fn variant_to_attached_json(value: &Variant, attachments: &mut Vec<Vec<u8>>) -> serde_json::Value {
+    match value {
+        Variant::Bytes(bytes) => {
+            attachments.push(bytes.clone());
+            serde_json::json!({"type": "Buffer", ATTACHMENT_PROPERTY: attachments.len() - 1})
+        }
+        Variant::Array(items) => {
+            serde_json::Value::Array(items.iter().map(|x| variant_to_attached_json(x, attachments)).collect())
+        }
+        Variant::Object(map) => serde_json::Value::Object(
+            map.iter().map(|(k, v)| (k.clone(), variant_to_attached_json(v, attachments))).collect(),
+        ),
+        other => serde_json::Value::from(other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn binary_msg() -> Msg {
+        let mut msg = Msg::default();
+        let body = msg.as_variant_object_mut();
+        body.insert("payload".to_string(), Variant::Bytes(vec![0, 1, 0xfe, 0xff]));
+        body.insert("topic".to_string(), Variant::from("t"));
+        body.insert(
+            "nested".to_string(),
+            Variant::Array(vec![Variant::from(-1), Variant::from(0.5), Variant::Bytes(vec![7]), Variant::Null]),
+        );
+        msg.set_id(Msg::generate_id());
+        msg
+    }
+
+    #[test]
+    fn test_msgpack_should_keep_buffers() {
+        let msg = binary_msg();
+        let encoded = match MsgsFormat::MsgPack.encode(std::slice::from_ref(&msg)).unwrap() {
+            EncodedMsgs::MsgPack(buf) => buf,
+            other => panic!("Unexpected: {:?}", other),
+        };
+        let decoded = rmpv::decode::read_value(&mut encoded.as_slice()).unwrap();
+        let decoded = &decoded.as_array().unwrap()[0];
+        let get = |key: &str| decoded.as_map().unwrap().iter().find(|x| x.0.as_str() == Some(key)).map(|x| &x.1);
+
+        assert_eq!(get("payload"), Some(&rmpv::Value::Binary(vec![0, 1, 0xfe, 0xff])));
+        assert_eq!(get("topic"), Some(&rmpv::Value::from("t")));
+        let expected_nested = rmpv::Value::Array(vec![
+            rmpv::Value::from(-1),
+            rmpv::Value::from(0.5),
+            rmpv::Value::Binary(vec![7]),
+            rmpv::Value::Nil,
+        ]);
+        assert_eq!(get("nested"), Some(&expected_nested));
+        let msg_id = msg.id().unwrap().to_string();
+        assert_eq!(get(wellknown::MSG_ID_PROPERTY).and_then(|x| x.as_str()), Some(msg_id.as_str()));
+    }
+
+    #[test]
+    fn test_attachments_should_replace_buffers() {
+        let encoded = MsgsFormat::Attachments.encode(&[binary_msg(), binary_msg()]).unwrap();
+        let (msgs, attachments) = match encoded {
+            EncodedMsgs::Attachments { msgs, attachments } => (msgs, attachments),
+            other => panic!("Unexpected: {:?}", other),
+        };
+        assert_eq!(attachments.len(), 4);
+        let attached = |placeholder: &serde_json::Value| {
+            assert_eq!(placeholder["type"], json!("Buffer"));
+            attachments[placeholder[ATTACHMENT_PROPERTY].as_u64().unwrap() as usize].clone()
+        };
+        assert_eq!(attached(&msgs[0]["payload"]), vec![0, 1, 0xfe, 0xff]);
+        assert_eq!(attached(&msgs[0]["nested"][2]), vec![7]);
+        assert_eq!(attached(&msgs[1]["payload"]), vec![0, 1, 0xfe, 0xff]);
+        assert_ne!(msgs[0]["payload"], msgs[1]["payload"]);
+        assert_eq!(msgs[1]["topic"], json!("t"));
+    }
+}
@@ -17,7 +17,7 @@ use crate::utils::constants::{ENV_STR, FLOW_STR, ID_STR, NAME_STR, SUB_FLOW_TYPE
 use super::*;
 
 pub fn load_flows_json_value(root_jv: JsonValue) -> crate::Result<ResolvedFlows> {
-    let mut preprocessed = preprocess_subflows(root_jv)?;
+    let (mut preprocessed, subflow_copies) = preprocess_subflows(root_jv)?;
     preprocess_merge_subflow_env(&mut preprocessed)?;
     let all_values = preprocessed
         .as_array()
@@ -26,11 +26,12 @@ pub fn load_flows_json_value(root_jv: JsonValue) -> crate::Result<ResolvedFlows>
     let mut flows = HashMap::new();
     let mut groups = HashMap::new();
     let mut flow_nodes = HashMap::new();
-    let mut global_nodes = Vec::new();
+    let mut global_nodes = HashMap::new();
 
     let mut flow_topo_sort = TopologicalSorter::<ElementId>::new();
     let mut group_topo_sort = TopologicalSorter::<ElementId>::new();
     let mut node_topo_sort = TopologicalSorter::<ElementId>::new();
+    let mut global_topo_sort = TopologicalSorter::<ElementId>::new();
 
     for jobject in all_values.iter() {
         if let Some(obj) = jobject.as_object() {
@@ -89,8 +90,8 @@ pub fn load_flows_json_value(root_jv: JsonValue) -> crate::Result<ResolvedFlows>
                             flow_nodes.insert(ele_id, jobject.clone());
                         }
                         None => {
-                            let global_config: RedGlobalNodeConfig = serde_json::from_value(jobject.clone())?;
-                            global_nodes.push(global_config);
+                            global_topo_sort.add_vertex(ele_id);
+                            global_nodes.insert(ele_id, jobject.clone());
                         }
                     },
                 }
@@ -132,10 +133,28 @@ pub fn load_flows_json_value(root_jv: JsonValue) -> crate::Result<ResolvedFlows>
         }
     }
 
+    // The global nodes referenced by other global nodes must be built first, e.g. the TLS config of a broker
+    for (global_id, global_node) in global_nodes.iter() {
+        if let Some(obj) = global_node.as_object() {
+            let deps = obj.get_global_node_dependencies().into_iter().filter(|x| global_nodes.contains_key(x));
+            global_topo_sort.add_deps(*global_id, deps);
+        }
+    }
+    let mut global_configs = Vec::with_capacity(global_nodes.len());
+    for (ordering, global_id) in global_topo_sort.dependency_sort().iter().enumerate() {
+        let global_node = global_nodes
+            .remove(global_id)
+            .ok_or(EdgelinkError::BadFlowsJson(format!("Cannot find the global node id '{}'", global_id)))?;
+        let mut global_config: RedGlobalNodeConfig = serde_json::from_value(global_node)?;
+        global_config.ordering = ordering;
+        global_configs.push(global_config);
+    }
+
     let mut flow_configs = Vec::with_capacity(flows.len());
     for (flow_ordering, flow) in sorted_flows.into_iter().enumerate() {
         let mut flow_config: RedFlowConfig = serde_json::from_value(flow)?;
         flow_config.ordering = flow_ordering;
+        flow_config.original_id = subflow_copies.get(&flow_config.id).copied();
 
         flow_config.subflow_node_id = if flow_config.type_name == SUB_FLOW_TYPE {
             let key_type = format!("{SUB_FLOW_TYPE}:{}", flow_config.id);
@@ -161,10 +180,14 @@ pub fn load_flows_json_value(root_jv: JsonValue) -> crate::Result<ResolvedFlows>
         flow_configs.push(flow_config);
     }
 
-    Ok(ResolvedFlows { flows: flow_configs, global_nodes })
+    Ok(ResolvedFlows { flows: flow_configs, global_nodes: global_configs })
 }
 
-fn preprocess_subflows(jv_root: JsonValue) -> crate::Result<JsonValue> {
+/// Copies the subflow definition and its children for every instance with new ids, returns the preprocessed elements
+/// and the map from the id of every copy to the id of the original subflow.
+///
+/// The id of a child in the copy is the XOR of the copy id and its original id, see `dump_flows_json_value()`.
+fn preprocess_subflows(jv_root: JsonValue) -> crate::Result<(JsonValue, HashMap<ElementId, ElementId>)> {
     let elements = jv_root.as_array().unwrap();
     let mut elements_to_delete = HashSet::new();
 
@@ -209,6 +232,7 @@ fn preprocess_subflows(jv_root: JsonValue) -> crate::Result<JsonValue> {
 
     let mut new_elements = Vec::new();
     let mut id_map: HashMap<String, String> = HashMap::new();
+    let mut subflow_copies = HashMap::new();
 
     for pack in subflow_packs.iter() {
         let subflow_new_id = ElementId::new();
@@ -218,6 +242,9 @@ fn preprocess_subflows(jv_root: JsonValue) -> crate::Result<JsonValue> {
             let mut new_subflow = pack.subflow.clone();
             new_subflow[ID_STR] = JsonValue::String(subflow_new_id.to_string());
             id_map.insert(pack.subflow_id.to_string(),subflow_new_id.to_string());
+            if let Some(original_id) = parse_red_id_str(pack.subflow_id) {
+                subflow_copies.insert(subflow_new_id, original_id);
+            }
             new_elements.push(new_subflow);
         }
 
@@ -239,47 +266,43 @@ fn preprocess_subflows(jv_root: JsonValue) -> crate::Result<JsonValue> {
 
     // Remap all known properties of the new elements
     for node in new_elements.iter_mut() {
-        let node = node.as_object_mut().unwrap();
+        remap_element_ids(node.as_object_mut().unwrap(), &id_map);
+    }
 
-        if let Some(JsonValue::String(pvalue)) = node.get_mut("z") {
-            if let Some(new_id) = id_map.get(pvalue.as_str()) {
-                *pvalue = new_id.to_string();
-            }
-        }
+    new_elements.extend(elements.iter().filter(|x| !elements_to_delete.contains(x)).cloned());
 
-        if let Some(JsonValue::String(pvalue)) = node.get_mut("g") {
-            if let Some(new_id) = id_map.get(pvalue.as_str()) {
-                *pvalue = new_id.to_string();
-            }
+    Ok((JsonValue::Array(new_elements), subflow_copies))
+}
+
+/// Replaces the ids referenced by the element, i.e. `z`, `g`, `wires`, `scope`, `links`, the `in`/`out` ports of
+/// subflows and the id in the `type` of subflow instances. The `id` of the element itself is left untouched.
+pub(super) fn remap_element_ids(node: &mut JsonMap<String, JsonValue>, id_map: &HashMap<String, String>) {
+    if let Some(JsonValue::String(pvalue)) = node.get_mut("z") {
+        if let Some(new_id) = id_map.get(pvalue.as_str()) {
+            *pvalue = new_id.to_string();
         }
+    }
 
-        // Replace the nested flow instance `type` property
-        if let Some(JsonValue::String(pvalue)) = node.get_mut(TYPE_STR) {
-            if let Some(("subflow", old_id)) = pvalue.split_once(':') {
-                if let Some(new_id) = id_map.get(old_id) {
-                    *pvalue = format!("{SUB_FLOW_TYPE}:{}", new_id);
-                }
-            }
+    if let Some(JsonValue::String(pvalue)) = node.get_mut("g") {
+        if let Some(new_id) = id_map.get(pvalue.as_str()) {
+            *pvalue = new_id.to_string();
         }
+    }
 
-        // Node with `wires` property
-        if let Some(wires) = node.get_mut("wires").and_then(|x| x.as_array_mut()) {
-            for wire in wires {
-                let wire = wire.as_array_mut().unwrap();
-                for id in wire {
-                    if let JsonValue::String(pvalue) = id {
-                        if let Some(new_id) = id_map.get(pvalue.as_str()) {
-                            *pvalue = new_id.to_string();
-                        }
-                    }
-                }
+    // Replace the nested flow instance `type` property
+    if let Some(JsonValue::String(pvalue)) = node.get_mut(TYPE_STR) {
+        if let Some(("subflow", old_id)) = pvalue.split_once(':') {
+            if let Some(new_id) = id_map.get(old_id) {
+                *pvalue = format!("{SUB_FLOW_TYPE}:{}", new_id);
             }
         }
+    }
 
-        // Node with `scope` property
-        // TODO CHECK TYPE: complete/catch/status
-        if let Some(scope) = node.get_mut("scope").and_then(|x| x.as_array_mut()) {
-            for id in scope {
+    // Node with `wires` property
+    if let Some(wires) = node.get_mut("wires").and_then(|x| x.as_array_mut()) {
+        for wire in wires {
+            let wire = wire.as_array_mut().unwrap();
+            for id in wire {
                 if let JsonValue::String(pvalue) = id {
                     if let Some(new_id) = id_map.get(pvalue.as_str()) {
                         *pvalue = new_id.to_string();
@@ -287,48 +310,56 @@ fn preprocess_subflows(jv_root: JsonValue) -> crate::Result<JsonValue> {
                 }
             }
         }
+    }
 
-        // Node with `links` property
-        if let Some(links) = node.get_mut("links").and_then(|x| x.as_array_mut()) {
-            for id in links {
-                if let JsonValue::String(pvalue) = id {
-                    if let Some(new_id) = id_map.get(pvalue.as_str()) {
-                        *pvalue = new_id.to_string();
-                    }
+    // Node with `scope` property
+    // TODO CHECK TYPE: complete/catch/status
+    if let Some(scope) = node.get_mut("scope").and_then(|x| x.as_array_mut()) {
+        for id in scope {
+            if let JsonValue::String(pvalue) = id {
+                if let Some(new_id) = id_map.get(pvalue.as_str()) {
+                    *pvalue = new_id.to_string();
                 }
             }
         }
+    }
 
-        // Replace the `in` property
-        if let Some(JsonValue::Array(in_props)) = node.get_mut("in") {
-            for in_item in in_props.iter_mut() {
-                for wires_item in in_item["wires"].as_array_mut().unwrap().iter_mut() {
-                    if let Some(JsonValue::String(pvalue)) = wires_item.get_mut(ID_STR) {
-                        if let Some(new_id) = id_map.get(pvalue.as_str()) {
-                            *pvalue = new_id.to_string();
-                        }
-                    }
+    // Node with `links` property
+    if let Some(links) = node.get_mut("links").and_then(|x| x.as_array_mut()) {
+        for id in links {
+            if let JsonValue::String(pvalue) = id {
+                if let Some(new_id) = id_map.get(pvalue.as_str()) {
+                    *pvalue = new_id.to_string();
                 }
             }
         }
+    }
 
-        // Replace the `out` property
-        if let Some(JsonValue::Array(out_props)) = node.get_mut("out") {
-            for out_item in out_props.iter_mut() {
-                for wires_item in out_item["wires"].as_array_mut().unwrap().iter_mut() {
-                    if let Some(JsonValue::String(pvalue)) = wires_item.get_mut(ID_STR) {
-                        if let Some(new_id) = id_map.get(pvalue.as_str()) {
-                            *pvalue = new_id.to_string();
-                        }
+    // Replace the `in` property
+    if let Some(JsonValue::Array(in_props)) = node.get_mut("in") {
+        for in_item in in_props.iter_mut() {
+            for wires_item in in_item["wires"].as_array_mut().unwrap().iter_mut() {
+                if let Some(JsonValue::String(pvalue)) = wires_item.get_mut(ID_STR) {
+                    if let Some(new_id) = id_map.get(pvalue.as_str()) {
+                        *pvalue = new_id.to_string();
                     }
                 }
             }
         }
     }
 
-    new_elements.extend(elements.iter().filter(|x| !elements_to_delete.contains(x)).cloned());
-
-    Ok(JsonValue::Array(new_elements))
+    // Replace the `out` property
+    if let Some(JsonValue::Array(out_props)) = node.get_mut("out") {
+        for out_item in out_props.iter_mut() {
+            for wires_item in out_item["wires"].as_array_mut().unwrap().iter_mut() {
+                if let Some(JsonValue::String(pvalue)) = wires_item.get_mut(ID_STR) {
+                    if let Some(new_id) = id_map.get(pvalue.as_str()) {
+                        *pvalue = new_id.to_string();
+                    }
+                }
+            }
+        }
+    }
 }
 
 fn generate_new_xored_id_value(subflow_id: ElementId, old_id: &str) -> crate::Result<JsonValue> {
@@ -457,6 +488,21 @@ impl RedFlowNodeJsonObject for JsonMap<String, JsonValue> {
     }
 }
 
+pub trait RedGlobalNodeJsonObject {
+    fn get_global_node_dependencies(&self) -> HashSet<ElementId>;
+}
+
+impl RedGlobalNodeJsonObject for JsonMap<String, JsonValue> {
+    /// Collects all top-level properties that look like element IDs, the caller should keep the global ones only.
+    fn get_global_node_dependencies(&self) -> HashSet<ElementId> {
+        self.iter()
+            .filter(|(k, _)| k.as_str() != ID_STR && k.as_str() != TYPE_STR)
+            .filter_map(|(_, v)| v.as_str())
+            .filter_map(parse_red_id_str)
+            .collect()
+    }
+}
+
 pub fn deser_red_id<'de, D>(deserializer: D) -> Result<ElementId, D::Error>
 where
     D: Deserializer<'de>,
@@ -572,6 +618,81 @@ where
     }
 }
 
+pub fn str_to_option_usize<'de, D>(deserializer: D) -> Result<Option<usize>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct UsizeVisitor;
+
+    impl<'de> de::Visitor<'de> for UsizeVisitor {
+        type Value = Option<usize>;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("an unsigned integer, a string containing an unsigned integer, or an empty string")
+        }
+
+        fn visit_u64<E>(self, value: u64) -> Result<Option<usize>, E>
+        where
+            E: de::Error,
+        {
+            Ok(Some(value as usize))
+        }
+
+        fn visit_i64<E>(self, value: i64) -> Result<Option<usize>, E>
+        where
+            E: de::Error,
+        {
+            usize::try_from(value).map(Some).map_err(de::Error::custom)
+        }
+
+        fn visit_str<E>(self, value: &str) -> Result<Option<usize>, E>
+        where
+            E: de::Error,
+        {
+            if value.trim().is_empty() {
+                Ok(None)
+            } else {
+                value.trim().parse::<usize>().map(Some).map_err(de::Error::custom)
+            }
+        }
+
+        fn visit_string<E>(self, value: String) -> Result<Option<usize>, E>
+        where
+            E: de::Error,
+        {
+            self.visit_str(&value)
+        }
+
+        fn visit_none<E>(self) -> Result<Option<usize>, E>
+        where
+            E: de::Error,
+        {
+            Ok(None)
+        }
+
+        fn visit_unit<E>(self) -> Result<Option<usize>, E>
+        where
+            E: de::Error,
+        {
+            Ok(None)
+        }
+    }
+
+    deserializer.deserialize_any(UsizeVisitor)
+}
+
+/// The `concurrency` of a node, an empty string means the default of 1.
+pub fn deser_concurrency<'de, D>(deserializer: D) -> Result<usize, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match str_to_option_usize(deserializer)? {
+        Some(0) => Err(de::Error::custom("The `concurrency` must be positive")),
+        Some(n) => Ok(n),
+        None => Ok(1),
+    }
+}
+
 pub fn str_to_option_f64<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
 where
     D: Deserializer<'de>,
@@ -684,6 +805,32 @@ where
     deserializer.deserialize_any(F64Visitor)
 }
 
+/// Node-RED saves some flags as strings, i.e. `"true"` or `"false"`.
+pub fn deser_bool_or_str<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match JsonValue::deserialize(deserializer)? {
+        JsonValue::Bool(b) => Ok(b),
+        JsonValue::String(s) => s.trim().parse::<bool>().map_err(de::Error::custom),
+        other => Err(de::Error::custom(format!("Expected a boolean, got: {}", other))),
+    }
+}
+
+/// The JSON Schema of a node, either a JSON object or a JSON string, an empty string means no schema.
+pub fn deser_json_schema<'de, D>(deserializer: D) -> Result<Option<JsonValue>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match JsonValue::deserialize(deserializer)? {
+        JsonValue::Null => Ok(None),
+        JsonValue::String(s) if s.trim().is_empty() => Ok(None),
+        JsonValue::String(s) => serde_json::from_str(&s).map(Some).map_err(de::Error::custom),
+        schema @ JsonValue::Object(_) => Ok(Some(schema)),
+        other => Err(de::Error::custom(format!("Bad JSON Schema: {}", other))),
+    }
+}
+
 pub fn str_to_option_u16<'de, D>(deserializer: D) -> Result<Option<u16>, D::Error>
 where
     D: Deserializer<'de>,
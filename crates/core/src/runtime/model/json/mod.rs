@@ -6,6 +6,10 @@ use serde_json::Value as JsonValue;
 pub mod deser;
 pub mod helpers;
 mod npdeser;
+pub mod ser;
+mod typed_config;
+
+pub use typed_config::*;
 
 #[derive(serde::Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub struct RedPortConfig {
@@ -80,6 +84,10 @@ pub struct RedFlowConfig {
     #[serde(skip)]
     pub subflow_node_id: Option<ElementId>,
 
+    /// The id of the subflow definition in `flows.json`, the subflow is copied with a new id for every instance
+    #[serde(skip)]
+    pub original_id: Option<ElementId>,
+
     #[serde(skip, default)]
     pub ordering: usize,
 
@@ -113,6 +121,18 @@ pub struct RedFlowNodeConfig {
     #[serde(default, deserialize_with = "deser::deserialize_wires")]
     pub wires: Vec<RedPortConfig>,
 
+    /// EdgeLink only, serializes the downstream processing of the messages sent by the node
+    #[serde(default)]
+    pub ordered: bool,
+
+    /// EdgeLink only, what the wires sending messages to the node do when its channel is full
+    #[serde(default)]
+    pub overflow: OverflowPolicy,
+
+    /// EdgeLink only, how many messages the node may process at the same time, for the nodes that support it
+    #[serde(default = "default_concurrency", deserialize_with = "deser::deser_concurrency")]
+    pub concurrency: usize,
+
     #[serde(skip, default)]
     pub ordering: usize,
 
@@ -120,6 +140,10 @@ pub struct RedFlowNodeConfig {
     pub rest: JsonValue,
 }
 
+fn default_concurrency() -> usize {
+    1
+}
+
 #[derive(Debug, Clone, serde::Deserialize)]
 pub struct RedGlobalNodeConfig {
     #[serde(deserialize_with = "deser::deser_red_id")]
@@ -159,6 +183,9 @@ pub struct RedSubflowPort {
     // y: i32,
     #[serde(default)]
     pub wires: Vec<RedSubflowPortWire>,
+
+    #[serde(flatten)]
+    pub rest: JsonValue,
 }
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,155 @@
+use std::collections::{HashMap, HashSet};
+
+use serde_json::Map as JsonMap;
+use serde_json::Value as JsonValue;
+
+use crate::utils::constants::{ID_STR, NAME_STR, SUB_FLOW_TYPE, TYPE_STR};
+
+use super::deser::remap_element_ids;
+use super::*;
+use crate::runtime::model::ElementId;
+
+/// Regenerates the Node-RED flows array from the loaded configurations, `load_flows_json_value()` of the result gives
+/// the equivalent configurations.
+///
+/// The loader copies a subflow and its children with new ids for every instance, the ids of the copies are
+/// de-mangled back to the original ones: the id of the subflow comes from `RedFlowConfig::original_id`, and the id of
+/// a child is the XOR of its id and the id of the copy. The copies of the same subflow are exported only once.
+///
+/// Note that the ids are exported in the 16-digit hex form, the comments are not exported since they are dropped by
+/// the loader, and the `env` of a subflow instance contains the merged defaults of its subflow.
+pub fn dump_flows_json_value(flows: &ResolvedFlows) -> JsonValue {
+    let mut id_map: HashMap<String, String> = HashMap::new();
+    for flow in flows.flows.iter() {
+        if let Some(original_id) = flow.original_id {
+            id_map.insert(flow.id.to_string(), original_id.to_string());
+            for node in flow.nodes.iter() {
+                id_map.insert(node.id.to_string(), (node.id ^ flow.id).to_string());
+            }
+            for group in flow.groups.iter() {
+                id_map.insert(group.id.to_string(), (group.id ^ flow.id).to_string());
+            }
+        }
+    }
+
+    let mut elements = Vec::new();
+    for flow in flows.flows.iter() {
+        elements.push(dump_flow(flow));
+        elements.extend(flow.groups.iter().map(dump_group));
+        elements.extend(flow.nodes.iter().map(dump_flow_node));
+    }
+    elements.extend(flows.global_nodes.iter().map(dump_global_node));
+
+    let mut exported_ids = HashSet::new();
+    let mut result = Vec::with_capacity(elements.len());
+    for mut element in elements.into_iter() {
+        if let Some(JsonValue::String(id)) = element.get_mut(ID_STR) {
+            if let Some(original_id) = id_map.get(id.as_str()) {
+                *id = original_id.clone();
+            }
+        }
+        remap_element_ids(&mut element, &id_map);
+        if let Some(JsonValue::Array(nodes)) = element.get_mut("nodes") {
+            for id in nodes.iter_mut() {
+                if let Some(original_id) = id.as_str().and_then(|x| id_map.get(x)) {
+                    *id = JsonValue::String(original_id.clone());
+                }
+            }
+        }
+        let id = element.get(ID_STR).and_then(|x| x.as_str()).unwrap_or_default().to_string();
+        if exported_ids.insert(id) {
+            result.push(JsonValue::Object(element));
+        }
+    }
+    JsonValue::Array(result)
+}
+
+fn new_element(rest: &JsonValue, id: ElementId) -> JsonMap<String, JsonValue> {
+    let mut obj = rest.as_object().cloned().unwrap_or_default();
+    obj.insert(ID_STR.to_string(), JsonValue::String(id.to_string()));
+    obj
+}
+
+fn dump_flow(flow: &RedFlowConfig) -> JsonMap<String, JsonValue> {
+    let mut obj = new_element(&flow.rest, flow.id);
+    obj.insert(TYPE_STR.to_string(), JsonValue::String(flow.type_name.clone()));
+    obj.insert("info".to_string(), JsonValue::String(flow.info.clone()));
+    if flow.type_name == SUB_FLOW_TYPE {
+        // The subflow has a `name` instead of the `label`, which is kept in the `rest`
+        if !flow.label.is_empty() {
+            obj.insert("label".to_string(), JsonValue::String(flow.label.clone()));
+        }
+        obj.insert("in".to_string(), JsonValue::Array(flow.in_ports.iter().map(dump_subflow_port).collect()));
+        obj.insert("out".to_string(), JsonValue::Array(flow.out_ports.iter().map(dump_subflow_port).collect()));
+    } else {
+        obj.insert("label".to_string(), JsonValue::String(flow.label.clone()));
+        obj.insert("disabled".to_string(), JsonValue::Bool(flow.disabled));
+    }
+    obj
+}
+
+fn dump_subflow_port(port: &RedSubflowPort) -> JsonValue {
+    let mut obj = port.rest.as_object().cloned().unwrap_or_default();
+    let wires = port.wires.iter().map(|x| serde_json::json!({ "id": x.id.to_string(), "port": x.port })).collect();
+    obj.insert("wires".to_string(), JsonValue::Array(wires));
+    JsonValue::Object(obj)
+}
+
+fn dump_group(group: &RedGroupConfig) -> JsonMap<String, JsonValue> {
+    let mut obj = new_element(&group.rest, group.id);
+    obj.insert(NAME_STR.to_string(), JsonValue::String(group.name.clone()));
+    obj.insert("z".to_string(), JsonValue::String(group.z.to_string()));
+    if let Some(g) = group.g {
+        obj.insert("g".to_string(), JsonValue::String(g.to_string()));
+    }
+    if group.disabled {
+        obj.insert("disabled".to_string(), JsonValue::Bool(true));
+    }
+    obj.insert("nodes".to_string(), group.nodes.iter().map(|x| JsonValue::String(x.to_string())).collect());
+    obj
+}
+
+fn dump_flow_node(node: &RedFlowNodeConfig) -> JsonMap<String, JsonValue> {
+    let mut obj = new_element(&node.rest, node.id);
+    obj.insert(TYPE_STR.to_string(), JsonValue::String(node.type_name.clone()));
+    obj.insert(NAME_STR.to_string(), JsonValue::String(node.name.clone()));
+    obj.insert("z".to_string(), JsonValue::String(node.z.to_string()));
+    if let Some(g) = node.g {
+        obj.insert("g".to_string(), JsonValue::String(g.to_string()));
+    }
+    if let Some(active) = node.active {
+        obj.insert("active".to_string(), JsonValue::Bool(active));
+    }
+    if node.disabled {
+        obj.insert("d".to_string(), JsonValue::Bool(true));
+    }
+    if node.ordered {
+        obj.insert("ordered".to_string(), JsonValue::Bool(true));
+    }
+    if node.overflow != OverflowPolicy::Block {
+        obj.insert("overflow".to_string(), JsonValue::String(node.overflow.as_str().to_string()));
+    }
+    if node.concurrency != 1 {
+        obj.insert("concurrency".to_string(), JsonValue::from(node.concurrency));
+    }
+    let wires = node
+        .wires
+        .iter()
+        .map(|port| port.node_ids.iter().map(|x| JsonValue::String(x.to_string())).collect())
+        .collect();
+    obj.insert("wires".to_string(), JsonValue::Array(wires));
+    obj
+}
+
+fn dump_global_node(node: &RedGlobalNodeConfig) -> JsonMap<String, JsonValue> {
+    let mut obj = new_element(&node.rest, node.id);
+    obj.insert(TYPE_STR.to_string(), JsonValue::String(node.type_name.clone()));
+    obj.insert(NAME_STR.to_string(), JsonValue::String(node.name.clone()));
+    if let Some(active) = node.active {
+        obj.insert("active".to_string(), JsonValue::Bool(active));
+    }
+    if node.disabled {
+        obj.insert("disabled".to_string(), JsonValue::Bool(true));
+    }
+    obj
+}
@@ -0,0 +1,211 @@
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{Map as JsonMap, Number as JsonNumber, Value as JsonValue};
+
+use crate::runtime::model::*;
+use crate::EdgelinkError;
+
+/// The config of a node deserialized from the `rest` of its JSON, usually implemented by `#[derive(RedNodeConfig)]`.
+///
+/// The constant typed properties, e.g. a `payload` of `"42"` with the `payloadType` of `num`, are coerced to the JSON
+/// values of their types before deserializing, so the fields can be declared as the `Variant` or the Rust types.
+/// The properties of other types like `msg` or `flow` are left as they are, they can only be evaluated at runtime.
+pub trait RedNodeConfig: DeserializeOwned {
+    /// The name of the config type in the error messages
+    const NAME: &'static str;
+
+    /// The typed properties as the pairs of the property and its type property, e.g. `("payload", "payloadType")`
+    const TYPED_PROPERTIES: &'static [(&'static str, &'static str)];
+
+    fn from_rest(rest: &JsonValue) -> crate::Result<Self> {
+        let mut rest = rest.clone();
+        if let Some(props) = rest.as_object_mut() {
+            for (prop, type_prop) in Self::TYPED_PROPERTIES {
+                coerce_typed_property(props, prop, type_prop)
+                    .map_err(|e| EdgelinkError::BadFlowsJson(format!("Bad config of `{}`: {}", Self::NAME, e)))?;
+            }
+        }
+        serde_json::from_value(rest)
+            .map_err(|e| EdgelinkError::BadFlowsJson(format!("Bad config of `{}`: {}", Self::NAME, e)).into())
+    }
+}
+
+/// Coerces the value of the property to its type in place, the property without a type is a `str` in Node-RED.
+pub fn coerce_typed_property(
+    props: &mut JsonMap<String, JsonValue>,
+    prop: &str,
+    type_prop: &str,
+) -> Result<(), String> {
+    let (type_, type_str) = match props.get(type_prop) {
+        None | Some(JsonValue::Null) => return Ok(()),
+        Some(JsonValue::String(s)) if s.is_empty() => return Ok(()),
+        Some(jv) => match RedPropertyType::deserialize(jv) {
+            Ok(type_) => (type_, jv.as_str().unwrap_or_default().to_string()),
+            Err(_) => return Err(format!("unknown type {} of the property '{}' in '{}'", jv, prop, type_prop)),
+        },
+    };
+    let value = match props.get_mut(prop) {
+        Some(value) => value,
+        None => return Ok(()),
+    };
+    let mismatch = |value: &JsonValue, expected: &str| {
+        format!("the property '{}' of the type '{}' must be {}, got: {}", prop, type_str, expected, value)
+    };
+
+    let coerced = match (type_, &*value) {
+        (RedPropertyType::Str, JsonValue::String(_)) => return Ok(()),
+        (RedPropertyType::Str, JsonValue::Number(_) | JsonValue::Bool(_)) => JsonValue::String(value.to_string()),
+
+        (RedPropertyType::Num, JsonValue::Number(_)) => return Ok(()),
+        (RedPropertyType::Num, JsonValue::String(s)) => match parse_number(s) {
+            Some(num) => JsonValue::Number(num),
+            None => return Err(mismatch(value, "a number")),
+        },
+
+        (RedPropertyType::Bool, JsonValue::Bool(_)) => return Ok(()),
+        (RedPropertyType::Bool, JsonValue::String(s)) => match s.trim() {
+            "true" => JsonValue::Bool(true),
+            "false" => JsonValue::Bool(false),
+            _ => return Err(mismatch(value, "'true' or 'false'")),
+        },
+
+        (RedPropertyType::Json, JsonValue::String(s)) => {
+            serde_json::from_str(s).map_err(|e| format!("{} ({})", mismatch(value, "a JSON text"), e))?
+        }
+
+        (RedPropertyType::Bin, JsonValue::String(s)) => match serde_json::from_str::<Vec<u8>>(s) {
+            Ok(bytes) => JsonValue::from(bytes),
+            Err(_) => return Err(mismatch(value, "a JSON array of bytes")),
+        },
+        (RedPropertyType::Bin, JsonValue::Array(_)) => match Vec::<u8>::deserialize(&*value) {
+            Ok(_) => return Ok(()),
+            Err(_) => return Err(mismatch(value, "an array of bytes")),
+        },
+
+        (RedPropertyType::Re, JsonValue::String(s)) => match Regex::new(s) {
+            Ok(_) => return Ok(()),
+            Err(e) => return Err(format!("{} ({})", mismatch(value, "a regular expression"), e)),
+        },
+
+        (RedPropertyType::Str | RedPropertyType::Num | RedPropertyType::Bool | RedPropertyType::Re, _) => {
+            return Err(mismatch(value, "a string"))
+        }
+
+        // Evaluated at runtime, or already a JSON value
+        _ => return Ok(()),
+    };
+    *value = coerced;
+    Ok(())
+}
+
+/// Converts the coerced value of a constant typed property to its `Variant`, e.g. the array of a `bin` to the bytes.
+pub fn typed_constant(value: Variant, type_: RedPropertyType) -> crate::Result<Variant> {
+    match (type_, value) {
+        (RedPropertyType::Bin, Variant::Array(items)) => Variant::bytes_from_vec(&items),
+        (_, value) => Ok(value),
+    }
+}
+
+/// Parses the number like `Number()` of JS, the integers are kept as integers.
+fn parse_number(s: &str) -> Option<JsonNumber> {
+    let s = s.trim();
+    if let Ok(i) = s.parse::<i64>() {
+        return Some(JsonNumber::from(i));
+    }
+    s.parse::<f64>().ok().filter(|x| x.is_finite()).and_then(JsonNumber::from_f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use edgelink_macro::RedNodeConfig;
+    use serde_json::json;
+
+    #[derive(Debug, Deserialize, RedNodeConfig)]
+    struct TestNodeConfig {
+        #[red_typed]
+        payload: Variant,
+
+        #[serde(rename = "payloadType")]
+        payload_type: RedPropertyType,
+
+        #[red_typed("countKind")]
+        count: f64,
+
+        #[serde(rename = "enabledValue")]
+        #[red_typed]
+        enabled: bool,
+
+        #[red_typed]
+        topic: Variant,
+    }
+
+    #[derive(Debug, Deserialize, RedNodeConfig)]
+    #[serde(rename_all = "camelCase")]
+    struct RenamedNodeConfig {
+        #[red_typed]
+        start_value: f64,
+
+        #[serde(rename(deserialize = "stop"))]
+        #[red_typed]
+        stop_value: bool,
+    }
+
+    #[test]
+    fn test_typed_properties_should_be_coerced() {
+        let config = TestNodeConfig::from_rest(&json!({
+            "payload": "42", "payloadType": "num",
+            "count": " 1.5 ", "countKind": "num",
+            "enabledValue": "true", "enabledValueType": "bool",
+            "topic": "payload.name", "topicType": "msg"
+        }))
+        .unwrap();
+        assert_eq!(config.payload, Variant::from(42));
+        assert_eq!(config.payload_type, RedPropertyType::Num);
+        assert_eq!(config.count, 1.5);
+        assert!(config.enabled);
+        // The `msg` property is evaluated at runtime
+        assert_eq!(config.topic, Variant::from("payload.name"));
+
+        let config = TestNodeConfig::from_rest(&json!({
+            "payload": "{\"a\": [1]}", "payloadType": "json",
+            "count": 2, "countKind": "num",
+            "enabledValue": false,
+            "topic": "[1, 2]", "topicType": "bin"
+        }))
+        .unwrap();
+        assert_eq!(config.payload, Variant::from(json!({"a": [1]})));
+        assert_eq!(config.count, 2.0);
+        assert!(!config.enabled);
+        assert_eq!(config.topic, Variant::from(json!([1, 2])));
+    }
+
+    #[test]
+    fn test_typed_properties_should_respect_the_renaming() {
+        let config = RenamedNodeConfig::from_rest(&json!({
+            "startValue": "3", "startValueType": "num", "stop": "true", "stopType": "bool"
+        }))
+        .unwrap();
+        assert_eq!(config.start_value, 3.0);
+        assert!(config.stop_value);
+    }
+
+    #[test]
+    fn test_bad_typed_property_should_fail_with_the_property_name() {
+        let err = TestNodeConfig::from_rest(&json!({
+            "payload": "abc", "payloadType": "num", "count": 1, "enabledValue": true, "topic": ""
+        }))
+        .unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("TestNodeConfig"));
+        assert!(message.contains("'payload'"));
+        assert!(message.contains("'num'"));
+
+        let err = TestNodeConfig::from_rest(&json!({
+            "payload": "", "payloadType": "nope", "count": 1, "enabledValue": true, "topic": ""
+        }))
+        .unwrap_err();
+        assert!(err.to_string().contains("payloadType"));
+    }
+}
@@ -1,4 +1,5 @@
 use std::any::Any;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tokio_util::sync::CancellationToken;
@@ -9,20 +10,24 @@ use tokio::sync::mpsc;
 use crate::runtime::nodes::FlowNodeBehavior;
 use crate::EdgelinkError;
 
+mod codec;
 mod eid;
 mod error;
 mod msg;
 mod red_types;
+mod sequence;
 mod settings;
 mod variant;
 
 pub mod json;
 pub mod propex;
 
+pub use codec::*;
 pub use eid::*;
 pub use error::*;
 pub use msg::*;
 pub use red_types::*;
+pub use sequence::*;
 pub use settings::*;
 pub use variant::*;
 
@@ -44,24 +49,115 @@ pub trait ContextHolder: FlowsElement + Sync + Send {
     fn context(&self) -> Arc<Context>;
 }
 
+/// What a wire does when the bounded channel of its target node is full.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+pub enum OverflowPolicy {
+    /// Waits until the target node has room for the message
+    #[default]
+    #[serde(rename = "block")]
+    Block,
+
+    /// Drops the message being sent
+    #[serde(rename = "drop_new")]
+    DropNew,
+
+    /// Drops the oldest message waiting in the channel to make room for the message being sent
+    #[serde(rename = "drop_old")]
+    DropOld,
+}
+
+impl OverflowPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OverflowPolicy::Block => "block",
+            OverflowPolicy::DropNew => "drop_new",
+            OverflowPolicy::DropOld => "drop_old",
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct PortWire {
-    // pub target_node_id: ElementId,
+    pub target_node_id: ElementId,
     // pub target_node: Weak<dyn FlowNodeBehavior>,
     pub msg_sender: tokio::sync::mpsc::Sender<MsgHandle>,
+    pub overflow: OverflowPolicy,
+
+    /// The receiving end of `msg_sender`, the oldest message can only be evicted if it is present
+    pub target_rx: Option<Arc<MsgReceiverHolder>>,
+
+    /// The count of the messages dropped by the overflow policy
+    pub dropped: AtomicU64,
 }
 
 impl PortWire {
-    pub async fn tx(&self, msg: MsgHandle, cancel: CancellationToken) -> crate::Result<()> {
-        tokio::select! {
+    pub fn new(target_node_id: ElementId, msg_sender: MsgSender) -> Self {
+        PortWire {
+            target_node_id,
+            msg_sender,
+            overflow: OverflowPolicy::Block,
+            target_rx: None,
+            dropped: AtomicU64::new(0),
+        }
+    }
 
-            send_result = self.msg_sender.send(msg) =>  send_result.map_err(|e|
+    /// Sends the message to the target node, returns the message dropped by the overflow policy if any, it is either
+    /// the message being sent or the oldest one evicted from the channel.
+    pub async fn tx(&self, mut msg: MsgHandle, cancel: CancellationToken) -> crate::Result<Option<MsgHandle>> {
+        msg.untrack_completion_of(&self.target_node_id);
+        msg.begin_hop();
+        let full_msg = match self.overflow {
+            OverflowPolicy::Block => return self.send(msg, cancel).await.map(|_| None),
+            OverflowPolicy::DropNew | OverflowPolicy::DropOld => match self.msg_sender.try_send(msg) {
+                Ok(()) => return Ok(None),
+                Err(mpsc::error::TrySendError::Full(full_msg)) => full_msg,
+                Err(e) => {
+                    return Err(
+                        crate::EdgelinkError::InvalidOperation(format!("Failed to transmit message: {}", e)).into()
+                    )
+                }
+            },
+        };
+        if self.overflow == OverflowPolicy::DropNew || self.target_rx.is_none() {
+            self.count_dropped();
+            return Ok(Some(full_msg));
+        }
+        match self.target_rx.as_ref().and_then(|rx| rx.try_evict_oldest()) {
+            Some(evicted) => {
+                self.count_dropped();
+                // Waits like `block` only if another sender has taken the room first
+                self.send(full_msg, cancel).await?;
+                Ok(Some(evicted))
+            }
+            None => {
+                // The target node is taking the oldest message right now, so there will be room at once
+                self.send(full_msg, cancel).await.map(|_| None)
+            }
+        }
+    }
+
+    async fn send(&self, msg: MsgHandle, cancel: CancellationToken) -> crate::Result<()> {
+        tokio::select! {
+            send_result = self.msg_sender.send(msg) => send_result.map_err(|e|
                 crate::EdgelinkError::InvalidOperation(format!("Failed to transmit message: {}", e)).into()),
 
-            _ = cancel.cancelled() =>
-                Err(crate::EdgelinkError::TaskCancelled.into()),
+            _ = cancel.cancelled() => Err(crate::EdgelinkError::TaskCancelled.into()),
         }
     }
+
+    pub fn dropped_count(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    fn count_dropped(&self) {
+        let count = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+        log::debug!(
+            "Dropped a message to node '{}' by the overflow policy '{}', dropped: {}",
+            self.target_node_id,
+            self.overflow.as_str(),
+            count
+        );
+    }
 }
 
 #[derive(Debug)]
@@ -88,6 +184,11 @@ impl MsgReceiverHolder {
         MsgReceiverHolder { rx: Mutex::new(rx) }
     }
 
+    /// Removes the oldest message in the channel, returns `None` if the channel is empty or being received.
+    pub fn try_evict_oldest(&self) -> Option<MsgHandle> {
+        self.rx.try_lock().ok()?.try_recv().ok()
+    }
+
     pub async fn recv_msg_forever(&self) -> crate::Result<MsgHandle> {
         let rx = &mut self.rx.lock().await;
         match rx.recv().await {
@@ -171,3 +272,84 @@ pub fn query_trait<T: RuntimeElement, U: 'static>(ele: &T) -> Option<&U> {
 
 pub type MsgEventSender = tokio::sync::broadcast::Sender<MsgHandle>;
 pub type MsgEventReceiver = tokio::sync::broadcast::Receiver<MsgHandle>;
+
+/// The event of a message has been sent to an output port of a node
+#[derive(Debug, Clone)]
+pub struct MsgSentEvent {
+    pub port: usize,
+    pub msg: MsgHandle,
+    pub at: std::time::Instant,
+}
+
+pub type MsgSentEventSender = tokio::sync::broadcast::Sender<MsgSentEvent>;
+pub type MsgSentEventReceiver = tokio::sync::broadcast::Receiver<MsgSentEvent>;
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    /// Makes a wire to a saturated target node whose channel holds 2 messages, and sends 4 messages to it.
+    async fn send_to_saturated(overflow: OverflowPolicy) -> (PortWire, Vec<crate::Result<Option<MsgHandle>>>) {
+        let (tx, rx) = mpsc::channel(2);
+        let wire = PortWire {
+            overflow,
+            target_rx: Some(Arc::new(MsgReceiverHolder::new(rx))),
+            ..PortWire::new(ElementId::new(), tx)
+        };
+        let mut results = Vec::new();
+        for i in 0..4 {
+            let msg = MsgHandle::with_payload(Variant::from(i));
+            let sending = wire.tx(msg, CancellationToken::new());
+            match tokio::time::timeout(Duration::from_millis(50), sending).await {
+                Ok(res) => results.push(res),
+                Err(_) => results.push(Err(EdgelinkError::Timeout.into())),
+            }
+        }
+        (wire, results)
+    }
+
+    /// The payloads of the messages dropped by the overflow policy, in the order they were dropped.
+    async fn dropped_payloads(results: Vec<crate::Result<Option<MsgHandle>>>) -> Vec<Variant> {
+        let mut payloads = Vec::new();
+        for msg in results.into_iter().filter_map(|x| x.unwrap()) {
+            payloads.push(msg.read().await["payload"].clone());
+        }
+        payloads
+    }
+
+    async fn drain(wire: &PortWire) -> Vec<Variant> {
+        let mut payloads = Vec::new();
+        while let Some(msg) = wire.target_rx.as_ref().unwrap().try_evict_oldest() {
+            payloads.push(msg.read().await["payload"].clone());
+        }
+        payloads
+    }
+
+    #[tokio::test]
+    async fn test_block_policy_should_wait_for_saturated_target() {
+        let (wire, results) = send_to_saturated(OverflowPolicy::default()).await;
+        assert_eq!(wire.overflow, OverflowPolicy::Block);
+        assert!(results[0].is_ok() && results[1].is_ok());
+        assert!(results[2].is_err() && results[3].is_err());
+        assert_eq!(wire.dropped_count(), 0);
+        assert_eq!(drain(&wire).await, vec![Variant::from(0), Variant::from(1)]);
+    }
+
+    #[tokio::test]
+    async fn test_drop_new_policy_should_drop_sending_msgs() {
+        let (wire, results) = send_to_saturated(OverflowPolicy::DropNew).await;
+        assert_eq!(dropped_payloads(results).await, vec![Variant::from(2), Variant::from(3)]);
+        assert_eq!(wire.dropped_count(), 2);
+        assert_eq!(drain(&wire).await, vec![Variant::from(0), Variant::from(1)]);
+    }
+
+    #[tokio::test]
+    async fn test_drop_old_policy_should_evict_queued_msgs() {
+        let (wire, results) = send_to_saturated(OverflowPolicy::DropOld).await;
+        assert_eq!(dropped_payloads(results).await, vec![Variant::from(0), Variant::from(1)]);
+        assert_eq!(wire.dropped_count(), 2);
+        assert_eq!(drain(&wire).await, vec![Variant::from(2), Variant::from(3)]);
+    }
+}
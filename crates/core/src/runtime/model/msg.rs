@@ -1,12 +1,14 @@
-use std::collections::BTreeMap;
 use std::fmt;
 use std::ops::{Index, IndexMut};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 use serde::de;
 use serde::ser::SerializeMap;
 use tokio::sync::RwLock;
+use tokio::time::Instant;
 
 #[cfg(feature = "js")]
 mod js {
@@ -27,11 +29,57 @@ pub struct Envelope {
     pub msg: MsgHandle,
 }
 
-pub type MsgBody = BTreeMap<String, Variant>;
+pub type MsgBody = VariantObjectMap;
 
 #[derive(Debug, Clone)]
 pub struct MsgHandle {
     inner: Arc<RwLock<Msg>>,
+
+    /// The ordered sends tracking this message, shared by its clones and deep clones
+    completions: Vec<Arc<CompletionSignal>>,
+
+    /// Keeps the hop of the message open while a node keeps it for later, see `hold_completion()`
+    hold: Option<Arc<CompletionHold>>,
+}
+
+/// Resolves the receiver of an ordered send after every node the message and its copies were sent to has reported
+/// the completion, see `begin_hop()` and `end_hop()`.
+///
+/// The receiver also resolves if all handles tracking the message have been dropped, for the nodes not reporting the
+/// completion.
+#[derive(Debug)]
+struct CompletionSignal {
+    origin: ElementId,
+
+    /// The hops not completed yet, i.e. the copies sent to a node which has not reported it done with them
+    pending: AtomicUsize,
+
+    tx: std::sync::Mutex<Option<tokio::sync::oneshot::Sender<()>>>,
+}
+
+impl CompletionSignal {
+    fn begin_hop(&self) {
+        self.pending.fetch_add(1, Ordering::AcqRel);
+    }
+
+    fn end_hop(&self) {
+        // The messages forwarded without `PortWire::tx()` have never been counted
+        if self.pending.fetch_update(Ordering::AcqRel, Ordering::Acquire, |x| x.checked_sub(1)) == Ok(1) {
+            if let Some(tx) = self.tx.lock().expect("`tx` lock").take() {
+                let _ = tx.send(());
+            }
+        }
+    }
+}
+
+/// An extra hop of the message kept by a node, ended when the last handle carrying it has been dropped.
+#[derive(Debug)]
+struct CompletionHold(Vec<Arc<CompletionSignal>>);
+
+impl Drop for CompletionHold {
+    fn drop(&mut self) {
+        self.0.iter().for_each(|x| x.end_hop());
+    }
 }
 
 #[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
@@ -42,29 +90,60 @@ pub struct LinkCallStackEntry {
 
 #[derive(Debug, Clone)]
 pub struct Msg {
-    body: Variant,
+    /// Shared by the clones of the message until one of them is modified
+    body: CowVariant,
     pub link_call_stack: Option<Vec<LinkCallStackEntry>>,
+
+    /// The message is dropped instead of processed by a node receiving it after this instant, see `set_ttl()`
+    expires_at: Option<Instant>,
 }
 
 impl Default for Msg {
     fn default() -> Self {
-        Msg { body: Variant::empty_object(), link_call_stack: None }
+        Msg {
+            body: Variant::empty_object().into(),
+            link_call_stack: None,
+            expires_at: None,
+        }
     }
 }
 
 impl Msg {
     pub fn id(&self) -> Option<ElementId> {
-        self.body
-            .as_object()
-            .unwrap()
-            .get(wellknown::MSG_ID_PROPERTY)
-            .and_then(|x| x.as_str())
-            .and_then(parse_red_id_str)
+        self.body.as_object().unwrap().get(wellknown::MSG_ID_PROPERTY).and_then(|x| match x {
+            Variant::String(s) => parse_red_id_str(s),
+            Variant::Number(n) => n.as_u64().map(ElementId::with_u64),
+            _ => None,
+        })
     }
 
     pub fn set_id(&mut self, id: ElementId) {
         let uid: u64 = id.into();
-        self.body.as_object_mut().unwrap().insert(wellknown::MSG_ID_PROPERTY.to_string(), Variant::from(uid));
+        self.as_variant_object_mut().insert(wellknown::MSG_ID_PROPERTY.to_string(), Variant::from(uid));
+    }
+
+    /// Gives the message a generated `_msgid` if it has none, like Node-RED does for a message entering a flow.
+    pub fn ensure_id(&mut self) {
+        if !self.contains(wellknown::MSG_ID_PROPERTY) {
+            self.set_id(Msg::generate_id());
+        }
+    }
+
+    /// Sets the time-to-live from now, the TTL is kept by the clones of the message.
+    pub fn set_ttl(&mut self, ttl: Duration) {
+        self.expires_at = Some(Instant::now() + ttl);
+    }
+
+    pub fn expires_at(&self) -> Option<Instant> {
+        self.expires_at
+    }
+
+    pub fn set_expires_at(&mut self, expires_at: Option<Instant>) {
+        self.expires_at = expires_at;
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|x| x <= Instant::now())
     }
 
     pub fn generate_id() -> ElementId {
@@ -81,7 +160,7 @@ impl Msg {
     }
 
     pub fn as_variant_mut(&mut self) -> &mut Variant {
-        &mut self.body
+        self.body.make_mut()
     }
 
     pub fn as_variant_object(&self) -> &VariantObjectMap {
@@ -89,7 +168,7 @@ impl Msg {
     }
 
     pub fn as_variant_object_mut(&mut self) -> &mut VariantObjectMap {
-        self.body.as_object_mut().unwrap()
+        self.body.make_mut().as_object_mut().unwrap()
     }
 
     pub fn contains(&self, prop: &str) -> bool {
@@ -101,7 +180,7 @@ impl Msg {
     }
 
     pub fn get_mut(&mut self, prop: &str) -> Option<&mut Variant> {
-        self.body.as_object_mut().unwrap().get_property_mut(prop)
+        self.body.make_mut().as_object_mut().unwrap().get_property_mut(prop)
     }
 
     /// Get the value of a navigation property
@@ -112,8 +191,13 @@ impl Msg {
         self.body.as_object().unwrap().get_nav_property(expr, &[PropexEnv::ThisRef("msg")])
     }
 
+    /// Returns `true` if the navigation property exists, even if its value is `null`
+    pub fn has_nav(&self, expr: &str) -> bool {
+        self.get_nav(expr).is_some()
+    }
+
     pub fn get_nav_mut(&mut self, expr: &str) -> Option<&mut Variant> {
-        self.body.as_object_mut().unwrap().get_nav_property_mut(expr, &[PropexEnv::ThisRef("msg")])
+        self.body.make_mut().as_object_mut().unwrap().get_nav_property_mut(expr, &[PropexEnv::ThisRef("msg")])
     }
 
     pub fn get_nav_stripped_mut(&mut self, expr: &str) -> Option<&mut Variant> {
@@ -135,11 +219,11 @@ impl Msg {
     }
 
     pub fn set(&mut self, prop: String, value: Variant) {
-        self.body.as_object_mut().unwrap().set_property(prop, value)
+        self.body.make_mut().as_object_mut().unwrap().set_property(prop, value)
     }
 
     pub fn set_nav(&mut self, expr: &str, value: Variant, create_missing: bool) -> crate::Result<()> {
-        self.body.set_nav(expr, value, create_missing, &[PropexEnv::ThisRef("msg")])
+        self.body.make_mut().set_nav(expr, value, create_missing, &[PropexEnv::ThisRef("msg")])
     }
 
     pub fn set_nav_stripped(&mut self, expr: &str, value: Variant, create_missing: bool) -> crate::Result<()> {
@@ -152,11 +236,40 @@ impl Msg {
     }
 
     pub fn remove(&mut self, prop: &str) -> Option<Variant> {
-        self.body.as_object_mut().unwrap().remove_property(prop)
+        self.body.make_mut().as_object_mut().unwrap().remove_property(prop)
     }
 
     pub fn remove_nav(&mut self, prop: &str) -> Option<Variant> {
-        self.body.as_object_mut().unwrap().remove_nav_property(prop, &[PropexEnv::ThisRef("msg")])
+        self.body.make_mut().as_object_mut().unwrap().remove_nav_property(prop, &[PropexEnv::ThisRef("msg")])
+    }
+
+    pub fn delete_nav(&mut self, expr: &str) -> crate::Result<Option<Variant>> {
+        self.body.make_mut().delete_nav(expr, &[PropexEnv::ThisRef("msg")])
+    }
+}
+
+impl Msg {
+    /// Converts the message into a JSON object without an intermediate serialization.
+    ///
+    /// The `payload` property is always present, the `_msgid` given at the creation of the message is output as the
+    /// hex string like Node-RED. `_linkSource` is only included when the message is inside a link call.
+    pub fn to_json_value(&self) -> serde_json::Value {
+        let body = self.as_variant_object();
+        let mut map = serde_json::Map::new();
+        for (k, v) in body.iter() {
+            map.insert(k.clone(), serde_json::Value::from(v));
+        }
+        // The `_msgid` not in the format of an ID is kept as is
+        if let Some(msg_id) = self.id() {
+            map.insert(wellknown::MSG_ID_PROPERTY.to_string(), msg_id.to_string().into());
+        }
+        map.entry("payload").or_insert(serde_json::Value::Null);
+        if let Some(link_call_stack) = &self.link_call_stack {
+            if let Ok(lcs) = serde_json::to_value(link_call_stack) {
+                map.insert(wellknown::LINK_SOURCE_PROPERTY.to_string(), lcs);
+            }
+        }
+        serde_json::Value::Object(map)
     }
 }
 
@@ -192,7 +305,7 @@ impl Index<&str> for Msg {
 
 impl IndexMut<&str> for Msg {
     fn index_mut(&mut self, key: &str) -> &mut Self::Output {
-        self.body.as_object_mut().unwrap().entry(key.to_string()).or_default()
+        self.body.make_mut().as_object_mut().unwrap().entry(key.to_string()).or_default()
     }
 }
 
@@ -229,7 +342,7 @@ impl<'de> serde::Deserialize<'de> for Msg {
                 V: serde::de::MapAccess<'de>,
             {
                 let mut link_call_stack = None;
-                let mut body: BTreeMap<String, Variant> = BTreeMap::new();
+                let mut body = VariantObjectMap::new();
 
                 while let Some(key) = map.next_key::<String>()? {
                     match key.as_str() {
@@ -246,7 +359,9 @@ impl<'de> serde::Deserialize<'de> for Msg {
                     }
                 }
 
-                Ok(Msg { body: Variant::Object(body), link_call_stack })
+                // The created message is given an ID, the same as the ones built by `MsgBuilder`
+                body.entry(wellknown::MSG_ID_PROPERTY.to_string()).or_insert_with(Msg::generate_id_variant);
+                Ok(Msg { body: Variant::Object(body).into(), link_call_stack, expires_at: None })
             }
         }
 
@@ -261,7 +376,7 @@ impl<'js> js::FromJs<'js> for Msg {
         match jv.type_of() {
             js::Type::Object => {
                 if let Some(jo) = jv.as_object() {
-                    let mut body = BTreeMap::new();
+                    let mut body = VariantObjectMap::new();
                     // TODO _msgid check
                     for result in jo.props::<String, js::Value>() {
                         match result {
@@ -303,7 +418,9 @@ impl<'js> js::FromJs<'js> for Msg {
                             }
                         }
                     }
-                    Ok(Msg { link_call_stack, body: Variant::Object(body) })
+                    // The new object returned by the user function is given an ID, like `node.send()` of Node-RED
+                    body.entry(wellknown::MSG_ID_PROPERTY.to_string()).or_insert_with(Msg::generate_id_variant);
+                    Ok(Msg { link_call_stack, body: Variant::Object(body).into(), expires_at: None })
                 } else {
                     Err(js::Error::FromJs { from: "JS object", to: "Variant::Object", message: None })
                 }
@@ -317,7 +434,7 @@ impl<'js> js::FromJs<'js> for Msg {
 impl<'js> js::IntoJs<'js> for Msg {
     fn into_js(self, ctx: &js::Ctx<'js>) -> js::Result<js::Value<'js>> {
         let msg_id = self.id();
-        let jsv = self.body.into_js(ctx)?;
+        let jsv = self.body.into_inner().into_js(ctx)?;
         let obj = jsv.as_object().unwrap();
         if let Some(msg_id) = msg_id {
             let msgid_atom = wellknown::MSG_ID_PROPERTY.into_js(ctx)?;
@@ -337,14 +454,92 @@ impl<'js> js::IntoJs<'js> for Msg {
     }
 }
 
+/// A builder to construct messages in Rust nodes and tests.
+///
+/// # Examples
+///
+/// ```
+/// use edgelink_core::runtime::model::*;
+///
+/// let msg = MsgBuilder::new().payload("foo").topic("bar").prop("data.items[0]", 1).build().unwrap();
+/// assert!(msg.id().is_some());
+/// ```
+#[derive(Debug)]
+pub struct MsgBuilder {
+    msg: Msg,
+    id: Option<ElementId>,
+    error: Option<anyhow::Error>,
+}
+
+impl Default for MsgBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MsgBuilder {
+    /// Creates a builder with a generated `_msgid`.
+    pub fn new() -> Self {
+        Self { msg: Msg::default(), id: Some(Msg::generate_id()), error: None }
+    }
+
+    pub fn id(mut self, id: ElementId) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    /// Builds the message without the `_msgid` property.
+    pub fn without_id(mut self) -> Self {
+        self.id = None;
+        self
+    }
+
+    pub fn payload(self, value: impl Into<Variant>) -> Self {
+        self.prop("payload", value)
+    }
+
+    pub fn topic(self, topic: impl Into<String>) -> Self {
+        self.prop("topic", Variant::String(topic.into()))
+    }
+
+    /// Sets the property by a property expression like `data.items[0]`, the missing parents will be created.
+    pub fn prop(mut self, expr: &str, value: impl Into<Variant>) -> Self {
+        if self.error.is_none() {
+            if let Err(e) = self.msg.set_nav_stripped(expr, value.into(), true) {
+                self.error = Some(e.context(format!("Failed to set the property 'msg.{}'", expr)));
+            }
+        }
+        self
+    }
+
+    pub fn link_call_stack(mut self, link_call_stack: Vec<LinkCallStackEntry>) -> Self {
+        self.msg.link_call_stack = Some(link_call_stack);
+        self
+    }
+
+    /// Builds the message, returns the first error occurred in setting properties.
+    pub fn build(self) -> crate::Result<Msg> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        let mut msg = self.msg;
+        if let Some(id) = self.id {
+            msg.set_id(id);
+        }
+        Ok(msg)
+    }
+}
+
 impl Default for MsgHandle {
     fn default() -> Self {
         let msg = Msg {
-            body: Variant::Object(BTreeMap::from([
+            body: Variant::Object(VariantObjectMap::from([
                 (wellknown::MSG_ID_PROPERTY.to_string(), Msg::generate_id_variant()),
                 ("payload".to_string(), Variant::Null),
-            ])),
+            ]))
+            .into(),
             link_call_stack: None,
+            expires_at: None,
         };
         MsgHandle::new(msg)
     }
@@ -352,21 +547,27 @@ impl Default for MsgHandle {
 
 impl MsgHandle {
     pub fn new(inner: Msg) -> Self {
-        MsgHandle { inner: (Arc::new(RwLock::new(inner))) }
+        MsgHandle { inner: (Arc::new(RwLock::new(inner))), completions: Vec::new(), hold: None }
     }
 
-    pub fn with_body(body: BTreeMap<String, Variant>) -> Self {
-        let msg = Msg { link_call_stack: None, body: Variant::Object(body) };
+    pub fn with_body(body: VariantObjectMap) -> Self {
+        let msg = Msg {
+            link_call_stack: None,
+            body: Variant::Object(body).into(),
+            expires_at: None,
+        };
         MsgHandle::new(msg)
     }
 
     pub fn with_payload(payload: Variant) -> Self {
         let msg = Msg {
             link_call_stack: None,
-            body: Variant::Object(BTreeMap::from([
+            body: Variant::Object(VariantObjectMap::from([
                 (wellknown::MSG_ID_PROPERTY.to_string(), Msg::generate_id_variant()),
                 ("payload".to_string(), payload),
-            ])),
+            ]))
+            .into(),
+            expires_at: None,
         };
         MsgHandle::new(msg)
     }
@@ -384,7 +585,45 @@ impl MsgHandle {
         if new_id {
             inner.set_id(Msg::generate_id());
         }
-        MsgHandle::new(inner)
+        MsgHandle { inner: Arc::new(RwLock::new(inner)), completions: self.completions.clone(), hold: None }
+    }
+
+    /// Tracks the message for an ordered send of the node `origin`, the returned receiver resolves after the downstream
+    /// nodes have all done with the message and its copies.
+    pub fn track_completion(&mut self, origin: ElementId) -> tokio::sync::oneshot::Receiver<()> {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let signal = CompletionSignal { origin, pending: AtomicUsize::new(0), tx: std::sync::Mutex::new(Some(tx)) };
+        self.completions.push(Arc::new(signal));
+        rx
+    }
+
+    /// Counts the message sent to a node for the ordered sends tracking it.
+    pub fn begin_hop(&mut self) {
+        self.completions.iter().for_each(|x| x.begin_hop());
+        // The hold is ended by the node keeping the message, not by the receiver of this copy
+        self.hold = None;
+    }
+
+    /// Reports the node has done with the message, the ordered sends resolve after their last hop ended.
+    pub fn end_hop(&self) {
+        self.completions.iter().for_each(|x| x.end_hop());
+    }
+
+    /// Returns the handle to keep the message for later, e.g. in the queue of a delay node, the ordered sends tracking
+    /// the message wait until the returned handle and its clones have been sent on or dropped.
+    pub fn hold_completion(&self) -> MsgHandle {
+        if self.completions.is_empty() {
+            return self.clone();
+        }
+        self.completions.iter().for_each(|x| x.begin_hop());
+        let hold = CompletionHold(self.completions.clone());
+        MsgHandle { inner: self.inner.clone(), completions: self.completions.clone(), hold: Some(Arc::new(hold)) }
+    }
+
+    /// Stops tracking the message for the ordered sends of the node, it must be called when the message is sent back
+    /// to that node, which cannot receive it while waiting for the completion.
+    pub fn untrack_completion_of(&mut self, node_id: &ElementId) {
+        self.completions.retain(|x| x.origin != *node_id);
     }
 
     pub async fn unwrap(self) -> Msg {
@@ -396,9 +635,69 @@ impl MsgHandle {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::utils::constants::NAME_STR;
+    use itertools::Itertools;
     use serde::Deserialize;
     use serde_json::json;
-    use crate::utils::constants::NAME_STR;
+
+    #[test]
+    fn test_msg_builder_should_set_nested_props() {
+        let msg = MsgBuilder::new()
+            .payload(42)
+            .topic("sensors")
+            .prop("data.items[0]", "first")
+            .prop("msg.data.meta.unit", "C")
+            .build()
+            .unwrap();
+
+        assert!(msg.id().is_some());
+        assert_eq!(msg["payload"], Variant::from(42));
+        assert_eq!(msg["topic"], Variant::from("sensors"));
+        let expected = Variant::deserialize(json!({"items": ["first"], "meta": {"unit": "C"}})).unwrap();
+        assert_eq!(msg["data"], expected);
+    }
+
+    #[test]
+    fn test_msg_builder_id_options() {
+        let msg = MsgBuilder::new().id(ElementId::with_u64(0x1234)).build().unwrap();
+        assert_eq!(msg.id(), Some(ElementId::with_u64(0x1234)));
+
+        let msg = MsgBuilder::new().without_id().payload("foo").build().unwrap();
+        assert!(!msg.contains(wellknown::MSG_ID_PROPERTY));
+    }
+
+    #[test]
+    fn test_msg_builder_should_report_bad_props() {
+        assert!(MsgBuilder::new().prop("data[", 1).build().is_err());
+    }
+
+    #[test]
+    fn test_to_json_value_should_contain_wellknown_properties() {
+        let msg = Msg::deserialize(json!({"topic": "foo", "data": {"bytes": [1, 2]}})).unwrap();
+        let jv = msg.to_json_value();
+        let keys: Vec<&str> = jv.as_object().unwrap().keys().map(|x| x.as_str()).sorted().collect();
+        assert_eq!(keys, vec!["_msgid", "data", "payload", "topic"]);
+        assert!(jv["_msgid"].as_str().and_then(parse_red_id_str).is_some());
+        // The `_msgid` generated at the deserialization is kept by the later calls and the clones
+        assert_eq!(msg.to_json_value()["_msgid"], jv["_msgid"]);
+        assert_eq!(msg.clone().to_json_value()["_msgid"], jv["_msgid"]);
+        assert_eq!(jv["payload"], serde_json::Value::Null);
+        assert_eq!(jv["data"], json!({"bytes": [1, 2]}));
+
+        let mut msg = Msg::deserialize(json!({"_msgid": "1234", "payload": "bar"})).unwrap();
+        msg.set("buf".into(), Variant::Bytes(vec![3, 4]));
+        msg.set_id(ElementId::with_u64(0x1234));
+        msg.push_link_source(LinkCallStackEntry {
+            id: ElementId::with_u64(1),
+            link_call_node_id: ElementId::with_u64(2),
+        });
+        let jv = msg.to_json_value();
+        let keys: Vec<&str> = jv.as_object().unwrap().keys().map(|x| x.as_str()).sorted().collect();
+        assert_eq!(keys, vec!["_linkSource", "_msgid", "buf", "payload"]);
+        assert_eq!(jv["_msgid"], json!("0000000000001234"));
+        assert_eq!(jv["buf"], json!([3, 4]));
+        assert_eq!(jv["payload"], json!("bar"));
+    }
 
     #[test]
     fn test_get_nested_nav_property() {
@@ -411,6 +710,18 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_has_nav_property() {
+        let jv = json!({"payload": {"a": {"b": [1, null]}, "c": null}, "topic": "a"});
+        let msg = Msg::deserialize(jv).unwrap();
+        assert!(msg.has_nav("payload.a.b[1]"));
+        assert!(msg.has_nav("payload.c"));
+        assert!(msg.has_nav("payload[msg.topic].b"));
+        assert!(!msg.has_nav("payload.a.b[2]"));
+        assert!(!msg.has_nav("payload.c.d"));
+        assert!(!msg.has_nav("payload.a.x"));
+    }
+
     #[test]
     fn test_get_nested_nav_property_mut() {
         let jv = json!({"payload": "newValue", "lookup": {"a": 1, "b": 2}, "topic": "b"});
@@ -467,4 +778,85 @@ mod tests {
             "new_new_value"
         );
     }
+
+    #[tokio::test]
+    async fn test_ttl_should_survive_clones() {
+        let mut msg = Msg::deserialize(json!({"payload": 1})).unwrap();
+        assert!(msg.expires_at().is_none());
+        assert!(!msg.is_expired());
+
+        msg.set_ttl(Duration::from_secs(60));
+        let handle = MsgHandle::new(msg.clone());
+        let deep_cloned = handle.deep_clone(true).await;
+        assert_eq!(msg.expires_at(), deep_cloned.read().await.expires_at());
+        assert!(!deep_cloned.read().await.is_expired());
+
+        msg.set_ttl(Duration::ZERO);
+        assert!(msg.is_expired());
+    }
+
+    #[tokio::test]
+    async fn test_deep_cloned_msg_should_share_the_body_until_modified() {
+        let handle = MsgHandle::new(Msg::deserialize(json!({"payload": {"values": [1, 2, 3]}})).unwrap());
+        let deep_cloned = handle.deep_clone(false).await;
+        assert!(handle.read().await.body.ptr_eq(&deep_cloned.read().await.body));
+
+        deep_cloned.write().await.set_nav("payload.values[0]", Variant::from(42), false).unwrap();
+        assert!(!handle.read().await.body.ptr_eq(&deep_cloned.read().await.body));
+        assert_eq!(handle.read().await.get_nav("payload.values[0]"), Some(&Variant::from(1)));
+        assert_eq!(deep_cloned.read().await.get_nav("payload.values[0]"), Some(&Variant::from(42)));
+    }
+
+    #[tokio::test]
+    async fn test_completion_should_resolve_after_all_tracked_handles_dropped() {
+        use tokio::sync::oneshot::error::TryRecvError;
+
+        let origin = ElementId::with_u64(1);
+        let mut tracked = MsgHandle::default();
+        let mut completed = tracked.track_completion(origin);
+        let cloned = tracked.clone();
+        let deep_cloned = tracked.deep_clone(true).await;
+        drop(tracked);
+        drop(cloned);
+        assert_eq!(completed.try_recv(), Err(TryRecvError::Empty));
+        drop(deep_cloned);
+        assert!(completed.await.is_err());
+
+        // The message sent back to the origin node does not hold the completion
+        let mut tracked = MsgHandle::default();
+        let completed = tracked.track_completion(origin);
+        let mut other_completed = tracked.track_completion(ElementId::with_u64(2));
+        let mut looped = tracked.clone();
+        looped.untrack_completion_of(&origin);
+        drop(tracked);
+        assert!(completed.await.is_err());
+        assert_eq!(other_completed.try_recv(), Err(TryRecvError::Empty));
+        drop(looped);
+        assert!(other_completed.await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_completion_should_resolve_after_the_last_hop_ended() {
+        use tokio::sync::oneshot::error::TryRecvError;
+
+        let mut sent = MsgHandle::default();
+        let mut completed = sent.track_completion(ElementId::with_u64(1));
+        sent.begin_hop();
+        // The receiving node forwards a copy and keeps another one for later before it reports the completion
+        let mut forwarded = sent.deep_clone(true).await;
+        forwarded.begin_hop();
+        let kept = sent.hold_completion();
+        sent.end_hop();
+        assert_eq!(completed.try_recv(), Err(TryRecvError::Empty));
+        forwarded.end_hop();
+        assert_eq!(completed.try_recv(), Err(TryRecvError::Empty));
+
+        // Resolved while the other handles are still alive
+        let mut released = kept.clone();
+        drop(kept);
+        released.begin_hop();
+        assert_eq!(completed.try_recv(), Err(TryRecvError::Empty));
+        released.end_hop();
+        assert_eq!(completed.try_recv(), Ok(()));
+    }
 }
@@ -0,0 +1,310 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+use crate::runtime::model::*;
+use crate::{EdgelinkError, ErrorContext};
+
+/// The `msg.parts` property of a message in a sequence, as generated by the split node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MsgParts {
+    pub id: String,
+    pub index: usize,
+    /// The count may be unknown until the last message of a stream arrives
+    pub count: Option<usize>,
+    pub kind: Option<String>,
+    pub key: Option<String>,
+    /// The delimiter of a string sequence
+    pub ch: Option<String>,
+    /// The `msg.parts` of the outer sequence, if the message was split from a part of another sequence
+    pub parent: Option<Box<MsgParts>>,
+}
+
+impl MsgParts {
+    /// Parses the `msg.parts`, returns `None` if the message is not in a sequence.
+    pub fn from_msg(msg: &Msg) -> crate::Result<Option<MsgParts>> {
+        msg.get("parts").map(MsgParts::from_variant).transpose()
+    }
+
+    pub fn from_variant(parts: &Variant) -> crate::Result<MsgParts> {
+        let parts = parts.as_object().ok_or(EdgelinkError::InvalidOperation("`msg.parts` must be an object".into()))?;
+        let id = match parts.get("id") {
+            Some(Variant::String(s)) => s.clone(),
+            Some(Variant::Number(n)) => n.to_string(),
+            _ => return Err(EdgelinkError::InvalidOperation("Message missing `msg.parts.id` property".into()).into()),
+        };
+        let index = parts
+            .get("index")
+            .and_then(|x| x.as_u64())
+            .ok_or(EdgelinkError::InvalidOperation("Message missing `msg.parts.index` property".into()))?
+            as usize;
+        let count = parts.get("count").and_then(|x| x.as_u64()).map(|x| x as usize);
+        let kind = parts.get("type").and_then(|x| x.as_str()).map(String::from);
+        let key = parts.get("key").and_then(|x| x.as_str()).map(String::from);
+        let ch = parts.get("ch").and_then(|x| x.as_str()).map(String::from);
+        let parent = parts.get("parts").map(MsgParts::from_variant).transpose()?.map(Box::new);
+        Ok(MsgParts { id, index, count, kind, key, ch, parent })
+    }
+
+    /// Converts back to the `msg.parts` object, `from_variant()` of the result gives the same `MsgParts`.
+    pub fn to_variant(&self) -> Variant {
+        let mut parts = VariantObjectMap::from([
+            ("id".to_string(), Variant::String(self.id.clone())),
+            ("index".to_string(), Variant::from(self.index as u64)),
+        ]);
+        if let Some(count) = self.count {
+            parts.insert("count".to_string(), Variant::from(count as u64));
+        }
+        if let Some(kind) = &self.kind {
+            parts.insert("type".to_string(), Variant::String(kind.clone()));
+        }
+        if let Some(key) = &self.key {
+            parts.insert("key".to_string(), Variant::String(key.clone()));
+        }
+        if let Some(ch) = &self.ch {
+            parts.insert("ch".to_string(), Variant::String(ch.clone()));
+        }
+        if let Some(parent) = &self.parent {
+            parts.insert("parts".to_string(), parent.to_variant());
+        }
+        Variant::Object(parts)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SequenceStatus<T> {
+    /// Waiting for more items, the count is `None` if it is still unknown
+    Incomplete { received: usize, count: Option<usize> },
+
+    /// All items in the index order
+    Complete(Vec<T>),
+
+    /// The index has been received, the item is given back and the sequence is untouched
+    Duplicated(T),
+}
+
+#[derive(Debug)]
+struct PendingSequence<T> {
+    items: BTreeMap<usize, T>,
+    count: Option<usize>,
+    started_at: Instant,
+}
+
+/// Tracks the sequences of messages by `msg.parts.id` and tells when a sequence is complete, i.e. all indices
+/// in `0..count` have been received, regardless of their arrival order.
+///
+/// The tracker does not spawn any timer, the owner node is expected to call `expire()` at `next_deadline()`.
+#[derive(Debug)]
+pub struct SequenceTracker<T> {
+    sequences: HashMap<String, PendingSequence<T>>,
+    timeout: Option<Duration>,
+    item_count: usize,
+}
+
+impl<T> Default for SequenceTracker<T> {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl<T> SequenceTracker<T> {
+    /// Creates a tracker, the incomplete sequences older than the `timeout` will be returned by `expire()`.
+    pub fn new(timeout: Option<Duration>) -> Self {
+        SequenceTracker { sequences: HashMap::new(), timeout, item_count: 0 }
+    }
+
+    pub fn push(&mut self, parts: &MsgParts, item: T, now: Instant) -> crate::Result<SequenceStatus<T>> {
+        if let Some(count) = parts.count {
+            if parts.index >= count {
+                return Err(EdgelinkError::OutOfRange).with_context(|| {
+                    format!("The index {} of the sequence '{}' exceeds its count {}", parts.index, parts.id, count)
+                });
+            }
+        }
+
+        let seq = self.sequences.entry(parts.id.clone()).or_insert_with(|| PendingSequence {
+            items: BTreeMap::new(),
+            count: None,
+            started_at: now,
+        });
+        match (seq.count, parts.count) {
+            (Some(known), Some(count)) if known != count => {
+                return Err(EdgelinkError::InvalidOperation(format!(
+                    "The count of the sequence '{}' changed from {} to {}",
+                    parts.id, known, count
+                ))
+                .into());
+            }
+            (_, Some(count)) => {
+                if let Some(max_index) = seq.items.keys().next_back().filter(|x| **x >= count) {
+                    return Err(EdgelinkError::OutOfRange).with_context(|| {
+                        format!(
+                            "The received index {} of the sequence '{}' exceeds its count {}",
+                            max_index, parts.id, count
+                        )
+                    });
+                }
+                seq.count = Some(count);
+            }
+            _ => {}
+        }
+        if seq.items.contains_key(&parts.index) {
+            return Ok(SequenceStatus::Duplicated(item));
+        }
+        seq.items.insert(parts.index, item);
+        self.item_count += 1;
+
+        match seq.count {
+            Some(count) if seq.items.len() == count => {
+                let seq = self.sequences.remove(&parts.id).expect("The sequence must exist");
+                self.item_count -= seq.items.len();
+                Ok(SequenceStatus::Complete(seq.items.into_values().collect()))
+            }
+            count => Ok(SequenceStatus::Incomplete { received: seq.items.len(), count }),
+        }
+    }
+
+    /// Removes the incomplete sequence and returns its received items in the index order.
+    pub fn remove(&mut self, id: &str) -> Option<Vec<T>> {
+        let seq = self.sequences.remove(id)?;
+        self.item_count -= seq.items.len();
+        Some(seq.items.into_values().collect())
+    }
+
+    /// The timeout hook: removes the timed out sequences and returns their received items in the index order.
+    pub fn expire(&mut self, now: Instant) -> Vec<(String, Vec<T>)> {
+        let timeout = match self.timeout {
+            Some(timeout) => timeout,
+            None => return Vec::new(),
+        };
+        let expired_ids: Vec<String> =
+            self.sequences.iter().filter(|(_, x)| x.started_at + timeout <= now).map(|(k, _)| k.clone()).collect();
+        expired_ids.into_iter().filter_map(|id| self.remove(&id).map(|items| (id, items))).collect()
+    }
+
+    /// The earliest instant that an incomplete sequence times out.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let timeout = self.timeout?;
+        self.sequences.values().map(|x| x.started_at + timeout).min()
+    }
+
+    pub fn clear(&mut self) {
+        self.sequences.clear();
+        self.item_count = 0;
+    }
+
+    /// The number of the items held by all incomplete sequences.
+    pub fn item_count(&self) -> usize {
+        self.item_count
+    }
+
+    pub fn len(&self) -> usize {
+        self.sequences.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sequences.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    fn parts(id: &str, index: usize, count: Option<usize>) -> MsgParts {
+        MsgParts { id: id.to_string(), index, count, kind: None, key: None, ch: None, parent: None }
+    }
+
+    #[test]
+    fn test_msg_parts_should_be_parsed_from_msg() {
+        let msg =
+            Msg::deserialize(json!({"parts": {"id": "abc", "type": "object", "key": "x", "index": 1, "count": 2}}))
+                .unwrap();
+        let parts = MsgParts::from_msg(&msg).unwrap().unwrap();
+        assert_eq!(parts.id, "abc");
+        assert_eq!(parts.index, 1);
+        assert_eq!(parts.count, Some(2));
+        assert_eq!(parts.kind.as_deref(), Some("object"));
+        assert_eq!(parts.key.as_deref(), Some("x"));
+
+        assert_eq!(MsgParts::from_variant(&parts.to_variant()).unwrap(), parts);
+
+        let nested = Variant::deserialize(json!({
+            "id": "inner", "type": "string", "ch": ",", "index": 0, "count": 3,
+            "parts": {"id": "outer", "type": "array", "index": 2, "count": 4}
+        }))
+        .unwrap();
+        let nested_parts = MsgParts::from_variant(&nested).unwrap();
+        assert_eq!(nested_parts.ch.as_deref(), Some(","));
+        assert_eq!(nested_parts.parent.as_ref().map(|x| x.index), Some(2));
+        assert_eq!(nested_parts.to_variant(), nested);
+
+        assert!(MsgParts::from_msg(&Msg::default()).unwrap().is_none());
+        let bad_msg = Msg::deserialize(json!({"parts": {"id": "abc"}})).unwrap();
+        assert!(MsgParts::from_msg(&bad_msg).is_err());
+    }
+
+    #[test]
+    fn test_out_of_order_sequence_should_complete() {
+        let now = Instant::now();
+        let mut tracker = SequenceTracker::new(None);
+        assert_eq!(
+            tracker.push(&parts("a", 2, None), "c", now).unwrap(),
+            SequenceStatus::Incomplete { received: 1, count: None }
+        );
+        assert_eq!(tracker.push(&parts("b", 0, Some(1)), "x", now).unwrap(), SequenceStatus::Complete(vec!["x"]));
+        assert_eq!(
+            tracker.push(&parts("a", 0, Some(3)), "a", now).unwrap(),
+            SequenceStatus::Incomplete { received: 2, count: Some(3) }
+        );
+        assert_eq!(
+            tracker.push(&parts("a", 1, None), "b", now).unwrap(),
+            SequenceStatus::Complete(vec!["a", "b", "c"])
+        );
+        assert!(tracker.is_empty());
+    }
+
+    #[test]
+    fn test_incomplete_sequence_should_expire() {
+        let now = Instant::now();
+        let mut tracker = SequenceTracker::new(Some(Duration::from_secs(1)));
+        tracker.push(&parts("a", 1, Some(3)), 2, now).unwrap();
+        tracker.push(&parts("a", 0, Some(3)), 1, now).unwrap();
+        tracker.push(&parts("b", 0, Some(2)), 10, now + Duration::from_millis(500)).unwrap();
+        assert_eq!(tracker.next_deadline(), Some(now + Duration::from_secs(1)));
+
+        assert!(tracker.expire(now + Duration::from_millis(900)).is_empty());
+        assert_eq!(tracker.expire(now + Duration::from_secs(1)), vec![("a".to_string(), vec![1, 2])]);
+        assert_eq!(tracker.len(), 1);
+        assert_eq!(tracker.next_deadline(), Some(now + Duration::from_millis(1500)));
+    }
+
+    #[test]
+    fn test_duplicated_index_should_be_given_back() {
+        let now = Instant::now();
+        let mut tracker = SequenceTracker::new(None);
+        tracker.push(&parts("a", 0, Some(2)), "first", now).unwrap();
+        assert_eq!(tracker.push(&parts("a", 0, Some(2)), "again", now).unwrap(), SequenceStatus::Duplicated("again"));
+        assert_eq!(tracker.item_count(), 1);
+        assert_eq!(
+            tracker.push(&parts("a", 1, Some(2)), "second", now).unwrap(),
+            SequenceStatus::Complete(vec!["first", "second"])
+        );
+        assert_eq!(tracker.item_count(), 0);
+    }
+
+    #[test]
+    fn test_inconsistent_count_should_be_rejected() {
+        let now = Instant::now();
+        let mut tracker = SequenceTracker::new(None);
+        assert!(tracker.push(&parts("a", 2, Some(2)), 0, now).is_err());
+        tracker.push(&parts("a", 5, None), 0, now).unwrap();
+        assert!(tracker.push(&parts("a", 0, Some(3)), 0, now).is_err());
+        tracker.push(&parts("b", 0, Some(3)), 0, now).unwrap();
+        assert!(tracker.push(&parts("b", 1, Some(4)), 0, now).is_err());
+        assert_eq!(tracker.item_count(), 2);
+        assert_eq!(tracker.remove("b"), Some(vec![0]));
+        assert_eq!(tracker.item_count(), 1);
+    }
+}
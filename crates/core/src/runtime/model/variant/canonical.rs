@@ -0,0 +1,50 @@
+use super::*;
+
+/// The integral floats in this range are converted to the integers exactly.
+const MAX_SAFE_INTEGER: f64 = 9007199254740991.0;
+
+impl Variant {
+    /// Canonicalizes the value recursively: the object keys are sorted, the arrays keep their order, and the integral
+    /// floats are normalized to the integers, e.g. `2.0` to `2` and `-0.0` to `0`.
+    ///
+    /// The keys of a `VariantObjectMap` are always kept sorted, so the objects only have their values canonicalized.
+    pub fn canonicalize(&mut self) {
+        match self {
+            Variant::Number(num) if num.is_f64() => {
+                let value = num.as_f64().unwrap_or(f64::NAN);
+                if value.fract() == 0.0 && value.abs() <= MAX_SAFE_INTEGER {
+                    *num = serde_json::Number::from(value as i64);
+                }
+            }
+            Variant::Array(items) => items.iter_mut().for_each(Variant::canonicalize),
+            Variant::Object(obj) => obj.values_mut().for_each(Variant::canonicalize),
+            _ => {}
+        }
+    }
+
+    /// Serializes the canonicalized value into the compact JSON, the equal values always give the same string.
+    pub fn to_canonical_json(&self) -> crate::Result<String> {
+        let mut canonical = self.clone();
+        canonical.canonicalize();
+        Ok(serde_json::to_string(&canonical)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_canonical_json_should_sort_nested_keys_and_keep_array_order() {
+        let value = Variant::deserialize(json!({
+            "b": [3, 1, {"z": 1.0, "y": -0.0}],
+            "a": {"d": {"f": 2.5, "e": null}, "c": "x"}
+        }))
+        .unwrap();
+        assert_eq!(
+            value.to_canonical_json().unwrap(),
+            r#"{"a":{"c":"x","d":{"e":null,"f":2.5}},"b":[3,1,{"y":0,"z":1}]}"#
+        );
+    }
+}
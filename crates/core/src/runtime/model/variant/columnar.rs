@@ -0,0 +1,150 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, BooleanArray, Float64Array, Int64Array, StringArray};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::ipc::writer::StreamWriter;
+use arrow::record_batch::RecordBatch;
+
+use super::*;
+
+impl Variant {
+    /// Converts an array of objects into an Arrow record batch, each property becomes a nullable column.
+    ///
+    /// The type of a column is inferred from its non-null values: booleans, integers, numbers (integers mixed
+    /// with floats) or strings. Columns with mixed or nested values are stored as their JSON texts.
+    /// Missing properties and `null` are converted to nulls.
+    pub fn to_record_batch(&self) -> crate::Result<RecordBatch> {
+        let rows = self.as_array().ok_or(EdgelinkError::BadArgument("self")).context("Expected an array of objects")?;
+        let mut columns = BTreeSet::new();
+        for row in rows.iter() {
+            let obj =
+                row.as_object().ok_or(EdgelinkError::BadArgument("self")).context("Expected an array of objects")?;
+            columns.extend(obj.keys().map(|x| x.as_str()));
+        }
+
+        let mut fields = Vec::with_capacity(columns.len());
+        let mut arrays: Vec<ArrayRef> = Vec::with_capacity(columns.len());
+        for column in columns.into_iter() {
+            let cells: Vec<Option<&Variant>> =
+                rows.iter().map(|row| row.as_object().and_then(|x| x.get(column)).filter(|x| !x.is_null())).collect();
+            let data_type = infer_column_type(&cells);
+            let array: ArrayRef = match data_type {
+                DataType::Boolean => {
+                    Arc::new(cells.iter().map(|x| x.and_then(|v| v.as_bool())).collect::<BooleanArray>())
+                }
+                DataType::Int64 => Arc::new(cells.iter().map(|x| x.and_then(|v| v.as_i64())).collect::<Int64Array>()),
+                DataType::Float64 => {
+                    Arc::new(cells.iter().map(|x| x.and_then(|v| v.as_f64())).collect::<Float64Array>())
+                }
+                _ => Arc::new(cells.iter().map(|x| x.map(cell_to_string)).collect::<StringArray>()),
+            };
+            fields.push(Field::new(column, array.data_type().clone(), true));
+            arrays.push(array);
+        }
+
+        let batch = RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)?;
+        Ok(batch)
+    }
+
+    /// Converts an array of objects into the Arrow IPC streaming format.
+    pub fn to_arrow_ipc(&self) -> crate::Result<Vec<u8>> {
+        let batch = self.to_record_batch()?;
+        let mut buf = Vec::new();
+        {
+            let mut writer = StreamWriter::try_new(&mut buf, &batch.schema())?;
+            writer.write(&batch)?;
+            writer.finish()?;
+        }
+        Ok(buf)
+    }
+}
+
+fn infer_column_type(cells: &[Option<&Variant>]) -> DataType {
+    let mut data_type: Option<DataType> = None;
+    for cell in cells.iter().flatten() {
+        let cell_type = match cell {
+            Variant::Bool(_) => DataType::Boolean,
+            Variant::Number(n) if n.is_i64() => DataType::Int64,
+            Variant::Number(_) => DataType::Float64,
+            _ => return DataType::Utf8,
+        };
+        data_type = match (data_type, cell_type) {
+            (None, t) => Some(t),
+            (Some(a), b) if a == b => Some(a),
+            (Some(DataType::Int64), DataType::Float64) | (Some(DataType::Float64), DataType::Int64) => {
+                Some(DataType::Float64)
+            }
+            _ => return DataType::Utf8,
+        };
+    }
+    data_type.unwrap_or(DataType::Utf8)
+}
+
+fn cell_to_string(cell: &Variant) -> String {
+    match cell {
+        Variant::String(s) => s.clone(),
+        _ => serde_json::Value::from(cell).to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::Array;
+    use serde_json::json;
+
+    #[test]
+    fn array_of_objects_should_be_converted_into_columns() {
+        let var = Variant::deserialize(json!([
+            {"name": "a", "count": 1, "value": 1.5, "ok": true},
+            {"name": "b", "count": 2, "value": 2},
+            {"name": "c", "value": null, "ok": false, "extra": {"x": 1}}
+        ]))
+        .unwrap();
+
+        let batch = var.to_record_batch().unwrap();
+        assert_eq!(batch.num_rows(), 3);
+        assert_eq!(batch.num_columns(), 5);
+
+        let schema = batch.schema();
+        let count_index = schema.index_of("count").unwrap();
+        assert_eq!(schema.field(count_index).data_type(), &DataType::Int64);
+        let count = batch.column(count_index).as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(count.value(0), 1);
+        assert_eq!(count.value(1), 2);
+        assert!(count.is_null(2));
+
+        let value_index = schema.index_of("value").unwrap();
+        let value = batch.column(value_index).as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(value.value(0), 1.5);
+        assert_eq!(value.value(1), 2.0);
+        assert!(value.is_null(2));
+
+        let ok = batch.column(schema.index_of("ok").unwrap()).as_any().downcast_ref::<BooleanArray>().unwrap();
+        assert!(ok.value(0));
+        assert!(ok.is_null(1));
+
+        let extra = batch.column(schema.index_of("extra").unwrap()).as_any().downcast_ref::<StringArray>().unwrap();
+        assert!(extra.is_null(0));
+        assert_eq!(extra.value(2), r#"{"x":1}"#);
+    }
+
+    #[test]
+    fn arrow_ipc_should_be_read_back() {
+        let var = Variant::deserialize(json!([{"name": "a"}, {"name": "b"}])).unwrap();
+        let bytes = var.to_arrow_ipc().unwrap();
+
+        let reader = arrow::ipc::reader::StreamReader::try_new(std::io::Cursor::new(bytes), None).unwrap();
+        let batches: Vec<RecordBatch> = reader.collect::<Result<_, _>>().unwrap();
+        assert_eq!(batches.len(), 1);
+        let names = batches[0].column(0).as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(names.value(0), "a");
+        assert_eq!(names.value(1), "b");
+    }
+
+    #[test]
+    fn non_array_should_be_rejected() {
+        assert!(Variant::from("foo").to_record_batch().is_err());
+    }
+}
@@ -176,3 +176,25 @@ impl From<&serde_json::Value> for Variant {
         }
     }
 }
+
+/// Converts the variant into a JSON value directly, with the same representation as its `Serialize` implementation.
+impl From<&Variant> for serde_json::Value {
+    fn from(var: &Variant) -> Self {
+        match var {
+            Variant::Null => serde_json::Value::Null,
+            Variant::Number(n) => serde_json::Value::Number(n.clone()),
+            Variant::String(s) => serde_json::Value::String(s.clone()),
+            Variant::Bool(b) => serde_json::Value::Bool(*b),
+            Variant::Bytes(bytes) => serde_json::Value::Array(bytes.iter().map(|x| (*x).into()).collect()),
+            Variant::Regexp(re) => serde_json::Value::String(re.as_str().to_string()),
+            Variant::Date(t) => {
+                let millis = t.duration_since(UNIX_EPOCH).map(|x| x.as_millis() as u64).unwrap_or(0);
+                serde_json::Value::from(millis)
+            }
+            Variant::Array(items) => serde_json::Value::Array(items.iter().map(serde_json::Value::from).collect()),
+            Variant::Object(map) => {
+                serde_json::Value::Object(map.iter().map(|(k, v)| (k.clone(), serde_json::Value::from(v))).collect())
+            }
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use std::ops::Deref;
+use std::sync::Arc;
+
+use super::*;
+
+/// A copy-on-write `Variant` shared by `Arc`.
+///
+/// Cloning a `CowVariant` only bumps the reference count, so the readers share one value. The first mutation of a
+/// shared value clones it, so the writer gets its private copy and the other holders never see the change.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CowVariant {
+    inner: Arc<Variant>,
+}
+
+impl CowVariant {
+    pub fn new(value: Variant) -> Self {
+        Self { inner: Arc::new(value) }
+    }
+
+    /// Gets a mutable reference of the value, the value will be cloned if it is shared with others.
+    pub fn make_mut(&mut self) -> &mut Variant {
+        Arc::make_mut(&mut self.inner)
+    }
+
+    /// Unwraps the value, it will be cloned if it is shared with others.
+    pub fn into_inner(self) -> Variant {
+        Arc::try_unwrap(self.inner).unwrap_or_else(|shared| shared.as_ref().clone())
+    }
+
+    /// Returns `true` if the value is shared, the next mutation will clone it.
+    pub fn is_shared(&self) -> bool {
+        Arc::strong_count(&self.inner) > 1
+    }
+
+    pub fn ptr_eq(&self, other: &CowVariant) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl Deref for CowVariant {
+    type Target = Variant;
+
+    fn deref(&self) -> &Variant {
+        &self.inner
+    }
+}
+
+impl AsRef<Variant> for CowVariant {
+    fn as_ref(&self) -> &Variant {
+        &self.inner
+    }
+}
+
+impl From<Variant> for CowVariant {
+    fn from(value: Variant) -> Self {
+        Self::new(value)
+    }
+}
+
+impl From<Arc<Variant>> for CowVariant {
+    fn from(inner: Arc<Variant>) -> Self {
+        Self { inner }
+    }
+}
+
+impl serde::Serialize for CowVariant {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.inner.serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn cow_variant_should_share_between_readers() {
+        let original = CowVariant::new(Variant::deserialize(json!({"payload": {"count": 1}})).unwrap());
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let reader = original.clone();
+                std::thread::spawn(move || {
+                    assert!(reader.is_shared());
+                    reader.get_nav("payload.count", &[]).cloned()
+                })
+            })
+            .collect();
+        for h in handles {
+            assert_eq!(h.join().unwrap(), Some(Variant::from(1)));
+        }
+        assert!(!original.is_shared());
+    }
+
+    #[test]
+    fn cow_variant_writer_should_get_private_copy() {
+        let reader = CowVariant::new(Variant::deserialize(json!({"payload": "foo"})).unwrap());
+        let mut writer = reader.clone();
+        assert!(writer.ptr_eq(&reader));
+
+        writer.make_mut().as_object_mut().unwrap().insert("payload".into(), Variant::from("bar"));
+        assert!(!writer.ptr_eq(&reader));
+        assert_eq!(reader.as_object().unwrap()["payload"], Variant::from("foo"));
+        assert_eq!(writer.as_object().unwrap()["payload"], Variant::from("bar"));
+
+        // The unshared value is mutated in place
+        let before = writer.as_ref() as *const Variant;
+        writer.make_mut().as_object_mut().unwrap().insert("topic".into(), Variant::from("t"));
+        assert_eq!(before, writer.as_ref() as *const Variant);
+        assert_eq!(writer.into_inner().as_object().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,253 @@
+use super::*;
+
+/// How many missing array items `from_flat_map()` fills besides the items of the keys, so a key like `a[99999999999]`
+/// cannot allocate a huge array
+const MAX_FLAT_HOLES: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum FlatSegment {
+    Property(String),
+    Index(usize),
+}
+
+impl Variant {
+    /// Flattens the nested objects and arrays into a map from the paths like `a.b[0]` to the leaf values.
+    ///
+    /// Property names that are not identifiers are quoted, e.g. `a["b.c"]`, and the empty objects and arrays are
+    /// kept as leaves, so `from_flat_map()` can always rebuild the original value. A value that is neither an object
+    /// nor an array is flattened into a single entry with an empty key.
+    pub fn to_flat_map(&self) -> VariantObjectMap {
+        let mut map = VariantObjectMap::new();
+        match self {
+            Variant::Object(obj) if !obj.is_empty() => self.flatten_into("", &mut map),
+            Variant::Array(arr) if !arr.is_empty() => self.flatten_into("", &mut map),
+            Variant::Object(_) => {}
+            _ => {
+                map.insert(String::new(), self.clone());
+            }
+        }
+        map
+    }
+
+    /// Rebuilds the nested value from the map generated by `to_flat_map()`, the missing array items are filled with
+    /// `null`.
+    ///
+    /// Every array item generated by `to_flat_map()` has a key, so the arrays can have as many items as the keys, and
+    /// up to `MAX_FLAT_HOLES` missing items in total.
+    pub fn from_flat_map(map: &VariantObjectMap) -> crate::Result<Variant> {
+        let mut root = Variant::Null;
+        let mut items_budget = map.len() + MAX_FLAT_HOLES;
+        for (key, value) in map.iter() {
+            let segs = parse_flat_key(key)?;
+            insert_flat(&mut root, &segs, value.clone(), &mut items_budget)
+                .with_context(|| format!("Failed to unflatten the key: '{}'", key))?;
+        }
+        if map.is_empty() {
+            root = Variant::empty_object();
+        }
+        Ok(root)
+    }
+
+    fn flatten_into(&self, prefix: &str, map: &mut VariantObjectMap) {
+        match self {
+            Variant::Object(obj) if !obj.is_empty() => {
+                for (k, v) in obj.iter() {
+                    v.flatten_into(&join_flat_property(prefix, k), map);
+                }
+            }
+            Variant::Array(arr) if !arr.is_empty() => {
+                for (i, v) in arr.iter().enumerate() {
+                    v.flatten_into(&format!("{}[{}]", prefix, i), map);
+                }
+            }
+            _ => {
+                map.insert(prefix.to_string(), self.clone());
+            }
+        }
+    }
+}
+
+fn is_flat_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_alphabetic() || c == '_' || c == '$' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+}
+
+fn join_flat_property(prefix: &str, name: &str) -> String {
+    if is_flat_identifier(name) {
+        if prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}.{}", prefix, name)
+        }
+    } else {
+        let escaped = name.replace('\\', "\\\\").replace('"', "\\\"");
+        format!("{}[\"{}\"]", prefix, escaped)
+    }
+}
+
+fn parse_flat_key(key: &str) -> crate::Result<Vec<FlatSegment>> {
+    let bad_key = || EdgelinkError::BadArgument("key");
+    let mut segs = Vec::new();
+    let mut chars = key.chars().peekable();
+    let mut expect_property = !key.starts_with('[') && !key.is_empty();
+    loop {
+        if expect_property {
+            let mut name = String::new();
+            while let Some(c) = chars.next_if(|c| *c != '.' && *c != '[') {
+                name.push(c);
+            }
+            if name.is_empty() {
+                return Err(bad_key()).with_context(|| format!("Empty property name in the key: '{}'", key));
+            }
+            segs.push(FlatSegment::Property(name));
+            expect_property = false;
+        }
+        match chars.next() {
+            None => break,
+            Some('.') => expect_property = true,
+            Some('[') if chars.next_if_eq(&'"').is_some() => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\\') => name.push(chars.next().ok_or_else(bad_key)?),
+                        Some('"') => break,
+                        Some(c) => name.push(c),
+                        None => return Err(bad_key()).with_context(|| format!("Unclosed quote in the key: '{}'", key)),
+                    }
+                }
+                chars.next_if_eq(&']').ok_or_else(bad_key)?;
+                segs.push(FlatSegment::Property(name));
+            }
+            Some('[') => {
+                let mut digits = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+                    digits.push(c);
+                }
+                chars.next_if_eq(&']').ok_or_else(bad_key)?;
+                let index = digits.parse::<usize>().with_context(|| format!("Bad index in the key: '{}'", key))?;
+                segs.push(FlatSegment::Index(index));
+            }
+            Some(c) => return Err(bad_key()).with_context(|| format!("Unexpected '{}' in the key: '{}'", c, key)),
+        }
+    }
+    Ok(segs)
+}
+
+fn insert_flat(
+    target: &mut Variant,
+    segs: &[FlatSegment],
+    value: Variant,
+    items_budget: &mut usize,
+) -> crate::Result<()> {
+    let (first, rest) = match segs.split_first() {
+        Some(x) => x,
+        None if target.is_null() => {
+            *target = value;
+            return Ok(());
+        }
+        None => return Err(EdgelinkError::InvalidOperation("The value has been set by another key".into()).into()),
+    };
+    match first {
+        FlatSegment::Property(name) => {
+            if target.is_null() {
+                *target = Variant::empty_object();
+            }
+            let obj = target.as_object_mut().ok_or(EdgelinkError::InvalidOperation(format!(
+                "Cannot set the property '{}' of a non-object",
+                name
+            )))?;
+            insert_flat(obj.entry(name.clone()).or_insert(Variant::Null), rest, value, items_budget)
+        }
+        FlatSegment::Index(index) => {
+            if target.is_null() {
+                *target = Variant::empty_array();
+            }
+            let arr = target
+                .as_array_mut()
+                .ok_or(EdgelinkError::InvalidOperation(format!("Cannot set the item [{}] of a non-array", index)))?;
+            if arr.len() <= *index {
+                // The item of the index is added besides the missing ones
+                let missing = *index - arr.len();
+                if missing >= *items_budget {
+                    return Err(EdgelinkError::OutOfRange)
+                        .with_context(|| format!("The index [{}] is too large for the flat map", index));
+                }
+                *items_budget -= missing + 1;
+                arr.resize(*index + 1, Variant::Null);
+            }
+            insert_flat(&mut arr[*index], rest, value, items_budget)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_variant_should_be_flattened_with_dotted_keys() {
+        let nested = Variant::deserialize(json!({
+            "a": {"b": [10, {"c": true}], "d": null},
+            "e": "str",
+            "empty": {"arr": [], "obj": {}}
+        }))
+        .unwrap();
+        let flat = nested.to_flat_map();
+        let expected = Variant::deserialize(json!({
+            "a.b[0]": 10,
+            "a.b[1].c": true,
+            "a.d": null,
+            "e": "str",
+            "empty.arr": [],
+            "empty.obj": {}
+        }))
+        .unwrap();
+        assert_eq!(Variant::Object(flat.clone()), expected);
+        assert_eq!(Variant::from_flat_map(&flat).unwrap(), nested);
+    }
+
+    #[test]
+    fn test_flat_map_should_round_trip_special_keys() {
+        let nested = Variant::deserialize(json!({
+            "x.y": {"": 1, "say \"hi\"": [2, [3]]},
+            "0abc": {"a[0]": "v", "back\\slash": null},
+            "$ok_1": {}
+        }))
+        .unwrap();
+        let flat = nested.to_flat_map();
+        assert_eq!(flat.get("[\"x.y\"][\"say \\\"hi\\\"\"][1][0]"), Some(&Variant::from(3)));
+        assert_eq!(flat.get("[\"0abc\"][\"a[0]\"]"), Some(&Variant::from("v")));
+        assert_eq!(Variant::from_flat_map(&flat).unwrap(), nested);
+
+        let array = Variant::deserialize(json!([1, {"a": [true]}])).unwrap();
+        assert_eq!(Variant::from_flat_map(&array.to_flat_map()).unwrap(), array);
+        assert_eq!(Variant::from_flat_map(&Variant::from(1).to_flat_map()).unwrap(), Variant::from(1));
+        assert_eq!(Variant::from_flat_map(&Variant::empty_object().to_flat_map()).unwrap(), Variant::empty_object());
+    }
+
+    #[test]
+    fn test_from_flat_map_should_fill_holes_and_reject_conflicts() {
+        let flat = Variant::deserialize(json!({"a[2]": 1, "a[10]": 2})).unwrap().into_object().unwrap();
+        let nested = Variant::from_flat_map(&flat).unwrap();
+        assert_eq!(nested.get_nav("a", &[]).unwrap().len(), 11);
+        assert_eq!(nested.get_nav("a[2]", &[]), Some(&Variant::from(1)));
+        assert_eq!(nested.get_nav("a[0]", &[]), Some(&Variant::Null));
+
+        let huge = Variant::deserialize(json!({"a[99999999999]": 1})).unwrap().into_object().unwrap();
+        assert!(Variant::from_flat_map(&huge).is_err());
+        let max = Variant::deserialize(json!({"a[18446744073709551615]": 1})).unwrap().into_object().unwrap();
+        assert!(Variant::from_flat_map(&max).is_err());
+        let holes = Variant::deserialize(json!({"a[1000]": 1, "b[1000]": 2})).unwrap().into_object().unwrap();
+        assert!(Variant::from_flat_map(&holes).is_err());
+
+        let conflicted = Variant::deserialize(json!({"a": 1, "a.b": 2})).unwrap().into_object().unwrap();
+        assert!(Variant::from_flat_map(&conflicted).is_err());
+        let bad_key = Variant::deserialize(json!({"a..b": 1})).unwrap().into_object().unwrap();
+        assert!(Variant::from_flat_map(&bad_key).is_err());
+    }
+}
@@ -111,12 +111,16 @@ impl<'js> js::IntoJs<'js> for Variant {
             Variant::Bytes(bytes) => Ok(js::ArrayBuffer::new(ctx.clone(), bytes)?.into_value()),
 
             Variant::Number(num) => {
-                if let Some(f) = num.as_f64() {
-                    f.into_js(ctx)
-                } else if let Some(i) = num.as_i64() {
+                // Integers must be checked first, `as_f64()` always succeeds and would turn `2` into `2.0`
+                if let Some(i) = num.as_i64() {
                     i.into_js(ctx)
                 } else if let Some(u) = num.as_u64() {
                     u.into_js(ctx)
+                } else if let Some(f) = num.as_f64() {
+                    // `Value::new_float()` turns the integral floats like `2.0` into the JS integers, the value is
+                    // tagged as a float directly, which is what the arithmetic of QuickJS produces for `1.5 * 4` too.
+                    // Safety: a float is a plain value without any reference counted to the context
+                    Ok(unsafe { js::Value::from_raw(ctx.clone(), js::qjs::__JS_NewFloat64(f)) })
                 } else {
                     unreachable!();
                 }
@@ -124,7 +128,14 @@ impl<'js> js::IntoJs<'js> for Variant {
 
             Variant::Null => Ok(js::Value::new_null(ctx.clone())),
 
-            Variant::Object(map) => map.into_js(ctx),
+            Variant::Object(map) => {
+                // The properties are defined in the order of the map, like the objects parsed by JavaScript
+                let obj = js::Object::new(ctx.clone())?;
+                for (key, value) in map.into_iter() {
+                    obj.set(key, value)?;
+                }
+                Ok(obj.into_value())
+            }
 
             Variant::String(s) => s.into_js(ctx),
 
@@ -151,7 +162,7 @@ impl<'js> js::IntoJs<'js> for UndefinableVariant {
 
 #[cfg(test)]
 mod tests {
-    use js::IntoJs;
+    use js::{FromJs, IntoJs};
     use serde_json::*;
 
     use super::*;
@@ -184,4 +195,28 @@ mod tests {
             assert_eq!(v, vec![Variant::from(1), Variant::from(2), Variant::from(3)]);
         });
     }
+
+    #[test]
+    fn variant_numbers_should_keep_int_or_float_through_js() {
+        let js_rt = js::Runtime::new().unwrap();
+        let ctx = js::Context::full(&js_rt).unwrap();
+
+        ctx.with(|ctx| {
+            let int_value = Variant::from(2).into_js(&ctx).unwrap();
+            assert!(int_value.is_int());
+            let int_value = Variant::from_js(&ctx, int_value).unwrap();
+            assert!(int_value.is_i64());
+            assert_eq!(int_value.as_i64(), Some(2));
+
+            let float_value = Variant::from(2.0).into_js(&ctx).unwrap();
+            let float_value = Variant::from_js(&ctx, float_value).unwrap();
+            assert!(float_value.is_f64());
+            assert_eq!(float_value.as_f64(), Some(2.0));
+
+            let v: Variant = ctx.eval("1.5 + 1").unwrap();
+            assert!(v.is_f64());
+            let v: Variant = ctx.eval("1 + 1").unwrap();
+            assert!(v.is_i64());
+        });
+    }
 }
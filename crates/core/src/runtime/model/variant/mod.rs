@@ -1,8 +1,8 @@
 use core::fmt::{self, Debug};
 use std::borrow::Cow;
-use std::collections::BTreeMap;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use indexmap::IndexMap;
 use regex::Regex;
 use rquickjs::function::Constructor;
 use serde::ser::{SerializeMap, SerializeSeq};
@@ -17,13 +17,28 @@ use super::propex::PropexSegment;
 #[cfg(feature = "js")]
 mod js_support;
 
+#[cfg(feature = "arrow")]
+mod columnar;
+
+#[cfg(feature = "protobuf")]
+mod protobuf;
+
 mod array;
+mod canonical;
 mod converts;
+mod cow;
+mod flat;
 mod map;
+mod net;
+mod patch;
+mod pretty;
+mod schema;
 mod ser;
 
 pub use self::array::*;
+pub use self::cow::*;
 pub use self::map::*;
+pub use self::net::*;
 
 #[derive(Debug, Clone)]
 pub enum PropexEnv<'a> {
@@ -49,7 +64,6 @@ pub struct UndefinableVariant(pub Option<Variant>);
 /// # Examples
 ///
 /// ```rust
-/// use std::collections::BTreeMap;
 /// use edgelink_core::runtime::model::Variant;
 ///
 /// // Create a null variant
@@ -277,6 +291,43 @@ impl Variant {
         }
     }
 
+    /// Coerces a number or a numeric string like `"42"`, `"-3.14"` or `"1e3"` into a number.
+    ///
+    /// Strings with leading zeros like `"007"`, `"NaN"` and `"inf"` are not numbers, so the IDs and codes are kept.
+    pub fn coerce_number(&self) -> Option<Variant> {
+        match self {
+            Variant::Number(_) => Some(self.clone()),
+            Variant::String(s) => {
+                let s = s.trim();
+                let digits = s.strip_prefix('-').unwrap_or(s);
+                let has_leading_zero = digits.len() > 1 && digits.starts_with('0') && !digits.starts_with("0.");
+                if digits.is_empty()
+                    || has_leading_zero
+                    || !digits.starts_with(|c: char| c.is_ascii_digit() || c == '.')
+                    || !digits.chars().all(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '+' | '-'))
+                {
+                    return None;
+                }
+                if let Ok(i) = s.parse::<i64>() {
+                    Some(Variant::from(i))
+                } else {
+                    s.parse::<f64>().ok().filter(|x| x.is_finite()).map(Variant::from)
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Coerces a boolean or a string `"true"`/`"false"` (case-insensitive) into a boolean.
+    pub fn coerce_bool(&self) -> Option<bool> {
+        match self {
+            Variant::Bool(b) => Some(*b),
+            Variant::String(s) if s.trim().eq_ignore_ascii_case("true") => Some(true),
+            Variant::String(s) if s.trim().eq_ignore_ascii_case("false") => Some(false),
+            _ => None,
+        }
+    }
+
     pub fn as_u8(&self) -> Option<u8> {
         match self {
             Variant::Number(number) => number.as_u64().map(|x| x as u8), // FIXME
@@ -468,6 +519,11 @@ impl Variant {
         self.get_segs(&prop_segs)
     }
 
+    /// Returns `true` if the path of the expression exists, even if its value is `Null`.
+    pub fn has_nav(&self, expr: &str, eval_env: &[PropexEnv]) -> bool {
+        self.get_nav(expr, eval_env).is_some()
+    }
+
     pub fn get_nav_mut(&mut self, expr: &str, eval_env: &[PropexEnv]) -> Option<&mut Variant> {
         let mut prop_segs = propex::parse(expr).ok()?;
         self.expand_sesg_property(&mut prop_segs, eval_env).ok()?;
@@ -597,6 +653,33 @@ impl Variant {
         self.set_segs_property(&prop_segs, value, create_missing)
     }
 
+    /// Removes the property of the expression entirely rather than setting it to `Null`, and returns the removed value.
+    ///
+    /// The terminal key is removed from its `Object`, and the indexed item is spliced out of its `Array` or `Bytes`,
+    /// so the following items are shifted. Returns `Ok(None)` if the path does not exist.
+    pub fn delete_nav(&mut self, expr: &str, eval_env: &[PropexEnv]) -> crate::Result<Option<Variant>> {
+        let mut prop_segs = propex::parse(expr)?;
+        self.expand_sesg_property(&mut prop_segs, eval_env)?;
+        let (last_seg, parent_segs) = match prop_segs.split_last() {
+            Some(x) => x,
+            None => return Err(EdgelinkError::BadArgument("expr").into()),
+        };
+        let parent = match self.get_segs_mut(parent_segs) {
+            Some(parent) => parent,
+            None => return Ok(None),
+        };
+        let removed = match (parent, last_seg) {
+            (Variant::Object(map), PropexSegment::Property(prop)) => map.shift_remove(prop.as_ref()),
+            (Variant::Object(map), PropexSegment::Index(index)) => map.shift_remove(&index.to_string()),
+            (Variant::Array(arr), PropexSegment::Index(index)) if *index < arr.len() => Some(arr.remove(*index)),
+            (Variant::Bytes(bytes), PropexSegment::Index(index)) if *index < bytes.len() => {
+                Some(Variant::from(bytes.remove(*index) as u32))
+            }
+            _ => None,
+        };
+        Ok(removed)
+    }
+
     pub fn take(&mut self) -> Variant {
         core::mem::replace(self, Variant::Null)
     }
@@ -736,6 +819,23 @@ mod tests {
         assert_ne!(value1, value2);
     }
 
+    #[test]
+    fn variant_coerce_number_and_bool() {
+        assert_eq!(Variant::from("42").coerce_number(), Some(Variant::from(42)));
+        assert_eq!(Variant::from(" -3.14 ").coerce_number(), Some(Variant::from(-3.14)));
+        assert_eq!(Variant::from("1e3").coerce_number(), Some(Variant::from(1000.0)));
+        assert_eq!(Variant::from("0.5").coerce_number(), Some(Variant::from(0.5)));
+        assert_eq!(Variant::from("007").coerce_number(), None);
+        assert_eq!(Variant::from("NaN").coerce_number(), None);
+        assert_eq!(Variant::from("inf").coerce_number(), None);
+        assert_eq!(Variant::from("12abc").coerce_number(), None);
+        assert_eq!(Variant::from("").coerce_number(), None);
+
+        assert_eq!(Variant::from("TRUE").coerce_bool(), Some(true));
+        assert_eq!(Variant::from("false").coerce_bool(), Some(false));
+        assert_eq!(Variant::from("yes").coerce_bool(), None);
+    }
+
     #[test]
     fn variant_propex_readonly_accessing_should_be_ok() {
         let obj1 = Variant::from([
@@ -814,6 +914,46 @@ mod tests {
         assert_eq!(res, 444);
     }
 
+    #[test]
+    fn variant_delete_nav_should_remove_deep_properties() {
+        let mut var = Variant::deserialize(json!({"a": {"b": {"c": 1, "d": 2}}, "e": 3})).unwrap();
+
+        assert_eq!(var.delete_nav("a.b.c", &[]).unwrap(), Some(Variant::from(1)));
+        assert_eq!(var, Variant::deserialize(json!({"a": {"b": {"d": 2}}, "e": 3})).unwrap());
+
+        assert_eq!(var.delete_nav("a['b']", &[]).unwrap(), Some(Variant::deserialize(json!({"d": 2})).unwrap()));
+        assert_eq!(var.delete_nav("e", &[]).unwrap(), Some(Variant::from(3)));
+        assert_eq!(var, Variant::deserialize(json!({"a": {}})).unwrap());
+    }
+
+    #[test]
+    fn variant_delete_nav_should_splice_arrays_and_bytes() {
+        let mut var = Variant::deserialize(json!({"arr": [10, 11, 12], "key": 1})).unwrap();
+        var.as_object_mut().unwrap().insert("buf".into(), Variant::Bytes(vec![1, 2, 3]));
+
+        assert_eq!(var.delete_nav("arr[1]", &[]).unwrap(), Some(Variant::from(11)));
+        assert_eq!(var.get_nav("arr", &[]).unwrap(), &Variant::deserialize(json!([10, 12])).unwrap());
+
+        let this = [PropexEnv::ThisRef("this")];
+        assert_eq!(var.delete_nav("buf[this.key]", &this).unwrap(), Some(Variant::from(2)));
+        assert_eq!(var.get_nav("buf", &[]).unwrap(), &Variant::Bytes(vec![1, 3]));
+    }
+
+    #[test]
+    fn variant_delete_nav_should_ignore_non_existent_paths() {
+        let mut var = Variant::deserialize(json!({"a": {"b": 1}, "arr": [1], "s": "text"})).unwrap();
+        let expected = var.clone();
+
+        assert_eq!(var.delete_nav("nope", &[]).unwrap(), None);
+        assert_eq!(var.delete_nav("a.nope", &[]).unwrap(), None);
+        assert_eq!(var.delete_nav("a.nope.deep", &[]).unwrap(), None);
+        assert_eq!(var.delete_nav("arr[5]", &[]).unwrap(), None);
+        assert_eq!(var.delete_nav("s.length", &[]).unwrap(), None);
+        assert_eq!(var, expected);
+
+        assert!(var.delete_nav("a..b", &[]).is_err());
+    }
+
     #[test]
     fn variant_can_serialize_to_json_value() {
         let org = Variant::Object(VariantObjectMap::from([
@@ -0,0 +1,103 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use super::*;
+
+impl Variant {
+    /// Gets the IP address from a string like `"192.168.1.1"`, `"::1"` or `"[::1]"`, or from a 4 or 16 bytes buffer.
+    pub fn as_ip_addr(&self) -> Option<IpAddr> {
+        match self {
+            Variant::String(s) => {
+                let s = s.trim();
+                let s = s.strip_prefix('[').and_then(|x| x.strip_suffix(']')).unwrap_or(s);
+                s.parse::<IpAddr>().ok()
+            }
+            Variant::Bytes(bytes) => match bytes.len() {
+                4 => <[u8; 4]>::try_from(bytes.as_slice()).ok().map(|x| IpAddr::V4(Ipv4Addr::from(x))),
+                16 => <[u8; 16]>::try_from(bytes.as_slice()).ok().map(|x| IpAddr::V6(Ipv6Addr::from(x))),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Gets the port number from an integer or a numeric string, `0` is not a valid port to connect.
+    pub fn as_port(&self) -> Option<u16> {
+        let port = match self {
+            Variant::Number(n) => n.as_u64(),
+            Variant::String(s) => s.trim().parse::<u64>().ok(),
+            _ => None,
+        }?;
+        u16::try_from(port).ok().filter(|x| is_valid_port(*x))
+    }
+
+    /// Gets the socket address from a string like `"127.0.0.1:8080"` or `"[::1]:8080"`.
+    pub fn as_socket_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Variant::String(s) => s.trim().parse::<SocketAddr>().ok().filter(|x| is_valid_port(x.port())),
+            _ => None,
+        }
+    }
+}
+
+/// Returns `true` if the port is able to be connected to, the port `0` only means "any port" when binding.
+pub fn is_valid_port(port: u16) -> bool {
+    port != 0
+}
+
+impl From<IpAddr> for Variant {
+    fn from(addr: IpAddr) -> Self {
+        Variant::String(addr.to_string())
+    }
+}
+
+impl From<Ipv4Addr> for Variant {
+    fn from(addr: Ipv4Addr) -> Self {
+        Variant::String(addr.to_string())
+    }
+}
+
+impl From<Ipv6Addr> for Variant {
+    fn from(addr: Ipv6Addr) -> Self {
+        Variant::String(addr.to_string())
+    }
+}
+
+impl From<SocketAddr> for Variant {
+    fn from(addr: SocketAddr) -> Self {
+        Variant::String(addr.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ip_addr_should_round_trip() {
+        for s in ["192.168.1.100", "0.0.0.0", "::1", "fe80::1ff:fe23:4567:890a", "2001:db8::8a2e:370:7334"] {
+            let ip: IpAddr = s.parse().unwrap();
+            let var = Variant::from(ip);
+            assert_eq!(var, Variant::from(s));
+            assert_eq!(var.as_ip_addr(), Some(ip));
+        }
+        assert_eq!(Variant::from("[::1]").as_ip_addr(), Some(IpAddr::V6(Ipv6Addr::LOCALHOST)));
+        assert_eq!(Variant::Bytes(vec![127, 0, 0, 1]).as_ip_addr(), Some(IpAddr::V4(Ipv4Addr::LOCALHOST)));
+        assert_eq!(Variant::from("localhost").as_ip_addr(), None);
+        assert_eq!(Variant::from("192.168.1.256").as_ip_addr(), None);
+    }
+
+    #[test]
+    fn port_should_be_validated() {
+        assert_eq!(Variant::from(8080).as_port(), Some(8080));
+        assert_eq!(Variant::from(" 65535 ").as_port(), Some(65535));
+        assert_eq!(Variant::from(0).as_port(), None);
+        assert_eq!(Variant::from(65536).as_port(), None);
+        assert_eq!(Variant::from(-1).as_port(), None);
+        assert_eq!(Variant::from(80.5).as_port(), None);
+
+        let addr = Variant::from("[::1]:1883").as_socket_addr().unwrap();
+        assert_eq!(addr.ip(), IpAddr::V6(Ipv6Addr::LOCALHOST));
+        assert_eq!(Variant::from(addr), Variant::from("[::1]:1883"));
+        assert!(Variant::from("127.0.0.1:0").as_socket_addr().is_none());
+    }
+}
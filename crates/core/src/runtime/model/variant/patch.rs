@@ -0,0 +1,260 @@
+use super::*;
+
+impl Variant {
+    /// Resolves the RFC6901 JSON Pointer like `/a/0/b`, the empty pointer refers to the whole value.
+    pub fn pointer(&self, pointer: &str) -> Option<&Variant> {
+        let tokens = parse_pointer(pointer).ok()?;
+        let mut target = self;
+        for token in tokens.iter() {
+            target = match target {
+                Variant::Object(map) => map.get(token)?,
+                Variant::Array(arr) => arr.get(parse_array_index(token)?)?,
+                _ => return None,
+            };
+        }
+        Some(target)
+    }
+
+    pub fn pointer_mut(&mut self, pointer: &str) -> Option<&mut Variant> {
+        let tokens = parse_pointer(pointer).ok()?;
+        let mut target = self;
+        for token in tokens.iter() {
+            target = match target {
+                Variant::Object(map) => map.get_mut(token)?,
+                Variant::Array(arr) => arr.get_mut(parse_array_index(token)?)?,
+                _ => return None,
+            };
+        }
+        Some(target)
+    }
+
+    /// Applies the RFC6902 JSON Patch, an array of the `add`, `remove`, `replace`, `move`, `copy` and `test`
+    /// operations.
+    ///
+    /// The patch is atomic, the value is left untouched if any operation fails, including a failed `test`.
+    pub fn apply_json_patch(&mut self, patch: &Variant) -> crate::Result<()> {
+        let ops = patch
+            .as_array()
+            .ok_or(EdgelinkError::BadArgument("patch"))
+            .context("The JSON Patch must be an array of operations")?;
+        let mut patched = self.clone();
+        for (i, op) in ops.iter().enumerate() {
+            patched.apply_patch_operation(op).map_err(|e| {
+                EdgelinkError::InvalidOperation(format!("The JSON Patch operation #{} failed: {:#}", i, e))
+            })?;
+        }
+        *self = patched;
+        Ok(())
+    }
+
+    fn apply_patch_operation(&mut self, op: &Variant) -> crate::Result<()> {
+        let path = patch_str_member(op, "path")?;
+        match patch_str_member(op, "op")? {
+            "add" => self.patch_add(path, patch_member(op, "value")?.clone()),
+            "remove" => self.patch_remove(path).map(|_| ()),
+            "replace" => {
+                let value = patch_member(op, "value")?.clone();
+                let target = self.pointer_mut(path).ok_or_else(|| path_not_found(path))?;
+                *target = value;
+                Ok(())
+            }
+            "move" => {
+                let from = patch_str_member(op, "from")?;
+                if path.starts_with(from) && path[from.len()..].starts_with('/') {
+                    return Err(EdgelinkError::InvalidOperation(format!(
+                        "Cannot move '{}' into its own child '{}'",
+                        from, path
+                    ))
+                    .into());
+                }
+                let value = self.patch_remove(from)?;
+                self.patch_add(path, value)
+            }
+            "copy" => {
+                let from = patch_str_member(op, "from")?;
+                let value = self.pointer(from).ok_or_else(|| path_not_found(from))?.clone();
+                self.patch_add(path, value)
+            }
+            "test" => {
+                let expected = patch_member(op, "value")?;
+                match self.pointer(path) {
+                    Some(actual) if patch_values_equal(actual, expected) => Ok(()),
+                    Some(actual) => Err(EdgelinkError::InvalidOperation(format!(
+                        "Test failed at '{}': expected {}, got: {}",
+                        path,
+                        serde_json::Value::from(expected),
+                        serde_json::Value::from(actual)
+                    ))
+                    .into()),
+                    None => Err(path_not_found(path).into()),
+                }
+            }
+            other => Err(EdgelinkError::NotSupported(format!("Unknown JSON Patch operation: '{}'", other)).into()),
+        }
+    }
+
+    fn patch_add(&mut self, path: &str, value: Variant) -> crate::Result<()> {
+        let (parent_path, token) = match split_pointer(path)? {
+            Some(x) => x,
+            None => {
+                *self = value;
+                return Ok(());
+            }
+        };
+        match self.pointer_mut(parent_path) {
+            Some(Variant::Object(map)) => {
+                map.insert(token, value);
+                Ok(())
+            }
+            Some(Variant::Array(arr)) => {
+                let index = if token == "-" { Some(arr.len()) } else { parse_array_index(&token) };
+                match index {
+                    Some(index) if index <= arr.len() => {
+                        arr.insert(index, value);
+                        Ok(())
+                    }
+                    _ => Err(EdgelinkError::OutOfRange).with_context(|| format!("Bad array index in '{}'", path)),
+                }
+            }
+            Some(_) => Err(EdgelinkError::InvalidOperation(format!(
+                "Cannot add '{}' to a value that is neither an object nor an array",
+                path
+            ))
+            .into()),
+            None => Err(path_not_found(parent_path).into()),
+        }
+    }
+
+    fn patch_remove(&mut self, path: &str) -> crate::Result<Variant> {
+        let (parent_path, token) =
+            split_pointer(path)?.ok_or(EdgelinkError::BadArgument("path")).context("Cannot remove the whole value")?;
+        let removed = match self.pointer_mut(parent_path) {
+            Some(Variant::Object(map)) => map.shift_remove(&token),
+            Some(Variant::Array(arr)) => match parse_array_index(&token) {
+                Some(index) if index < arr.len() => Some(arr.remove(index)),
+                _ => None,
+            },
+            _ => None,
+        };
+        removed.ok_or_else(|| path_not_found(path).into())
+    }
+}
+
+fn patch_member<'a>(op: &'a Variant, name: &'static str) -> crate::Result<&'a Variant> {
+    op.as_object()
+        .and_then(|x| x.get(name))
+        .ok_or(EdgelinkError::BadArgument(name))
+        .with_context(|| format!("Missing the `{}` member", name))
+}
+
+fn patch_str_member<'a>(op: &'a Variant, name: &'static str) -> crate::Result<&'a str> {
+    patch_member(op, name)?.as_str().ok_or(EdgelinkError::BadArgument(name)).context("Expected a string")
+}
+
+fn path_not_found(path: &str) -> EdgelinkError {
+    EdgelinkError::InvalidOperation(format!("The path '{}' does not exist", path))
+}
+
+/// Parses the pointer into the unescaped reference tokens.
+fn parse_pointer(pointer: &str) -> crate::Result<Vec<String>> {
+    if pointer.is_empty() {
+        return Ok(Vec::new());
+    }
+    let rest = pointer
+        .strip_prefix('/')
+        .ok_or(EdgelinkError::BadArgument("pointer"))
+        .with_context(|| format!("The JSON Pointer must start with '/': '{}'", pointer))?;
+    Ok(rest.split('/').map(|x| x.replace("~1", "/").replace("~0", "~")).collect())
+}
+
+/// Splits the pointer into the pointer of its parent and the last unescaped token, `None` for the whole value.
+fn split_pointer(pointer: &str) -> crate::Result<Option<(&str, String)>> {
+    parse_pointer(pointer)?;
+    Ok(pointer.rfind('/').map(|pos| (&pointer[..pos], pointer[pos + 1..].replace("~1", "/").replace("~0", "~"))))
+}
+
+/// The array index must be a decimal number without leading zeros.
+fn parse_array_index(token: &str) -> Option<usize> {
+    if token.is_empty() || (token.len() > 1 && token.starts_with('0')) || !token.bytes().all(|x| x.is_ascii_digit()) {
+        return None;
+    }
+    token.parse().ok()
+}
+
+/// The numbers are equal if they are numerically equal, as RFC6902 required.
+fn patch_values_equal(a: &Variant, b: &Variant) -> bool {
+    match (a, b) {
+        (Variant::Number(x), Variant::Number(y)) => x == y || x.as_f64() == y.as_f64(),
+        (Variant::Array(x), Variant::Array(y)) => {
+            x.len() == y.len() && x.iter().zip(y.iter()).all(|(x, y)| patch_values_equal(x, y))
+        }
+        (Variant::Object(x), Variant::Object(y)) => {
+            x.len() == y.len() && x.iter().all(|(k, v)| y.get(k).is_some_and(|yv| patch_values_equal(v, yv)))
+        }
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_json_pointer_should_be_resolved() {
+        let value = Variant::deserialize(json!({"a": [1, {"b/c": 2, "d~e": 3}], "": 4})).unwrap();
+        assert_eq!(value.pointer(""), Some(&value));
+        assert_eq!(value.pointer("/a/0"), Some(&Variant::from(1)));
+        assert_eq!(value.pointer("/a/1/b~1c"), Some(&Variant::from(2)));
+        assert_eq!(value.pointer("/a/1/d~0e"), Some(&Variant::from(3)));
+        assert_eq!(value.pointer("/"), Some(&Variant::from(4)));
+        assert_eq!(value.pointer("/a/01"), None);
+        assert_eq!(value.pointer("a"), None);
+    }
+
+    #[test]
+    fn test_json_patch_should_apply_all_operations() {
+        let mut value = Variant::deserialize(json!({"a": {"b": 1}, "arr": [1, 2], "old": "x"})).unwrap();
+        let patch = Variant::deserialize(json!([
+            {"op": "test", "path": "/a/b", "value": 1.0},
+            {"op": "add", "path": "/arr/1", "value": 10},
+            {"op": "add", "path": "/arr/-", "value": 20},
+            {"op": "replace", "path": "/a/b", "value": {"c": true}},
+            {"op": "move", "from": "/old", "path": "/new"},
+            {"op": "copy", "from": "/a/b", "path": "/copied"},
+            {"op": "remove", "path": "/arr/0"}
+        ]))
+        .unwrap();
+        value.apply_json_patch(&patch).unwrap();
+        let expected = Variant::deserialize(json!({
+            "a": {"b": {"c": true}},
+            "arr": [10, 2, 20],
+            "new": "x",
+            "copied": {"c": true}
+        }))
+        .unwrap();
+        assert_eq!(value, expected);
+    }
+
+    #[test]
+    fn test_failed_json_patch_should_leave_the_value_untouched() {
+        let orig = Variant::deserialize(json!({"a": 1})).unwrap();
+        let mut value = orig.clone();
+        let patch = Variant::deserialize(json!([
+            {"op": "add", "path": "/b", "value": 2},
+            {"op": "test", "path": "/a", "value": 2}
+        ]))
+        .unwrap();
+        let err = value.apply_json_patch(&patch).unwrap_err();
+        assert!(err.to_string().contains("#1"));
+        assert_eq!(value, orig);
+
+        let bad_ops = Variant::deserialize(json!([
+            {"op": "move", "from": "/a", "path": "/a/b"},
+            {"op": "remove", "path": "/missing"}
+        ]))
+        .unwrap();
+        assert!(value.apply_json_patch(&bad_ops).is_err());
+        assert_eq!(value, orig);
+    }
+}
@@ -0,0 +1,131 @@
+use std::fmt::Write;
+
+use super::*;
+
+const INDENT: &str = "  ";
+
+impl Variant {
+    /// Renders the variant into a human-readable, indented string for logging, which is not a valid JSON.
+    ///
+    /// Arrays and objects nested deeper than `max_depth` are collapsed into `[Array(n)]` and `[Object]`,
+    /// strings, buffers, arrays and objects longer than `max_len` are truncated with ellipses.
+    pub fn to_pretty_string(&self, max_depth: usize, max_len: usize) -> String {
+        let mut buf = String::new();
+        self.write_pretty(&mut buf, 0, max_depth, max_len);
+        buf
+    }
+
+    fn write_pretty(&self, buf: &mut String, depth: usize, max_depth: usize, max_len: usize) {
+        match self {
+            Variant::Null => buf.push_str("null"),
+            Variant::Number(n) => buf.push_str(&n.to_string()),
+            Variant::Bool(b) => buf.push_str(if *b { "true" } else { "false" }),
+            Variant::String(s) => {
+                if s.chars().count() > max_len {
+                    let truncated: String = s.chars().take(max_len).collect();
+                    let _ = write!(buf, "{:?}...", truncated);
+                } else {
+                    let _ = write!(buf, "{:?}", s);
+                }
+            }
+            Variant::Date(t) => {
+                let dt: chrono::DateTime<chrono::Utc> = (*t).into();
+                buf.push_str(&dt.to_rfc3339_opts(chrono::SecondsFormat::Millis, true));
+            }
+            Variant::Regexp(re) => {
+                let _ = write!(buf, "/{}/", re.as_str());
+            }
+            Variant::Bytes(bytes) => {
+                buf.push_str("<Buffer");
+                for b in bytes.iter().take(max_len) {
+                    let _ = write!(buf, " {:02x}", b);
+                }
+                if bytes.len() > max_len {
+                    buf.push_str(" ...");
+                }
+                buf.push('>');
+            }
+            Variant::Array(items) => {
+                if items.is_empty() {
+                    buf.push_str("[]");
+                } else if depth >= max_depth {
+                    let _ = write!(buf, "[Array({})]", items.len());
+                } else {
+                    buf.push_str("[\n");
+                    for item in items.iter().take(max_len) {
+                        push_indent(buf, depth + 1);
+                        item.write_pretty(buf, depth + 1, max_depth, max_len);
+                        buf.push_str(",\n");
+                    }
+                    if items.len() > max_len {
+                        push_indent(buf, depth + 1);
+                        let _ = writeln!(buf, "... {} more items", items.len() - max_len);
+                    }
+                    push_indent(buf, depth);
+                    buf.push(']');
+                }
+            }
+            Variant::Object(map) => {
+                if map.is_empty() {
+                    buf.push_str("{}");
+                } else if depth >= max_depth {
+                    buf.push_str("[Object]");
+                } else {
+                    buf.push_str("{\n");
+                    for (key, value) in map.iter().take(max_len) {
+                        push_indent(buf, depth + 1);
+                        let _ = write!(buf, "{}: ", key);
+                        value.write_pretty(buf, depth + 1, max_depth, max_len);
+                        buf.push_str(",\n");
+                    }
+                    if map.len() > max_len {
+                        push_indent(buf, depth + 1);
+                        let _ = writeln!(buf, "... {} more properties", map.len() - max_len);
+                    }
+                    push_indent(buf, depth);
+                    buf.push('}');
+                }
+            }
+        }
+    }
+}
+
+fn push_indent(buf: &mut String, depth: usize) {
+    for _ in 0..depth {
+        buf.push_str(INDENT);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn pretty_string_should_format_buffers() {
+        let var = Variant::Bytes(vec![0x01, 0x02, 0xab]);
+        assert_eq!(var.to_pretty_string(4, 10), "<Buffer 01 02 ab>");
+
+        let var = Variant::Bytes((0u8..20).collect());
+        assert_eq!(var.to_pretty_string(4, 3), "<Buffer 00 01 02 ...>");
+    }
+
+    #[test]
+    fn pretty_string_should_truncate_long_values() {
+        let var = Variant::from("hello world");
+        assert_eq!(var.to_pretty_string(4, 5), "\"hello\"...");
+
+        let var = Variant::deserialize(json!([1, 2, 3, 4, 5])).unwrap();
+        assert_eq!(var.to_pretty_string(4, 2), "[\n  1,\n  2,\n  ... 3 more items\n]");
+    }
+
+    #[test]
+    fn pretty_string_should_limit_depth() {
+        let var = Variant::deserialize(json!({"a": {"b": {"c": 1}}, "d": [1, [2]]})).unwrap();
+        assert_eq!(
+            var.to_pretty_string(2, 100),
+            "{\n  a: {\n    b: [Object],\n  },\n  d: [\n    1,\n    [Array(1)],\n  ],\n}"
+        );
+        assert_eq!(var.to_pretty_string(0, 100), "[Object]");
+    }
+}
@@ -0,0 +1,348 @@
+use std::collections::HashMap;
+
+use prost_reflect::{DynamicMessage, FieldDescriptor, Kind, MapKey, MessageDescriptor, Value};
+
+use super::*;
+
+impl Variant {
+    /// Builds a Protobuf message of the type `desc` from an object, the properties are matched to the fields by their
+    /// names or JSON names.
+    ///
+    /// Nested objects become nested messages, arrays become repeated fields and objects assigned to map fields become
+    /// maps. Enum values can be given by their names or numbers. `null` properties are left unset.
+    pub fn to_protobuf(&self, desc: &MessageDescriptor) -> crate::Result<DynamicMessage> {
+        object_to_message(self, desc, desc.full_name())
+    }
+
+    /// Encodes an object into the Protobuf wire format, see `to_protobuf()` for the mapping.
+    pub fn to_protobuf_bytes(&self, desc: &MessageDescriptor) -> crate::Result<Vec<u8>> {
+        Ok(prost::Message::encode_to_vec(&self.to_protobuf(desc)?))
+    }
+
+    /// Converts a Protobuf message into an object keyed by the field names.
+    ///
+    /// Fields without presence always show up, with their default values if they are absent. Absent nested messages
+    /// and optional fields are omitted. Enum values are converted to their names if they are known.
+    pub fn from_protobuf(msg: &DynamicMessage) -> Variant {
+        let mut obj = VariantObjectMap::new();
+        for field in msg.descriptor().fields() {
+            if field.supports_presence() && !msg.has_field(&field) {
+                continue;
+            }
+            let value = msg.get_field(&field);
+            obj.insert(field.name().to_string(), value_to_variant(&value, &field.kind()));
+        }
+        Variant::Object(obj)
+    }
+
+    /// Decodes the Protobuf wire format into an object, see `from_protobuf()` for the mapping.
+    pub fn from_protobuf_bytes(bytes: &[u8], desc: &MessageDescriptor) -> crate::Result<Variant> {
+        let msg = DynamicMessage::decode(desc.clone(), bytes).map_err(|e| {
+            EdgelinkError::InvalidOperation(format!("Cannot decode the message '{}': {}", desc.full_name(), e))
+        })?;
+        Ok(Variant::from_protobuf(&msg))
+    }
+}
+
+fn value_to_variant(value: &Value, kind: &Kind) -> Variant {
+    match value {
+        Value::Bool(b) => Variant::Bool(*b),
+        Value::I32(i) => Variant::from(*i),
+        Value::I64(i) => Variant::from(*i),
+        Value::U32(u) => Variant::from(*u),
+        Value::U64(u) => Variant::from(*u),
+        Value::F32(f) => Variant::from(*f as f64),
+        Value::F64(f) => Variant::from(*f),
+        Value::String(s) => Variant::String(s.clone()),
+        Value::Bytes(bytes) => Variant::Bytes(bytes.to_vec()),
+        Value::EnumNumber(number) => match kind {
+            Kind::Enum(enum_desc) => match enum_desc.get_value(*number) {
+                Some(enum_value) => Variant::String(enum_value.name().to_string()),
+                None => Variant::from(*number),
+            },
+            _ => Variant::from(*number),
+        },
+        Value::Message(msg) => Variant::from_protobuf(msg),
+        Value::List(items) => Variant::Array(items.iter().map(|x| value_to_variant(x, kind)).collect()),
+        Value::Map(map) => {
+            let value_kind = match kind {
+                Kind::Message(entry_desc) => entry_desc.map_entry_value_field().kind(),
+                _ => kind.clone(),
+            };
+            let obj = map.iter().map(|(k, v)| (map_key_to_string(k), value_to_variant(v, &value_kind))).collect();
+            Variant::Object(obj)
+        }
+    }
+}
+
+fn map_key_to_string(key: &MapKey) -> String {
+    match key {
+        MapKey::Bool(b) => b.to_string(),
+        MapKey::I32(i) => i.to_string(),
+        MapKey::I64(i) => i.to_string(),
+        MapKey::U32(u) => u.to_string(),
+        MapKey::U64(u) => u.to_string(),
+        MapKey::String(s) => s.clone(),
+    }
+}
+
+fn object_to_message(var: &Variant, desc: &MessageDescriptor, path: &str) -> crate::Result<DynamicMessage> {
+    let obj = var.as_object().ok_or_else(|| mismatch(path, &format!("message '{}'", desc.full_name()), var))?;
+    let mut msg = DynamicMessage::new(desc.clone());
+    for (key, value) in obj.iter() {
+        let field = desc.get_field_by_name(key).or_else(|| desc.get_field_by_json_name(key)).ok_or_else(|| {
+            EdgelinkError::InvalidOperation(format!(
+                "Unknown field '{}.{}', the message '{}' has no such field",
+                path,
+                key,
+                desc.full_name()
+            ))
+        })?;
+        if value.is_null() {
+            continue;
+        }
+        let field_path = format!("{}.{}", path, key);
+        let field_value = field_to_value(value, &field, &field_path)?;
+        msg.try_set_field(&field, field_value)
+            .map_err(|e| EdgelinkError::InvalidOperation(format!("Cannot set the field '{}': {}", field_path, e)))?;
+    }
+    Ok(msg)
+}
+
+fn field_to_value(var: &Variant, field: &FieldDescriptor, path: &str) -> crate::Result<Value> {
+    if field.is_map() {
+        let entry_desc = match field.kind() {
+            Kind::Message(entry_desc) => entry_desc,
+            _ => unreachable!("The map field must be a message of entries"),
+        };
+        let key_kind = entry_desc.map_entry_key_field().kind();
+        let value_kind = entry_desc.map_entry_value_field().kind();
+        let obj = var.as_object().ok_or_else(|| mismatch(path, "map", var))?;
+        let mut map = HashMap::with_capacity(obj.len());
+        for (key, value) in obj.iter() {
+            let item_path = format!("{}[{}]", path, key);
+            map.insert(
+                string_to_map_key(key, &key_kind, &item_path)?,
+                scalar_to_value(value, &value_kind, &item_path)?,
+            );
+        }
+        Ok(Value::Map(map))
+    } else if field.is_list() {
+        let items = var.as_array().ok_or_else(|| mismatch(path, "repeated field", var))?;
+        let kind = field.kind();
+        let values = items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| scalar_to_value(item, &kind, &format!("{}[{}]", path, i)))
+            .collect::<crate::Result<Vec<_>>>()?;
+        Ok(Value::List(values))
+    } else {
+        scalar_to_value(var, &field.kind(), path)
+    }
+}
+
+fn scalar_to_value(var: &Variant, kind: &Kind, path: &str) -> crate::Result<Value> {
+    let value = match kind {
+        Kind::Double => var.as_f64().map(Value::F64),
+        Kind::Float => var.as_f64().map(|f| Value::F32(f as f32)),
+        Kind::Int32 | Kind::Sint32 | Kind::Sfixed32 => var.as_i64().and_then(|i| i32::try_from(i).ok()).map(Value::I32),
+        Kind::Int64 | Kind::Sint64 | Kind::Sfixed64 => var.as_i64().map(Value::I64),
+        Kind::Uint32 | Kind::Fixed32 => var.as_u64().and_then(|u| u32::try_from(u).ok()).map(Value::U32),
+        Kind::Uint64 | Kind::Fixed64 => var.as_u64().map(Value::U64),
+        Kind::Bool => var.as_bool().map(Value::Bool),
+        Kind::String => var.as_str().map(|s| Value::String(s.to_string())),
+        Kind::Bytes => var.as_bytes().map(|bytes| Value::Bytes(bytes.to_vec().into())),
+        Kind::Enum(enum_desc) => match var {
+            Variant::String(name) => enum_desc.get_value_by_name(name).map(|x| Value::EnumNumber(x.number())),
+            _ => var.as_i64().and_then(|i| i32::try_from(i).ok()).map(Value::EnumNumber),
+        },
+        Kind::Message(msg_desc) => return object_to_message(var, msg_desc, path).map(Value::Message),
+    };
+    value.ok_or_else(|| mismatch(path, &kind_name(kind), var))
+}
+
+fn string_to_map_key(key: &str, kind: &Kind, path: &str) -> crate::Result<MapKey> {
+    let map_key = match kind {
+        Kind::Bool => key.parse().ok().map(MapKey::Bool),
+        Kind::Int32 | Kind::Sint32 | Kind::Sfixed32 => key.parse().ok().map(MapKey::I32),
+        Kind::Int64 | Kind::Sint64 | Kind::Sfixed64 => key.parse().ok().map(MapKey::I64),
+        Kind::Uint32 | Kind::Fixed32 => key.parse().ok().map(MapKey::U32),
+        Kind::Uint64 | Kind::Fixed64 => key.parse().ok().map(MapKey::U64),
+        Kind::String => Some(MapKey::String(key.to_string())),
+        _ => None,
+    };
+    map_key.ok_or_else(|| {
+        EdgelinkError::InvalidOperation(format!("The key of '{}' expected to be {}", path, kind_name(kind))).into()
+    })
+}
+
+fn kind_name(kind: &Kind) -> String {
+    match kind {
+        Kind::Message(desc) => format!("message '{}'", desc.full_name()),
+        Kind::Enum(desc) => format!("enum '{}'", desc.full_name()),
+        _ => format!("{:?}", kind).to_lowercase(),
+    }
+}
+
+fn mismatch(path: &str, expected: &str, actual: &Variant) -> anyhow::Error {
+    EdgelinkError::InvalidOperation(format!(
+        "The field '{}' expected to be {}, but got {}",
+        path,
+        expected,
+        variant_type_name(actual)
+    ))
+    .into()
+}
+
+fn variant_type_name(var: &Variant) -> &'static str {
+    match var {
+        Variant::Null => "null",
+        Variant::Number(_) => "number",
+        Variant::String(_) => "string",
+        Variant::Bool(_) => "boolean",
+        Variant::Date(_) => "date",
+        Variant::Regexp(_) => "regexp",
+        Variant::Bytes(_) => "bytes",
+        Variant::Array(_) => "array",
+        Variant::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost_reflect::DescriptorPool;
+    use serde_json::json;
+
+    /// The descriptor set of:
+    ///
+    /// ```proto
+    /// syntax = "proto3";
+    /// package test;
+    /// message Point { int32 x = 1; int32 y = 2; }
+    /// enum Color { RED = 0; GREEN = 1; }
+    /// message Shape {
+    ///   string name = 1;
+    ///   repeated Point points = 2;
+    ///   Color color = 3;
+    ///   map<string, double> tags = 4;
+    ///   bytes data = 5;
+    ///   Point origin = 6;
+    /// }
+    /// ```
+    fn shape_descriptor() -> MessageDescriptor {
+        use prost_types::field_descriptor_proto::{Label, Type};
+        use prost_types::*;
+
+        fn field(name: &str, number: i32, label: Label, ty: Type, type_name: Option<&str>) -> FieldDescriptorProto {
+            FieldDescriptorProto {
+                name: Some(name.to_string()),
+                number: Some(number),
+                label: Some(label as i32),
+                r#type: Some(ty as i32),
+                type_name: type_name.map(|x| x.to_string()),
+                json_name: Some(name.to_string()),
+                ..Default::default()
+            }
+        }
+
+        let file = FileDescriptorProto {
+            name: Some("test.proto".to_string()),
+            package: Some("test".to_string()),
+            syntax: Some("proto3".to_string()),
+            message_type: vec![
+                DescriptorProto {
+                    name: Some("Point".to_string()),
+                    field: vec![
+                        field("x", 1, Label::Optional, Type::Int32, None),
+                        field("y", 2, Label::Optional, Type::Int32, None),
+                    ],
+                    ..Default::default()
+                },
+                DescriptorProto {
+                    name: Some("Shape".to_string()),
+                    field: vec![
+                        field("name", 1, Label::Optional, Type::String, None),
+                        field("points", 2, Label::Repeated, Type::Message, Some(".test.Point")),
+                        field("color", 3, Label::Optional, Type::Enum, Some(".test.Color")),
+                        field("tags", 4, Label::Repeated, Type::Message, Some(".test.Shape.TagsEntry")),
+                        field("data", 5, Label::Optional, Type::Bytes, None),
+                        field("origin", 6, Label::Optional, Type::Message, Some(".test.Point")),
+                    ],
+                    nested_type: vec![DescriptorProto {
+                        name: Some("TagsEntry".to_string()),
+                        field: vec![
+                            field("key", 1, Label::Optional, Type::String, None),
+                            field("value", 2, Label::Optional, Type::Double, None),
+                        ],
+                        options: Some(MessageOptions { map_entry: Some(true), ..Default::default() }),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                },
+            ],
+            enum_type: vec![EnumDescriptorProto {
+                name: Some("Color".to_string()),
+                value: vec![
+                    EnumValueDescriptorProto { name: Some("RED".to_string()), number: Some(0), ..Default::default() },
+                    EnumValueDescriptorProto { name: Some("GREEN".to_string()), number: Some(1), ..Default::default() },
+                ],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let pool = DescriptorPool::from_file_descriptor_set(FileDescriptorSet { file: vec![file] }).unwrap();
+        pool.get_message_by_name("test.Shape").unwrap()
+    }
+
+    #[test]
+    fn protobuf_should_round_trip() {
+        let desc = shape_descriptor();
+        let mut shape = Variant::deserialize(json!({
+            "name": "triangle",
+            "points": [{"x": 1, "y": 2}, {"x": -3, "y": 4}, {"x": 5}],
+            "color": 1,
+            "tags": {"area": 2.5},
+        }))
+        .unwrap();
+        shape.as_object_mut().unwrap().insert("data".to_string(), Variant::Bytes(vec![0xde, 0xad]));
+
+        let bytes = shape.to_protobuf_bytes(&desc).unwrap();
+        let decoded = Variant::from_protobuf_bytes(&bytes, &desc).unwrap();
+
+        let mut expected = Variant::deserialize(json!({
+            "name": "triangle",
+            "points": [{"x": 1, "y": 2}, {"x": -3, "y": 4}, {"x": 5, "y": 0}],
+            "color": "GREEN",
+            "tags": {"area": 2.5},
+        }))
+        .unwrap();
+        expected.as_object_mut().unwrap().insert("data".to_string(), Variant::Bytes(vec![0xde, 0xad]));
+        assert_eq!(decoded, expected);
+
+        // The enum names are accepted too
+        let bytes = decoded.to_protobuf_bytes(&desc).unwrap();
+        assert_eq!(Variant::from_protobuf_bytes(&bytes, &desc).unwrap(), expected);
+    }
+
+    #[test]
+    fn protobuf_schema_mismatches_should_be_reported() {
+        let desc = shape_descriptor();
+        let cases = [
+            (json!({"nope": 1}), "Unknown field 'test.Shape.nope'"),
+            (json!({"name": 1}), "The field 'test.Shape.name' expected to be string"),
+            (json!({"points": {"x": 1}}), "The field 'test.Shape.points' expected to be repeated field"),
+            (json!({"points": [{"x": "1"}]}), "The field 'test.Shape.points[0].x' expected to be int32"),
+            (json!({"points": [{"x": 3_000_000_000i64}]}), "The field 'test.Shape.points[0].x' expected to be int32"),
+            (json!({"color": "BLUE"}), "The field 'test.Shape.color' expected to be enum 'test.Color'"),
+            (json!({"origin": 1}), "The field 'test.Shape.origin' expected to be message 'test.Point'"),
+        ];
+        for (value, error) in cases {
+            let err = Variant::deserialize(value).unwrap().to_protobuf(&desc).unwrap_err();
+            assert!(err.to_string().starts_with(error), "{}", err);
+        }
+
+        let err = Variant::from_protobuf_bytes(&[0xff, 0xff], &desc).unwrap_err();
+        assert!(err.to_string().starts_with("Cannot decode the message 'test.Shape'"), "{}", err);
+    }
+}
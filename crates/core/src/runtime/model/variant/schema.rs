@@ -0,0 +1,143 @@
+use serde_json::json;
+
+use super::*;
+
+impl Variant {
+    /// Infers a JSON Schema describing the structure of the variant, all properties of the sampled objects are
+    /// required and the element schema of an array is inferred from its first element.
+    ///
+    /// Buffers and dates are described by their JSON representations, an array of bytes and the milliseconds since
+    /// the UNIX epoch.
+    pub fn infer_schema(&self) -> serde_json::Value {
+        match self {
+            Variant::Null => json!({"type": "null"}),
+            Variant::Number(n) if n.is_f64() => json!({"type": "number"}),
+            Variant::Number(_) => json!({"type": "integer"}),
+            Variant::String(_) => json!({"type": "string"}),
+            Variant::Bool(_) => json!({"type": "boolean"}),
+            Variant::Regexp(_) => json!({"type": "string", "format": "regex"}),
+            Variant::Date(_) => json!({"type": "integer"}),
+            Variant::Bytes(_) => {
+                json!({"type": "array", "items": {"type": "integer", "minimum": 0, "maximum": 255}})
+            }
+            Variant::Array(items) => match items.first() {
+                Some(first) => json!({"type": "array", "items": first.infer_schema()}),
+                None => json!({"type": "array"}),
+            },
+            Variant::Object(map) => {
+                let properties: serde_json::Map<String, serde_json::Value> =
+                    map.iter().map(|(k, v)| (k.clone(), v.infer_schema())).collect();
+                let required: Vec<&String> = map.keys().collect();
+                json!({"type": "object", "properties": properties, "required": required})
+            }
+        }
+    }
+
+    /// Compiles the JSON Schema by `jsonschema`, the schema is checked against its meta-schema so a malformed one is
+    /// rejected instead of accepting everything.
+    pub fn compile_schema(schema: &serde_json::Value) -> crate::Result<jsonschema::Validator> {
+        jsonschema::validator_for(schema)
+            .map_err(|e| EdgelinkError::InvalidOperation(format!("Bad JSON Schema: {}", e)).into())
+    }
+
+    /// Validates the JSON representation of the variant against the compiled schema, see `compile_schema()`.
+    pub fn validate_with(&self, validator: &jsonschema::Validator) -> crate::Result<()> {
+        let instance = serde_json::Value::from(self);
+        validator.validate(&instance).map_err(|e| schema_error(e.instance_path.as_str(), &e.to_string()))
+    }
+
+    /// Validates the variant against a JSON Schema compiled for this call only.
+    pub fn validate_schema(&self, schema: &serde_json::Value) -> crate::Result<()> {
+        self.validate_with(&Self::compile_schema(schema)?)
+    }
+
+    pub(crate) fn is_schema_type(&self, t: &str) -> bool {
+        match (t, self) {
+            ("null", Variant::Null) => true,
+            ("boolean", Variant::Bool(_)) => true,
+            ("string", Variant::String(_) | Variant::Regexp(_)) => true,
+            ("number", Variant::Number(_) | Variant::Date(_)) => true,
+            ("integer", Variant::Date(_)) => true,
+            ("integer", Variant::Number(n)) => !n.is_f64() || n.as_f64().is_some_and(|x| x.fract() == 0.0),
+            ("array", Variant::Array(_) | Variant::Bytes(_)) => true,
+            ("object", Variant::Object(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+/// The `path` is the JSON pointer of the invalid value, e.g. `/payload/0`.
+fn schema_error(path: &str, reason: &str) -> anyhow::Error {
+    let path = if path.is_empty() { "<root>" } else { path };
+    EdgelinkError::InvalidOperation(format!("Schema validation failed at '{}': {}", path, reason)).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn infer_schema_should_describe_nested_objects_and_arrays() {
+        let msg = Variant::deserialize(json!({
+            "payload": {"temperature": 21.5, "tags": ["a", "b"], "sensor": {"id": 7, "online": true}},
+            "topic": "room1"
+        }))
+        .unwrap();
+        let schema = msg.infer_schema();
+        assert_eq!(
+            schema,
+            json!({
+                "type": "object",
+                "properties": {
+                    "payload": {
+                        "type": "object",
+                        "properties": {
+                            "sensor": {
+                                "type": "object",
+                                "properties": {"id": {"type": "integer"}, "online": {"type": "boolean"}},
+                                "required": ["id", "online"]
+                            },
+                            "tags": {"type": "array", "items": {"type": "string"}},
+                            "temperature": {"type": "number"}
+                        },
+                        "required": ["sensor", "tags", "temperature"]
+                    },
+                    "topic": {"type": "string"}
+                },
+                "required": ["payload", "topic"]
+            })
+        );
+
+        assert!(msg.validate_schema(&schema).is_ok());
+
+        let other = Variant::deserialize(json!({"payload": {"temperature": "hot"}, "topic": "room1"})).unwrap();
+        assert!(other.validate_schema(&schema).is_err());
+    }
+
+    #[test]
+    fn validate_schema_should_check_array_items() {
+        let schema = json!({"type": "array", "items": {"type": "integer"}});
+        assert!(Variant::deserialize(json!([1, 2, 3])).unwrap().validate_schema(&schema).is_ok());
+        assert!(Variant::Bytes(vec![1, 2]).validate_schema(&schema).is_ok());
+        let err = Variant::deserialize(json!([1, "2"])).unwrap().validate_schema(&schema).unwrap_err();
+        assert!(err.to_string().contains("'/1'"));
+    }
+
+    #[test]
+    fn validate_schema_should_check_every_keyword_and_reject_bad_schemas() {
+        let value = Variant::from("abc");
+        assert!(value.validate_schema(&json!({"type": "string", "title": "Name", "pattern": "^a"})).is_ok());
+        assert!(value.validate_schema(&json!({"type": "string", "maxLength": 2})).is_err());
+        assert!(value.validate_schema(&json!({"oneOf": [{"type": "integer"}, {"const": "abd"}]})).is_err());
+        let nested = json!({"type": "object", "properties": {"items": {"type": "array", "items": {"oneOf": []}}}});
+        assert!(Variant::compile_schema(&nested).unwrap_err().to_string().contains("Bad JSON Schema"));
+    }
+
+    #[test]
+    fn validate_schema_should_check_minimum_and_maximum() {
+        let schema = Variant::Bytes(vec![0, 255]).infer_schema();
+        assert!(Variant::Bytes(vec![1, 2]).validate_schema(&schema).is_ok());
+        assert!(Variant::deserialize(json!([1, 256])).unwrap().validate_schema(&schema).is_err());
+        assert!(Variant::from(-1).validate_schema(&json!({"minimum": 0})).is_err());
+    }
+}
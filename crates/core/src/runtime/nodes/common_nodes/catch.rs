@@ -1,17 +1,47 @@
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 
 use serde::Deserialize;
+use tokio::sync::Mutex;
 
 use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
 use crate::runtime::nodes::*;
+use crate::utils;
+use crate::utils::time::unix_now;
 use edgelink_macro::*;
 
+/// How long to wait for the downstream nodes to accept all the aggregated errors flushed when the node stops
+const FLUSH_ON_STOP_TIMEOUT: Duration = Duration::from_secs(1);
+
 #[flow_node("catch")]
 #[derive(Debug)]
 pub struct CatchNode {
     base: FlowNode,
     pub scope: CatchNodeScope,
     pub uncaught: bool,
+    pub keep_payload: bool,
+
+    /// The window to aggregate the identical errors in, the errors are sent one by one if it's `None`
+    aggregate_window: Option<Duration>,
+    aggregations: Mutex<HashMap<ErrorIdentity, AggregatedError>>,
+}
+
+/// The errors are identical if they were reported by the same node with the same message
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ErrorIdentity {
+    source_id: String,
+    message: String,
+}
+
+/// The first error msg of the window, and how many times the error occurred in the window
+#[derive(Debug)]
+struct AggregatedError {
+    msg: MsgHandle,
+    count: u64,
+    first: i64,
+    last: i64,
 }
 
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
@@ -28,21 +58,111 @@ impl CatchNodeScope {
     }
 }
 
-#[derive(Debug, Default, Deserialize)]
+#[derive(Debug, Deserialize)]
 struct CatchNodeConfig {
     #[serde(default)]
     scope: CatchNodeScope,
 
     #[serde(default)]
     uncaught: bool,
+
+    /// Retains the properties of the original message, otherwise only `_msgid` and `error` will be sent
+    #[serde(rename = "keepPayload", default = "keep_payload_default")]
+    keep_payload: bool,
+
+    /// Sends a single summary of the identical errors caught in the `aggregateWindow`
+    #[serde(default, deserialize_with = "json::deser::deser_bool_or_str")]
+    aggregate: bool,
+
+    /// The aggregation window in seconds
+    #[serde(rename = "aggregateWindow", default, deserialize_with = "json::deser::str_to_option_f64")]
+    aggregate_window: Option<f64>,
+}
+
+fn keep_payload_default() -> bool {
+    true
 }
 
 impl CatchNode {
     fn build(_flow: &Flow, state: FlowNode, _config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
         let catch_config = CatchNodeConfig::deserialize(&_config.rest)?;
-        let node = CatchNode { base: state, scope: catch_config.scope, uncaught: catch_config.uncaught };
+        let aggregate_window = if catch_config.aggregate {
+            let window = catch_config.aggregate_window.unwrap_or(1.0);
+            match utils::time::delay_from_secs_f64(window).filter(|x| !x.is_zero()) {
+                Some(window) => Some(window),
+                None => {
+                    return Err(EdgelinkError::BadFlowsJson(format!(
+                        "The aggregation window of the catch node must be greater than 0: {}",
+                        window
+                    ))
+                    .into())
+                }
+            }
+        } else {
+            None
+        };
+        let node = CatchNode {
+            base: state,
+            scope: catch_config.scope,
+            uncaught: catch_config.uncaught,
+            keep_payload: catch_config.keep_payload,
+            aggregate_window,
+            aggregations: Mutex::new(HashMap::new()),
+        };
         Ok(Box::new(node))
     }
+
+    async fn receive(self: &Arc<Self>, msg: MsgHandle, stop_token: CancellationToken) -> crate::Result<()> {
+        let window = match self.aggregate_window {
+            Some(window) => window,
+            None => return self.fan_out_one(Envelope { port: 0, msg }, stop_token.child_token()).await,
+        };
+
+        let identity = {
+            let msg_guard = msg.read().await;
+            let field =
+                |path: &str| msg_guard.get_nav_stripped(path).and_then(|x| x.to_string().ok()).unwrap_or_default();
+            ErrorIdentity { source_id: field("error.source.id"), message: field("error.message") }
+        };
+        let now = unix_now();
+        let mut aggregations = self.aggregations.lock().await;
+        if let Some(aggregated) = aggregations.get_mut(&identity) {
+            aggregated.count += 1;
+            aggregated.last = now;
+            return Ok(());
+        }
+        aggregations.insert(identity.clone(), AggregatedError { msg, count: 1, first: now, last: now });
+        drop(aggregations);
+
+        let node = self.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = stop_token.cancelled() => return,
+                _ = tokio::time::sleep(window) => {}
+            }
+            let aggregated = node.aggregations.lock().await.remove(&identity);
+            if let Some(aggregated) = aggregated {
+                if let Err(e) = node.send_aggregated(aggregated, stop_token.child_token()).await {
+                    log::warn!("[CATCH:{}] Failed to send the aggregated error: {}", node.name(), e);
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Sends the first error msg of the window with the summary in `msg.error.aggregate`.
+    async fn send_aggregated(&self, aggregated: AggregatedError, cancel: CancellationToken) -> crate::Result<()> {
+        {
+            let mut msg_guard = aggregated.msg.write().await;
+            let summary = Variant::from(serde_json::json!({
+                "count": aggregated.count,
+                "first": aggregated.first,
+                "last": aggregated.last,
+            }));
+            msg_guard.set_nav_stripped("error.aggregate", summary, true)?;
+        }
+        self.fan_out_one(Envelope { port: 0, msg: aggregated.msg }, cancel).await
+    }
 }
 
 #[async_trait]
@@ -53,12 +173,21 @@ impl FlowNodeBehavior for CatchNode {
 
     async fn run(self: Arc<Self>, stop_token: CancellationToken) {
         while !stop_token.is_cancelled() {
-            let cancel = stop_token.child_token();
-            with_uow(self.as_ref(), cancel.child_token(), |node, msg| async move {
-                node.fan_out_one(Envelope { port: 0, msg }, cancel.child_token()).await?;
-                Ok(())
-            })
-            .await;
+            let cancel = stop_token.clone();
+            let this = self.clone();
+            with_uow(self.as_ref(), cancel.child_token(), |_, msg| async move { this.receive(msg, cancel).await })
+                .await;
+        }
+
+        // The errors aggregated in the unfinished windows are not lost silently, the flushing is bounded as a whole
+        let pending: Vec<_> = self.aggregations.lock().await.drain().map(|(_, x)| x).collect();
+        let deadline = tokio::time::Instant::now() + FLUSH_ON_STOP_TIMEOUT;
+        for aggregated in pending {
+            let sent =
+                tokio::time::timeout_at(deadline, self.send_aggregated(aggregated, CancellationToken::new())).await;
+            if !matches!(sent, Ok(Ok(()))) {
+                log::warn!("[CATCH:{}] Failed to flush the aggregated error on stop", self.name());
+            }
         }
     }
 }
@@ -106,3 +235,54 @@ impl<'de> Deserialize<'de> for CatchNodeScope {
         deserializer.deserialize_any(CatchNodeScopeVisitor)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_it_should_aggregate_identical_errors_in_the_window() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "round", "property": "payload", "places": "1", "wires": []},
+            {"id": "4", "z": "100", "type": "round", "property": "other", "places": "1", "wires": []},
+            {"id": "2", "z": "100", "type": "test-once"},
+            {"id": "3", "z": "100", "type": "catch", "aggregate": true, "aggregateWindow": "0.2", "wires": [["2"]]}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"payload": "bad", "topic": "first"}],
+            ["1", {"payload": "bad", "topic": "second"}],
+            ["1", {"payload": "bad", "topic": "third"}],
+            ["4", {"other": "bad", "topic": "other"}],
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(2, Duration::from_secs_f64(0.6), msgs_to_inject).await.unwrap();
+        // The first error msg of the window is sent with the summary
+        let burst = msgs.iter().find(|x| x["topic"] == "first".into()).unwrap();
+        assert_eq!(burst.get_nav_stripped("error.aggregate.count"), Some(&Variant::from(3)));
+        let first = burst.get_nav_stripped("error.aggregate.first").and_then(|x| x.as_i64()).unwrap();
+        let last = burst.get_nav_stripped("error.aggregate.last").and_then(|x| x.as_i64()).unwrap();
+        assert!(first <= last);
+        let other = msgs.iter().find(|x| x["topic"] == "other".into()).unwrap();
+        assert_eq!(other.get_nav_stripped("error.aggregate.count"), Some(&Variant::from(1)));
+    }
+
+    #[test]
+    fn test_bad_aggregate_window_should_fail_to_build() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "catch", "aggregate": "true", "aggregateWindow": "0", "wires": []}
+        ]);
+        assert!(crate::runtime::engine::build_test_engine(flows_json).is_err());
+
+        // The long windows are clamped instead of overflowing
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "catch", "aggregate": "true", "aggregateWindow": "1e300", "wires": []}
+        ]);
+        assert!(crate::runtime::engine::build_test_engine(flows_json).is_ok());
+    }
+}
@@ -7,6 +7,9 @@ use crate::runtime::model::json::RedFlowNodeConfig;
 use crate::runtime::nodes::*;
 use edgelink_macro::*;
 
+const DEBUG_MAX_DEPTH: usize = 10;
+const DEBUG_MAX_LENGTH: usize = 1000;
+
 #[derive(Deserialize, Debug)]
 struct DebugNodeConfig {
     //#[serde(default)]
@@ -48,7 +51,11 @@ impl FlowNodeBehavior for DebugNode {
                 match self.recv_msg(stop_token.child_token()).await {
                     Ok(msg) => {
                         let msg = msg.read().await;
-                        log::info!("[debug:{}] Message Received: \n{:#?}", self.name(), &msg)
+                        log::info!(
+                            "[debug:{}] Message Received: \n{}",
+                            self.name(),
+                            msg.as_variant().to_pretty_string(DEBUG_MAX_DEPTH, DEBUG_MAX_LENGTH)
+                        )
                     }
                     Err(ref err) => {
                         log::error!("[debug:{}] Error: {:#?}", self.name(), err);
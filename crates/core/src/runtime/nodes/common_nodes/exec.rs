@@ -0,0 +1,557 @@
+use std::process::{ExitStatus, Stdio};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Deserialize;
+use smallvec::SmallVec;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, Command};
+use tokio::sync::Mutex;
+
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use crate::utils;
+use crate::ErrorContext;
+use edgelink_macro::*;
+
+/// The most commands running at the same time in the exec mode if the `concurrency` of the node is not set
+const EXEC_CONCURRENCY_DEFAULT: usize = 64;
+
+/// The `CREATE_NO_WINDOW` process creation flag of Windows
+#[cfg(windows)]
+const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+#[derive(Debug, Clone, Deserialize)]
+struct ExecNodeConfig {
+    #[serde(default)]
+    command: String,
+
+    /// The message property appended to the command as an argument, `true` in the old flows means `payload`
+    #[serde(default, deserialize_with = "deser_addpay")]
+    addpay: String,
+
+    /// The extra arguments appended to the command after the message property
+    #[serde(default)]
+    append: String,
+
+    /// Keeps the process alive and streams its output line by line, instead of waiting for the whole output
+    #[serde(default, rename = "useSpawn", deserialize_with = "json::deser::deser_bool_or_str")]
+    use_spawn: bool,
+
+    /// Kills the process if it runs longer than the seconds
+    #[serde(default, deserialize_with = "json::deser::str_to_option_f64")]
+    timer: Option<f64>,
+
+    /// Hides the console window of the process on Windows
+    #[serde(default, rename = "winHide", deserialize_with = "json::deser::deser_bool_or_str")]
+    #[cfg_attr(not(windows), allow(dead_code))]
+    win_hide: bool,
+}
+
+fn deser_addpay<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Bool(true) => Ok("payload".to_string()),
+        serde_json::Value::String(s) => Ok(s),
+        _ => Ok(String::new()),
+    }
+}
+
+/// The process started by the `useSpawn` mode
+#[derive(Debug)]
+struct SpawnedProcess {
+    pid: Option<u32>,
+    /// Locked apart from the process, so `msg.kill` is not blocked by a write waiting for the process to read
+    stdin: Option<Arc<Mutex<ChildStdin>>>,
+    kill: CancellationToken,
+}
+
+/// Runs the command and sends its stdout, stderr and return code to the 1st, 2nd and 3rd outputs.
+///
+/// In the exec mode the command runs in the shell for every message, and the whole output is sent when it exits, the
+/// commands of several messages run at the same time. In the `useSpawn` mode the first message starts the process, the
+/// payloads of the following messages are written to its stdin, and its output is sent line by line. `msg.kill` kills
+/// the spawned process or all running commands.
+#[derive(Debug)]
+#[flow_node("exec")]
+struct ExecNode {
+    base: FlowNode,
+    config: ExecNodeConfig,
+    timeout: Option<Duration>,
+    spawned: Mutex<Option<SpawnedProcess>>,
+    /// The parent token of the running commands of the exec mode, replaced after `msg.kill` cancelled it
+    exec_kill: std::sync::Mutex<CancellationToken>,
+}
+
+impl ExecNode {
+    fn build(
+        _flow: &Flow,
+        mut state: FlowNode,
+        config: &RedFlowNodeConfig,
+    ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let exec_config = ExecNodeConfig::deserialize(&config.rest)?;
+        if exec_config.command.trim().is_empty() {
+            return Err(EdgelinkError::BadFlowsJson("The command of the exec node must be set".into()).into());
+        }
+        // No timeout if the timer is empty or 0 like Node-RED
+        let timeout = match exec_config.timer.filter(|x| *x != 0.0) {
+            None => None,
+            Some(secs) => Some(
+                utils::time::delay_from_secs_f64(secs)
+                    .filter(|x| !x.is_zero())
+                    .ok_or_else(|| EdgelinkError::BadFlowsJson(format!("Bad timeout of the exec node: {}", secs)))?,
+            ),
+        };
+        // The commands run at the same time like Node-RED, so a long command blocks neither the others nor `msg.kill`
+        if !exec_config.use_spawn && config.concurrency <= 1 {
+            state.concurrency = EXEC_CONCURRENCY_DEFAULT;
+            state.uow_permits = Arc::new(tokio::sync::Semaphore::new(EXEC_CONCURRENCY_DEFAULT));
+        }
+        let node = ExecNode {
+            base: state,
+            config: exec_config,
+            timeout,
+            spawned: Mutex::new(None),
+            exec_kill: std::sync::Mutex::new(CancellationToken::new()),
+        };
+        Ok(Box::new(node))
+    }
+
+    async fn receive(self: &Arc<Self>, msg: MsgHandle, stop_token: CancellationToken) -> crate::Result<()> {
+        let origin = msg.read().await.clone();
+        if origin.contains("kill") {
+            self.kill(&stop_token).await;
+            Ok(())
+        } else if self.config.use_spawn {
+            self.spawn_or_write(origin, stop_token).await
+        } else {
+            let kill = self.exec_kill.lock().expect("`exec_kill` lock").child_token();
+            self.exec(origin, kill, stop_token).await
+        }
+    }
+
+    /// Kills the spawned process, or the running commands of the exec mode.
+    async fn kill(&self, stop_token: &CancellationToken) {
+        if self.config.use_spawn {
+            match self.spawned.lock().await.take() {
+                Some(process) => process.kill.cancel(),
+                None => log::debug!("[EXEC:{}] No process to kill", self.name()),
+            }
+        } else {
+            let mut exec_kill = self.exec_kill.lock().expect("`exec_kill` lock");
+            exec_kill.cancel();
+            *exec_kill = stop_token.child_token();
+        }
+    }
+
+    /// Runs the command in the shell and waits for the whole output, the command is killed by cancelling `kill`.
+    async fn exec(&self, origin: Msg, kill: CancellationToken, stop_token: CancellationToken) -> crate::Result<()> {
+        let mut args = vec![self.config.command.trim().to_string()];
+        args.extend(self.payload_arg(&origin));
+        args.extend(Some(self.config.append.trim().to_string()).filter(|x| !x.is_empty()));
+        let command_line = args.join(" ");
+
+        let mut command = if cfg!(windows) { Command::new("cmd") } else { Command::new("sh") };
+        command.arg(if cfg!(windows) { "/C" } else { "-c" }).arg(&command_line).stdin(Stdio::null());
+        let mut child =
+            self.spawn_command(command).with_context(|| format!("Failed to run the command '{}'", command_line))?;
+
+        let (stdout_pipe, stderr_pipe) = (child.stdout.take(), child.stderr.take());
+        let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+        let rc = {
+            let output = async {
+                tokio::join!(read_all(stdout_pipe, &mut stdout), read_all(stderr_pipe, &mut stderr));
+            };
+            tokio::pin!(output);
+            let wait = self.wait_child(&mut child, kill);
+            tokio::pin!(wait);
+            let mut output_closed = false;
+            let rc = loop {
+                tokio::select! {
+                    rc = &mut wait => break rc?,
+                    _ = &mut output, if !output_closed => output_closed = true,
+                }
+            };
+            // The children of the killed shell may still hold the pipes, so only the output read so far is sent
+            let killed = rc.as_object().is_some_and(|x| x.contains_key("killed"));
+            if !output_closed && !killed {
+                output.await;
+            }
+            rc
+        };
+        if stop_token.is_cancelled() {
+            return Ok(());
+        }
+
+        let make_msg = |port: usize, payload: Variant| {
+            let mut msg = origin.clone();
+            msg.set("payload".to_string(), payload);
+            msg.set("rc".to_string(), rc.clone());
+            Envelope { port, msg: MsgHandle::new(msg) }
+        };
+        let mut envelopes: SmallVec<[Envelope; 4]> = SmallVec::new();
+        envelopes.push(make_msg(0, bytes_to_payload(stdout)));
+        if !stderr.is_empty() {
+            envelopes.push(make_msg(1, bytes_to_payload(stderr)));
+        }
+        envelopes.push(make_msg(2, rc.clone()));
+        self.fan_out_many(envelopes, stop_token.child_token()).await
+    }
+
+    /// Starts the process by the first message, then writes the payloads to its stdin.
+    async fn spawn_or_write(self: &Arc<Self>, origin: Msg, stop_token: CancellationToken) -> crate::Result<()> {
+        let mut spawned = self.spawned.lock().await;
+        let payload_as_arg = !self.config.addpay.is_empty();
+        if spawned.is_none() {
+            let mut args = split_args(&self.config.command);
+            args.extend(self.payload_arg(&origin));
+            args.extend(split_args(&self.config.append));
+            let mut command = Command::new(&args[0]);
+            command.args(&args[1..]).stdin(Stdio::piped());
+            let mut child =
+                self.spawn_command(command).with_context(|| format!("Failed to spawn the command '{}'", args[0]))?;
+
+            let kill = stop_token.child_token();
+            let stdin = child.stdin.take().map(|x| Arc::new(Mutex::new(x)));
+            *spawned = Some(SpawnedProcess { pid: child.id(), stdin, kill: kill.clone() });
+            let node = self.clone();
+            let first_msg = origin.clone();
+            tokio::spawn(async move { node.supervise(child, first_msg, kill, stop_token).await });
+
+            // The payload of the first message is the argument
+            if payload_as_arg {
+                return Ok(());
+            }
+        }
+
+        let bytes = match origin.get("payload") {
+            None | Some(Variant::Null) => return Ok(()),
+            Some(Variant::Bytes(bytes)) => bytes.clone(),
+            Some(payload) => payload_to_string(payload).into_bytes(),
+        };
+        let stdin = spawned.as_ref().and_then(|x| x.stdin.clone());
+        drop(spawned);
+        match stdin {
+            Some(stdin) => {
+                let mut stdin = stdin.lock().await;
+                stdin.write_all(&bytes).await.context("Failed to write to the stdin of the process")?;
+                stdin.flush().await?;
+                Ok(())
+            }
+            None => Err(EdgelinkError::InvalidOperation("The stdin of the process has been closed".into()).into()),
+        }
+    }
+
+    /// Sends the output of the spawned process line by line, and the return code when it exits.
+    async fn supervise(&self, mut child: Child, origin: Msg, kill: CancellationToken, stop_token: CancellationToken) {
+        let pid = child.id();
+        let (_, _, rc) = tokio::join!(
+            self.forward_lines(child.stdout.take(), 0, &origin, &stop_token),
+            self.forward_lines(child.stderr.take(), 1, &origin, &stop_token),
+            self.wait_child(&mut child, kill)
+        );
+
+        {
+            // A newer process may have been started if this one was killed by `msg.kill`
+            let mut spawned = self.spawned.lock().await;
+            if spawned.as_ref().is_some_and(|x| x.pid == pid) {
+                *spawned = None;
+            }
+        }
+
+        let rc = match rc {
+            Ok(rc) => rc,
+            Err(e) => {
+                log::warn!("[EXEC:{}] Failed to wait for the process: {}", self.name(), e);
+                return;
+            }
+        };
+        if stop_token.is_cancelled() {
+            return;
+        }
+        let mut msg = origin;
+        msg.set("payload".to_string(), rc);
+        if let Err(e) = self.fan_out_one(Envelope { port: 2, msg: MsgHandle::new(msg) }, stop_token.child_token()).await
+        {
+            log::warn!("[EXEC:{}] Failed to send the return code: {}", self.name(), e);
+        }
+    }
+
+    async fn forward_lines<R>(&self, reader: Option<R>, port: usize, origin: &Msg, stop_token: &CancellationToken)
+    where
+        R: AsyncRead + Unpin,
+    {
+        let mut reader = match reader {
+            Some(reader) => BufReader::new(reader),
+            None => return,
+        };
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            match reader.read_until(b'\n', &mut line).await {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(e) => {
+                    log::warn!("[EXEC:{}] Failed to read the output of the process: {}", self.name(), e);
+                    break;
+                }
+            }
+            if line.ends_with(b"\n") {
+                line.pop();
+                if line.ends_with(b"\r") {
+                    line.pop();
+                }
+            }
+            let mut msg = origin.clone();
+            msg.set("payload".to_string(), Variant::String(String::from_utf8_lossy(&line).into_owned()));
+            if let Err(e) =
+                self.fan_out_one(Envelope { port, msg: MsgHandle::new(msg) }, stop_token.child_token()).await
+            {
+                log::warn!("[EXEC:{}] Failed to send the output: {}", self.name(), e);
+            }
+        }
+    }
+
+    /// Waits for the process to exit, it will be killed if the `timer` elapsed or the token cancelled.
+    async fn wait_child(&self, child: &mut Child, kill: CancellationToken) -> crate::Result<Variant> {
+        let timeout = async {
+            match self.timeout {
+                Some(timeout) => tokio::time::sleep(timeout).await,
+                None => std::future::pending::<()>().await,
+            }
+        };
+        tokio::select! {
+            status = child.wait() => return Ok(make_rc(status?, false)),
+            _ = timeout => log::debug!("[EXEC:{}] The process timed out", self.name()),
+            _ = kill.cancelled() => {}
+        }
+        child.kill().await?;
+        let status = child.wait().await?;
+        Ok(make_rc(status, true))
+    }
+
+    fn spawn_command(&self, mut command: Command) -> std::io::Result<Child> {
+        command.stdout(Stdio::piped()).stderr(Stdio::piped()).kill_on_drop(true);
+        #[cfg(windows)]
+        if self.config.win_hide {
+            command.creation_flags(CREATE_NO_WINDOW);
+        }
+        command.spawn()
+    }
+
+    /// The message property as the argument, it's omitted if the property is missing or null.
+    fn payload_arg(&self, msg: &Msg) -> Option<String> {
+        if self.config.addpay.is_empty() {
+            return None;
+        }
+        match msg.get_nav_stripped(&self.config.addpay) {
+            None | Some(Variant::Null) => None,
+            Some(value) => Some(payload_to_string(value)),
+        }
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for ExecNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        *self.exec_kill.lock().expect("`exec_kill` lock") = stop_token.child_token();
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow_concurrent(&self, cancel.clone(), |node, msg| async move { node.receive(msg, cancel).await })
+                .await;
+        }
+        wait_uows_completed(self.as_ref()).await;
+
+        // The spawned process is killed by the cancelled token
+        self.spawned.lock().await.take();
+        log::debug!("ExecNode process() task has been terminated.");
+    }
+}
+
+/// The return code object `{code}`, the `code` is null if the process was terminated by a signal.
+fn make_rc(status: ExitStatus, killed: bool) -> Variant {
+    let mut rc = VariantObjectMap::new();
+    rc.insert("code".to_string(), status.code().map(Variant::from).unwrap_or(Variant::Null));
+    #[cfg(unix)]
+    if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        rc.insert("signal".to_string(), Variant::from(signal));
+    }
+    if killed {
+        rc.insert("killed".to_string(), Variant::Bool(true));
+    }
+    Variant::Object(rc)
+}
+
+/// Appends the output to `buf`, so the bytes read are kept even if the reading is abandoned.
+async fn read_all<R: AsyncRead + Unpin>(reader: Option<R>, buf: &mut Vec<u8>) {
+    if let Some(mut reader) = reader {
+        if let Err(e) = reader.read_to_end(buf).await {
+            log::warn!("Failed to read the output of the process: {}", e);
+        }
+    }
+}
+
+/// The output is a string if it's valid UTF-8, otherwise a buffer.
+fn bytes_to_payload(bytes: Vec<u8>) -> Variant {
+    match String::from_utf8(bytes) {
+        Ok(s) => Variant::String(s),
+        Err(e) => Variant::Bytes(e.into_bytes()),
+    }
+}
+
+fn payload_to_string(value: &Variant) -> String {
+    match value {
+        Variant::String(s) => s.clone(),
+        Variant::Bytes(bytes) => String::from_utf8_lossy(bytes).into_owned(),
+        Variant::Number(_) | Variant::Bool(_) => value.to_string().unwrap_or_default(),
+        other => serde_json::to_string(other).unwrap_or_default(),
+    }
+}
+
+/// Splits the arguments by the whitespaces, the quoted arguments may contain the whitespaces.
+fn split_args(s: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current: Option<String> = None;
+    let mut quote: Option<char> = None;
+    for c in s.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => current.get_or_insert_with(String::new).push(c),
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                current.get_or_insert_with(String::new);
+            }
+            None if c.is_whitespace() => args.extend(current.take()),
+            None => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(current);
+    args
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_split_args() {
+        assert_eq!(split_args(r#" ls  -l "a b" 'c "d"' e"f g" "#), vec!["ls", "-l", "a b", "c \"d\"", "ef g"]);
+        assert_eq!(split_args(r#"echo """#), vec!["echo", ""]);
+        assert!(split_args("  ").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_it_should_exec_the_command_with_the_payload() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "exec", "command": "/bin/echo", "addpay": "payload", "append": "world",
+                "useSpawn": "false", "timer": "", "wires": [["2"], ["2"], ["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": "hello"}]])).unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(2, Duration::from_secs(2), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0]["payload"], "hello world\n".into());
+        assert_eq!(msgs[0].get_nav_stripped("rc.code"), Some(&Variant::from(0)));
+        assert_eq!(msgs[1]["payload"], Variant::from(json!({"code": 0})));
+    }
+
+    #[tokio::test]
+    async fn test_it_should_report_the_exit_code_and_kill_timed_out_commands() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "exec", "command": "echo oops >&2; exit 3", "addpay": false,
+                "wires": [[], ["2"], ["2"]]},
+            {"id": "3", "z": "100", "type": "exec", "command": "sleep 10", "addpay": "", "timer": "0.2",
+                "wires": [[], [], ["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject =
+            Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"topic": "exit"}], ["3", {"topic": "sleep"}]])).unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(3, Duration::from_secs(3), msgs_to_inject).await.unwrap();
+        let stderr = msgs.iter().find(|x| x["payload"] == "oops\n".into()).unwrap();
+        assert_eq!(stderr.get_nav_stripped("rc.code"), Some(&Variant::from(3)));
+        let sleep = msgs.iter().find(|x| x["topic"] == "sleep".into()).unwrap();
+        assert_eq!(sleep.get_nav_stripped("payload.code"), Some(&Variant::Null));
+        assert_eq!(sleep.get_nav_stripped("payload.killed"), Some(&Variant::Bool(true)));
+    }
+
+    #[tokio::test]
+    async fn test_it_should_split_the_spawned_output_into_lines() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "exec", "command": "/bin/echo", "addpay": "payload", "useSpawn": "true",
+                "wires": [["2"], ["2"], ["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject =
+            Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": "line1\nline2\r\nline 3"}]])).unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(4, Duration::from_secs(2), msgs_to_inject).await.unwrap();
+        let payloads: Vec<_> = msgs.iter().map(|x| x["payload"].clone()).collect();
+        assert_eq!(payloads[..3], ["line1".into(), "line2".into(), "line 3".into()]);
+        assert_eq!(payloads[3], Variant::from(json!({"code": 0})));
+    }
+
+    #[tokio::test]
+    async fn test_it_should_pipe_msgs_to_the_spawned_process() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "exec", "command": "cat", "addpay": "", "useSpawn": true, "timer": "0.5",
+                "wires": [["2"], [], ["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"payload": "a\n", "topic": "first"}],
+            ["1", {"payload": "b\n", "topic": "second"}],
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(3, Duration::from_secs(2), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0]["payload"], "a".into());
+        assert_eq!(msgs[1]["payload"], "b".into());
+        // The outputs are sent with the message started the process
+        assert_eq!(msgs[1]["topic"], "first".into());
+        assert_eq!(msgs[2].get_nav_stripped("payload.killed"), Some(&Variant::Bool(true)));
+    }
+
+    #[tokio::test]
+    async fn test_msg_kill_should_kill_the_running_commands() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "exec", "command": "sleep 10", "addpay": "", "wires": [[], [], ["2"]]},
+            // The `msg.kill` arrives after the commands started
+            {"id": "3", "z": "100", "type": "delay", "pauseType": "delay", "timeout": "200",
+                "timeoutUnits": "milliseconds", "wires": [["1"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"topic": "first"}],
+            ["1", {"topic": "second"}],
+            ["3", {"kill": "SIGTERM"}],
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        // Both commands ran at the same time and were killed together long before they exit
+        let msgs = engine.run_once_with_inject(2, Duration::from_secs(3), msgs_to_inject).await.unwrap();
+        let mut topics: Vec<_> = msgs.iter().map(|x| x["topic"].as_str().unwrap().to_string()).collect();
+        topics.sort();
+        assert_eq!(topics, vec!["first", "second"]);
+        assert!(msgs.iter().all(|x| x.get_nav_stripped("payload.killed") == Some(&Variant::Bool(true))));
+    }
+}
@@ -1,4 +1,3 @@
-use std::collections::BTreeMap;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -20,10 +19,24 @@ struct RedPropertyTriple {
     #[serde(default)]
     vt: RedPropertyType,
 
-    #[serde(default)]
+    #[serde(default, deserialize_with = "deser_prop_value")]
     v: String,
 }
 
+/// The `v` may be written as a JSON literal instead of a string in hand-written flows, e.g. `"v": 10`.
+fn deser_prop_value<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    match Value::deserialize(deserializer)? {
+        Value::String(s) => Ok(s),
+        Value::Null => Ok(String::new()),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        other => Ok(other.to_string()),
+    }
+}
+
 #[derive(serde::Deserialize, Debug)]
 struct InjectNodeConfig {
     #[serde(default)]
@@ -145,16 +158,18 @@ impl InjectNode {
     }
 
     async fn inject_msg(&self, stop_token: CancellationToken) -> crate::Result<()> {
-        // TODO msg.field1 references msg.field2
-        let mut msg_body: BTreeMap<String, Variant> = BTreeMap::new();
+        // The props are evaluated in order, so a `msg` typed prop can reference the props set before it
+        let mut msg = MsgBuilder::new().build()?;
+        let flow = self.flow();
         for prop in self.config.props.iter() {
-            let k = prop.p.to_string();
-            let v = eval::evaluate_node_property(&prop.v, prop.vt, Some(self), self.flow().as_ref(), None).await?;
-            msg_body.insert(k, v);
+            let v = eval::evaluate_node_property(&prop.v, prop.vt, Some(self), flow.as_ref(), Some(&msg))
+                .await
+                .with_context(|| format!("Failed to evaluate the property 'msg.{}'", prop.p))?;
+            msg.set_nav_stripped(&prop.p, v, true)
+                .with_context(|| format!("Failed to set the property 'msg.{}'", prop.p))?;
         }
-        msg_body.insert(wellknown::MSG_ID_PROPERTY.to_string(), Variant::String(Msg::generate_id().to_string()));
 
-        let envelope = Envelope { port: 0, msg: MsgHandle::with_body(msg_body) };
+        let envelope = Envelope { port: 0, msg: MsgHandle::new(msg) };
 
         self.notify_uow_completed(envelope.msg.clone(), stop_token.clone()).await;
 
@@ -206,7 +221,9 @@ fn handle_legacy_json(orig: &Value) -> Value {
                         if let Some(p) = prop_map.get("p") {
                             if p == "payload" && !prop_map.contains_key("v") {
                                 prop_map.insert("v".to_string(), orig["payload"].clone());
-                                prop_map.insert("vt".to_string(), orig["payloadType"].clone());
+                                if let Some(vt) = orig.get("payloadType").filter(|x| !x.is_null()) {
+                                    prop_map.insert("vt".to_string(), vt.clone());
+                                }
                             } else if p == "topic"
                                 && prop_map.get("vt") == Some(&Value::String("str".to_string()))
                                 && !prop_map.contains_key("v")
@@ -238,6 +255,7 @@ fn handle_legacy_json(orig: &Value) -> Value {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde_json::json;
 
     #[test]
     fn parse_red_property_triple_should_be_ok() {
@@ -256,4 +274,57 @@ mod tests {
         assert_eq!("timestamp", triples[0].p);
         assert_eq!(RedPropertyType::Date, triples[0].vt);
     }
+
+    #[tokio::test]
+    async fn test_it_should_inject_multiple_typed_props() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab", "env": [{"name": "FOO", "value": "bar", "type": "str"}]},
+            {
+                "id": "1", "type": "inject", "z": "100", "once": true, "wires": [["2"]],
+                "props": [
+                    {"p": "payload", "v": "foo", "vt": "str"},
+                    {"p": "topic", "v": "t1", "vt": "str"},
+                    {"p": "num", "v": "10", "vt": "num"},
+                    {"p": "flag", "v": "true", "vt": "bool"},
+                    {"p": "data.inner", "v": "{\"a\":[1,2]}", "vt": "json"},
+                    {"p": "timestamp", "v": "", "vt": "date"},
+                    {"p": "fv", "v": "fkey", "vt": "flow"},
+                    {"p": "gv", "v": "gkey", "vt": "global"},
+                    {"p": "ev", "v": "FOO", "vt": "env"},
+                    {"p": "copied", "v": "data.inner.a[1]", "vt": "msg"}
+                ]
+            },
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let flow = engine.get_flow(&ElementId::with_u64(0x100)).unwrap();
+        flow.context().set_one(None, "fkey", Some(Variant::from("flow value")), &[]).await.unwrap();
+        engine.context().set_one(None, "gkey", Some(Variant::from(123)), &[]).await.unwrap();
+
+        let start_time = crate::utils::time::unix_now();
+        let msgs = engine.run_once(1, std::time::Duration::from_secs_f64(0.5)).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        let msg = &msgs[0];
+        assert_eq!(msg["payload"], Variant::from("foo"));
+        assert_eq!(msg["topic"], Variant::from("t1"));
+        assert_eq!(msg["num"], Variant::from(10));
+        assert_eq!(msg["flag"], Variant::from(true));
+        assert_eq!(msg.get_nav_stripped("data.inner.a[0]"), Some(&Variant::from(1)));
+        assert!(msg["timestamp"].as_i64().unwrap() >= start_time);
+        assert_eq!(msg["fv"], Variant::from("flow value"));
+        assert_eq!(msg["gv"], Variant::from(123));
+        assert_eq!(msg["ev"], Variant::from("bar"));
+        assert_eq!(msg["copied"], Variant::from(2));
+    }
+
+    #[test]
+    fn non_string_prop_values_should_be_accepted() {
+        let v =
+            json!([{"p": "a", "v": 10, "vt": "num"}, {"p": "b", "v": null}, {"p": "c", "v": {"x": 1}, "vt": "json"}]);
+        let triples = Vec::<RedPropertyTriple>::deserialize(&v).unwrap();
+        assert_eq!(triples[0].v, "10");
+        assert_eq!(triples[1].v, "");
+        assert_eq!(triples[1].vt, RedPropertyType::Str);
+        assert_eq!(triples[2].v, r#"{"x":1}"#);
+    }
 }
@@ -2,6 +2,7 @@ pub(crate) mod catch;
 mod complete;
 mod console_json;
 mod debug;
+mod exec;
 mod inject;
 mod junction;
 pub(crate) mod link_call;
@@ -11,5 +12,8 @@ mod status;
 mod subflow;
 mod unknown;
 
-#[cfg(any(test, feature = "pymod"))]
+#[cfg(any(test, feature = "testing"))]
 mod test_once;
+
+#[cfg(any(test, feature = "testing"))]
+mod test_config;
@@ -0,0 +1,91 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::runtime::context::ContextScope;
+use crate::runtime::flow::Flow;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+use runtime::engine::Engine;
+
+/// A configuration node for testing, it holds a value for the flow nodes referencing it.
+#[derive(Debug)]
+#[global_node("test-config")]
+struct TestConfigNode {
+    base: GlobalNode,
+    value: Variant,
+}
+
+impl TestConfigNode {
+    fn build(engine: &Engine, config: &RedGlobalNodeConfig) -> crate::Result<Box<dyn GlobalNodeBehavior>> {
+        let context =
+            engine.get_context_manager().new_context(&engine.context(), config.id.to_string(), ContextScope::Node);
+        let value = config.rest.get("value").map(Variant::deserialize).transpose()?.unwrap_or(Variant::Null);
+        let node = Self {
+            base: GlobalNode {
+                id: config.id,
+                name: config.name.clone(),
+                type_str: "test-config",
+                ordering: config.ordering,
+                disabled: config.disabled,
+                context,
+            },
+            value,
+        };
+        Ok(Box::new(node))
+    }
+}
+
+impl GlobalNodeBehavior for TestConfigNode {
+    fn get_node(&self) -> &GlobalNode {
+        &self.base
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TestConfigReaderNodeConfig {
+    #[serde(deserialize_with = "json::deser::deser_red_id")]
+    config: ElementId,
+}
+
+/// Sets `msg.payload` to the value of the referenced `test-config` node.
+#[flow_node("test-config-reader")]
+struct TestConfigReaderNode {
+    base: FlowNode,
+    value: Variant,
+}
+
+impl TestConfigReaderNode {
+    fn build(flow: &Flow, base: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let reader_config = TestConfigReaderNodeConfig::deserialize(&config.rest)?;
+        let engine = flow.engine().ok_or(EdgelinkError::InvalidOperation("The engine has been released".into()))?;
+        let config_node = engine
+            .get_global_node(&reader_config.config)
+            .with_context(|| format!("Failed to get the config node of the node: id='{}'", base.id))?;
+        let value = config_node
+            .as_any()
+            .downcast_ref::<TestConfigNode>()
+            .map(|x| x.value.clone())
+            .ok_or(EdgelinkError::BadFlowsJson(format!("Not a 'test-config' node: {}", config_node)))?;
+        let node = TestConfigReaderNode { base, value };
+        Ok(Box::new(node))
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for TestConfigReaderNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow(self.as_ref(), cancel.child_token(), |node, msg| async move {
+                msg.write().await.set("payload".into(), node.value.clone());
+                node.fan_out_one(Envelope { port: 0, msg }, cancel).await
+            })
+            .await;
+        }
+    }
+}
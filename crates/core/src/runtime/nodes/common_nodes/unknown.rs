@@ -1,5 +1,6 @@
 use std::sync::Arc;
 
+use crate::runtime::context::ContextScope;
 use crate::runtime::flow::Flow;
 use crate::runtime::nodes::*;
 use edgelink_macro::*;
@@ -15,7 +16,8 @@ struct UnknownGlobalNode {
 
 impl UnknownGlobalNode {
     fn build(engine: &Engine, config: &RedGlobalNodeConfig) -> crate::Result<Box<dyn GlobalNodeBehavior>> {
-        let context = engine.get_context_manager().new_context(&engine.context(), config.id.to_string());
+        let context =
+            engine.get_context_manager().new_context(&engine.context(), config.id.to_string(), ContextScope::Node);
         let node = Self {
             base: GlobalNode {
                 id: config.id,
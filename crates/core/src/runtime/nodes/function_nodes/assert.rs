@@ -0,0 +1,300 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::runtime::eval;
+use crate::runtime::flow::Flow;
+use crate::runtime::model::json::{typed_constant, RedNodeConfig};
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+const VALUE_TYPES: &[&str] = &["null", "boolean", "string", "number", "integer", "array", "object"];
+
+#[derive(Debug, Clone, Deserialize, RedNodeConfig)]
+struct AssertNodeConfig {
+    /// The message property to check
+    #[serde(default = "property_default")]
+    property: String,
+
+    /// The expected value, the property must be deeply equal to it if present
+    #[serde(default)]
+    #[red_typed]
+    expected: Option<Variant>,
+
+    #[serde(rename = "expectedType", default)]
+    expected_type: RedPropertyType,
+
+    /// The expected type of the property, in the JSON Schema type names
+    #[serde(rename = "valueType", default)]
+    value_type: Option<String>,
+
+    /// Fails the `run_once()` of the engine in testing
+    #[serde(default)]
+    fatal: bool,
+}
+
+fn property_default() -> String {
+    "payload".to_string()
+}
+
+#[derive(Debug)]
+#[flow_node("assert")]
+struct AssertNode {
+    base: FlowNode,
+    config: AssertNodeConfig,
+}
+
+impl AssertNode {
+    fn build(
+        _flow: &Flow,
+        base_node: FlowNode,
+        config: &RedFlowNodeConfig,
+    ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let mut assert_config = AssertNodeConfig::from_rest(&config.rest)?;
+        assert_config.value_type = assert_config.value_type.filter(|x| !x.is_empty());
+        if let Some(value_type) = &assert_config.value_type {
+            if !VALUE_TYPES.contains(&value_type.as_str()) {
+                return Err(EdgelinkError::BadFlowsJson(format!(
+                    "Unsupported value type of the assert node: '{}'",
+                    value_type
+                ))
+                .into());
+            }
+        }
+        if assert_config.expected.is_none() && assert_config.value_type.is_none() {
+            return Err(EdgelinkError::BadFlowsJson(
+                "The assert node requires the `expected` value or the `valueType`".into(),
+            )
+            .into());
+        }
+        if let Some(expected) = assert_config.expected.take() {
+            assert_config.expected = Some(typed_constant(expected, assert_config.expected_type)?);
+        }
+        let node = AssertNode { base: base_node, config: assert_config };
+        Ok(Box::new(node))
+    }
+
+    async fn check(&self, msg: &Msg) -> crate::Result<()> {
+        let path = format!("msg.{}", self.config.property);
+        let actual = msg.get_nav_stripped(&self.config.property);
+
+        if let Some(value_type) = &self.config.value_type {
+            match actual {
+                Some(actual) if actual.is_schema_type(value_type) => {}
+                _ => {
+                    return Err(EdgelinkError::InvalidOperation(format!(
+                        "Assertion failed at '{}': expected a value of type '{}', got: {}",
+                        path,
+                        value_type,
+                        describe(actual)
+                    ))
+                    .into())
+                }
+            }
+        }
+
+        if let Some(expected) = &self.config.expected {
+            let expected = match expected {
+                Variant::String(expr) if !self.config.expected_type.is_constant() => {
+                    let flow = self.flow();
+                    eval::evaluate_node_property(expr, self.config.expected_type, Some(self), flow.as_ref(), Some(msg))
+                        .await
+                        .with_context(|| format!("Failed to evaluate the expected value of '{}'", path))?
+                }
+                constant => constant.clone(),
+            };
+            let difference = match actual {
+                Some(actual) => find_difference(&path, actual, &expected),
+                None => Some(format!("'{}' is missing, expected: {}", path, describe(Some(&expected)))),
+            };
+            if let Some(difference) = difference {
+                return Err(EdgelinkError::InvalidOperation(format!("Assertion failed at {}", difference)).into());
+            }
+        }
+        Ok(())
+    }
+
+    async fn receive(&self, msg: MsgHandle, cancel: CancellationToken) -> crate::Result<()> {
+        let checked = {
+            let msg_guard = msg.read().await;
+            self.check(&msg_guard).await
+        };
+        if let Err(e) = checked {
+            if self.config.fatal {
+                #[cfg(any(test, feature = "testing"))]
+                if let Some(engine) = self.engine() {
+                    engine.report_test_failure(format!("[assert:{}] {}", self.id(), e))?;
+                }
+            }
+            return Err(e);
+        }
+        self.fan_out_one(Envelope { port: 0, msg }, cancel).await
+    }
+}
+
+/// Returns the description of the first difference between the two values, `None` if they are deeply equal.
+///
+/// Numbers are compared by their values, so `1` equals to `1.0`.
+fn find_difference(path: &str, actual: &Variant, expected: &Variant) -> Option<String> {
+    match (actual, expected) {
+        (Variant::Object(actual_map), Variant::Object(expected_map)) => {
+            for (key, expected_value) in expected_map.iter() {
+                let sub_path = format!("{}.{}", path, key);
+                match actual_map.get(key) {
+                    Some(actual_value) => {
+                        if let Some(difference) = find_difference(&sub_path, actual_value, expected_value) {
+                            return Some(difference);
+                        }
+                    }
+                    None => {
+                        return Some(format!("'{}' is missing, expected: {}", sub_path, describe(Some(expected_value))))
+                    }
+                }
+            }
+            actual_map
+                .keys()
+                .find(|key| !expected_map.contains_key(*key))
+                .map(|key| format!("'{}.{}' is unexpected", path, key))
+        }
+        (Variant::Array(actual_items), Variant::Array(expected_items)) => {
+            if actual_items.len() != expected_items.len() {
+                return Some(format!(
+                    "'{}': expected an array of length {}, got: {}",
+                    path,
+                    expected_items.len(),
+                    actual_items.len()
+                ));
+            }
+            actual_items
+                .iter()
+                .zip(expected_items.iter())
+                .enumerate()
+                .find_map(|(i, (a, e))| find_difference(&format!("{}[{}]", path, i), a, e))
+        }
+        (Variant::Number(a), Variant::Number(e)) if a == e || a.as_f64() == e.as_f64() => None,
+        _ if actual == expected => None,
+        _ => Some(format!("'{}': expected {}, got: {}", path, describe(Some(expected)), describe(Some(actual)))),
+    }
+}
+
+fn describe(value: Option<&Variant>) -> String {
+    match value {
+        Some(v) => serde_json::Value::from(v).to_string(),
+        None => "undefined".to_string(),
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for AssertNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow(self.as_ref(), cancel.child_token(), |node, msg| async move { node.receive(msg, cancel).await })
+                .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[test]
+    fn test_find_difference_should_report_the_path() {
+        let expected = Variant::deserialize(json!({"a": [1, {"b": "x"}], "c": 2.0})).unwrap();
+        let same = Variant::deserialize(json!({"c": 2, "a": [1.0, {"b": "x"}]})).unwrap();
+        assert_eq!(find_difference("msg.payload", &same, &expected), None);
+
+        let changed = Variant::deserialize(json!({"a": [1, {"b": "y"}], "c": 2})).unwrap();
+        assert_eq!(
+            find_difference("msg.payload", &changed, &expected).unwrap(),
+            r#"'msg.payload.a[1].b': expected "x", got: "y""#
+        );
+        let extra = Variant::deserialize(json!({"a": [1, {"b": "x"}], "c": 2, "d": null})).unwrap();
+        assert_eq!(find_difference("msg.payload", &extra, &expected).unwrap(), "'msg.payload.d' is unexpected");
+    }
+
+    #[tokio::test]
+    async fn test_passed_assertion_should_forward_the_msg() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "assert", "property": "payload.data",
+                "expected": "{\"a\":[1,2]}", "expectedType": "json", "valueType": "object", "fatal": true,
+                "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"payload": {"data": {"a": [1, 2]}}}],
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0].get_nav_stripped("payload.data.a[1]"), Some(&Variant::from(2)));
+    }
+
+    #[tokio::test]
+    async fn test_constant_expected_value_should_be_coerced_to_its_type() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "assert", "expected": "42", "expectedType": "num", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "assert", "property": "data", "expected": "[1, 2]", "expectedType": "bin",
+                "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let mut msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": 42.0}]])).unwrap();
+        msgs_to_inject[0].1.set("data".into(), Variant::from(vec![1u8, 2u8]));
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0].get("payload"), Some(&Variant::from(42.0)));
+    }
+
+    #[tokio::test]
+    async fn test_failed_assertion_should_report_the_error() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "assert", "expected": "{\"a\":[1,2]}", "expectedType": "json",
+                "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"},
+            {"id": "3", "z": "100", "type": "catch", "wires": [["2"]]}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"payload": {"a": [1, 3]}}],
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        let error_message = msgs[0].get_nav_stripped("error.message").unwrap().as_str().unwrap();
+        assert_eq!(error_message, "Assertion failed at 'msg.payload.a[1]': expected 2, got: 3");
+    }
+
+    #[tokio::test]
+    async fn test_fatal_assertion_should_fail_the_run() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "assert", "valueType": "string", "fatal": true, "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": 42}]])).unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let result = engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.5), msgs_to_inject).await;
+        let error = result.unwrap_err().to_string();
+        assert!(error.contains("Assertion failed at 'msg.payload': expected a value of type 'string', got: 42"));
+    }
+}
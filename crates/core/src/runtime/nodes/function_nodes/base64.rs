@@ -0,0 +1,226 @@
+use std::sync::Arc;
+
+use base64::alphabet;
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use base64::Engine as _;
+use serde::Deserialize;
+
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+/// Accepts the input with or without the padding
+const PADDED: GeneralPurposeConfig =
+    GeneralPurposeConfig::new().with_encode_padding(true).with_decode_padding_mode(DecodePaddingMode::Indifferent);
+
+const UNPADDED: GeneralPurposeConfig =
+    GeneralPurposeConfig::new().with_encode_padding(false).with_decode_padding_mode(DecodePaddingMode::Indifferent);
+
+const STANDARD_ENGINE: GeneralPurpose = GeneralPurpose::new(&alphabet::STANDARD, PADDED);
+const URL_SAFE_ENGINE: GeneralPurpose = GeneralPurpose::new(&alphabet::URL_SAFE, PADDED);
+const BASE64URL_ENGINE: GeneralPurpose = GeneralPurpose::new(&alphabet::URL_SAFE, UNPADDED);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+enum Base64Action {
+    /// Decodes the strings, encodes the buffers
+    #[default]
+    #[serde(rename = "")]
+    Auto,
+
+    #[serde(rename = "encode")]
+    Encode,
+
+    #[serde(rename = "decode")]
+    Decode,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+enum Base64Alphabet {
+    /// `+` and `/` with the padding
+    #[default]
+    #[serde(rename = "standard")]
+    Standard,
+
+    /// `-` and `_` with the padding
+    #[serde(rename = "urlsafe")]
+    UrlSafe,
+
+    /// `-` and `_` without the padding, i.e. the `base64url` of JWT
+    #[serde(rename = "base64url")]
+    Base64Url,
+}
+
+impl Base64Alphabet {
+    fn engine(&self) -> &'static GeneralPurpose {
+        match self {
+            Base64Alphabet::Standard => &STANDARD_ENGINE,
+            Base64Alphabet::UrlSafe => &URL_SAFE_ENGINE,
+            Base64Alphabet::Base64Url => &BASE64URL_ENGINE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Base64NodeConfig {
+    #[serde(default = "property_default")]
+    property: String,
+
+    #[serde(default)]
+    action: Base64Action,
+
+    #[serde(default)]
+    alphabet: Base64Alphabet,
+}
+
+fn property_default() -> String {
+    "payload".to_string()
+}
+
+/// Converts the value by the action, the strings are encoded as UTF-8.
+fn convert(config: &Base64NodeConfig, value: &Variant) -> crate::Result<Variant> {
+    let engine = config.alphabet.engine();
+    let decode = |text: &str| -> crate::Result<Variant> {
+        let bytes = engine
+            .decode(text.trim())
+            .map_err(|e| EdgelinkError::InvalidOperation(format!("Failed to decode the base64 string: {}", e)))?;
+        Ok(Variant::Bytes(bytes))
+    };
+    match (config.action, value) {
+        (Base64Action::Encode, Variant::String(s)) => Ok(Variant::String(engine.encode(s))),
+        (Base64Action::Encode | Base64Action::Auto, Variant::Bytes(bytes)) => Ok(Variant::String(engine.encode(bytes))),
+        (Base64Action::Decode, Variant::String(s)) => decode(s),
+        // Not every string is base64, e.g. "hello world"
+        (Base64Action::Auto, Variant::String(s)) => decode(s).or_else(|_| Ok(Variant::String(engine.encode(s)))),
+        (_, other) => Err(EdgelinkError::NotSupported(format!(
+            "The base64 node only supports strings and buffers for {:?}, got: {:?}",
+            config.action, other
+        ))
+        .into()),
+    }
+}
+
+/// Encodes the buffer or the string to the base64 string, or decodes the base64 string to the buffer.
+#[derive(Debug)]
+#[flow_node("base64")]
+struct Base64Node {
+    base: FlowNode,
+    config: Base64NodeConfig,
+}
+
+impl Base64Node {
+    fn build(_flow: &Flow, state: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let base64_config = Base64NodeConfig::deserialize(&config.rest)?;
+        let node = Base64Node { base: state, config: base64_config };
+        Ok(Box::new(node))
+    }
+
+    async fn uow(&self, msg: MsgHandle, cancel: CancellationToken) -> crate::Result<()> {
+        {
+            let mut msg_guard = msg.write().await;
+            let property = &self.config.property;
+            let value = msg_guard.get_nav_stripped(property).ok_or_else(|| {
+                EdgelinkError::InvalidOperation(format!("Cannot find the property 'msg.{}'", property))
+            })?;
+            let converted = convert(&self.config, value)?;
+            msg_guard.set_nav_stripped(property, converted, true)?;
+        }
+        self.fan_out_one(Envelope { port: 0, msg }, cancel).await
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for Base64Node {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow_concurrent(&self, cancel.child_token(), |node, msg| async move { node.uow(msg, cancel).await })
+                .await;
+        }
+        wait_uows_completed(self.as_ref()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn make_config(config: serde_json::Value) -> Base64NodeConfig {
+        Base64NodeConfig::deserialize(config).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip_with_alphabets() {
+        let bytes = Variant::Bytes(vec![0xfb, 0xff, 0xbf, 0x00, 0x61]);
+        for (alphabet, expected) in [("standard", "+/+/AGE="), ("urlsafe", "-_-_AGE="), ("base64url", "-_-_AGE")] {
+            let encoder = make_config(json!({"action": "encode", "alphabet": alphabet}));
+            let encoded = convert(&encoder, &bytes).unwrap();
+            assert_eq!(encoded, Variant::from(expected));
+            let decoder = make_config(json!({"action": "decode", "alphabet": alphabet}));
+            assert_eq!(convert(&decoder, &encoded).unwrap(), bytes);
+        }
+        let encoder = make_config(json!({"action": "encode"}));
+        assert_eq!(convert(&encoder, &Variant::from("héllo")).unwrap(), Variant::from("aMOpbGxv"));
+    }
+
+    #[test]
+    fn test_decode_should_accept_both_padding_variants() {
+        for alphabet in ["standard", "urlsafe", "base64url"] {
+            let decoder = make_config(json!({"action": "decode", "alphabet": alphabet}));
+            for text in ["YWI=", "YWI", "YQ==", "YQ", " YWJj\n"] {
+                let decoded = convert(&decoder, &Variant::from(text)).unwrap();
+                assert!(b"abc".starts_with(decoded.as_bytes().unwrap()), "{} {:?}", text, decoded);
+            }
+        }
+    }
+
+    #[test]
+    fn test_auto_should_detect_direction() {
+        let auto = make_config(json!({"action": ""}));
+        assert_eq!(convert(&auto, &Variant::from("aGVsbG8=")).unwrap(), Variant::Bytes(b"hello".to_vec()));
+        assert_eq!(convert(&auto, &Variant::Bytes(b"hello".to_vec())).unwrap(), Variant::from("aGVsbG8="));
+        // Not a base64 string
+        assert_eq!(convert(&auto, &Variant::from("hi there")).unwrap(), Variant::from("aGkgdGhlcmU="));
+    }
+
+    #[test]
+    fn test_invalid_input_should_fail() {
+        let decoder = make_config(json!({"action": "decode"}));
+        assert!(convert(&decoder, &Variant::from("not base64!")).is_err());
+        assert!(convert(&decoder, &Variant::from("YQ=a")).is_err());
+        // The standard alphabet does not accept the URL-safe characters
+        assert!(convert(&decoder, &Variant::from("-_-_AGE=")).is_err());
+        assert!(convert(&decoder, &Variant::Bytes(vec![1])).is_err());
+        assert!(convert(&make_config(json!({"action": "encode"})), &Variant::from(42)).is_err());
+        assert!(Base64NodeConfig::deserialize(json!({"alphabet": "base32"})).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_it_should_round_trip_and_catch_invalid_input() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "base64", "action": "encode", "alphabet": "base64url", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "base64", "action": "decode", "alphabet": "base64url", "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"},
+            {"id": "4", "z": "100", "type": "catch", "wires": [["3"]]}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"topic": "ok", "payload": "edge?link"}],
+            ["2", {"topic": "bad", "payload": "***"}],
+        ]))
+        .unwrap();
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(2, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+
+        let (caught, decoded): (Vec<_>, Vec<_>) = msgs.into_iter().partition(|x| x.contains("error"));
+        assert_eq!(decoded[0]["payload"], Variant::Bytes(b"edge?link".to_vec()));
+        assert_eq!(caught[0]["topic"], Variant::from("bad"));
+        assert!(caught[0].get_nav_stripped("error.message").unwrap().as_str().unwrap().contains("base64"));
+    }
+}
@@ -0,0 +1,361 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Deserialize;
+use tokio::sync::Mutex;
+use tokio::time::MissedTickBehavior;
+
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum BatchMode {
+    /// Groups every `count` messages
+    #[default]
+    #[serde(rename = "count")]
+    Count,
+
+    /// Groups the messages arrived in every `interval`
+    #[serde(rename = "interval")]
+    Interval,
+
+    /// Concatenates a sequence of each topic in the order of `topics`
+    #[serde(rename = "concat")]
+    Concat,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum IntervalUnits {
+    #[serde(rename = "milliseconds")]
+    Milliseconds,
+
+    #[default]
+    #[serde(rename = "seconds")]
+    Seconds,
+
+    #[serde(rename = "minutes")]
+    Minutes,
+
+    #[serde(rename = "hours")]
+    Hours,
+}
+
+impl IntervalUnits {
+    fn as_secs_f64(&self) -> f64 {
+        match self {
+            IntervalUnits::Milliseconds => 0.001,
+            IntervalUnits::Seconds => 1.0,
+            IntervalUnits::Minutes => 60.0,
+            IntervalUnits::Hours => 60.0 * 60.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct BatchTopic {
+    topic: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct BatchNodeConfig {
+    #[serde(default)]
+    mode: BatchMode,
+
+    #[serde(default, deserialize_with = "json::deser::str_to_option_usize")]
+    count: Option<usize>,
+
+    #[serde(default, deserialize_with = "json::deser::str_to_option_f64")]
+    interval: Option<f64>,
+
+    #[serde(rename = "intervalUnits", default)]
+    interval_units: IntervalUnits,
+
+    /// Sends a message with an empty array if nothing arrived in the interval
+    #[serde(rename = "allowEmptySequence", default, deserialize_with = "json::deser::deser_bool_or_str")]
+    allow_empty_sequence: bool,
+
+    /// The topics of the sequences to concatenate, in the order of the output
+    #[serde(default)]
+    topics: Vec<BatchTopic>,
+}
+
+/// The sequences waiting to be concatenated
+#[derive(Debug, Default)]
+struct ConcatState {
+    tracker: SequenceTracker<MsgHandle>,
+    completed: HashMap<String, VecDeque<Vec<MsgHandle>>>,
+}
+
+#[derive(Debug)]
+#[flow_node("batch")]
+struct BatchNode {
+    base: FlowNode,
+    config: BatchNodeConfig,
+    interval: Duration,
+    buffer: Mutex<Vec<MsgHandle>>,
+    concat: Mutex<ConcatState>,
+}
+
+impl BatchNode {
+    fn build(
+        _flow: &Flow,
+        base_node: FlowNode,
+        config: &RedFlowNodeConfig,
+    ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let batch_config = BatchNodeConfig::deserialize(&config.rest)?;
+        let interval = batch_config.interval.unwrap_or(1.0) * batch_config.interval_units.as_secs_f64();
+        match batch_config.mode {
+            BatchMode::Count if batch_config.count.unwrap_or(1) == 0 => {
+                return Err(EdgelinkError::BadFlowsJson("The count of the batch node must be positive".into()).into());
+            }
+            BatchMode::Interval if interval <= 0.0 => {
+                return Err(
+                    EdgelinkError::BadFlowsJson(format!("Bad interval of the batch node: {}s", interval)).into()
+                );
+            }
+            BatchMode::Concat if batch_config.topics.is_empty() => {
+                return Err(EdgelinkError::BadFlowsJson("No topics to concatenate in the batch node".into()).into());
+            }
+            _ => {}
+        }
+        let node = BatchNode {
+            base: base_node,
+            interval: Duration::from_secs_f64(interval.max(0.0)),
+            config: batch_config,
+            buffer: Mutex::new(Vec::new()),
+            concat: Mutex::new(ConcatState::default()),
+        };
+        Ok(Box::new(node))
+    }
+
+    async fn receive(&self, msg: MsgHandle, cancel: CancellationToken) -> crate::Result<()> {
+        match self.config.mode {
+            BatchMode::Count => {
+                let batch = {
+                    let mut buffer = self.buffer.lock().await;
+                    buffer.push(msg);
+                    if buffer.len() >= self.config.count.unwrap_or(1) {
+                        std::mem::take(&mut *buffer)
+                    } else {
+                        return Ok(());
+                    }
+                };
+                self.send_batch(batch, cancel).await
+            }
+            BatchMode::Interval => {
+                self.buffer.lock().await.push(msg);
+                Ok(())
+            }
+            BatchMode::Concat => self.receive_concat(msg, cancel).await,
+        }
+    }
+
+    /// Sends the batch as one message, whose payload is the array of the payloads of the batched messages.
+    async fn send_batch(&self, batch: Vec<MsgHandle>, cancel: CancellationToken) -> crate::Result<()> {
+        let mut payloads = Vec::with_capacity(batch.len());
+        for msg in batch.iter() {
+            let msg_guard = msg.read().await;
+            payloads.push(msg_guard.get("payload").cloned().unwrap_or(Variant::Null));
+        }
+        let out = MsgHandle::with_payload(Variant::Array(payloads));
+        self.fan_out_one(Envelope { port: 0, msg: out }, cancel).await
+    }
+
+    async fn receive_concat(&self, msg: MsgHandle, cancel: CancellationToken) -> crate::Result<()> {
+        let (topic, parts) = {
+            let msg_guard = msg.read().await;
+            (msg_guard.get("topic").and_then(|x| x.as_str()).map(String::from), MsgParts::from_msg(&msg_guard)?)
+        };
+        let topic = match topic.filter(|t| self.config.topics.iter().any(|x| x.topic == *t)) {
+            Some(topic) => topic,
+            None => {
+                log::debug!("[BATCH:{}] Ignored a message of a topic not to concatenate", self.name());
+                return Ok(());
+            }
+        };
+        let parts = parts.ok_or(EdgelinkError::InvalidOperation("The message is not in a sequence".into()))?;
+
+        let sequences = {
+            let mut state = self.concat.lock().await;
+            match state.tracker.push(&parts, msg, std::time::Instant::now())? {
+                SequenceStatus::Complete(seq) => state.completed.entry(topic).or_default().push_back(seq),
+                SequenceStatus::Duplicated(_) => {
+                    log::warn!("[BATCH:{}] Dropped a duplicated message of the sequence '{}'", self.name(), parts.id);
+                    return Ok(());
+                }
+                SequenceStatus::Incomplete { .. } => return Ok(()),
+            }
+            // Waits until every topic has a complete sequence
+            let is_ready =
+                self.config.topics.iter().all(|x| state.completed.get(&x.topic).is_some_and(|q| !q.is_empty()));
+            if !is_ready {
+                return Ok(());
+            }
+            let mut sequences = Vec::with_capacity(self.config.topics.len());
+            for topic in self.config.topics.iter() {
+                sequences.push(state.completed.get_mut(&topic.topic).and_then(|q| q.pop_front()).unwrap_or_default());
+            }
+            sequences
+        };
+
+        // The concatenated messages make up a new sequence
+        let id = Msg::generate_id().to_string();
+        let count = sequences.iter().map(|x| x.len()).sum::<usize>();
+        let mut envelopes = Vec::with_capacity(count);
+        for (index, msg) in sequences.into_iter().flatten().enumerate() {
+            {
+                let mut msg_guard = msg.write().await;
+                let parts = MsgParts {
+                    id: id.clone(),
+                    index,
+                    count: Some(count),
+                    kind: None,
+                    key: None,
+                    ch: None,
+                    parent: None,
+                };
+                msg_guard.set("parts".into(), parts.to_variant());
+            }
+            envelopes.push(Envelope { port: 0, msg });
+        }
+        for envelope in envelopes.into_iter() {
+            self.fan_out_one(envelope, cancel.clone()).await?;
+        }
+        Ok(())
+    }
+
+    /// Sends the messages arrived in every interval, the first tick is skipped since nothing has been accumulated.
+    async fn interval_loop(self: Arc<Self>, stop_token: CancellationToken) {
+        let mut ticker = tokio::time::interval(self.interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        ticker.tick().await;
+        loop {
+            tokio::select! {
+                _ = stop_token.cancelled() => break,
+                _ = ticker.tick() => {}
+            }
+            let batch = std::mem::take(&mut *self.buffer.lock().await);
+            if batch.is_empty() && !self.config.allow_empty_sequence {
+                continue;
+            }
+            if let Err(e) = self.send_batch(batch, stop_token.child_token()).await {
+                log::warn!("[BATCH:{}] Failed to send the batch: {}", self.name(), e);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for BatchNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        let ticker = if self.config.mode == BatchMode::Interval {
+            Some(tokio::spawn(self.clone().interval_loop(stop_token.clone())))
+        } else {
+            None
+        };
+
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow(self.as_ref(), cancel.child_token(), |node, msg| async move { node.receive(msg, cancel).await })
+                .await;
+        }
+
+        if let Some(ticker) = ticker {
+            let _ = ticker.await;
+        }
+        log::debug!("BatchNode process() task has been terminated.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_it_should_batch_by_count() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "batch", "mode": "count", "count": "2", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"payload": 1}],
+            ["1", {"payload": 2}],
+            ["1", {"payload": 3}],
+            ["1", {"payload": 4}],
+            ["1", {"payload": 5}],
+        ]))
+        .unwrap();
+        let msgs = engine.run_once_with_inject(2, Duration::from_millis(200), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0]["payload"], Variant::from(json!([1, 2])));
+        assert_eq!(msgs[1]["payload"], Variant::from(json!([3, 4])));
+        assert!(!msgs[0].contains("parts"));
+    }
+
+    #[tokio::test]
+    async fn test_it_should_batch_by_interval() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "batch", "mode": "interval", "interval": "100",
+                "intervalUnits": "milliseconds", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject =
+            Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": "a"}], ["1", {"payload": "b"}]])).unwrap();
+        let msgs = engine.run_once_with_inject(1, Duration::from_millis(300), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0]["payload"], Variant::from(json!(["a", "b"])));
+    }
+
+    #[tokio::test]
+    async fn test_it_should_send_empty_batch_if_allowed() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "batch", "mode": "interval", "interval": "50",
+                "intervalUnits": "milliseconds", "allowEmptySequence": true, "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(2, Duration::from_millis(300), Vec::new()).await.unwrap();
+        assert_eq!(msgs[0]["payload"], Variant::Array(Vec::new()));
+        assert_eq!(msgs[1]["payload"], Variant::Array(Vec::new()));
+    }
+
+    #[tokio::test]
+    async fn test_it_should_concat_sequences_by_topics() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "batch", "mode": "concat", "topics": [{"topic": "a"}, {"topic": "b"}],
+                "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"topic": "b", "payload": "b0", "parts": {"id": "y", "index": 0, "count": 1}}],
+            ["1", {"topic": "a", "payload": "a1", "parts": {"id": "x", "index": 1, "count": 2}}],
+            ["1", {"topic": "c", "payload": "c0", "parts": {"id": "z", "index": 0, "count": 1}}],
+            ["1", {"topic": "a", "payload": "a0", "parts": {"id": "x", "index": 0, "count": 2}}],
+        ]))
+        .unwrap();
+        let msgs = engine.run_once_with_inject(3, Duration::from_millis(200), msgs_to_inject).await.unwrap();
+        let payloads: Vec<&str> = msgs.iter().map(|x| x["payload"].as_str().unwrap()).collect();
+        assert_eq!(payloads, vec!["a0", "a1", "b0"]);
+        for (i, msg) in msgs.iter().enumerate() {
+            let parts = MsgParts::from_msg(msg).unwrap().unwrap();
+            assert_eq!(parts.index, i);
+            assert_eq!(parts.count, Some(3));
+            assert_eq!(parts.id, MsgParts::from_msg(&msgs[0]).unwrap().unwrap().id);
+            assert_ne!(parts.id, "x");
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+const HIT_PORT: usize = 0;
+const MISS_PORT: usize = 1;
+
+#[derive(Debug, Clone, Deserialize)]
+struct CacheNodeConfig {
+    /// The message property used as the key
+    #[serde(default = "property_default")]
+    property: String,
+
+    #[serde(default, deserialize_with = "json::deser::str_to_option_usize")]
+    capacity: Option<usize>,
+
+    /// The time-to-live of entries in seconds, entries never expire if absent or zero
+    #[serde(default, deserialize_with = "json::deser::str_to_option_f64")]
+    ttl: Option<f64>,
+}
+
+fn property_default() -> String {
+    "topic".to_string()
+}
+
+fn capacity_default() -> usize {
+    100
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    value: Variant,
+    expires_at: Option<Instant>,
+}
+
+/// A least-recently-used map with an optional time-to-live for each entry.
+///
+/// Both the lookups and the insertions take constant time: the full cache evicts its least recently used entry, the
+/// expired entries are removed when they are looked up or become the least recently used ones.
+#[derive(Debug)]
+struct LruCache {
+    ttl: Option<Duration>,
+    entries: lru::LruCache<String, CacheEntry>,
+}
+
+impl LruCache {
+    fn new(capacity: NonZeroUsize, ttl: Option<Duration>) -> Self {
+        LruCache { ttl, entries: lru::LruCache::new(capacity) }
+    }
+
+    fn get(&mut self, key: &str, now: Instant) -> Option<Variant> {
+        match self.entries.get(key) {
+            Some(entry) if entry.expires_at.map_or(true, |x| x > now) => Some(entry.value.clone()),
+            Some(_) => {
+                self.entries.pop(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn put(&mut self, key: String, value: Variant, now: Instant) {
+        let entry = CacheEntry { value, expires_at: self.ttl.map(|x| now + x) };
+        self.entries.put(key, entry);
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[derive(Debug)]
+#[flow_node("cache")]
+struct CacheNode {
+    base: FlowNode,
+    config: CacheNodeConfig,
+    cache: Mutex<LruCache>,
+}
+
+impl CacheNode {
+    fn build(
+        _flow: &Flow,
+        base_node: FlowNode,
+        config: &RedFlowNodeConfig,
+    ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let cache_config = CacheNodeConfig::deserialize(&config.rest)?;
+        let capacity = NonZeroUsize::new(cache_config.capacity.unwrap_or_else(capacity_default))
+            .ok_or(EdgelinkError::BadFlowsJson("The capacity of the cache node must be positive".into()))?;
+        let ttl = match cache_config.ttl {
+            Some(secs) if secs < 0.0 || !secs.is_finite() => {
+                return Err(EdgelinkError::BadFlowsJson(format!("Bad TTL of the cache node: {}", secs)).into())
+            }
+            Some(secs) if secs > 0.0 => Some(Duration::from_secs_f64(secs)),
+            _ => None,
+        };
+        let node = CacheNode { base: base_node, config: cache_config, cache: Mutex::new(LruCache::new(capacity, ttl)) };
+        Ok(Box::new(node))
+    }
+
+    /// Handles a message in one of three ways:
+    /// - `msg.reset` clears the cache and drops the message;
+    /// - `msg.store` stores `msg.payload` under the key and sends the message to the hit port;
+    /// - Otherwise looks up the key, a hit replaces `msg.payload` with the cached value and is sent to the hit port,
+    ///   a miss is passed through the miss port unchanged.
+    async fn receive(&self, msg: MsgHandle, cancel: CancellationToken) -> crate::Result<()> {
+        let port = {
+            let mut msg_guard = msg.write().await;
+            if msg_guard.contains("reset") {
+                self.cache.lock().unwrap().clear();
+                return Ok(());
+            }
+
+            let key = match msg_guard.get_nav_stripped(&self.config.property) {
+                Some(Variant::String(s)) => s.clone(),
+                Some(Variant::Number(n)) => n.to_string(),
+                Some(Variant::Bool(b)) => b.to_string(),
+                Some(other) => {
+                    return Err(EdgelinkError::InvalidOperation(format!(
+                        "The cache key 'msg.{}' must be a string, number or boolean, got: {:?}",
+                        self.config.property, other
+                    ))
+                    .into())
+                }
+                None => {
+                    return Err(EdgelinkError::InvalidOperation(format!(
+                        "Cannot find the cache key 'msg.{}'",
+                        self.config.property
+                    ))
+                    .into())
+                }
+            };
+
+            let now = Instant::now();
+            if msg_guard.remove("store").is_some() {
+                let value = msg_guard.get("payload").cloned().unwrap_or(Variant::Null);
+                self.cache.lock().unwrap().put(key, value, now);
+                HIT_PORT
+            } else {
+                let cached = self.cache.lock().unwrap().get(&key, now);
+                match cached {
+                    Some(value) => {
+                        msg_guard.set("payload".into(), value);
+                        HIT_PORT
+                    }
+                    None => MISS_PORT,
+                }
+            }
+        };
+        self.fan_out_one(Envelope { port, msg }, cancel).await
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for CacheNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow(self.as_ref(), cancel.child_token(), |node, msg| async move { node.receive(msg, cancel).await })
+                .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[test]
+    fn test_lru_cache_should_evict_least_recently_used() {
+        let now = Instant::now();
+        let mut cache = LruCache::new(NonZeroUsize::new(2).unwrap(), None);
+        cache.put("a".into(), Variant::from(1), now);
+        cache.put("b".into(), Variant::from(2), now);
+        assert_eq!(cache.get("a", now), Some(Variant::from(1)));
+        cache.put("c".into(), Variant::from(3), now);
+        assert_eq!(cache.entries.len(), 2);
+        assert_eq!(cache.get("b", now), None);
+        assert_eq!(cache.get("a", now), Some(Variant::from(1)));
+        assert_eq!(cache.get("c", now), Some(Variant::from(3)));
+    }
+
+    #[test]
+    fn test_lru_cache_should_expire_entries() {
+        let now = Instant::now();
+        let mut cache = LruCache::new(NonZeroUsize::new(2).unwrap(), Some(Duration::from_secs(10)));
+        cache.put("a".into(), Variant::from(1), now);
+        cache.put("b".into(), Variant::from(2), now + Duration::from_secs(5));
+        assert_eq!(cache.get("a", now + Duration::from_secs(9)), Some(Variant::from(1)));
+        assert_eq!(cache.get("a", now + Duration::from_secs(10)), None);
+        assert_eq!(cache.entries.len(), 1);
+
+        // The expired entry is the least recently used one, it is evicted first
+        cache.put("c".into(), Variant::from(3), now + Duration::from_secs(15));
+        cache.put("d".into(), Variant::from(4), now + Duration::from_secs(15));
+        assert_eq!(cache.entries.len(), 2);
+        assert_eq!(cache.get("b", now + Duration::from_secs(15)), None);
+        assert_eq!(cache.get("c", now + Duration::from_secs(15)), Some(Variant::from(3)));
+    }
+
+    #[tokio::test]
+    async fn test_it_should_emit_hits_and_pass_misses_through() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "cache", "capacity": "2", "wires": [["2"], ["3"]]},
+            {"id": "2", "z": "100", "type": "test-once"},
+            {"id": "3", "z": "100", "type": "change", "wires": [["2"]], "rules": [
+                {"t": "set", "p": "miss", "pt": "msg", "to": "true", "tot": "bool"}
+            ]}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"seq": 0, "topic": "a", "payload": 1, "store": true}],
+            ["1", {"seq": 1, "topic": "b", "payload": 2, "store": true}],
+            ["1", {"seq": 2, "topic": "a", "payload": null}],
+            ["1", {"seq": 3, "topic": "c", "payload": 3, "store": true}],
+            ["1", {"seq": 4, "topic": "b", "payload": "?"}],
+            ["1", {"seq": 5, "topic": "a", "payload": null}],
+            ["1", {"reset": true}],
+            ["1", {"seq": 7, "topic": "c", "payload": "?"}],
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let mut msgs =
+            engine.run_once_with_inject(7, std::time::Duration::from_secs_f64(0.3), msgs_to_inject).await.unwrap();
+        // The misses take a longer path than the hits, so the outputs are put back in the order of the inputs
+        msgs.sort_by_key(|x| x["seq"].as_u64());
+        let results: Vec<(Variant, bool)> = msgs.iter().map(|x| (x["topic"].clone(), x.contains("miss"))).collect();
+        assert_eq!(
+            results,
+            vec![
+                (Variant::from("a"), false),
+                (Variant::from("b"), false),
+                (Variant::from("a"), false),
+                (Variant::from("c"), false),
+                (Variant::from("b"), true),
+                (Variant::from("a"), false),
+                (Variant::from("c"), true),
+            ]
+        );
+        assert_eq!(msgs[2]["payload"], Variant::from(1));
+        assert_eq!(msgs[5]["payload"], Variant::from(1));
+        assert!(!msgs[0].contains("store"));
+    }
+}
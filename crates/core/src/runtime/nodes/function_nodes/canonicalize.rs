@@ -0,0 +1,105 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+#[derive(Debug, Clone, Deserialize)]
+struct CanonicalizeNodeConfig {
+    /// The message property to canonicalize, the message passes through untouched if it is missing
+    #[serde(default = "property_default")]
+    property: String,
+}
+
+fn property_default() -> String {
+    "payload".to_string()
+}
+
+/// Canonicalizes the message property by `Variant::canonicalize()`, so the equal values always give the same
+/// `Variant::to_canonical_json()`, e.g. for hashing or signing.
+#[derive(Debug)]
+#[flow_node("canonicalize")]
+struct CanonicalizeNode {
+    base: FlowNode,
+    config: CanonicalizeNodeConfig,
+}
+
+impl CanonicalizeNode {
+    fn build(
+        _flow: &Flow,
+        base_node: FlowNode,
+        config: &RedFlowNodeConfig,
+    ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let canonicalize_config = CanonicalizeNodeConfig::deserialize(&config.rest)?;
+        let node = CanonicalizeNode { base: base_node, config: canonicalize_config };
+        Ok(Box::new(node))
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for CanonicalizeNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow_concurrent(&self, cancel.child_token(), |node, msg| async move {
+                {
+                    let mut msg_guard = msg.write().await;
+                    if let Some(value) = msg_guard.get_nav_stripped_mut(&node.config.property) {
+                        value.canonicalize();
+                    }
+                }
+                node.fan_out_one(Envelope { port: 0, msg }, cancel.child_token()).await?;
+                Ok(())
+            })
+            .await;
+        }
+        wait_uows_completed(self.as_ref()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_it_should_sort_nested_keys_deterministically() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "canonicalize", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        // The same object in different key orders and number forms
+        let payloads = [
+            r#"{"z": {"b": [3, {"y": 1, "x": 2.0}], "a": true}, "m": [2, 1], "a": -0.0}"#,
+            r#"{"a": 0, "m": [2, 1], "z": {"a": true, "b": [3, {"x": 2, "y": 1.0}]}}"#,
+        ];
+        let msgs_to_inject = payloads
+            .iter()
+            .map(|x| {
+                let payload: serde_json::Value = serde_json::from_str(x).unwrap();
+                (ElementId::with_u64(1), Msg::deserialize(json!({"payload": payload, "topic": "t"})).unwrap())
+            })
+            .collect();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(2, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        let expected = r#"{"a":0,"m":[2,1],"z":{"a":true,"b":[3,{"x":2,"y":1}]}}"#;
+        for msg in msgs.iter() {
+            assert_eq!(serde_json::to_string(&msg["payload"]).unwrap(), expected);
+            assert_eq!(msg["topic"], Variant::from("t"));
+        }
+        assert_eq!(msgs[0]["payload"], msgs[1]["payload"]);
+        let keys: Vec<&String> = msgs[0]["payload"].as_object().unwrap().keys().collect();
+        assert_eq!(keys, vec!["a", "m", "z"]);
+    }
+}
@@ -4,8 +4,10 @@ use regex::Regex;
 use serde::Deserialize;
 use serde_json::Value;
 
+use crate::runtime::context::{ContextKey, ContextKeyRef};
 use crate::runtime::eval;
 use crate::runtime::flow::Flow;
+use crate::runtime::jsonata;
 use crate::runtime::model::*;
 use crate::runtime::nodes::*;
 use edgelink_macro::*;
@@ -31,6 +33,14 @@ enum RuleKind {
 
     #[serde(rename = "move")]
     Move,
+
+    /// Adds the `to` value to a numeric property
+    #[serde(rename = "inc")]
+    Inc,
+
+    /// Subtracts the `to` value from a numeric property
+    #[serde(rename = "dec")]
+    Dec,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -54,12 +64,51 @@ struct Rule {
 
     #[serde(default, rename = "fromRE", with = "crate::text::regex::serde_optional_regex")]
     pub from_regex: Option<Regex>,
+
+    /// The flow/global context keys of `p`, `to` and `from`, parsed once when the node is built
+    #[serde(skip)]
+    pub p_key: Option<ContextKeyRef>,
+
+    #[serde(skip)]
+    pub to_key: Option<ContextKeyRef>,
+
+    #[serde(skip)]
+    pub from_key: Option<ContextKeyRef>,
+
+    /// The JSONata expression of `to`, parsed once when the node is built
+    #[serde(skip)]
+    pub to_expr: Option<jsonata::Expression>,
     /*
     #[serde(default, rename = "dc")]
     pub deep_clone: bool,
     */
 }
 
+impl Rule {
+    fn parse_properties(&mut self) -> crate::Result<()> {
+        self.p_key = parse_context_key(Some(&self.p), Some(self.pt))?;
+        self.to_key = parse_context_key(self.to.as_deref(), self.tot)?;
+        self.from_key = parse_context_key(self.from.as_deref(), self.fromt)?;
+        if let (Some(to), Some(RedPropertyType::Jsonata)) = (self.to.as_deref(), self.tot) {
+            self.to_expr = Some(jsonata::Expression::parse(to)?);
+        }
+        Ok(())
+    }
+}
+
+fn parse_context_key(prop: Option<&str>, prop_type: Option<RedPropertyType>) -> crate::Result<Option<ContextKeyRef>> {
+    match (prop, prop_type) {
+        (Some(prop), Some(RedPropertyType::Flow | RedPropertyType::Global)) => Ok(Some(ContextKeyRef::parse(prop)?)),
+        _ => Ok(None),
+    }
+}
+
+/// Gets the context key parsed by `Rule::parse_properties()`.
+fn expect_context_key<'a>(key: Option<&'a ContextKeyRef>, prop: &str) -> crate::Result<ContextKey<'a>> {
+    key.map(|x| x.as_key())
+        .ok_or_else(|| EdgelinkError::InvalidOperation(format!("The property '{}' is not a context key", prop)).into())
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd)]
 enum ReducedType {
     Str = 0,
@@ -84,7 +133,7 @@ impl FlowNodeBehavior for ChangeNode {
     async fn run(self: Arc<Self>, stop_token: CancellationToken) {
         while !stop_token.is_cancelled() {
             let cancel = stop_token.clone();
-            with_uow(self.as_ref(), cancel.child_token(), |node, msg| async move {
+            with_uow_concurrent(&self, cancel.child_token(), |node, msg| async move {
                 {
                     let mut msg_guard = msg.write().await;
                     // We always relay the message, regardless of whether the rules are followed or not.
@@ -94,20 +143,47 @@ impl FlowNodeBehavior for ChangeNode {
             })
             .await;
         }
+        wait_uows_completed(self.as_ref()).await;
     }
 }
 
 impl ChangeNode {
-    fn build(_flow: &Flow, state: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+    fn build(
+        _flow: &Flow,
+        mut state: FlowNode,
+        config: &RedFlowNodeConfig,
+    ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
         let json = handle_legacy_json(config.rest.clone())?;
-        let change_config = ChangeNodeConfig::deserialize(&json)?;
+        let mut change_config = ChangeNodeConfig::deserialize(&json)?;
+        for rule in change_config.rules.iter_mut() {
+            rule.parse_properties()?;
+        }
+        // Increasing a context property reads it and writes it back, so the messages processed at the same time would
+        // lose the updates of each other
+        let updates_context = change_config.rules.iter().any(|x| {
+            matches!(x.t, RuleKind::Inc | RuleKind::Dec)
+                && matches!(x.pt, RedPropertyType::Flow | RedPropertyType::Global)
+        });
+        if updates_context && state.concurrency > 1 {
+            log::warn!(
+                "The change node {} updates the context by inc/dec rules, its messages are processed one by one",
+                state.id
+            );
+            state.concurrency = 1;
+            state.uow_permits = Arc::new(tokio::sync::Semaphore::new(1));
+        }
         let node = ChangeNode { base: state, config: change_config };
         Ok(Box::new(node))
     }
 
     async fn get_to_value(&self, rule: &Rule, msg: &Msg) -> crate::Result<Variant> {
-        if let (Some(tot), Some(to)) = (rule.tot, rule.to.as_ref()) {
-            eval::evaluate_node_property(to, tot, Some(self), None, Some(msg)).await
+        if let (Some(expr), Some(to)) = (rule.to_expr.as_ref(), rule.to.as_ref()) {
+            eval::evaluate_jsonata(expr, Some(self), None, Some(msg))
+                .await?
+                .ok_or(EdgelinkError::BadArgument("to"))
+                .with_context(|| format!("The JSONata expression `{}` is evaluated to undefined", to))
+        } else if let (Some(tot), Some(to)) = (rule.tot, rule.to.as_ref()) {
+            self.evaluate_property(to, tot, rule.to_key.as_ref(), msg).await
         } else {
             Err(EdgelinkError::BadFlowsJson("The `tot` and `to` in the rule cannot be None".into()).into())
         }
@@ -115,12 +191,26 @@ impl ChangeNode {
 
     async fn get_from_value(&self, rule: &Rule, msg: &Msg) -> crate::Result<Variant> {
         if let (Some(fromt), Some(from)) = (rule.fromt, rule.from.as_ref()) {
-            eval::evaluate_node_property(from, fromt, Some(self), None, Some(msg)).await
+            self.evaluate_property(from, fromt, rule.from_key.as_ref(), msg).await
         } else {
             Err(EdgelinkError::BadFlowsJson("The `fromt` and `from` in the rule cannot be None".into()).into())
         }
     }
 
+    /// Evaluates the property, the flow/global context key is not parsed again if it has been parsed.
+    async fn evaluate_property(
+        &self,
+        prop: &str,
+        prop_type: RedPropertyType,
+        key: Option<&ContextKeyRef>,
+        msg: &Msg,
+    ) -> crate::Result<Variant> {
+        match key {
+            Some(key) => eval::evaluate_context_property(key.as_key(), prop_type, Some(self), None, Some(msg)).await,
+            None => eval::evaluate_node_property(prop, prop_type, Some(self), None, Some(msg)).await,
+        }
+    }
+
     fn reduce_from_value(&self, rule: &Rule, from_value: &Variant) -> crate::Result<ReducedType> {
         let result = match (from_value, rule.fromt) {
             (Variant::String(_), Some(_)) => ReducedType::Str,
@@ -134,6 +224,7 @@ impl ChangeNode {
         Ok(result)
     }
 
+    /// Applies the rules in order, each rule sees the message and the context values changed by the previous ones.
     async fn apply_rules(&self, msg: &mut Msg) {
         for rule in self.config.rules.iter() {
             if let Err(err) = self.apply_rule(rule, msg).await {
@@ -149,12 +240,13 @@ impl ChangeNode {
             RuleKind::Change => self.apply_rule_change(rule, msg, to_value).await,
             RuleKind::Delete => self.apply_rule_delete(rule, msg).await,
             RuleKind::Move => self.apply_rule_move(rule, msg).await,
+            RuleKind::Inc | RuleKind::Dec => self.apply_rule_inc_dec(rule, msg, to_value).await,
         }
     }
 
     async fn apply_rule_set(&self, rule: &Rule, msg: &mut Msg, to_value: Option<Variant>) -> crate::Result<()> {
         assert!(rule.t == RuleKind::Set);
-        self.set_property(&rule.p, rule.pt, to_value, msg).await
+        self.set_property(&rule.p, rule.pt, rule.p_key.as_ref(), to_value, msg).await
     }
 
     async fn apply_rule_change(&self, rule: &Rule, msg: &mut Msg, to_value: Option<Variant>) -> crate::Result<()> {
@@ -170,7 +262,7 @@ impl ChangeNode {
             Err(_) => return Ok(()),
         };
 
-        let current = match eval::evaluate_node_property(&rule.p, rule.pt, Some(self), None, Some(msg)).await {
+        let current = match self.evaluate_property(&rule.p, rule.pt, rule.p_key.as_ref(), msg).await {
             Ok(v) => v,
             Err(_) => return Ok(()),
         };
@@ -244,7 +336,7 @@ impl ChangeNode {
                     (Variant::String(_), ReducedType::Num | ReducedType::Bool | ReducedType::Str)
                         if current == from_value =>
                     {
-                        let ctx_prop = crate::runtime::context::evaluate_key(&rule.p)?;
+                        let ctx_prop = expect_context_key(rule.p_key.as_ref(), &rule.p)?;
                         ctx.set_one(
                             ctx_prop.store,
                             ctx_prop.key,
@@ -262,7 +354,7 @@ impl ChangeNode {
                             (Some(RedPropertyType::Bool), "false") => to_value,
                             _ => Variant::String(replaced.into()),
                         };
-                        let ctx_prop = crate::runtime::context::evaluate_key(&rule.p)?;
+                        let ctx_prop = expect_context_key(rule.p_key.as_ref(), &rule.p)?;
                         ctx.set_one(
                             ctx_prop.store,
                             ctx_prop.key,
@@ -276,7 +368,7 @@ impl ChangeNode {
                         // Otherwise we search and replace
                         // TODO: In the future, this string needs to be optimized.
                         let replaced = cs.replace(from_value.to_string()?.as_str(), to_value.to_string()?.as_str());
-                        let ctx_prop = crate::runtime::context::evaluate_key(&rule.p)?;
+                        let ctx_prop = expect_context_key(rule.p_key.as_ref(), &rule.p)?;
                         ctx.set_one(
                             ctx_prop.store,
                             ctx_prop.key,
@@ -287,7 +379,7 @@ impl ChangeNode {
                     }
 
                     (Variant::Number(_), ReducedType::Num) if from_value == current => {
-                        let ctx_prop = crate::runtime::context::evaluate_key(&rule.p)?;
+                        let ctx_prop = expect_context_key(rule.p_key.as_ref(), &rule.p)?;
                         ctx.set_one(
                             ctx_prop.store,
                             ctx_prop.key,
@@ -298,7 +390,7 @@ impl ChangeNode {
                     }
 
                     (Variant::Bool(_), ReducedType::Bool) if from_value == current => {
-                        let ctx_prop = crate::runtime::context::evaluate_key(&rule.p)?;
+                        let ctx_prop = expect_context_key(rule.p_key.as_ref(), &rule.p)?;
                         ctx.set_one(
                             ctx_prop.store,
                             ctx_prop.key,
@@ -329,7 +421,7 @@ impl ChangeNode {
 
     async fn apply_rule_delete(&self, rule: &Rule, msg: &mut Msg) -> crate::Result<()> {
         assert!(rule.t == RuleKind::Delete);
-        self.delete_property(&rule.p, rule.pt, msg).await
+        self.delete_property(&rule.p, rule.pt, rule.p_key.as_ref(), msg).await
     } // apply_rule_delete
 
     async fn apply_rule_move(&self, rule: &Rule, msg: &mut Msg) -> crate::Result<()> {
@@ -350,15 +442,67 @@ impl ChangeNode {
         };
 
         // let target_prop = rule.to.as_ref().unwrap().as_str();
-        let current = match eval::evaluate_node_property(&rule.p, rule.pt, Some(self), None, Some(msg)).await {
+        let current = match self.evaluate_property(&rule.p, rule.pt, rule.p_key.as_ref(), msg).await {
             Ok(v) => v,
             Err(_) => return Ok(()),
         };
         // Remove the from side
-        self.set_property(&rule.p, rule.pt, None, msg).await?;
-        self.set_property(to, tot, Some(current), msg).await
+        self.delete_property(&rule.p, rule.pt, rule.p_key.as_ref(), msg).await?;
+        self.set_property(to, tot, rule.to_key.as_ref(), Some(current), msg).await
     } // apply_rule_move
 
+    async fn apply_rule_inc_dec(&self, rule: &Rule, msg: &mut Msg, to_value: Option<Variant>) -> crate::Result<()> {
+        assert!(matches!(rule.t, RuleKind::Inc | RuleKind::Dec));
+
+        // The delta defaults to 1 if the `to` is absent
+        let delta = match to_value {
+            Some(v) => {
+                to_numeric(&v).ok_or(EdgelinkError::InvalidOperation(format!("The delta is not a number: {:?}", v)))?
+            }
+            None => serde_json::Number::from(1),
+        };
+
+        // The missing property counts from zero, so it will be created from the delta
+        let current = match rule.pt {
+            RedPropertyType::Msg => msg.get_nav_stripped(&rule.p).cloned(),
+            RedPropertyType::Flow | RedPropertyType::Global => {
+                let ctx = self.get_context_by_property_type(rule.pt)?;
+                let ctx_prop = expect_context_key(rule.p_key.as_ref(), &rule.p)?;
+                let env = [PropexEnv::ExtRef("msg", msg.as_variant())];
+                ctx.try_get_one(ctx_prop.store, ctx_prop.key, &env).await?
+            }
+            _ => {
+                return Err(EdgelinkError::InvalidOperation(format!(
+                    "Cannot increase or decrease the property of type '{:?}'",
+                    rule.pt
+                ))
+                .into());
+            }
+        };
+        let current = match current {
+            None | Some(Variant::Null) => serde_json::Number::from(0),
+            Some(v) => to_numeric(&v).ok_or(EdgelinkError::InvalidOperation(format!(
+                "The property '{}' is not a number: {:?}",
+                rule.p, v
+            )))?,
+        };
+
+        let sign = if rule.t == RuleKind::Inc { 1 } else { -1 };
+        let int_result = match (current.as_i64(), delta.as_i64()) {
+            (Some(a), Some(b)) => b.checked_mul(sign).and_then(|b| a.checked_add(b)),
+            _ => None,
+        };
+        let result = match int_result {
+            Some(x) => Variant::from(x),
+            None => {
+                let a = current.as_f64().unwrap_or(0.0);
+                let b = delta.as_f64().unwrap_or(0.0);
+                Variant::from(a + b * sign as f64)
+            }
+        };
+        self.set_property(&rule.p, rule.pt, rule.p_key.as_ref(), Some(result), msg).await
+    } // apply_rule_inc_dec
+
     fn get_context_by_property_type(&self, pt: RedPropertyType) -> crate::Result<Arc<Context>> {
         let res = match pt {
             RedPropertyType::Flow => self.flow().map(|x| x.context()),
@@ -372,6 +516,7 @@ impl ChangeNode {
         &self,
         target_prop: &str,
         target_type: RedPropertyType,
+        target_key: Option<&ContextKeyRef>,
         to_value: Option<Variant>,
         msg: &mut Msg,
     ) -> crate::Result<()> {
@@ -393,7 +538,7 @@ impl ChangeNode {
             RedPropertyType::Global | RedPropertyType::Flow => {
                 let ctx = self.get_context_by_property_type(target_type)?;
                 if let Some(to_value) = to_value {
-                    let ctx_prop = crate::runtime::context::evaluate_key(target_prop)?;
+                    let ctx_prop = expect_context_key(target_key, target_prop)?;
                     ctx.set_one(
                         ctx_prop.store,
                         ctx_prop.key,
@@ -413,18 +558,23 @@ impl ChangeNode {
         }
     }
 
-    async fn delete_property(&self, prop: &str, prop_type: RedPropertyType, msg: &mut Msg) -> crate::Result<()> {
+    async fn delete_property(
+        &self,
+        prop: &str,
+        prop_type: RedPropertyType,
+        prop_key: Option<&ContextKeyRef>,
+        msg: &mut Msg,
+    ) -> crate::Result<()> {
         match prop_type {
             RedPropertyType::Msg => {
-                let _ = msg
-                    .remove_nav(prop)
-                    .ok_or(EdgelinkError::NotSupported(format!("cannot remove the property '{}' in the msg", prop)))?;
+                // Deleting a missing property is a no-op like Node-RED
+                let _ = msg.delete_nav(prop)?;
                 Ok(())
             }
 
             RedPropertyType::Global | RedPropertyType::Flow => {
                 let ctx = self.get_context_by_property_type(prop_type)?;
-                let ctx_prop = crate::runtime::context::evaluate_key(prop)?;
+                let ctx_prop = expect_context_key(prop_key, prop)?;
                 ctx.set_one(ctx_prop.store, ctx_prop.key, None, &[PropexEnv::ExtRef("msg", msg.as_variant())]).await
                 // Setting it to "None" means to delete.
             }
@@ -437,6 +587,23 @@ impl ChangeNode {
     } // apply_rule_delete
 }
 
+/// Coerces numbers, numeric strings and booleans into a number, like the unary plus in JS.
+fn to_numeric(v: &Variant) -> Option<serde_json::Number> {
+    match v {
+        Variant::Number(n) => Some(n.clone()),
+        Variant::Bool(b) => Some(serde_json::Number::from(*b as i64)),
+        Variant::String(s) => {
+            let s = s.trim();
+            if let Ok(i) = s.parse::<i64>() {
+                Some(serde_json::Number::from(i))
+            } else {
+                s.parse::<f64>().ok().and_then(serde_json::Number::from_f64)
+            }
+        }
+        _ => None,
+    }
+}
+
 fn handle_legacy_json(n: Value) -> crate::Result<Value> {
     let mut rules: Vec<Value> = if let Some(Value::Array(existed_rules)) = n.get("rules") {
         existed_rules.to_vec()
@@ -572,3 +739,121 @@ fn handle_legacy_json(n: Value) -> crate::Result<Value> {
     changed["rules"] = Value::Array(rules);
     Ok(changed)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::context::KEY_PARSE_COUNT;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_context_keys_should_be_parsed_once_per_rule() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "change", "wires": [["2"]], "rules": [
+                {"t": "set", "p": "#:(memory)::last", "pt": "flow", "to": "payload", "tot": "msg"},
+                {"t": "inc", "p": "count", "pt": "global"},
+                {"t": "set", "p": "topic", "pt": "msg", "to": "count", "tot": "global"},
+            ]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject: Vec<(ElementId, Msg)> =
+            (0..10).map(|i| Deserialize::deserialize(json!(["1", {"payload": i}])).unwrap()).collect();
+
+        let parsed_before = KEY_PARSE_COUNT.with(|x| x.get());
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let parsed_by_build = KEY_PARSE_COUNT.with(|x| x.get()) - parsed_before;
+        assert_eq!(parsed_by_build, 3);
+
+        // The keys are parsed by the building, the messages are processed with them as they are
+        let node = engine.find_flow_node_by_id(&ElementId::with_u64(1)).unwrap();
+        let rules = &node.as_any().downcast_ref::<ChangeNode>().unwrap().config.rules;
+        let parsed_keys: Vec<_> = rules.iter().map(|x| (x.p_key.clone(), x.to_key.clone())).collect();
+        let key = |store: Option<&str>, key: &str| ContextKeyRef { store: store.map(Into::into), key: key.into() };
+        assert_eq!(
+            parsed_keys,
+            vec![
+                (Some(key(Some("memory"), "last")), None),
+                (Some(key(None, "count")), None),
+                (None, Some(key(None, "count")))
+            ]
+        );
+
+        let msgs =
+            engine.run_once_with_inject(10, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(KEY_PARSE_COUNT.with(|x| x.get()) - parsed_before, parsed_by_build);
+
+        let topics: Vec<i64> = msgs.iter().map(|x| x["topic"].as_i64().unwrap()).collect();
+        assert_eq!(topics, (1..=10).collect::<Vec<_>>());
+        let flow = engine.get_flow(&ElementId::with_u64(0x100)).unwrap();
+        assert_eq!(flow.context().get_one(Some("memory"), "last", &[]).await, Some(Variant::from(9)));
+        assert_eq!(engine.context().get_one(None, "count", &[]).await, Some(Variant::from(10)));
+    }
+
+    #[tokio::test]
+    async fn test_context_inc_should_not_lose_updates_with_concurrency() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "change", "concurrency": 8, "wires": [["2"]], "rules": [
+                {"t": "inc", "p": "count", "pt": "flow"},
+                {"t": "set", "p": "topic", "pt": "msg", "to": "count", "tot": "flow"},
+            ]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject: Vec<(ElementId, Msg)> =
+            (0..50).map(|i| Deserialize::deserialize(json!(["1", {"payload": i}])).unwrap()).collect();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let node = engine.find_flow_node_by_id(&ElementId::with_u64(1)).unwrap();
+        assert_eq!(node.get_node().concurrency, 1);
+        let msgs =
+            engine.run_once_with_inject(50, std::time::Duration::from_secs_f64(0.5), msgs_to_inject).await.unwrap();
+
+        let mut topics: Vec<i64> = msgs.iter().map(|x| x["topic"].as_i64().unwrap()).collect();
+        topics.sort();
+        assert_eq!(topics, (1..=50).collect::<Vec<_>>());
+        let flow = engine.get_flow(&ElementId::with_u64(0x100)).unwrap();
+        assert_eq!(flow.context().get_one(None, "count", &[]).await, Some(Variant::from(50)));
+    }
+
+    #[tokio::test]
+    async fn test_it_should_set_jsonata_results() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "change", "wires": [["2"]], "rules": [
+                {"t": "set", "p": "rate", "pt": "flow", "to": "2", "tot": "num"},
+                {"t": "set", "p": "total", "pt": "msg", "tot": "jsonata",
+                    "to": "$sum(payload.price) * $flowContext('rate')"},
+                {"t": "set", "p": "names", "pt": "msg", "to": "$join(payload[price > 1].name, ',')", "tot": "jsonata"},
+            ]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"payload": [{"name": "a", "price": 1.5}, {"name": "b", "price": 2}, {"name": "c", "price": 0.5}]}],
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        // The expressions are parsed by the building
+        let node = engine.find_flow_node_by_id(&ElementId::with_u64(1)).unwrap();
+        let rules = &node.as_any().downcast_ref::<ChangeNode>().unwrap().config.rules;
+        assert_eq!(rules.iter().map(|x| x.to_expr.is_some()).collect::<Vec<_>>(), vec![false, true, true]);
+
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0]["total"], Variant::from(8));
+        assert_eq!(msgs[0]["names"], Variant::from("a,b"));
+    }
+
+    #[test]
+    fn test_bad_jsonata_should_fail_to_build() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "change", "rules": [
+                {"t": "set", "p": "total", "pt": "msg", "to": "$sum(payload.price", "tot": "jsonata"},
+            ]}
+        ]);
+        assert!(crate::runtime::engine::build_test_engine(flows_json).is_err());
+    }
+}
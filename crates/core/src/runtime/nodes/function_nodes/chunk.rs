@@ -0,0 +1,220 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+#[derive(Debug, Clone, Deserialize)]
+struct ChunkNodeConfig {
+    #[serde(default = "property_default")]
+    property: String,
+
+    /// The max bytes of a chunk, the strings are measured in UTF-8
+    #[serde(deserialize_with = "json::deser::str_to_option_usize")]
+    size: Option<usize>,
+}
+
+fn property_default() -> String {
+    "payload".to_string()
+}
+
+/// Splits a large string or buffer into the chunks of a bounded size, sent as a sequence which the `join` node in the
+/// automatic mode reassembles. The strings are only split on the character boundaries.
+#[derive(Debug)]
+#[flow_node("chunk")]
+struct ChunkNode {
+    base: FlowNode,
+    config: ChunkNodeConfig,
+    size: usize,
+}
+
+impl ChunkNode {
+    fn build(
+        _flow: &Flow,
+        base_node: FlowNode,
+        config: &RedFlowNodeConfig,
+    ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let chunk_config = ChunkNodeConfig::deserialize(&config.rest)?;
+        let size = chunk_config
+            .size
+            .filter(|x| *x > 0)
+            .ok_or(EdgelinkError::BadFlowsJson("The size of the chunk node must be a positive integer".into()))?;
+        let node = ChunkNode { base: base_node, config: chunk_config, size };
+        Ok(Box::new(node))
+    }
+
+    fn make_chunks(&self, origin: &Msg) -> crate::Result<Vec<MsgHandle>> {
+        let (kind, chunks) = match origin.get_nav_stripped(&self.config.property) {
+            Some(Variant::String(text)) => {
+                ("string", chunk_str(text, self.size).into_iter().map(Variant::from).collect::<Vec<_>>())
+            }
+            Some(Variant::Bytes(bytes)) => ("buffer", bytes.chunks(self.size).map(Variant::from).collect()),
+            Some(other) => {
+                return Err(EdgelinkError::NotSupported(format!(
+                    "The chunk node only splits strings and buffers, got: {:?}",
+                    other
+                ))
+                .into())
+            }
+            // Nothing to split, pass it through
+            None => return Ok(vec![MsgHandle::new(origin.clone())]),
+        };
+
+        let id = Msg::generate_id().to_string();
+        let count = chunks.len();
+        let mut msgs = Vec::with_capacity(count);
+        for (index, chunk) in chunks.into_iter().enumerate() {
+            let mut new_msg = origin.clone();
+            new_msg.set_nav_stripped(&self.config.property, chunk, true)?;
+            let mut parts = VariantObjectMap::from([
+                ("id".to_string(), Variant::String(id.clone())),
+                ("type".to_string(), Variant::from(kind)),
+                ("index".to_string(), Variant::from(index as u64)),
+                ("count".to_string(), Variant::from(count as u64)),
+                ("len".to_string(), Variant::from(self.size as u64)),
+            ]);
+            if kind == "string" {
+                parts.insert("ch".to_string(), Variant::from(""));
+            }
+            // Keeps the parts of the outer sequence
+            if let Some(outer_parts) = origin.get("parts") {
+                parts.insert("parts".to_string(), outer_parts.clone());
+            }
+            new_msg.set("parts".to_string(), Variant::Object(parts));
+            new_msg.set_id(Msg::generate_id());
+            msgs.push(MsgHandle::new(new_msg));
+        }
+        Ok(msgs)
+    }
+}
+
+/// Splits the string into the pieces of at most `size` bytes without breaking any character, a character longer than
+/// `size` makes a piece alone. An empty string gives one empty piece.
+fn chunk_str(text: &str, size: usize) -> Vec<&str> {
+    let mut chunks = Vec::with_capacity(text.len() / size + 1);
+    let mut rest = text;
+    while rest.len() > size {
+        let mut end = size;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        if end == 0 {
+            end = rest.chars().next().map_or(rest.len(), char::len_utf8);
+        }
+        let (chunk, tail) = rest.split_at(end);
+        chunks.push(chunk);
+        rest = tail;
+    }
+    chunks.push(rest);
+    chunks
+}
+
+#[async_trait]
+impl FlowNodeBehavior for ChunkNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow(self.as_ref(), cancel.child_token(), |node, msg| async move {
+                let msgs_to_send = node.make_chunks(&*msg.read().await)?;
+                // Sends one by one to keep the sequence in order
+                for msg in msgs_to_send.into_iter() {
+                    node.fan_out_one(Envelope { port: 0, msg }, cancel.clone()).await?;
+                }
+                Ok(())
+            })
+            .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+    use std::time::Duration;
+
+    #[test]
+    fn test_chunk_str_should_keep_char_boundaries() {
+        assert_eq!(chunk_str("abcdefg", 3), vec!["abc", "def", "g"]);
+        assert_eq!(chunk_str("", 3), vec![""]);
+        // "é" is 2 bytes and "你" is 3 bytes
+        assert_eq!(chunk_str("aéé你", 3), vec!["aé", "é", "你"]);
+        // A character longer than the size
+        assert_eq!(chunk_str("a🌍b", 2), vec!["a", "🌍", "b"]);
+    }
+
+    #[tokio::test]
+    async fn test_it_should_chunk_and_rejoin_buffer() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "chunk", "size": "4", "wires": [["2", "3"]]},
+            {"id": "2", "z": "100", "type": "join", "mode": "auto", "wires": [["4"]]},
+            {"id": "3", "z": "100", "type": "test-once"},
+            {"id": "4", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let mut msg = Msg::deserialize(json!({"topic": "buffer"})).unwrap();
+        msg.set("payload".to_string(), Variant::Bytes((0..10).collect()));
+        let msgs_to_inject = vec![(ElementId::with_u64(1), msg)];
+        let msgs = engine.run_once_with_inject(4, Duration::from_secs_f64(0.3), msgs_to_inject).await.unwrap();
+
+        let chunks: Vec<&Msg> = msgs.iter().filter(|x| x.contains("parts")).collect();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0]["payload"], Variant::Bytes(vec![0, 1, 2, 3]));
+        assert_eq!(chunks[2]["payload"], Variant::Bytes(vec![8, 9]));
+        assert_eq!(chunks[2].get_nav_stripped("parts.index"), Some(&Variant::from(2)));
+        assert!(chunks.iter().all(|x| x.get_nav_stripped("parts.count") == Some(&Variant::from(3))));
+
+        let joined = msgs.iter().find(|x| !x.contains("parts")).unwrap();
+        assert_eq!(joined["payload"], Variant::Bytes((0..10).collect()));
+        assert_eq!(joined["topic"], Variant::from("buffer"));
+    }
+
+    #[tokio::test]
+    async fn test_it_should_chunk_and_rejoin_multibyte_string() {
+        let text = "héllo, 世界! 🌍🌍";
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "chunk", "size": 5, "wires": [["2", "3"]]},
+            {"id": "2", "z": "100", "type": "join", "mode": "auto", "wires": [["4"]]},
+            {"id": "3", "z": "100", "type": "test-once"},
+            {"id": "4", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": text}]])).unwrap();
+        let chunk_count = chunk_str(text, 5).len();
+        let msgs =
+            engine.run_once_with_inject(chunk_count + 1, Duration::from_secs_f64(0.3), msgs_to_inject).await.unwrap();
+
+        let chunks: Vec<&str> =
+            msgs.iter().filter(|x| x.contains("parts")).map(|x| x["payload"].as_str().unwrap()).collect();
+        assert_eq!(chunks.len(), chunk_count);
+        assert!(chunks.iter().all(|x| !x.is_empty() && x.len() <= 5));
+        assert_eq!(chunks.concat(), text);
+
+        let joined = msgs.iter().find(|x| !x.contains("parts")).unwrap();
+        assert_eq!(joined["payload"], Variant::from(text));
+    }
+
+    #[test]
+    fn test_bad_size_should_fail_to_build() {
+        for size in [json!(0), json!("x"), json!("")] {
+            let flows_json = json!([
+                {"id": "100", "type": "tab"},
+                {"id": "1", "z": "100", "type": "chunk", "size": size, "wires": [["2", "3"]]},
+                {"id": "2", "z": "100", "type": "join", "mode": "auto", "wires": [["4"]]},
+                {"id": "3", "z": "100", "type": "test-once"},
+                {"id": "4", "z": "100", "type": "test-once"}
+            ]);
+            assert!(crate::runtime::engine::build_test_engine(flows_json).is_err());
+        }
+    }
+}
@@ -0,0 +1,542 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use serde::Deserialize;
+use smallvec::SmallVec;
+
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+/// How to represent the empty cells
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum EmptyCell {
+    #[default]
+    #[serde(rename = "null")]
+    Null,
+
+    #[serde(rename = "string")]
+    String,
+}
+
+/// When to write the header row in the serialization
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum HeaderOutput {
+    #[default]
+    None,
+
+    /// Every message
+    All,
+
+    /// The first message only, until a message with `msg.reset`
+    Once,
+}
+
+fn deser_hdrout<'de, D>(deserializer: D) -> Result<HeaderOutput, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Bool(true) => Ok(HeaderOutput::All),
+        serde_json::Value::Bool(false) | serde_json::Value::Null => Ok(HeaderOutput::None),
+        serde_json::Value::String(s) => match s.as_str() {
+            "all" | "true" => Ok(HeaderOutput::All),
+            "once" => Ok(HeaderOutput::Once),
+            "none" | "false" | "" => Ok(HeaderOutput::None),
+            _ => Err(serde::de::Error::invalid_value(serde::de::Unexpected::Str(&s), &"'all', 'once' or 'none'")),
+        },
+        other => Err(serde::de::Error::custom(format!("Bad `hdrout`: {}", other))),
+    }
+}
+
+/// Sends the parsed rows in a single message or a sequence of messages
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum MultiMode {
+    #[default]
+    #[serde(rename = "one")]
+    One,
+
+    #[serde(rename = "mult")]
+    Mult,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct CsvNodeConfig {
+    #[serde(default = "sep_default")]
+    sep: String,
+
+    /// The first row contains the column names
+    #[serde(default)]
+    hdrin: bool,
+
+    /// The comma separated column names, the unnamed columns will be named as `col1`, `col2`...
+    #[serde(default)]
+    temp: String,
+
+    /// Coerces the unquoted cells into numbers and booleans
+    #[serde(default)]
+    typed: bool,
+
+    #[serde(default)]
+    empty: EmptyCell,
+
+    /// Writes the column names as the first row in the serialization
+    #[serde(default, deserialize_with = "deser_hdrout")]
+    hdrout: HeaderOutput,
+
+    #[serde(default)]
+    multi: MultiMode,
+
+    /// The line ending of the serialization, the `\r` and `\n` are escaped
+    #[serde(default = "ret_default")]
+    ret: String,
+}
+
+fn sep_default() -> String {
+    ",".to_string()
+}
+
+fn ret_default() -> String {
+    "\\n".to_string()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CsvCell {
+    text: String,
+    quoted: bool,
+}
+
+#[derive(Debug)]
+#[flow_node("csv")]
+struct CsvNode {
+    base: FlowNode,
+    config: CsvNodeConfig,
+    sep: u8,
+    template: Vec<String>,
+    terminator: csv::Terminator,
+    header_sent: AtomicBool,
+}
+
+impl CsvNode {
+    fn build(
+        _flow: &Flow,
+        base_node: FlowNode,
+        config: &RedFlowNodeConfig,
+    ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let csv_config = CsvNodeConfig::deserialize(&config.rest)?;
+        let sep = match csv_config.sep.as_bytes() {
+            b"\\t" => b'\t',
+            [c] if c.is_ascii() => *c,
+            _ => {
+                return Err(EdgelinkError::BadFlowsJson(format!(
+                    "Bad CSV separator, it must be an ASCII character: '{}'",
+                    csv_config.sep
+                ))
+                .into())
+            }
+        };
+        let template = if csv_config.temp.trim().is_empty() {
+            Vec::new()
+        } else {
+            parse_csv(&csv_config.temp, b',')?
+                .into_iter()
+                .next()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|x| x.text)
+                .collect()
+        };
+        let terminator = match csv_config.ret.replace("\\r", "\r").replace("\\n", "\n").as_str() {
+            "\r\n" => csv::Terminator::CRLF,
+            "\n" => csv::Terminator::Any(b'\n'),
+            "\r" => csv::Terminator::Any(b'\r'),
+            _ => return Err(EdgelinkError::BadFlowsJson(format!("Bad CSV line ending: '{}'", csv_config.ret)).into()),
+        };
+        let node = CsvNode {
+            base: base_node,
+            config: csv_config,
+            sep,
+            template,
+            terminator,
+            header_sent: AtomicBool::new(false),
+        };
+        Ok(Box::new(node))
+    }
+
+    /// Parses the rows to the objects, and the column names used.
+    fn parse_payload(&self, text: &str) -> crate::Result<(Vec<Variant>, Vec<String>)> {
+        let mut rows = parse_csv(text, self.sep)?.into_iter();
+        let mut columns = self.template.clone();
+        if self.config.hdrin {
+            if let Some(header) = rows.next() {
+                columns = header.into_iter().map(|x| x.text).collect();
+            }
+        }
+
+        let mut max_len = columns.len();
+        let objects = rows
+            .map(|row| {
+                max_len = max_len.max(row.len());
+                let mut obj = VariantObjectMap::new();
+                for (i, cell) in row.into_iter().enumerate() {
+                    obj.insert(column_name(&columns, i), self.cell_to_variant(cell));
+                }
+                Variant::Object(obj)
+            })
+            .collect();
+        let columns = (0..max_len).map(|i| column_name(&columns, i)).collect();
+        Ok((objects, columns))
+    }
+
+    /// Serializes the array of objects or arrays to the CSV text, a single object is a single row.
+    fn serialize_payload(&self, payload: &Variant, reset: bool) -> crate::Result<String> {
+        let rows = match payload {
+            Variant::Array(items) => items.as_slice(),
+            _ => std::slice::from_ref(payload),
+        };
+        let columns = if self.template.iter().any(|x| !x.is_empty()) {
+            self.template.iter().enumerate().map(|(i, _)| column_name(&self.template, i)).collect()
+        } else {
+            // The properties of the first object, they're sorted by name
+            match rows.first() {
+                Some(Variant::Object(obj)) => obj.keys().cloned().collect(),
+                _ => Vec::new(),
+            }
+        };
+
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(self.sep)
+            .terminator(self.terminator)
+            .flexible(true)
+            .from_writer(Vec::new());
+        let write_header = match self.config.hdrout {
+            HeaderOutput::None => false,
+            HeaderOutput::All => true,
+            HeaderOutput::Once => !self.header_sent.swap(true, Ordering::Relaxed) || reset,
+        };
+        if write_header && !columns.is_empty() {
+            writer.write_record(&columns)?;
+        }
+        for row in rows {
+            let cells: Vec<String> = match row {
+                Variant::Object(obj) => columns.iter().map(|x| cell_to_string(obj.get(x))).collect(),
+                Variant::Array(items) => items.iter().map(|x| cell_to_string(Some(x))).collect(),
+                other => vec![cell_to_string(Some(other))],
+            };
+            writer.write_record(&cells)?;
+        }
+        let bytes = writer.into_inner().map_err(|e| EdgelinkError::InvalidOperation(e.to_string()))?;
+        Ok(String::from_utf8(bytes)?)
+    }
+
+    fn cell_to_variant(&self, cell: CsvCell) -> Variant {
+        if cell.text.is_empty() {
+            return match self.config.empty {
+                EmptyCell::Null => Variant::Null,
+                EmptyCell::String => Variant::String(cell.text),
+            };
+        }
+        let value = Variant::String(cell.text);
+        // The quoted cells are always strings, e.g. `"00123"`
+        if self.config.typed && !cell.quoted {
+            if let Some(n) = value.coerce_number() {
+                return n;
+            }
+            if let Some(b) = value.coerce_bool() {
+                return Variant::Bool(b);
+            }
+        }
+        value
+    }
+
+    async fn receive(&self, msg: MsgHandle, cancel: CancellationToken) -> crate::Result<()> {
+        let mut msg_guard = msg.write().await;
+        let reset = msg_guard.remove("reset").is_some();
+        let (objects, columns) = match msg_guard.get("payload") {
+            Some(Variant::String(text)) => self.parse_payload(text)?,
+            Some(payload @ (Variant::Array(_) | Variant::Object(_))) => {
+                let text = self.serialize_payload(payload, reset)?;
+                msg_guard.set("payload".into(), Variant::String(text));
+                drop(msg_guard);
+                return self.fan_out_one(Envelope { port: 0, msg }, cancel).await;
+            }
+            Some(other) => {
+                return Err(EdgelinkError::NotSupported(format!(
+                    "The csv node only supports strings, arrays and objects, got: {:?}",
+                    other
+                ))
+                .into())
+            }
+            None if reset => {
+                // Only resets the header of the serialization
+                self.header_sent.store(false, Ordering::Relaxed);
+                return Ok(());
+            }
+            None => return Err(EdgelinkError::InvalidOperation("No `msg.payload` to parse".into()).into()),
+        };
+        msg_guard.set("columns".into(), Variant::String(columns.join(",")));
+
+        match self.config.multi {
+            MultiMode::One => {
+                msg_guard.set("payload".into(), Variant::Array(objects));
+                drop(msg_guard);
+                self.fan_out_one(Envelope { port: 0, msg }, cancel).await
+            }
+            MultiMode::Mult => {
+                let seq_id = Msg::generate_id().to_string();
+                let count = objects.len();
+                let mut envelopes: SmallVec<[Envelope; 4]> = SmallVec::new();
+                for (index, obj) in objects.into_iter().enumerate() {
+                    let mut row_msg = msg_guard.clone();
+                    row_msg.set("payload".into(), obj);
+                    let parts = VariantObjectMap::from([
+                        ("id".to_string(), Variant::String(seq_id.clone())),
+                        ("index".to_string(), Variant::from(index as u64)),
+                        ("count".to_string(), Variant::from(count as u64)),
+                    ]);
+                    row_msg.set("parts".into(), Variant::Object(parts));
+                    row_msg.set_id(Msg::generate_id());
+                    envelopes.push(Envelope { port: 0, msg: MsgHandle::new(row_msg) });
+                }
+                drop(msg_guard);
+                self.fan_out_many(envelopes, cancel).await
+            }
+        }
+    }
+}
+
+/// The name of the column in the template, or `col1`, `col2`... if it's unnamed.
+fn column_name(columns: &[String], index: usize) -> String {
+    match columns.get(index).filter(|x| !x.is_empty()) {
+        Some(name) => name.clone(),
+        None => format!("col{}", index + 1),
+    }
+}
+
+fn cell_to_string(value: Option<&Variant>) -> String {
+    match value {
+        None | Some(Variant::Null) => String::new(),
+        Some(Variant::String(s)) => s.clone(),
+        Some(value @ (Variant::Number(_) | Variant::Bool(_))) => value.to_string().unwrap_or_default(),
+        Some(other) => serde_json::to_string(other).unwrap_or_default(),
+    }
+}
+
+const CSV_QUOTE: u8 = b'"';
+
+/// Parses the CSV text in RFC 4180 by the `csv` crate, the blank lines are skipped.
+///
+/// The crate removes the quotes of the cells, so whether a cell is quoted is found in the raw text of its record,
+/// which is located by the positions of the reader before and after reading it.
+fn parse_csv(text: &str, sep: u8) -> crate::Result<Vec<Vec<CsvCell>>> {
+    let bytes = text.as_bytes();
+    let mut reader =
+        csv::ReaderBuilder::new().delimiter(sep).quote(CSV_QUOTE).has_headers(false).flexible(true).from_reader(bytes);
+    let mut record = csv::StringRecord::new();
+    let mut rows = Vec::new();
+    loop {
+        let start = reader.position().byte() as usize;
+        if !reader.read_record(&mut record)? {
+            break;
+        }
+        let end = (reader.position().byte() as usize).min(bytes.len());
+        let quoted = quoted_fields(&bytes[start..end], sep);
+        let row = record
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| CsvCell { text: cell.to_string(), quoted: quoted.get(i).copied().unwrap_or(false) })
+            .collect();
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+/// Finds whether each field of the raw record is quoted, a field is quoted only if it starts with the quote.
+fn quoted_fields(raw: &[u8], sep: u8) -> Vec<bool> {
+    // Skips the blank lines before the record
+    let first = raw.iter().position(|x| !matches!(x, b'\r' | b'\n')).unwrap_or(raw.len());
+    let mut quoted = Vec::new();
+    let mut field_start = true;
+    let mut in_quotes = false;
+    let mut i = first;
+    while i < raw.len() {
+        let b = raw[i];
+        if field_start {
+            field_start = false;
+            in_quotes = b == CSV_QUOTE;
+            quoted.push(in_quotes);
+            if in_quotes {
+                i += 1;
+                continue;
+            }
+        }
+        if in_quotes {
+            if b == CSV_QUOTE {
+                // A doubled quote is an escaped one
+                if raw.get(i + 1) == Some(&CSV_QUOTE) {
+                    i += 1;
+                } else {
+                    in_quotes = false;
+                }
+            }
+        } else if b == sep {
+            field_start = true;
+        } else if matches!(b, b'\r' | b'\n') {
+            break;
+        }
+        i += 1;
+    }
+    // The empty last field, e.g. the one after the trailing separator
+    if field_start {
+        quoted.push(false);
+    }
+    quoted
+}
+
+#[async_trait]
+impl FlowNodeBehavior for CsvNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow(self.as_ref(), cancel.child_token(), |node, msg| async move { node.receive(msg, cancel).await })
+                .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+    use std::time::Duration;
+
+    const CSV_TEXT: &str =
+        "id,name,score,active,note\r\n\"007\",Bob,3.5,true,\n8,\"Smith, Alice\",42,FALSE,\"\"\"quoted\"\"\"\n";
+
+    async fn parse_with(typed: bool, empty: &str) -> Variant {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "csv", "sep": ",", "hdrin": true, "typed": typed, "empty": empty,
+                "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": CSV_TEXT}]])).unwrap();
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(1, Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        msgs[0]["payload"].clone()
+    }
+
+    #[tokio::test]
+    async fn test_it_should_parse_cells_as_strings_if_untyped() {
+        let expected = Variant::deserialize(json!([
+            {"id": "007", "name": "Bob", "score": "3.5", "active": "true", "note": null},
+            {"id": "8", "name": "Smith, Alice", "score": "42", "active": "FALSE", "note": "\"quoted\""}
+        ]))
+        .unwrap();
+        assert_eq!(parse_with(false, "null").await, expected);
+    }
+
+    #[tokio::test]
+    async fn test_it_should_coerce_unquoted_cells_if_typed() {
+        let expected = Variant::deserialize(json!([
+            {"id": "007", "name": "Bob", "score": 3.5, "active": true, "note": ""},
+            {"id": 8, "name": "Smith, Alice", "score": 42, "active": false, "note": "\"quoted\""}
+        ]))
+        .unwrap();
+        assert_eq!(parse_with(true, "string").await, expected);
+    }
+
+    #[tokio::test]
+    async fn test_parsed_rows_should_serialize_back_to_csv() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "csv", "sep": ",", "hdrin": true, "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "csv", "sep": ",", "temp": "id,name,score,active,note", "hdrout": "all",
+                "ret": "\\r\\n", "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": CSV_TEXT}]])).unwrap();
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(1, Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        let expected =
+            "id,name,score,active,note\r\n007,Bob,3.5,true,\r\n8,\"Smith, Alice\",42,FALSE,\"\"\"quoted\"\"\"\r\n";
+        assert_eq!(msgs[0]["payload"], expected.into());
+        assert_eq!(msgs[0]["columns"], "id,name,score,active,note".into());
+    }
+
+    #[tokio::test]
+    async fn test_it_should_send_headerless_rows_one_by_one() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "csv", "sep": ";", "temp": "a,,c", "typed": true, "multi": "mult",
+                "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject =
+            Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": "1;x;\"y;z\";4\n5;;7"}]])).unwrap();
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(2, Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0]["payload"], Variant::from(json!({"a": 1, "col2": "x", "c": "y;z", "col4": 4})));
+        assert_eq!(msgs[1]["payload"], Variant::from(json!({"a": 5, "col2": null, "c": 7})));
+        assert_eq!(msgs[1].get_nav_stripped("parts.index"), Some(&Variant::from(1)));
+        assert_eq!(msgs[1].get_nav_stripped("parts.count"), Some(&Variant::from(2)));
+        assert_eq!(msgs[0]["columns"], "a,col2,c,col4".into());
+    }
+
+    #[tokio::test]
+    async fn test_it_should_write_the_header_once_until_reset() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "csv", "hdrout": "once", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"payload": [{"b": 1, "a": "x,y"}]}],
+            ["1", {"payload": {"b": true, "a": null}}],
+            ["1", {"payload": [[1, 2]], "reset": true}],
+        ]))
+        .unwrap();
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(3, Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0]["payload"], "a,b\n\"x,y\",1\n".into());
+        assert_eq!(msgs[1]["payload"], ",true\n".into());
+        // The rows of arrays have no columns
+        assert_eq!(msgs[2]["payload"], "1,2\n".into());
+        assert!(!msgs[2].contains("reset"));
+    }
+
+    #[test]
+    fn test_parse_csv_without_header_should_name_columns() {
+        let rows = parse_csv("1;\"a;b\"\n\n2;c", b';').unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0][1], CsvCell { text: "a;b".to_string(), quoted: true });
+        assert_eq!(rows[1][0], CsvCell { text: "2".to_string(), quoted: false });
+    }
+
+    #[test]
+    fn test_parse_csv_should_find_the_quoted_cells() {
+        let quoted_of = |text: &str| -> Vec<Vec<bool>> {
+            parse_csv(text, b',').unwrap().iter().map(|row| row.iter().map(|x| x.quoted).collect()).collect()
+        };
+        // The empty quoted cells and the escaped quotes
+        assert_eq!(
+            quoted_of("\"\",1,\"a\"\"b\",\"\"\"\"\n2,\"3\""),
+            vec![vec![true, false, true, true], vec![false, true]]
+        );
+        // The quotes inside the unquoted cells are literal
+        assert_eq!(quoted_of("a\"b,\"1\"\r\n"), vec![vec![false, true]]);
+        // The line breaks and separators inside the quoted cells
+        assert_eq!(quoted_of("\"x\r\n,y\",2,\"3\"\n\n4,"), vec![vec![true, false, true], vec![false, false]]);
+
+        let rows = parse_csv("\"\",\"a\"\"b\",7", b',').unwrap();
+        assert_eq!(rows[0][0], CsvCell { text: "".to_string(), quoted: true });
+        assert_eq!(rows[0][1], CsvCell { text: "a\"b".to_string(), quoted: true });
+        assert_eq!(rows[0][2], CsvCell { text: "7".to_string(), quoted: false });
+    }
+}
@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use itertools::Itertools;
+use serde::Deserialize;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use crate::utils;
+use edgelink_macro::*;
+
+/// How long to wait for the downstream nodes to accept the pending messages flushed when the node stops
+const FLUSH_ON_STOP_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum TimeoutUnits {
+    #[serde(rename = "milliseconds")]
+    Milliseconds,
+
+    #[default]
+    #[serde(rename = "seconds")]
+    Seconds,
+
+    #[serde(rename = "minutes")]
+    Minutes,
+
+    #[serde(rename = "hours")]
+    Hours,
+}
+
+impl TimeoutUnits {
+    fn as_secs_f64(&self) -> f64 {
+        match self {
+            TimeoutUnits::Milliseconds => 0.001,
+            TimeoutUnits::Seconds => 1.0,
+            TimeoutUnits::Minutes => 60.0,
+            TimeoutUnits::Hours => 60.0 * 60.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct DebounceNodeConfig {
+    /// The quiet period in `timeoutUnits`
+    #[serde(default, deserialize_with = "json::deser::str_to_option_f64")]
+    timeout: Option<f64>,
+
+    #[serde(rename = "timeoutUnits", default)]
+    timeout_units: TimeoutUnits,
+}
+
+/// The latest message of a topic waiting for the quiet period
+#[derive(Debug)]
+struct PendingMsg {
+    msg: MsgHandle,
+    received_at: Instant,
+    timer: JoinHandle<()>,
+}
+
+/// Sends the latest message of each `msg.topic` once no more messages of the topic arrived for the quiet period.
+///
+/// `msg.flush` sends all the pending messages immediately and `msg.reset` drops them, the pending messages are flushed
+/// when the node stops as well.
+#[derive(Debug)]
+#[flow_node("debounce")]
+struct DebounceNode {
+    base: FlowNode,
+    quiet_period: Duration,
+    pending: Mutex<HashMap<String, PendingMsg>>,
+}
+
+impl DebounceNode {
+    fn build(
+        _flow: &Flow,
+        base_node: FlowNode,
+        config: &RedFlowNodeConfig,
+    ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let debounce_config = DebounceNodeConfig::deserialize(&config.rest)?;
+        let timeout = debounce_config.timeout.unwrap_or(1.0);
+        let quiet_period = utils::time::delay_from_secs_f64(timeout * debounce_config.timeout_units.as_secs_f64())
+            .filter(|x| !x.is_zero())
+            .ok_or_else(|| {
+                EdgelinkError::BadFlowsJson(format!(
+                    "The quiet period of the debounce node must be greater than 0: {}",
+                    timeout
+                ))
+            })?;
+        let node = DebounceNode { base: base_node, quiet_period, pending: Mutex::new(HashMap::new()) };
+        Ok(Box::new(node))
+    }
+
+    async fn receive(self: &Arc<Self>, msg: MsgHandle, stop_token: CancellationToken) -> crate::Result<()> {
+        let (topic, flush, reset) = {
+            let msg_guard = msg.read().await;
+            // The topics of other types are keyed by their string form, e.g. `1` and `"1"` are the same topic
+            let topic = msg_guard.get("topic").and_then(|x| x.to_string().ok()).unwrap_or_default();
+            (topic, msg_guard.contains("flush"), msg_guard.contains("reset"))
+        };
+        if reset {
+            let dropped = self.take_pending().await;
+            log::debug!("[DEBOUNCE:{}] Dropped {} pending message(s) by the reset", self.name(), dropped.len());
+            return Ok(());
+        }
+        if flush {
+            for msg in self.take_pending().await {
+                self.fan_out_one(Envelope { port: 0, msg }, stop_token.child_token()).await?;
+            }
+            return Ok(());
+        }
+
+        let mut pending = self.pending.lock().await;
+        if let Some(previous) = pending.remove(&topic) {
+            previous.timer.abort();
+        }
+        let timer = self.start_timer(topic.clone(), stop_token);
+        pending.insert(topic, PendingMsg { msg, received_at: Instant::now(), timer });
+        Ok(())
+    }
+
+    /// Spawns the timer sending the pending message of the topic after the quiet period.
+    fn start_timer(self: &Arc<Self>, topic: String, stop_token: CancellationToken) -> JoinHandle<()> {
+        let node = self.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = stop_token.cancelled() => return,
+                _ = tokio::time::sleep(node.quiet_period) => {}
+            }
+
+            // The timer is aborted while holding the lock, so the pending message is still its own here
+            let pending = node.pending.lock().await.remove(&topic);
+            if let Some(pending) = pending {
+                let envelope = Envelope { port: 0, msg: pending.msg };
+                if let Err(e) = node.fan_out_one(envelope, stop_token.child_token()).await {
+                    log::warn!("[DEBOUNCE:{}] Failed to send the message of topic '{}': {}", node.name(), topic, e);
+                }
+            }
+        })
+    }
+
+    /// Removes all the pending messages in the order they were received, and cancels their timers.
+    async fn take_pending(&self) -> Vec<MsgHandle> {
+        let mut pending = self.pending.lock().await;
+        pending
+            .drain()
+            .map(|(_, x)| {
+                x.timer.abort();
+                x
+            })
+            .sorted_by_key(|x| x.received_at)
+            .map(|x| x.msg)
+            .collect()
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for DebounceNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            let this = self.clone();
+            with_uow(self.as_ref(), cancel.child_token(), |_, msg| async move { this.receive(msg, cancel).await })
+                .await;
+        }
+
+        // The nodes in other flows may still be running, so the pending messages are not lost silently
+        let pending = self.take_pending().await;
+        if !pending.is_empty() {
+            log::debug!("[DEBOUNCE:{}] Flushing {} pending message(s) on stop", self.name(), pending.len());
+        }
+        for msg in pending {
+            let sent = tokio::time::timeout(
+                FLUSH_ON_STOP_TIMEOUT,
+                self.fan_out_one(Envelope { port: 0, msg }, CancellationToken::new()),
+            )
+            .await;
+            if !matches!(sent, Ok(Ok(()))) {
+                log::warn!("[DEBOUNCE:{}] Failed to flush the pending message on stop", self.name());
+            }
+        }
+        log::debug!("DebounceNode process() task has been terminated.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    async fn inject_later(engine: &crate::runtime::engine::Engine, after: Duration, msgs: serde_json::Value) {
+        tokio::time::sleep(after).await;
+        for msg in Vec::<Msg>::deserialize(msgs).unwrap() {
+            engine.inject_msg(&ElementId::with_u64(1), MsgHandle::new(msg), CancellationToken::new()).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_it_should_send_the_final_msg_of_each_burst() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "debounce", "timeout": "100", "timeoutUnits": "milliseconds",
+                "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let started = Instant::now();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"topic": "a", "payload": "a1"}],
+            ["1", {"topic": "b", "payload": "b1"}],
+            ["1", {"topic": "a", "payload": "a2"}],
+            ["1", {"topic": "b", "payload": "b2"}],
+            ["1", {"topic": "a", "payload": "a3"}],
+        ]))
+        .unwrap();
+        let (msgs, _) = tokio::join!(
+            engine.run_once_with_inject(3, Duration::from_millis(800), msgs_to_inject),
+            inject_later(
+                &engine,
+                Duration::from_millis(250),
+                json!([{"topic": "a", "payload": "a4"}, {"topic": "a", "payload": "a5"}])
+            ),
+        );
+        let msgs = msgs.unwrap();
+        let mut first_burst: Vec<&str> = msgs[..2].iter().map(|x| x["payload"].as_str().unwrap()).collect();
+        first_burst.sort();
+        assert_eq!(first_burst, vec!["a3", "b2"]);
+        assert_eq!(msgs[2]["payload"], "a5".into());
+        assert_eq!(msgs[2]["topic"], "a".into());
+        assert!(started.elapsed() >= Duration::from_millis(350));
+    }
+
+    #[tokio::test]
+    async fn test_it_should_restart_the_quiet_period_on_receive() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "debounce", "timeout": "150", "timeoutUnits": "milliseconds",
+                "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let started = Instant::now();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": 1}]])).unwrap();
+        let (msgs, _) = tokio::join!(
+            engine.run_once_with_inject(1, Duration::from_millis(600), msgs_to_inject),
+            inject_later(&engine, Duration::from_millis(100), json!([{"payload": 2}])),
+        );
+        let msgs = msgs.unwrap();
+        assert_eq!(msgs[0]["payload"], 2.into());
+        assert!(started.elapsed() >= Duration::from_millis(250));
+    }
+
+    #[tokio::test]
+    async fn test_it_should_flush_and_reset_pending_msgs() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "debounce", "timeout": "10000", "timeoutUnits": "milliseconds",
+                "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"topic": "a", "payload": "a1"}],
+            ["1", {"topic": "b", "payload": "b1"}],
+            ["1", {"topic": "a", "payload": "a2"}],
+            ["1", {"flush": true}],
+            ["1", {"topic": "c", "payload": "c1"}],
+            ["1", {"reset": true}],
+        ]))
+        .unwrap();
+        let msgs = engine.run_once_with_inject(2, Duration::from_millis(300), msgs_to_inject).await.unwrap();
+        let payloads: Vec<&str> = msgs.iter().map(|x| x["payload"].as_str().unwrap()).collect();
+        assert_eq!(payloads, vec!["b1", "a2"]);
+
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "debounce", "timeout": "100", "timeoutUnits": "milliseconds",
+                "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject =
+            Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": "x"}], ["1", {"reset": true}]])).unwrap();
+        assert!(engine.run_once_with_inject(1, Duration::from_millis(200), msgs_to_inject).await.is_err());
+    }
+
+    #[test]
+    fn test_quiet_period_should_be_validated_by_the_building() {
+        let build = |timeout: &str| {
+            crate::runtime::engine::build_test_engine(json!([
+                {"id": "100", "type": "tab"},
+                {"id": "1", "z": "100", "type": "debounce", "timeout": timeout, "timeoutUnits": "hours", "wires": []}
+            ]))
+        };
+        assert!(build("0").is_err());
+        assert!(build("-1").is_err());
+        // The long ones are clamped instead of overflowing
+        assert!(build("1e300").is_ok());
+    }
+}
@@ -0,0 +1,734 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rand::Rng;
+use serde::Deserialize;
+use tokio::sync::{Mutex, Notify};
+use tokio::time::{Instant, MissedTickBehavior};
+
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use crate::utils;
+use edgelink_macro::*;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum PauseType {
+    /// Holds every message for the fixed `timeout`
+    #[serde(rename = "delay")]
+    Delay,
+
+    /// Holds every message for `msg.delay` milliseconds, or the `timeout` if it is absent
+    #[serde(rename = "delayv")]
+    DelayV,
+
+    /// Holds every message for a random interval between `randomFirst` and `randomLast`
+    #[serde(rename = "random")]
+    Random,
+
+    /// Limits the rate of all messages
+    #[default]
+    #[serde(rename = "rate")]
+    Rate,
+
+    /// Limits the rate of each `msg.topic` independently
+    #[serde(rename = "queue")]
+    Queue,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum RateUnits {
+    #[default]
+    #[serde(rename = "second")]
+    Second,
+
+    #[serde(rename = "minute")]
+    Minute,
+
+    #[serde(rename = "hour")]
+    Hour,
+
+    #[serde(rename = "day")]
+    Day,
+}
+
+impl RateUnits {
+    fn as_secs_f64(&self) -> f64 {
+        match self {
+            RateUnits::Second => 1.0,
+            RateUnits::Minute => 60.0,
+            RateUnits::Hour => 60.0 * 60.0,
+            RateUnits::Day => 24.0 * 60.0 * 60.0,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum TimeoutUnits {
+    #[serde(rename = "milliseconds")]
+    Milliseconds,
+
+    #[default]
+    #[serde(rename = "seconds")]
+    Seconds,
+
+    #[serde(rename = "minutes")]
+    Minutes,
+
+    #[serde(rename = "hours")]
+    Hours,
+
+    #[serde(rename = "days")]
+    Days,
+}
+
+impl TimeoutUnits {
+    fn as_secs_f64(&self) -> f64 {
+        match self {
+            TimeoutUnits::Milliseconds => 0.001,
+            TimeoutUnits::Seconds => 1.0,
+            TimeoutUnits::Minutes => 60.0,
+            TimeoutUnits::Hours => 60.0 * 60.0,
+            TimeoutUnits::Days => 24.0 * 60.0 * 60.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct DelayNodeConfig {
+    #[serde(rename = "pauseType", default)]
+    pause_type: PauseType,
+
+    /// The interval to hold the messages in `timeoutUnits`
+    #[serde(default, deserialize_with = "json::deser::str_to_option_f64")]
+    timeout: Option<f64>,
+
+    #[serde(rename = "timeoutUnits", default)]
+    timeout_units: TimeoutUnits,
+
+    #[serde(rename = "randomFirst", default, deserialize_with = "json::deser::str_to_option_f64")]
+    random_first: Option<f64>,
+
+    #[serde(rename = "randomLast", default, deserialize_with = "json::deser::str_to_option_f64")]
+    random_last: Option<f64>,
+
+    #[serde(rename = "randomUnits", default)]
+    random_units: TimeoutUnits,
+
+    /// The count of messages released in `nbRateUnits` of `rateUnits`
+    #[serde(default, deserialize_with = "json::deser::str_to_option_f64")]
+    rate: Option<f64>,
+
+    #[serde(rename = "nbRateUnits", default, deserialize_with = "json::deser::str_to_option_f64")]
+    nb_rate_units: Option<f64>,
+
+    #[serde(rename = "rateUnits", default)]
+    rate_units: RateUnits,
+
+    /// The maximum count of the queued messages, the oldest one is dropped if exceeded, unlimited if absent or 0
+    #[serde(rename = "maxMsg", default, deserialize_with = "json::deser::str_to_option_usize")]
+    max_msg: Option<usize>,
+
+    /// Drops the messages arrived in the limited period instead of queuing them
+    #[serde(default)]
+    drop: bool,
+}
+
+#[derive(Debug)]
+struct TopicQueue {
+    msgs: VecDeque<MsgHandle>,
+    next_release: Instant,
+}
+
+#[derive(Debug)]
+#[flow_node("delay")]
+struct DelayNode {
+    base: FlowNode,
+    config: DelayNodeConfig,
+    interval: Duration,
+    /// The messages waiting for the ticker in the rate mode
+    rate_queue: Mutex<TopicQueue>,
+    /// The messages waiting for their topics in the queue mode
+    queues: Mutex<HashMap<String, TopicQueue>>,
+    queued: Notify,
+    /// The messages held in the delay modes, by the order of arrival
+    held: Mutex<BTreeMap<u64, MsgHandle>>,
+    held_serial: AtomicU64,
+}
+
+impl DelayNode {
+    fn build(
+        _flow: &Flow,
+        base_node: FlowNode,
+        config: &RedFlowNodeConfig,
+    ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let delay_config = DelayNodeConfig::deserialize(&config.rest)?;
+        let interval = match delay_config.pause_type {
+            PauseType::Delay | PauseType::DelayV | PauseType::Random => {
+                let timeout = delay_config.timeout.unwrap_or(5.0);
+                let (first, last) = (delay_config.random_first.unwrap_or(1.0), delay_config.random_last.unwrap_or(5.0));
+                let interval = utils::time::delay_from_secs_f64(timeout * delay_config.timeout_units.as_secs_f64());
+                match interval {
+                    Some(interval) if first >= 0.0 && first <= last && last.is_finite() => interval,
+                    _ => {
+                        return Err(EdgelinkError::BadFlowsJson(format!(
+                            "Bad interval of the delay node: timeout={}, random={}..{}",
+                            timeout, first, last
+                        ))
+                        .into())
+                    }
+                }
+            }
+            PauseType::Rate | PauseType::Queue => {
+                let rate = delay_config.rate.unwrap_or(1.0);
+                let period = delay_config.nb_rate_units.unwrap_or(1.0) * delay_config.rate_units.as_secs_f64();
+                // The ticker of the rate mode cannot tick with a zero period
+                let interval = (rate > 0.0 && period > 0.0)
+                    .then(|| utils::time::delay_from_secs_f64(period / rate))
+                    .flatten()
+                    .filter(|x| !x.is_zero());
+                match interval {
+                    Some(interval) => interval,
+                    None => {
+                        return Err(EdgelinkError::BadFlowsJson(format!(
+                            "Bad rate of the delay node: {} msg(s) in {}s",
+                            rate, period
+                        ))
+                        .into())
+                    }
+                }
+            }
+        };
+
+        let node = DelayNode {
+            base: base_node,
+            interval,
+            config: delay_config,
+            rate_queue: Mutex::new(TopicQueue { msgs: VecDeque::new(), next_release: Instant::now() }),
+            queues: Mutex::new(HashMap::new()),
+            queued: Notify::new(),
+            held: Mutex::new(BTreeMap::new()),
+            held_serial: AtomicU64::new(0),
+        };
+        Ok(Box::new(node))
+    }
+
+    async fn receive(self: &Arc<Self>, msg: MsgHandle, cancel: CancellationToken) -> crate::Result<()> {
+        if self.handle_command(&msg, cancel.clone()).await? {
+            return Ok(());
+        }
+        match self.config.pause_type {
+            PauseType::Delay | PauseType::DelayV | PauseType::Random => {
+                let delay = self.delay_of(&msg).await;
+                self.hold(msg, delay, cancel).await;
+                Ok(())
+            }
+            PauseType::Rate => {
+                let mut queue = self.rate_queue.lock().await;
+                if self.config.drop && (!queue.msgs.is_empty() || queue.next_release > Instant::now()) {
+                    log::debug!("[DELAY:{}] Dropped a message in the limited period", self.name());
+                } else {
+                    self.push_bounded(&mut queue.msgs, msg);
+                    self.queued.notify_one();
+                }
+                Ok(())
+            }
+            PauseType::Queue => self.receive_topic(msg, cancel).await,
+        }
+    }
+
+    /// Handles the commands, returns `true` if the message is a command and should not be forwarded.
+    ///
+    /// `msg.reset` drops all the pending messages, and `msg.flush` releases the pending messages immediately, only
+    /// the first `msg.flush` ones if it is a number.
+    async fn handle_command(&self, msg: &MsgHandle, cancel: CancellationToken) -> crate::Result<bool> {
+        let (flush, reset) = {
+            let msg_guard = msg.read().await;
+            (msg_guard.get("flush").cloned(), msg_guard.contains("reset"))
+        };
+        if reset {
+            let dropped = self.take_pending(None).await;
+            log::debug!("[DELAY:{}] Dropped {} pending message(s) by the reset", self.name(), dropped.len());
+            return Ok(true);
+        }
+        let limit = match flush {
+            Some(Variant::Number(num)) => Some(num.as_f64().map_or(0, |x| x.max(0.0) as usize)),
+            Some(_) => None,
+            None => return Ok(false),
+        };
+        for msg in self.take_pending(limit).await {
+            self.fan_out_one(Envelope { port: 0, msg }, cancel.clone()).await?;
+        }
+        Ok(true)
+    }
+
+    /// Removes the pending messages by the order of their releasing, all of them if the `limit` is `None`.
+    async fn take_pending(&self, limit: Option<usize>) -> Vec<MsgHandle> {
+        let limit = limit.unwrap_or(usize::MAX);
+        let mut pending = Vec::new();
+        match self.config.pause_type {
+            PauseType::Delay | PauseType::DelayV | PauseType::Random => {
+                let mut held = self.held.lock().await;
+                while pending.len() < limit {
+                    match held.pop_first() {
+                        Some((_, msg)) => pending.push(msg),
+                        None => break,
+                    }
+                }
+            }
+            PauseType::Rate => {
+                let mut queue = self.rate_queue.lock().await;
+                let count = limit.min(queue.msgs.len());
+                pending.extend(queue.msgs.drain(..count));
+            }
+            PauseType::Queue => {
+                let mut queues = self.queues.lock().await;
+                let mut topics: Vec<&mut TopicQueue> = queues.values_mut().filter(|q| !q.msgs.is_empty()).collect();
+                topics.sort_by_key(|q| q.next_release);
+                for queue in topics.into_iter() {
+                    let count = (limit - pending.len()).min(queue.msgs.len());
+                    pending.extend(queue.msgs.drain(..count));
+                }
+            }
+        }
+        pending
+    }
+
+    /// Pushes the message to the queue, the oldest message is dropped if the queue is full.
+    fn push_bounded(&self, msgs: &mut VecDeque<MsgHandle>, msg: MsgHandle) {
+        if let Some(max_msg) = self.config.max_msg.filter(|x| *x > 0) {
+            while msgs.len() >= max_msg {
+                msgs.pop_front();
+                log::warn!("[DELAY:{}] Dropped the oldest message, the queue is full", self.name());
+            }
+        }
+        // The ordered sender waits for the message to be released
+        msgs.push_back(msg.hold_completion());
+    }
+
+    async fn delay_of(&self, msg: &MsgHandle) -> Duration {
+        match self.config.pause_type {
+            PauseType::DelayV => {
+                let msg_guard = msg.read().await;
+                let delay_ms = match msg_guard.get("delay") {
+                    Some(Variant::Number(num)) => num.as_f64(),
+                    Some(Variant::String(s)) => s.trim().parse::<f64>().ok(),
+                    _ => None,
+                };
+                delay_ms.and_then(|x| utils::time::delay_from_secs_f64(x / 1000.0)).unwrap_or(self.interval)
+            }
+            PauseType::Random => {
+                let first = self.config.random_first.unwrap_or(1.0);
+                let last = self.config.random_last.unwrap_or(5.0);
+                let secs = rand::thread_rng().gen_range(first..=last) * self.config.random_units.as_secs_f64();
+                utils::time::delay_from_secs_f64(secs).unwrap_or(self.interval)
+            }
+            _ => self.interval,
+        }
+    }
+
+    /// Holds the message for the delay, the message is dropped if the node has been stopped before the delay elapsed.
+    async fn hold(self: &Arc<Self>, msg: MsgHandle, delay: Duration, stop_token: CancellationToken) {
+        let serial = self.held_serial.fetch_add(1, Ordering::Relaxed);
+        self.held.lock().await.insert(serial, msg.hold_completion());
+        let node = self.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = stop_token.cancelled() => {}
+                _ = tokio::time::sleep(delay) => {
+                    // The message may have been flushed or reset
+                    let msg = node.held.lock().await.remove(&serial);
+                    if let Some(msg) = msg {
+                        if let Err(e) = node.fan_out_one(Envelope { port: 0, msg }, stop_token.child_token()).await {
+                            log::warn!("[DELAY:{}] Failed to release the message: {}", node.name(), e);
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    async fn receive_topic(&self, msg: MsgHandle, cancel: CancellationToken) -> crate::Result<()> {
+        let topic = {
+            let msg_guard = msg.read().await;
+            msg_guard.get("topic").map(String::from)
+        };
+
+        let release_now = {
+            let mut queues = self.queues.lock().await;
+            let now = Instant::now();
+            let queue = queues
+                .entry(topic.unwrap_or_default())
+                .or_insert_with(|| TopicQueue { msgs: VecDeque::new(), next_release: now });
+            if queue.msgs.is_empty() && queue.next_release <= now {
+                queue.next_release = now + self.interval;
+                true
+            } else {
+                if self.config.drop {
+                    log::debug!("[DELAY:{}] Dropped a message in the limited period", self.name());
+                } else {
+                    self.push_bounded(&mut queue.msgs, msg.clone());
+                    self.queued.notify_one();
+                }
+                false
+            }
+        };
+
+        if release_now {
+            self.fan_out_one(Envelope { port: 0, msg }, cancel).await?;
+        }
+        Ok(())
+    }
+
+    /// Releases the messages of every topic whose period elapsed, then removes the idle topics.
+    /// Returns the earliest instant to release the next message.
+    async fn release_due(&self, cancel: CancellationToken) -> Option<Instant> {
+        let (due_msgs, next_release) = {
+            let mut queues = self.queues.lock().await;
+            let now = Instant::now();
+            let mut due: Vec<(Instant, MsgHandle)> = Vec::new();
+            for queue in queues.values_mut() {
+                if queue.next_release <= now {
+                    if let Some(msg) = queue.msgs.pop_front() {
+                        // Keeps the topics fair by releasing the longest waiting one first
+                        due.push((queue.next_release, msg));
+                        queue.next_release = now + self.interval;
+                    }
+                }
+            }
+            queues.retain(|_, q| !q.msgs.is_empty() || q.next_release > now);
+            due.sort_by_key(|x| x.0);
+            let next_release = queues.values().filter(|q| !q.msgs.is_empty()).map(|q| q.next_release).min();
+            (due, next_release)
+        };
+
+        for (_, msg) in due_msgs.into_iter() {
+            if let Err(e) = self.fan_out_one(Envelope { port: 0, msg }, cancel.clone()).await {
+                log::warn!("[DELAY:{}] Failed to release the message: {}", self.name(), e);
+            }
+        }
+        next_release
+    }
+
+    async fn release_loop(self: Arc<Self>, stop_token: CancellationToken) {
+        loop {
+            let next_release = self.release_due(stop_token.child_token()).await;
+            tokio::select! {
+                _ = stop_token.cancelled() => break,
+                _ = self.queued.notified() => {}
+                _ = tokio::time::sleep_until(next_release.unwrap_or_else(Instant::now)), if next_release.is_some() => {}
+            }
+        }
+    }
+
+    /// Releases one message of the rate queue per tick, the ticker only runs while there are queued messages, so the
+    /// first message after an idle period is released immediately.
+    async fn rate_loop(self: Arc<Self>, stop_token: CancellationToken) {
+        let mut ticker = tokio::time::interval(self.interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            let is_empty = self.rate_queue.lock().await.msgs.is_empty();
+            if is_empty {
+                tokio::select! {
+                    _ = stop_token.cancelled() => break,
+                    _ = self.queued.notified() => continue,
+                }
+            }
+            tokio::select! {
+                _ = stop_token.cancelled() => break,
+                _ = ticker.tick() => {}
+            }
+            let msg = {
+                let mut queue = self.rate_queue.lock().await;
+                queue.next_release = Instant::now() + self.interval;
+                queue.msgs.pop_front()
+            };
+            // The queue may have been flushed or reset while waiting for the tick
+            if let Some(msg) = msg {
+                if let Err(e) = self.fan_out_one(Envelope { port: 0, msg }, stop_token.child_token()).await {
+                    log::warn!("[DELAY:{}] Failed to release the message: {}", self.name(), e);
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for DelayNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        let releaser = match self.config.pause_type {
+            PauseType::Rate => Some(tokio::spawn(self.clone().rate_loop(stop_token.clone()))),
+            PauseType::Queue => Some(tokio::spawn(self.clone().release_loop(stop_token.clone()))),
+            _ => None,
+        };
+
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            let this = self.clone();
+            with_uow(self.as_ref(), cancel.child_token(), |_, msg| async move { this.receive(msg, cancel).await })
+                .await;
+        }
+
+        if let Some(releaser) = releaser {
+            let _ = releaser.await;
+        }
+        log::debug!("DelayNode process() task has been terminated.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    fn make_interleaved_msgs() -> Vec<(ElementId, Msg)> {
+        Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"topic": "a", "payload": "a1"}],
+            ["1", {"topic": "a", "payload": "a2"}],
+            ["1", {"topic": "b", "payload": "b1"}],
+            ["1", {"topic": "b", "payload": "b2"}],
+        ]))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_it_should_not_limit_a_topic_by_another() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "delay", "pauseType": "queue",
+                "rate": "1", "nbRateUnits": "0.2", "rateUnits": "second", "drop": false, "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(2, Duration::from_millis(100), make_interleaved_msgs()).await.unwrap();
+        let payloads: Vec<&str> = msgs.iter().map(|x| x["payload"].as_str().unwrap()).collect();
+        assert_eq!(payloads, vec!["a1", "b1"]);
+    }
+
+    #[tokio::test]
+    async fn test_it_should_limit_each_topic_independently() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "delay", "pauseType": "queue",
+                "rate": "1", "nbRateUnits": "0.2", "rateUnits": "second", "drop": false, "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(4, Duration::from_millis(600), make_interleaved_msgs()).await.unwrap();
+        let payloads: Vec<&str> = msgs.iter().map(|x| x["payload"].as_str().unwrap()).collect();
+        assert_eq!(&payloads[..2], &["a1", "b1"]);
+        assert!(payloads[2..].contains(&"a2"));
+        assert!(payloads[2..].contains(&"b2"));
+    }
+
+    #[tokio::test]
+    async fn test_it_should_limit_all_msgs_in_rate_mode() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "delay", "pauseType": "rate",
+                "rate": "1", "nbRateUnits": "1", "rateUnits": "second", "drop": true, "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let res = engine.run_once_with_inject(2, Duration::from_millis(200), make_interleaved_msgs()).await;
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_out_of_range_intervals_should_be_clamped_or_rejected() {
+        let build = |delay_node: serde_json::Value| {
+            let mut delay_node = delay_node;
+            let node = delay_node.as_object_mut().unwrap();
+            node.insert("id".into(), json!("1"));
+            node.insert("z".into(), json!("100"));
+            node.insert("type".into(), json!("delay"));
+            crate::runtime::engine::build_test_engine(json!([{"id": "100", "type": "tab"}, delay_node]))
+        };
+        assert!(build(json!({"pauseType": "delay", "timeout": "1e300", "timeoutUnits": "days"})).is_ok());
+        assert!(build(json!({"pauseType": "delay", "timeout": "-1"})).is_err());
+        assert!(build(json!({"pauseType": "random", "randomFirst": "1", "randomLast": "1e400"})).is_err());
+        assert!(build(json!({"pauseType": "rate", "rate": "1e300", "nbRateUnits": "1"})).is_err());
+        assert!(build(json!({"pauseType": "rate", "rate": "0"})).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_huge_or_bad_msg_delay_should_not_panic() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "delay", "pauseType": "delayv", "timeout": "10",
+                "timeoutUnits": "milliseconds", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"payload": "huge", "delay": 1e300}],
+            ["1", {"payload": "bad", "delay": "-5"}],
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(1, Duration::from_millis(300), msgs_to_inject).await.unwrap();
+        // The bad delay falls back to the configured timeout, the huge one is clamped and still held
+        assert_eq!(payloads_of(&msgs), vec!["bad"]);
+    }
+
+    fn payloads_of(msgs: &[Msg]) -> Vec<&str> {
+        msgs.iter().map(|x| x["payload"].as_str().unwrap()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_it_should_hold_msgs_for_fixed_interval() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "delay",
+                "pauseType": "delay", "timeout": "100", "timeoutUnits": "milliseconds", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": "a"}]])).unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json.clone()).unwrap();
+        let res = engine.run_once_with_inject(1, Duration::from_millis(50), msgs_to_inject.clone()).await;
+        assert!(res.is_err());
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let started_at = Instant::now();
+        let msgs = engine.run_once_with_inject(1, Duration::from_millis(400), msgs_to_inject).await.unwrap();
+        assert!(started_at.elapsed() >= Duration::from_millis(100));
+        assert_eq!(payloads_of(&msgs), vec!["a"]);
+    }
+
+    #[tokio::test]
+    async fn test_it_should_hold_msgs_by_msg_delay_or_random_interval() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "delay",
+                "pauseType": "delayv", "timeout": "1", "timeoutUnits": "seconds", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"payload": "slow", "delay": 150}],
+            ["1", {"payload": "fast", "delay": "30"}],
+        ]))
+        .unwrap();
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(2, Duration::from_millis(400), msgs_to_inject).await.unwrap();
+        assert_eq!(payloads_of(&msgs), vec!["fast", "slow"]);
+
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "delay", "pauseType": "random",
+                "randomFirst": "50", "randomLast": "100", "randomUnits": "milliseconds", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": "a"}]])).unwrap();
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let started_at = Instant::now();
+        let msgs = engine.run_once_with_inject(1, Duration::from_millis(400), msgs_to_inject).await.unwrap();
+        assert!(started_at.elapsed() >= Duration::from_millis(50));
+        assert_eq!(payloads_of(&msgs), vec!["a"]);
+    }
+
+    #[tokio::test]
+    async fn test_it_should_flush_and_reset_held_msgs() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "delay", "pauseType": "delay", "timeout": "10", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"payload": "a"}],
+            ["1", {"payload": "b"}],
+            ["1", {"payload": "c"}],
+            ["1", {"flush": 1}],
+            ["1", {"reset": true}],
+            ["1", {"payload": "d"}],
+            ["1", {"flush": true}],
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(2, Duration::from_millis(300), msgs_to_inject).await.unwrap();
+        assert_eq!(payloads_of(&msgs), vec!["a", "d"]);
+    }
+
+    #[tokio::test]
+    async fn test_it_should_release_queued_msgs_by_the_rate() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "delay", "pauseType": "rate",
+                "rate": "1", "nbRateUnits": "0.1", "rateUnits": "second", "drop": false, "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"payload": "a"}],
+            ["1", {"payload": "b"}],
+            ["1", {"payload": "c"}],
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let started_at = Instant::now();
+        let msgs = engine.run_once_with_inject(3, Duration::from_millis(600), msgs_to_inject).await.unwrap();
+        assert!(started_at.elapsed() >= Duration::from_millis(200));
+        assert_eq!(payloads_of(&msgs), vec!["a", "b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn test_it_should_drop_the_oldest_msgs_if_the_queue_is_full() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "delay", "pauseType": "queue",
+                "rate": "1", "nbRateUnits": "10", "rateUnits": "second", "maxMsg": "2", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"topic": "t", "payload": "a"}],
+            ["1", {"topic": "t", "payload": "b"}],
+            ["1", {"topic": "t", "payload": "c"}],
+            ["1", {"topic": "t", "payload": "d"}],
+            ["1", {"topic": "t", "payload": "e"}],
+            ["1", {"flush": true}],
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(3, Duration::from_millis(300), msgs_to_inject).await.unwrap();
+        assert_eq!(payloads_of(&msgs), vec!["a", "d", "e"]);
+    }
+
+    #[tokio::test]
+    async fn test_msgs_sent_after_the_node_stopped_should_not_be_emitted() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "delay",
+                "pauseType": "delay", "timeout": "50", "timeoutUnits": "milliseconds", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let node_id = ElementId::with_u64(1);
+        let delay_node = engine.find_flow_node_by_id(&node_id).unwrap();
+        let mut sent_rx = delay_node.get_node().on_sent.subscribe();
+        let make_msg = |payload: &str| MsgHandle::new(Msg::deserialize(json!({"payload": payload})).unwrap());
+
+        engine.start().await.unwrap();
+        let cancel = CancellationToken::new();
+        engine.inject_msg(&node_id, make_msg("running"), cancel.clone()).await.unwrap();
+        let sent = tokio::time::timeout(Duration::from_millis(300), sent_rx.recv()).await.unwrap().unwrap();
+        assert_eq!(sent.msg.read().await["payload"], Variant::from("running"));
+
+        // The held message is dropped by stopping the node
+        engine.inject_msg(&node_id, make_msg("held"), cancel.clone()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        engine.stop().await.unwrap();
+        let _ = engine.inject_msg(&node_id, make_msg("stopped"), cancel.clone()).await;
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(sent_rx.try_recv().is_err());
+    }
+}
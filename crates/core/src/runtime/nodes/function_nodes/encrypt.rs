@@ -0,0 +1,314 @@
+use std::sync::Arc;
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::prelude::*;
+use serde::Deserialize;
+
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+const NONCE_PROPERTY: &str = "nonce";
+const CIPHERTEXT_PROPERTY: &str = "ciphertext";
+const ENCODING_PROPERTY: &str = "encoding";
+
+/// The length of the AES-GCM nonce in bytes
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+enum CryptoAction {
+    #[default]
+    #[serde(rename = "encrypt")]
+    Encrypt,
+
+    #[serde(rename = "decrypt")]
+    Decrypt,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+enum KeyType {
+    #[default]
+    #[serde(rename = "str")]
+    Str,
+
+    /// The `key` is the name of an environment variable holding the key
+    #[serde(rename = "env")]
+    Env,
+}
+
+#[derive(Deserialize)]
+struct EncryptNodeConfig {
+    #[serde(default = "property_default")]
+    property: String,
+
+    #[serde(default)]
+    action: CryptoAction,
+
+    /// The base64 of the 32 bytes AES-256 key
+    #[serde(default)]
+    key: String,
+
+    #[serde(rename = "keyType", default)]
+    key_type: KeyType,
+}
+
+fn property_default() -> String {
+    "payload".to_string()
+}
+
+/// How the plaintext is restored to the value, it is authenticated as the associated data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PlainEncoding {
+    Utf8,
+    Binary,
+    Json,
+}
+
+impl PlainEncoding {
+    fn as_str(&self) -> &'static str {
+        match self {
+            PlainEncoding::Utf8 => "utf8",
+            PlainEncoding::Binary => "binary",
+            PlainEncoding::Json => "json",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "utf8" => Some(PlainEncoding::Utf8),
+            "binary" => Some(PlainEncoding::Binary),
+            "json" => Some(PlainEncoding::Json),
+            _ => None,
+        }
+    }
+}
+
+/// The AES-256-GCM cipher, the key is never printed.
+struct FieldCipher(Aes256Gcm);
+
+impl std::fmt::Debug for FieldCipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("FieldCipher(AES-256-GCM)")
+    }
+}
+
+impl FieldCipher {
+    fn new(key: &str) -> crate::Result<Self> {
+        let key = BASE64_STANDARD
+            .decode(key.trim())
+            .map_err(|e| EdgelinkError::BadFlowsJson(format!("The key must be in base64: {}", e)))?;
+        let cipher = Aes256Gcm::new_from_slice(&key).map_err(|_| {
+            EdgelinkError::BadFlowsJson(format!("The key must be 32 bytes for AES-256-GCM, got {} bytes", key.len()))
+        })?;
+        Ok(FieldCipher(cipher))
+    }
+
+    /// Encrypts the value with a random nonce, the result is an object of the base64 nonce and ciphertext, and the
+    /// encoding of the plaintext.
+    fn encrypt(&self, value: &Variant) -> crate::Result<Variant> {
+        let (encoding, plaintext) = match value {
+            Variant::String(s) => (PlainEncoding::Utf8, s.as_bytes().to_vec()),
+            Variant::Bytes(bytes) => (PlainEncoding::Binary, bytes.clone()),
+            other => (PlainEncoding::Json, serde_json::to_vec(&serde_json::Value::from(other))?),
+        };
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .0
+            .encrypt(&nonce, Payload { msg: &plaintext, aad: encoding.as_str().as_bytes() })
+            .map_err(|_| EdgelinkError::InvalidOperation("Failed to encrypt the value".to_string()))?;
+        Ok(Variant::Object(VariantObjectMap::from([
+            (NONCE_PROPERTY.to_string(), Variant::String(BASE64_STANDARD.encode(nonce))),
+            (CIPHERTEXT_PROPERTY.to_string(), Variant::String(BASE64_STANDARD.encode(ciphertext))),
+            (ENCODING_PROPERTY.to_string(), Variant::from(encoding.as_str())),
+        ])))
+    }
+
+    /// Decrypts the object made by `encrypt()`, fails if the nonce, the ciphertext or the encoding has been tampered.
+    fn decrypt(&self, value: &Variant) -> crate::Result<Variant> {
+        let bad_format = || {
+            EdgelinkError::InvalidOperation(
+                "Bad encrypted value, expected an object of `nonce`, `ciphertext` and `encoding`".to_string(),
+            )
+        };
+        let obj = value.as_object().ok_or_else(bad_format)?;
+        let field = |key: &str| obj.get(key).and_then(|x| x.as_str()).ok_or_else(bad_format);
+        let nonce = BASE64_STANDARD.decode(field(NONCE_PROPERTY)?).map_err(|_| bad_format())?;
+        let ciphertext = BASE64_STANDARD.decode(field(CIPHERTEXT_PROPERTY)?).map_err(|_| bad_format())?;
+        let encoding_str = field(ENCODING_PROPERTY)?;
+        let encoding = PlainEncoding::parse(encoding_str).ok_or_else(bad_format)?;
+        if nonce.len() != NONCE_LEN {
+            return Err(bad_format().into());
+        }
+        let plaintext = self
+            .0
+            .decrypt(Nonce::from_slice(&nonce), Payload { msg: &ciphertext, aad: encoding_str.as_bytes() })
+            .map_err(|_| {
+                EdgelinkError::InvalidOperation(
+                    "Failed to decrypt the value, the key is wrong or the value has been tampered".to_string(),
+                )
+            })?;
+        Ok(match encoding {
+            PlainEncoding::Utf8 => Variant::String(String::from_utf8(plaintext)?),
+            PlainEncoding::Binary => Variant::Bytes(plaintext),
+            PlainEncoding::Json => Variant::from(serde_json::from_slice::<serde_json::Value>(&plaintext)?),
+        })
+    }
+}
+
+/// Encrypts or decrypts a property of the message with AES-256-GCM.
+///
+/// The failures, e.g. the authentication failures of the decryption, are reported to the `catch` nodes.
+#[derive(Debug)]
+#[flow_node("encrypt")]
+struct EncryptNode {
+    base: FlowNode,
+    property: String,
+    action: CryptoAction,
+    cipher: FieldCipher,
+}
+
+impl EncryptNode {
+    fn build(_flow: &Flow, state: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let encrypt_config = EncryptNodeConfig::deserialize(&config.rest)?;
+        let key = match encrypt_config.key_type {
+            KeyType::Str => encrypt_config.key,
+            KeyType::Env => {
+                let name = &encrypt_config.key;
+                let value = state.envs.evalute_env(name).and_then(|x| x.as_str().map(|s| s.to_string()));
+                value.ok_or_else(|| {
+                    EdgelinkError::BadFlowsJson(format!("Cannot find the key in the environment variable '{}'", name))
+                })?
+            }
+        };
+        let cipher = FieldCipher::new(&key)?;
+        let node =
+            EncryptNode { base: state, property: encrypt_config.property, action: encrypt_config.action, cipher };
+        Ok(Box::new(node))
+    }
+
+    async fn uow(&self, msg: MsgHandle, cancel: CancellationToken) -> crate::Result<()> {
+        {
+            let mut msg_guard = msg.write().await;
+            let value = msg_guard.get_nav_stripped(&self.property).ok_or_else(|| {
+                EdgelinkError::InvalidOperation(format!("Cannot find the property 'msg.{}'", self.property))
+            })?;
+            let converted = match self.action {
+                CryptoAction::Encrypt => self.cipher.encrypt(value)?,
+                CryptoAction::Decrypt => self.cipher.decrypt(value)?,
+            };
+            msg_guard.set_nav_stripped(&self.property, converted, true)?;
+        }
+        self.fan_out_one(Envelope { port: 0, msg }, cancel).await
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for EncryptNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow_concurrent(&self, cancel.child_token(), |node, msg| async move { node.uow(msg, cancel).await })
+                .await;
+        }
+        wait_uows_completed(self.as_ref()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const TEST_KEY: &str = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=";
+
+    #[test]
+    fn test_cipher_should_round_trip_and_use_random_nonces() {
+        let cipher = FieldCipher::new(TEST_KEY).unwrap();
+        for value in [
+            Variant::from("secret"),
+            Variant::Bytes(vec![0, 1, 0xff]),
+            Variant::from(json!({"card": "4111", "cvv": 123, "tags": [true, null]})),
+        ] {
+            let encrypted = cipher.encrypt(&value).unwrap();
+            assert_eq!(cipher.decrypt(&encrypted).unwrap(), value);
+            // The same value is never encrypted to the same ciphertext
+            let nonce_of = |x: &Variant| x.as_object().unwrap()[NONCE_PROPERTY].clone();
+            assert_ne!(nonce_of(&cipher.encrypt(&value).unwrap()), nonce_of(&encrypted));
+        }
+        assert!(FieldCipher::new("c2hvcnQ=").is_err());
+    }
+
+    #[test]
+    fn test_tampered_values_should_fail_to_decrypt() {
+        let cipher = FieldCipher::new(TEST_KEY).unwrap();
+        let encrypted = cipher.encrypt(&Variant::from("secret")).unwrap();
+
+        let mut ciphertext =
+            BASE64_STANDARD.decode(encrypted.as_object().unwrap()[CIPHERTEXT_PROPERTY].as_str().unwrap()).unwrap();
+        ciphertext[0] ^= 1;
+        let mut tampered = encrypted.clone();
+        tampered.as_object_mut().unwrap().insert(CIPHERTEXT_PROPERTY.into(), BASE64_STANDARD.encode(ciphertext).into());
+        assert!(cipher.decrypt(&tampered).unwrap_err().to_string().contains("tampered"));
+
+        // The encoding is authenticated too
+        let mut tampered = encrypted.clone();
+        tampered.as_object_mut().unwrap().insert(ENCODING_PROPERTY.into(), "binary".into());
+        assert!(cipher.decrypt(&tampered).unwrap_err().to_string().contains("tampered"));
+
+        let other = FieldCipher::new(&BASE64_STANDARD.encode([7u8; 32])).unwrap();
+        assert!(other.decrypt(&encrypted).is_err());
+        assert!(cipher.decrypt(&Variant::from("secret")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_it_should_encrypt_and_decrypt_with_key_from_env() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab", "env": [{"name": "FIELD_KEY", "value": TEST_KEY, "type": "str"}]},
+            {"id": "1", "z": "100", "type": "encrypt", "property": "card", "key": "FIELD_KEY", "keyType": "env",
+                "wires": [["2", "3"]]},
+            {"id": "2", "z": "100", "type": "encrypt", "action": "decrypt", "property": "card", "key": TEST_KEY,
+                "wires": [["4"]]},
+            {"id": "3", "z": "100", "type": "test-once"},
+            {"id": "4", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject =
+            Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"card": {"number": "4111", "cvv": 123}}]])).unwrap();
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(2, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+
+        let (decrypted, encrypted): (Vec<_>, Vec<_>) =
+            msgs.into_iter().partition(|x| x.get_nav_stripped("card.number").is_some());
+        assert_eq!(decrypted[0]["card"], Variant::from(json!({"number": "4111", "cvv": 123})));
+        assert_eq!(encrypted[0].get_nav_stripped("card.encoding"), Some(&Variant::from("json")));
+    }
+
+    #[tokio::test]
+    async fn test_tampered_value_should_be_caught() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "encrypt", "action": "decrypt", "key": TEST_KEY, "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"},
+            {"id": "3", "z": "100", "type": "catch", "wires": [["2"]]}
+        ]);
+        let cipher = FieldCipher::new(TEST_KEY).unwrap();
+        let mut encrypted = cipher.encrypt(&Variant::from("secret")).unwrap();
+        let other_nonce = BASE64_STANDARD.encode([0u8; NONCE_LEN]);
+        encrypted.as_object_mut().unwrap().insert(NONCE_PROPERTY.into(), other_nonce.into());
+        let msgs_to_inject =
+            Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": serde_json::Value::from(&encrypted)}]]))
+                .unwrap();
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+
+        let error = msgs[0].get_nav_stripped("error.message").unwrap().as_str().unwrap();
+        assert!(error.contains("tampered"), "{}", error);
+    }
+}
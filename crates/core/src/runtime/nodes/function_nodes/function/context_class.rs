@@ -1,114 +1,207 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use rquickjs::{class::Trace, Ctx, Function, IntoJs, Value};
 use rquickjs::{prelude::*, Exception};
 
-use crate::runtime::context::Context as RedContext;
+use crate::runtime::context::{Context as RedContext, ContextKeyRef};
+use crate::runtime::model::PropexEnv;
 use crate::utils::async_util::SyncWaitableFuture;
 
 use super::{UndefinableVariant, Variant};
 
+/// The most keys with the store prefix cached by a context, the cache is cleared if it is full
+const PARSED_KEYS_CAPACITY: usize = 256;
+
 #[derive(Clone, Trace)]
 #[rquickjs::class(frozen)]
-pub(super) struct ContextClass {
+pub(super) struct ContextClass<'js> {
     #[qjs(skip_trace)]
     pub red_ctx: Arc<RedContext>,
+
+    /// Gets the `msg` of the running user function, the nested properties in the keys, e.g. `device[msg.id]`, are
+    /// resolved against it
+    get_msg: Option<Function<'js>>,
+
+    /// The keys with the store prefix like `#:(file)::foo`, parsed once and reused for every message
+    #[qjs(skip_trace)]
+    parsed_keys: Arc<std::sync::Mutex<HashMap<String, ContextKeyRef>>>,
 }
 
 #[allow(non_snake_case)]
 #[rquickjs::methods]
-impl ContextClass {
+impl<'js> ContextClass<'js> {
     #[qjs(skip)]
     pub fn new(red_ctx: Arc<RedContext>) -> Self {
-        ContextClass { red_ctx }
+        ContextClass { red_ctx, get_msg: None, parsed_keys: Default::default() }
+    }
+
+    /// Creates the context bound to the `msg` of a user function invocation, the getter is called on every access,
+    /// so the keys are resolved against the `msg` even if the user function has replaced it.
+    #[qjs(rename = "__el_bindMsg")]
+    pub fn bind_msg(&self, get_msg: Function<'js>) -> Self {
+        ContextClass { red_ctx: self.red_ctx.clone(), get_msg: Some(get_msg), parsed_keys: self.parsed_keys.clone() }
     }
 
     #[qjs(rename = "get")]
-    pub fn get<'js>(
+    pub fn get(
         self,
         keys: Value<'js>,
-        store: Opt<rquickjs::String<'js>>,
+        store: Opt<Value<'js>>,
+        cb: Opt<Function<'js>>,
+        ctx: Ctx<'js>,
+    ) -> rquickjs::Result<Value<'js>> {
+        let keys = ContextKeys::from_js(keys)?;
+        let msg = self.env_msg(keys.as_slice())?;
+        self.get_with_env(msg, keys, store, cb, ctx)
+    }
+
+    #[qjs(skip)]
+    fn get_with_env(
+        self,
+        msg: Option<Variant>,
+        keys: ContextKeys,
+        store: Opt<Value<'js>>,
         cb: Opt<Function<'js>>,
         ctx: Ctx<'js>,
     ) -> rquickjs::Result<Value<'js>> {
-        let keys: String = keys.get()?;
+        let (store, cb) = split_store_and_callback(store, cb)?;
+        let (store, keys) =
+            self.split_store_of_key(store, keys).map_err(|e| Exception::throw_message(&ctx, &e.to_string()))?;
 
-        if let Some(cb) = cb.0 {
+        if let Some(cb) = cb {
             let async_ctx = ctx.clone();
             // User provides the callback, we do it in async
             ctx.spawn(async move {
-                let store = store.0.and_then(|x| x.get::<String>().ok());
-                match self.red_ctx.get_one(store.as_deref(), keys.as_ref(), &[]).await {
-                    Some(ctx_value) => {
-                        let args = (Value::new_undefined(async_ctx.clone()), ctx_value.into_js(&async_ctx));
-                        cb.call::<_, ()>(args).unwrap();
-                    }
-                    None => {
-                        let args = (Value::new_undefined(async_ctx.clone()), Value::new_undefined(async_ctx.clone()));
-                        cb.call::<_, ()>(args).unwrap();
-                    }
+                if let Err(e) = self.red_ctx.resolve_store(store.as_deref()) {
+                    let args = (Exception::from_message(async_ctx.clone(), &e.to_string()).into_js(&async_ctx),);
+                    cb.call::<_, ()>(args).unwrap();
+                    return;
                 }
+                // The values of multiple keys are passed as the separate arguments, e.g. `cb(err, a, b)`
+                let values = get_values(&self.red_ctx, store.as_deref(), keys.as_slice(), msg.as_ref()).await;
+                let args = (Value::new_undefined(async_ctx.clone()), Rest(values));
+                cb.call::<_, ()>(args).unwrap();
             });
             Ok(Value::new_undefined(ctx.clone()))
         } else {
-            // No callback, we do it in sync
-            let store = store.0.and_then(|x| x.get::<String>().ok());
-            let ctx_value = async move { self.red_ctx.get_one(store.as_deref(), keys.as_ref(), &[]).await }.wait();
-            UndefinableVariant(ctx_value).into_js(&ctx)
+            let red_store = self
+                .red_ctx
+                .resolve_store(store.as_deref())
+                .map_err(|e| Exception::throw_message(&ctx, &e.to_string()))?;
+            let is_many = matches!(keys, ContextKeys::Many(_));
+            if red_store.is_async() {
+                // The store is too slow to block on, resolve a Promise after the values arrived
+                return self.spawn_promise(&ctx, move |this, async_ctx| async move {
+                    let mut values = get_values(&this.red_ctx, store.as_deref(), keys.as_slice(), msg.as_ref()).await;
+                    if is_many {
+                        Ok(values.into_js(&async_ctx)?)
+                    } else {
+                        Ok(values.pop().into_js(&async_ctx)?)
+                    }
+                });
+            }
+            // No callback, we do it in sync, only the `Send` context is moved into the blocking future
+            let red_ctx = self.red_ctx.clone();
+            let mut values =
+                async move { get_values(&red_ctx, store.as_deref(), keys.as_slice(), msg.as_ref()).await }.wait();
+            if is_many {
+                values.into_js(&ctx)
+            } else {
+                values.pop().into_js(&ctx)
+            }
         }
     }
 
     #[qjs(rename = "set")]
-    pub fn set<'js>(
+    pub fn set(
         self,
         keys: Value<'js>,
         values: Value<'js>,
-        store: Opt<rquickjs::String<'js>>,
+        store: Opt<Value<'js>>,
+        cb: Opt<Function<'js>>,
+        ctx: Ctx<'js>,
+    ) -> rquickjs::Result<Value<'js>> {
+        let keys = ContextKeys::from_js(keys)?;
+        let msg = self.env_msg(keys.as_slice())?;
+        self.set_with_env(msg, keys, values, store, cb, ctx)
+    }
+
+    #[qjs(skip)]
+    fn set_with_env(
+        self,
+        msg: Option<Variant>,
+        keys: ContextKeys,
+        values: Value<'js>,
+        store: Opt<Value<'js>>,
         cb: Opt<Function<'js>>,
         ctx: Ctx<'js>,
-    ) -> rquickjs::Result<()> {
-        let keys: String = keys.get()?;
-        let values: Variant = values.get()?;
+    ) -> rquickjs::Result<Value<'js>> {
+        let (store, cb) = split_store_and_callback(store, cb)?;
+        let (store, keys) =
+            self.split_store_of_key(store, keys).map_err(|e| Exception::throw_message(&ctx, &e.to_string()))?;
+        let pairs = match keys {
+            ContextKeys::One(key) => vec![(key, to_context_value(values)?)],
+            ContextKeys::Many(keys) => {
+                let values = values.into_array().ok_or_else(|| {
+                    Exception::throw_type(&ctx, "The values must be an array if the keys are an array")
+                })?;
+                let mut pairs = Vec::with_capacity(keys.len());
+                for (i, key) in keys.into_iter().enumerate() {
+                    pairs.push((key, to_context_value(values.get(i)?)?));
+                }
+                pairs
+            }
+        };
 
-        if let Some(cb) = cb.0 {
+        if let Some(cb) = cb {
             let async_ctx = ctx.clone();
             // User provides the callback, we do it in async
             ctx.spawn(async move {
-                let store = store.0.and_then(|x| x.get::<String>().ok());
-                match self.red_ctx.set_one(store.as_deref(), keys.as_ref(), Some(values), &[]).await {
+                match set_values(&self.red_ctx, store.as_deref(), pairs, msg.as_ref()).await {
                     Ok(()) => {
                         let args = (Value::new_undefined(async_ctx.clone()),);
                         cb.call::<_, ()>(args).unwrap();
                     }
-                    Err(_) => {
-                        let args =
-                            (Exception::from_message(async_ctx.clone(), "Failed to parse key").into_js(&async_ctx),);
+                    Err(e) => {
+                        let args = (Exception::from_message(async_ctx.clone(), &e.to_string()).into_js(&async_ctx),);
                         cb.call::<_, ()>(args).unwrap();
                     }
                 }
             });
         } else {
-            // No callback, we do it in sync
-            let store = store.0.and_then(|x| x.get::<String>().ok());
-            async move { self.red_ctx.set_one(store.as_deref(), keys.as_ref(), Some(values), &[]).await }
+            let red_store = self
+                .red_ctx
+                .resolve_store(store.as_deref())
+                .map_err(|e| Exception::throw_message(&ctx, &e.to_string()))?;
+            if red_store.is_async() {
+                // The store is too slow to block on, the Promise will be rejected if the store failed
+                return self.spawn_promise(&ctx, move |this, async_ctx| async move {
+                    set_values(&this.red_ctx, store.as_deref(), pairs, msg.as_ref()).await?;
+                    Ok(Value::new_undefined(async_ctx))
+                });
+            }
+            // No callback, we do it in sync, only the `Send` context is moved into the blocking future
+            let red_ctx = self.red_ctx.clone();
+            async move { set_values(&red_ctx, store.as_deref(), pairs, msg.as_ref()).await }
                 .wait()
-                .map_err(|e| ctx.throw(format!("{}", e).into_js(&ctx).unwrap()))?;
+                .map_err(|e| Exception::throw_message(&ctx, &e.to_string()))?;
         }
-        Ok(())
+        Ok(Value::new_undefined(ctx))
     }
 
     #[qjs(rename = "keys")]
-    pub fn keys<'js>(
-        self,
-        store: Opt<rquickjs::String<'js>>,
-        cb: Opt<Function<'js>>,
-        ctx: Ctx<'js>,
-    ) -> rquickjs::Result<Value<'js>> {
+    pub fn keys(self, store: Opt<Value<'js>>, cb: Opt<Function<'js>>, ctx: Ctx<'js>) -> rquickjs::Result<Value<'js>> {
         let async_ctx = ctx.clone();
-        if let Some(cb) = cb.0 {
+        let (store, cb) = split_store_and_callback(store, cb)?;
+        if let Some(cb) = cb {
             // User provides the callback, we do it in async
             ctx.spawn(async move {
-                let store = store.0.and_then(|x| x.get::<String>().ok());
+                if let Err(e) = self.red_ctx.resolve_store(store.as_deref()) {
+                    let args = (Exception::from_message(async_ctx.clone(), &e.to_string()).into_js(&async_ctx),);
+                    cb.call::<_, ()>(args).unwrap();
+                    return;
+                }
                 match self.red_ctx.keys(store.as_deref()).await {
                     Some(ctx_keys) => {
                         let args = (Value::new_undefined(async_ctx.clone()), ctx_keys.into_js(&async_ctx));
@@ -122,8 +215,20 @@ impl ContextClass {
             });
             Ok(Value::new_undefined(ctx.clone()))
         } else {
+            let red_store = self
+                .red_ctx
+                .resolve_store(store.as_deref())
+                .map_err(|e| Exception::throw_message(&ctx, &e.to_string()))?;
+            if red_store.is_async() {
+                // The store is too slow to block on, resolve a Promise after the keys arrived
+                return self.spawn_promise(&ctx, move |this, async_ctx| async move {
+                    match this.red_ctx.keys(store.as_deref()).await {
+                        Some(ctx_keys) => Ok(ctx_keys.into_js(&async_ctx)?),
+                        None => Ok(Value::new_undefined(async_ctx)),
+                    }
+                });
+            }
             // No callback, we do it in sync
-            let store = store.0.and_then(|x| x.get::<String>().ok());
             match async move { self.red_ctx.keys(store.as_deref()).await }.wait() {
                 Some(ctx_keys) => ctx_keys.into_js(&ctx),
                 None => Ok(Value::new_undefined(ctx.clone())),
@@ -131,3 +236,148 @@ impl ContextClass {
         }
     }
 }
+
+impl<'js> ContextClass<'js> {
+    /// Converts the bound `msg` to resolve the templated keys against, the conversion is skipped if no key refers to
+    /// the nested properties, since the message can be large.
+    fn env_msg(&self, keys: &[String]) -> rquickjs::Result<Option<Variant>> {
+        match &self.get_msg {
+            Some(get_msg) if keys.iter().any(|key| key.contains('[')) => {
+                let msg: Value<'js> = get_msg.call(())?;
+                if msg.is_object() {
+                    Ok(Some(msg.get()?))
+                } else {
+                    Ok(None)
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Spawns the operation into the JS runtime and returns a Promise settled by its result, so the JS event loop
+    /// keeps running while the store is working.
+    fn spawn_promise<F, Fut>(self, ctx: &Ctx<'js>, op: F) -> rquickjs::Result<Value<'js>>
+    where
+        F: FnOnce(Self, Ctx<'js>) -> Fut,
+        Fut: std::future::Future<Output = crate::Result<Value<'js>>> + 'js,
+    {
+        let (promise, resolve, reject) = ctx.promise()?;
+        let async_ctx = ctx.clone();
+        let fut = op(self, ctx.clone());
+        ctx.spawn(async move {
+            let settled = match fut.await {
+                Ok(value) => resolve.call::<_, ()>((value,)),
+                Err(e) => {
+                    let error = Exception::from_message(async_ctx.clone(), &e.to_string()).into_js(&async_ctx);
+                    reject.call::<_, ()>((error,))
+                }
+            };
+            if let Err(e) = settled {
+                log::warn!("Failed to settle the context Promise: {}", e);
+            }
+        });
+        Ok(promise.into_value())
+    }
+
+    /// Takes the store from the key like `#:(file)::foo` if the store is not specified, as Node-RED does for a key
+    /// which is not an array.
+    fn split_store_of_key(
+        &self,
+        store: Option<String>,
+        keys: ContextKeys,
+    ) -> crate::Result<(Option<String>, ContextKeys)> {
+        let key = match keys {
+            ContextKeys::One(key) if key.starts_with("#:") => key,
+            keys => return Ok((store, keys)),
+        };
+        let mut parsed_keys = self.parsed_keys.lock().expect("`parsed_keys` lock");
+        let parsed = match parsed_keys.get(&key) {
+            Some(parsed) => parsed.clone(),
+            None => {
+                let parsed = ContextKeyRef::parse(&key)?;
+                if parsed_keys.len() >= PARSED_KEYS_CAPACITY {
+                    parsed_keys.clear();
+                }
+                parsed_keys.insert(key, parsed.clone());
+                parsed
+            }
+        };
+        Ok((store.or(parsed.store), ContextKeys::One(parsed.key)))
+    }
+}
+
+async fn get_values(
+    red_ctx: &RedContext,
+    store: Option<&str>,
+    keys: &[String],
+    msg: Option<&Variant>,
+) -> Vec<UndefinableVariant> {
+    let env = msg.map(|msg| [PropexEnv::ExtRef("msg", msg)]);
+    let env = env.as_ref().map_or(&[][..], |x| x.as_slice());
+    let mut values = Vec::with_capacity(keys.len());
+    for key in keys.iter() {
+        values.push(UndefinableVariant(red_ctx.get_one(store, key, env).await));
+    }
+    values
+}
+
+/// Sets the values in order, the `None` value removes the key.
+async fn set_values(
+    red_ctx: &RedContext,
+    store: Option<&str>,
+    pairs: Vec<(String, Option<Variant>)>,
+    msg: Option<&Variant>,
+) -> crate::Result<()> {
+    let env = msg.map(|msg| [PropexEnv::ExtRef("msg", msg)]);
+    let env = env.as_ref().map_or(&[][..], |x| x.as_slice());
+    for (key, value) in pairs.into_iter() {
+        red_ctx.set_one(store, &key, value, env).await?;
+    }
+    Ok(())
+}
+
+/// The `keys` argument of `get()` and `set()`, either a key or an array of keys.
+enum ContextKeys {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl ContextKeys {
+    fn from_js(keys: Value<'_>) -> rquickjs::Result<Self> {
+        if keys.is_array() {
+            Ok(ContextKeys::Many(keys.get()?))
+        } else {
+            Ok(ContextKeys::One(keys.get()?))
+        }
+    }
+
+    fn as_slice(&self) -> &[String] {
+        match self {
+            ContextKeys::One(key) => std::slice::from_ref(key),
+            ContextKeys::Many(keys) => keys.as_slice(),
+        }
+    }
+}
+
+/// Converts the JS value to store, the `undefined` becomes `None`, which deletes the key like Node-RED.
+fn to_context_value(value: Value<'_>) -> rquickjs::Result<Option<Variant>> {
+    if value.is_undefined() {
+        Ok(None)
+    } else {
+        Ok(Some(value.get()?))
+    }
+}
+
+/// The store name can be omitted before the callback, e.g. `flow.get('x', cb)`, and the default store is used
+/// if the store name is absent, `undefined` or `null`.
+fn split_store_and_callback<'js>(
+    store: Opt<Value<'js>>,
+    cb: Opt<Function<'js>>,
+) -> rquickjs::Result<(Option<String>, Option<Function<'js>>)> {
+    match store.0 {
+        Some(value) if value.is_function() => Ok((None, value.into_function().or(cb.0))),
+        Some(value) if value.is_undefined() || value.is_null() => Ok((None, cb.0)),
+        Some(value) => Ok((Some(value.get::<String>()?), cb.0)),
+        None => Ok((None, cb.0)),
+    }
+}
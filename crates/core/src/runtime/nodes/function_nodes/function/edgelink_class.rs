@@ -1,6 +1,8 @@
-use rquickjs::{class::Trace, Ctx, Result, Value};
+use rquickjs::convert::Coerced;
+use rquickjs::{class::Trace, Ctx, Exception, FromJs, Result, Type, Value};
 
 use crate::runtime::js::util;
+use crate::runtime::model::{Msg, MsgParts, Variant};
 
 #[derive(Clone, Trace, Default)]
 #[rquickjs::class(frozen)]
@@ -14,4 +16,68 @@ impl<'js> EdgelinkClass {
     fn deep_clone(&self, obj: Value<'js>, ctx: Ctx<'js>) -> Result<Value<'js>> {
         util::deep_clone(ctx, obj)
     }
+
+    /// Generates a new `msg.parts.id` in the same format of the split node
+    #[qjs(rename = "newSequenceId")]
+    fn new_sequence_id(&self) -> String {
+        Msg::generate_id().to_string()
+    }
+
+    /// Validates the `msg.parts` object and normalizes it through the `MsgParts` of Rust
+    #[qjs(rename = "normalizeParts")]
+    fn normalize_parts(&self, parts: Variant, ctx: Ctx<'js>) -> Result<Variant> {
+        MsgParts::from_variant(&parts)
+            .map(|x| x.to_variant())
+            .map_err(|e| Exception::throw_message(&ctx, &e.to_string()))
+    }
+
+    /// `RED.util.ensureString`, the buffer is decoded as UTF-8 and the object is stringified as JSON
+    #[qjs(rename = "ensureString")]
+    fn ensure_string(&self, value: Value<'js>, ctx: Ctx<'js>) -> Result<String> {
+        match value.type_of() {
+            // `typeof null` is "object" in JS, which is stringified as "null" too
+            Type::Object | Type::Array | Type::Null => match Variant::from_js(&ctx, value)? {
+                Variant::Bytes(bytes) => Ok(String::from_utf8_lossy(&bytes).into_owned()),
+                Variant::String(s) => Ok(s),
+                other => serde_json::to_string(&other).map_err(|e| Exception::throw_message(&ctx, &e.to_string())),
+            },
+            // The same as `"" + value`, e.g. `1.0` becomes "1" and `undefined` becomes "undefined"
+            _ => Ok(Coerced::<String>::from_js(&ctx, value)?.0),
+        }
+    }
+
+    /// `RED.util.ensureBuffer`, the non-buffer value is converted by `ensureString` and encoded as UTF-8
+    #[qjs(rename = "ensureBuffer")]
+    fn ensure_buffer(&self, value: Value<'js>, ctx: Ctx<'js>) -> Result<Variant> {
+        if let Some(buf) = value.as_object().and_then(|x| x.as_array_buffer()) {
+            return Ok(Variant::Bytes(buf.as_bytes().map(|x| x.to_vec()).unwrap_or_default()));
+        }
+        Ok(Variant::Bytes(self.ensure_string(value, ctx)?.into_bytes()))
+    }
+
+    /// `RED.util.compareObjects`, compares the values deeply, the buffers are compared by their bytes
+    #[qjs(rename = "compareObjects")]
+    fn compare_objects(&self, obj1: Value<'js>, obj2: Value<'js>, ctx: Ctx<'js>) -> Result<bool> {
+        // `undefined` and `null` are both `Variant::Null`, but they are not equal in Node-RED
+        if obj1.is_undefined() || obj2.is_undefined() {
+            return Ok(obj1.is_undefined() && obj2.is_undefined());
+        }
+        let obj1 = Variant::from_js(&ctx, obj1)?;
+        let obj2 = Variant::from_js(&ctx, obj2)?;
+        Ok(js_values_equal(&obj1, &obj2))
+    }
+}
+
+/// Compares the `Variant`s like JS, the integer and the float numbers of the same value are equal, e.g. `1` and `0.5 * 2`.
+fn js_values_equal(a: &Variant, b: &Variant) -> bool {
+    match (a, b) {
+        (Variant::Number(x), Variant::Number(y)) => x.as_f64() == y.as_f64(),
+        (Variant::Array(x), Variant::Array(y)) => {
+            x.len() == y.len() && x.iter().zip(y.iter()).all(|(x, y)| js_values_equal(x, y))
+        }
+        (Variant::Object(x), Variant::Object(y)) => {
+            x.len() == y.len() && x.iter().all(|(k, v)| y.get(k).is_some_and(|w| js_values_equal(v, w)))
+        }
+        _ => a == b,
+    }
 }
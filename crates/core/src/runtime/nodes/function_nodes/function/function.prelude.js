@@ -3,39 +3,16 @@ const RED = (function () {
     return {
         util: {
 
-            __cloneDeep: function (value, map = new WeakMap()) {
-                if (value === null || typeof value !== 'object') {
-                    return value;
-                }
-
-                if (map.has(value)) {
-                    return map.get(value);
-                }
-
-                if (value instanceof Date) {
-                    return new Date(value.getTime());
-                }
-
-                if (value instanceof RegExp) {
-                    return new RegExp(value);
-                }
-
-                if (Array.isArray(value)) {
-                    const clonedArray = value.map(item => this.__cloneDeep(item, map));
-                    map.set(value, clonedArray);
-                    return clonedArray;
-                }
-
-                const clonedObj = {};
-                map.set(value, clonedObj);
+            ensureString: function (o) {
+                return __edgelink.ensureString(o);
+            },
 
-                for (const key in value) {
-                    if (value.hasOwnProperty(key)) {
-                        clonedObj[key] = this.__cloneDeep(value[key], map);
-                    }
-                }
+            ensureBuffer: function (o) {
+                return __edgelink.ensureBuffer(o);
+            },
 
-                return clonedObj;
+            compareObjects: function (obj1, obj2) {
+                return __edgelink.compareObjects(obj1, obj2);
             },
 
             cloneMessage: function (msg) {
@@ -47,7 +24,7 @@ const RED = (function () {
                     var res = msg.res;
                     delete msg.req;
                     delete msg.res;
-                    var m = this.__cloneDeep(msg);
+                    var m = __edgelink.deepClone(msg);
                     if (req) {
                         m.req = req;
                         msg.req = req;
@@ -60,7 +37,66 @@ const RED = (function () {
                 }
                 return msg;
 
+            },
+
+            // Returns the normalized `msg.parts`, or `null` if the message is not in a sequence
+            getParts: function (msg) {
+                if (msg === null || typeof msg !== 'object' || msg.parts === undefined || msg.parts === null) {
+                    return null;
+                }
+                return __edgelink.normalizeParts(msg.parts);
+            },
+
+            // Validates and sets the `msg.parts`, throws if the `id` or `index` is missing
+            setParts: function (msg, parts) {
+                msg.parts = __edgelink.normalizeParts(parts);
+                return msg;
+            },
+
+            // Splits the array or object into a sequence of cloned messages, like the split node does
+            makeSequence: function (msg, items, property) {
+                property = property || 'payload';
+                const isArray = Array.isArray(items);
+                if (!isArray && (items === null || typeof items !== 'object')) {
+                    throw new Error('Only an array or an object can be made into a sequence');
+                }
+                const keys = isArray ? null : Object.keys(items);
+                const count = isArray ? items.length : keys.length;
+                const id = __edgelink.newSequenceId();
+                const msgs = [];
+                for (let i = 0; i < count; i++) {
+                    const m = this.cloneMessage(msg);
+                    const parts = { id: id, type: isArray ? 'array' : 'object', index: i, count: count };
+                    if (!isArray) {
+                        parts.key = keys[i];
+                    }
+                    // Keeps the parts of the outer sequence
+                    if (msg.parts !== undefined && msg.parts !== null) {
+                        parts.parts = msg.parts;
+                    }
+                    m[property] = __edgelink.deepClone(isArray ? items[i] : items[keys[i]]);
+                    m.parts = __edgelink.normalizeParts(parts);
+                    msgs.push(m);
+                }
+                return msgs;
             }
         }
     };
 })();
+
+// The handlers registered by `node.on('close', ...)`
+var __el_closeHandlers = [];
+
+// Invokes a close handler like Node-RED, the handler may take the `done` callback or return a promise
+function __el_invokeCloseHandler(handler, removed) {
+    return new Promise((resolve, reject) => {
+        const done = (err) => err ? reject(err) : resolve();
+        if (handler.length >= 2) {
+            handler.call(node, removed, done);
+        } else if (handler.length === 1) {
+            handler.call(node, done);
+        } else {
+            Promise.resolve(handler.call(node)).then(() => resolve(), reject);
+        }
+    });
+}
@@ -0,0 +1,16 @@
+// Replaces the clock and the random numbers with deterministic ones, only evaluated if the test clock is configured
+(function (nowMs, seed) {
+    Date.now = function () {
+        return nowMs;
+    };
+
+    // Mulberry32, a small seeded PRNG
+    let state = seed >>> 0;
+    Math.random = function () {
+        state = (state + 0x6D2B79F5) >>> 0;
+        let t = state;
+        t = Math.imul(t ^ (t >>> 15), t | 1);
+        t ^= t + Math.imul(t ^ (t >>> 7), t | 61);
+        return ((t ^ (t >>> 14)) >>> 0) / 4294967296;
+    };
+})
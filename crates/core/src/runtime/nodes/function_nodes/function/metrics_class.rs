@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Weak};
+
+use rquickjs::{class::Trace, prelude::Opt, Ctx, Exception};
+
+use crate::runtime::metrics;
+
+use super::*;
+
+const COUNTER_HELP: &str = "Counter created by a function node";
+const HISTOGRAM_HELP: &str = "Histogram created by a function node";
+
+/// The prefix of the metrics created by the user scripts, so they never collide with the metrics of the engine
+const USER_METRIC_PREFIX: &str = "edgelink_user_";
+
+/// The most metrics a function node can create, a script creating the metrics by its data would grow the registry
+/// without bounds
+const MAX_USER_METRICS: usize = 64;
+
+/// The metrics created by the user script, cached by the names so the script can get them for every message.
+///
+/// They live as long as the node like the `NodeMetrics`, and are removed from the registry when the node is dropped.
+pub(super) struct UserMetrics {
+    registry: prometheus::Registry,
+    counters: HashMap<String, prometheus::Counter>,
+    histograms: HashMap<String, prometheus::Histogram>,
+}
+
+impl std::fmt::Debug for UserMetrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UserMetrics")
+            .field("counters", &self.counters.keys().collect::<Vec<_>>())
+            .field("histograms", &self.histograms.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl UserMetrics {
+    pub fn new(registry: prometheus::Registry) -> Self {
+        UserMetrics { registry, counters: HashMap::new(), histograms: HashMap::new() }
+    }
+
+    fn counter(&mut self, node: &FunctionNode, name: &str) -> crate::Result<prometheus::Counter> {
+        if let Some(counter) = self.counters.get(name) {
+            return Ok(counter.clone());
+        }
+        self.check_capacity()?;
+        let opts = prometheus::Opts::new(format!("{}{}", USER_METRIC_PREFIX, name), COUNTER_HELP)
+            .const_labels(metrics::node_const_labels(node));
+        let counter = prometheus::Counter::with_opts(opts)?;
+        self.registry.register(Box::new(counter.clone()))?;
+        self.counters.insert(name.to_string(), counter.clone());
+        Ok(counter)
+    }
+
+    /// The buckets of a histogram are fixed by its first creation.
+    fn histogram(
+        &mut self,
+        node: &FunctionNode,
+        name: &str,
+        buckets: Option<Vec<f64>>,
+    ) -> crate::Result<prometheus::Histogram> {
+        if let Some(histogram) = self.histograms.get(name) {
+            return Ok(histogram.clone());
+        }
+        self.check_capacity()?;
+        let opts = prometheus::HistogramOpts::new(format!("{}{}", USER_METRIC_PREFIX, name), HISTOGRAM_HELP)
+            .const_labels(metrics::node_const_labels(node))
+            .buckets(buckets.unwrap_or_else(|| prometheus::DEFAULT_BUCKETS.to_vec()));
+        let histogram = prometheus::Histogram::with_opts(opts)?;
+        self.registry.register(Box::new(histogram.clone()))?;
+        self.histograms.insert(name.to_string(), histogram.clone());
+        Ok(histogram)
+    }
+
+    fn check_capacity(&self) -> crate::Result<()> {
+        if self.counters.len() + self.histograms.len() >= MAX_USER_METRICS {
+            return Err(EdgelinkError::InvalidOperation(format!(
+                "A function node can create {} metrics at most",
+                MAX_USER_METRICS
+            ))
+            .into());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for UserMetrics {
+    fn drop(&mut self) {
+        for (_, counter) in self.counters.drain() {
+            let _ = self.registry.unregister(Box::new(counter));
+        }
+        for (_, histogram) in self.histograms.drain() {
+            let _ = self.registry.unregister(Box::new(histogram));
+        }
+    }
+}
+
+/// The `node.metrics` object.
+#[derive(Clone, Trace)]
+#[rquickjs::class(frozen)]
+pub(super) struct MetricsClass {
+    #[qjs(skip_trace)]
+    node: Weak<FunctionNode>,
+}
+
+#[rquickjs::methods]
+impl MetricsClass {
+    #[qjs(skip)]
+    pub fn new(node: &Arc<FunctionNode>) -> Self {
+        MetricsClass { node: Arc::downgrade(node) }
+    }
+
+    /// Gets or creates the counter of the name, labelled by the node and exported with the `edgelink_user_` prefix.
+    fn counter<'js>(&self, name: String, ctx: Ctx<'js>) -> rquickjs::Result<CounterClass> {
+        let node = self.node.upgrade().ok_or(rquickjs::Error::UnrelatedRuntime)?;
+        let mut user_metrics = node.user_metrics.lock().expect("`user_metrics` lock");
+        match user_metrics.counter(&node, &name) {
+            Ok(counter) => Ok(CounterClass { counter }),
+            Err(e) => Err(Exception::throw_message(&ctx, &format!("Failed to create the counter '{}': {}", name, e))),
+        }
+    }
+
+    /// Gets or creates the histogram of the name, labelled by the node, the buckets are the upper bounds.
+    fn histogram<'js>(&self, name: String, buckets: Opt<Vec<f64>>, ctx: Ctx<'js>) -> rquickjs::Result<HistogramClass> {
+        let node = self.node.upgrade().ok_or(rquickjs::Error::UnrelatedRuntime)?;
+        let mut user_metrics = node.user_metrics.lock().expect("`user_metrics` lock");
+        match user_metrics.histogram(&node, &name, buckets.0) {
+            Ok(histogram) => Ok(HistogramClass { histogram }),
+            Err(e) => Err(Exception::throw_message(&ctx, &format!("Failed to create the histogram '{}': {}", name, e))),
+        }
+    }
+}
+
+#[derive(Clone, Trace)]
+#[rquickjs::class(frozen)]
+pub(super) struct CounterClass {
+    #[qjs(skip_trace)]
+    counter: prometheus::Counter,
+}
+
+#[rquickjs::methods]
+impl CounterClass {
+    /// Increases the counter by 1 or the non-negative value.
+    fn inc<'js>(&self, value: Opt<f64>, ctx: Ctx<'js>) -> rquickjs::Result<()> {
+        match value.0 {
+            None => self.counter.inc(),
+            Some(value) if value >= 0.0 => self.counter.inc_by(value),
+            Some(value) => {
+                return Err(Exception::throw_range(&ctx, &format!("A counter cannot be decreased, got: {}", value)))
+            }
+        }
+        Ok(())
+    }
+
+    #[qjs(get, rename = "value")]
+    fn get_value(&self) -> f64 {
+        self.counter.get()
+    }
+}
+
+#[derive(Clone, Trace)]
+#[rquickjs::class(frozen)]
+pub(super) struct HistogramClass {
+    #[qjs(skip_trace)]
+    histogram: prometheus::Histogram,
+}
+
+#[rquickjs::methods]
+impl HistogramClass {
+    fn observe(&self, value: f64) {
+        self.histogram.observe(value);
+    }
+
+    #[qjs(get, rename = "count")]
+    fn get_count(&self) -> u64 {
+        self.histogram.get_sample_count()
+    }
+}
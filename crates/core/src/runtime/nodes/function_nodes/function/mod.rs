@@ -21,10 +21,18 @@ use crate::utils::constants::ENV_STR;
 mod context_class;
 mod edgelink_class;
 mod env_class;
+#[cfg(feature = "metrics")]
+mod metrics_class;
 mod node_class;
 
 const OUTPUT_MSGS_CAP: usize = 4;
 
+/// The JavaScript array of the handlers registered by `node.on('close', ...)`, declared in the prelude
+const CLOSE_HANDLERS_GLOBAL: &str = "__el_closeHandlers";
+
+/// How long to wait for the close handlers, the same as the `nodeCloseTimeout` of Node-RED
+const CLOSE_HANDLERS_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(15);
+
 type OutputMsgs = smallvec::SmallVec<[(usize, Msg); OUTPUT_MSGS_CAP]>;
 
 #[derive(Deserialize, Debug)]
@@ -40,18 +48,57 @@ struct FunctionNodeConfig {
 
     #[serde(default, rename = "outputs")]
     output_count: usize,
+
+    /// Clones the input message before passing it to the user function.
+    ///
+    /// If `false`, the user function takes the message and only `_msgid` is kept in the original one, it saves a
+    /// deep clone for each message. The `catch` and `complete` nodes will not see the other properties of the
+    /// original message then.
+    #[serde(default = "clone_input_default", rename = "cloneInput")]
+    clone_input: bool,
+
+    /// The JSON Schema to validate the returned messages, either a JSON object or a JSON string
+    #[serde(default, deserialize_with = "json::deser::deser_json_schema")]
+    schema: Option<serde_json::Value>,
+
+    /// Sends the messages failed the schema validation to an extra output port instead of the `catch` nodes
+    #[serde(default, rename = "schemaOutput")]
+    schema_output: bool,
 }
 
+fn clone_input_default() -> bool {
+    true
+}
+
+type SentEnvelopes = SmallVec<[Envelope; OUTPUT_MSGS_CAP]>;
+
 #[derive(Debug)]
 #[flow_node("function")]
 struct FunctionNode {
     base: FlowNode,
 
     output_count: usize,
+    clone_input: bool,
+    schema: Option<jsonschema::Validator>,
+    schema_output: bool,
     user_script: Vec<u8>,
+
+    /// The running invocation of the user function and the channel to stream the msgs sent by its `node.send()`
+    streaming_tx: std::sync::Mutex<Option<(u64, tokio::sync::mpsc::UnboundedSender<SentEnvelopes>)>>,
+
+    /// The sequence to number the invocations of the user function
+    invocation_seq: std::sync::atomic::AtomicU64,
+
+    /// The stop token of `run()`, the msgs sent out of the invocations are cancelled by it when the node stops
+    stop_token: std::sync::Mutex<CancellationToken>,
+
+    /// The metrics created by `node.metrics` of the user script
+    #[cfg(feature = "metrics")]
+    user_metrics: std::sync::Mutex<metrics_class::UserMetrics>,
 }
 
 const JS_PRELUDE_SCRIPT: &str = include_str!("./function.prelude.js");
+const JS_TEST_CLOCK_SCRIPT: &str = include_str!("./function.test_clock.js");
 
 #[async_trait]
 impl FlowNodeBehavior for FunctionNode {
@@ -60,6 +107,8 @@ impl FlowNodeBehavior for FunctionNode {
     }
 
     async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        *self.stop_token.lock().unwrap() = stop_token.clone();
+
         // This is a workaround; ideally, all function nodes should share a runtime. However,
         // for some reason, if the runtime of rquickjs is used as a global variable,
         // the members of node and env will disappear upon the second load.
@@ -76,9 +125,7 @@ impl FlowNodeBehavior for FunctionNode {
         async_with!(js_ctx => |ctx| {
             if let Err(e) = cloned_this.prepare_js_ctx(&ctx) {
                 // It's a fatal error
-                log::error!("[function:{}] Fatal error! Failed to prepare JavaScript context: {:?}", cloned_this.name(), e);
-
-                stop_token.cancel();
+                cloned_this.report_fatal(&format!("Failed to prepare JavaScript context: {:?}", e), &stop_token);
                 stop_token.cancelled().await;
                 return;
             }
@@ -86,9 +133,7 @@ impl FlowNodeBehavior for FunctionNode {
 
             if let Err(e) = cloned_this.init_async(ctx.clone()).await {
                 // It's a fatal error
-                log::error!("[function:{}] Fatal error! Failed to initialize JavaScript environment: {:?}", cloned_this.name(), e);
-
-                stop_token.cancel();
+                cloned_this.report_fatal(&format!("Failed to initialize JavaScript environment: {:?}", e), &stop_token);
                 stop_token.cancelled().await;
                 return;
             }
@@ -99,13 +144,37 @@ impl FlowNodeBehavior for FunctionNode {
                 let cancel = stop_token.child_token();
                 let this_node = cloned_this.clone();
                 with_uow(this_node.clone().as_ref(), cancel.child_token(), |_, msg| async move {
-                    let res = {
-                        let msg_guard = msg.write().await;
+                    let (origin_msg_id, expires_at, res) = {
+                        let mut msg_guard = msg.write().await;
+                        let origin_msg_id = msg_guard.id();
+                        let expires_at = msg_guard.expires_at();
+                        let input_msg = if this_node.clone_input {
+                            msg_guard.clone()
+                        } else {
+                            let id = msg_guard.id();
+                            let taken = std::mem::take(&mut *msg_guard);
+                            if let Some(id) = id {
+                                msg_guard.set_id(id);
+                            }
+                            taken
+                        };
                         // This gonna eat the msg and produce a new one
-                        this_node.filter_msg(sub_ctx.clone(), msg_guard.clone()).await
+                        let res = this_node.filter_msg(sub_ctx.clone(), input_msg, cancel.clone()).await;
+                        (origin_msg_id, expires_at, res)
                     };
                     match res {
-                        Ok(changed_msgs) => {
+                        Ok(mut changed_msgs) => {
+                            // The TTL is lost in the JavaScript, the returned messages inherit it from the input
+                            for (_, changed_msg) in changed_msgs.iter_mut() {
+                                if changed_msg.expires_at().is_none() {
+                                    changed_msg.set_expires_at(expires_at);
+                                }
+                            }
+                            let changed_msgs = if this_node.schema.is_some() {
+                                this_node.guard_schema(changed_msgs, origin_msg_id, cancel.clone()).await
+                            } else {
+                                changed_msgs
+                            };
                             // Pack the new messages
                             if !changed_msgs.is_empty() {
                                 let envelopes = changed_msgs
@@ -130,6 +199,9 @@ impl FlowNodeBehavior for FunctionNode {
                 log::error!("[function:{}] Fatal error! Failed to finalize JavaScript environment: {:?}", cloned_this.name(), e);
             }
             while ctx.execute_pending_job() {}
+
+            cloned_this.close_async(ctx.clone()).await;
+            while ctx.execute_pending_job() {}
         })
         .await;
 
@@ -149,6 +221,13 @@ impl FunctionNode {
         if function_config.output_count == 0 {
             function_config.output_count = 1;
         }
+        if function_config.schema_output && base_node.ports.len() <= function_config.output_count {
+            return Err(EdgelinkError::BadFlowsJson(
+                "The function node requires an extra output port for the schema validation failures".into(),
+            )
+            .into());
+        }
+        let schema = function_config.schema.as_ref().map(Variant::compile_schema).transpose()?;
 
         let user_script = format!(
             "
@@ -161,10 +240,10 @@ impl FunctionNode {
                 \n{}\n
             }}
 
-            async function __el_user_func(msg) {{ 
-                let global = __edgelinkGlobalContext; 
-                let flow = __edgelinkFlowContext; 
-                let context = __edgelinkNodeContext; 
+            async function __el_user_func(msg, node) {{ 
+                let global = __edgelinkGlobalContext.__el_bindMsg(() => msg); 
+                let flow = __edgelinkFlowContext.__el_bindMsg(() => msg); 
+                let context = __edgelinkNodeContext.__el_bindMsg(() => msg); 
                 let __msgid__ = msg._msgid; 
                 context.flow = flow;
                 context.global = global;
@@ -185,10 +264,20 @@ impl FunctionNode {
             function_config.finalize.unwrap_or("".to_string()),
         );
 
+        #[cfg(feature = "metrics")]
+        let user_metrics = metrics_class::UserMetrics::new(base_node.metrics_registry.clone());
         let node = FunctionNode {
             base: base_node,
             output_count: function_config.output_count,
+            clone_input: function_config.clone_input,
+            schema,
+            schema_output: function_config.schema_output,
             user_script: user_script.as_bytes().to_vec(),
+            streaming_tx: std::sync::Mutex::new(None),
+            invocation_seq: std::sync::atomic::AtomicU64::new(0),
+            stop_token: std::sync::Mutex::new(CancellationToken::new()),
+            #[cfg(feature = "metrics")]
+            user_metrics: std::sync::Mutex::new(user_metrics),
         };
         Ok(Box::new(node))
     }
@@ -198,14 +287,38 @@ impl FunctionNode {
     }
     */
 
-    async fn filter_msg<'js>(self: &Arc<Self>, ctx: js::Ctx<'js>, msg: Msg) -> crate::Result<OutputMsgs> {
+    async fn filter_msg<'js>(
+        self: &Arc<Self>,
+        ctx: js::Ctx<'js>,
+        msg: Msg,
+        cancel: CancellationToken,
+    ) -> crate::Result<OutputMsgs> {
         let origin_msg_id = msg.id();
 
         let user_func: js::Function = ctx.globals().get("__el_user_func")?;
         let js_msg = msg.into_js(&ctx)?;
-        let args = (js_msg,);
+        // Each invocation gets its own `node`, so the msgs sent later by its timers are not taken by another one
+        let invocation = self.invocation_seq.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let js_node = node_class::NodeClass::with_invocation(self, invocation).into_js_node(&ctx)?;
+        let args = (js_msg, js_node);
+
+        // Streams the msgs sent by `node.send()` in order while the user function is awaiting
+        let (streaming_tx, mut streaming_rx) = tokio::sync::mpsc::unbounded_channel();
+        *self.streaming_tx.lock().unwrap() = Some((invocation, streaming_tx));
         let promised = user_func.call::<_, rquickjs::Promise>(args)?;
-        let js_res_value: js::Result<js::Value> = promised.into_future().await;
+        let js_res_future = promised.into_future::<js::Value>();
+        tokio::pin!(js_res_future);
+        let js_res_value: js::Result<js::Value> = loop {
+            tokio::select! {
+                res = &mut js_res_future => break res,
+                Some(envelopes) = streaming_rx.recv() => self.fan_out_sent(envelopes, cancel.clone()).await,
+            }
+        };
+        *self.streaming_tx.lock().unwrap() = None;
+        while let Ok(envelopes) = streaming_rx.try_recv() {
+            self.fan_out_sent(envelopes, cancel.clone()).await;
+        }
+
         let eval_result = match js_res_value.catch(&ctx) {
             Ok(js_result) => self.convert_return_value(&ctx, js_result, origin_msg_id),
             Err(e) => {
@@ -227,6 +340,73 @@ impl FunctionNode {
         }
     }
 
+    /// Validates the returned msgs against the schema, the invalid ones will be sent to the extra output port if
+    /// `schemaOutput` is set, or reported to the `catch` nodes with the ID of the input msg.
+    async fn guard_schema(
+        self: &Arc<Self>,
+        msgs: OutputMsgs,
+        origin_msg_id: Option<ElementId>,
+        cancel: CancellationToken,
+    ) -> OutputMsgs {
+        let schema = match self.schema.as_ref() {
+            Some(schema) => schema,
+            None => return msgs,
+        };
+        let mut valid_msgs = OutputMsgs::new();
+        for (port, mut msg) in msgs.into_iter() {
+            let err = match msg.as_variant().validate_with(schema) {
+                Ok(()) => {
+                    valid_msgs.push((port, msg));
+                    continue;
+                }
+                Err(err) => err,
+            };
+            if let Some(id) = origin_msg_id {
+                msg.set_id(id);
+            }
+            if self.schema_output {
+                msg.set("error".into(), Variant::from(serde_json::json!({ "message": err.to_string() })));
+                valid_msgs.push((self.output_count, msg));
+            } else {
+                let handled = match self.flow() {
+                    Some(flow) => flow
+                        .handle_error(self.as_ref(), &err.to_string(), Some(MsgHandle::new(msg)), None, cancel.clone())
+                        .await
+                        .unwrap_or(false),
+                    None => false,
+                };
+                if !handled {
+                    log::error!("[function:{}] {}", self.name(), err);
+                }
+            }
+        }
+        valid_msgs
+    }
+
+    /// Sends the msgs of `node.send()`, they will be streamed if the invocation sending them is still running.
+    fn send_from_js(self: &Arc<Self>, ctx: &js::Ctx<'_>, invocation: Option<u64>, envelopes: SentEnvelopes) {
+        let envelopes = match (self.streaming_tx.lock().unwrap().as_ref(), invocation) {
+            (Some((running, tx)), Some(invocation)) if *running == invocation => match tx.send(envelopes) {
+                Ok(()) => return,
+                Err(err) => err.0,
+            },
+            _ => envelopes,
+        };
+
+        // Not in the running invocation, e.g. sent by a timer
+        let node = self.clone();
+        let cancel = self.stop_token.lock().unwrap().child_token();
+        ctx.spawn(async move {
+            node.fan_out_sent(envelopes, cancel).await;
+        });
+    }
+
+    async fn fan_out_sent(&self, envelopes: SentEnvelopes, cancel: CancellationToken) {
+        if let Err(err) = self.fan_out_many(envelopes, cancel).await {
+            log::error!("[function:{}] Failed to send msg(s): {}", self.name(), err);
+        }
+    }
+
     fn convert_return_value<'js>(
         &self,
         ctx: &js::Ctx<'js>,
@@ -235,33 +415,17 @@ impl FunctionNode {
     ) -> js::Result<OutputMsgs> {
         let mut items = OutputMsgs::new();
         match js_result.type_of() {
-            // Returns an array of Msgs
+            // Returns an array of Msgs, the item of each port can be a Msg or an array of Msgs
             js::Type::Array => {
                 for (port, ele) in js_result.as_array().unwrap().iter::<js::Value>().enumerate() {
                     match ele {
                         Ok(ele) => {
                             if let Some(subarr) = ele.as_array() {
-                                for subele in subarr.iter() {
-                                    let obj: js::Value = subele.unwrap();
-                                    if obj.is_null() {
-                                        continue;
-                                    }
-                                    let mut msg = Msg::from_js(ctx, obj)?;
-                                    if let Some(org_id) = origin_msg_id {
-                                        msg.set_id(org_id);
-                                    }
-                                    items.push((port, msg));
-                                }
-                            } else if ele.is_object() && !ele.is_null() {
-                                let mut msg = Msg::from_js(ctx, ele)?;
-                                if let Some(org_id) = origin_msg_id {
-                                    msg.set_id(org_id);
+                                for subele in subarr.iter::<js::Value>() {
+                                    self.push_returned_msg(ctx, &mut items, port, subele?, origin_msg_id)?;
                                 }
-                                items.push((port, msg));
-                            } else if ele.is_null() {
-                                continue;
                             } else {
-                                log::warn!("Bad msg array item: \n{:#?}", ele);
+                                self.push_returned_msg(ctx, &mut items, port, ele, origin_msg_id)?;
                             }
                         }
                         Err(ref e) => {
@@ -273,8 +437,7 @@ impl FunctionNode {
 
             // Returns single Msg
             js::Type::Object => {
-                let item = (0, Msg::from_js(ctx, js_result)?);
-                items.push(item);
+                self.push_returned_msg(ctx, &mut items, 0, js_result, origin_msg_id)?;
             }
 
             js::Type::Null => {
@@ -296,6 +459,31 @@ impl FunctionNode {
         Ok(items)
     }
 
+    /// Converts a returned msg of the port, the msgs inherit the ID of the input msg and `null`/`undefined` are
+    /// skipped.
+    fn push_returned_msg<'js>(
+        &self,
+        ctx: &js::Ctx<'js>,
+        items: &mut OutputMsgs,
+        port: usize,
+        value: js::Value<'js>,
+        origin_msg_id: Option<ElementId>,
+    ) -> js::Result<()> {
+        if value.is_null() || value.is_undefined() {
+            return Ok(());
+        }
+        if !value.is_object() || value.is_array() {
+            log::warn!("[function:{}] Bad msg array item: \n{:#?}", self.name(), value);
+            return Ok(());
+        }
+        let mut msg = Msg::from_js(ctx, value)?;
+        if let Some(org_id) = origin_msg_id {
+            msg.set_id(org_id);
+        }
+        items.push((port, msg));
+        Ok(())
+    }
+
     async fn init_async<'js>(self: &Arc<Self>, ctx: js::Ctx<'js>) -> crate::Result<()> {
         log::debug!("[function:{}] Initializing JavaScript context...", self.name());
 
@@ -324,6 +512,40 @@ impl FunctionNode {
         }
     }
 
+    /// Runs the handlers registered by `node.on('close', ...)` in order, the failures are logged and the handlers
+    /// not settled in `CLOSE_HANDLERS_TIMEOUT` are abandoned.
+    async fn close_async<'js>(self: &Arc<Self>, ctx: js::Ctx<'js>) {
+        let (handlers, invoke) = match (
+            ctx.globals().get::<_, js::Array>(CLOSE_HANDLERS_GLOBAL),
+            ctx.globals().get::<_, js::Function>("__el_invokeCloseHandler"),
+        ) {
+            (Ok(handlers), Ok(invoke)) => (handlers, invoke),
+            _ => return,
+        };
+        let deadline = tokio::time::Instant::now() + CLOSE_HANDLERS_TIMEOUT;
+        for (i, handler) in handlers.iter::<js::Function>().enumerate() {
+            // The node is always reported as not removed, the engine does not tell the removal from the restart
+            let promised = match handler.and_then(|x| invoke.call::<_, rquickjs::Promise>((x, false))) {
+                Ok(promised) => promised,
+                Err(e) => {
+                    log::warn!("[function:{}] Failed to invoke the close handler #{}: {}", self.name(), i, e);
+                    continue;
+                }
+            };
+            match tokio::time::timeout_at(deadline, promised.into_future::<()>()).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => {
+                    let e = js::CaughtError::from_error(&ctx, e);
+                    log::warn!("[function:{}] The close handler #{} failed: {}", self.name(), i, e);
+                }
+                Err(_) => {
+                    log::warn!("[function:{}] The close handlers have timed out, the rest are skipped", self.name());
+                    return;
+                }
+            }
+        }
+    }
+
     fn prepare_js_ctx(self: &Arc<Self>, ctx: &js::Ctx<'_>) -> crate::Result<()> {
         // crate::runtime::red::js::red::register_red_object(&ctx).unwrap();
         // js::Class::<node_class::NodeClass>::register(&ctx)?;
@@ -342,8 +564,14 @@ impl FunctionNode {
         */
         ::rquickjs_extra::timers::init(ctx)?;
 
+        // Only the engine in testing has a test clock, the real `Date.now()` and `Math.random()` are kept otherwise
+        if let Some(test_clock) = self.engine().and_then(|x| x.test_clock().cloned()) {
+            let override_clock: js::Function = ctx.eval(JS_TEST_CLOCK_SCRIPT)?;
+            override_clock.call::<_, ()>((test_clock.now_ms as f64, test_clock.random_seed))?;
+        }
+
         ctx.globals().set(ENV_STR, env_class::EnvClass::new(self.envs()))?;
-        ctx.globals().set("node", node_class::NodeClass::new(self))?;
+        ctx.globals().set("node", node_class::NodeClass::new(self).into_js_node(ctx)?)?;
 
         // Register the global-scoped context
         if let Some(global_context) = self.engine().map(|x| x.context()) {
@@ -393,6 +621,7 @@ impl FunctionNode {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::runtime::model::propex::PropexSegment;
     use serde_json::json;
 
     #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
@@ -421,4 +650,870 @@ mod tests {
             assert_eq!(msg["count"], "0".into());
         }
     }
+
+    fn make_schema_guard_flows_json(schema_output: bool) -> serde_json::Value {
+        json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "schemaOutput": schema_output,
+                "schema": "{\"type\": \"object\", \"properties\": {\"payload\": {\"type\": \"number\"}}}",
+                "wires": [["3"], ["3"]], "func": "return {payload: msg.payload === 1 ? 1 : 'bad'};"},
+            {"id": "2", "type": "catch", "z": "100", "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"},
+        ])
+    }
+
+    #[tokio::test]
+    async fn test_schema_violation_should_be_caught_with_origin_msg_id() {
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"_msgid": "1234", "payload": 1}],
+            ["1", {"_msgid": "5678", "payload": 2}],
+        ]))
+        .unwrap();
+        let engine = crate::runtime::engine::build_test_engine(make_schema_guard_flows_json(false)).unwrap();
+        let msgs =
+            engine.run_once_with_inject(2, std::time::Duration::from_secs_f64(0.5), msgs_to_inject).await.unwrap();
+
+        assert_eq!(msgs[0]["payload"], Variant::from(1));
+        assert_eq!(msgs[1]["payload"], Variant::from("bad"));
+        assert_eq!(msgs[1].id(), Some(ElementId::with_u64(0x5678)));
+        let error_message = msgs[1].get_nav_stripped("error.message").unwrap().as_str().unwrap();
+        assert!(error_message.contains("Schema validation failed at '/payload'"));
+    }
+
+    #[tokio::test]
+    async fn test_schema_violation_should_be_sent_to_extra_port() {
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": 2}]])).unwrap();
+        let engine = crate::runtime::engine::build_test_engine(make_schema_guard_flows_json(true)).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.5), msgs_to_inject).await.unwrap();
+
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0]["payload"], Variant::from("bad"));
+        assert!(msgs[0].get_nav_stripped("error.message").is_some());
+        assert!(msgs[0].get_nav_stripped("error.source").is_none());
+    }
+
+    async fn run_clone_input_flow(clone_input: bool) -> Vec<Msg> {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "cloneInput": clone_input, "wires": [["3"]], "func": "
+                if (msg.topic === 'throw') { throw new Error('failed'); }
+                msg.payload += 1;
+                return msg;
+            "},
+            {"id": "2", "type": "catch", "z": "100", "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"},
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"payload": 1}],
+            ["1", {"payload": 2, "topic": "throw"}],
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        engine.run_once_with_inject(2, std::time::Duration::from_secs_f64(0.5), msgs_to_inject).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_clone_input_modes_should_produce_same_outputs() {
+        let cloned = run_clone_input_flow(true).await;
+        let taken = run_clone_input_flow(false).await;
+        assert_eq!(cloned[0]["payload"], Variant::from(2));
+        assert_eq!(taken[0]["payload"], Variant::from(2));
+
+        // The catch node sees the original message only if it has been cloned
+        assert_eq!(cloned[1]["payload"], Variant::from(2));
+        assert!(cloned[1].get_nav_stripped("error.message").is_some());
+        assert!(!taken[1].contains("payload"));
+        assert!(taken[1].id().is_some());
+        assert!(taken[1].get_nav_stripped("error.message").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_node_send_should_stream_msgs_across_awaits() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]], "func": "
+                const sleep = (ms) => new Promise(resolve => setTimeout(resolve, ms));
+                for (let i = 1; i <= 3; i++) {
+                    node.send({payload: i});
+                    await sleep(20);
+                }
+                return {payload: 4};
+            "},
+            {"id": "2", "z": "100", "type": "test-once"},
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": 0}]])).unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(4, std::time::Duration::from_secs_f64(0.5), msgs_to_inject).await.unwrap();
+        let payloads: Vec<i64> = msgs.iter().map(|x| x["payload"].as_i64().unwrap()).collect();
+        assert_eq!(payloads, vec![1, 2, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn test_node_send_of_timers_should_not_be_streamed_by_another_invocation() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]], "func": "
+                const sleep = (ms) => new Promise(resolve => setTimeout(resolve, ms));
+                if (msg.payload === 'first') {
+                    setTimeout(() => node.send({payload: 'late'}), 30);
+                    return null;
+                }
+                node.send({payload: 'streamed'});
+                await sleep(100);
+                return {payload: 'returned'};
+            "},
+            {"id": "2", "z": "100", "type": "test-once"},
+        ]);
+        let msgs_to_inject =
+            Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": "first"}], ["1", {"payload": "second"}]]))
+                .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(3, std::time::Duration::from_secs_f64(0.5), msgs_to_inject).await.unwrap();
+        let payloads: Vec<&str> = msgs.iter().map(|x| x["payload"].as_str().unwrap()).collect();
+        assert_eq!(payloads, vec!["streamed", "late", "returned"]);
+    }
+
+    #[tokio::test]
+    async fn test_close_handlers_should_run_on_flow_stop() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]], "initialize": "
+                node.on('close', () => { throw new Error('failed'); });
+                node.on('close', async () => {
+                    await new Promise(resolve => setTimeout(resolve, 50));
+                    global.set('closedAsync', true);
+                });
+                node.on('close', (removed, done) => {
+                    setTimeout(() => { global.set('removed', removed); done(); }, 10);
+                });
+            ", "func": "return msg;"},
+            {"id": "2", "z": "100", "type": "test-once"},
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": 1}]])).unwrap();
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.5), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+
+        // The failed handler does not stop the rest
+        let global_context = engine.context();
+        assert_eq!(global_context.get_one(None, "closedAsync", &[]).await, Some(Variant::Bool(true)));
+        assert_eq!(global_context.get_one(None, "removed", &[]).await, Some(Variant::Bool(false)));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_node_context_should_persist_across_reload_flows() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]],
+                "func": "let n = (context.get('count') || 0) + 1;\ncontext.set('count', n);\nmsg.payload = n;\nreturn msg;"},
+            {"id": "2", "z": "100", "type": "test-once"},
+        ]);
+        let msgs_to_inject_json = json!([["1", {"payload": 0}]]);
+
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let engine = crate::runtime::engine::Engine::with_json(&registry, flows_json.clone(), None).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json.clone()).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0]["payload"], 1.into());
+
+        engine.reload_flows(flows_json).await.unwrap();
+
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0]["payload"], 2.into());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_context_should_keep_typed_values() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]], "func": "
+                flow.set('obj', {name: 'dev', tags: ['a', 'b'], nested: {ratio: 0.5, count: 2, on: true, none: null}});
+                const obj = flow.get('obj');
+                msg.isObject = typeof obj === 'object' && !Array.isArray(obj);
+                msg.types = [typeof obj.nested.ratio, typeof obj.nested.count, typeof obj.nested.on, typeof obj.tags];
+                msg.ratio = flow.get('obj.nested.ratio');
+                flow.set(['n', 'b'], [42, false]);
+                msg.many = flow.get(['n', 'b', 'missing']);
+                flow.set('n', undefined);
+                msg.removed = flow.get('n') === undefined;
+                msg.payload = obj;
+                return msg;
+            "},
+            {"id": "2", "z": "100", "type": "test-once"},
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {}]])).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+
+        let msg = &msgs[0];
+        let expected =
+            json!({"name": "dev", "tags": ["a", "b"], "nested": {"ratio": 0.5, "count": 2, "on": true, "none": null}});
+        assert_eq!(msg["payload"], Variant::deserialize(expected.clone()).unwrap());
+        assert_eq!(msg["isObject"], Variant::Bool(true));
+        assert_eq!(msg["types"], Variant::deserialize(json!(["number", "number", "boolean", "object"])).unwrap());
+        assert_eq!(msg["ratio"], Variant::from(0.5));
+        assert_eq!(msg["many"], Variant::deserialize(json!([42, false, null])).unwrap());
+        assert_eq!(msg["removed"], Variant::Bool(true));
+
+        // Stored as the object rather than its JSON string
+        let flow_context = engine.get_flow(&ElementId::with_u64(0x100)).unwrap().context();
+        assert_eq!(flow_context.get_one(None, "obj", &[]).await, Some(Variant::deserialize(expected).unwrap()));
+        assert_eq!(flow_context.get_one(None, "b", &[]).await, Some(Variant::Bool(false)));
+        assert_eq!(flow_context.get_one(None, "n", &[]).await, None);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_context_key_should_be_templated_from_msg() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]], "func": "
+                flow.set('device[msg.id].value', msg.payload);
+                msg.value = flow.get('device[msg.id].value');
+                msg.id = 'dev2';
+                flow.set(['device[msg.id]', 'last'], ['two', msg.id]);
+                msg.values = flow.get(['device[msg.id]', 'device.dev1.value']);
+                msg.missing = flow.get('device[msg.nope]') === undefined;
+                // The replaced `msg` is resolved against, with the store name and the callback forms
+                msg = RED.util.cloneMessage(msg);
+                msg.id = 'dev3';
+                flow.set('device[msg.id]', 'three', 'memory');
+                msg.stored = flow.get('device[msg.id]', 'memory');
+                msg.called = await new Promise((resolve) => flow.get('device[msg.id]', (err, v) => resolve(v)));
+                msg.isContext = Object.getPrototypeOf(flow) === Object.getPrototypeOf(__edgelinkFlowContext);
+                return msg;
+            "},
+            {"id": "2", "z": "100", "type": "test-once"},
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject =
+            Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"id": "dev1", "payload": 42}]])).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+
+        let msg = &msgs[0];
+        assert_eq!(msg["value"], Variant::from(42));
+        assert_eq!(msg["values"], Variant::deserialize(json!(["two", 42])).unwrap());
+        assert_eq!(msg["missing"], Variant::Bool(true));
+        assert_eq!(msg["stored"], Variant::from("three"));
+        assert_eq!(msg["called"], Variant::from("three"));
+        assert_eq!(msg["isContext"], Variant::Bool(true));
+
+        let flow_context = engine.get_flow(&ElementId::with_u64(0x100)).unwrap().context();
+        let expected = json!({"dev1": {"value": 42}, "dev2": "two", "dev3": "three"});
+        assert_eq!(flow_context.get_one(None, "device", &[]).await, Some(Variant::deserialize(expected).unwrap()));
+        assert_eq!(flow_context.get_one(None, "last", &[]).await, Some(Variant::from("dev2")));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_it_should_set_and_get_context_with_named_store() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]], "func": "
+                flow.set('x', msg.payload, 'file');
+                flow.set('y', 'default');
+                msg.fromFile = flow.get('x', 'file');
+                msg.fromDefault = flow.get('x');
+                msg.keys = flow.keys('file');
+                flow.set('#:(file)::z', 'prefixed');
+                msg.fromPrefix = flow.get('#:(file)::z');
+                try { flow.get('x', 'nope'); } catch (e) { msg.getError = e.message; }
+                try { flow.set('x', 1, 'nope'); } catch (e) { msg.setError = e.message; }
+                return msg;
+            "},
+            {"id": "2", "z": "100", "type": "test-once"},
+        ]);
+        let cfg = config::Config::builder()
+            .add_source(config::File::from_str(
+                r#"
+                [runtime.context]
+                default = "memory"
+
+                [runtime.context.stores]
+                memory = { provider = "memory" }
+                file = { provider = "memory" }
+                "#,
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap();
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let engine = crate::runtime::engine::Engine::with_json(&registry, flows_json, Some(&cfg)).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": "foo"}]])).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+
+        let msg = &msgs[0];
+        assert_eq!(msg["fromFile"], "foo".into());
+        assert!(msg.get("fromDefault").map_or(true, |x| x.is_null()));
+        assert_eq!(msg["keys"], Variant::Array(vec!["x".into()]));
+        assert_eq!(msg["fromPrefix"], "prefixed".into());
+        assert!(msg["getError"].as_str().unwrap().contains("nope"));
+        assert!(msg["setError"].as_str().unwrap().contains("nope"));
+
+        let flow_context = engine.get_flow(&ElementId::with_u64(0x100)).unwrap().context();
+        assert_eq!(flow_context.get_one(Some("file"), "x", &[]).await, Some("foo".into()));
+        assert_eq!(flow_context.get_one(None, "y", &[]).await, Some("default".into()));
+        assert_eq!(flow_context.get_one(None, "x", &[]).await, None);
+        assert_eq!(flow_context.get_one(Some("file"), "z", &[]).await, Some("prefixed".into()));
+    }
+
+    /// A memory store pretending to be a slow one like a file or database store.
+    struct SlowContextStore {
+        inner: Box<dyn crate::runtime::context::ContextStore>,
+    }
+
+    impl SlowContextStore {
+        const LATENCY: std::time::Duration = std::time::Duration::from_millis(50);
+
+        fn build(
+            name: String,
+            options: Option<&crate::runtime::context::ContextStoreOptions>,
+        ) -> crate::Result<Box<dyn crate::runtime::context::ContextStore>> {
+            let memory = inventory::iter::<crate::runtime::context::ProviderMetadata>
+                .into_iter()
+                .find(|x| x.type_ == "memory")
+                .unwrap();
+            Ok(Box::new(SlowContextStore { inner: (memory.factory)(name, options)? }))
+        }
+    }
+
+    inventory::submit! {
+        crate::runtime::context::ProviderMetadata { type_: "test-slow", factory: SlowContextStore::build }
+    }
+
+    #[async_trait::async_trait]
+    impl crate::runtime::context::ContextStore for SlowContextStore {
+        async fn name(&self) -> &str {
+            self.inner.name().await
+        }
+
+        async fn open(&self) -> crate::Result<()> {
+            self.inner.open().await
+        }
+
+        async fn close(&self) -> crate::Result<()> {
+            self.inner.close().await
+        }
+
+        async fn get_one(&self, scope: &str, path: &[PropexSegment]) -> crate::Result<Variant> {
+            tokio::time::sleep(Self::LATENCY).await;
+            self.inner.get_one(scope, path).await
+        }
+
+        async fn get_many(&self, scope: &str, keys: &[&str]) -> crate::Result<Vec<Variant>> {
+            tokio::time::sleep(Self::LATENCY).await;
+            self.inner.get_many(scope, keys).await
+        }
+
+        async fn get_keys(&self, scope: &str) -> crate::Result<Vec<String>> {
+            tokio::time::sleep(Self::LATENCY).await;
+            self.inner.get_keys(scope).await
+        }
+
+        async fn set_one(&self, scope: &str, path: &[PropexSegment], value: Variant) -> crate::Result<()> {
+            tokio::time::sleep(Self::LATENCY).await;
+            if matches!(path.first(), Some(PropexSegment::Property(x)) if x == "readonly") {
+                return Err(EdgelinkError::invalid_operation("The key 'readonly' is read-only").into());
+            }
+            self.inner.set_one(scope, path, value).await
+        }
+
+        async fn set_many(&self, scope: &str, pairs: Vec<(String, Variant)>) -> crate::Result<()> {
+            tokio::time::sleep(Self::LATENCY).await;
+            self.inner.set_many(scope, pairs).await
+        }
+
+        async fn remove_one(&self, scope: &str, path: &[PropexSegment]) -> crate::Result<Variant> {
+            tokio::time::sleep(Self::LATENCY).await;
+            self.inner.remove_one(scope, path).await
+        }
+
+        async fn delete(&self, scope: &str) -> crate::Result<()> {
+            self.inner.delete(scope).await
+        }
+
+        async fn clean(&self, active_nodes: &[ElementId]) -> crate::Result<()> {
+            self.inner.clean(active_nodes).await
+        }
+
+        fn is_async(&self) -> bool {
+            true
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_it_should_await_context_from_slow_store() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]], "func": "
+                let ticked = false;
+                setTimeout(() => { ticked = true; }, 0);
+                await flow.set('x', msg.payload, 'slow');
+                msg.value = await flow.get('x', 'slow');
+                msg.values = await flow.get(['x', 'missing'], 'slow');
+                msg.keys = await flow.keys('slow');
+                msg.ticked = ticked;
+                msg.pending = flow.get('x', 'slow') instanceof Promise;
+                msg.fromMemory = flow.get('x');
+                try { await flow.set('readonly', 1, 'slow'); } catch (e) { msg.setError = e.message; }
+                return msg;
+            "},
+            {"id": "2", "z": "100", "type": "test-once"},
+        ]);
+        let cfg = config::Config::builder()
+            .add_source(config::File::from_str(
+                r#"
+                [runtime.context]
+                default = "memory"
+
+                [runtime.context.stores]
+                memory = { provider = "memory" }
+                slow = { provider = "test-slow" }
+                "#,
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap();
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let engine = crate::runtime::engine::Engine::with_json(&registry, flows_json, Some(&cfg)).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": "foo"}]])).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(1.0), msgs_to_inject).await.unwrap();
+
+        let msg = &msgs[0];
+        assert_eq!(msg["value"], "foo".into());
+        assert_eq!(msg["values"].as_array().unwrap()[0], "foo".into());
+        assert!(msg["values"].as_array().unwrap()[1].is_null());
+        assert_eq!(msg["keys"], Variant::Array(vec!["x".into()]));
+        // The timer fired while the script was waiting for the store
+        assert_eq!(msg["ticked"], Variant::Bool(true));
+        assert_eq!(msg["pending"], Variant::Bool(true));
+        assert!(msg.get("fromMemory").map_or(true, |x| x.is_null()));
+        assert!(msg["setError"].as_str().unwrap().contains("read-only"));
+    }
+
+    #[tokio::test]
+    async fn test_it_should_await_context_from_localfs_store() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]], "func": "
+                msg.pending = global.get('x', 'file') instanceof Promise;
+                await global.set('x', msg.payload, 'file');
+                msg.value = await global.get('x', 'file');
+                msg.keys = await global.keys('file');
+                return msg;
+            "},
+            {"id": "2", "z": "100", "type": "test-once"},
+        ]);
+        let dir = tempfile::tempdir().unwrap();
+        let cfg = config::Config::builder()
+            .add_source(config::File::from_str(
+                &format!(
+                    "[runtime.context]\ndefault = \"memory\"\n\n[runtime.context.stores]\nmemory = {{ provider = \
+                     \"memory\" }}\nfile = {{ provider = \"localfs\", dir = {:?} }}\n",
+                    dir.path().to_str().unwrap()
+                ),
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap();
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let engine = crate::runtime::engine::Engine::with_json(&registry, flows_json, Some(&cfg)).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": "foo"}]])).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(1.0), msgs_to_inject).await.unwrap();
+
+        let msg = &msgs[0];
+        assert_eq!(msg["pending"], Variant::Bool(true));
+        assert_eq!(msg["value"], "foo".into());
+        assert_eq!(msg["keys"], Variant::Array(vec!["x".into()]));
+        // The value has been written to the scope file
+        let saved = std::fs::read_to_string(dir.path().join("global.json")).unwrap();
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&saved).unwrap(), json!({"x": "foo"}));
+    }
+
+    #[tokio::test]
+    async fn test_fatal_error_should_not_stop_unrelated_flow() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]],
+                "initialize": "throw new Error('boom');", "func": "return msg;"},
+            {"id": "2", "z": "100", "type": "test-once"},
+            {"id": "200", "type": "tab"},
+            {"id": "3", "type": "function", "z": "200", "wires": [["4"]], "func": "return msg;"},
+            {"id": "4", "z": "200", "type": "test-once"},
+        ]);
+        let cfg = config::Config::builder()
+            .add_source(config::File::from_str(
+                "[runtime.flow]\nnode_msg_queue_capacity = 16\non_fatal = \"flow\"\n",
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap();
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+
+        // The failing flow should be stopped
+        let engine = crate::runtime::engine::Engine::with_json(&registry, flows_json.clone(), Some(&cfg)).unwrap();
+        engine.start().await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        assert!(engine.get_flow(&ElementId::with_u64(0x100)).unwrap().is_stopped());
+        assert!(!engine.get_flow(&ElementId::with_u64(0x200)).unwrap().is_stopped());
+        engine.stop().await.unwrap();
+
+        // And the unrelated flow should keep working
+        let engine = crate::runtime::engine::Engine::with_json(&registry, flows_json, Some(&cfg)).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["3", {"payload": "foo"}]])).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.3), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0]["payload"], "foo".into());
+    }
+
+    #[tokio::test]
+    async fn test_sends_out_of_invocations_should_be_cancelled_by_the_node_stop() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]], "func": "return msg;"},
+            {"id": "2", "z": "100", "type": "test-once"},
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let node = engine.find_flow_node_by_id(&ElementId::with_u64(1)).unwrap();
+        engine.start().await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let stop_token = node.as_any().downcast_ref::<FunctionNode>().unwrap().stop_token.lock().unwrap().clone();
+        assert!(!stop_token.is_cancelled());
+
+        // The msgs sent by the timers are sent with the children of it
+        engine.stop().await.unwrap();
+        assert!(stop_token.is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn test_it_should_keep_integers_and_floats_through_function_node() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]],
+                "func": "msg.sum = msg.count + 1;\nmsg.half = msg.count / 4;\nreturn msg;"},
+            {"id": "2", "z": "100", "type": "test-once"},
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": 2.0, "count": 2}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+
+        assert_eq!(msgs.len(), 1);
+        let msg = &msgs[0];
+        assert!(msg["payload"].is_f64());
+        assert_eq!(msg["payload"].as_f64(), Some(2.0));
+        assert!(msg["count"].is_i64());
+        assert_eq!(msg["count"].as_i64(), Some(2));
+        assert!(msg["sum"].is_i64());
+        assert_eq!(msg["sum"].as_i64(), Some(3));
+        assert!(msg["half"].is_f64());
+        assert_eq!(msg["half"].as_f64(), Some(0.5));
+    }
+
+    #[tokio::test]
+    async fn test_nested_array_should_send_all_msgs_on_the_port_in_order() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"], ["2"]],
+                "func": "return [[{payload: 'a'}, null, {payload: 'b'}, msg], undefined];", "outputs": 2},
+            {"id": "2", "z": "100", "type": "test-once"},
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"_msgid": "0000000000000abc", "payload": "c"}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(3, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+
+        let payloads: Vec<&Variant> = msgs.iter().map(|x| &x["payload"]).collect();
+        assert_eq!(payloads, vec![&Variant::from("a"), &Variant::from("b"), &Variant::from("c")]);
+        assert!(msgs.iter().all(|x| x.id() == Some(ElementId::with_u64(0xabc))));
+    }
+
+    #[tokio::test]
+    async fn test_sequence_made_by_function_should_be_joined() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]], "func": "
+                try { RED.util.setParts({}, {id: 'x'}); } catch (e) { msg.partsError = e.message; }
+                const msgs = RED.util.makeSequence(msg, msg.payload);
+                const parts = RED.util.getParts(msgs[1]);
+                msg.checked = parts.index === 1 && parts.count === 3 && parts.type === 'object';
+                return [msgs.map(m => { m.checked = msg.checked; m.partsError = msg.partsError; return m; })];
+            "},
+            {"id": "2", "z": "100", "type": "join", "mode": "auto", "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject =
+            Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": {"a": 1, "b": [2], "c": "x"}}]])).unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.5), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0]["payload"], Variant::deserialize(json!({"a": 1, "b": [2], "c": "x"})).unwrap());
+        assert_eq!(msgs[0]["checked"], Variant::Bool(true));
+        assert!(msgs[0]["partsError"].as_str().unwrap().contains("msg.parts.index"));
+        assert!(!msgs[0].contains("parts"));
+    }
+
+    #[tokio::test]
+    async fn test_red_util_helpers_should_behave_like_node_red() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]], "func": "
+                const buf = new Uint8Array([104, 105]).buffer;
+                msg.strings = [buf, {a: 1}, 's', 0.5 * 2, true, null, undefined].map(x => RED.util.ensureString(x));
+                msg.buffers = ['hi', [1], 12, buf].map(x => RED.util.ensureBuffer(x));
+                msg.compared = [
+                    RED.util.compareObjects({a: [1, {b: 'x'}]}, {a: [1, {b: 'x'}]}),
+                    RED.util.compareObjects({a: 1}, {a: 1, b: 2}),
+                    RED.util.compareObjects([1, 2], [2, 1]),
+                    RED.util.compareObjects(0.5 * 2, 1),
+                    RED.util.compareObjects(1, '1'),
+                    RED.util.compareObjects(undefined, null),
+                    RED.util.compareObjects(buf, RED.util.ensureBuffer('hi')),
+                ];
+                const cloned = RED.util.cloneMessage(msg);
+                cloned.payload.nested.value = 2;
+                msg.clonedValue = cloned.payload.nested.value;
+                msg.clonedBuffer = RED.util.ensureString(RED.util.cloneMessage({b: buf}).b);
+                return msg;
+            "},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject =
+            Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": {"nested": {"value": 1}}}]])).unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.5), msgs_to_inject).await.unwrap();
+        let msg = &msgs[0];
+        assert_eq!(
+            msg["strings"],
+            Variant::deserialize(json!(["hi", "{\"a\":1}", "s", "1", "true", "null", "undefined"])).unwrap()
+        );
+        assert_eq!(
+            msg["buffers"],
+            Variant::Array(vec![
+                Variant::Bytes(b"hi".to_vec()),
+                Variant::Bytes(b"[1]".to_vec()),
+                Variant::Bytes(b"12".to_vec()),
+                Variant::Bytes(b"hi".to_vec()),
+            ])
+        );
+        assert_eq!(
+            msg["compared"],
+            Variant::deserialize(json!([true, false, false, true, false, false, true])).unwrap()
+        );
+        assert_eq!(msg.get_nav_stripped("payload.nested.value"), Some(&Variant::from(1)));
+        assert_eq!(msg["clonedValue"], Variant::from(2));
+        assert_eq!(msg["clonedBuffer"], Variant::from("hi"));
+    }
+
+    #[tokio::test]
+    async fn test_clone_message_should_keep_cyclic_and_shared_references() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]], "func": "
+                const shared = {x: 1};
+                msg.self = msg;
+                msg.list = [shared, shared];
+                const cloned = RED.util.cloneMessage(msg);
+                delete msg.self;
+                delete msg.list;
+                msg.payload = [
+                    cloned.self === cloned,
+                    cloned !== msg,
+                    cloned.list[0] === cloned.list[1],
+                    cloned.list[0] !== shared,
+                    cloned.payload,
+                ];
+                return msg;
+            "},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": "p"}]])).unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.5), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0]["payload"], Variant::deserialize(json!([true, true, true, true, "p"])).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_test_clock_should_fix_date_now_and_random() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["3"]],
+                "func": "msg.now = Date.now(); msg.random = [Math.random(), Math.random()]; return msg;"},
+            {"id": "2", "type": "function", "z": "100", "wires": [["3"]],
+                "func": "msg.now = Date.now(); msg.random = [Math.random(), Math.random()]; return msg;"},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject_json = json!([["1", {"payload": 1}], ["2", {"payload": 2}]]);
+
+        let cfg = config::Config::builder()
+            .add_source(config::File::from_str(
+                "[runtime.engine.test_clock]\nnow_ms = 1700000000000\nrandom_seed = 42\n",
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap();
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let engine = crate::runtime::engine::Engine::with_json(&registry, flows_json.clone(), Some(&cfg)).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json.clone()).unwrap();
+        let msgs =
+            engine.run_once_with_inject(2, std::time::Duration::from_secs_f64(0.5), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 2);
+        assert!(msgs.iter().all(|x| x["now"].as_f64() == Some(1700000000000.0)));
+        // Every function node starts the same sequence from the seed
+        assert_eq!(msgs[0]["random"], msgs[1]["random"]);
+        let randoms = msgs[0]["random"].as_array().unwrap();
+        assert_ne!(randoms[0], randoms[1]);
+        assert!(randoms.iter().all(|x| (0.0..1.0).contains(&x.as_f64().unwrap())));
+
+        // The real clock is used without the test clock
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(2, std::time::Duration::from_secs_f64(0.5), msgs_to_inject).await.unwrap();
+        let real_now = chrono::Utc::now().timestamp_millis() as f64;
+        assert!(msgs.iter().all(|x| (real_now - x["now"].as_f64().unwrap()).abs() < 60_000.0));
+    }
+
+    #[cfg(feature = "metrics")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_node_metric_should_be_exported() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]], "func": "
+                node.metric('temperature', msg.payload);
+                node.metric('count', (context.get('count') || 0) + 1);
+                context.set('count', 1);
+                return msg;
+            "},
+            {"id": "2", "z": "100", "type": "test-once"},
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"payload": 21.5}],
+            ["1", {"payload": 23}],
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        engine.run_once_with_inject(2, std::time::Duration::from_secs_f64(0.5), msgs_to_inject).await.unwrap();
+        let text = crate::runtime::metrics::render(&engine).await.unwrap();
+
+        // Only the last values are kept
+        let sample = |name: &str| {
+            text.lines()
+                .find(|x| x.starts_with("edgelink_node_custom_metric{") && x.contains(&format!("name=\"{}\"", name)))
+                .map(|x| x.to_string())
+                .unwrap()
+        };
+        let node1 = format!("node_id=\"{}\"", ElementId::with_u64(1));
+        assert!(sample("temperature").contains(&node1));
+        assert!(sample("temperature").ends_with(" 23"));
+        assert!(sample("count").ends_with(" 2"));
+    }
+
+    #[cfg(feature = "metrics")]
+    #[tokio::test]
+    async fn test_node_metrics_should_be_exported() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]], "func": "
+                node.metrics.counter('orders_total').inc();
+                node.metrics.counter('order_items_total').inc(msg.items);
+                node.metrics.histogram('order_value', [10, 100]).observe(msg.payload);
+                try {
+                    node.metrics.counter('orders_total').inc(-1);
+                } catch (e) {
+                    msg.error = e.name;
+                }
+                msg.count = node.metrics.counter('orders_total').value;
+                return msg;
+            "},
+            {"id": "2", "z": "100", "type": "test-once"},
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"payload": 5, "items": 2}],
+            ["1", {"payload": 50, "items": 3}],
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(2, std::time::Duration::from_secs_f64(0.5), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[1]["count"].as_f64(), Some(2.0));
+        assert_eq!(msgs[1]["error"], Variant::from("RangeError"));
+
+        let text = crate::runtime::metrics::render(&engine).await.unwrap();
+        let node1 = format!("node_id=\"{}\"", ElementId::with_u64(1));
+        let sample = |name: &str, label: &str| {
+            text.lines()
+                .find(|x| x.starts_with(&format!("{}{{", name)) && x.contains(&node1) && x.contains(label))
+                .and_then(|x| x.rsplit(' ').next())
+                .and_then(|x| x.parse::<f64>().ok())
+                .unwrap()
+        };
+        assert_eq!(sample("edgelink_user_orders_total", ""), 2.0);
+        assert_eq!(sample("edgelink_user_order_items_total", ""), 5.0);
+        assert_eq!(sample("edgelink_user_order_value_bucket", "le=\"10\""), 1.0);
+        assert_eq!(sample("edgelink_user_order_value_bucket", "le=\"100\""), 2.0);
+        assert_eq!(sample("edgelink_user_order_value_count", ""), 2.0);
+        assert_eq!(sample("edgelink_user_order_value_sum", ""), 55.0);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[tokio::test]
+    async fn test_node_metrics_should_be_limited() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "type": "function", "z": "100", "wires": [["2"]], "func": "
+                try {
+                    for (let i = 0; i < 100; i++) {
+                        node.metrics.counter('device_' + i).inc();
+                    }
+                } catch (e) {
+                    msg.error = e.message;
+                }
+                node.metrics.counter('device_0').inc();
+                return msg;
+            "},
+            {"id": "2", "z": "100", "type": "test-once"},
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": 1}]])).unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.5), msgs_to_inject).await.unwrap();
+        assert!(msgs[0]["error"].as_str().unwrap().contains("64 metrics at most"));
+
+        let text = crate::runtime::metrics::render(&engine).await.unwrap();
+        let created = text.lines().filter(|x| x.starts_with("edgelink_user_device_")).count();
+        assert_eq!(created, 64);
+        assert!(text.lines().any(|x| x.starts_with("edgelink_user_device_0{") && x.ends_with(" 2")));
+    }
 }
@@ -1,10 +1,11 @@
 use std::sync::{Arc, Weak};
 
-use rquickjs::{class::Trace, prelude::Opt, Ctx, FromJs, IntoJs, Value};
-use tokio_util::sync::CancellationToken;
+use rquickjs::{class::Trace, prelude::Opt, Ctx, Exception, FromJs, IntoJs, Value};
 
 use crate::runtime::js::util;
 
+#[cfg(feature = "metrics")]
+use super::metrics_class::MetricsClass;
 use super::*;
 
 #[derive(Clone, Trace)]
@@ -12,6 +13,10 @@ use super::*;
 pub(super) struct NodeClass {
     #[qjs(skip_trace)]
     node: Weak<FunctionNode>,
+
+    /// The invocation of the user function this object is passed to, `None` for the global `node`
+    #[qjs(skip_trace)]
+    invocation: Option<u64>,
 }
 
 #[allow(non_snake_case)]
@@ -21,7 +26,13 @@ impl NodeClass {
     // class. This attributes allows you to skip certain functions.
     #[qjs(skip)]
     pub fn new(node: &Arc<FunctionNode>) -> Self {
-        NodeClass { node: Arc::downgrade(node) }
+        NodeClass { node: Arc::downgrade(node), invocation: None }
+    }
+
+    /// Creates the `node` object bound to an invocation of the user function.
+    #[qjs(skip)]
+    pub fn with_invocation(node: &Arc<FunctionNode>, invocation: u64) -> Self {
+        NodeClass { node: Arc::downgrade(node), invocation: Some(invocation) }
     }
 
     #[qjs(get, rename = "id")]
@@ -53,6 +64,22 @@ impl NodeClass {
         // do nothing...
     }
 
+    /// Registers the handler of the event, only `close` is supported and the handlers run when the node stops.
+    #[qjs(rename = "on")]
+    fn on<'js>(&self, event: String, handler: rquickjs::Function<'js>, ctx: Ctx<'js>) -> rquickjs::Result<()> {
+        let node = self.node.upgrade().ok_or(rquickjs::Error::UnrelatedRuntime)?;
+        match event.as_str() {
+            "close" => {
+                let handlers: rquickjs::Array = ctx.globals().get(CLOSE_HANDLERS_GLOBAL)?;
+                handlers.set(handlers.len(), handler)
+            }
+            _ => {
+                log::warn!("[function:{}] Unsupported event of `node.on()`: '{}'", node.name(), event);
+                Ok(())
+            }
+        }
+    }
+
     #[qjs(rename = "send")]
     fn send<'js>(self, msgs: Value<'js>, cloning: Opt<bool>, ctx: Ctx<'js>) -> rquickjs::Result<()> {
         let cloning = cloning.unwrap_or(true);
@@ -64,13 +91,25 @@ impl NodeClass {
         Ok(())
     }
 
+    /// Emits a custom metric of the node, exported with the engine metrics and labelled by the node and the name.
+    ///
+    /// Throws if the node has emitted `MAX_CUSTOM_METRICS` different metrics already.
+    #[qjs(rename = "metric")]
+    fn metric<'js>(&self, name: String, value: f64, ctx: Ctx<'js>) -> rquickjs::Result<()> {
+        let node = self.node.upgrade().ok_or(rquickjs::Error::UnrelatedRuntime)?;
+        if name.is_empty() {
+            return Err(Exception::throw_type(&ctx, "The name of the metric must not be empty"));
+        }
+        node.get_node().metrics.set_custom(&name, value).map_err(|e| Exception::throw_message(&ctx, &e.to_string()))
+    }
+
     #[qjs(skip)]
     fn send_msgs_internal<'js>(&self, ctx: Ctx<'js>, msgs: rquickjs::Value<'js>, cloning: bool) -> crate::Result<()> {
-        let node = self.node.upgrade().clone().ok_or(rquickjs::Error::UnrelatedRuntime)? as Arc<dyn FlowNodeBehavior>;
+        let node = self.node.upgrade().clone().ok_or(rquickjs::Error::UnrelatedRuntime)?;
 
+        let mut msgs_to_send = SmallVec::new();
         match msgs.type_of() {
             rquickjs::Type::Array => {
-                let mut msgs_to_send = SmallVec::new();
                 let ports = msgs.as_array().expect("Must be an array");
                 // The first-level array is bound to a port.
                 let mut is_first = true;
@@ -102,35 +141,19 @@ impl NodeClass {
                         log::warn!("Unknown msg type: {}", port);
                     }
                 }
-
-                let cancel = CancellationToken::new();
-                let async_node = node.clone();
-                ctx.spawn(async move {
-                    match async_node.fan_out_many(msgs_to_send, cancel).await {
-                        Ok(_) => {}
-                        Err(err) => log::error!("Failed to send msg in function node: {}", err),
-                    }
-                });
             }
 
             rquickjs::Type::Object => {
                 let msg_to_send = MsgHandle::new(Msg::from_js(&ctx, msgs)?);
-                let envelope = Envelope { port: 0, msg: msg_to_send };
-                // FIXME
-                let cancel = CancellationToken::new();
-                let async_node = node.clone();
-                ctx.spawn(async move {
-                    match async_node.fan_out_one(envelope, cancel).await {
-                        Ok(_) => {}
-                        Err(err) => log::error!("Failed to send msg in function node: {}", err),
-                    }
-                });
+                msgs_to_send.push(Envelope { port: 0, msg: msg_to_send });
             }
 
             _ => {
                 return Err(EdgelinkError::InvalidOperation(format!("Unsupported: {:?}", msgs.type_of())).into());
             }
         }
+
+        node.send_from_js(&ctx, self.invocation, msgs_to_send);
         Ok(())
     }
 
@@ -191,6 +214,21 @@ impl NodeClass {
     }
 }
 
+impl NodeClass {
+    /// Creates the JS `node` object, with the counters and the histograms of the node as `node.metrics` if the
+    /// `metrics` feature is enabled, e.g. `node.metrics.counter("orders_total").inc()`.
+    pub fn into_js_node<'js>(self, ctx: &Ctx<'js>) -> rquickjs::Result<Value<'js>> {
+        #[cfg(feature = "metrics")]
+        let metrics = self.node.upgrade().map(|node| MetricsClass::new(&node));
+        let value = self.into_js(ctx)?;
+        #[cfg(feature = "metrics")]
+        if let (Some(obj), Some(metrics)) = (value.as_object(), metrics) {
+            obj.set("metrics", metrics)?;
+        }
+        Ok(value)
+    }
+}
+
 /*
 pub fn init(ctx: &Ctx<'_>, node: &Arc<FunctionNode>) -> crate::Result<()> {
     let globals = ctx.globals();
@@ -0,0 +1,506 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+/// The mean radius of the Earth in metres (IUGG)
+const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct LatLon {
+    lat: f64,
+    lon: f64,
+}
+
+impl LatLon {
+    fn new(lat: f64, lon: f64) -> Result<Self, String> {
+        if !lat.is_finite() || !(-90.0..=90.0).contains(&lat) {
+            return Err(format!("the latitude must be in [-90, 90], got {}", lat));
+        }
+        if !lon.is_finite() || !(-180.0..=180.0).contains(&lon) {
+            return Err(format!("the longitude must be in [-180, 180], got {}", lon));
+        }
+        Ok(LatLon { lat, lon })
+    }
+}
+
+/// The great-circle distance in metres by the haversine formula.
+fn haversine_distance(a: LatLon, b: LatLon) -> f64 {
+    let (lat1, lat2) = (a.lat.to_radians(), b.lat.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lon = (b.lon - a.lon).to_radians();
+    let h = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+    // Clamps the rounding errors of the nearly antipodal points
+    2.0 * EARTH_RADIUS_M * h.sqrt().min(1.0).asin()
+}
+
+/// The bounding box in the GeoJSON order `[west, south, east, north]`, it crosses the antimeridian if `west > east`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct BoundingBox {
+    south_west: LatLon,
+    north_east: LatLon,
+}
+
+impl BoundingBox {
+    fn contains(&self, p: LatLon) -> bool {
+        let (sw, ne) = (self.south_west, self.north_east);
+        let in_lat = p.lat >= sw.lat && p.lat <= ne.lat;
+        let in_lon =
+            if sw.lon <= ne.lon { p.lon >= sw.lon && p.lon <= ne.lon } else { p.lon >= sw.lon || p.lon <= ne.lon };
+        in_lat && in_lon
+    }
+}
+
+/// The polygons of the area, the first ring of a polygon is the exterior and the rest are the holes.
+#[derive(Debug, Clone, PartialEq)]
+struct Area {
+    polygons: Vec<Vec<Vec<LatLon>>>,
+}
+
+impl Area {
+    /// The planar ray casting in the longitude/latitude space, like most of the GeoJSON tools do.
+    fn contains(&self, p: LatLon) -> bool {
+        self.polygons.iter().any(|rings| match rings.split_first() {
+            Some((exterior, holes)) => ring_contains(exterior, p) && !holes.iter().any(|x| ring_contains(x, p)),
+            None => false,
+        })
+    }
+}
+
+fn ring_contains(ring: &[LatLon], p: LatLon) -> bool {
+    let mut inside = false;
+    let mut j = ring.len().wrapping_sub(1);
+    for i in 0..ring.len() {
+        let (a, b) = (ring[i], ring[j]);
+        if (a.lat > p.lat) != (b.lat > p.lat) && p.lon < (b.lon - a.lon) * (p.lat - a.lat) / (b.lat - a.lat) + a.lon {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+fn to_coordinate(value: &Variant, what: &str) -> Result<f64, String> {
+    match value {
+        Variant::Number(n) => n.as_f64().ok_or_else(|| format!("the {} is not a float: {}", what, n)),
+        Variant::String(s) => s.trim().parse::<f64>().map_err(|_| format!("the {} is not a number: '{}'", what, s)),
+        other => Err(format!("the {} is not a number: {:?}", what, other)),
+    }
+}
+
+/// Unwraps the geometry of the GeoJSON `Feature`, other values are returned as is.
+fn unwrap_feature(value: &Variant) -> Result<&Variant, String> {
+    match value.as_object() {
+        Some(obj) if obj.get("type").and_then(|x| x.as_str()) == Some("Feature") => {
+            obj.get("geometry").ok_or_else(|| "the GeoJSON feature has no geometry".to_string())
+        }
+        _ => Ok(value),
+    }
+}
+
+/// The coordinates of the GeoJSON geometry if its type is the expected one.
+fn geometry_coordinates<'a>(value: &'a Variant, geometry_type: &str) -> Option<&'a Variant> {
+    let obj = value.as_object()?;
+    if obj.get("type").and_then(|x| x.as_str()) == Some(geometry_type) {
+        obj.get("coordinates")
+    } else {
+        None
+    }
+}
+
+/// Parses the point from `{lat, lon}` (or `lng`, `latitude`, `longitude`), the GeoJSON position `[lon, lat]`, or the
+/// GeoJSON `Point` geometry or feature.
+fn parse_point(value: &Variant) -> Result<LatLon, String> {
+    let value = unwrap_feature(value)?;
+    if let Some(coords) = geometry_coordinates(value, "Point") {
+        return parse_point(coords);
+    }
+    match value {
+        Variant::Object(obj) => {
+            let lat = ["lat", "latitude"].iter().find_map(|k| obj.get(*k)).ok_or("no `lat` or `latitude`")?;
+            let lon = ["lon", "lng", "longitude"].iter().find_map(|k| obj.get(*k)).ok_or("no `lon` or `lng`")?;
+            LatLon::new(to_coordinate(lat, "latitude")?, to_coordinate(lon, "longitude")?)
+        }
+        Variant::Array(items) if items.len() == 2 || items.len() == 3 => {
+            // The altitude is ignored
+            LatLon::new(to_coordinate(&items[1], "latitude")?, to_coordinate(&items[0], "longitude")?)
+        }
+        Variant::Array(items) => Err(format!("a position must be `[lon, lat]`, got {} items", items.len())),
+        other => Err(format!("not a point: {:?}", other)),
+    }
+}
+
+/// Parses `[west, south, east, north]` or the `{sw, ne}` corners.
+fn parse_bbox(value: &Variant) -> Result<BoundingBox, String> {
+    let bbox = match value {
+        Variant::Array(items) if items.len() == 4 => BoundingBox {
+            south_west: LatLon::new(to_coordinate(&items[1], "south")?, to_coordinate(&items[0], "west")?)?,
+            north_east: LatLon::new(to_coordinate(&items[3], "north")?, to_coordinate(&items[2], "east")?)?,
+        },
+        Variant::Object(obj) => {
+            let sw = obj.get("sw").ok_or("no `sw` corner in the bounding box")?;
+            let ne = obj.get("ne").ok_or("no `ne` corner in the bounding box")?;
+            BoundingBox { south_west: parse_point(sw)?, north_east: parse_point(ne)? }
+        }
+        _ => return Err("a bounding box must be `[west, south, east, north]` or `{sw, ne}`".to_string()),
+    };
+    if bbox.south_west.lat > bbox.north_east.lat {
+        return Err(format!("the south {} is above the north {}", bbox.south_west.lat, bbox.north_east.lat));
+    }
+    Ok(bbox)
+}
+
+fn parse_ring(value: &Variant) -> Result<Vec<LatLon>, String> {
+    let points = value.as_array().ok_or("a ring must be an array of points")?;
+    let mut ring = points.iter().map(parse_point).collect::<Result<Vec<_>, _>>()?;
+    // The closing point of GeoJSON is optional here
+    if ring.len() > 1 && ring.first() == ring.last() {
+        ring.pop();
+    }
+    if ring.len() < 3 {
+        return Err(format!("a ring must have at least 3 points, got {}", ring.len()));
+    }
+    Ok(ring)
+}
+
+fn parse_rings(value: &Variant) -> Result<Vec<Vec<LatLon>>, String> {
+    let rings = value.as_array().ok_or("a polygon must be an array of rings")?;
+    if rings.is_empty() {
+        return Err("the polygon has no rings".to_string());
+    }
+    rings.iter().map(parse_ring).collect()
+}
+
+/// Parses the GeoJSON `Polygon` or `MultiPolygon` geometry or feature, the coordinates of a GeoJSON polygon, or a
+/// single ring of points.
+fn parse_area(value: &Variant) -> Result<Area, String> {
+    let value = unwrap_feature(value)?;
+    if let Some(coords) = geometry_coordinates(value, "Polygon") {
+        return Ok(Area { polygons: vec![parse_rings(coords)?] });
+    }
+    if let Some(coords) = geometry_coordinates(value, "MultiPolygon") {
+        let polygons = coords.as_array().ok_or("the coordinates of a multi-polygon must be an array")?;
+        return Ok(Area { polygons: polygons.iter().map(parse_rings).collect::<Result<_, _>>()? });
+    }
+    // The coordinates of a polygon are nested one level deeper than a ring
+    let is_rings = value.as_array().and_then(|x| x.first()).and_then(|x| x.as_array()).and_then(|x| x.first());
+    if is_rings.is_some_and(|x| x.as_array().is_some() || x.as_object().is_some()) {
+        Ok(Area { polygons: vec![parse_rings(value)?] })
+    } else {
+        Ok(Area { polygons: vec![vec![parse_ring(value)?]] })
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum GeoAction {
+    /// The distance from the point to the target point
+    #[default]
+    #[serde(rename = "distance")]
+    Distance,
+
+    /// Whether the point is in the target bounding box
+    #[serde(rename = "bbox")]
+    BoundingBox,
+
+    /// Whether the point is in the target polygon
+    #[serde(rename = "polygon")]
+    Polygon,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum TargetType {
+    /// The target is the property of the message
+    #[serde(rename = "msg")]
+    Msg,
+
+    /// The target is fixed in the config
+    #[default]
+    #[serde(rename = "json")]
+    Json,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum DistanceUnit {
+    #[default]
+    #[serde(rename = "m")]
+    Metre,
+
+    #[serde(rename = "km")]
+    Kilometre,
+
+    #[serde(rename = "mi")]
+    Mile,
+
+    #[serde(rename = "nmi")]
+    NauticalMile,
+}
+
+impl DistanceUnit {
+    fn convert_metres(self, metres: f64) -> f64 {
+        match self {
+            DistanceUnit::Metre => metres,
+            DistanceUnit::Kilometre => metres / 1000.0,
+            DistanceUnit::Mile => metres / 1609.344,
+            DistanceUnit::NauticalMile => metres / 1852.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct GeoNodeConfig {
+    #[serde(default)]
+    action: GeoAction,
+
+    /// The point to compute
+    #[serde(default = "property_default")]
+    property: String,
+
+    /// The target point, bounding box or polygon, either fixed or read from the message
+    #[serde(default)]
+    target: serde_json::Value,
+
+    #[serde(default, rename = "targetType")]
+    target_type: TargetType,
+
+    /// The property to set the result, the distance as a number or `true`/`false` for the containment
+    #[serde(default = "property_default")]
+    output: String,
+
+    #[serde(default)]
+    unit: DistanceUnit,
+}
+
+fn property_default() -> String {
+    "payload".to_string()
+}
+
+#[derive(Debug, Clone)]
+enum Target {
+    Point(LatLon),
+    BoundingBox(BoundingBox),
+    Area(Area),
+}
+
+fn parse_target(action: GeoAction, value: &Variant) -> Result<Target, String> {
+    match action {
+        GeoAction::Distance => parse_point(value).map(Target::Point),
+        GeoAction::BoundingBox => parse_bbox(value).map(Target::BoundingBox),
+        GeoAction::Polygon => parse_area(value).map(Target::Area),
+    }
+}
+
+/// Computes the distances between the coordinates, and checks whether they are in the bounding boxes or polygons.
+///
+/// The coordinates are in WGS 84 degrees, the GeoJSON positions are in the `[lon, lat]` order.
+#[derive(Debug)]
+#[flow_node("geo")]
+struct GeoNode {
+    base: FlowNode,
+    config: GeoNodeConfig,
+
+    /// The parsed target if it's fixed
+    fixed_target: Option<Target>,
+}
+
+impl GeoNode {
+    fn build(
+        _flow: &Flow,
+        base_node: FlowNode,
+        config: &RedFlowNodeConfig,
+    ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let geo_config = GeoNodeConfig::deserialize(&config.rest)?;
+        let fixed_target = match geo_config.target_type {
+            TargetType::Msg if geo_config.target.as_str().is_some_and(|x| !x.trim().is_empty()) => None,
+            TargetType::Msg => {
+                return Err(EdgelinkError::BadFlowsJson("The target property of the geo node must be set".into()).into())
+            }
+            TargetType::Json => {
+                // The typed input of Node-RED stores the JSON as a string
+                let target = match &geo_config.target {
+                    serde_json::Value::String(s) => serde_json::from_str(s).map_err(|e| {
+                        EdgelinkError::BadFlowsJson(format!("The target of the geo node is not a JSON: {}", e))
+                    })?,
+                    other => other.clone(),
+                };
+                let target = parse_target(geo_config.action, &Variant::from(target)).map_err(|e| {
+                    EdgelinkError::BadFlowsJson(format!(
+                        "Bad target of the geo node for '{:?}': {}",
+                        geo_config.action, e
+                    ))
+                })?;
+                Some(target)
+            }
+        };
+        let node = GeoNode { base: base_node, config: geo_config, fixed_target };
+        Ok(Box::new(node))
+    }
+
+    fn do_geo(&self, msg: &mut Msg) -> crate::Result<()> {
+        let property = &self.config.property;
+        let point = msg
+            .get_nav_stripped(property)
+            .ok_or_else(|| EdgelinkError::InvalidOperation(format!("Cannot find the property 'msg.{}'", property)))
+            .and_then(|x| {
+                parse_point(x).map_err(|e| {
+                    EdgelinkError::InvalidOperation(format!("Bad coordinates in 'msg.{}': {}", property, e))
+                })
+            })?;
+
+        let msg_target;
+        let target = match &self.fixed_target {
+            Some(target) => target,
+            None => {
+                let path = self.config.target.as_str().unwrap_or_default();
+                let value = msg.get_nav_stripped(path).ok_or_else(|| {
+                    EdgelinkError::InvalidOperation(format!("Cannot find the target property 'msg.{}'", path))
+                })?;
+                msg_target = parse_target(self.config.action, value)
+                    .map_err(|e| EdgelinkError::InvalidOperation(format!("Bad target in 'msg.{}': {}", path, e)))?;
+                &msg_target
+            }
+        };
+
+        let result = match target {
+            Target::Point(to) => Variant::from(self.config.unit.convert_metres(haversine_distance(point, *to))),
+            Target::BoundingBox(bbox) => Variant::Bool(bbox.contains(point)),
+            Target::Area(area) => Variant::Bool(area.contains(point)),
+        };
+        msg.set_nav_stripped(&self.config.output, result, true)
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for GeoNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow_concurrent(&self, cancel.child_token(), |node, msg| async move {
+                {
+                    let mut msg_guard = msg.write().await;
+                    node.do_geo(&mut msg_guard)?;
+                }
+                node.fan_out_one(Envelope { port: 0, msg }, cancel.child_token()).await?;
+                Ok(())
+            })
+            .await;
+        }
+        wait_uows_completed(self.as_ref()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn point(lat: f64, lon: f64) -> LatLon {
+        LatLon::new(lat, lon).unwrap()
+    }
+
+    #[test]
+    fn test_haversine_distance() {
+        // A degree of the meridian
+        assert!((haversine_distance(point(0.0, 0.0), point(1.0, 0.0)) - 111_195.080).abs() < 1e-3);
+        // Half of the great circle
+        let half = std::f64::consts::PI * EARTH_RADIUS_M;
+        assert!((haversine_distance(point(0.0, 0.0), point(0.0, 180.0)) - half).abs() < 1e-6);
+        assert!((haversine_distance(point(90.0, 0.0), point(-90.0, 0.0)) - half).abs() < 1e-6);
+        // London to Paris
+        let d = haversine_distance(point(51.5074, -0.1278), point(48.8566, 2.3522));
+        assert!((d - 343_556.535).abs() < 1e-3, "{}", d);
+        assert_eq!(haversine_distance(point(10.0, 20.0), point(10.0, 20.0)), 0.0);
+        // It's symmetric and across the antimeridian
+        let d = haversine_distance(point(0.0, 179.5), point(0.0, -179.5));
+        assert!((d - 111_195.080).abs() < 1e-3, "{}", d);
+    }
+
+    #[test]
+    fn test_point_in_polygon_with_hole() {
+        let area = parse_area(&Variant::from(json!({
+            "type": "Feature",
+            "geometry": {
+                "type": "Polygon",
+                "coordinates": [
+                    [[0, 0], [10, 0], [10, 10], [0, 10], [0, 0]],
+                    [[4, 4], [6, 4], [6, 6], [4, 6], [4, 4]]
+                ]
+            }
+        })))
+        .unwrap();
+        assert!(area.contains(point(2.0, 2.0)));
+        assert!(area.contains(point(9.9, 0.1)));
+        assert!(!area.contains(point(5.0, 5.0)));
+        assert!(!area.contains(point(11.0, 5.0)));
+        assert!(!area.contains(point(-0.1, 5.0)));
+
+        // A concave ring of `{lat, lon}` without the closing point
+        let area = parse_area(&Variant::from(json!([
+            {"lat": 0, "lon": 0}, {"lat": 0, "lon": 10}, {"lat": 10, "lon": 10},
+            {"lat": 5, "lon": 5}, {"lat": 10, "lon": 0}
+        ])))
+        .unwrap();
+        assert!(area.contains(point(2.0, 5.0)));
+        assert!(!area.contains(point(8.0, 5.0)));
+    }
+
+    #[test]
+    fn test_malformed_coordinates_should_be_rejected() {
+        assert!(parse_point(&Variant::from(json!({"lat": 91, "lon": 0}))).unwrap_err().contains("latitude"));
+        assert!(parse_point(&Variant::from(json!({"lat": 0, "lon": -181}))).unwrap_err().contains("longitude"));
+        assert!(parse_point(&Variant::from(json!({"lat": "north", "lon": 0}))).unwrap_err().contains("'north'"));
+        assert!(parse_point(&Variant::from(json!({"lat": 1}))).unwrap_err().contains("`lon`"));
+        assert!(parse_point(&Variant::from(json!([1]))).unwrap_err().contains("[lon, lat]"));
+        assert!(parse_bbox(&Variant::from(json!([0, 10, 10, 0]))).is_err());
+        assert!(parse_area(&Variant::from(json!([[0, 0], [1, 1]]))).unwrap_err().contains("at least 3 points"));
+        assert_eq!(parse_point(&Variant::from(json!({"latitude": "1.5", "lng": 2}))).unwrap(), point(1.5, 2.0));
+        assert_eq!(parse_point(&Variant::from(json!([2, 1.5, 100]))).unwrap(), point(1.5, 2.0));
+    }
+
+    #[tokio::test]
+    async fn test_it_should_compute_distance_and_containment() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "geo", "action": "distance",
+                "target": "{\"lat\": 48.8566, \"lon\": 2.3522}", "unit": "km", "output": "distance", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "geo", "action": "bbox", "target": "bounds", "targetType": "msg",
+                "output": "inBounds", "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"},
+            {"id": "4", "z": "100", "type": "catch", "wires": [["3"]]}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"payload": {"lat": 51.5074, "lon": -0.1278}, "bounds": [-10, 40, 5, 60]}],
+            ["1", {"payload": {"type": "Point", "coordinates": [2.3522, 48.8566]}, "bounds": [170, -10, -170, 10]}],
+            ["1", {"payload": {"lat": 100, "lon": 0}}],
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(3, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        // The caught error may overtake the others
+        let (errors, msgs): (Vec<_>, Vec<_>) = msgs.into_iter().partition(|x| x.contains("error"));
+        assert!((msgs[0]["distance"].as_f64().unwrap() - 343.557).abs() < 1e-3);
+        assert_eq!(msgs[0]["inBounds"], Variant::Bool(true));
+        assert_eq!(msgs[1]["distance"], Variant::from(0.0));
+        assert_eq!(msgs[1]["inBounds"], Variant::Bool(false));
+        let error_message = errors[0].get_nav_stripped("error.message").unwrap().as_str().unwrap();
+        assert!(error_message.contains("Bad coordinates in 'msg.payload'"), "{}", error_message);
+    }
+
+    #[test]
+    fn test_bad_fixed_target_should_fail_to_build() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "geo", "action": "polygon", "target": "[[0, 0], [1, 1]]", "wires": []}
+        ]);
+        assert!(crate::runtime::engine::build_test_engine(flows_json).is_err());
+    }
+}
@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde::{Deserialize, Deserializer};
+use tokio::sync::Mutex;
+
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+const NO_TOPIC: &str = "_no_topic";
+
+#[derive(Debug, Clone, Deserialize)]
+struct HistogramNodeConfig {
+    /// The message property of the value to count
+    #[serde(default = "property_default")]
+    property: String,
+
+    /// The ascending bucket boundaries, `[0, 10]` makes the buckets `< 0`, `[0, 10)` and `>= 10`
+    #[serde(deserialize_with = "deser_bounds")]
+    bounds: Vec<f64>,
+
+    #[serde(rename = "septopics", default = "septopics_default")]
+    sep_topics: bool,
+
+    #[serde(rename = "topi", default = "topic_default")]
+    topic: String,
+}
+
+fn property_default() -> String {
+    "payload".to_string()
+}
+
+fn septopics_default() -> bool {
+    true
+}
+
+fn topic_default() -> String {
+    "topic".to_string()
+}
+
+/// Accepts an array of numbers or a comma-separated string like `"0, 10, 20"`.
+fn deser_bounds<'de, D>(deserializer: D) -> Result<Vec<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value: serde_json::Value = Deserialize::deserialize(deserializer)?;
+    let parse_item = |item: &serde_json::Value| match item {
+        serde_json::Value::Number(num) => num.as_f64(),
+        serde_json::Value::String(s) => s.trim().parse::<f64>().ok(),
+        _ => None,
+    };
+    let bounds = match &value {
+        serde_json::Value::Array(items) => items.iter().map(parse_item).collect::<Option<Vec<f64>>>(),
+        serde_json::Value::String(s) => s.split(',').map(|x| x.trim().parse::<f64>().ok()).collect(),
+        _ => None,
+    };
+    bounds.ok_or_else(|| serde::de::Error::custom(format!("Invalid histogram bounds: {}", value)))
+}
+
+#[derive(Debug)]
+#[flow_node("histogram")]
+struct HistogramNode {
+    base: FlowNode,
+    config: HistogramNodeConfig,
+    counts: Mutex<HashMap<String, Vec<u64>>>,
+}
+
+impl HistogramNode {
+    fn build(
+        _flow: &Flow,
+        base_node: FlowNode,
+        config: &RedFlowNodeConfig,
+    ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let histogram_config = HistogramNodeConfig::deserialize(&config.rest)?;
+        let bounds = &histogram_config.bounds;
+        if bounds.is_empty() || bounds.iter().any(|x| !x.is_finite()) || bounds.windows(2).any(|x| x[0] >= x[1]) {
+            return Err(EdgelinkError::BadFlowsJson(format!(
+                "The bounds of the histogram node must be finite and strictly ascending, got: {:?}",
+                bounds
+            ))
+            .into());
+        }
+        let node = HistogramNode { base: base_node, config: histogram_config, counts: Mutex::new(HashMap::new()) };
+        Ok(Box::new(node))
+    }
+
+    /// The index of the bucket, the first and the last buckets are open-ended.
+    fn bucket_index(&self, value: f64) -> usize {
+        self.config.bounds.partition_point(|x| *x <= value)
+    }
+
+    /// Counts the value into the histogram of its topic and replaces the payload with the histogram, returns `false`
+    /// if there is nothing to send, i.e. a `msg.reset` without the value.
+    fn do_count(&self, msg: &mut Msg, counts: &mut HashMap<String, Vec<u64>>) -> crate::Result<bool> {
+        let topic = match (self.config.sep_topics, msg.get_nav_stripped(&self.config.topic)) {
+            (true, Some(Variant::String(topic))) if !topic.is_empty() => topic.clone(),
+            _ => NO_TOPIC.to_string(),
+        };
+
+        if msg.contains("reset") {
+            if topic != NO_TOPIC {
+                counts.remove(&topic);
+            } else {
+                counts.clear();
+            }
+        }
+
+        let value = match msg.get_nav_stripped(&self.config.property) {
+            Some(Variant::Number(num)) => num.as_f64().unwrap_or(f64::NAN),
+            Some(Variant::String(s)) => s.trim().parse::<f64>().unwrap_or(f64::NAN),
+            Some(_) => f64::NAN,
+            None if msg.contains("reset") => return Ok(false),
+            None => {
+                return Err(EdgelinkError::InvalidOperation(format!(
+                    "Cannot find the property 'msg.{}'",
+                    self.config.property
+                ))
+                .into())
+            }
+        };
+        if value.is_nan() {
+            return Err(EdgelinkError::InvalidOperation(format!(
+                "The property 'msg.{}' is not a number",
+                self.config.property
+            ))
+            .into());
+        }
+
+        let topic_counts = counts.entry(topic).or_insert_with(|| vec![0; self.config.bounds.len() + 1]);
+        topic_counts[self.bucket_index(value)] += 1;
+        msg.set("payload".to_string(), self.make_histogram(topic_counts));
+        Ok(true)
+    }
+
+    /// Makes the histogram like `{"buckets": [{"min": null, "max": 0, "count": 1}, ...], "count": 1}`, the `min` of
+    /// the first bucket and the `max` of the last bucket are `null`.
+    fn make_histogram(&self, counts: &[u64]) -> Variant {
+        let bounds = &self.config.bounds;
+        let bound_or_null =
+            |i: Option<usize>| i.and_then(|i| bounds.get(i)).map_or(Variant::Null, |x| Variant::from(*x));
+        let buckets = counts
+            .iter()
+            .enumerate()
+            .map(|(i, count)| {
+                Variant::Object(VariantObjectMap::from([
+                    ("min".to_string(), bound_or_null(i.checked_sub(1))),
+                    ("max".to_string(), bound_or_null(Some(i))),
+                    ("count".to_string(), Variant::from(*count)),
+                ]))
+            })
+            .collect();
+        Variant::Object(VariantObjectMap::from([
+            ("buckets".to_string(), Variant::Array(buckets)),
+            ("count".to_string(), Variant::from(counts.iter().sum::<u64>())),
+        ]))
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for HistogramNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow(self.as_ref(), cancel.child_token(), |node, msg| async move {
+                let can_send = {
+                    let mut msg_guard = msg.write().await;
+                    let mut counts_guard = node.counts.lock().await;
+                    node.do_count(&mut msg_guard, &mut counts_guard)?
+                };
+                if can_send {
+                    node.fan_out_one(Envelope { port: 0, msg }, cancel.child_token()).await?;
+                }
+                Ok(())
+            })
+            .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+    use std::time::Duration;
+
+    fn bucket_counts(msg: &Msg) -> Vec<u64> {
+        let buckets = msg.get_nav_stripped("payload.buckets").and_then(|x| x.as_array()).unwrap();
+        buckets.iter().map(|x| x.get_nav("count", &[]).and_then(|x| x.as_u64()).unwrap()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_it_should_count_values_into_open_ended_buckets() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "histogram", "bounds": "0, 10, 20", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let values = json!([-5, 0, 3, 9.99, 10, 15, 25, "100"]);
+        let msgs_to_inject: Vec<(ElementId, Msg)> = values
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| (ElementId::with_u64(1), Msg::deserialize(json!({"payload": x})).unwrap()))
+            .collect();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(8, Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        assert_eq!(bucket_counts(&msgs[0]), vec![1, 0, 0, 0]);
+        assert_eq!(bucket_counts(&msgs[7]), vec![1, 3, 2, 2]);
+        assert_eq!(msgs[7].get_nav_stripped("payload.count"), Some(&Variant::from(8)));
+        let expected_buckets = json!([
+            {"min": null, "max": 0.0, "count": 1},
+            {"min": 0.0, "max": 10.0, "count": 3},
+            {"min": 10.0, "max": 20.0, "count": 2},
+            {"min": 20.0, "max": null, "count": 2}
+        ]);
+        assert_eq!(msgs[7].get_nav_stripped("payload.buckets"), Some(&Variant::deserialize(expected_buckets).unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_it_should_count_and_reset_per_topic() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "histogram", "bounds": [50], "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"topic": "a", "payload": 10}],
+            ["1", {"topic": "b", "payload": 60}],
+            ["1", {"topic": "a", "payload": 70}],
+            ["1", {"topic": "a", "reset": true}],
+            ["1", {"topic": "b", "payload": 1}],
+            ["1", {"topic": "a", "payload": 80, "reset": true}],
+            ["1", {"topic": "a", "payload": 2}],
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(6, Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        let results: Vec<(Variant, Vec<u64>)> = msgs.iter().map(|x| (x["topic"].clone(), bucket_counts(x))).collect();
+        let expected: Vec<(Variant, Vec<u64>)> = vec![
+            ("a".into(), vec![1, 0]),
+            ("b".into(), vec![0, 1]),
+            ("a".into(), vec![1, 1]),
+            // The reset without a value sends nothing
+            ("b".into(), vec![1, 1]),
+            ("a".into(), vec![0, 1]),
+            ("a".into(), vec![1, 1]),
+        ];
+        assert_eq!(results, expected);
+    }
+
+    #[test]
+    fn test_bad_bounds_should_fail_to_build() {
+        for bounds in [json!([]), json!([10, 5]), json!("1, x")] {
+            let flows_json = json!([
+                {"id": "100", "type": "tab"},
+                {"id": "1", "z": "100", "type": "histogram", "bounds": bounds, "wires": []}
+            ]);
+            assert!(crate::runtime::engine::build_test_engine(flows_json).is_err());
+        }
+    }
+}
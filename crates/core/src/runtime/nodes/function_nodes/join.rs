@@ -0,0 +1,806 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use serde::Deserialize;
+use tokio::time::Instant;
+
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum JoinMode {
+    /// Reassemble the sequence by `msg.parts`
+    #[default]
+    #[serde(rename = "auto")]
+    Auto,
+
+    /// Join the messages by the `build` type, until the `count` of messages or a `msg.complete` arrives
+    #[serde(rename = "custom")]
+    Custom,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum JoinBuild {
+    #[serde(rename = "string")]
+    String,
+
+    #[serde(rename = "buffer")]
+    Buffer,
+
+    #[default]
+    #[serde(rename = "array")]
+    Array,
+
+    #[serde(rename = "object")]
+    Object,
+
+    #[serde(rename = "merged")]
+    Merged,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum JoinerType {
+    #[default]
+    #[serde(rename = "str")]
+    Str,
+
+    /// A JSON array of bytes, like `[13, 10]`
+    #[serde(rename = "bin")]
+    Bin,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct JoinNodeConfig {
+    #[serde(default)]
+    mode: JoinMode,
+
+    #[serde(default)]
+    build: JoinBuild,
+
+    #[serde(default = "join_property_default")]
+    property: String,
+
+    #[serde(default = "join_key_default")]
+    key: String,
+
+    /// The delimiter of the `string` and `buffer` builds in the custom mode
+    #[serde(default = "joiner_default")]
+    joiner: String,
+
+    #[serde(rename = "joinerType", default)]
+    joiner_type: JoinerType,
+
+    /// Keeps the joined messages after sending in the custom mode, every subsequent message sends them again
+    #[serde(default, deserialize_with = "json::deser::deser_bool_or_str")]
+    accumulate: bool,
+
+    #[serde(default, deserialize_with = "json::deser::str_to_option_usize")]
+    count: Option<usize>,
+
+    /// The timeout in seconds
+    #[serde(default, deserialize_with = "json::deser::str_to_option_f64")]
+    timeout: Option<f64>,
+}
+
+fn join_property_default() -> String {
+    "payload".to_string()
+}
+
+fn join_key_default() -> String {
+    "topic".to_string()
+}
+
+fn joiner_default() -> String {
+    "\\n".to_string()
+}
+
+/// The message resolved by the mode of the join node.
+#[derive(Debug)]
+struct JoinInput {
+    group_id: ElementId,
+    build: JoinBuild,
+    /// The `msg.parts` in the auto mode, the value is appended to the group if `None`
+    parts: Option<MsgParts>,
+    key: Option<String>,
+    /// `None` if the message has no value to join, i.e. it only completes the group
+    value: Option<Variant>,
+    expected: Option<usize>,
+    joiner: Option<Variant>,
+    flatten: bool,
+}
+
+#[derive(Debug)]
+struct PartialJoin {
+    serial: u64,
+    build: JoinBuild,
+    /// The `msg.parts.id` of the sequence in the auto mode, its values are kept by the sequence tracker until joined
+    sequence_id: Option<String>,
+    /// The received values and their keys in the order
+    items: Vec<(Option<String>, Variant)>,
+    expected: Option<usize>,
+    /// The delimiter of a string or buffer, either a string or bytes
+    joiner: Option<Variant>,
+    /// The array sequence split into chunks, i.e. `msg.parts.len > 1`, is flattened
+    flatten: bool,
+    /// The keys are the property paths of `msg.parts.key` in the auto mode, the keys taken from the message like
+    /// `msg.topic` in the custom mode are literal
+    nested_keys: bool,
+    last_msg: MsgHandle,
+    /// Cancels the timeout timer of the group
+    timer: CancellationToken,
+}
+
+#[derive(Debug)]
+#[flow_node("join")]
+struct JoinNode {
+    base: FlowNode,
+    config: JoinNodeConfig,
+    /// The joiner of the custom mode resolved from the configuration
+    joiner: Variant,
+    groups: DashMap<ElementId, PartialJoin>,
+    sequences: std::sync::Mutex<SequenceTracker<(Option<String>, Variant)>>,
+    serial: AtomicU64,
+}
+
+impl JoinNode {
+    fn build(
+        _flow: &Flow,
+        base_node: FlowNode,
+        config: &RedFlowNodeConfig,
+    ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let join_config = JoinNodeConfig::deserialize(&config.rest)?;
+        let joiner = match join_config.joiner_type {
+            JoinerType::Str => {
+                Variant::String(join_config.joiner.replace("\\n", "\n").replace("\\r", "\r").replace("\\t", "\t"))
+            }
+            JoinerType::Bin => Variant::Bytes(
+                serde_json::from_str::<Vec<u8>>(&join_config.joiner)
+                    .map_err(|e| EdgelinkError::BadFlowsJson(format!("Bad binary joiner of the join node: {}", e)))?,
+            ),
+        };
+        let node = JoinNode {
+            base: base_node,
+            config: join_config,
+            joiner,
+            groups: DashMap::new(),
+            sequences: std::sync::Mutex::new(SequenceTracker::default()),
+            serial: AtomicU64::new(0),
+        };
+        Ok(Box::new(node))
+    }
+
+    async fn receive(self: &Arc<Self>, msg: MsgHandle, stop_token: CancellationToken) -> crate::Result<()> {
+        let (mut input, complete) = {
+            let msg_guard = msg.read().await;
+            if msg_guard.contains("reset") {
+                // Drops the partly joined messages without sending
+                match self.group_id_of(&msg_guard) {
+                    Some(group_id) => {
+                        self.take_group(&group_id);
+                    }
+                    None => {
+                        self.groups.retain(|_, group| {
+                            group.timer.cancel();
+                            false
+                        });
+                        self.sequences.lock().expect("`sequences` lock").clear();
+                    }
+                }
+                return Ok(());
+            }
+            let input = match self.config.mode {
+                JoinMode::Auto => self.parse_auto(&msg_guard)?,
+                JoinMode::Custom => self.parse_custom(&msg_guard)?,
+            };
+            (input, msg_guard.contains("complete"))
+        };
+        let has_value = input.value.is_some();
+        if !has_value && !complete {
+            return Ok(());
+        }
+
+        let status = match (input.parts.as_ref(), input.value.take()) {
+            (Some(parts), Some(value)) => {
+                let item = (input.key.take(), value);
+                Some(self.sequences.lock().expect("`sequences` lock").push(parts, item, std::time::Instant::now())?)
+            }
+            (_, value) => {
+                input.value = value;
+                None
+            }
+        };
+        if let Some(SequenceStatus::Duplicated(_)) = status {
+            log::warn!("[JOIN:{}] Dropped the message of a received index in the sequence", self.name());
+            return Ok(());
+        }
+
+        let group_id = input.group_id;
+        let completed = {
+            let mut group = match self.groups.entry(group_id) {
+                Entry::Occupied(entry) => entry.into_ref(),
+                // There is nothing to send for the `msg.complete` without any joined message
+                Entry::Vacant(_) if !has_value => return Ok(()),
+                Entry::Vacant(entry) => entry.insert(self.new_group(&input, msg.clone(), &stop_token)),
+            };
+            group.last_msg = msg.clone();
+            match status {
+                Some(SequenceStatus::Complete(items)) => {
+                    group.items = items;
+                    true
+                }
+                Some(_) => complete,
+                None => {
+                    if let Some(value) = input.value {
+                        group.items.push((input.key, value));
+                    }
+                    if input.expected.is_some() {
+                        group.expected = input.expected;
+                    }
+                    complete || group.expected.is_some_and(|x| group.items.len() >= x)
+                }
+            }
+        };
+
+        if completed {
+            if self.config.mode == JoinMode::Custom && self.config.accumulate {
+                let joined = self.groups.get(&group_id).map(|x| Self::join_items(&x).map(|y| (y, x.last_msg.clone())));
+                if let Some(joined) = joined {
+                    let (payload, last_msg) = joined?;
+                    self.send(payload, &last_msg, stop_token).await?;
+                }
+            } else if let Some(group) = self.take_group(&group_id) {
+                self.emit(group, stop_token).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The group of the message, `None` if it is unknown, i.e. a message without `msg.parts` in the auto mode.
+    fn group_id_of(&self, msg: &Msg) -> Option<ElementId> {
+        match self.config.mode {
+            JoinMode::Auto => msg.get_nav_stripped("parts.id").map(parts_id_to_element_id),
+            JoinMode::Custom => Some(ElementId::empty()),
+        }
+    }
+
+    fn parse_auto(&self, msg: &Msg) -> crate::Result<JoinInput> {
+        let parts = MsgParts::from_msg(msg)?
+            .ok_or(EdgelinkError::InvalidOperation("Message missing msg.parts property".into()))?;
+        let build = match parts.kind.as_deref() {
+            Some("string") => JoinBuild::String,
+            Some("buffer") => JoinBuild::Buffer,
+            Some("object") => JoinBuild::Object,
+            _ => JoinBuild::Array,
+        };
+        let key = if build == JoinBuild::Object {
+            let key = parts
+                .key
+                .clone()
+                .ok_or(EdgelinkError::InvalidOperation("Message missing msg.parts.key property".into()))?;
+            Some(key)
+        } else {
+            None
+        };
+        Ok(JoinInput {
+            group_id: msg.get_nav_stripped("parts.id").map(parts_id_to_element_id).unwrap_or_default(),
+            build,
+            parts: Some(parts),
+            key,
+            value: Some(msg.get_nav_stripped(&self.config.property).cloned().unwrap_or_default()),
+            expected: None,
+            // The delimiter of a buffer sequence may be bytes, so it is not taken from the `MsgParts`
+            joiner: msg.get_nav_stripped("parts.ch").cloned(),
+            flatten: msg.get_nav_stripped("parts.len").and_then(|x| x.as_u64()).is_some_and(|x| x > 1),
+        })
+    }
+
+    fn parse_custom(&self, msg: &Msg) -> crate::Result<JoinInput> {
+        let value = msg.get_nav_stripped(&self.config.property).cloned();
+        let key = match (self.config.build, &value) {
+            (JoinBuild::Object, Some(_)) => {
+                let key = msg.get_nav_stripped(&self.config.key).and_then(|x| x.as_str()).ok_or(
+                    EdgelinkError::InvalidOperation(format!("Message missing key property: 'msg.{}'", self.config.key)),
+                )?;
+                Some(key.to_string())
+            }
+            _ => None,
+        };
+        // The count of the sequence is used if the count is not configured
+        let count = self
+            .config
+            .count
+            .filter(|x| *x > 0)
+            .or_else(|| msg.get_nav_stripped("parts.count").and_then(|x| x.as_u64()).map(|x| x as usize));
+        Ok(JoinInput {
+            group_id: ElementId::empty(),
+            build: self.config.build,
+            parts: None,
+            key,
+            value,
+            expected: count,
+            joiner: Some(self.joiner.clone()),
+            flatten: false,
+        })
+    }
+
+    fn new_group(self: &Arc<Self>, input: &JoinInput, msg: MsgHandle, stop_token: &CancellationToken) -> PartialJoin {
+        let serial = self.serial.fetch_add(1, Ordering::Relaxed);
+        let timer = stop_token.child_token();
+        if let Some(timeout) = self.config.timeout.filter(|x| *x > 0.0) {
+            let deadline = Instant::now() + Duration::from_secs_f64(timeout);
+            self.start_timer(input.group_id, serial, deadline, timer.clone(), stop_token.clone());
+        }
+        PartialJoin {
+            serial,
+            build: input.build,
+            sequence_id: input.parts.as_ref().map(|x| x.id.clone()),
+            items: Vec::new(),
+            expected: None,
+            joiner: input.joiner.clone(),
+            flatten: input.flatten,
+            nested_keys: input.parts.is_some(),
+            last_msg: msg,
+            timer,
+        }
+    }
+
+    /// Removes the group and stops its timer.
+    fn take_group(&self, group_id: &ElementId) -> Option<PartialJoin> {
+        let (_, mut group) = self.groups.remove(group_id)?;
+        group.timer.cancel();
+        self.take_sequence_items(&mut group);
+        Some(group)
+    }
+
+    /// Moves the values of the incomplete sequence of the group out of the sequence tracker.
+    fn take_sequence_items(&self, group: &mut PartialJoin) {
+        if let Some(sequence_id) = &group.sequence_id {
+            if let Some(items) = self.sequences.lock().expect("`sequences` lock").remove(sequence_id) {
+                group.items = items;
+            }
+        }
+    }
+
+    /// Sends the partly joined group at the deadline, the timer is cancelled if the group has been completed or the
+    /// node has been stopped.
+    fn start_timer(
+        self: &Arc<Self>,
+        group_id: ElementId,
+        serial: u64,
+        deadline: Instant,
+        timer: CancellationToken,
+        stop_token: CancellationToken,
+    ) {
+        let node = self.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = tokio::time::sleep_until(deadline) => {
+                    // The group may have been completed and replaced by a new one with the same id
+                    if let Some((_, mut group)) = node.groups.remove_if(&group_id, |_, g| g.serial == serial) {
+                        node.take_sequence_items(&mut group);
+                        if let Err(e) = node.emit(group, stop_token).await {
+                            log::warn!("[JOIN:{}] Failed to emit the timed out group: {}", node.name(), e);
+                        }
+                    }
+                }
+                _ = timer.cancelled() => {}
+            }
+        });
+    }
+
+    /// Joins the received values by the build type of the group.
+    fn join_items(group: &PartialJoin) -> crate::Result<Variant> {
+        let values = group.items.iter();
+        match group.build {
+            JoinBuild::Array => {
+                let mut array = Vec::with_capacity(group.items.len());
+                for (_, value) in values {
+                    match value {
+                        Variant::Array(chunk) if group.flatten => array.extend(chunk.iter().cloned()),
+                        _ => array.push(value.clone()),
+                    }
+                }
+                Ok(Variant::Array(array))
+            }
+            JoinBuild::String => {
+                let joiner = group.joiner.as_ref().and_then(|x| x.as_str()).unwrap_or_default();
+                let strs = values.map(|(_, x)| x.to_string()).collect::<crate::Result<Vec<String>>>()?;
+                Ok(Variant::String(strs.join(joiner)))
+            }
+            JoinBuild::Buffer => {
+                let joiner = group.joiner.as_ref().and_then(|x| x.to_bytes()).unwrap_or_default();
+                let mut bytes = Vec::new();
+                for (i, (_, value)) in values.enumerate() {
+                    if i > 0 {
+                        bytes.extend_from_slice(&joiner);
+                    }
+                    let part = value.to_bytes().ok_or_else(|| {
+                        EdgelinkError::InvalidOperation(format!("Cannot join the value into a buffer: {:?}", value))
+                    })?;
+                    bytes.extend(part);
+                }
+                Ok(Variant::Bytes(bytes))
+            }
+            JoinBuild::Object => {
+                let mut object = Variant::empty_object();
+                for (key, value) in values {
+                    match key {
+                        Some(key) if group.nested_keys => object.set_nav(key, value.clone(), true, &[])?,
+                        Some(key) => {
+                            object.as_object_mut().expect("an object").insert(key.clone(), value.clone());
+                        }
+                        None => {}
+                    }
+                }
+                Ok(object)
+            }
+            JoinBuild::Merged => {
+                let mut merged = VariantObjectMap::new();
+                for (_, value) in values {
+                    if let Variant::Object(object) = value {
+                        merged.extend(object.iter().map(|(k, v)| (k.clone(), v.clone())));
+                    }
+                }
+                Ok(Variant::Object(merged))
+            }
+        }
+    }
+
+    async fn emit(&self, group: PartialJoin, cancel: CancellationToken) -> crate::Result<()> {
+        let payload = Self::join_items(&group)?;
+        self.send(payload, &group.last_msg, cancel).await
+    }
+
+    /// Sends the joined value in the copy of the last received message.
+    async fn send(&self, payload: Variant, last_msg: &MsgHandle, cancel: CancellationToken) -> crate::Result<()> {
+        let msg = last_msg.deep_clone(false).await;
+        {
+            let mut msg_guard = msg.write().await;
+            msg_guard.set_nav_stripped(&self.config.property, payload, true)?;
+            // Restores the parts of the outer sequence
+            match msg_guard.get_nav_stripped("parts.parts").cloned() {
+                Some(outer_parts) => msg_guard.set("parts".to_string(), outer_parts),
+                None => {
+                    let _ = msg_guard.remove("parts");
+                }
+            }
+            let _ = msg_guard.remove("complete");
+        }
+        self.fan_out_one(Envelope { port: 0, msg }, cancel).await
+    }
+}
+
+fn parts_id_to_element_id(id: &Variant) -> ElementId {
+    match id {
+        Variant::Number(n) => n.as_u64().map(ElementId::with_u64).unwrap_or_default(),
+        Variant::String(s) => json::deser::parse_red_id_str(s).unwrap_or_else(|| {
+            let mut hasher = DefaultHasher::new();
+            s.hash(&mut hasher);
+            ElementId::with_u64(hasher.finish())
+        }),
+        _ => ElementId::empty(),
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for JoinNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            let this = self.clone();
+            with_uow(self.as_ref(), cancel.child_token(), |_, msg| async move { this.receive(msg, cancel).await })
+                .await;
+        }
+
+        log::debug!("JoinNode process() task has been terminated.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_it_should_join_keyed_messages_by_the_literal_topics() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "join", "mode": "custom", "build": "object",
+                "property": "payload", "key": "topic", "count": "3", "timeout": "", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        // The topics are not property paths
+        let msgs_to_inject_json = json!([
+            ["1", {"topic": "sensors/room 1", "payload": 1}],
+            ["1", {"topic": "a.b", "payload": 2}],
+            ["1", {"topic": "c[0]", "payload": 3}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs = engine.run_once_with_inject(1, Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        let expected = Variant::deserialize(json!({"sensors/room 1": 1, "a.b": 2, "c[0]": 3})).unwrap();
+        assert_eq!(msgs[0]["payload"], expected);
+    }
+
+    #[tokio::test]
+    async fn test_it_should_join_object_sequence_by_parts_key() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "join", "mode": "auto", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": "x", "parts": {"id": "abc", "type": "object", "key": "foo.bar", "index": 0, "count": 2}}],
+            ["1", {"payload": "y", "parts": {"id": "abc", "type": "object", "key": "foo.baz", "index": 1, "count": 2}}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs = engine.run_once_with_inject(1, Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        let expected = Variant::deserialize(json!({"foo": {"bar": "x", "baz": "y"}})).unwrap();
+        assert_eq!(msgs[0]["payload"], expected);
+        assert!(!msgs[0].contains("parts"));
+    }
+
+    #[tokio::test]
+    async fn test_it_should_emit_partial_object_on_timeout() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "join", "mode": "custom", "build": "object",
+                "key": "topic", "count": "10", "timeout": "0.1", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"topic": "a", "payload": 1}],
+            ["1", {"topic": "b", "payload": 2}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs = engine.run_once_with_inject(1, Duration::from_secs_f64(0.5), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        let expected = Variant::deserialize(json!({"a": 1, "b": 2})).unwrap();
+        assert_eq!(msgs[0]["payload"], expected);
+    }
+
+    fn inject(msgs: serde_json::Value) -> Vec<(ElementId, Msg)> {
+        Vec::<(ElementId, Msg)>::deserialize(msgs).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_it_should_join_array_sequence_received_out_of_order() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "join", "mode": "auto", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let outer = json!({"id": "outer", "type": "array", "index": 1, "count": 2});
+        let msgs_to_inject = inject(json!([
+            ["1", {"payload": "c", "parts": {"id": "s", "type": "array", "index": 2, "count": 3, "parts": outer}}],
+            ["1", {"payload": "a", "parts": {"id": "s", "type": "array", "index": 0, "count": 3, "parts": outer}}],
+            ["1", {"payload": "b", "parts": {"id": "s", "type": "array", "index": 1, "count": 3, "parts": outer}}],
+        ]));
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(1, Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0]["payload"], Variant::deserialize(json!(["a", "b", "c"])).unwrap());
+        // The parts of the outer sequence are restored
+        assert_eq!(msgs[0]["parts"], Variant::deserialize(outer).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_duplicated_index_should_not_complete_the_sequence() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "join", "mode": "auto", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = inject(json!([
+            ["1", {"payload": "a", "parts": {"id": "s", "type": "array", "index": 0, "count": 3}}],
+            ["1", {"payload": "x", "parts": {"id": "s", "type": "array", "index": 0, "count": 3}}],
+            ["1", {"payload": "y", "parts": {"id": "s", "type": "array", "index": 1, "count": 3}}],
+            ["1", {"payload": "z", "parts": {"id": "s", "type": "array", "index": 1, "count": 3}}],
+            ["1", {"payload": "b", "parts": {"id": "s", "type": "array", "index": 2, "count": 3}}],
+        ]));
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(1, Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0]["payload"], Variant::deserialize(json!(["a", "y", "b"])).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_it_should_rejoin_the_split_arrays_strings_and_buffers() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "split", "arraySplt": 2, "arraySpltType": "len", "wires": [["4"]]},
+            {"id": "2", "z": "100", "type": "split", "splt": "\\n", "spltType": "str", "wires": [["4"]]},
+            {"id": "3", "z": "100", "type": "split", "splt": "[0]", "spltType": "bin", "wires": [["4"]]},
+            {"id": "4", "z": "100", "type": "join", "mode": "auto", "wires": [["5"]]},
+            {"id": "5", "z": "100", "type": "test-once"}
+        ]);
+        let mut msgs_to_inject = inject(json!([
+            ["1", {"topic": "array", "payload": [1, 2, 3, 4, 5]}],
+            ["2", {"topic": "string", "payload": "a\nb\n\nc"}],
+        ]));
+        let mut bytes_msg = Msg::deserialize(json!({"topic": "buffer"})).unwrap();
+        bytes_msg.set("payload".to_string(), Variant::Bytes(vec![1, 0, 2, 2, 0, 3]));
+        msgs_to_inject.push((ElementId::with_u64(3), bytes_msg));
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(3, Duration::from_secs_f64(0.3), msgs_to_inject).await.unwrap();
+        let payload_of =
+            |topic: &str| msgs.iter().find(|x| x["topic"] == Variant::from(topic)).unwrap()["payload"].clone();
+        assert_eq!(payload_of("array"), Variant::deserialize(json!([1, 2, 3, 4, 5])).unwrap());
+        assert_eq!(payload_of("string"), Variant::from("a\nb\n\nc"));
+        assert_eq!(payload_of("buffer"), Variant::Bytes(vec![1, 0, 2, 2, 0, 3]));
+        assert!(msgs.iter().all(|x| !x.contains("parts")));
+    }
+
+    #[tokio::test]
+    async fn test_it_should_join_strings_until_msg_complete() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "join", "mode": "custom", "build": "string",
+                "joiner": "\\n", "joinerType": "str", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = inject(json!([
+            ["1", {"payload": "a"}],
+            ["1", {"payload": 2}],
+            ["1", {"complete": true}],
+            ["1", {"payload": "c", "complete": true}],
+        ]));
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(2, Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0]["payload"], Variant::from("a\n2"));
+        assert!(!msgs[0].contains("complete"));
+        assert_eq!(msgs[1]["payload"], Variant::from("c"));
+    }
+
+    #[tokio::test]
+    async fn test_it_should_join_buffers_with_binary_joiner() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "join", "mode": "custom", "build": "buffer",
+                "joiner": "[44]", "joinerType": "bin", "count": "3", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = inject(json!([
+            ["1", {"payload": "ab"}],
+            ["1", {"payload": [1, 2]}],
+            ["1", {"payload": "c"}],
+        ]));
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(1, Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0]["payload"], Variant::Bytes(b"ab,\x01\x02,c".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn test_it_should_merge_objects_and_drop_the_reset_messages() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "join", "mode": "custom", "build": "merged",
+                "count": "2", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = inject(json!([
+            ["1", {"payload": {"a": 0}}],
+            ["1", {"reset": true}],
+            ["1", {"payload": {"a": 1, "b": 1}}],
+            ["1", {"payload": {"b": 2, "c": 2}}],
+        ]));
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(1, Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0]["payload"], Variant::deserialize(json!({"a": 1, "b": 2, "c": 2})).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_it_should_send_the_accumulated_object_for_subsequent_messages() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "join", "mode": "custom", "build": "object",
+                "count": "2", "accumulate": "true", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = inject(json!([
+            ["1", {"topic": "a", "payload": 1}],
+            ["1", {"topic": "b", "payload": 2}],
+            ["1", {"topic": "c", "payload": 3}],
+        ]));
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(2, Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0]["payload"], Variant::deserialize(json!({"a": 1, "b": 2})).unwrap());
+        assert_eq!(msgs[1]["payload"], Variant::deserialize(json!({"a": 1, "b": 2, "c": 3})).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_completed_group_should_cancel_its_timer() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "join", "mode": "auto", "timeout": "0.1", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = inject(json!([
+            ["1", {"topic": "done", "payload": 1, "parts": {"id": "a", "type": "array", "index": 0, "count": 1}}],
+            ["1", {"topic": "partial", "payload": 2, "parts": {"id": "b", "type": "array", "index": 1, "count": 3}}],
+        ]));
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(2, Duration::from_secs_f64(0.4), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0]["topic"], Variant::from("done"));
+        assert_eq!(msgs[1]["topic"], Variant::from("partial"));
+        assert_eq!(msgs[1]["payload"], Variant::deserialize(json!([2])).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_it_should_join_shuffled_sequences_across_concurrent_flows() {
+        use rand::seq::SliceRandom;
+
+        const FLOW_COUNT: u64 = 4;
+        const SEQ_COUNT: usize = 25;
+        const PART_COUNT: usize = 8;
+
+        let mut flows = Vec::new();
+        for i in 0..FLOW_COUNT {
+            let (tab_id, join_id, sink_id) = (0x100 + i, 0x10 + i, 0x20 + i);
+            flows.push(json!({"id": format!("{:x}", tab_id), "type": "tab"}));
+            flows.push(json!({"id": format!("{:x}", join_id), "z": format!("{:x}", tab_id), "type": "join",
+                "mode": "auto", "wires": [[format!("{:x}", sink_id)]]}));
+            flows.push(json!({"id": format!("{:x}", sink_id), "z": format!("{:x}", tab_id), "type": "test-once"}));
+        }
+
+        // The even sequences are arrays and the odd ones are strings
+        let mut msgs_to_inject = Vec::new();
+        for flow in 0..FLOW_COUNT {
+            for seq in 0..SEQ_COUNT {
+                let seq_id = format!("seq-{}-{}", flow, seq);
+                for index in 0..PART_COUNT {
+                    let (kind, payload) =
+                        if seq % 2 == 0 { ("array", json!(index)) } else { ("string", json!(index.to_string())) };
+                    let msg = json!({"topic": seq_id, "payload": payload,
+                        "parts": {"id": seq_id, "type": kind, "ch": ",", "index": index, "count": PART_COUNT}});
+                    msgs_to_inject.push((ElementId::with_u64(0x10 + flow), Msg::deserialize(msg).unwrap()));
+                }
+            }
+        }
+        msgs_to_inject.shuffle(&mut rand::thread_rng());
+
+        let engine = crate::runtime::engine::build_test_engine(serde_json::Value::Array(flows)).unwrap();
+        let msgs = engine
+            .run_once_with_inject(FLOW_COUNT as usize * SEQ_COUNT, Duration::from_secs(5), msgs_to_inject)
+            .await
+            .unwrap();
+        assert_eq!(msgs.len(), FLOW_COUNT as usize * SEQ_COUNT);
+
+        let expected_array = Variant::Array((0..PART_COUNT).map(|x| Variant::from(x as u64)).collect());
+        let expected_string = Variant::from((0..PART_COUNT).map(|x| x.to_string()).collect::<Vec<_>>().join(","));
+        let mut topics = std::collections::HashSet::new();
+        for msg in msgs.iter() {
+            let topic = msg["topic"].as_str().unwrap().to_string();
+            let seq: usize = topic.rsplit('-').next().unwrap().parse().unwrap();
+            let expected = if seq % 2 == 0 { &expected_array } else { &expected_string };
+            assert_eq!(&msg["payload"], expected, "The sequence '{}' is joined wrongly", topic);
+            assert!(!msg.contains("parts"));
+            assert!(topics.insert(topic), "The sequence is joined more than once");
+        }
+    }
+}
@@ -0,0 +1,308 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+enum JsonAction {
+    /// Parses the strings and the buffers, serializes the others
+    #[default]
+    #[serde(rename = "")]
+    Toggle,
+
+    /// Always converts to the JSON string, the strings and the buffers are left as is
+    #[serde(rename = "str")]
+    Str,
+
+    /// Always converts to the object, the values other than the strings and the buffers are left as is
+    #[serde(rename = "obj")]
+    Obj,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct JsonNodeConfig {
+    #[serde(default = "property_default")]
+    property: String,
+
+    #[serde(default)]
+    action: JsonAction,
+
+    /// Indents the JSON string with 4 spaces like Node-RED does
+    #[serde(default, deserialize_with = "json::deser::deser_bool_or_str")]
+    pretty: bool,
+
+    /// The JSON Schema to validate the object, either before the serialization or after the parsing, the
+    /// `msg.schema` overrides it
+    #[serde(default, deserialize_with = "json::deser::deser_json_schema")]
+    schema: Option<serde_json::Value>,
+}
+
+fn property_default() -> String {
+    "payload".to_string()
+}
+
+fn parse_json(text: &str) -> Result<Variant, String> {
+    serde_json::from_str::<serde_json::Value>(text)
+        .map(Variant::from)
+        .map_err(|e| format!("Failed to parse the JSON: {}", e))
+}
+
+fn stringify_json(value: &Variant, pretty: bool) -> Result<String, String> {
+    let value = serde_json::Value::from(value);
+    if pretty {
+        let mut buf = Vec::new();
+        let formatter = serde_json::ser::PrettyFormatter::with_indent(b"    ");
+        let mut serializer = serde_json::Serializer::with_formatter(&mut buf, formatter);
+        value.serialize(&mut serializer).map_err(|e| e.to_string())?;
+        String::from_utf8(buf).map_err(|e| e.to_string())
+    } else {
+        serde_json::to_string(&value).map_err(|e| e.to_string())
+    }
+}
+
+/// Converts the value by the action, returns `None` if the value is left as is.
+///
+/// The error is the reason of the failure of the parsing or the validation.
+fn convert(
+    config: &JsonNodeConfig,
+    value: &Variant,
+    schema: Option<&jsonschema::Validator>,
+) -> Result<Option<Variant>, String> {
+    let validate = |obj: &Variant| match schema {
+        Some(schema) => obj.validate_with(schema).map_err(|e| e.to_string()),
+        None => Ok(()),
+    };
+    let text = match value {
+        Variant::String(s) => Some(s.as_str()),
+        Variant::Bytes(bytes) => {
+            Some(std::str::from_utf8(bytes).map_err(|e| format!("Failed to parse the JSON: {}", e))?)
+        }
+        _ => None,
+    };
+    match text {
+        Some(text) if config.action == JsonAction::Str => {
+            if schema.is_some() {
+                validate(&parse_json(text)?)?;
+            }
+            Ok(None)
+        }
+        Some(text) => {
+            let parsed = parse_json(text)?;
+            validate(&parsed)?;
+            Ok(Some(parsed))
+        }
+        None => {
+            validate(value)?;
+            if config.action == JsonAction::Obj {
+                Ok(None)
+            } else {
+                Ok(Some(Variant::String(stringify_json(value, config.pretty)?)))
+            }
+        }
+    }
+}
+
+/// Converts between the JSON string and the object, optionally validated by a JSON Schema.
+///
+/// The messages failed to parse or validate are sent to the second output with the `error` property if the node has
+/// it, otherwise the error is reported to the `catch` nodes.
+#[derive(Debug)]
+#[flow_node("json")]
+struct JsonNode {
+    base: FlowNode,
+    config: JsonNodeConfig,
+
+    /// Compiled from the `schema` of the config once
+    validator: Option<jsonschema::Validator>,
+}
+
+impl JsonNode {
+    fn build(_flow: &Flow, state: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let json_config = JsonNodeConfig::deserialize(&config.rest)?;
+        let validator = json_config.schema.as_ref().map(Variant::compile_schema).transpose()?;
+        let node = JsonNode { base: state, config: json_config, validator };
+        Ok(Box::new(node))
+    }
+
+    async fn uow(&self, msg: MsgHandle, cancel: CancellationToken) -> crate::Result<()> {
+        let port = {
+            let mut msg_guard = msg.write().await;
+            let property = &self.config.property;
+            let msg_validator = msg_guard.get("schema").map(|x| Variant::compile_schema(&serde_json::Value::from(x)));
+            let converted = match (msg_guard.get_nav_stripped(property), msg_validator) {
+                (Some(value), None) => convert(&self.config, value, self.validator.as_ref()),
+                (Some(value), Some(Ok(validator))) => convert(&self.config, value, Some(&validator)),
+                // The bad schema of the message fails the message only
+                (Some(_), Some(Err(e))) => Err(e.to_string()),
+                (None, _) => {
+                    return Err(
+                        EdgelinkError::InvalidOperation(format!("Cannot find the property 'msg.{}'", property)).into()
+                    )
+                }
+            };
+            match converted {
+                Ok(Some(converted)) => {
+                    msg_guard.set_nav_stripped(property, converted, true)?;
+                    0
+                }
+                Ok(None) => 0,
+                Err(e) if self.get_node().ports.len() > 1 => {
+                    let error = VariantObjectMap::from([("message".to_string(), Variant::String(e))]);
+                    msg_guard.set("error".to_string(), Variant::Object(error));
+                    1
+                }
+                Err(e) => return Err(EdgelinkError::InvalidOperation(e).into()),
+            }
+        };
+        self.fan_out_one(Envelope { port, msg }, cancel).await
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for JsonNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow_concurrent(&self, cancel.child_token(), |node, msg| async move { node.uow(msg, cancel).await })
+                .await;
+        }
+        wait_uows_completed(self.as_ref()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn make_config(config: serde_json::Value) -> JsonNodeConfig {
+        JsonNodeConfig::deserialize(config).unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_stringify_by_action() {
+        let toggle = make_config(json!({"action": ""}));
+        let parsed = convert(&toggle, &Variant::from(r#"{"a": [1, true, null]}"#), None).unwrap();
+        assert_eq!(parsed, Some(Variant::from(json!({"a": [1, true, null]}))));
+        let text = convert(&toggle, &Variant::from(json!({"b": "x", "a": 1})), None).unwrap();
+        assert_eq!(text, Some(Variant::from(r#"{"a":1,"b":"x"}"#)));
+        assert_eq!(
+            convert(&toggle, &Variant::Bytes(b"[1,2]".to_vec()), None).unwrap(),
+            Some(Variant::from(json!([1, 2])))
+        );
+        assert_eq!(convert(&toggle, &Variant::from(42), None).unwrap(), Some(Variant::from("42")));
+        assert!(convert(&toggle, &Variant::from("{oops"), None).unwrap_err().contains("Failed to parse the JSON"));
+
+        // Converted to the type of the action only
+        let to_obj = make_config(json!({"action": "obj"}));
+        assert_eq!(convert(&to_obj, &Variant::from(json!({"a": 1})), None).unwrap(), None);
+        assert_eq!(convert(&to_obj, &Variant::from("[1]"), None).unwrap(), Some(Variant::from(json!([1]))));
+        let to_str = make_config(json!({"action": "str"}));
+        assert_eq!(convert(&to_str, &Variant::from("[1]"), None).unwrap(), None);
+        assert_eq!(convert(&to_str, &Variant::from(json!([1])), None).unwrap(), Some(Variant::from("[1]")));
+    }
+
+    #[test]
+    fn test_pretty_should_indent_with_four_spaces() {
+        let config = make_config(json!({"pretty": true}));
+        let text = convert(&config, &Variant::from(json!({"a": [1]})), None).unwrap();
+        assert_eq!(text, Some(Variant::from("{\n    \"a\": [\n        1\n    ]\n}")));
+    }
+
+    #[test]
+    fn test_schema_should_validate_both_directions() {
+        let schema = json!({"type": "object", "properties": {"temp": {"type": "number"}}, "required": ["temp"]});
+        let config = make_config(json!({"schema": schema.to_string()}));
+        let schema = Variant::compile_schema(config.schema.as_ref().unwrap()).unwrap();
+        assert_eq!(
+            convert(&config, &Variant::from(r#"{"temp": 21.5}"#), Some(&schema)).unwrap(),
+            Some(Variant::from(json!({"temp": 21.5})))
+        );
+        assert_eq!(
+            convert(&config, &Variant::from(json!({"temp": 21.5})), Some(&schema)).unwrap(),
+            Some(Variant::from(r#"{"temp":21.5}"#))
+        );
+        let err = convert(&config, &Variant::from(r#"{"temp": "hot"}"#), Some(&schema)).unwrap_err();
+        assert!(err.contains("Schema validation failed at '/temp'"), "{}", err);
+        let err = convert(&config, &Variant::from(json!({"humidity": 40})), Some(&schema)).unwrap_err();
+        assert!(err.contains("\"temp\" is a required property"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_it_should_send_failures_to_error_output() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "json", "wires": [["2"], ["2"]],
+                "schema": "{\"type\": \"object\", \"required\": [\"temp\"]}"},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"topic": "parsed", "payload": "{\"temp\": 1}"}],
+            ["1", {"topic": "invalid", "payload": "{\"humidity\": 40}"}],
+            ["1", {"topic": "malformed", "payload": "{\"temp\": "}],
+        ]))
+        .unwrap();
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(3, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+
+        let (failed, converted): (Vec<_>, Vec<_>) = msgs.into_iter().partition(|x| x.contains("error"));
+        assert_eq!(converted.len(), 1);
+        assert_eq!(converted[0]["payload"], Variant::from(json!({"temp": 1})));
+        let error_of = |topic: &str| {
+            let msg = failed.iter().find(|x| x["topic"] == Variant::from(topic)).unwrap();
+            msg.get_nav_stripped("error.message").unwrap().as_str().unwrap().to_string()
+        };
+        assert!(error_of("invalid").contains("\"temp\" is a required property"));
+        assert!(error_of("malformed").contains("Failed to parse the JSON"));
+    }
+
+    #[tokio::test]
+    async fn test_failures_should_be_caught_without_error_output() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "json", "wires": [["2"]], "schema": {"type": "array"}},
+            {"id": "2", "z": "100", "type": "test-once"},
+            {"id": "3", "z": "100", "type": "catch", "wires": [["2"]]}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"payload": {"a": 1}}],
+            // The schema of the message overrides the configured one
+            ["1", {"payload": {"a": 1}, "schema": {"type": "object"}}],
+        ]))
+        .unwrap();
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(2, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+
+        let (caught, converted): (Vec<_>, Vec<_>) = msgs.into_iter().partition(|x| x.contains("error"));
+        assert!(caught[0].get_nav_stripped("error.message").unwrap().as_str().unwrap().contains("is not of type"));
+        assert_eq!(converted[0]["payload"], Variant::from(r#"{"a":1}"#));
+    }
+
+    #[test]
+    fn test_bad_schema_should_be_rejected() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "json", "wires": [[]], "schema": {"type": "strnig"}}
+        ]);
+        let err = crate::runtime::engine::build_test_engine(flows_json).unwrap_err();
+        assert!(format!("{:?}", err).contains("Bad JSON Schema"), "{:?}", err);
+    }
+
+    #[test]
+    fn test_pattern_should_be_validated() {
+        let config = make_config(json!({"schema": {"type": "string", "pattern": "^a"}}));
+        let schema = Variant::compile_schema(config.schema.as_ref().unwrap()).unwrap();
+        assert_eq!(convert(&config, &Variant::from(r#""abc""#), Some(&schema)).unwrap(), Some(Variant::from("abc")));
+        assert!(convert(&config, &Variant::from(r#""xyz""#), Some(&schema)).is_err());
+    }
+}
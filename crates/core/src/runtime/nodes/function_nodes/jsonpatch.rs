@@ -0,0 +1,149 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+#[derive(Debug, Clone, Deserialize)]
+struct JsonPatchNodeConfig {
+    /// The message property to patch
+    #[serde(default = "property_default")]
+    property: String,
+
+    /// The RFC6902 operations, either an array or its JSON string
+    #[serde(default)]
+    patch: Value,
+}
+
+fn property_default() -> String {
+    "payload".to_string()
+}
+
+#[derive(Debug)]
+#[flow_node("jsonpatch")]
+struct JsonPatchNode {
+    base: FlowNode,
+    config: JsonPatchNodeConfig,
+    patch: Option<Variant>,
+}
+
+impl JsonPatchNode {
+    fn build(
+        _flow: &Flow,
+        base_node: FlowNode,
+        config: &RedFlowNodeConfig,
+    ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let patch_config = JsonPatchNodeConfig::deserialize(&config.rest)?;
+        let patch = match &patch_config.patch {
+            Value::Null => None,
+            Value::String(s) if s.trim().is_empty() => None,
+            Value::String(s) => Some(Variant::deserialize(serde_json::from_str::<Value>(s)?)?),
+            other => Some(Variant::deserialize(other)?),
+        };
+        if patch.as_ref().is_some_and(|x| !x.is_array()) {
+            return Err(EdgelinkError::BadFlowsJson("The patch of the jsonpatch node must be an array".into()).into());
+        }
+        let node = JsonPatchNode { base: base_node, config: patch_config, patch };
+        Ok(Box::new(node))
+    }
+
+    /// Applies `msg.patch` or the configured patch to the property, `msg.patch` is removed after applied.
+    async fn receive(&self, msg: MsgHandle, cancel: CancellationToken) -> crate::Result<()> {
+        {
+            let mut msg_guard = msg.write().await;
+            let patch = match msg_guard.remove("patch").or_else(|| self.patch.clone()) {
+                Some(patch) => patch,
+                None => {
+                    return Err(EdgelinkError::InvalidOperation(
+                        "No patch to apply, neither `msg.patch` nor the node configuration".into(),
+                    )
+                    .into())
+                }
+            };
+            let target = msg_guard
+                .get_nav_stripped_mut(&self.config.property)
+                .ok_or(EdgelinkError::InvalidOperation(format!("Cannot find 'msg.{}'", self.config.property)))?;
+            target.apply_json_patch(&patch)?;
+        }
+        self.fan_out_one(Envelope { port: 0, msg }, cancel).await
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for JsonPatchNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow_concurrent(
+                &self,
+                cancel.child_token(),
+                |node, msg| async move { node.receive(msg, cancel).await },
+            )
+            .await;
+        }
+        wait_uows_completed(self.as_ref()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_it_should_apply_multi_op_patch() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "jsonpatch", "wires": [["2"]], "patch": [
+                {"op": "add", "path": "/tags/-", "value": "new"},
+                {"op": "replace", "path": "/user/name", "value": "bob"},
+                {"op": "move", "from": "/legacy", "path": "/user/id"},
+                {"op": "remove", "path": "/tags/0"}
+            ]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"payload": {"user": {"name": "alice"}, "tags": ["old"], "legacy": 42}}],
+            ["1", {"payload": {"a": 1}, "patch": [{"op": "copy", "from": "/a", "path": "/b"}]}],
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(2, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 2);
+        let expected = Variant::deserialize(json!({"user": {"name": "bob", "id": 42}, "tags": ["new"]})).unwrap();
+        assert_eq!(msgs[0]["payload"], expected);
+        assert_eq!(msgs[1]["payload"], Variant::deserialize(json!({"a": 1, "b": 1})).unwrap());
+        assert!(!msgs[1].contains("patch"));
+    }
+
+    #[tokio::test]
+    async fn test_failed_test_op_should_be_caught() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "jsonpatch", "wires": [["2"]],
+                "patch": "[{\"op\": \"replace\", \"path\": \"/v\", \"value\": 2}, {\"op\": \"test\", \"path\": \"/v\", \"value\": 3}]"},
+            {"id": "2", "z": "100", "type": "test-once"},
+            {"id": "3", "z": "100", "type": "catch", "wires": [["2"]]}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": {"v": 1}}]])).unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0]["payload"], Variant::deserialize(json!({"v": 1})).unwrap());
+        let error_message = msgs[0].get_nav_stripped("error.message").unwrap().as_str().unwrap();
+        assert!(error_message.contains("Test failed at '/v'"));
+    }
+}
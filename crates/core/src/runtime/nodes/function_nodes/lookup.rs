@@ -0,0 +1,277 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::runtime::context::ContextKeyRef;
+use crate::runtime::eval;
+use crate::runtime::flow::Flow;
+use crate::runtime::model::json::{typed_constant, RedNodeConfig};
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+#[derive(Debug, Clone, Deserialize, RedNodeConfig)]
+struct LookupNodeConfig {
+    /// The property holding the key
+    #[serde(default = "property_default")]
+    property: String,
+
+    /// The property to write the value to, the key is replaced if it is absent
+    #[serde(default, rename = "outputProperty")]
+    output_property: Option<String>,
+
+    /// The table object, or its JSON text or context key according to the `tableType`
+    #[serde(default)]
+    #[red_typed]
+    table: serde_json::Value,
+
+    #[serde(default = "table_type_default", rename = "tableType")]
+    table_type: RedPropertyType,
+
+    /// The value for the keys not in the table, the messages are passed unchanged if it is absent
+    #[serde(default, rename = "default")]
+    #[red_typed]
+    default_value: Option<Variant>,
+
+    #[serde(default, rename = "defaultType")]
+    default_type: RedPropertyType,
+}
+
+fn property_default() -> String {
+    "payload".to_string()
+}
+
+fn table_type_default() -> RedPropertyType {
+    RedPropertyType::Json
+}
+
+#[derive(Debug)]
+enum LookupTable {
+    Static(VariantObjectMap),
+
+    /// Loaded from the `flow` or `global` context for every message, so it can be updated at runtime
+    Context(RedPropertyType, ContextKeyRef),
+}
+
+/// Translates the property through a key to value table, e.g. the device IDs to their names.
+#[derive(Debug)]
+#[flow_node("lookup")]
+struct LookupNode {
+    base: FlowNode,
+    config: LookupNodeConfig,
+    table: LookupTable,
+}
+
+impl LookupNode {
+    fn build(
+        _flow: &Flow,
+        base_node: FlowNode,
+        config: &RedFlowNodeConfig,
+    ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let mut lookup_config = LookupNodeConfig::from_rest(&config.rest)?;
+        if let Some(default_value) = lookup_config.default_value.take() {
+            lookup_config.default_value = Some(typed_constant(default_value, lookup_config.default_type)?);
+        }
+        let table = match (&lookup_config.table, lookup_config.table_type) {
+            (serde_json::Value::Object(_), _) => LookupTable::Static(to_table(Variant::from(&lookup_config.table))?),
+            // The table without the `tableType`
+            (serde_json::Value::String(text), RedPropertyType::Json) => {
+                let jv: serde_json::Value = serde_json::from_str(text)?;
+                LookupTable::Static(to_table(Variant::deserialize(jv)?)?)
+            }
+            (serde_json::Value::String(key), table_type @ (RedPropertyType::Flow | RedPropertyType::Global)) => {
+                LookupTable::Context(table_type, ContextKeyRef::parse(key)?)
+            }
+            _ => {
+                return Err(EdgelinkError::BadFlowsJson(format!(
+                    "The table of the lookup node must be an object, a JSON text or a flow/global context key, got: \
+                     {} of '{:?}'",
+                    lookup_config.table, lookup_config.table_type
+                ))
+                .into())
+            }
+        };
+        let node = LookupNode { base: base_node, config: lookup_config, table };
+        Ok(Box::new(node))
+    }
+
+    async fn lookup(&self, msg: &mut Msg) -> crate::Result<()> {
+        let key = msg.get_nav_stripped(&self.config.property).and_then(table_key);
+        let found = match &self.table {
+            LookupTable::Static(table) => key.and_then(|k| table.get(&k).cloned()),
+            LookupTable::Context(table_type, ctx_key) => {
+                let ctx = match table_type {
+                    RedPropertyType::Flow => self.flow().map(|x| x.context()),
+                    _ => self.engine().map(|x| x.context()),
+                }
+                .ok_or(EdgelinkError::invalid_operation("The context of the lookup table has been released"))?;
+                let env = [PropexEnv::ExtRef("msg", msg.as_variant())];
+                // The table has not been set yet if it is absent, the failures of the store are not missed
+                match ctx.try_get_one(ctx_key.store.as_deref(), &ctx_key.key, &env).await? {
+                    Some(table) => {
+                        let mut table = to_table(table)?;
+                        key.and_then(|k| table.swap_remove(&k))
+                    }
+                    None => None,
+                }
+            }
+        };
+        let value = match (found, &self.config.default_value) {
+            (Some(value), _) => value,
+            (None, Some(Variant::String(expr))) if !self.config.default_type.is_constant() => {
+                eval::evaluate_node_property(expr, self.config.default_type, Some(self), None, Some(msg)).await?
+            }
+            (None, Some(constant)) => constant.clone(),
+            (None, None) => return Ok(()),
+        };
+        let output = self.config.output_property.as_deref().unwrap_or(&self.config.property);
+        msg.set_nav_stripped(output, value, true)
+    }
+}
+
+fn to_table(table: Variant) -> crate::Result<VariantObjectMap> {
+    match table {
+        Variant::Object(map) => Ok(map),
+        other => {
+            Err(EdgelinkError::InvalidOperation(format!("The lookup table must be an object, got: {:?}", other)).into())
+        }
+    }
+}
+
+/// Converts the key into the property name of the table, e.g. both `1` and `1.0` find the property `"1"`.
+fn table_key(key: &Variant) -> Option<String> {
+    match key {
+        Variant::String(s) => Some(s.clone()),
+        Variant::Number(num) => match num.as_f64() {
+            Some(f) if !num.is_i64() && !num.is_u64() && f.fract() == 0.0 && f.abs() < (1u64 << 53) as f64 => {
+                Some((f as i64).to_string())
+            }
+            _ => Some(num.to_string()),
+        },
+        Variant::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for LookupNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow_concurrent(&self, cancel.child_token(), |node, msg| async move {
+                {
+                    let mut msg_guard = msg.write().await;
+                    node.lookup(&mut msg_guard).await?;
+                }
+                node.fan_out_one(Envelope { port: 0, msg }, cancel.child_token()).await
+            })
+            .await;
+        }
+        wait_uows_completed(self.as_ref()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use itertools::Itertools;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_it_should_translate_string_and_numeric_keys() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "lookup", "table": {"a": "Alpha", "1": "One", "2.5": "Two and a half"},
+                "outputProperty": "name", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"payload": "a"}],
+            ["1", {"payload": 1}],
+            ["1", {"payload": 1.0}],
+            ["1", {"payload": 2.5}],
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(4, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        let names: Vec<&str> = msgs.iter().map(|x| x["name"].as_str().unwrap()).collect();
+        assert_eq!(names, vec!["Alpha", "One", "One", "Two and a half"]);
+        assert_eq!(msgs[1]["payload"], Variant::from(1));
+    }
+
+    #[tokio::test]
+    async fn test_misses_should_get_the_default_or_pass_unchanged() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "lookup", "table": "{\"a\": 1}", "tableType": "json",
+                "default": "-1", "defaultType": "num", "wires": [["3"]]},
+            {"id": "2", "z": "100", "type": "lookup", "table": {"a": 1}, "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"payload": "a"}],
+            ["1", {"payload": "b"}],
+            ["1", {"payload": {"not": "a key"}}],
+            ["2", {"payload": "b", "topic": "no default"}],
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(4, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        let (unchanged, defaulted): (Vec<_>, Vec<_>) = msgs.iter().partition(|x| x.contains("topic"));
+        let payloads: Vec<&Variant> = defaulted.iter().map(|x| &x["payload"]).collect();
+        assert_eq!(payloads, vec![&Variant::from(1), &Variant::from(-1), &Variant::from(-1)]);
+        assert_eq!(unchanged[0]["payload"], Variant::from("b"));
+    }
+
+    #[tokio::test]
+    async fn test_it_should_use_the_table_updated_at_runtime() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "change", "wires": [["2"]],
+                "rules": [{"t": "set", "p": "table", "pt": "flow", "to": "table", "tot": "msg"}]},
+            {"id": "2", "z": "100", "type": "lookup", "table": "table", "tableType": "flow",
+                "default": "unknown", "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        // Every table contains the previous ones, so the outputs do not depend on the order of the lookups and updates
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["2", {"payload": "c"}],
+            ["1", {"payload": "a", "table": {"a": "first"}}],
+            ["1", {"payload": "b", "table": {"a": "first", "b": "second"}}],
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(3, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        let payloads: Vec<&str> = msgs.iter().map(|x| x["payload"].as_str().unwrap()).sorted().collect();
+        assert_eq!(payloads, vec!["first", "second", "unknown"]);
+    }
+
+    #[tokio::test]
+    async fn test_failed_table_store_should_be_reported() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "lookup", "table": "#:(nope)::table", "tableType": "flow",
+                "default": "unknown", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"},
+            {"id": "3", "z": "100", "type": "catch", "wires": [["2"]]}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": "a"}]])).unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0]["payload"], Variant::from("a"));
+        assert!(msgs[0].get_nav_stripped("error.message").unwrap().as_str().unwrap().contains("nope"));
+    }
+}
@@ -1,6 +1,39 @@
+mod assert;
+mod base64;
+mod batch;
+mod cache;
+mod canonicalize;
 mod change;
+mod chunk;
+mod csv;
+mod debounce;
+mod delay;
+mod encrypt;
+mod geo;
+mod histogram;
+mod join;
+mod json;
+mod jsonpatch;
+mod lookup;
+mod normalize;
 mod range;
 mod rbe;
+mod round;
+mod sort;
+mod split;
+mod switch;
+mod template;
+mod threshold;
+mod trigger;
+mod unit_converter;
+mod xml;
+mod yaml;
+
+#[cfg(feature = "arrow")]
+mod to_arrow;
+
+#[cfg(feature = "protobuf")]
+mod protobuf;
 
 #[cfg(feature = "js")]
 mod function;
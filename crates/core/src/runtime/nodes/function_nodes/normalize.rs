@@ -0,0 +1,189 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum CaseFolding {
+    #[default]
+    #[serde(rename = "none", alias = "")]
+    None,
+
+    #[serde(rename = "lower")]
+    Lower,
+
+    #[serde(rename = "upper")]
+    Upper,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum UnicodeForm {
+    #[default]
+    #[serde(rename = "none", alias = "")]
+    None,
+
+    #[serde(rename = "nfc")]
+    Nfc,
+
+    #[serde(rename = "nfkc")]
+    Nfkc,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct NormalizeNodeConfig {
+    /// The message property to normalize, it is left untouched if it is not a string
+    #[serde(default = "property_default")]
+    property: String,
+
+    /// Removes the leading and trailing whitespaces
+    #[serde(default)]
+    trim: bool,
+
+    /// Replaces every run of whitespaces with a single space
+    #[serde(default)]
+    collapse: bool,
+
+    #[serde(rename = "case", default)]
+    case_folding: CaseFolding,
+
+    #[serde(default)]
+    unicode: UnicodeForm,
+}
+
+fn property_default() -> String {
+    "payload".to_string()
+}
+
+impl NormalizeNodeConfig {
+    /// Applies the Unicode normalization first, so the compatibility whitespaces can be collapsed and trimmed.
+    fn normalize(&self, text: &str) -> String {
+        let mut result = match self.unicode {
+            UnicodeForm::None => text.to_string(),
+            UnicodeForm::Nfc => text.nfc().collect(),
+            UnicodeForm::Nfkc => text.nfkc().collect(),
+        };
+        if self.collapse {
+            result = result.split_whitespace().collect::<Vec<_>>().join(" ");
+        } else if self.trim {
+            result = result.trim().to_string();
+        }
+        match self.case_folding {
+            CaseFolding::None => result,
+            CaseFolding::Lower => result.to_lowercase(),
+            CaseFolding::Upper => result.to_uppercase(),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[flow_node("normalize")]
+struct NormalizeNode {
+    base: FlowNode,
+    config: NormalizeNodeConfig,
+}
+
+impl NormalizeNode {
+    fn build(
+        _flow: &Flow,
+        base_node: FlowNode,
+        config: &RedFlowNodeConfig,
+    ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let normalize_config = NormalizeNodeConfig::deserialize(&config.rest)?;
+        let node = NormalizeNode { base: base_node, config: normalize_config };
+        Ok(Box::new(node))
+    }
+
+    fn do_normalize(&self, msg: &mut Msg) {
+        if let Some(Variant::String(text)) = msg.get_nav_stripped_mut(&self.config.property) {
+            *text = self.config.normalize(text);
+        }
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for NormalizeNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow_concurrent(&self, cancel.child_token(), |node, msg| async move {
+                {
+                    let mut msg_guard = msg.write().await;
+                    node.do_normalize(&mut msg_guard);
+                }
+                node.fan_out_one(Envelope { port: 0, msg }, cancel.child_token()).await?;
+                Ok(())
+            })
+            .await;
+        }
+        wait_uows_completed(self.as_ref()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    async fn run_normalize(node_json: serde_json::Value, value: serde_json::Value) -> Variant {
+        let mut node_json = node_json;
+        let node = node_json.as_object_mut().unwrap();
+        node.insert("id".into(), json!("1"));
+        node.insert("z".into(), json!("100"));
+        node.insert("type".into(), json!("normalize"));
+        node.insert("property".into(), json!("data.name"));
+        node.insert("wires".into(), json!([["2"]]));
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            node_json,
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject =
+            Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"data": {"name": value}, "payload": " x "}]])).unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0]["payload"], Variant::from(" x "));
+        msgs[0].get_nav_stripped("data.name").unwrap().clone()
+    }
+
+    #[tokio::test]
+    async fn test_it_should_apply_each_normalization_to_nested_property() {
+        let text = json!("  Hello \t\n  WORLD  ");
+        assert_eq!(run_normalize(json!({"trim": true}), text.clone()).await, Variant::from("Hello \t\n  WORLD"));
+        assert_eq!(run_normalize(json!({"collapse": true}), text.clone()).await, Variant::from("Hello WORLD"));
+        assert_eq!(run_normalize(json!({"case": "lower"}), text.clone()).await, Variant::from("  hello \t\n  world  "));
+        assert_eq!(run_normalize(json!({"case": "upper"}), json!("straße")).await, Variant::from("STRASSE"));
+
+        // "e" followed by the combining acute accent
+        assert_eq!(run_normalize(json!({"unicode": "nfc"}), json!("Cafe\u{301}")).await, Variant::from("Café"));
+        // The full-width letters and the ideographic space
+        assert_eq!(
+            run_normalize(
+                json!({"unicode": "nfkc", "collapse": true, "case": "lower"}),
+                json!("\u{3000}ＡＢＣ\u{3000}１２")
+            )
+            .await,
+            Variant::from("abc 12")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_non_string_values_should_pass_through() {
+        let config = json!({"trim": true, "collapse": true, "case": "upper", "unicode": "nfkc"});
+        assert_eq!(run_normalize(config.clone(), json!(42)).await, Variant::from(42));
+        assert_eq!(run_normalize(config.clone(), json!(null)).await, Variant::Null);
+        let nested = json!({"inner": " keep me "});
+        assert_eq!(run_normalize(config, nested.clone()).await, Variant::deserialize(nested).unwrap());
+    }
+}
@@ -0,0 +1,153 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use prost_reflect::{DescriptorPool, MessageDescriptor};
+use serde::Deserialize;
+
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+#[derive(Debug, Clone, Deserialize)]
+struct ProtobufNodeConfig {
+    /// The `.proto` file, or the binary `FileDescriptorSet` generated by `protoc --descriptor_set_out`
+    protofile: String,
+
+    /// The full name of the message type, e.g. `package.Message`
+    #[serde(rename = "protoType")]
+    proto_type: String,
+
+    #[serde(default = "protobuf_property_default")]
+    property: String,
+}
+
+fn protobuf_property_default() -> String {
+    "payload".to_string()
+}
+
+/// Encodes an object in the message into the Protobuf wire format, or decodes the Protobuf bytes into an object.
+#[derive(Debug)]
+#[flow_node("protobuf")]
+struct ProtobufNode {
+    base: FlowNode,
+    config: ProtobufNodeConfig,
+    desc: MessageDescriptor,
+}
+
+impl ProtobufNode {
+    fn build(
+        _flow: &Flow,
+        base_node: FlowNode,
+        config: &RedFlowNodeConfig,
+    ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let protobuf_config = ProtobufNodeConfig::deserialize(&config.rest)?;
+        let pool = load_descriptor_pool(Path::new(&protobuf_config.protofile))
+            .with_context(|| format!("Failed to load the Protobuf schema '{}'", protobuf_config.protofile))?;
+        let desc = pool.get_message_by_name(&protobuf_config.proto_type).ok_or_else(|| {
+            EdgelinkError::BadFlowsJson(format!(
+                "Cannot find the message type '{}' in '{}'",
+                protobuf_config.proto_type, protobuf_config.protofile
+            ))
+        })?;
+        let node = ProtobufNode { base: base_node, config: protobuf_config, desc };
+        Ok(Box::new(node))
+    }
+
+    async fn convert(&self, msg: MsgHandle) -> crate::Result<()> {
+        let mut msg_guard = msg.write().await;
+        let value = msg_guard.get_nav_stripped(&self.config.property).ok_or(EdgelinkError::InvalidOperation(
+            format!("Cannot get the property 'msg.{}'", self.config.property),
+        ))?;
+        let converted = match value {
+            Variant::Bytes(bytes) => Variant::from_protobuf_bytes(bytes, &self.desc)?,
+            Variant::Object(_) => Variant::Bytes(value.to_protobuf_bytes(&self.desc)?),
+            _ => {
+                return Err(EdgelinkError::InvalidOperation(format!(
+                    "The property 'msg.{}' must be bytes to decode or an object to encode",
+                    self.config.property
+                ))
+                .into())
+            }
+        };
+        msg_guard.set_nav_stripped(&self.config.property, converted, true)
+    }
+}
+
+fn load_descriptor_pool(path: &Path) -> crate::Result<DescriptorPool> {
+    if path.extension().is_some_and(|x| x == "proto") {
+        // The imports are resolved relative to the directory of the `.proto` file
+        let include = path.parent().unwrap_or(Path::new(""));
+        let file = path.file_name().ok_or(EdgelinkError::BadArgument("protofile"))?;
+        let file_set = protox::compile([file], [include])?;
+        Ok(DescriptorPool::from_file_descriptor_set(file_set)?)
+    } else {
+        let bytes = std::fs::read(path)?;
+        Ok(DescriptorPool::decode(bytes.as_slice())?)
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for ProtobufNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow_concurrent(&self, cancel.child_token(), |node, msg| async move {
+                node.convert(msg.clone()).await?;
+                node.fan_out_one(Envelope { port: 0, msg }, cancel.child_token()).await
+            })
+            .await;
+        }
+        wait_uows_completed(self.as_ref()).await;
+
+        log::debug!("ProtobufNode process() task has been terminated.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_it_should_encode_and_decode_with_proto_file() {
+        let dir = std::env::temp_dir().join(format!("edgelink-protobuf-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let proto_path = dir.join("sensor.proto");
+        std::fs::write(
+            &proto_path,
+            r#"
+            syntax = "proto3";
+            package test;
+            message Reading { string unit = 1; double value = 2; }
+            message Sensor { string id = 1; repeated Reading readings = 2; }
+            "#,
+        )
+        .unwrap();
+        let protofile = proto_path.to_str().unwrap();
+
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "protobuf", "protofile": protofile, "protoType": "test.Sensor",
+                "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "protobuf", "protofile": protofile, "protoType": "test.Sensor",
+                "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let sensor = json!({"id": "s1", "readings": [{"unit": "C", "value": 21.5}, {"unit": "%", "value": 40.0}]});
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": sensor}]])).unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0]["payload"], Variant::deserialize(sensor).unwrap());
+    }
+}
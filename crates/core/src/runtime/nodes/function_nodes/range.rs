@@ -139,7 +139,7 @@ impl FlowNodeBehavior for RangeNode {
     async fn run(self: Arc<Self>, stop_token: CancellationToken) {
         while !stop_token.is_cancelled() {
             let cancel = stop_token.child_token();
-            with_uow(self.as_ref(), cancel.child_token(), |node, msg| async move {
+            with_uow_concurrent(&self, cancel.child_token(), |node, msg| async move {
                 {
                     let mut msg_guard = msg.write().await;
                     node.do_range(&mut msg_guard)?;
@@ -149,5 +149,6 @@ impl FlowNodeBehavior for RangeNode {
             })
             .await;
         }
+        wait_uows_completed(self.as_ref()).await;
     }
 }
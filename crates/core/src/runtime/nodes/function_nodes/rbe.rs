@@ -201,9 +201,8 @@ impl RbeNode {
                 };
             } else {
                 let num_value = match value {
-                    Variant::Number(v) => v.as_f64().unwrap(), // FIXME
                     Variant::String(s) => parsing::parse_float_lossy::<f64>(s).unwrap_or(f64::NAN),
-                    _ => f64::NAN,
+                    _ => value.as_f64().unwrap_or(f64::NAN),
                 };
 
                 if num_value.is_nan() {
@@ -296,6 +295,86 @@ impl FlowNodeBehavior for RbeNode {
             .await;
         }
 
-        log::debug!("DebugNode process() task has been terminated.");
+        log::debug!("RbeNode process() task has been terminated.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn inject_payloads(payloads: serde_json::Value) -> Vec<(ElementId, Msg)> {
+        Vec::<(ElementId, Msg)>::deserialize(payloads).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_it_should_only_pass_the_changed_payloads() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "rbe", "func": "rbe", "gap": "", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = inject_payloads(json!([
+            ["1", {"payload": "a"}],
+            ["1", {"payload": "a"}],
+            ["1", {"payload": {"b": 1}}],
+            ["1", {"payload": {"b": 1}}],
+            ["1", {"payload": "a"}],
+        ]));
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(3, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 3);
+        assert_eq!(msgs[0]["payload"], Variant::from("a"));
+        assert_eq!(msgs[1]["payload"], Variant::from([("b", Variant::from(1))]));
+        assert_eq!(msgs[2]["payload"], Variant::from("a"));
+    }
+
+    #[tokio::test]
+    async fn test_topics_should_be_tracked_separately() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "rbe", "func": "rbe", "gap": "", "septopics": true, "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = inject_payloads(json!([
+            ["1", {"topic": "t1", "payload": 1}],
+            ["1", {"topic": "t2", "payload": 1}],
+            ["1", {"topic": "t1", "payload": 1}],
+            ["1", {"topic": "t2", "payload": 2}],
+        ]));
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(3, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        let passed: Vec<(&str, i64)> =
+            msgs.iter().map(|x| (x["topic"].as_str().unwrap(), x["payload"].as_i64().unwrap())).collect();
+        assert_eq!(passed, vec![("t1", 1), ("t2", 1), ("t2", 2)]);
+    }
+
+    #[tokio::test]
+    async fn test_deadband_should_only_pass_the_changes_exceeding_the_gap() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "rbe", "func": "deadband", "gap": "5", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = inject_payloads(json!([
+            ["1", {"payload": 10}],
+            ["1", {"payload": 14}],
+            ["1", {"payload": 15}],
+            ["1", {"payload": 16}],
+            ["1", {"payload": "22.5"}],
+        ]));
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(3, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 3);
+        assert_eq!(msgs[0]["payload"], Variant::from(10));
+        assert_eq!(msgs[1]["payload"], Variant::from(16));
+        assert_eq!(msgs[2]["payload"], Variant::from("22.5"));
     }
 }
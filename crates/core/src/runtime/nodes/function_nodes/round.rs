@@ -0,0 +1,215 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+/// The maximum absolute decimal places, `10^22` is the largest power of ten an `f64` holds exactly
+const MAX_PLACES: i32 = 22;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum RoundMode {
+    /// Rounds half away from zero
+    #[default]
+    #[serde(rename = "round")]
+    Round,
+
+    #[serde(rename = "floor")]
+    Floor,
+
+    #[serde(rename = "ceil")]
+    Ceil,
+
+    #[serde(rename = "trunc")]
+    Trunc,
+}
+
+impl RoundMode {
+    fn apply(&self, value: f64) -> f64 {
+        match self {
+            RoundMode::Round => value.round(),
+            RoundMode::Floor => value.floor(),
+            RoundMode::Ceil => value.ceil(),
+            RoundMode::Trunc => value.trunc(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RoundNodeConfig {
+    #[serde(default = "property_default")]
+    property: String,
+
+    #[serde(default)]
+    mode: RoundMode,
+
+    /// The decimal places to keep, the negative places round to tens, hundreds and so on
+    #[serde(default, deserialize_with = "json::deser::str_to_option_f64")]
+    places: Option<f64>,
+}
+
+fn property_default() -> String {
+    "payload".to_string()
+}
+
+/// Rounds the number to the decimal places, the result is an integer if `places` is not positive.
+fn round_number(num: &serde_json::Number, mode: RoundMode, places: i32) -> crate::Result<Variant> {
+    if !num.is_f64() && places >= 0 {
+        // An integer has no fractional digits to round
+        return Ok(Variant::Number(num.clone()));
+    }
+    let value = num.as_f64().ok_or(EdgelinkError::BadArgument("num"))?;
+    let rounded = if places >= 0 {
+        let scale = 10f64.powi(places);
+        mode.apply(value * scale) / scale
+    } else {
+        let scale = 10f64.powi(-places);
+        mode.apply(value / scale) * scale
+    };
+    if !rounded.is_finite() {
+        return Err(EdgelinkError::OutOfRange).with_context(|| format!("Cannot round the number: {}", num));
+    }
+    if places <= 0 && rounded.abs() < i64::MAX as f64 {
+        Ok(Variant::from(rounded as i64))
+    } else {
+        Ok(Variant::from(rounded))
+    }
+}
+
+#[derive(Debug)]
+#[flow_node("round")]
+struct RoundNode {
+    base: FlowNode,
+    config: RoundNodeConfig,
+    places: i32,
+}
+
+impl RoundNode {
+    fn build(
+        _flow: &Flow,
+        base_node: FlowNode,
+        config: &RedFlowNodeConfig,
+    ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let round_config = RoundNodeConfig::deserialize(&config.rest)?;
+        let places = round_config.places.unwrap_or(0.0);
+        if places.fract() != 0.0 || places.abs() > MAX_PLACES as f64 {
+            return Err(EdgelinkError::BadFlowsJson(format!(
+                "The decimal places of the round node must be an integer in [-{}, {}], got: {}",
+                MAX_PLACES, MAX_PLACES, places
+            ))
+            .into());
+        }
+        let node = RoundNode { base: base_node, config: round_config, places: places as i32 };
+        Ok(Box::new(node))
+    }
+
+    fn do_round(&self, msg: &mut Msg) -> crate::Result<()> {
+        let value = msg.get_nav_stripped_mut(&self.config.property).ok_or(EdgelinkError::InvalidOperation(format!(
+            "Cannot find the property 'msg.{}'",
+            self.config.property
+        )))?;
+        match &*value {
+            Variant::Number(num) => {
+                *value = round_number(num, self.config.mode, self.places)?;
+                Ok(())
+            }
+            other => Err(EdgelinkError::InvalidOperation(format!(
+                "The property 'msg.{}' is not a number: {:?}",
+                self.config.property, other
+            ))
+            .into()),
+        }
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for RoundNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow_concurrent(&self, cancel.child_token(), |node, msg| async move {
+                {
+                    let mut msg_guard = msg.write().await;
+                    node.do_round(&mut msg_guard)?;
+                }
+                node.fan_out_one(Envelope { port: 0, msg }, cancel.child_token()).await?;
+                Ok(())
+            })
+            .await;
+        }
+        wait_uows_completed(self.as_ref()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    fn round_f64(value: f64, mode: RoundMode, places: i32) -> Variant {
+        round_number(&serde_json::Number::from_f64(value).unwrap(), mode, places).unwrap()
+    }
+
+    #[test]
+    fn test_round_modes_with_positive_places() {
+        assert_eq!(round_f64(3.14159, RoundMode::Round, 2), Variant::from(3.14));
+        assert_eq!(round_f64(2.5, RoundMode::Round, 0), Variant::from(3));
+        assert_eq!(round_f64(-2.5, RoundMode::Round, 0), Variant::from(-3));
+        assert_eq!(round_f64(3.14159, RoundMode::Floor, 3), Variant::from(3.141));
+        assert_eq!(round_f64(-3.14159, RoundMode::Floor, 1), Variant::from(-3.2));
+        assert_eq!(round_f64(3.14159, RoundMode::Ceil, 1), Variant::from(3.2));
+        assert_eq!(round_f64(-3.19, RoundMode::Ceil, 1), Variant::from(-3.1));
+        assert_eq!(round_f64(3.19, RoundMode::Trunc, 1), Variant::from(3.1));
+        assert_eq!(round_f64(-3.19, RoundMode::Trunc, 1), Variant::from(-3.1));
+        assert_eq!(round_number(&serde_json::Number::from(42), RoundMode::Floor, 2).unwrap(), Variant::from(42));
+    }
+
+    #[test]
+    fn test_round_modes_with_negative_places() {
+        assert_eq!(round_f64(1234.5, RoundMode::Round, -1), Variant::from(1230));
+        assert_eq!(round_f64(1250.0, RoundMode::Round, -2), Variant::from(1300));
+        assert_eq!(round_f64(1299.0, RoundMode::Floor, -2), Variant::from(1200));
+        assert_eq!(round_f64(1201.0, RoundMode::Ceil, -2), Variant::from(1300));
+        assert_eq!(round_f64(-1299.0, RoundMode::Trunc, -2), Variant::from(-1200));
+        assert_eq!(round_number(&serde_json::Number::from(1234), RoundMode::Round, -3).unwrap(), Variant::from(1000));
+    }
+
+    #[tokio::test]
+    async fn test_it_should_round_property_and_keep_it_a_number() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "round", "property": "data.value", "mode": "floor", "places": "1",
+                "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"},
+            {"id": "3", "z": "100", "type": "catch", "wires": [["2"]]}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"data": {"value": 21.987}}],
+            ["1", {"data": {"value": "21.987"}}],
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(2, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0].get_nav_stripped("data.value"), Some(&Variant::from(21.9)));
+        assert!(msgs[1].get_nav_stripped("error.message").is_some());
+    }
+
+    #[test]
+    fn test_bad_places_should_fail_to_build() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "round", "places": 1.5, "wires": []}
+        ]);
+        assert!(crate::runtime::engine::build_test_engine(flows_json).is_err());
+    }
+}
@@ -0,0 +1,501 @@
+use std::cmp::Ordering;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+/// The max number of the messages buffered for the incomplete sequences, like the `nodeMaxMessageBufferLength` of
+/// Node-RED, all of them are dropped once it is exceeded
+const MAX_PENDING_MSGS: usize = 10_000;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum SortTargetType {
+    /// Sorts the array of the message property
+    #[default]
+    #[serde(rename = "msg")]
+    Msg,
+
+    /// Sorts the messages of a sequence
+    #[serde(rename = "seq")]
+    Seq,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum SortKeyType {
+    /// Sorts by the array elements themselves
+    #[serde(rename = "elem")]
+    Elem,
+
+    /// Sorts by a property of the messages of a sequence
+    #[serde(rename = "msg")]
+    Msg,
+
+    #[serde(rename = "jsonata")]
+    Jsonata,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum SortOrder {
+    #[default]
+    #[serde(rename = "ascending", alias = "asc")]
+    Ascending,
+
+    #[serde(rename = "descending", alias = "desc")]
+    Descending,
+}
+
+impl SortOrder {
+    fn apply(&self, ord: Ordering) -> Ordering {
+        match self {
+            SortOrder::Ascending => ord,
+            SortOrder::Descending => ord.reverse(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct SortNodeConfig {
+    /// The property holding the array to sort
+    #[serde(default = "property_default")]
+    target: String,
+
+    #[serde(default, rename = "targetType")]
+    target_type: SortTargetType,
+
+    /// The key of the array elements
+    #[serde(default, rename = "msgKey")]
+    msg_key: String,
+
+    #[serde(default = "msg_key_type_default", rename = "msgKeyType")]
+    msg_key_type: SortKeyType,
+
+    /// The key of the messages of a sequence
+    #[serde(default = "property_default", rename = "seqKey")]
+    seq_key: String,
+
+    #[serde(default = "seq_key_type_default", rename = "seqKeyType")]
+    seq_key_type: SortKeyType,
+
+    #[serde(default)]
+    order: SortOrder,
+
+    /// Compares the keys as numbers, e.g. `"10"` is after `"9"`
+    #[serde(default, deserialize_with = "json::deser::deser_bool_or_str")]
+    as_num: bool,
+}
+
+fn property_default() -> String {
+    "payload".to_string()
+}
+
+fn msg_key_type_default() -> SortKeyType {
+    SortKeyType::Elem
+}
+
+fn seq_key_type_default() -> SortKeyType {
+    SortKeyType::Msg
+}
+
+#[derive(Debug)]
+enum SortKey {
+    Elem,
+
+    /// The propex path in the element or the message
+    Path(String),
+}
+
+impl SortKey {
+    fn new(key: &str, key_type: SortKeyType) -> crate::Result<Self> {
+        let key = key.trim();
+        match key_type {
+            SortKeyType::Elem => Ok(SortKey::Elem),
+            SortKeyType::Msg => {
+                propex::parse(key).with_context(|| format!("Bad key of the sort node: '{}'", key))?;
+                Ok(SortKey::Path(key.to_string()))
+            }
+            // Only the expressions of a plain property path are supported since we do not have a JSONata engine
+            SortKeyType::Jsonata => match propex::parse(key) {
+                Ok(_) => Ok(SortKey::Path(key.to_string())),
+                Err(_) => Err(EdgelinkError::NotSupported(format!(
+                    "Only the JSONata expressions of a property path are supported by the sort node, got: '{}'",
+                    key
+                ))
+                .into()),
+            },
+        }
+    }
+
+    fn get<'a>(&self, elem: &'a Variant) -> Option<&'a Variant> {
+        match self {
+            SortKey::Elem => Some(elem),
+            SortKey::Path(path) => elem.get_nav(path, &[]),
+        }
+    }
+
+    fn get_from_msg<'a>(&self, msg: &'a Msg) -> Option<&'a Variant> {
+        match self {
+            SortKey::Elem => msg.get("payload"),
+            SortKey::Path(path) => msg.get_nav_stripped(path),
+        }
+    }
+}
+
+/// The comparable form of a key, the numbers go first, then the dates and the strings in the ascending order, the
+/// others go last in their original order for both orders.
+#[derive(Debug)]
+enum SortValue {
+    Number(f64),
+    Date(SystemTime),
+    String(String),
+    Other,
+}
+
+impl SortValue {
+    fn new(value: Option<&Variant>, as_num: bool) -> Self {
+        let value = match value {
+            Some(value) if as_num => value.coerce_number(),
+            Some(value) => Some(value.clone()),
+            None => None,
+        };
+        match value {
+            Some(Variant::Number(num)) => num.as_f64().map(SortValue::Number).unwrap_or(SortValue::Other),
+            Some(Variant::Date(date)) => SortValue::Date(date),
+            Some(Variant::String(s)) => SortValue::String(s),
+            _ => SortValue::Other,
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            SortValue::Number(_) => 0,
+            SortValue::Date(_) => 1,
+            SortValue::String(_) => 2,
+            SortValue::Other => 3,
+        }
+    }
+
+    fn compare(&self, other: &Self, order: SortOrder) -> Ordering {
+        match (self, other) {
+            (SortValue::Other, _) | (_, SortValue::Other) => self.rank().cmp(&other.rank()),
+            (SortValue::Number(a), SortValue::Number(b)) => order.apply(a.total_cmp(b)),
+            (SortValue::Date(a), SortValue::Date(b)) => order.apply(a.cmp(b)),
+            (SortValue::String(a), SortValue::String(b)) => order.apply(a.cmp(b)),
+            _ => order.apply(self.rank().cmp(&other.rank())),
+        }
+    }
+}
+
+/// Sorts the array of a message property, or the messages of a sequence. The sort is stable in both orders.
+#[derive(Debug)]
+#[flow_node("sort")]
+struct SortNode {
+    base: FlowNode,
+    config: SortNodeConfig,
+    key: SortKey,
+    tracker: Mutex<SequenceTracker<MsgHandle>>,
+}
+
+impl SortNode {
+    fn build(
+        _flow: &Flow,
+        base_node: FlowNode,
+        config: &RedFlowNodeConfig,
+    ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let sort_config = SortNodeConfig::deserialize(&config.rest)?;
+        let key = match sort_config.target_type {
+            SortTargetType::Msg => {
+                propex::parse(&sort_config.target)
+                    .with_context(|| format!("Bad target of the sort node: '{}'", sort_config.target))?;
+                SortKey::new(&sort_config.msg_key, sort_config.msg_key_type)?
+            }
+            SortTargetType::Seq if sort_config.seq_key_type == SortKeyType::Elem => {
+                return Err(EdgelinkError::BadFlowsJson(
+                    "The messages of a sequence cannot be sorted by `elem`".into(),
+                )
+                .into());
+            }
+            SortTargetType::Seq => SortKey::new(&sort_config.seq_key, sort_config.seq_key_type)?,
+        };
+        let node =
+            SortNode { base: base_node, config: sort_config, key, tracker: Mutex::new(SequenceTracker::default()) };
+        Ok(Box::new(node))
+    }
+
+    fn sort_array(&self, items: &mut Vec<Variant>) {
+        let mut keyed: Vec<(SortValue, Variant)> = std::mem::take(items)
+            .into_iter()
+            .map(|x| (SortValue::new(self.key.get(&x), self.config.as_num), x))
+            .collect();
+        keyed.sort_by(|a, b| a.0.compare(&b.0, self.config.order));
+        *items = keyed.into_iter().map(|x| x.1).collect();
+    }
+
+    async fn receive(&self, msg: MsgHandle, cancel: CancellationToken) -> crate::Result<()> {
+        match self.config.target_type {
+            SortTargetType::Msg => {
+                {
+                    let mut msg_guard = msg.write().await;
+                    match msg_guard.get_nav_stripped_mut(&self.config.target) {
+                        Some(Variant::Array(items)) => self.sort_array(items),
+                        _ => log::debug!("[SORT:{}] The target is not an array, passed unchanged", self.name()),
+                    }
+                }
+                self.fan_out_one(Envelope { port: 0, msg }, cancel).await
+            }
+            SortTargetType::Seq => self.receive_seq(msg, cancel).await,
+        }
+    }
+
+    /// Buffers the messages until the sequence is complete, then sends them in the order of their keys with the
+    /// `msg.parts.index` renumbered.
+    ///
+    /// All buffered messages are dropped if there are more than `MAX_PENDING_MSGS` of them, so the incomplete
+    /// sequences cannot exhaust the memory.
+    async fn receive_seq(&self, msg: MsgHandle, cancel: CancellationToken) -> crate::Result<()> {
+        let parts = {
+            let msg_guard = msg.read().await;
+            MsgParts::from_msg(&msg_guard)?
+        };
+        let parts = parts.ok_or(EdgelinkError::InvalidOperation("The message is not in a sequence".into()))?;
+
+        let seq = {
+            let mut tracker = self.tracker.lock().await;
+            match tracker.push(&parts, msg, std::time::Instant::now())? {
+                SequenceStatus::Complete(seq) => seq,
+                SequenceStatus::Duplicated(_) => {
+                    log::warn!("[SORT:{}] Dropped a duplicated message of the sequence '{}'", self.name(), parts.id);
+                    return Ok(());
+                }
+                SequenceStatus::Incomplete { .. } if tracker.item_count() > MAX_PENDING_MSGS => {
+                    tracker.clear();
+                    return Err(EdgelinkError::InvalidOperation(format!(
+                        "Too many pending messages, dropped all incomplete sequences of the sort node: more than {}",
+                        MAX_PENDING_MSGS
+                    ))
+                    .into());
+                }
+                SequenceStatus::Incomplete { .. } => return Ok(()),
+            }
+        };
+
+        let mut keyed = Vec::with_capacity(seq.len());
+        for msg in seq.into_iter() {
+            let key = {
+                let msg_guard = msg.read().await;
+                SortValue::new(self.key.get_from_msg(&msg_guard), self.config.as_num)
+            };
+            keyed.push((key, msg));
+        }
+        keyed.sort_by(|a, b| a.0.compare(&b.0, self.config.order));
+
+        for (index, (_, msg)) in keyed.into_iter().enumerate() {
+            {
+                let mut msg_guard = msg.write().await;
+                if let Some(Variant::Object(parts)) = msg_guard.get_mut("parts") {
+                    parts.insert("index".to_string(), Variant::from(index as u64));
+                }
+            }
+            self.fan_out_one(Envelope { port: 0, msg }, cancel.clone()).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for SortNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow(self.as_ref(), cancel.child_token(), |node, msg| async move { node.receive(msg, cancel).await })
+                .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_it_should_sort_arrays_in_both_orders() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "sort", "target": "payload.values", "order": "descending",
+                "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"payload": {"values": [3, 10, 1.5, "b", "a", -2]}}],
+        ]))
+        .unwrap();
+        let msgs = engine.run_once_with_inject(1, Duration::from_millis(200), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0].get_nav("payload.values"), Some(&Variant::from(json!(["b", "a", 10, 3, 1.5, -2]))));
+
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "sort", "order": "ascending", "as_num": true, "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject =
+            Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": ["10", "9", 8, "100"]}]])).unwrap();
+        let msgs = engine.run_once_with_inject(1, Duration::from_millis(200), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0]["payload"], Variant::from(json!([8, "9", "10", "100"])));
+    }
+
+    #[tokio::test]
+    async fn test_equal_keys_should_keep_their_order() {
+        let people = json!([
+            {"name": "a", "age": 30},
+            {"name": "b", "age": 20},
+            {"name": "c", "age": 30},
+            {"name": "d", "age": 20},
+        ]);
+        for (order, expected) in [("ascending", ["b", "d", "a", "c"]), ("descending", ["a", "c", "b", "d"])] {
+            let flows_json = json!([
+                {"id": "100", "type": "tab"},
+                {"id": "1", "z": "100", "type": "sort", "msgKey": "age", "msgKeyType": "jsonata", "order": order,
+                    "wires": [["2"]]},
+                {"id": "2", "z": "100", "type": "test-once"}
+            ]);
+            let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+            let msgs_to_inject =
+                Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": people.clone()}]])).unwrap();
+            let msgs = engine.run_once_with_inject(1, Duration::from_millis(200), msgs_to_inject).await.unwrap();
+            let people = msgs[0]["payload"].as_array().unwrap();
+            let names: Vec<&str> = people.iter().map(|x| x.get_nav("name", &[]).unwrap().as_str().unwrap()).collect();
+            assert_eq!(names, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_it_should_sort_and_reassemble_sequences() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "sort", "targetType": "seq", "seqKey": "payload", "seqKeyType": "msg",
+                "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"payload": 3, "parts": {"id": "s1", "index": 2, "count": 3}}],
+            ["1", {"payload": 1, "parts": {"id": "s2", "index": 0, "count": 2}}],
+            ["1", {"payload": 5, "parts": {"id": "s1", "index": 0, "count": 3}}],
+            ["1", {"payload": 4, "parts": {"id": "s1", "index": 1, "count": 3}}],
+            ["1", {"payload": 0, "parts": {"id": "s2", "index": 1, "count": 2}}],
+        ]))
+        .unwrap();
+        let msgs = engine.run_once_with_inject(5, Duration::from_millis(200), msgs_to_inject).await.unwrap();
+        let sorted: Vec<(&str, i64, i64)> = msgs
+            .iter()
+            .map(|x| {
+                let (id, index) = (x.get_nav("parts.id").unwrap(), x.get_nav("parts.index").unwrap());
+                (id.as_str().unwrap(), index.as_i64().unwrap(), x["payload"].as_i64().unwrap())
+            })
+            .collect();
+        assert_eq!(sorted, vec![("s1", 0, 3), ("s1", 1, 4), ("s1", 2, 5), ("s2", 0, 0), ("s2", 1, 1)]);
+        assert!(msgs.iter().all(|x| x.get_nav("parts.count").is_some()));
+    }
+
+    #[test]
+    fn test_sort_values_should_compare_numbers_dates_and_strings() {
+        let epoch = SystemTime::UNIX_EPOCH;
+        let mut values = [
+            Variant::from("b"),
+            Variant::Null,
+            Variant::Date(epoch + Duration::from_secs(10)),
+            Variant::from(2.5),
+            Variant::Date(epoch),
+            Variant::from("a"),
+            Variant::from(-1),
+        ];
+        let sort = |values: &mut [Variant], order: SortOrder| {
+            values.sort_by(|a, b| SortValue::new(Some(a), false).compare(&SortValue::new(Some(b), false), order))
+        };
+        sort(&mut values, SortOrder::Ascending);
+        assert_eq!(
+            values,
+            [
+                Variant::from(-1),
+                Variant::from(2.5),
+                Variant::Date(epoch),
+                Variant::Date(epoch + Duration::from_secs(10)),
+                Variant::from("a"),
+                Variant::from("b"),
+                Variant::Null,
+            ]
+        );
+
+        // The values which cannot be compared stay last in their original order
+        let mut values = [Variant::Null, Variant::from(1), Variant::from(false), Variant::from("a"), Variant::from(3)];
+        sort(&mut values, SortOrder::Descending);
+        assert_eq!(
+            values,
+            [Variant::from("a"), Variant::from(3), Variant::from(1), Variant::Null, Variant::from(false)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_other_values_should_go_last_in_descending_sequences() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "sort", "targetType": "seq", "seqKey": "payload", "seqKeyType": "msg",
+                "order": "descending", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"payload": null, "parts": {"id": "s1", "index": 0, "count": 4}}],
+            ["1", {"payload": 1, "parts": {"id": "s1", "index": 1, "count": 4}}],
+            ["1", {"payload": {"x": 1}, "parts": {"id": "s1", "index": 2, "count": 4}}],
+            ["1", {"payload": 2, "parts": {"id": "s1", "index": 3, "count": 4}}],
+        ]))
+        .unwrap();
+        let msgs = engine.run_once_with_inject(4, Duration::from_millis(200), msgs_to_inject).await.unwrap();
+        let payloads: Vec<&Variant> = msgs.iter().map(|x| &x["payload"]).collect();
+        assert_eq!(payloads, [&Variant::from(2), &Variant::from(1), &Variant::Null, &Variant::from(json!({"x": 1}))]);
+    }
+
+    #[tokio::test]
+    async fn test_too_many_pending_msgs_should_be_dropped() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "sort", "targetType": "seq", "seqKey": "payload", "seqKeyType": "msg",
+                "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"},
+            {"id": "3", "z": "100", "type": "catch", "wires": [["2"]]}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let mut msgs_to_inject: Vec<(ElementId, Msg)> = (0..=MAX_PENDING_MSGS)
+            .map(|i| {
+                Deserialize::deserialize(
+                    json!(["1", {"payload": i, "parts": {"id": format!("s{}", i), "index": 0, "count": 2}}]),
+                )
+                .unwrap()
+            })
+            .collect();
+        // The first sequence has been dropped, so it stays incomplete
+        msgs_to_inject.push(
+            Deserialize::deserialize(json!(["1", {"payload": -1, "parts": {"id": "s0", "index": 1, "count": 2}}]))
+                .unwrap(),
+        );
+        let msgs = engine.run_once_with_inject(1, Duration::from_secs(5), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0]["payload"], Variant::from(MAX_PENDING_MSGS as u64));
+        assert!(msgs[0].get_nav_stripped("error.message").unwrap().as_str().unwrap().contains("Too many"));
+    }
+}
@@ -0,0 +1,655 @@
+use std::sync::Arc;
+
+use regex::Regex;
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+#[derive(Debug, Clone, Deserialize)]
+struct SplitNodeConfig {
+    #[serde(default = "split_property_default")]
+    property: String,
+
+    /// Copies the key of each property into this message property when splitting an object, e.g. `topic`
+    #[serde(default)]
+    addname: String,
+
+    /// The delimiter of strings and buffers, interpreted according to `spltType`
+    #[serde(default = "splt_default")]
+    splt: String,
+
+    #[serde(rename = "spltType", default)]
+    splt_type: SplitType,
+
+    /// Emits the text of the capturing groups as the parts too, like `String.prototype.split()` in JavaScript
+    #[serde(rename = "regexCaptures", default)]
+    regex_captures: bool,
+
+    /// The length of the chunks of arrays, each element is sent alone if it is `1`
+    #[serde(rename = "arraySplt", default, deserialize_with = "json::deser::str_to_option_usize")]
+    array_splt: Option<usize>,
+
+    #[serde(rename = "arraySpltType", default)]
+    array_splt_type: ArraySplitType,
+
+    /// Treats the strings and buffers as a stream, the incomplete last part is prepended to the next message
+    #[serde(default)]
+    stream: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum SplitType {
+    #[default]
+    #[serde(rename = "str")]
+    Str,
+
+    #[serde(rename = "regex")]
+    Regex,
+
+    /// The delimiter is a JSON array of bytes, e.g. `[13, 10]`
+    #[serde(rename = "bin")]
+    Bin,
+
+    /// Splits into the chunks of a fixed length
+    #[serde(rename = "len")]
+    Len,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum ArraySplitType {
+    #[default]
+    #[serde(rename = "len")]
+    Len,
+}
+
+fn split_property_default() -> String {
+    "payload".to_string()
+}
+
+fn splt_default() -> String {
+    "\\n".to_string()
+}
+
+/// The resolved `splt` of the configuration.
+#[derive(Debug)]
+enum Delimiter {
+    Str(String),
+    Regex(Regex),
+    Bin(Vec<u8>),
+    Len(usize),
+}
+
+/// The `msg.parts` properties shared by the messages of a sequence.
+struct SequenceInfo {
+    id: String,
+    kind: &'static str,
+    ch: Option<Variant>,
+    len: Option<usize>,
+    first_index: usize,
+    /// The count of a stream is unknown
+    count: Option<usize>,
+}
+
+/// The state of the stream mode, all the messages split from the stream are in one sequence.
+#[derive(Debug, Default)]
+struct StreamState {
+    id: Option<String>,
+    next_index: usize,
+    /// The incomplete last part of the previous message, either a string or bytes
+    remainder: Option<Variant>,
+}
+
+#[derive(Debug)]
+#[flow_node("split")]
+struct SplitNode {
+    base: FlowNode,
+    config: SplitNodeConfig,
+    delimiter: Delimiter,
+    array_len: usize,
+    stream_state: Mutex<StreamState>,
+}
+
+impl SplitNode {
+    fn build(
+        _flow: &Flow,
+        base_node: FlowNode,
+        config: &RedFlowNodeConfig,
+    ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let split_config = SplitNodeConfig::deserialize(&config.rest)?;
+        let delimiter = match split_config.splt_type {
+            SplitType::Str => {
+                Delimiter::Str(split_config.splt.replace("\\n", "\n").replace("\\r", "\r").replace("\\t", "\t"))
+            }
+            SplitType::Regex => Delimiter::Regex(Regex::new(&split_config.splt).map_err(|e| {
+                EdgelinkError::BadFlowsJson(format!("Bad regular expression of the split node: {}", e))
+            })?),
+            SplitType::Bin => Delimiter::Bin(serde_json::from_str::<Vec<u8>>(&split_config.splt).map_err(|e| {
+                EdgelinkError::BadFlowsJson(format!("The binary delimiter must be an array of bytes: {}", e))
+            })?),
+            SplitType::Len => match split_config.splt.trim().parse::<usize>() {
+                Ok(len) if len > 0 => Delimiter::Len(len),
+                _ => {
+                    return Err(EdgelinkError::BadFlowsJson(format!(
+                        "The length of the split node must be a positive integer, got: '{}'",
+                        split_config.splt
+                    ))
+                    .into())
+                }
+            },
+        };
+        let array_len = match split_config.array_splt_type {
+            ArraySplitType::Len => split_config.array_splt.unwrap_or(1),
+        };
+        if array_len == 0 {
+            return Err(EdgelinkError::BadFlowsJson("The array length of the split node cannot be 0".into()).into());
+        }
+        let node = SplitNode {
+            base: base_node,
+            config: split_config,
+            delimiter,
+            array_len,
+            stream_state: Mutex::new(StreamState::default()),
+        };
+        Ok(Box::new(node))
+    }
+
+    async fn receive(&self, msg: MsgHandle, cancel: CancellationToken) -> crate::Result<()> {
+        let msgs_to_send = {
+            let msg_guard = msg.read().await;
+            match msg_guard.get_nav_stripped(&self.config.property) {
+                Some(Variant::Object(map)) => self.split_object(&msg_guard, map)?,
+                Some(Variant::Array(items)) => self.split_array(&msg_guard, items)?,
+                Some(Variant::String(text)) => self.split_string(&msg_guard, text).await?,
+                Some(Variant::Bytes(bytes)) => self.split_bytes(&msg_guard, bytes).await?,
+                Some(other) => {
+                    return Err(EdgelinkError::NotSupported(format!(
+                        "The split node only splits objects, arrays, strings and buffers, got: {:?}",
+                        other
+                    ))
+                    .into())
+                }
+                // Nothing to split, pass it through
+                None => vec![msg.clone()],
+            }
+        };
+
+        // Sends one by one to keep the sequence in order
+        for msg in msgs_to_send.into_iter() {
+            self.fan_out_one(Envelope { port: 0, msg }, cancel.clone()).await?;
+        }
+        Ok(())
+    }
+
+    /// Emits one message per property, in the insertion order of the object like `Object.keys()` of JavaScript.
+    fn split_object(&self, origin: &Msg, map: &VariantObjectMap) -> crate::Result<Vec<MsgHandle>> {
+        let seq = SequenceInfo {
+            id: Msg::generate_id().to_string(),
+            kind: "object",
+            ch: None,
+            len: None,
+            first_index: 0,
+            count: Some(map.len()),
+        };
+        let items = map.iter().map(|(key, value)| (Some(key.clone()), value.clone())).collect();
+        self.make_sequence(origin, seq, items)
+    }
+
+    /// Emits the elements, or the chunks of `arraySplt` elements.
+    fn split_array(&self, origin: &Msg, items: &[Variant]) -> crate::Result<Vec<MsgHandle>> {
+        let items: Vec<(Option<String>, Variant)> = if self.array_len == 1 {
+            items.iter().map(|x| (None, x.clone())).collect()
+        } else {
+            items.chunks(self.array_len).map(|x| (None, Variant::Array(x.to_vec()))).collect()
+        };
+        let seq = SequenceInfo {
+            id: Msg::generate_id().to_string(),
+            kind: "array",
+            ch: None,
+            len: Some(self.array_len),
+            first_index: 0,
+            count: Some(items.len()),
+        };
+        self.make_sequence(origin, seq, items)
+    }
+
+    /// Emits one message per part of the string, the empty parts are kept as Node-RED does.
+    async fn split_string(&self, origin: &Msg, text: &str) -> crate::Result<Vec<MsgHandle>> {
+        let mut state = self.stream_state.lock().await;
+        let text = match (self.config.stream, state.remainder.take()) {
+            (true, Some(Variant::String(remainder))) => remainder + text,
+            _ => text.to_string(),
+        };
+        let (mut items, ch, len) = match &self.delimiter {
+            Delimiter::Regex(re) => (split_by_regex(&text, re, self.config.regex_captures), "".to_string(), None),
+            Delimiter::Len(len) => {
+                let chars: Vec<char> = text.chars().collect();
+                (chars.chunks(*len).map(|x| Variant::String(x.iter().collect())).collect(), "".to_string(), Some(*len))
+            }
+            // Splits into characters like `"abc".split("")` in JavaScript
+            Delimiter::Str(splt) if splt.is_empty() => {
+                (text.chars().map(|c| Variant::String(c.to_string())).collect(), "".to_string(), None)
+            }
+            Delimiter::Str(splt) => (text.split(splt.as_str()).map(Variant::from).collect(), splt.clone(), None),
+            Delimiter::Bin(splt) => {
+                let splt = String::from_utf8_lossy(splt).into_owned();
+                (text.split(splt.as_str()).map(Variant::from).collect::<Vec<_>>(), splt, None)
+            }
+        };
+        if self.config.stream {
+            // The last part is incomplete until the next delimiter or the full length
+            let is_incomplete = match (&self.delimiter, items.last()) {
+                (Delimiter::Len(len), Some(Variant::String(last))) => last.chars().count() < *len,
+                _ => true,
+            };
+            if is_incomplete {
+                state.remainder = items.pop();
+            }
+        }
+        let seq = self.next_sequence(&mut state, "string", Some(Variant::String(ch)), len, items.len());
+        self.make_sequence(origin, seq, items.into_iter().map(|x| (None, x)).collect())
+    }
+
+    /// Emits one message per part of the buffer, the empty trailing part is dropped like Node-RED.
+    async fn split_bytes(&self, origin: &Msg, bytes: &[u8]) -> crate::Result<Vec<MsgHandle>> {
+        let mut state = self.stream_state.lock().await;
+        let data = match (self.config.stream, state.remainder.take()) {
+            (true, Some(Variant::Bytes(mut remainder))) => {
+                remainder.extend_from_slice(bytes);
+                remainder
+            }
+            _ => bytes.to_vec(),
+        };
+        let (mut items, ch, len) = match &self.delimiter {
+            Delimiter::Len(len) => (data.chunks(*len).map(|x| x.to_vec()).collect::<Vec<_>>(), None, Some(*len)),
+            Delimiter::Str(splt) => (split_bytes_by(&data, splt.as_bytes()), Some(Variant::from(splt.as_str())), None),
+            Delimiter::Bin(splt) => {
+                let ch = Variant::Array(splt.iter().map(|x| Variant::from(*x as u64)).collect());
+                (split_bytes_by(&data, splt), Some(ch), None)
+            }
+            Delimiter::Regex(_) => {
+                return Err(EdgelinkError::NotSupported("Cannot split a buffer by a regular expression".into()).into())
+            }
+        };
+        if self.config.stream {
+            let is_incomplete = match &self.delimiter {
+                Delimiter::Len(len) => items.last().is_some_and(|x| x.len() < *len),
+                // The data after the last delimiter
+                Delimiter::Str(splt) => !data.ends_with(splt.as_bytes()),
+                Delimiter::Bin(splt) => !data.ends_with(splt),
+                Delimiter::Regex(_) => false,
+            };
+            if is_incomplete {
+                state.remainder = items.pop().map(Variant::Bytes);
+            }
+        }
+        let seq = self.next_sequence(&mut state, "buffer", ch, len, items.len());
+        self.make_sequence(origin, seq, items.into_iter().map(|x| (None, Variant::Bytes(x))).collect())
+    }
+
+    /// Starts a new sequence, or continues the sequence of the stream.
+    fn next_sequence(
+        &self,
+        state: &mut StreamState,
+        kind: &'static str,
+        ch: Option<Variant>,
+        len: Option<usize>,
+        item_count: usize,
+    ) -> SequenceInfo {
+        if self.config.stream {
+            let id = state.id.get_or_insert_with(|| Msg::generate_id().to_string()).clone();
+            let first_index = state.next_index;
+            state.next_index += item_count;
+            SequenceInfo { id, kind, ch, len, first_index, count: None }
+        } else {
+            SequenceInfo { id: Msg::generate_id().to_string(), kind, ch, len, first_index: 0, count: Some(item_count) }
+        }
+    }
+
+    /// Makes the messages of the items, the key of an item is set to `parts.key` and the `addname` property.
+    fn make_sequence(
+        &self,
+        origin: &Msg,
+        seq: SequenceInfo,
+        items: Vec<(Option<String>, Variant)>,
+    ) -> crate::Result<Vec<MsgHandle>> {
+        let mut msgs = Vec::with_capacity(items.len());
+        for (i, (key, value)) in items.into_iter().enumerate() {
+            let mut new_msg = origin.clone();
+            new_msg.set_nav_stripped(&self.config.property, value, true)?;
+            let mut parts = VariantObjectMap::from([
+                ("id".to_string(), Variant::String(seq.id.clone())),
+                ("type".to_string(), Variant::from(seq.kind)),
+                ("index".to_string(), Variant::from((seq.first_index + i) as u64)),
+            ]);
+            if let Some(count) = seq.count {
+                parts.insert("count".to_string(), Variant::from(count as u64));
+            }
+            if let Some(ch) = &seq.ch {
+                parts.insert("ch".to_string(), ch.clone());
+            }
+            if let Some(len) = seq.len {
+                parts.insert("len".to_string(), Variant::from(len as u64));
+            }
+            if let Some(key) = key {
+                if !self.config.addname.is_empty() {
+                    new_msg.set_nav_stripped(&self.config.addname, Variant::String(key.clone()), true)?;
+                }
+                parts.insert("key".to_string(), Variant::String(key));
+            }
+            // Keeps the parts of the outer sequence
+            if let Some(outer_parts) = origin.get("parts") {
+                parts.insert("parts".to_string(), outer_parts.clone());
+            }
+            new_msg.set("parts".to_string(), Variant::Object(parts));
+            new_msg.set_id(Msg::generate_id());
+            msgs.push(MsgHandle::new(new_msg));
+        }
+        Ok(msgs)
+    }
+}
+
+/// Splits the string like `String.prototype.split()` with a `RegExp` in JavaScript.
+///
+/// The empty matches at the start and the end of the string do not split, and an empty string splits into nothing
+/// if the regex matches it. The unmatched capturing groups are emitted as `null`.
+fn split_by_regex(text: &str, re: &Regex, with_captures: bool) -> Vec<Variant> {
+    if text.is_empty() {
+        return if re.is_match(text) { Vec::new() } else { vec![Variant::from(text)] };
+    }
+    let mut items = Vec::new();
+    let mut last_end = 0;
+    for caps in re.captures_iter(text) {
+        let whole = caps.get(0).expect("the whole match");
+        if whole.start() >= text.len() || whole.end() == last_end {
+            continue;
+        }
+        items.push(Variant::from(&text[last_end..whole.start()]));
+        if with_captures {
+            items.extend(caps.iter().skip(1).map(|x| x.map_or(Variant::Null, |m| Variant::from(m.as_str()))));
+        }
+        last_end = whole.end();
+    }
+    items.push(Variant::from(&text[last_end..]));
+    items
+}
+
+/// Splits the bytes by the delimiter, the empty part after the last delimiter is dropped.
+fn split_bytes_by(data: &[u8], delimiter: &[u8]) -> Vec<Vec<u8>> {
+    if delimiter.is_empty() {
+        return data.chunks(1).map(|x| x.to_vec()).collect();
+    }
+    let mut items = Vec::new();
+    let (mut start, mut pos) = (0, 0);
+    while pos + delimiter.len() <= data.len() {
+        if data[pos..].starts_with(delimiter) {
+            items.push(data[start..pos].to_vec());
+            pos += delimiter.len();
+            start = pos;
+        } else {
+            pos += 1;
+        }
+    }
+    if start < data.len() {
+        items.push(data[start..].to_vec());
+    }
+    items
+}
+
+#[async_trait]
+impl FlowNodeBehavior for SplitNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow(self.as_ref(), cancel.child_token(), |node, msg| async move { node.receive(msg, cancel).await })
+                .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_it_should_split_object_by_keys() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "split", "addname": "topic", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        // Parsed from the text, so the properties are kept in their order instead of being sorted by `json!()`
+        let msg: Msg = serde_json::from_str(r#"{"payload": {"b": [1, 2], "a": {"x": true}, "c": 3}}"#).unwrap();
+        let msgs_to_inject = vec![(ElementId::with_u64(1), msg)];
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(3, Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        let topics: Vec<&str> = msgs.iter().map(|x| x["topic"].as_str().unwrap()).collect();
+        assert_eq!(topics, vec!["b", "a", "c"]);
+        assert_eq!(msgs[0]["payload"], Variant::deserialize(json!([1, 2])).unwrap());
+        assert_eq!(msgs[0].get_nav_stripped("parts.key"), Some(&Variant::from("b")));
+        assert_eq!(msgs[0].get_nav_stripped("parts.index"), Some(&Variant::from(0)));
+        assert_eq!(msgs[1]["payload"], Variant::deserialize(json!({"x": true})).unwrap());
+        assert_eq!(msgs[1].get_nav_stripped("parts.index"), Some(&Variant::from(1)));
+        assert_eq!(msgs[1].get_nav_stripped("parts.count"), Some(&Variant::from(3)));
+        assert_eq!(msgs[0].get_nav_stripped("parts.id"), msgs[1].get_nav_stripped("parts.id"));
+    }
+
+    #[test]
+    fn test_split_by_regex_should_match_javascript() {
+        let strs = |items: &[&str]| items.iter().map(|x| Variant::from(*x)).collect::<Vec<_>>();
+        let digits = Regex::new(r"\d+").unwrap();
+        assert_eq!(split_by_regex("a1b22c333", &digits, false), strs(&["a", "b", "c", ""]));
+        assert_eq!(split_by_regex("1a", &digits, false), strs(&["", "a"]));
+        assert_eq!(split_by_regex("", &digits, false), strs(&[""]));
+        let empty = Regex::new("").unwrap();
+        assert_eq!(split_by_regex("abc", &empty, false), strs(&["a", "b", "c"]));
+        assert!(split_by_regex("", &empty, false).is_empty());
+
+        let captured = Regex::new(r"(\d)").unwrap();
+        assert_eq!(split_by_regex("a1b2", &captured, true), strs(&["a", "1", "b", "2", ""]));
+        assert_eq!(split_by_regex("a1b2", &captured, false), strs(&["a", "b", ""]));
+        let optional = Regex::new(r"(-)|(\+)").unwrap();
+        assert_eq!(
+            split_by_regex("a-b", &optional, true),
+            vec![Variant::from("a"), Variant::from("-"), Variant::Null, Variant::from("b")]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_it_should_split_string_by_regex() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "split", "splt": "\\s*([,;])\\s*", "spltType": "regex", "wires": [["3"]]},
+            {"id": "2", "z": "100", "type": "split", "splt": "\\s*([,;])\\s*", "spltType": "regex",
+                "regexCaptures": true, "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"topic": "plain", "payload": "a , b;c;"}],
+            ["2", {"topic": "captures", "payload": "a,b"}]
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(7, Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        // The two sequences may interleave, but each of them is in order
+        let plain: Vec<&Msg> = msgs.iter().filter(|x| x["topic"] == Variant::from("plain")).collect();
+        let captures: Vec<&Msg> = msgs.iter().filter(|x| x["topic"] == Variant::from("captures")).collect();
+        let payloads = |seq: &[&Msg]| seq.iter().map(|x| x["payload"].clone()).collect::<Vec<_>>();
+        let strs = |items: &[&str]| items.iter().map(|x| Variant::from(*x)).collect::<Vec<_>>();
+        assert_eq!(payloads(&plain), strs(&["a", "b", "c", ""]));
+        assert_eq!(payloads(&captures), strs(&["a", ",", "b"]));
+        assert_eq!(plain[3].get_nav_stripped("parts.type"), Some(&Variant::from("string")));
+        assert_eq!(plain[3].get_nav_stripped("parts.index"), Some(&Variant::from(3)));
+        assert_eq!(plain[3].get_nav_stripped("parts.count"), Some(&Variant::from(4)));
+        assert_eq!(captures[2].get_nav_stripped("parts.count"), Some(&Variant::from(3)));
+    }
+
+    fn payloads_of(msgs: &[Msg], topic: &str) -> Vec<Variant> {
+        msgs.iter().filter(|x| x["topic"] == Variant::from(topic)).map(|x| x["payload"].clone()).collect()
+    }
+
+    fn bytes_msg(topic: &str, bytes: &[u8]) -> Msg {
+        let mut msg = Msg::deserialize(json!({ "topic": topic })).unwrap();
+        msg.set("payload".to_string(), Variant::Bytes(bytes.to_vec()));
+        msg
+    }
+
+    #[test]
+    fn test_split_bytes_by_should_drop_trailing_empty_part() {
+        assert_eq!(split_bytes_by(b"a\r\nb\r\n", b"\r\n"), vec![b"a".to_vec(), b"b".to_vec()]);
+        assert_eq!(split_bytes_by(b"\nab\n\nc", b"\n"), vec![vec![], b"ab".to_vec(), vec![], b"c".to_vec()]);
+        assert_eq!(split_bytes_by(b"ab", b""), vec![b"a".to_vec(), b"b".to_vec()]);
+        assert!(split_bytes_by(b"", b"\n").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_it_should_split_array_into_elements_and_chunks() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "split", "wires": [["3"]]},
+            {"id": "2", "z": "100", "type": "split", "arraySplt": 2, "arraySpltType": "len", "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"topic": "one", "payload": [1, "two", {"x": 3}]}],
+            ["2", {"topic": "two", "payload": [1, 2, 3, 4, 5]}]
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(6, Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        assert_eq!(payloads_of(&msgs, "one"), Vec::<Variant>::deserialize(json!([1, "two", {"x": 3}])).unwrap());
+        assert_eq!(payloads_of(&msgs, "two"), Vec::<Variant>::deserialize(json!([[1, 2], [3, 4], [5]])).unwrap());
+        let last = msgs.iter().rfind(|x| x["topic"] == Variant::from("two")).unwrap();
+        let expected_parts =
+            json!({"id": last.get_nav_stripped("parts.id"), "type": "array", "index": 2, "count": 3, "len": 2});
+        assert_eq!(last["parts"], Variant::deserialize(expected_parts).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_it_should_split_string_by_delimiter_and_length() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "split", "wires": [["3"]]},
+            {"id": "2", "z": "100", "type": "split", "splt": "3", "spltType": "len", "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"topic": "lines", "payload": "a\nb\n"}],
+            ["2", {"topic": "len", "payload": "abcdéfgh"}]
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(6, Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        let strs = |items: &[&str]| items.iter().map(|x| Variant::from(*x)).collect::<Vec<_>>();
+        assert_eq!(payloads_of(&msgs, "lines"), strs(&["a", "b", ""]));
+        assert_eq!(payloads_of(&msgs, "len"), strs(&["abc", "déf", "gh"]));
+        let line = msgs.iter().find(|x| x["topic"] == Variant::from("lines")).unwrap();
+        assert_eq!(line.get_nav_stripped("parts.ch"), Some(&Variant::from("\n")));
+        assert_eq!(line.get_nav_stripped("parts.count"), Some(&Variant::from(3)));
+        let chunk = msgs.iter().find(|x| x["topic"] == Variant::from("len")).unwrap();
+        assert_eq!(chunk.get_nav_stripped("parts.len"), Some(&Variant::from(3)));
+    }
+
+    #[tokio::test]
+    async fn test_it_should_split_bytes_by_length_and_delimiter() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "split", "splt": "2", "spltType": "len", "wires": [["3"]]},
+            {"id": "2", "z": "100", "type": "split", "splt": "[0]", "spltType": "bin", "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = vec![
+            (ElementId::with_u64(1), bytes_msg("len", &[1, 2, 3, 4, 5])),
+            (ElementId::with_u64(2), bytes_msg("bin", &[1, 0, 2, 3, 0])),
+        ];
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(5, Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        assert_eq!(
+            payloads_of(&msgs, "len"),
+            vec![Variant::Bytes(vec![1, 2]), Variant::Bytes(vec![3, 4]), Variant::Bytes(vec![5])]
+        );
+        assert_eq!(payloads_of(&msgs, "bin"), vec![Variant::Bytes(vec![1]), Variant::Bytes(vec![2, 3])]);
+        let part = msgs.iter().find(|x| x["topic"] == Variant::from("bin")).unwrap();
+        assert_eq!(part.get_nav_stripped("parts.type"), Some(&Variant::from("buffer")));
+        assert_eq!(part.get_nav_stripped("parts.ch"), Some(&Variant::Array(vec![Variant::from(0)])));
+        assert_eq!(part.get_nav_stripped("parts.count"), Some(&Variant::from(2)));
+    }
+
+    #[tokio::test]
+    async fn test_stream_should_carry_incomplete_part_to_next_message() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "split", "stream": true, "wires": [["3"]]},
+            {"id": "2", "z": "100", "type": "split", "splt": "3", "spltType": "len", "stream": true, "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let mut msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"topic": "lines", "payload": "a\nb"}],
+            ["1", {"topic": "lines", "payload": "c\nd\n"}],
+        ]))
+        .unwrap();
+        msgs_to_inject.push((ElementId::with_u64(2), bytes_msg("len", &[1, 2, 3, 4])));
+        msgs_to_inject.push((ElementId::with_u64(2), bytes_msg("len", &[5, 6, 7])));
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(5, Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        let strs = |items: &[&str]| items.iter().map(|x| Variant::from(*x)).collect::<Vec<_>>();
+        assert_eq!(payloads_of(&msgs, "lines"), strs(&["a", "bc", "d"]));
+        assert_eq!(payloads_of(&msgs, "len"), vec![Variant::Bytes(vec![1, 2, 3]), Variant::Bytes(vec![4, 5, 6])]);
+
+        // All the parts are in one sequence of unknown count
+        let lines: Vec<&Msg> = msgs.iter().filter(|x| x["topic"] == Variant::from("lines")).collect();
+        let indices: Vec<Variant> = lines.iter().map(|x| x.get_nav_stripped("parts.index").unwrap().clone()).collect();
+        assert_eq!(indices, vec![Variant::from(0), Variant::from(1), Variant::from(2)]);
+        assert!(lines.iter().all(|x| x.get_nav_stripped("parts.id") == lines[0].get_nav_stripped("parts.id")));
+        assert!(lines.iter().all(|x| x.get_nav_stripped("parts.count").is_none()));
+    }
+
+    #[test]
+    fn test_bad_split_config_should_fail_to_build() {
+        for config in [
+            json!({"splt": "0", "spltType": "len"}),
+            json!({"splt": "abc", "spltType": "len"}),
+            json!({"splt": "[256]", "spltType": "bin"}),
+            json!({"arraySplt": 0}),
+        ] {
+            let mut node = json!({"id": "1", "z": "100", "type": "split", "wires": []});
+            node.as_object_mut().unwrap().extend(config.as_object().unwrap().clone());
+            let flows_json = json!([{"id": "100", "type": "tab"}, node]);
+            assert!(crate::runtime::engine::build_test_engine(flows_json).is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_split_object_should_round_trip_with_join() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "split", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "join", "mode": "auto", "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let payload = json!({"name": "sensor", "values": [1.5, 2.5], "meta": {"unit": "C", "tags": ["a"]}, "ok": true});
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": payload}]])).unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(1, Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0]["payload"], Variant::deserialize(payload).unwrap());
+        assert!(!msgs[0].contains("parts"));
+    }
+}
@@ -0,0 +1,978 @@
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use smallvec::SmallVec;
+
+use crate::runtime::eval;
+use crate::runtime::flow::Flow;
+use crate::runtime::jsonata;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum SwitchOperator {
+    #[serde(rename = "eq")]
+    Eq,
+
+    #[serde(rename = "neq")]
+    Neq,
+
+    #[serde(rename = "lt")]
+    Lt,
+
+    #[serde(rename = "lte")]
+    Lte,
+
+    #[serde(rename = "gt")]
+    Gt,
+
+    #[serde(rename = "gte")]
+    Gte,
+
+    /// Inclusive, the two bounds can be given in either order, `btwi` names the inclusive range explicitly
+    #[serde(rename = "btwn", alias = "between", alias = "btwi")]
+    Between,
+
+    #[serde(rename = "cont")]
+    Contains,
+
+    #[serde(rename = "regex")]
+    Regex,
+
+    #[serde(rename = "true")]
+    True,
+
+    #[serde(rename = "false")]
+    False,
+
+    /// Matches `null` and the missing property
+    #[serde(rename = "null")]
+    Null,
+
+    #[serde(rename = "nnull")]
+    NotNull,
+
+    #[serde(rename = "istype")]
+    IsType,
+
+    /// The first N messages of a sequence
+    #[serde(rename = "head")]
+    Head,
+
+    /// The last N messages of a sequence, the count of the sequence must be known
+    #[serde(rename = "tail")]
+    Tail,
+
+    /// The messages of a sequence whose index is between the two bounds, inclusive
+    #[serde(rename = "index")]
+    Index,
+
+    #[serde(rename = "hask")]
+    HasKey,
+
+    /// Matches if the propex path exists in the property, e.g. `a.b[1]`, the `null` value counts as existing
+    #[serde(rename = "hasp")]
+    HasPath,
+
+    /// Matches if the JSONata expression `v` evaluated against the `msg` is truthy
+    #[serde(rename = "jsonata_exp")]
+    JsonataExp,
+
+    /// Matches if none of the previous rules matched
+    #[serde(rename = "else")]
+    Else,
+}
+
+impl SwitchOperator {
+    fn operand_count(&self) -> usize {
+        match self {
+            SwitchOperator::Between | SwitchOperator::Index => 2,
+            SwitchOperator::True
+            | SwitchOperator::False
+            | SwitchOperator::Null
+            | SwitchOperator::NotNull
+            | SwitchOperator::JsonataExp
+            | SwitchOperator::Else => 0,
+            _ => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Rule {
+    #[serde(rename = "t")]
+    operator: SwitchOperator,
+
+    #[serde(rename = "v", default, deserialize_with = "deser_rule_value")]
+    value: String,
+
+    /// The type of `v`, or the type name to check for the `istype` rule
+    #[serde(rename = "vt", default = "value_type_default")]
+    value_type: String,
+
+    #[serde(rename = "v2", default, deserialize_with = "deser_rule_value")]
+    value2: String,
+
+    #[serde(rename = "v2t", default = "value_type_default")]
+    value2_type: String,
+
+    /// Ignores the case for the `regex` rule
+    #[serde(rename = "case", default)]
+    ignore_case: bool,
+}
+
+fn value_type_default() -> String {
+    "str".to_string()
+}
+
+/// The `v` may be written as a JSON literal instead of a string in hand-written flows, e.g. `"v": 10`.
+fn deser_rule_value<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Value::deserialize(deserializer)? {
+        Value::String(s) => Ok(s),
+        Value::Null => Ok(String::new()),
+        other => Ok(other.to_string()),
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct SwitchNodeConfig {
+    #[serde(default = "property_default")]
+    property: String,
+
+    #[serde(rename = "propertyType", default = "property_type_default")]
+    property_type: RedPropertyType,
+
+    #[serde(default)]
+    rules: Vec<Rule>,
+
+    /// Sends the message to every matched rule instead of the first one
+    #[serde(rename = "checkall", default = "checkall_default", deserialize_with = "json::deser::deser_bool_or_str")]
+    check_all: bool,
+
+    /// The output count saved by the editor, one output for each rule including the `else` rules
+    #[serde(default, deserialize_with = "json::deser::str_to_option_usize")]
+    outputs: Option<usize>,
+}
+
+fn property_default() -> String {
+    "payload".to_string()
+}
+
+fn property_type_default() -> RedPropertyType {
+    RedPropertyType::Msg
+}
+
+fn checkall_default() -> bool {
+    true
+}
+
+/// The rule resolved from its configuration.
+#[derive(Debug)]
+struct SwitchRule {
+    operator: SwitchOperator,
+    operands: SmallVec<[(RedPropertyType, String); 2]>,
+    ignore_case: bool,
+    /// The precompiled regex of the `regex` rule with a constant pattern
+    regex: Option<Regex>,
+    /// The parsed expression of the `jsonata_exp` rule
+    jsonata: Option<jsonata::Expression>,
+}
+
+impl SwitchRule {
+    fn new(rule: &Rule) -> crate::Result<Self> {
+        let mut operands: SmallVec<[(RedPropertyType, String); 2]> = SmallVec::new();
+        if rule.operator == SwitchOperator::IsType {
+            operands.push((RedPropertyType::Str, rule.value_type.clone()));
+        } else {
+            let raw_operands = [(&rule.value_type, &rule.value), (&rule.value2_type, &rule.value2)];
+            for (vt, v) in raw_operands.into_iter().take(rule.operator.operand_count()) {
+                let vt = RedPropertyType::from(vt).with_context(|| format!("Bad rule: {:?}", rule))?;
+                operands.push((vt, v.clone()));
+            }
+        }
+        let regex = match operands.first() {
+            Some((RedPropertyType::Str, pattern)) if rule.operator == SwitchOperator::Regex => {
+                Some(RegexBuilder::new(pattern).case_insensitive(rule.ignore_case).build()?)
+            }
+            _ => None,
+        };
+        let jsonata = if rule.operator == SwitchOperator::JsonataExp {
+            Some(jsonata::Expression::parse(&rule.value).with_context(|| format!("Bad rule: {:?}", rule))?)
+        } else {
+            None
+        };
+        Ok(SwitchRule { operator: rule.operator, operands, ignore_case: rule.ignore_case, regex, jsonata })
+    }
+
+    /// Evaluates the operands, an operand failed to evaluate is treated as `undefined`.
+    async fn evaluate_operands(
+        &self,
+        node: Option<&dyn FlowNodeBehavior>,
+        msg: &Msg,
+    ) -> SmallVec<[Option<Variant>; 2]> {
+        let mut result = SmallVec::new();
+        for (vt, v) in self.operands.iter() {
+            result.push(eval::evaluate_node_property(v, *vt, node, None, Some(msg)).await.ok());
+        }
+        if let Some(expr) = &self.jsonata {
+            result.push(eval::evaluate_jsonata(expr, node, None, Some(msg)).await.ok().flatten());
+        }
+        result
+    }
+
+    /// Tests the value of the property against the rule, the `None` value stands for the missing property.
+    fn is_match(&self, value: Option<&Variant>, operands: &[Option<Variant>], parts: Option<&MsgParts>) -> bool {
+        let operand = operands.first().and_then(|x| x.as_ref());
+        let operand2 = operands.get(1).and_then(|x| x.as_ref());
+        match self.operator {
+            SwitchOperator::Eq => loose_eq(value, operand),
+            SwitchOperator::Neq => !loose_eq(value, operand),
+            SwitchOperator::Lt => compare(value, operand).is_some_and(|x| x.is_lt()),
+            SwitchOperator::Lte => compare(value, operand).is_some_and(|x| x.is_le()),
+            SwitchOperator::Gt => compare(value, operand).is_some_and(|x| x.is_gt()),
+            SwitchOperator::Gte => compare(value, operand).is_some_and(|x| x.is_ge()),
+            SwitchOperator::Between => {
+                let (lower, upper) = (compare(value, operand), compare(value, operand2));
+                (lower.is_some_and(|x| x.is_ge()) && upper.is_some_and(|x| x.is_le()))
+                    || (lower.is_some_and(|x| x.is_le()) && upper.is_some_and(|x| x.is_ge()))
+            }
+            SwitchOperator::Contains => match (value.and_then(to_js_string), operand.and_then(to_js_string)) {
+                (Some(text), Some(pattern)) => text.contains(&pattern),
+                _ => false,
+            },
+            SwitchOperator::Regex => {
+                let text = match value.and_then(to_js_string) {
+                    Some(text) => text,
+                    None => return false,
+                };
+                match (&self.regex, operand) {
+                    (Some(re), _) => re.is_match(&text),
+                    (None, Some(Variant::Regexp(re))) => re.is_match(&text),
+                    (None, Some(Variant::String(pattern))) => RegexBuilder::new(pattern)
+                        .case_insensitive(self.ignore_case)
+                        .build()
+                        .is_ok_and(|re| re.is_match(&text)),
+                    _ => false,
+                }
+            }
+            SwitchOperator::True => matches!(value, Some(Variant::Bool(true))),
+            SwitchOperator::False => matches!(value, Some(Variant::Bool(false))),
+            SwitchOperator::Null => matches!(value, None | Some(Variant::Null)),
+            SwitchOperator::NotNull => !matches!(value, None | Some(Variant::Null)),
+            SwitchOperator::IsType => operand.and_then(|x| x.as_str()).is_some_and(|x| is_type(value, x)),
+            SwitchOperator::Head => match (parts, operand.and_then(to_number)) {
+                (Some(parts), Some(n)) => (parts.index as f64) < n,
+                _ => false,
+            },
+            SwitchOperator::Tail => match (parts.and_then(|x| x.count.map(|count| (x.index, count))), operand) {
+                (Some((index, count)), Some(n)) => {
+                    to_number(n).is_some_and(|n| count.saturating_sub(index) as f64 <= n)
+                }
+                _ => false,
+            },
+            SwitchOperator::Index => match (parts, operand.and_then(to_number), operand2.and_then(to_number)) {
+                (Some(parts), Some(from), Some(to)) => from <= parts.index as f64 && parts.index as f64 <= to,
+                _ => false,
+            },
+            SwitchOperator::HasKey => match (value, operand.and_then(to_js_string)) {
+                (Some(Variant::Object(obj)), Some(key)) => obj.contains_key(&key),
+                (Some(Variant::Array(arr)), Some(key)) => key.parse::<usize>().is_ok_and(|i| i < arr.len()),
+                _ => false,
+            },
+            SwitchOperator::HasPath => match (value, operand.and_then(|x| x.as_str())) {
+                (Some(value), Some(path)) => value.has_nav(path, &[]),
+                _ => false,
+            },
+            SwitchOperator::JsonataExp => operand.is_some_and(jsonata::is_truthy),
+            SwitchOperator::Else => true,
+        }
+    }
+}
+
+/// Converts the value to a number like the JavaScript `Number()`.
+fn to_number(value: &Variant) -> Option<f64> {
+    match value {
+        Variant::Number(num) => num.as_f64(),
+        Variant::String(s) if s.trim().is_empty() => Some(0.0),
+        Variant::String(s) => s.trim().parse::<f64>().ok().filter(|x| !x.is_nan()),
+        Variant::Bool(b) => Some(if *b { 1.0 } else { 0.0 }),
+        Variant::Null => Some(0.0),
+        _ => None,
+    }
+}
+
+/// Converts the primitive value to a string like the JavaScript `value + ""`.
+fn to_js_string(value: &Variant) -> Option<String> {
+    match value {
+        Variant::Null => Some("null".to_string()),
+        Variant::String(_) | Variant::Number(_) | Variant::Bool(_) => value.to_string().ok(),
+        _ => None,
+    }
+}
+
+/// The JavaScript `==`, the missing value equals to `null` only.
+fn loose_eq(a: Option<&Variant>, b: Option<&Variant>) -> bool {
+    match (a.unwrap_or(&Variant::Null), b.unwrap_or(&Variant::Null)) {
+        (Variant::Null, Variant::Null) => true,
+        (Variant::Null, _) | (_, Variant::Null) => false,
+        (Variant::String(x), Variant::String(y)) => x == y,
+        (
+            x @ (Variant::Number(_) | Variant::String(_) | Variant::Bool(_)),
+            y @ (Variant::Number(_) | Variant::String(_) | Variant::Bool(_)),
+        ) => {
+            matches!((to_number(x), to_number(y)), (Some(x), Some(y)) if x == y)
+        }
+        (x, y) => x == y,
+    }
+}
+
+/// The JavaScript relational comparison, two strings are compared lexicographically, otherwise numerically.
+fn compare(a: Option<&Variant>, b: Option<&Variant>) -> Option<Ordering> {
+    match (a?, b?) {
+        (Variant::String(x), Variant::String(y)) => Some(x.cmp(y)),
+        (x, y) => to_number(x)?.partial_cmp(&to_number(y)?),
+    }
+}
+
+/// Checks the type name like the Node-RED `istype` rule.
+fn is_type(value: Option<&Variant>, type_name: &str) -> bool {
+    match (type_name, value) {
+        ("undefined", None) => true,
+        ("null", Some(Variant::Null)) => true,
+        ("string", Some(Variant::String(_))) => true,
+        ("number", Some(Variant::Number(_))) => true,
+        ("boolean", Some(Variant::Bool(_))) => true,
+        ("array", Some(Variant::Array(_))) => true,
+        ("buffer", Some(Variant::Bytes(_))) => true,
+        ("object", Some(Variant::Object(_) | Variant::Date(_) | Variant::Regexp(_))) => true,
+        ("json", Some(Variant::String(s))) => serde_json::from_str::<Value>(s).is_ok(),
+        _ => false,
+    }
+}
+
+#[derive(Debug)]
+#[flow_node("switch")]
+struct SwitchNode {
+    base: FlowNode,
+    config: SwitchNodeConfig,
+    rules: Vec<SwitchRule>,
+}
+
+impl SwitchNode {
+    fn build(
+        _flow: &Flow,
+        base_node: FlowNode,
+        config: &RedFlowNodeConfig,
+    ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let switch_config = SwitchNodeConfig::deserialize(&config.rest)?;
+        Self::validate_outputs(&switch_config, base_node.ports.len())?;
+        let rules = switch_config.rules.iter().map(SwitchRule::new).collect::<crate::Result<Vec<_>>>()?;
+        let node = SwitchNode { base: base_node, config: switch_config, rules };
+        Ok(Box::new(node))
+    }
+
+    /// Checks that every rule has its own output, so the port of a rule is its index and an `else` rule at the end of
+    /// the rules is routed to the last output. A node without any wires is accepted and drops the messages.
+    fn validate_outputs(config: &SwitchNodeConfig, port_count: usize) -> crate::Result<()> {
+        let rule_count = config.rules.len();
+        if let Some(outputs) = config.outputs {
+            if outputs != rule_count {
+                return Err(EdgelinkError::BadFlowsJson(format!(
+                    "The switch node has {} rule(s) but `outputs` is {}, each rule requires one output",
+                    rule_count, outputs
+                ))
+                .into());
+            }
+        }
+        if port_count > 0 && port_count != rule_count {
+            return Err(EdgelinkError::BadFlowsJson(format!(
+                "The switch node has {} rule(s) but is wired with {} output(s), each rule requires one output",
+                rule_count, port_count
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Returns the output ports of the matched rules, the port of a rule is its index.
+    async fn route(&self, msg: &Msg) -> SmallVec<[usize; 4]> {
+        let value =
+            eval::evaluate_node_property(&self.config.property, self.config.property_type, Some(self), None, Some(msg))
+                .await
+                .ok();
+        let parts = MsgParts::from_msg(msg).ok().flatten();
+        let mut ports = SmallVec::new();
+        for (port, rule) in self.rules.iter().enumerate() {
+            let matched = if rule.operator == SwitchOperator::Else {
+                ports.is_empty()
+            } else {
+                let operands = rule.evaluate_operands(Some(self), msg).await;
+                rule.is_match(value.as_ref(), &operands, parts.as_ref())
+            };
+            if matched {
+                ports.push(port);
+                if !self.config.check_all {
+                    break;
+                }
+            }
+        }
+        ports
+    }
+
+    async fn receive(&self, msg: MsgHandle, cancel: CancellationToken) -> crate::Result<()> {
+        let ports = {
+            let msg_guard = msg.read().await;
+            self.route(&msg_guard).await
+        };
+        let mut envelopes: SmallVec<[Envelope; 4]> = SmallVec::with_capacity(ports.len());
+        for (i, port) in ports.into_iter().enumerate() {
+            // Every output port gets its own copy of the message
+            let msg = if i == 0 { msg.clone() } else { msg.deep_clone(false).await };
+            envelopes.push(Envelope { port, msg });
+        }
+        if envelopes.is_empty() {
+            return Ok(());
+        }
+        self.fan_out_many(envelopes, cancel).await
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for SwitchNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow_concurrent(
+                &self,
+                cancel.child_token(),
+                |node, msg| async move { node.receive(msg, cancel).await },
+            )
+            .await;
+        }
+        wait_uows_completed(self.as_ref()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    /// Tests the rule against the `msg`, the rule is applied on `msg.payload`.
+    async fn check_msg(rule: serde_json::Value, msg: serde_json::Value) -> bool {
+        let rule = SwitchRule::new(&Rule::deserialize(rule).unwrap()).unwrap();
+        let msg = Msg::deserialize(msg).unwrap();
+        let operands = rule.evaluate_operands(None, &msg).await;
+        let parts = MsgParts::from_msg(&msg).unwrap();
+        rule.is_match(msg.get("payload"), &operands, parts.as_ref())
+    }
+
+    async fn check(rule: serde_json::Value, payload: serde_json::Value) -> bool {
+        check_msg(rule, json!({ "payload": payload })).await
+    }
+
+    #[tokio::test]
+    async fn test_eq_should_match_equal_numbers() {
+        assert!(check(json!({"t": "eq", "v": "10", "vt": "num"}), json!(10)).await);
+        assert!(check(json!({"t": "eq", "v": "10", "vt": "num"}), json!(10.0)).await);
+        assert!(!check(json!({"t": "eq", "v": "10", "vt": "num"}), json!(11)).await);
+    }
+
+    #[tokio::test]
+    async fn test_eq_should_match_strings_exactly() {
+        assert!(check(json!({"t": "eq", "v": "Hello"}), json!("Hello")).await);
+        assert!(!check(json!({"t": "eq", "v": "Hello"}), json!("hello")).await);
+    }
+
+    #[tokio::test]
+    async fn test_eq_should_convert_between_string_and_number() {
+        assert!(check(json!({"t": "eq", "v": "10"}), json!(10)).await);
+        assert!(check(json!({"t": "eq", "v": "10", "vt": "num"}), json!("10")).await);
+        assert!(!check(json!({"t": "eq", "v": "ten"}), json!(10)).await);
+    }
+
+    #[tokio::test]
+    async fn test_eq_should_match_booleans() {
+        assert!(check(json!({"t": "eq", "v": "true", "vt": "bool"}), json!(true)).await);
+        assert!(!check(json!({"t": "eq", "v": "true", "vt": "bool"}), json!(false)).await);
+    }
+
+    #[tokio::test]
+    async fn test_eq_should_compare_with_msg_property() {
+        let rule = json!({"t": "eq", "v": "expected", "vt": "msg"});
+        assert!(check_msg(rule.clone(), json!({"payload": 42, "expected": 42})).await);
+        assert!(!check_msg(rule.clone(), json!({"payload": 42, "expected": 43})).await);
+        assert!(!check_msg(rule, json!({"payload": 42})).await);
+    }
+
+    #[tokio::test]
+    async fn test_neq_should_match_different_values() {
+        assert!(check(json!({"t": "neq", "v": "5", "vt": "num"}), json!(6)).await);
+        assert!(!check(json!({"t": "neq", "v": "5", "vt": "num"}), json!(5)).await);
+        assert!(check(json!({"t": "neq", "v": "a"}), json!("b")).await);
+    }
+
+    #[tokio::test]
+    async fn test_neq_should_match_missing_property() {
+        assert!(check_msg(json!({"t": "neq", "v": "a"}), json!({"topic": "x"})).await);
+    }
+
+    #[tokio::test]
+    async fn test_lt_and_lte() {
+        assert!(check(json!({"t": "lt", "v": "3", "vt": "num"}), json!(2)).await);
+        assert!(!check(json!({"t": "lt", "v": "3", "vt": "num"}), json!(3)).await);
+        assert!(check(json!({"t": "lte", "v": "3", "vt": "num"}), json!(3)).await);
+        assert!(!check(json!({"t": "lte", "v": "3", "vt": "num"}), json!(3.5)).await);
+    }
+
+    #[tokio::test]
+    async fn test_gt_and_gte() {
+        assert!(check(json!({"t": "gt", "v": "3", "vt": "num"}), json!(4)).await);
+        assert!(!check(json!({"t": "gt", "v": "3", "vt": "num"}), json!(3)).await);
+        assert!(check(json!({"t": "gte", "v": "3", "vt": "num"}), json!(3)).await);
+        assert!(!check(json!({"t": "gte", "v": "3", "vt": "num"}), json!(-3)).await);
+    }
+
+    #[tokio::test]
+    async fn test_relational_operators_should_convert_numeric_strings() {
+        assert!(check(json!({"t": "gt", "v": "9", "vt": "num"}), json!("10")).await);
+        assert!(check(json!({"t": "lt", "v": "10"}), json!(9)).await);
+    }
+
+    #[tokio::test]
+    async fn test_relational_operators_should_compare_strings_lexicographically() {
+        assert!(check(json!({"t": "lt", "v": "b"}), json!("a")).await);
+        // Unlike the numbers, "10" < "9" for the strings
+        assert!(check(json!({"t": "lt", "v": "9"}), json!("10")).await);
+        assert!(!check(json!({"t": "gt", "v": "b"}), json!("a")).await);
+    }
+
+    #[tokio::test]
+    async fn test_relational_operators_should_not_match_non_numbers() {
+        assert!(!check(json!({"t": "lt", "v": "3", "vt": "num"}), json!("abc")).await);
+        assert!(!check(json!({"t": "gte", "v": "3", "vt": "num"}), json!({"a": 1})).await);
+        assert!(!check_msg(json!({"t": "lt", "v": "3", "vt": "num"}), json!({})).await);
+    }
+
+    #[tokio::test]
+    async fn test_between_should_be_inclusive() {
+        let rule = json!({"t": "btwn", "v": "3", "vt": "num", "v2": "5", "v2t": "num"});
+        assert!(check(rule.clone(), json!(3)).await);
+        assert!(check(rule.clone(), json!(4.5)).await);
+        assert!(check(rule.clone(), json!(5)).await);
+        assert!(!check(rule.clone(), json!(2.99)).await);
+        assert!(!check(rule, json!(6)).await);
+    }
+
+    #[tokio::test]
+    async fn test_btwi_should_match_the_inclusive_range() {
+        let rule = json!({"t": "btwi", "v": "a", "vt": "str", "v2": "c", "v2t": "str"});
+        assert!(check(rule.clone(), json!("a")).await);
+        assert!(check(rule.clone(), json!("b")).await);
+        assert!(check(rule.clone(), json!("c")).await);
+        assert!(!check(rule.clone(), json!("d")).await);
+        let rule = json!({"t": "btwi", "v": "10", "vt": "num", "v2": "1", "v2t": "num"});
+        assert!(check(rule.clone(), json!(10)).await);
+        assert!(!check(rule, json!(0)).await);
+    }
+
+    #[tokio::test]
+    async fn test_between_should_accept_reversed_bounds() {
+        let rule = json!({"t": "between", "v": 5, "vt": "num", "v2": 3, "v2t": "num"});
+        assert!(check(rule.clone(), json!(4)).await);
+        assert!(!check(rule, json!(6)).await);
+    }
+
+    #[tokio::test]
+    async fn test_contains_should_match_substring() {
+        assert!(check(json!({"t": "cont", "v": "ell"}), json!("Hello")).await);
+        assert!(!check(json!({"t": "cont", "v": "xyz"}), json!("Hello")).await);
+    }
+
+    #[tokio::test]
+    async fn test_contains_should_stringify_numbers() {
+        assert!(check(json!({"t": "cont", "v": "23"}), json!(1234)).await);
+        assert!(!check(json!({"t": "cont", "v": "23"}), json!([2, 3])).await);
+    }
+
+    #[tokio::test]
+    async fn test_regex_should_match_pattern() {
+        assert!(check(json!({"t": "regex", "v": "^a.*z$"}), json!("abcz")).await);
+        assert!(!check(json!({"t": "regex", "v": "^a.*z$"}), json!("abc")).await);
+    }
+
+    #[tokio::test]
+    async fn test_regex_should_ignore_case_if_set() {
+        assert!(!check(json!({"t": "regex", "v": "^hello$"}), json!("HELLO")).await);
+        assert!(check(json!({"t": "regex", "v": "^hello$", "case": true}), json!("HELLO")).await);
+    }
+
+    #[tokio::test]
+    async fn test_regex_should_use_pattern_from_msg() {
+        let rule = json!({"t": "regex", "v": "pattern", "vt": "msg", "case": true});
+        assert!(check_msg(rule.clone(), json!({"payload": "Temperature", "pattern": "^temp"})).await);
+        assert!(!check_msg(rule, json!({"payload": "humidity", "pattern": "^temp"})).await);
+    }
+
+    #[tokio::test]
+    async fn test_true_and_false_should_match_booleans_only() {
+        assert!(check(json!({"t": "true"}), json!(true)).await);
+        assert!(!check(json!({"t": "true"}), json!(false)).await);
+        assert!(!check(json!({"t": "true"}), json!("true")).await);
+        assert!(!check(json!({"t": "true"}), json!(1)).await);
+        assert!(check(json!({"t": "false"}), json!(false)).await);
+        assert!(!check(json!({"t": "false"}), json!(0)).await);
+    }
+
+    #[tokio::test]
+    async fn test_null_should_match_null_and_missing_property() {
+        assert!(check(json!({"t": "null"}), json!(null)).await);
+        assert!(check_msg(json!({"t": "null"}), json!({})).await);
+        assert!(!check(json!({"t": "null"}), json!(0)).await);
+        assert!(!check(json!({"t": "null"}), json!("")).await);
+    }
+
+    #[tokio::test]
+    async fn test_nnull_should_match_present_values() {
+        assert!(check(json!({"t": "nnull"}), json!(0)).await);
+        assert!(check(json!({"t": "nnull"}), json!(false)).await);
+        assert!(!check(json!({"t": "nnull"}), json!(null)).await);
+        assert!(!check_msg(json!({"t": "nnull"}), json!({})).await);
+    }
+
+    #[tokio::test]
+    async fn test_istype_should_check_primitive_types() {
+        assert!(check(json!({"t": "istype", "v": "string", "vt": "string"}), json!("a")).await);
+        assert!(check(json!({"t": "istype", "v": "number", "vt": "number"}), json!(1.5)).await);
+        assert!(check(json!({"t": "istype", "v": "boolean", "vt": "boolean"}), json!(false)).await);
+        assert!(check(json!({"t": "istype", "v": "null", "vt": "null"}), json!(null)).await);
+        assert!(!check(json!({"t": "istype", "v": "number", "vt": "number"}), json!("1")).await);
+        assert!(!check(json!({"t": "istype", "v": "string", "vt": "string"}), json!(1)).await);
+    }
+
+    #[tokio::test]
+    async fn test_istype_should_check_structured_types() {
+        assert!(check(json!({"t": "istype", "v": "array", "vt": "array"}), json!([1, 2])).await);
+        assert!(check(json!({"t": "istype", "v": "object", "vt": "object"}), json!({"a": 1})).await);
+        assert!(!check(json!({"t": "istype", "v": "object", "vt": "object"}), json!([1, 2])).await);
+        assert!(!check(json!({"t": "istype", "v": "array", "vt": "array"}), json!({"a": 1})).await);
+    }
+
+    #[tokio::test]
+    async fn test_istype_should_check_buffer_json_and_undefined() {
+        let rule = SwitchRule::new(&Rule::deserialize(json!({"t": "istype", "v": "buffer", "vt": "buffer"})).unwrap())
+            .unwrap();
+        let operands = [Some(Variant::from("buffer"))];
+        assert!(rule.is_match(Some(&Variant::Bytes(vec![1, 2])), &operands, None));
+        assert!(!rule.is_match(Some(&Variant::from("12")), &operands, None));
+
+        assert!(check(json!({"t": "istype", "v": "json", "vt": "json"}), json!("{\"a\": [1]}")).await);
+        assert!(!check(json!({"t": "istype", "v": "json", "vt": "json"}), json!("{a: 1}")).await);
+        assert!(check_msg(json!({"t": "istype", "v": "undefined", "vt": "undefined"}), json!({})).await);
+        assert!(!check(json!({"t": "istype", "v": "undefined", "vt": "undefined"}), json!(null)).await);
+    }
+
+    #[tokio::test]
+    async fn test_head_should_match_first_messages_of_sequence() {
+        let rule = json!({"t": "head", "v": "2", "vt": "num"});
+        assert!(check_msg(rule.clone(), json!({"payload": 1, "parts": {"id": "s", "index": 0, "count": 5}})).await);
+        assert!(check_msg(rule.clone(), json!({"payload": 1, "parts": {"id": "s", "index": 1, "count": 5}})).await);
+        assert!(!check_msg(rule.clone(), json!({"payload": 1, "parts": {"id": "s", "index": 2, "count": 5}})).await);
+        assert!(!check(rule, json!(1)).await);
+    }
+
+    #[tokio::test]
+    async fn test_tail_should_match_last_messages_of_sequence() {
+        let rule = json!({"t": "tail", "v": "2", "vt": "num"});
+        assert!(!check_msg(rule.clone(), json!({"payload": 1, "parts": {"id": "s", "index": 2, "count": 5}})).await);
+        assert!(check_msg(rule.clone(), json!({"payload": 1, "parts": {"id": "s", "index": 3, "count": 5}})).await);
+        assert!(check_msg(rule.clone(), json!({"payload": 1, "parts": {"id": "s", "index": 4, "count": 5}})).await);
+        // The count of a stream is unknown
+        assert!(!check_msg(rule, json!({"payload": 1, "parts": {"id": "s", "index": 4}})).await);
+    }
+
+    #[tokio::test]
+    async fn test_index_should_match_inclusive_range_of_sequence() {
+        let rule = json!({"t": "index", "v": "1", "vt": "num", "v2": "2", "v2t": "num"});
+        let mut matched = Vec::new();
+        for i in 0..4 {
+            let msg = json!({"payload": 1, "parts": {"id": "s", "index": i, "count": 4}});
+            matched.push(check_msg(rule.clone(), msg).await);
+        }
+        assert_eq!(matched, vec![false, true, true, false]);
+    }
+
+    #[tokio::test]
+    async fn test_hask_should_check_object_keys() {
+        assert!(check(json!({"t": "hask", "v": "name"}), json!({"name": null})).await);
+        assert!(!check(json!({"t": "hask", "v": "age"}), json!({"name": "bob"})).await);
+        assert!(!check(json!({"t": "hask", "v": "name"}), json!("name")).await);
+        assert!(check(json!({"t": "hask", "v": "1"}), json!(["a", "b"])).await);
+        assert!(!check(json!({"t": "hask", "v": "2"}), json!(["a", "b"])).await);
+    }
+
+    #[tokio::test]
+    async fn test_hasp_should_check_nested_paths() {
+        assert!(check(json!({"t": "hasp", "v": "a.b"}), json!({"a": {"b": null}})).await);
+        assert!(check(json!({"t": "hasp", "v": "a['b'][1].c"}), json!({"a": {"b": [0, {"c": 1}]}})).await);
+        assert!(!check(json!({"t": "hasp", "v": "a.b[2]"}), json!({"a": {"b": [0, 1]}})).await);
+        assert!(!check(json!({"t": "hasp", "v": "a.b.c"}), json!({"a": {"b": 1}})).await);
+        assert!(check(json!({"t": "hasp", "v": "a[0]"}), json!({"a": [null]})).await);
+        assert!(!check(json!({"t": "hasp", "v": "a[0]"}), json!({"a": []})).await);
+    }
+
+    #[tokio::test]
+    async fn test_jsonata_exp_should_match_truthy_results() {
+        let rule = json!({"t": "jsonata_exp", "v": "payload.count > 2 and topic = 'a'", "vt": "jsonata"});
+        assert!(check_msg(rule.clone(), json!({"topic": "a", "payload": {"count": 3}})).await);
+        assert!(!check_msg(rule.clone(), json!({"topic": "b", "payload": {"count": 3}})).await);
+        assert!(!check_msg(rule, json!({"topic": "a"})).await);
+        let rule = json!({"t": "jsonata_exp", "v": "$count(payload[$ > 1])", "vt": "jsonata"});
+        assert!(check(rule, json!([1, 2])).await);
+        // The failed evaluation does not match
+        assert!(!check(json!({"t": "jsonata_exp", "v": "payload + 'x'", "vt": "jsonata"}), json!(1)).await);
+    }
+
+    #[test]
+    fn test_bad_rules_should_fail_to_build() {
+        for rule in [
+            json!({"t": "nope", "v": "1"}),
+            json!({"t": "eq", "v": "1", "vt": "nope"}),
+            json!({"t": "regex", "v": "(unclosed"}),
+        ] {
+            let flows_json = json!([
+                {"id": "100", "type": "tab"},
+                {"id": "1", "z": "100", "type": "switch", "rules": [rule], "wires": [[]]}
+            ]);
+            assert!(crate::runtime::engine::build_test_engine(flows_json).is_err());
+        }
+    }
+
+    fn switch_flows(
+        rules: serde_json::Value,
+        outputs: serde_json::Value,
+        wires: serde_json::Value,
+    ) -> serde_json::Value {
+        json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "switch", "rules": rules, "outputs": outputs, "wires": wires}
+        ])
+    }
+
+    #[test]
+    fn test_consistent_outputs_should_build() {
+        let rules = json!([{"t": "eq", "v": "a"}, {"t": "eq", "v": "b"}, {"t": "else"}]);
+        for (outputs, wires) in [
+            (json!(3), json!([[], [], []])),
+            (json!("3"), json!([[], [], []])),
+            (json!(null), json!([[], [], []])),
+            // Not wired at all
+            (json!(3), json!([])),
+        ] {
+            assert!(crate::runtime::engine::build_test_engine(switch_flows(rules.clone(), outputs, wires)).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_mismatched_outputs_should_fail_to_build() {
+        let rules = json!([{"t": "eq", "v": "a"}, {"t": "eq", "v": "b"}, {"t": "else"}]);
+        for (outputs, wires, expected) in [
+            (json!(2), json!([[], [], []]), "has 3 rule(s) but `outputs` is 2"),
+            (json!(4), json!([[], [], [], []]), "has 3 rule(s) but `outputs` is 4"),
+            (json!(null), json!([[], []]), "has 3 rule(s) but is wired with 2 output(s)"),
+            (json!(null), json!([[], [], [], []]), "has 3 rule(s) but is wired with 4 output(s)"),
+        ] {
+            let err =
+                crate::runtime::engine::build_test_engine(switch_flows(rules.clone(), outputs, wires)).unwrap_err();
+            assert!(format!("{:?}", err).contains(expected), "{:?}", err);
+        }
+    }
+
+    fn make_flows(rules: serde_json::Value, checkall: &str) -> serde_json::Value {
+        let port_count = rules.as_array().unwrap().len();
+        let mut flows = vec![
+            json!({"id": "100", "type": "tab"}),
+            json!({"id": "1", "z": "100", "type": "switch", "property": "payload", "propertyType": "msg",
+                "rules": rules, "checkall": checkall, "outputs": port_count,
+                "wires": (0..port_count).map(|i| vec![format!("{}", 10 + i)]).collect::<Vec<_>>()}),
+            json!({"id": "2", "z": "100", "type": "test-once"}),
+        ];
+        // Tags the messages with the output port
+        for i in 0..port_count {
+            flows.push(json!({"id": format!("{}", 10 + i), "z": "100", "type": "change", "wires": [["2"]], "rules": [
+                {"t": "set", "p": "port", "pt": "msg", "to": i.to_string(), "tot": "num"}
+            ]}));
+        }
+        serde_json::Value::Array(flows)
+    }
+
+    fn ports_of(msgs: &[Msg], topic: &str) -> Vec<Variant> {
+        let mut ports: Vec<Variant> =
+            msgs.iter().filter(|x| x["topic"] == Variant::from(topic)).map(|x| x["port"].clone()).collect();
+        ports.sort_by_key(|x| x.as_u64());
+        ports
+    }
+
+    #[tokio::test]
+    async fn test_it_should_stop_at_first_match_without_checkall() {
+        let rules = json!([
+            {"t": "gt", "v": "10", "vt": "num"},
+            {"t": "gt", "v": "5", "vt": "num"},
+            {"t": "else"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"topic": "a", "payload": 20}],
+            ["1", {"topic": "b", "payload": 7}],
+            ["1", {"topic": "c", "payload": 1}],
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(make_flows(rules, "false")).unwrap();
+        let msgs =
+            engine.run_once_with_inject(3, std::time::Duration::from_secs_f64(0.3), msgs_to_inject).await.unwrap();
+        assert_eq!(ports_of(&msgs, "a"), vec![Variant::from(0)]);
+        assert_eq!(ports_of(&msgs, "b"), vec![Variant::from(1)]);
+        assert_eq!(ports_of(&msgs, "c"), vec![Variant::from(2)]);
+    }
+
+    #[tokio::test]
+    async fn test_it_should_send_to_all_matches_with_checkall() {
+        let rules = json!([
+            {"t": "gt", "v": "10", "vt": "num"},
+            {"t": "istype", "v": "number", "vt": "number"},
+            {"t": "else"},
+            {"t": "eq", "v": "hello"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"topic": "a", "payload": 20}],
+            ["1", {"topic": "b", "payload": 7}],
+            ["1", {"topic": "c", "payload": "hello"}],
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(make_flows(rules, "true")).unwrap();
+        let msgs =
+            engine.run_once_with_inject(5, std::time::Duration::from_secs_f64(0.3), msgs_to_inject).await.unwrap();
+        assert_eq!(ports_of(&msgs, "a"), vec![Variant::from(0), Variant::from(1)]);
+        assert_eq!(ports_of(&msgs, "b"), vec![Variant::from(1)]);
+        // The `else` rule only sees the rules before it
+        assert_eq!(ports_of(&msgs, "c"), vec![Variant::from(2), Variant::from(3)]);
+    }
+
+    #[tokio::test]
+    async fn test_else_should_route_to_last_output_with_overlapping_matches() {
+        let rules = json!([
+            {"t": "gt", "v": "5", "vt": "num"},
+            {"t": "gt", "v": "10", "vt": "num"},
+            {"t": "else"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"topic": "a", "payload": 20}],
+            ["1", {"topic": "b", "payload": 7}],
+            ["1", {"topic": "c", "payload": 1}],
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(make_flows(rules, "true")).unwrap();
+        let msgs =
+            engine.run_once_with_inject(4, std::time::Duration::from_secs_f64(0.3), msgs_to_inject).await.unwrap();
+        // One copy for each matched rule, the `else` output only gets the message matched nothing
+        assert_eq!(ports_of(&msgs, "a"), vec![Variant::from(0), Variant::from(1)]);
+        assert_eq!(ports_of(&msgs, "b"), vec![Variant::from(0)]);
+        assert_eq!(ports_of(&msgs, "c"), vec![Variant::from(2)]);
+    }
+
+    #[tokio::test]
+    async fn test_it_should_route_by_nested_path_existence() {
+        let rules = json!([{"t": "hasp", "v": "sensor.readings[1]"}, {"t": "else"}]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"topic": "a", "payload": {"sensor": {"readings": [1, null]}}}],
+            ["1", {"topic": "b", "payload": {"sensor": {"readings": [1]}}}],
+            ["1", {"topic": "c", "payload": {"sensor": null}}],
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(make_flows(rules, "false")).unwrap();
+        let msgs =
+            engine.run_once_with_inject(3, std::time::Duration::from_secs_f64(0.3), msgs_to_inject).await.unwrap();
+        assert_eq!(ports_of(&msgs, "a"), vec![Variant::from(0)]);
+        assert_eq!(ports_of(&msgs, "b"), vec![Variant::from(1)]);
+        assert_eq!(ports_of(&msgs, "c"), vec![Variant::from(1)]);
+    }
+
+    #[tokio::test]
+    async fn test_copies_sent_to_each_port_should_be_independent() {
+        let rules = json!([{"t": "nnull"}, {"t": "nnull"}]);
+        let msgs_to_inject =
+            Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"topic": "a", "payload": 1}]])).unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(make_flows(rules, "true")).unwrap();
+        let msgs =
+            engine.run_once_with_inject(2, std::time::Duration::from_secs_f64(0.3), msgs_to_inject).await.unwrap();
+        assert_eq!(ports_of(&msgs, "a"), vec![Variant::from(0), Variant::from(1)]);
+        assert_eq!(msgs[0]["_msgid"], msgs[1]["_msgid"]);
+    }
+
+    #[tokio::test]
+    async fn test_it_should_route_by_flow_context_property() {
+        let mut flows_json = make_flows(json!([{"t": "eq", "v": "on"}, {"t": "else"}]), "false");
+        let switch_node = flows_json.as_array_mut().unwrap()[1].as_object_mut().unwrap();
+        switch_node.insert("property".into(), json!("mode"));
+        switch_node.insert("propertyType".into(), json!("flow"));
+        flows_json.as_array_mut().unwrap().push(json!(
+            {"id": "3", "z": "100", "type": "change", "wires": [["1"]], "rules": [
+                {"t": "set", "p": "mode", "pt": "flow", "to": "payload", "tot": "msg"}
+            ]}
+        ));
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["3", {"topic": "a", "payload": "on"}],
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.3), msgs_to_inject).await.unwrap();
+        assert_eq!(ports_of(&msgs, "a"), vec![Variant::from(0)]);
+    }
+
+    #[tokio::test]
+    async fn test_it_should_route_by_jsonata_exp_with_contexts() {
+        let rules = json!([
+            {"t": "jsonata_exp", "vt": "jsonata",
+                "v": "payload > $flowContext('limit') and $globalContext('mode') = 'on'"},
+            {"t": "else"}
+        ]);
+        let mut flows_json = make_flows(rules, "false");
+        flows_json.as_array_mut().unwrap().push(json!(
+            {"id": "3", "z": "100", "type": "change", "wires": [["1"]], "rules": [
+                {"t": "set", "p": "limit", "pt": "flow", "to": "10", "tot": "num"},
+                {"t": "set", "p": "mode", "pt": "global", "to": "on", "tot": "str"}
+            ]}
+        ));
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["3", {"topic": "a", "payload": 11}],
+            ["3", {"topic": "b", "payload": 10}],
+            ["1", {"topic": "c", "payload": "no limit"}],
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(3, std::time::Duration::from_secs_f64(0.3), msgs_to_inject).await.unwrap();
+        assert_eq!(ports_of(&msgs, "a"), vec![Variant::from(0)]);
+        assert_eq!(ports_of(&msgs, "b"), vec![Variant::from(1)]);
+        assert_eq!(ports_of(&msgs, "c"), vec![Variant::from(1)]);
+    }
+}
@@ -0,0 +1,341 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::runtime::context::ContextKeyRef;
+use crate::runtime::eval;
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+/// The partials including other partials are expanded up to this depth
+const MAX_PARTIAL_DEPTH: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum TemplateSyntax {
+    #[serde(rename = "mustache")]
+    Mustache,
+
+    /// The template is used as it is
+    #[serde(rename = "plain")]
+    Plain,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum TemplateOutput {
+    #[default]
+    #[serde(rename = "str")]
+    Str,
+
+    /// Parses the rendered text as JSON
+    #[serde(rename = "json")]
+    Json,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct TemplateNodeConfig {
+    #[serde(default = "default_field")]
+    field: String,
+
+    #[serde(default, rename = "fieldType")]
+    field_type: Option<RedPropertyType>,
+
+    #[serde(default)]
+    syntax: Option<TemplateSyntax>,
+
+    /// The `mustache` or `plain` if the `syntax` is missing, otherwise it's only the highlighting of the editor
+    #[serde(default)]
+    format: String,
+
+    #[serde(default)]
+    template: String,
+
+    #[serde(default)]
+    output: TemplateOutput,
+
+    /// The named templates included by `{{> name}}`
+    #[serde(default)]
+    partials: BTreeMap<String, String>,
+}
+
+fn default_field() -> String {
+    "payload".to_string()
+}
+
+struct CompiledTemplate(mustache::Template);
+
+impl std::fmt::Debug for CompiledTemplate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("CompiledTemplate")
+    }
+}
+
+/// Renders the Mustache template to the message property or the context variable.
+///
+/// The properties of the message are available as `{{payload}}` or `{{msg.payload}}`, and the context and environment
+/// variables as `{{flow.name}}`, `{{global.name}}` and `{{env.NAME}}`.
+#[derive(Debug)]
+#[flow_node("template")]
+struct TemplateNode {
+    base: FlowNode,
+    config: TemplateNodeConfig,
+    field_type: RedPropertyType,
+    field_key: Option<ContextKeyRef>,
+    compiled: Option<CompiledTemplate>,
+
+    /// The variables referenced by the template, they are fetched before rendering
+    refs: Vec<(RedPropertyType, String)>,
+}
+
+impl TemplateNode {
+    fn build(_flow: &Flow, state: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let template_config = TemplateNodeConfig::deserialize(&config.rest)?;
+        let field_type = template_config.field_type.unwrap_or(RedPropertyType::Msg);
+        let field_key = match field_type {
+            RedPropertyType::Msg => None,
+            RedPropertyType::Flow | RedPropertyType::Global => Some(ContextKeyRef::parse(&template_config.field)?),
+            _ => {
+                return Err(EdgelinkError::BadFlowsJson(format!(
+                    "The template node can only set the `msg`, `flow` or `global` property, got: {:?}",
+                    field_type
+                ))
+                .into())
+            }
+        };
+
+        let syntax = template_config.syntax.unwrap_or(match template_config.format.as_str() {
+            "plain" => TemplateSyntax::Plain,
+            _ => TemplateSyntax::Mustache,
+        });
+        let (compiled, refs) = match syntax {
+            TemplateSyntax::Mustache => {
+                let expanded = expand_partials(&template_config.template, &template_config.partials, 0);
+                let compiled = mustache::compile_str(&expanded)
+                    .map_err(|e| EdgelinkError::BadFlowsJson(format!("Bad template of the template node: {}", e)))?;
+                (Some(CompiledTemplate(compiled)), parse_refs(&expanded))
+            }
+            TemplateSyntax::Plain => (None, Vec::new()),
+        };
+
+        let node = TemplateNode { base: state, config: template_config, field_type, field_key, compiled, refs };
+        Ok(Box::new(node))
+    }
+
+    async fn uow(&self, msg: MsgHandle, cancel: CancellationToken) -> crate::Result<()> {
+        {
+            let mut msg_guard = msg.write().await;
+            let rendered = match &self.compiled {
+                Some(compiled) => {
+                    let view = self.make_view(&msg_guard).await;
+                    let mut buf = Vec::new();
+                    compiled.0.render_data(&mut buf, &variant_to_data(&view)).map_err(|e| {
+                        EdgelinkError::InvalidOperation(format!("Failed to render the template: {}", e))
+                    })?;
+                    String::from_utf8_lossy(&buf).into_owned()
+                }
+                None => self.config.template.clone(),
+            };
+            let value = match self.config.output {
+                TemplateOutput::Str => Variant::String(rendered),
+                TemplateOutput::Json => Variant::from(serde_json::from_str::<serde_json::Value>(&rendered)?),
+            };
+            self.set_field(&mut msg_guard, value).await?;
+        }
+        self.fan_out_one(Envelope { port: 0, msg }, cancel).await
+    }
+
+    /// The properties of the message with the `msg`, `flow`, `global` and `env` objects.
+    async fn make_view(&self, msg: &Msg) -> Variant {
+        let mut flow_vars = VariantObjectMap::new();
+        let mut global_vars = VariantObjectMap::new();
+        let mut env_vars = VariantObjectMap::new();
+        let flow = self.flow();
+        for (kind, name) in self.refs.iter() {
+            let (vars, value) = match kind {
+                RedPropertyType::Flow => (&mut flow_vars, flow.as_ref().map(|x| x.context())),
+                RedPropertyType::Global => (&mut global_vars, self.engine().map(|x| x.context())),
+                _ => {
+                    let value =
+                        eval::evaluate_node_property(name, RedPropertyType::Env, Some(self), flow.as_ref(), None).await;
+                    if let Ok(value) = value {
+                        env_vars.insert(name.clone(), value);
+                    }
+                    continue;
+                }
+            };
+            if let Some(ctx) = value {
+                if let Some(value) = ctx.get_one(None, name, &[]).await {
+                    vars.insert(name.clone(), value);
+                }
+            }
+        }
+
+        let mut view = msg.as_variant_object().clone();
+        view.insert("msg".to_string(), msg.as_variant().clone());
+        view.insert("flow".to_string(), Variant::Object(flow_vars));
+        view.insert("global".to_string(), Variant::Object(global_vars));
+        view.insert("env".to_string(), Variant::Object(env_vars));
+        Variant::Object(view)
+    }
+
+    async fn set_field(&self, msg: &mut Msg, value: Variant) -> crate::Result<()> {
+        let ctx = match self.field_type {
+            RedPropertyType::Flow => self.flow().map(|x| x.context()),
+            RedPropertyType::Global => self.engine().map(|x| x.context()),
+            _ => return msg.set_nav_stripped(&self.config.field, value, true),
+        };
+        let ctx = ctx.ok_or(EdgelinkError::InvalidOperation("Failed to get context".to_string()))?;
+        let key = self.field_key.as_ref().expect("The context key of the field").as_key();
+        ctx.set_one(key.store, key.key, Some(value), &[PropexEnv::ExtRef("msg", msg.as_variant())]).await
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for TemplateNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow_concurrent(&self, cancel.child_token(), |node, msg| async move { node.uow(msg, cancel).await })
+                .await;
+        }
+        wait_uows_completed(self.as_ref()).await;
+    }
+}
+
+/// Replaces the `{{> name}}` tags with the partials, the missing partials are rendered as empty like Mustache does.
+fn expand_partials(template: &str, partials: &BTreeMap<String, String>, depth: usize) -> String {
+    let re = Regex::new(r"\{\{>\s*([^\s}]+)\s*\}\}").expect("partial tag regex");
+    re.replace_all(template, |caps: &regex::Captures| match partials.get(&caps[1]) {
+        Some(partial) if depth < MAX_PARTIAL_DEPTH => expand_partials(partial, partials, depth + 1),
+        _ => String::new(),
+    })
+    .into_owned()
+}
+
+/// Finds the `flow.xxx`, `global.xxx` and `env.xxx` variables in the tags of the template.
+fn parse_refs(template: &str) -> Vec<(RedPropertyType, String)> {
+    let re = Regex::new(r"\{\{[{#^/&]?\s*(flow|global|env)\.([^\s}.\[]+)").expect("context tag regex");
+    let mut refs: Vec<(RedPropertyType, String)> = Vec::new();
+    for caps in re.captures_iter(template) {
+        let kind = match &caps[1] {
+            "flow" => RedPropertyType::Flow,
+            "global" => RedPropertyType::Global,
+            _ => RedPropertyType::Env,
+        };
+        let name = caps[2].to_string();
+        if !refs.iter().any(|(k, n)| *k == kind && *n == name) {
+            refs.push((kind, name));
+        }
+    }
+    refs
+}
+
+fn variant_to_data(value: &Variant) -> mustache::Data {
+    match value {
+        Variant::Null => mustache::Data::Null,
+        Variant::Bool(b) => mustache::Data::Bool(*b),
+        Variant::String(s) => mustache::Data::String(s.clone()),
+        Variant::Number(n) => mustache::Data::String(n.to_string()),
+        Variant::Bytes(bytes) => mustache::Data::String(String::from_utf8_lossy(bytes).into_owned()),
+        Variant::Regexp(re) => mustache::Data::String(re.as_str().to_string()),
+        Variant::Date(_) => mustache::Data::String(serde_json::Value::from(value).to_string()),
+        Variant::Array(items) => mustache::Data::Vec(items.iter().map(variant_to_data).collect()),
+        Variant::Object(map) => {
+            mustache::Data::Map(map.iter().map(|(k, v)| (k.clone(), variant_to_data(v))).collect::<HashMap<_, _>>())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_it_should_render_msg_properties() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "template", "field": "out.text", "fieldType": "msg",
+                "format": "handlebars", "syntax": "mustache",
+                "template": "Hello {{ msg.payload }} from {{topic}}, {{#items}}[{{.}}]{{/items}} {{raw}}{{{raw}}}",
+                "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"payload": "world", "topic": "t", "items": [1, 2], "raw": "<b>"}]
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(1, Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0].get_nav_stripped("out.text"), Some(&"Hello world from t, [1][2] &lt;b&gt;<b>".into()));
+        assert_eq!(msgs[0]["payload"], "world".into());
+    }
+
+    #[tokio::test]
+    async fn test_it_should_render_partials_as_json() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "template", "field": "payload", "output": "json",
+                "template": "{\"greeting\": \"{{> greet}}\", \"missing\": \"{{> nope}}\"}",
+                "partials": {"greet": "Hi {{> name}}", "name": "{{user.name}}"}, "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"user": {"name": "Bob"}}]])).unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(1, Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0]["payload"], Variant::from(json!({"greeting": "Hi Bob", "missing": ""})));
+    }
+
+    #[tokio::test]
+    async fn test_it_should_render_context_variables_to_flow_context() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "template", "field": "rendered", "fieldType": "flow",
+                "template": "{{flow.prefix}}-{{global.count}}-{{payload}}", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let flow = engine.get_flow(&ElementId::with_u64(0x100)).unwrap();
+        flow.context().set_one(None, "prefix", Some(Variant::from("pre")), &[]).await.unwrap();
+        engine.context().set_one(None, "count", Some(Variant::from(3)), &[]).await.unwrap();
+
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": "x"}]])).unwrap();
+        let msgs = engine.run_once_with_inject(1, Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        // The msg is not changed
+        assert_eq!(msgs[0]["payload"], "x".into());
+        assert_eq!(flow.context().get_one(None, "rendered", &[]).await, Some(Variant::from("pre-3-x")));
+    }
+
+    #[tokio::test]
+    async fn test_plain_syntax_should_not_render() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "template", "syntax": "plain", "template": "{{payload}}",
+                "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": "x"}]])).unwrap();
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(1, Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0]["payload"], "{{payload}}".into());
+    }
+
+    #[test]
+    fn test_bad_template_should_fail_to_build() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "template", "template": "{{#section}} never closed", "wires": []}
+        ]);
+        assert!(crate::runtime::engine::build_test_engine(flows_json).is_err());
+    }
+}
@@ -0,0 +1,379 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Deserialize;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use crate::utils;
+use edgelink_macro::*;
+
+const NO_TOPIC: &str = "_no_topic";
+
+/// The max number of the topics tracked at the same time, the least recently seen topic is dropped for a new one
+const MAX_TOPICS: usize = 1024;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum ThresholdDirection {
+    /// Alerts when the values are greater than the threshold
+    #[default]
+    #[serde(rename = "above")]
+    Above,
+
+    /// Alerts when the values are less than the threshold
+    #[serde(rename = "below")]
+    Below,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ThresholdNodeConfig {
+    /// The message property of the value to check
+    #[serde(default = "property_default")]
+    property: String,
+
+    #[serde(deserialize_with = "json::deser::str_to_option_f64")]
+    threshold: Option<f64>,
+
+    #[serde(default)]
+    direction: ThresholdDirection,
+
+    /// The alert is cleared only if the values are back past the threshold by the hysteresis
+    #[serde(default, deserialize_with = "json::deser::str_to_option_f64")]
+    hysteresis: Option<f64>,
+
+    /// How many consecutive samples are required to raise or to clear the alert
+    #[serde(default, deserialize_with = "json::deser::str_to_option_usize")]
+    count: Option<usize>,
+
+    /// The sliding window in seconds the consecutive samples must fall in, unlimited if it's `None` or 0
+    #[serde(default, deserialize_with = "json::deser::str_to_option_f64")]
+    window: Option<f64>,
+
+    #[serde(rename = "septopics", default = "septopics_default")]
+    sep_topics: bool,
+
+    #[serde(rename = "topi", default = "topic_default")]
+    topic: String,
+}
+
+fn property_default() -> String {
+    "payload".to_string()
+}
+
+fn septopics_default() -> bool {
+    true
+}
+
+fn topic_default() -> String {
+    "topic".to_string()
+}
+
+/// The alert state of a topic
+#[derive(Debug)]
+struct ThresholdState {
+    alerting: bool,
+
+    /// The arrival times of the consecutive samples for the alert to change, the ones out of the window are dropped
+    samples: VecDeque<Instant>,
+
+    last_seen: Instant,
+}
+
+impl ThresholdState {
+    fn new(now: Instant) -> Self {
+        ThresholdState { alerting: false, samples: VecDeque::new(), last_seen: now }
+    }
+}
+
+/// Raises an alert when the values cross the threshold for a number of consecutive samples within the sliding window,
+/// and clears it when the values are back to normal. Only the messages changing the alert are sent, with `msg.alert`
+/// set to `true` or `false`.
+#[derive(Debug)]
+#[flow_node("threshold")]
+struct ThresholdNode {
+    base: FlowNode,
+    config: ThresholdNodeConfig,
+    threshold: f64,
+    hysteresis: f64,
+    count: usize,
+    window: Option<Duration>,
+    states: Mutex<HashMap<String, ThresholdState>>,
+}
+
+impl ThresholdNode {
+    fn build(
+        _flow: &Flow,
+        base_node: FlowNode,
+        config: &RedFlowNodeConfig,
+    ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let threshold_config = ThresholdNodeConfig::deserialize(&config.rest)?;
+        let threshold = threshold_config
+            .threshold
+            .filter(|x| x.is_finite())
+            .ok_or(EdgelinkError::BadFlowsJson("The threshold of the threshold node must be a finite number".into()))?;
+        let hysteresis = threshold_config.hysteresis.unwrap_or(0.0);
+        if !(hysteresis.is_finite() && hysteresis >= 0.0) {
+            return Err(EdgelinkError::BadFlowsJson(format!(
+                "The hysteresis of the threshold node must not be negative, got: {}",
+                hysteresis
+            ))
+            .into());
+        }
+        let count = threshold_config.count.unwrap_or(1);
+        if count == 0 {
+            return Err(EdgelinkError::BadFlowsJson("The count of the threshold node must be positive".into()).into());
+        }
+        let window = match threshold_config.window.filter(|x| *x != 0.0) {
+            None => None,
+            Some(window) => Some(utils::time::delay_from_secs_f64(window).ok_or_else(|| {
+                EdgelinkError::BadFlowsJson(format!("Invalid window of the threshold node: {}", window))
+            })?),
+        };
+        let node = ThresholdNode {
+            base: base_node,
+            config: threshold_config,
+            threshold,
+            hysteresis,
+            count,
+            window,
+            states: Mutex::new(HashMap::new()),
+        };
+        Ok(Box::new(node))
+    }
+
+    fn is_crossed(&self, value: f64) -> bool {
+        match self.config.direction {
+            ThresholdDirection::Above => value > self.threshold,
+            ThresholdDirection::Below => value < self.threshold,
+        }
+    }
+
+    fn is_normal(&self, value: f64) -> bool {
+        match self.config.direction {
+            ThresholdDirection::Above => value <= self.threshold - self.hysteresis,
+            ThresholdDirection::Below => value >= self.threshold + self.hysteresis,
+        }
+    }
+
+    /// Checks the value received at `now` against the state of its topic, returns `true` if the alert changed and the
+    /// message should be sent.
+    fn do_check(
+        &self,
+        msg: &mut Msg,
+        states: &mut HashMap<String, ThresholdState>,
+        now: Instant,
+    ) -> crate::Result<bool> {
+        let topic = match (self.config.sep_topics, msg.get_nav_stripped(&self.config.topic)) {
+            (true, Some(Variant::String(topic))) if !topic.is_empty() => topic.clone(),
+            _ => NO_TOPIC.to_string(),
+        };
+
+        if msg.contains("reset") {
+            if topic != NO_TOPIC {
+                states.remove(&topic);
+            } else {
+                states.clear();
+            }
+            if msg.get_nav_stripped(&self.config.property).is_none() {
+                return Ok(false);
+            }
+        }
+
+        let value = match msg.get_nav_stripped(&self.config.property) {
+            Some(Variant::String(s)) => s.trim().parse::<f64>().ok(),
+            Some(value) => value.as_f64(),
+            None => None,
+        };
+        let value = value.filter(|x| !x.is_nan()).ok_or_else(|| {
+            EdgelinkError::InvalidOperation(format!("The property 'msg.{}' is not a number", self.config.property))
+        })?;
+
+        if !states.contains_key(&topic) && states.len() >= MAX_TOPICS {
+            let oldest = states.iter().min_by_key(|(_, state)| state.last_seen).map(|(topic, _)| topic.clone());
+            if let Some(oldest) = oldest {
+                log::warn!("[THRESHOLD:{}] Too many topics, dropped the state of the topic '{}'", self.name(), oldest);
+                states.remove(&oldest);
+            }
+        }
+
+        let state = states.entry(topic).or_insert_with(|| ThresholdState::new(now));
+        state.last_seen = now;
+        let is_changing = if state.alerting { self.is_normal(value) } else { self.is_crossed(value) };
+        if !is_changing {
+            state.samples.clear();
+            return Ok(false);
+        }
+        if let Some(window) = self.window {
+            while state.samples.front().is_some_and(|x| now.duration_since(*x) > window) {
+                state.samples.pop_front();
+            }
+        }
+        state.samples.push_back(now);
+        if state.samples.len() < self.count {
+            return Ok(false);
+        }
+        state.alerting = !state.alerting;
+        state.samples.clear();
+        msg.set("alert".to_string(), Variant::Bool(state.alerting));
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for ThresholdNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow(self.as_ref(), cancel.child_token(), |node, msg| async move {
+                let can_send = {
+                    let mut msg_guard = msg.write().await;
+                    let mut states_guard = node.states.lock().await;
+                    node.do_check(&mut msg_guard, &mut states_guard, Instant::now())?
+                };
+                if can_send {
+                    node.fan_out_one(Envelope { port: 0, msg }, cancel.child_token()).await?;
+                }
+                Ok(())
+            })
+            .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+    use std::time::Duration;
+
+    fn inject_values(values: serde_json::Value) -> Vec<(ElementId, Msg)> {
+        let values = values.as_array().unwrap().iter().enumerate();
+        values
+            .map(|(i, x)| (ElementId::with_u64(1), Msg::deserialize(json!({"seq": i, "payload": x})).unwrap()))
+            .collect()
+    }
+
+    /// The `(seq, alert)` of the sent messages
+    fn alerts(msgs: &[Msg]) -> Vec<(i64, bool)> {
+        msgs.iter().map(|x| (x["seq"].as_i64().unwrap(), x["alert"].as_bool().unwrap())).collect()
+    }
+
+    #[tokio::test]
+    async fn test_it_should_alert_after_consecutive_crossings() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "threshold", "threshold": "10", "count": "3", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        // The crossings at 1 and 2 are interrupted by 3, the alert is raised at 6
+        let msgs_to_inject = inject_values(json!([5, 11, 12, 10, 11, "12", 13, 14]));
+        let msgs = engine.run_once_with_inject(1, Duration::from_millis(200), msgs_to_inject).await.unwrap();
+        assert_eq!(alerts(&msgs), vec![(6, true)]);
+    }
+
+    #[tokio::test]
+    async fn test_it_should_not_clear_within_hysteresis() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "threshold", "threshold": 20, "direction": "below", "hysteresis": 5,
+                "count": 1, "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = inject_values(json!([19, 21, 24.9, 22, 25, 24, 19.5]));
+        let msgs = engine.run_once_with_inject(3, Duration::from_millis(200), msgs_to_inject).await.unwrap();
+        assert_eq!(alerts(&msgs), vec![(0, true), (4, false), (6, true)]);
+    }
+
+    #[tokio::test]
+    async fn test_it_should_recover_per_topic() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "threshold", "threshold": 10, "hysteresis": 2, "count": 2,
+                "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"topic": "a", "seq": 0, "payload": 15}],
+            ["1", {"topic": "b", "seq": 1, "payload": 15}],
+            ["1", {"topic": "a", "seq": 2, "payload": 16}],
+            ["1", {"topic": "a", "seq": 3, "payload": 7}],
+            ["1", {"topic": "a", "seq": 4, "payload": 12}],
+            ["1", {"topic": "a", "seq": 5, "payload": 8}],
+            ["1", {"topic": "a", "seq": 6, "payload": 6}],
+            ["1", {"topic": "b", "seq": 7, "payload": 5}],
+        ]))
+        .unwrap();
+        let msgs = engine.run_once_with_inject(2, Duration::from_millis(200), msgs_to_inject).await.unwrap();
+        // The recovery of `a` is interrupted by 12, and `b` never crossed twice
+        assert_eq!(alerts(&msgs), vec![(2, true), (6, false)]);
+        assert!(msgs.iter().all(|x| x["topic"] == Variant::from("a")));
+    }
+
+    #[test]
+    fn test_consecutive_samples_should_be_in_the_window() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "threshold", "threshold": 10, "count": 2, "window": 0.1}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let node = engine.find_flow_node_by_id(&ElementId::with_u64(1)).unwrap();
+        let node = node.as_any().downcast_ref::<ThresholdNode>().unwrap();
+        let mut states = HashMap::new();
+        let mut check_at = |value: i32, millis: u64, started: Instant| {
+            let mut msg = Msg::deserialize(json!({"payload": value})).unwrap();
+            node.do_check(&mut msg, &mut states, started + Duration::from_millis(millis)).unwrap()
+        };
+
+        let started = Instant::now();
+        assert!(!check_at(11, 0, started));
+        // The first crossing is out of the window
+        assert!(!check_at(12, 150, started));
+        assert!(check_at(13, 200, started));
+    }
+
+    #[test]
+    fn test_topics_should_be_limited() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "threshold", "threshold": 10, "count": 2}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let node = engine.find_flow_node_by_id(&ElementId::with_u64(1)).unwrap();
+        let node = node.as_any().downcast_ref::<ThresholdNode>().unwrap();
+        let mut states = HashMap::new();
+        let started = Instant::now();
+        for i in 0..=MAX_TOPICS {
+            let mut msg = Msg::deserialize(json!({"topic": format!("t{}", i), "payload": 11})).unwrap();
+            node.do_check(&mut msg, &mut states, started + Duration::from_millis(i as u64)).unwrap();
+        }
+        assert_eq!(states.len(), MAX_TOPICS);
+        assert!(!states.contains_key("t0"));
+        assert!(states.contains_key(&format!("t{}", MAX_TOPICS)));
+    }
+
+    #[test]
+    fn test_bad_config_should_fail_to_build() {
+        for threshold_node in [
+            json!({"id": "1", "z": "100", "type": "threshold"}),
+            json!({"id": "1", "z": "100", "type": "threshold", "threshold": "x"}),
+            json!({"id": "1", "z": "100", "type": "threshold", "threshold": 1, "hysteresis": -1}),
+            json!({"id": "1", "z": "100", "type": "threshold", "threshold": 1, "count": 0}),
+            json!({"id": "1", "z": "100", "type": "threshold", "threshold": 1, "window": -1}),
+        ] {
+            let flows_json = json!([{"id": "100", "type": "tab"}, threshold_node]);
+            assert!(crate::runtime::engine::build_test_engine(flows_json).is_err());
+        }
+    }
+}
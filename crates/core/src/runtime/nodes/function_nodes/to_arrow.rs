@@ -0,0 +1,100 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+#[derive(Debug, Clone, Deserialize)]
+struct ToArrowNodeConfig {
+    #[serde(default = "to_arrow_property_default")]
+    property: String,
+}
+
+fn to_arrow_property_default() -> String {
+    "payload".to_string()
+}
+
+/// Converts an array of objects in the message into the Arrow IPC streaming format.
+#[derive(Debug)]
+#[flow_node("to_arrow")]
+struct ToArrowNode {
+    base: FlowNode,
+    config: ToArrowNodeConfig,
+}
+
+impl ToArrowNode {
+    fn build(
+        _flow: &Flow,
+        base_node: FlowNode,
+        config: &RedFlowNodeConfig,
+    ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let to_arrow_config = ToArrowNodeConfig::deserialize(&config.rest)?;
+        let node = ToArrowNode { base: base_node, config: to_arrow_config };
+        Ok(Box::new(node))
+    }
+
+    async fn convert(&self, msg: MsgHandle) -> crate::Result<()> {
+        let mut msg_guard = msg.write().await;
+        let ipc = msg_guard
+            .get_nav_stripped(&self.config.property)
+            .ok_or(EdgelinkError::InvalidOperation(format!("Cannot get the property 'msg.{}'", self.config.property)))?
+            .to_arrow_ipc()?;
+        msg_guard.set_nav_stripped(&self.config.property, Variant::Bytes(ipc), true)
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for ToArrowNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow(self.as_ref(), cancel.child_token(), |node, msg| async move {
+                node.convert(msg.clone()).await?;
+                node.fan_out_one(Envelope { port: 0, msg }, cancel.child_token()).await
+            })
+            .await;
+        }
+
+        log::debug!("ToArrowNode process() task has been terminated.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, Int64Array};
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_it_should_convert_payload_into_arrow_ipc() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "to_arrow", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject_json = json!([
+            ["1", {"payload": [{"a": 1}, {"a": 2}, {"b": "x"}]}],
+        ]);
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(msgs_to_inject_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 1);
+
+        let bytes = msgs[0]["payload"].as_bytes().unwrap().to_vec();
+        let mut reader = arrow::ipc::reader::StreamReader::try_new(std::io::Cursor::new(bytes), None).unwrap();
+        let batch = reader.next().unwrap().unwrap();
+        let a = batch.column(batch.schema().index_of("a").unwrap()).as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(a.value(1), 2);
+        assert!(a.is_null(2));
+    }
+}
@@ -0,0 +1,434 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Deserialize;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+
+use crate::runtime::eval;
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use crate::utils;
+use edgelink_macro::*;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+enum TriggerValueType {
+    /// The legacy type of Node-RED, a boolean or a number if the value looks like one, otherwise a string
+    #[default]
+    #[serde(rename = "val")]
+    Value,
+
+    /// Sends the message that started the trigger
+    #[serde(rename = "pay")]
+    Original,
+
+    /// Sends the latest message received while triggered
+    #[serde(rename = "payl")]
+    Latest,
+
+    /// Sends nothing
+    #[serde(rename = "nul")]
+    Nothing,
+
+    /// Sets the payload to the evaluated property
+    #[serde(untagged)]
+    Property(RedPropertyType),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum DurationUnits {
+    #[default]
+    #[serde(rename = "ms")]
+    Milliseconds,
+
+    #[serde(rename = "s")]
+    Seconds,
+
+    #[serde(rename = "min")]
+    Minutes,
+
+    #[serde(rename = "hr")]
+    Hours,
+}
+
+impl DurationUnits {
+    fn as_secs_f64(&self) -> f64 {
+        match self {
+            DurationUnits::Milliseconds => 0.001,
+            DurationUnits::Seconds => 1.0,
+            DurationUnits::Minutes => 60.0,
+            DurationUnits::Hours => 60.0 * 60.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct TriggerNodeConfig {
+    #[serde(default = "default_op1")]
+    op1: String,
+
+    #[serde(default)]
+    op1type: TriggerValueType,
+
+    #[serde(default = "default_op2")]
+    op2: String,
+
+    #[serde(default)]
+    op2type: TriggerValueType,
+
+    /// The delay before sending `op2`, the node waits for a reset if it is 0
+    #[serde(default, deserialize_with = "json::deser::str_to_option_f64")]
+    duration: Option<f64>,
+
+    #[serde(default, alias = "durationUnits")]
+    units: DurationUnits,
+
+    /// Restarts the delay if a message arrived while triggered
+    #[serde(default, deserialize_with = "json::deser::deser_bool_or_str")]
+    extend: bool,
+
+    /// Overrides the delay by `msg.delay` in milliseconds
+    #[serde(rename = "overrideDelay", default, deserialize_with = "json::deser::deser_bool_or_str")]
+    override_delay: bool,
+
+    /// The payload resetting the node, besides any message with the `reset` property
+    #[serde(default)]
+    reset: String,
+}
+
+fn default_op1() -> String {
+    "1".to_string()
+}
+
+fn default_op2() -> String {
+    "0".to_string()
+}
+
+/// The messages of the current trigger, for the `pay` and `payl` types of `op2`
+#[derive(Debug, Default)]
+struct TriggeredMsgs {
+    first: Option<MsgHandle>,
+    latest: Option<MsgHandle>,
+}
+
+#[derive(Debug)]
+#[flow_node("trigger")]
+struct TriggerNode {
+    base: FlowNode,
+    config: TriggerNodeConfig,
+    duration: Option<Duration>,
+    /// The pending timer of `op2`, the node is triggered while it is present
+    timer: Mutex<Option<JoinHandle<()>>>,
+    msgs: Mutex<TriggeredMsgs>,
+}
+
+impl TriggerNode {
+    fn build(
+        _flow: &Flow,
+        base_node: FlowNode,
+        config: &RedFlowNodeConfig,
+    ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let trigger_config = TriggerNodeConfig::deserialize(&config.rest)?;
+        let duration = trigger_config.duration.unwrap_or(250.0);
+        if duration < 0.0 {
+            return Err(EdgelinkError::BadFlowsJson(format!(
+                "Bad duration of the trigger node, repeating is not supported: {}",
+                duration
+            ))
+            .into());
+        }
+        let duration =
+            utils::time::delay_from_secs_f64(duration * trigger_config.units.as_secs_f64()).filter(|x| !x.is_zero());
+        let node = TriggerNode {
+            base: base_node,
+            config: trigger_config,
+            duration,
+            timer: Mutex::new(None),
+            msgs: Mutex::new(TriggeredMsgs::default()),
+        };
+        Ok(Box::new(node))
+    }
+
+    async fn receive(self: &Arc<Self>, msg: MsgHandle, stop_token: CancellationToken) -> crate::Result<()> {
+        let (is_reset, delay) = {
+            let msg_guard = msg.read().await;
+            (self.is_reset(&msg_guard), self.delay_of(&msg_guard))
+        };
+        if is_reset {
+            self.reset().await;
+            return Ok(());
+        }
+
+        let mut timer = self.timer.lock().await;
+        if let Some(pending) = timer.take() {
+            self.msgs.lock().await.latest = Some(msg);
+            if self.config.extend {
+                pending.abort();
+                *timer = Some(self.start_timer(delay, stop_token));
+            } else {
+                *timer = Some(pending);
+            }
+            return Ok(());
+        }
+
+        {
+            let mut msgs = self.msgs.lock().await;
+            msgs.first = Some(msg.clone());
+            msgs.latest = Some(msg.clone());
+        }
+        *timer = Some(self.start_timer(delay, stop_token.clone()));
+        drop(timer);
+
+        if let Some(out) = self.make_output(self.config.op1type, &self.config.op1, &msg, &msg).await? {
+            self.fan_out_one(Envelope { port: 0, msg: out }, stop_token).await?;
+        }
+        Ok(())
+    }
+
+    fn is_reset(&self, msg: &Msg) -> bool {
+        if msg.contains("reset") {
+            return true;
+        }
+        !self.config.reset.is_empty()
+            && msg.get("payload").and_then(|x| x.to_string().ok()).is_some_and(|x| x == self.config.reset)
+    }
+
+    fn delay_of(&self, msg: &Msg) -> Option<Duration> {
+        if self.config.override_delay {
+            let delay_ms = match msg.get("delay") {
+                Some(Variant::Number(num)) => num.as_f64(),
+                Some(Variant::String(s)) => s.trim().parse::<f64>().ok(),
+                _ => None,
+            };
+            if let Some(delay) = delay_ms.and_then(|x| utils::time::delay_from_secs_f64(x / 1000.0)) {
+                return Some(delay).filter(|x| !x.is_zero());
+            }
+        }
+        self.duration
+    }
+
+    /// Cancels the pending `op2` without sending it.
+    async fn reset(&self) {
+        let mut timer = self.timer.lock().await;
+        if let Some(pending) = timer.take() {
+            pending.abort();
+            log::debug!("[TRIGGER:{}] The trigger has been reset", self.name());
+        }
+        *self.msgs.lock().await = TriggeredMsgs::default();
+    }
+
+    /// Spawns the timer sending `op2` after the delay, or waiting for a reset if the delay is `None`.
+    fn start_timer(self: &Arc<Self>, delay: Option<Duration>, stop_token: CancellationToken) -> JoinHandle<()> {
+        let node = self.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = stop_token.cancelled() => return,
+                _ = async {
+                    match delay {
+                        Some(delay) => tokio::time::sleep(delay).await,
+                        None => std::future::pending().await,
+                    }
+                } => {}
+            }
+
+            // The timer is aborted while holding the lock, so it is still the current one here
+            let msgs = {
+                let mut timer = node.timer.lock().await;
+                timer.take();
+                std::mem::take(&mut *node.msgs.lock().await)
+            };
+            let (Some(first), Some(latest)) = (msgs.first, msgs.latest) else {
+                return;
+            };
+            let port = if node.get_node().ports.len() > 1 { 1 } else { 0 };
+            match node.make_output(node.config.op2type, &node.config.op2, &first, &latest).await {
+                Ok(Some(out)) => {
+                    if let Err(e) = node.fan_out_one(Envelope { port, msg: out }, stop_token.child_token()).await {
+                        log::warn!("[TRIGGER:{}] Failed to send the second output: {}", node.name(), e);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => log::warn!("[TRIGGER:{}] Failed to evaluate the second output: {}", node.name(), e),
+            }
+        })
+    }
+
+    /// Makes the output message from the latest message, returns `None` if nothing should be sent.
+    async fn make_output(
+        &self,
+        value_type: TriggerValueType,
+        value: &str,
+        first: &MsgHandle,
+        latest: &MsgHandle,
+    ) -> crate::Result<Option<MsgHandle>> {
+        let prop_type = match value_type {
+            TriggerValueType::Nothing => return Ok(None),
+            TriggerValueType::Original => return Ok(Some(first.deep_clone(false).await)),
+            TriggerValueType::Latest => return Ok(Some(latest.deep_clone(false).await)),
+            TriggerValueType::Value => match value.trim() {
+                "true" | "false" => RedPropertyType::Bool,
+                x if x.parse::<f64>().is_ok() => RedPropertyType::Num,
+                _ => RedPropertyType::Str,
+            },
+            TriggerValueType::Property(prop_type) => prop_type,
+        };
+        let out = latest.deep_clone(false).await;
+        {
+            let mut out_guard = out.write().await;
+            let payload = eval::evaluate_node_property(value, prop_type, Some(self), None, Some(&out_guard)).await?;
+            out_guard.set("payload".into(), payload);
+        }
+        Ok(Some(out))
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for TriggerNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            let this = self.clone();
+            with_uow(self.as_ref(), cancel.child_token(), |_, msg| async move { this.receive(msg, cancel).await })
+                .await;
+        }
+
+        if let Some(pending) = self.timer.lock().await.take() {
+            pending.abort();
+        }
+        log::debug!("TriggerNode process() task has been terminated.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    async fn inject_later(engine: &crate::runtime::engine::Engine, after: Duration, payload: &str) {
+        tokio::time::sleep(after).await;
+        let msg = MsgHandle::with_payload(payload.into());
+        engine.inject_msg(&ElementId::with_u64(1), msg, CancellationToken::new()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_it_should_send_op1_then_op2() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "trigger", "op1": "on", "op1type": "str", "op2": "off", "op2type": "str",
+                "duration": "150", "units": "ms", "extend": false, "reset": "stop", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"payload": "a", "topic": "t"}],
+            ["1", {"payload": "b"}],
+        ]))
+        .unwrap();
+        let msgs = engine.run_once_with_inject(2, Duration::from_millis(400), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs.len(), 2);
+        assert_eq!(msgs[0]["payload"], "on".into());
+        assert_eq!(msgs[0]["topic"], "t".into());
+        assert_eq!(msgs[1]["payload"], "off".into());
+    }
+
+    #[tokio::test]
+    async fn test_it_should_extend_the_delay_on_receive() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "trigger", "op1": "on", "op1type": "str", "op2": "off", "op2type": "str",
+                "duration": "150", "units": "ms", "extend": true, "reset": "stop", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let started = tokio::time::Instant::now();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": "a"}]])).unwrap();
+        let (msgs, _) = tokio::join!(
+            engine.run_once_with_inject(2, Duration::from_millis(600), msgs_to_inject),
+            inject_later(&engine, Duration::from_millis(100), "b"),
+        );
+        let msgs = msgs.unwrap();
+        let payloads: Vec<&str> = msgs.iter().map(|x| x["payload"].as_str().unwrap()).collect();
+        assert_eq!(payloads, vec!["on", "off"]);
+        // The second message restarted the 150ms delay
+        assert!(started.elapsed() >= Duration::from_millis(250));
+    }
+
+    #[tokio::test]
+    async fn test_it_should_not_send_op2_after_reset() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "trigger", "op1": "on", "op1type": "str", "op2": "off", "op2type": "str",
+                "duration": "150", "units": "ms", "extend": false, "reset": "stop", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": "a"}]])).unwrap();
+        let (msgs, _) = tokio::join!(
+            engine.run_once_with_inject(2, Duration::from_millis(400), msgs_to_inject),
+            inject_later(&engine, Duration::from_millis(50), "stop"),
+        );
+        assert!(msgs.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_it_should_trigger_again_after_reset() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "trigger", "op1": "on", "op1type": "str", "op2": "off", "op2type": "str",
+                "duration": "150", "units": "ms", "extend": false, "reset": "stop", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"payload": "a"}],
+            ["1", {"reset": true}],
+            ["1", {"payload": "b"}],
+        ]))
+        .unwrap();
+        let msgs = engine.run_once_with_inject(3, Duration::from_millis(400), msgs_to_inject).await.unwrap();
+        let payloads: Vec<&str> = msgs.iter().map(|x| x["payload"].as_str().unwrap()).collect();
+        assert_eq!(payloads, vec!["on", "on", "off"]);
+    }
+
+    #[tokio::test]
+    async fn test_it_should_send_original_and_latest_messages() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "trigger", "op1": "", "op1type": "pay", "op2": "", "op2type": "payl",
+                "duration": "0.1", "units": "s", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"payload": "a"}],
+            ["1", {"payload": "b"}],
+            ["1", {"payload": "c"}],
+        ]))
+        .unwrap();
+        let msgs = engine.run_once_with_inject(2, Duration::from_millis(400), msgs_to_inject).await.unwrap();
+        let payloads: Vec<&str> = msgs.iter().map(|x| x["payload"].as_str().unwrap()).collect();
+        assert_eq!(payloads, vec!["a", "c"]);
+    }
+
+    #[tokio::test]
+    async fn test_huge_msg_delay_should_not_panic() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "trigger", "op1": "on", "op1type": "str", "op2": "off", "op2type": "str",
+                "duration": "1e300", "units": "hr", "overrideDelay": true, "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject =
+            Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": "a", "delay": 1e300}]])).unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(1, Duration::from_millis(200), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0]["payload"], "on".into());
+    }
+}
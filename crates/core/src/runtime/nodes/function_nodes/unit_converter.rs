@@ -0,0 +1,241 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dimension {
+    Length,
+    Mass,
+    Temperature,
+    Time,
+    Speed,
+    Pressure,
+    Volume,
+}
+
+/// A unit converted into the base unit of its dimension by `(value + offset) * factor`.
+#[derive(Debug)]
+struct Unit {
+    names: &'static [&'static str],
+    dimension: Dimension,
+    factor: f64,
+    offset: f64,
+}
+
+const fn unit(names: &'static [&'static str], dimension: Dimension, factor: f64) -> Unit {
+    Unit { names, dimension, factor, offset: 0.0 }
+}
+
+static UNITS: &[Unit] = &[
+    // Length, the base unit is metre
+    unit(&["m", "metre", "meter"], Dimension::Length, 1.0),
+    unit(&["km", "kilometre", "kilometer"], Dimension::Length, 1000.0),
+    unit(&["cm", "centimetre", "centimeter"], Dimension::Length, 0.01),
+    unit(&["mm", "millimetre", "millimeter"], Dimension::Length, 0.001),
+    unit(&["in", "inch"], Dimension::Length, 0.0254),
+    unit(&["ft", "foot", "feet"], Dimension::Length, 0.3048),
+    unit(&["yd", "yard"], Dimension::Length, 0.9144),
+    unit(&["mi", "mile"], Dimension::Length, 1609.344),
+    // Mass, the base unit is kilogram
+    unit(&["kg", "kilogram"], Dimension::Mass, 1.0),
+    unit(&["g", "gram"], Dimension::Mass, 0.001),
+    unit(&["mg", "milligram"], Dimension::Mass, 0.000001),
+    unit(&["t", "tonne"], Dimension::Mass, 1000.0),
+    unit(&["lb", "pound"], Dimension::Mass, 0.45359237),
+    unit(&["oz", "ounce"], Dimension::Mass, 0.028349523125),
+    // Temperature, the base unit is kelvin
+    unit(&["K", "kelvin"], Dimension::Temperature, 1.0),
+    Unit { names: &["C", "°C", "degC", "celsius"], dimension: Dimension::Temperature, factor: 1.0, offset: 273.15 },
+    Unit {
+        names: &["F", "°F", "degF", "fahrenheit"],
+        dimension: Dimension::Temperature,
+        factor: 5.0 / 9.0,
+        offset: 459.67,
+    },
+    // Time, the base unit is second
+    unit(&["ms", "millisecond"], Dimension::Time, 0.001),
+    unit(&["s", "second"], Dimension::Time, 1.0),
+    unit(&["min", "minute"], Dimension::Time, 60.0),
+    unit(&["h", "hour"], Dimension::Time, 3600.0),
+    unit(&["d", "day"], Dimension::Time, 86400.0),
+    // Speed, the base unit is metre per second
+    unit(&["m/s", "mps"], Dimension::Speed, 1.0),
+    unit(&["km/h", "kph"], Dimension::Speed, 1.0 / 3.6),
+    unit(&["mph"], Dimension::Speed, 0.44704),
+    unit(&["kn", "knot"], Dimension::Speed, 1852.0 / 3600.0),
+    // Pressure, the base unit is pascal
+    unit(&["Pa", "pascal"], Dimension::Pressure, 1.0),
+    unit(&["hPa"], Dimension::Pressure, 100.0),
+    unit(&["kPa"], Dimension::Pressure, 1000.0),
+    unit(&["bar"], Dimension::Pressure, 100000.0),
+    unit(&["atm"], Dimension::Pressure, 101325.0),
+    unit(&["psi"], Dimension::Pressure, 6894.757293168361),
+    // Volume, the base unit is cubic metre
+    unit(&["m3", "m³"], Dimension::Volume, 1.0),
+    unit(&["L", "l", "litre", "liter"], Dimension::Volume, 0.001),
+    unit(&["mL", "ml"], Dimension::Volume, 0.000001),
+    unit(&["gal", "gallon"], Dimension::Volume, 0.003785411784),
+];
+
+fn find_unit(name: &str) -> Option<&'static Unit> {
+    let name = name.trim();
+    UNITS
+        .iter()
+        .find(|u| u.names.contains(&name))
+        .or_else(|| UNITS.iter().find(|u| u.names.iter().any(|x| x.eq_ignore_ascii_case(name))))
+}
+
+fn convert(value: f64, from: &str, to: &str) -> crate::Result<f64> {
+    let from_unit = find_unit(from)
+        .ok_or(EdgelinkError::BadArgument("from"))
+        .with_context(|| format!("Unknown unit: '{}'", from))?;
+    let to_unit =
+        find_unit(to).ok_or(EdgelinkError::BadArgument("to")).with_context(|| format!("Unknown unit: '{}'", to))?;
+    if from_unit.dimension != to_unit.dimension {
+        return Err(EdgelinkError::InvalidOperation(format!(
+            "Cannot convert '{}' ({:?}) to '{}' ({:?})",
+            from, from_unit.dimension, to, to_unit.dimension
+        ))
+        .into());
+    }
+    let base = (value + from_unit.offset) * from_unit.factor;
+    Ok(base / to_unit.factor - to_unit.offset)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct UnitConverterNodeConfig {
+    #[serde(default = "property_default")]
+    property: String,
+
+    from: String,
+
+    to: String,
+
+    /// The decimal places to round the result, keeps the full precision if absent
+    #[serde(default, deserialize_with = "json::deser::str_to_option_usize")]
+    precision: Option<usize>,
+}
+
+fn property_default() -> String {
+    "payload".to_string()
+}
+
+#[derive(Debug)]
+#[flow_node("unitconverter")]
+struct UnitConverterNode {
+    base: FlowNode,
+    config: UnitConverterNodeConfig,
+}
+
+impl UnitConverterNode {
+    fn build(
+        _flow: &Flow,
+        base_node: FlowNode,
+        config: &RedFlowNodeConfig,
+    ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let converter_config = UnitConverterNodeConfig::deserialize(&config.rest)?;
+        let node = UnitConverterNode { base: base_node, config: converter_config };
+        Ok(Box::new(node))
+    }
+
+    fn do_convert(&self, msg: &mut Msg) -> crate::Result<()> {
+        let value = msg.get_nav_stripped_mut(&self.config.property).ok_or(EdgelinkError::InvalidOperation(format!(
+            "Cannot find the property 'msg.{}'",
+            self.config.property
+        )))?;
+        let n = match &*value {
+            Variant::Number(num) => num.as_f64(),
+            Variant::String(s) => s.trim().parse::<f64>().ok(),
+            _ => None,
+        }
+        .ok_or_else(|| EdgelinkError::InvalidOperation(format!("The value is not a number: {:?}", value)))?;
+
+        let mut converted = convert(n, &self.config.from, &self.config.to)?;
+        if let Some(precision) = self.config.precision {
+            let scale = 10f64.powi(precision as i32);
+            converted = (converted * scale).round() / scale;
+        }
+        *value = Variant::from(converted);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for UnitConverterNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow_concurrent(&self, cancel.child_token(), |node, msg| async move {
+                {
+                    let mut msg_guard = msg.write().await;
+                    node.do_convert(&mut msg_guard)?;
+                }
+                node.fan_out_one(Envelope { port: 0, msg }, cancel.child_token()).await?;
+                Ok(())
+            })
+            .await;
+        }
+        wait_uows_completed(self.as_ref()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[test]
+    fn test_convert_units() {
+        assert!((convert(100.0, "C", "F").unwrap() - 212.0).abs() < 1e-9);
+        assert!((convert(-40.0, "degF", "celsius").unwrap() + 40.0).abs() < 1e-9);
+        assert!((convert(0.0, "C", "K").unwrap() - 273.15).abs() < 1e-9);
+        assert!((convert(1.0, "m", "ft").unwrap() - 3.280839895013123).abs() < 1e-12);
+        assert!((convert(10.0, "ft", "m").unwrap() - 3.048).abs() < 1e-12);
+        assert!(convert(1.0, "m", "C").is_err());
+        assert!(convert(1.0, "m", "parsec").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_it_should_convert_payload() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "unitconverter", "from": "C", "to": "F", "precision": "2", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject =
+            Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": 36.6}], ["1", {"payload": "-40"}]])).unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(2, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0]["payload"], Variant::from(97.88));
+        assert_eq!(msgs[1]["payload"], Variant::from(-40.0));
+    }
+
+    #[tokio::test]
+    async fn test_incompatible_units_should_be_caught() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "unitconverter", "from": "m", "to": "F", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"},
+            {"id": "3", "z": "100", "type": "catch", "wires": [["2"]]}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": 1}]])).unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0]["payload"], Variant::from(1));
+        assert!(msgs[0].get_nav_stripped("error.message").is_some());
+    }
+}
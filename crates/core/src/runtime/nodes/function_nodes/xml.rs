@@ -0,0 +1,473 @@
+use std::sync::Arc;
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde::Deserialize;
+
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+/// The name of the root element if the object has more than one property
+const DEFAULT_ROOT_NAME: &str = "root";
+
+/// The options of `xml2js` supported by the node.
+#[derive(Debug, Clone, Default, Deserialize)]
+struct XmlOptions {
+    /// Puts the child elements in arrays even if there's only one
+    #[serde(default, rename = "explicitArray")]
+    explicit_array: Option<bool>,
+
+    /// Puts the text under the `charkey` even if the element has no attributes or children
+    #[serde(default, rename = "explicitCharkey")]
+    explicit_charkey: Option<bool>,
+
+    #[serde(default)]
+    charkey: Option<String>,
+
+    #[serde(default)]
+    attrkey: Option<String>,
+
+    /// Omits the XML declaration in the serialization
+    #[serde(default)]
+    headless: Option<bool>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct XmlNodeConfig {
+    #[serde(default = "property_default")]
+    property: String,
+
+    /// The key of the attributes, `$` if empty
+    #[serde(default)]
+    attr: String,
+
+    /// The key of the text, `_` if empty
+    #[serde(default)]
+    chr: String,
+
+    #[serde(default)]
+    options: XmlOptions,
+}
+
+fn property_default() -> String {
+    "payload".to_string()
+}
+
+/// The effective options with the defaults of `xml2js`.
+#[derive(Debug, Clone)]
+struct XmlMapping {
+    explicit_array: bool,
+    explicit_charkey: bool,
+    charkey: String,
+    attrkey: String,
+    headless: bool,
+}
+
+impl XmlMapping {
+    fn new(config: &XmlNodeConfig) -> Self {
+        let or_default = |key: &str, default: &str| if key.is_empty() { default.to_string() } else { key.to_string() };
+        let options = &config.options;
+        XmlMapping {
+            explicit_array: options.explicit_array.unwrap_or(true),
+            explicit_charkey: options.explicit_charkey.unwrap_or(false),
+            charkey: options.charkey.clone().unwrap_or_else(|| or_default(&config.chr, "_")),
+            attrkey: options.attrkey.clone().unwrap_or_else(|| or_default(&config.attr, "$")),
+            headless: options.headless.unwrap_or(true),
+        }
+    }
+}
+
+/// An element being parsed.
+struct OpenElement {
+    name: String,
+    attrs: VariantObjectMap,
+    children: Vec<(String, Variant)>,
+    text: String,
+    has_cdata: bool,
+}
+
+impl OpenElement {
+    fn new(start: &BytesStart<'_>) -> crate::Result<Self> {
+        let mut attrs = VariantObjectMap::new();
+        for attr in start.attributes() {
+            let attr = attr?;
+            let key = String::from_utf8_lossy(attr.key.as_ref()).into_owned();
+            attrs.insert(key, Variant::String(attr.unescape_value()?.into_owned()));
+        }
+        Ok(OpenElement {
+            name: String::from_utf8_lossy(start.name().as_ref()).into_owned(),
+            attrs,
+            children: Vec::new(),
+            text: String::new(),
+            has_cdata: false,
+        })
+    }
+
+    /// Converts the element like `xml2js` does.
+    fn into_variant(self, mapping: &XmlMapping) -> Variant {
+        let mut obj = VariantObjectMap::new();
+        if !self.attrs.is_empty() {
+            obj.insert(mapping.attrkey.clone(), Variant::Object(self.attrs));
+        }
+        for (name, child) in self.children {
+            match obj.get_mut(&name) {
+                Some(Variant::Array(items)) if mapping.explicit_array => items.push(child),
+                Some(existing) => {
+                    // The repeated elements become an array
+                    let first = std::mem::replace(existing, Variant::Null);
+                    *existing = match first {
+                        Variant::Array(mut items) if !mapping.explicit_array => {
+                            items.push(child);
+                            Variant::Array(items)
+                        }
+                        first => Variant::Array(vec![first, child]),
+                    };
+                }
+                None if mapping.explicit_array => {
+                    obj.insert(name, Variant::Array(vec![child]));
+                }
+                None => {
+                    obj.insert(name, child);
+                }
+            }
+        }
+
+        // The whitespaces between the elements are not the text, unless they're in a CDATA section
+        let blank = self.text.trim().is_empty() && !self.has_cdata;
+        if obj.is_empty() && blank {
+            return Variant::String(self.text);
+        }
+        if !blank {
+            if obj.is_empty() && !mapping.explicit_charkey {
+                return Variant::String(self.text);
+            }
+            obj.insert(mapping.charkey.clone(), Variant::String(self.text));
+        }
+        Variant::Object(obj)
+    }
+}
+
+/// Parses the XML document into `{rootName: value}`, the namespace prefixes are kept in the names.
+fn parse_xml(text: &str, mapping: &XmlMapping) -> crate::Result<Variant> {
+    let mut reader = Reader::from_str(text);
+    reader.config_mut().trim_text(false);
+    let mut stack: Vec<OpenElement> = Vec::new();
+    let mut root: Option<(String, Variant)> = None;
+
+    let close = |element: OpenElement, stack: &mut Vec<OpenElement>, root: &mut Option<(String, Variant)>| {
+        let name = element.name.clone();
+        let value = element.into_variant(mapping);
+        match stack.last_mut() {
+            Some(parent) => parent.children.push((name, value)),
+            None => *root = Some((name, value)),
+        }
+    };
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(start) => {
+                if root.is_some() {
+                    return Err(EdgelinkError::InvalidOperation("The XML has more than one root element".into()).into());
+                }
+                stack.push(OpenElement::new(&start)?);
+            }
+            Event::Empty(start) => {
+                if root.is_some() {
+                    return Err(EdgelinkError::InvalidOperation("The XML has more than one root element".into()).into());
+                }
+                close(OpenElement::new(&start)?, &mut stack, &mut root);
+            }
+            Event::End(_) => {
+                let element = stack.pop().ok_or(EdgelinkError::InvalidOperation("Unexpected end tag".into()))?;
+                close(element, &mut stack, &mut root);
+            }
+            Event::Text(text) => {
+                if let Some(element) = stack.last_mut() {
+                    element.text.push_str(&text.unescape()?);
+                }
+            }
+            Event::CData(cdata) => {
+                if let Some(element) = stack.last_mut() {
+                    element.text.push_str(&String::from_utf8_lossy(&cdata));
+                    element.has_cdata = true;
+                }
+            }
+            Event::Eof => break,
+            // The declaration, comments, processing instructions and DTD are ignored
+            _ => {}
+        }
+    }
+
+    if let Some(element) = stack.last() {
+        return Err(EdgelinkError::InvalidOperation(format!("Unclosed element: <{}>", element.name)).into());
+    }
+    let (name, value) = root.ok_or(EdgelinkError::InvalidOperation("No root element in the XML".into()))?;
+    Ok(Variant::Object(VariantObjectMap::from([(name, value)])))
+}
+
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_' || c == ':')
+        && chars.all(|c| c.is_alphanumeric() || matches!(c, '_' | ':' | '-' | '.'))
+}
+
+fn text_of(value: &Variant) -> String {
+    match value {
+        Variant::String(s) => s.clone(),
+        Variant::Null => String::new(),
+        Variant::Number(_) | Variant::Bool(_) => value.to_string().unwrap_or_default(),
+        other => serde_json::to_string(other).unwrap_or_default(),
+    }
+}
+
+fn write_element(out: &mut String, name: &str, value: &Variant, mapping: &XmlMapping) -> crate::Result<()> {
+    if let Variant::Array(items) = value {
+        // An array is the repeated elements
+        for item in items.iter() {
+            write_element(out, name, item, mapping)?;
+        }
+        return Ok(());
+    }
+    if !is_valid_name(name) {
+        return Err(EdgelinkError::InvalidOperation(format!("Bad XML element name: '{}'", name)).into());
+    }
+
+    out.push('<');
+    out.push_str(name);
+    let obj = match value {
+        Variant::Object(obj) => obj,
+        Variant::Null => {
+            out.push_str("/>");
+            return Ok(());
+        }
+        other => {
+            out.push('>');
+            out.push_str(&quick_xml::escape::escape(text_of(other).as_str()));
+            out.push_str("</");
+            out.push_str(name);
+            out.push('>');
+            return Ok(());
+        }
+    };
+
+    if let Some(attrs) = obj.get(&mapping.attrkey).and_then(|x| x.as_object()) {
+        for (key, value) in attrs.iter() {
+            if !is_valid_name(key) {
+                return Err(EdgelinkError::InvalidOperation(format!("Bad XML attribute name: '{}'", key)).into());
+            }
+            out.push(' ');
+            out.push_str(key);
+            out.push_str("=\"");
+            out.push_str(&quick_xml::escape::escape(text_of(value).as_str()));
+            out.push('"');
+        }
+    }
+    let text = obj.get(&mapping.charkey).map(text_of).unwrap_or_default();
+    let mut children = obj.iter().filter(|(k, _)| **k != mapping.attrkey && **k != mapping.charkey).peekable();
+    if text.is_empty() && children.peek().is_none() {
+        out.push_str("/>");
+        return Ok(());
+    }
+    out.push('>');
+    out.push_str(&quick_xml::escape::escape(text.as_str()));
+    for (child_name, child) in children {
+        write_element(out, child_name, child, mapping)?;
+    }
+    out.push_str("</");
+    out.push_str(name);
+    out.push('>');
+    Ok(())
+}
+
+/// Serializes the object like the `Builder` of `xml2js`, the object with a single property is the root element,
+/// otherwise it's wrapped in `<root>`.
+///
+/// The properties are written in the order of their names.
+fn build_xml(value: &Variant, mapping: &XmlMapping) -> crate::Result<String> {
+    let mut out = String::new();
+    if !mapping.headless {
+        out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>");
+    }
+    let single_root = value.as_object().filter(|x| x.len() == 1).and_then(|x| x.iter().next()).filter(|(k, v)| {
+        **k != mapping.attrkey && **k != mapping.charkey && !matches!(v, Variant::Array(items) if items.len() != 1)
+    });
+    match single_root {
+        Some((name, Variant::Array(items))) => write_element(&mut out, name, &items[0], mapping)?,
+        Some((name, root)) => write_element(&mut out, name, root, mapping)?,
+        None => write_element(&mut out, DEFAULT_ROOT_NAME, value, mapping)?,
+    }
+    Ok(out)
+}
+
+/// Converts between the XML string and the object with the same conventions of the Node-RED `xml` node (`xml2js`),
+/// the attributes are under `$` and the child elements are under their names in arrays.
+#[derive(Debug)]
+#[flow_node("xml")]
+struct XmlNode {
+    base: FlowNode,
+    config: XmlNodeConfig,
+    mapping: XmlMapping,
+}
+
+impl XmlNode {
+    fn build(_flow: &Flow, state: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let xml_config = XmlNodeConfig::deserialize(&config.rest)?;
+        let mapping = XmlMapping::new(&xml_config);
+        if mapping.attrkey == mapping.charkey {
+            return Err(EdgelinkError::BadFlowsJson(format!(
+                "The attribute key and the text key of the xml node must differ: '{}'",
+                mapping.attrkey
+            ))
+            .into());
+        }
+        let node = XmlNode { base: state, config: xml_config, mapping };
+        Ok(Box::new(node))
+    }
+
+    fn convert(&self, msg: &mut Msg) -> crate::Result<()> {
+        let property = &self.config.property;
+        let converted = match msg.get_nav_stripped(property) {
+            Some(Variant::String(text)) => parse_xml(text, &self.mapping)?,
+            Some(value @ (Variant::Object(_) | Variant::Array(_))) => Variant::String(build_xml(value, &self.mapping)?),
+            Some(other) => {
+                return Err(EdgelinkError::NotSupported(format!(
+                    "The xml node only supports strings and objects, got: {:?}",
+                    other
+                ))
+                .into())
+            }
+            // Nothing to convert
+            None => return Ok(()),
+        };
+        msg.set_nav_stripped(property, converted, true)
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for XmlNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow_concurrent(&self, cancel.child_token(), |node, msg| async move {
+                {
+                    let mut msg_guard = msg.write().await;
+                    node.convert(&mut msg_guard)?;
+                }
+                node.fan_out_one(Envelope { port: 0, msg }, cancel.child_token()).await
+            })
+            .await;
+        }
+        wait_uows_completed(self.as_ref()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn mapping(options: serde_json::Value) -> XmlMapping {
+        let config = XmlNodeConfig::deserialize(json!({"options": options})).unwrap();
+        XmlMapping::new(&config)
+    }
+
+    const EMPLOYEES_XML: &str = r#"<?xml version="1.0"?>
+<!-- The staff -->
+<employees xmlns:hr="urn:hr" dept="R&amp;D">
+    <employee hr:id="1"><firstName>John</firstName><lastName>Smith</lastName></employee>
+    <employee hr:id="2"><firstName>Anna</firstName><lastName>Jones</lastName>
+        <hr:note><![CDATA[<b>new</b>]]></hr:note>
+    </employee>
+    <manager/>
+    <title lang="en">Engineers</title>
+</employees>"#;
+
+    #[test]
+    fn test_parse_with_explicit_arrays() {
+        let parsed = parse_xml(EMPLOYEES_XML, &mapping(json!({}))).unwrap();
+        let expected = json!({
+            "employees": {
+                "$": {"xmlns:hr": "urn:hr", "dept": "R&D"},
+                "employee": [
+                    {"$": {"hr:id": "1"}, "firstName": ["John"], "lastName": ["Smith"]},
+                    {"$": {"hr:id": "2"}, "firstName": ["Anna"], "lastName": ["Jones"], "hr:note": ["<b>new</b>"]}
+                ],
+                "manager": [""],
+                "title": [{"$": {"lang": "en"}, "_": "Engineers"}]
+            }
+        });
+        assert_eq!(parsed, Variant::from(expected));
+    }
+
+    #[test]
+    fn test_parse_without_explicit_arrays() {
+        let options = json!({"explicitArray": false, "explicitCharkey": true, "attrkey": "@", "charkey": "#"});
+        let parsed = parse_xml(EMPLOYEES_XML, &mapping(options)).unwrap();
+        let employees = parsed.get_nav("employees", &[]).unwrap();
+        assert_eq!(employees.get_nav("employee[0].firstName", &[]), Some(&Variant::from(json!({"#": "John"}))));
+        assert_eq!(employees.get_nav("employee[1]['hr:note']['#']", &[]), Some(&Variant::from("<b>new</b>")));
+        assert_eq!(
+            employees.get_nav("title", &[]),
+            Some(&Variant::from(json!({"@": {"lang": "en"}, "#": "Engineers"})))
+        );
+        assert_eq!(employees.get_nav("manager", &[]), Some(&Variant::from("")));
+    }
+
+    #[test]
+    fn test_malformed_xml_should_be_rejected() {
+        let mapping = mapping(json!({}));
+        assert!(parse_xml("<a><b></a>", &mapping).is_err());
+        assert!(parse_xml("<a>", &mapping).is_err());
+        assert!(parse_xml("<a/><b/>", &mapping).is_err());
+        assert!(parse_xml("just text", &mapping).is_err());
+    }
+
+    #[test]
+    fn test_build_xml() {
+        let mapping = mapping(json!({}));
+        let value = Variant::from(json!({
+            "employees": {
+                "$": {"xmlns:hr": "urn:hr"},
+                "employee": [
+                    {"$": {"hr:id": 1}, "firstName": ["John"], "note": "a < b & c"},
+                    {"$": {"hr:id": "2"}, "firstName": "Anna", "manager": null}
+                ]
+            }
+        }));
+        let expected = concat!(
+            r#"<employees xmlns:hr="urn:hr">"#,
+            r#"<employee hr:id="1"><firstName>John</firstName><note>a &lt; b &amp; c</note></employee>"#,
+            r#"<employee hr:id="2"><firstName>Anna</firstName><manager/></employee>"#,
+            "</employees>"
+        );
+        assert_eq!(build_xml(&value, &mapping).unwrap(), expected);
+
+        // More than one property
+        let value = Variant::from(json!({"a": 1, "b": {"_": "x", "$": {"k": "v"}}}));
+        assert_eq!(build_xml(&value, &mapping).unwrap(), r#"<root><a>1</a><b k="v">x</b></root>"#);
+        assert!(build_xml(&Variant::from(json!({"bad name": 1})), &mapping).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_it_should_convert_xml_back_and_forth() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "xml", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "xml", "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let xml = r#"<note id="7"><to>Tove</to><to>Jani</to><body>Don&apos;t forget me!</body></note>"#;
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": xml}]])).unwrap();
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(1, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+        // The elements are kept in their order
+        assert_eq!(msgs[0]["payload"], xml.into());
+    }
+}
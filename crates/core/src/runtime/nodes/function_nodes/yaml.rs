@@ -0,0 +1,238 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+#[derive(Debug, Clone, Deserialize)]
+struct YamlNodeConfig {
+    #[serde(default = "property_default")]
+    property: String,
+}
+
+fn property_default() -> String {
+    "payload".to_string()
+}
+
+/// Converts the YAML value, the tags are dropped and the scalar keys are converted to strings.
+fn yaml_to_variant(value: serde_yaml::Value) -> Result<Variant, String> {
+    Ok(match value {
+        serde_yaml::Value::Null => Variant::Null,
+        serde_yaml::Value::Bool(b) => Variant::Bool(b),
+        serde_yaml::Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                Variant::from(i)
+            } else if let Some(u) = n.as_u64() {
+                Variant::from(u)
+            } else {
+                // `.nan` and `.inf` are `null` like `JSON.stringify()` does
+                n.as_f64().map(Variant::from).unwrap_or(Variant::Null)
+            }
+        }
+        serde_yaml::Value::String(s) => Variant::String(s),
+        serde_yaml::Value::Sequence(items) => {
+            Variant::Array(items.into_iter().map(yaml_to_variant).collect::<Result<_, _>>()?)
+        }
+        serde_yaml::Value::Mapping(mapping) => {
+            let mut obj = VariantObjectMap::new();
+            for (key, value) in mapping.into_iter() {
+                let key = match key {
+                    serde_yaml::Value::String(s) => s,
+                    serde_yaml::Value::Number(n) => n.to_string(),
+                    serde_yaml::Value::Bool(b) => b.to_string(),
+                    serde_yaml::Value::Null => "null".to_string(),
+                    other => return Err(format!("Only the scalar keys are supported, got: {:?}", other)),
+                };
+                obj.insert(key, yaml_to_variant(value)?);
+            }
+            Variant::Object(obj)
+        }
+        serde_yaml::Value::Tagged(tagged) => yaml_to_variant(tagged.value)?,
+    })
+}
+
+/// Parses the YAML document, the aliases and the merge keys (`<<: *anchor`) are expanded, so the anchors are lost in
+/// the serialization.
+fn parse_yaml(text: &str) -> Result<Variant, String> {
+    let mut value: serde_yaml::Value = serde_yaml::from_str(text).map_err(|e| e.to_string())?;
+    value.apply_merge().map_err(|e| e.to_string())?;
+    yaml_to_variant(value)
+}
+
+/// Converts between the YAML string and the object or array.
+///
+/// The messages failed to parse are sent to the second output with the `error` property if the node has it,
+/// otherwise the error is reported to the `catch` nodes.
+#[derive(Debug)]
+#[flow_node("yaml")]
+struct YamlNode {
+    base: FlowNode,
+    config: YamlNodeConfig,
+}
+
+impl YamlNode {
+    fn build(_flow: &Flow, state: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let yaml_config = YamlNodeConfig::deserialize(&config.rest)?;
+        let node = YamlNode { base: state, config: yaml_config };
+        Ok(Box::new(node))
+    }
+
+    async fn uow(&self, msg: MsgHandle, cancel: CancellationToken) -> crate::Result<()> {
+        let port = {
+            let mut msg_guard = msg.write().await;
+            let property = &self.config.property;
+            let converted = match msg_guard.get_nav_stripped(property) {
+                Some(Variant::String(text)) => parse_yaml(text),
+                Some(value @ (Variant::Object(_) | Variant::Array(_))) => {
+                    Ok(Variant::String(serde_yaml::to_string(value)?))
+                }
+                Some(other) => {
+                    return Err(EdgelinkError::NotSupported(format!(
+                        "The yaml node only supports strings, objects and arrays, got: {:?}",
+                        other
+                    ))
+                    .into())
+                }
+                None => {
+                    return Err(
+                        EdgelinkError::InvalidOperation(format!("Cannot find the property 'msg.{}'", property)).into()
+                    )
+                }
+            };
+            match converted {
+                Ok(converted) => {
+                    msg_guard.set_nav_stripped(property, converted, true)?;
+                    0
+                }
+                Err(e) if self.get_node().ports.len() > 1 => {
+                    let error = VariantObjectMap::from([(
+                        "message".to_string(),
+                        Variant::String(format!("Failed to parse the YAML: {}", e)),
+                    )]);
+                    msg_guard.set("error".to_string(), Variant::Object(error));
+                    1
+                }
+                Err(e) => {
+                    return Err(EdgelinkError::InvalidOperation(format!("Failed to parse the YAML: {}", e)).into())
+                }
+            }
+        };
+        self.fan_out_one(Envelope { port, msg }, cancel).await
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for YamlNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow_concurrent(&self, cancel.child_token(), |node, msg| async move { node.uow(msg, cancel).await })
+                .await;
+        }
+        wait_uows_completed(self.as_ref()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_scalars_and_coercion() {
+        assert_eq!(parse_yaml("42").unwrap(), Variant::from(42));
+        assert_eq!(parse_yaml("-1.5").unwrap(), Variant::from(-1.5));
+        assert_eq!(parse_yaml("hello").unwrap(), Variant::from("hello"));
+        assert_eq!(parse_yaml("'42'").unwrap(), Variant::from("42"));
+        assert_eq!(parse_yaml(".nan").unwrap(), Variant::Null);
+        let expected = json!({
+            "t": true, "f": false, "upper": true, "yes": "yes", "off": "off",
+            "tilde": null, "null": null, "empty": null, "quoted": "null", "3": "three"
+        });
+        let text = "t: true\nf: false\nupper: TRUE\nyes: yes\noff: off\n\
+                    tilde: ~\nnull: null\nempty:\nquoted: 'null'\n3: three\n";
+        let parsed = parse_yaml(text).unwrap();
+        assert_eq!(parsed, Variant::from(expected));
+    }
+
+    #[test]
+    fn test_parse_nested_objects_and_multiline_strings() {
+        let text = r#"
+server:
+  host: example.com
+  ports: [80, 443]
+  tags:
+    - name: a
+      weight: 0.5
+    - name: b
+literal: |
+  line 1
+  line 2
+folded: >
+  folded
+  text
+"#;
+        let expected = json!({
+            "server": {
+                "host": "example.com",
+                "ports": [80, 443],
+                "tags": [{"name": "a", "weight": 0.5}, {"name": "b"}]
+            },
+            "literal": "line 1\nline 2\n",
+            "folded": "folded text\n"
+        });
+        assert_eq!(parse_yaml(text).unwrap(), Variant::from(expected));
+    }
+
+    #[test]
+    fn test_anchors_should_be_expanded() {
+        let text = r#"
+base: &base
+  retries: 3
+  timeout: 10
+dev:
+  <<: *base
+  timeout: 1
+list: [*base, *base]
+"#;
+        let parsed = parse_yaml(text).unwrap();
+        assert_eq!(parsed.get_nav("dev", &[]), Some(&Variant::from(json!({"retries": 3, "timeout": 1}))));
+        assert_eq!(parsed.get_nav("list[1].retries", &[]), Some(&Variant::from(3)));
+        assert!(parse_yaml("a: *missing").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_it_should_convert_yaml_back_and_forth() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "yaml", "wires": [["2"], ["3"]]},
+            {"id": "2", "z": "100", "type": "yaml", "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"payload": "a: 1\nb:\n  - true\n  - null\n  - x\nc:\n  d: |\n    multi\n    line\n"}],
+            ["1", {"payload": "a: [1, 2"}],
+        ]))
+        .unwrap();
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs =
+            engine.run_once_with_inject(2, std::time::Duration::from_secs_f64(0.2), msgs_to_inject).await.unwrap();
+
+        // The failed one is not serialized again
+        let (failed, converted): (Vec<_>, Vec<_>) = msgs.into_iter().partition(|x| x.contains("error"));
+        assert_eq!(failed[0]["payload"], "a: [1, 2".into());
+        assert!(failed[0].get_nav_stripped("error.message").unwrap().as_str().unwrap().contains("YAML"));
+
+        let text = converted[0]["payload"].as_str().unwrap();
+        assert!(text.starts_with("a: 1\n"), "{}", text);
+        let expected = json!({"a": 1, "b": [true, null, "x"], "c": {"d": "multi\nline\n"}});
+        assert_eq!(parse_yaml(text).unwrap(), Variant::from(expected));
+    }
+}
@@ -9,6 +9,7 @@ use tokio::select;
 use tokio_util::sync::CancellationToken;
 
 use super::context::Context;
+use super::metrics::NodeMetrics;
 use crate::runtime::env::*;
 use crate::runtime::flow::*;
 use crate::runtime::model::json::{RedFlowNodeConfig, RedGlobalNodeConfig};
@@ -26,6 +27,11 @@ mod storage_nodes;
 mod network_nodes;
 
 pub const NODE_MSG_CHANNEL_CAPACITY: usize = 16;
+pub const NODE_EVENT_CHANNEL_CAPACITY: usize = 16;
+
+/// The maximum time an ordered node waits for the downstream nodes, to avoid stalling on a node which neither reports
+/// the completion nor drops the message
+pub const ORDERED_SEND_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
 
 #[derive(Debug, Clone, Copy)]
 pub enum NodeState {
@@ -77,17 +83,35 @@ pub struct FlowNode {
     pub ordering: usize,
     pub disabled: bool,
     pub active: bool,
+
+    /// Waits until the downstream nodes have done with each sent message before sending the next one
+    pub ordered: bool,
     pub flow: WeakFlow,
     pub msg_tx: MsgSender,
-    pub msg_rx: MsgReceiverHolder,
+    pub msg_rx: Arc<MsgReceiverHolder>,
+
+    /// The overflow policy of the wires sending messages to this node
+    pub overflow: OverflowPolicy,
+
+    /// The max count of the messages processed at the same time, see `with_uow_concurrent()`
+    pub concurrency: usize,
+    pub uow_permits: Arc<tokio::sync::Semaphore>,
     pub ports: Vec<Port>,
     pub group: Option<WeakGroup>,
     pub envs: Envs,
     pub context: Arc<Context>,
 
+    /// The counters exported by the `/metrics` endpoint
+    pub metrics: NodeMetrics,
+
+    /// The registry of the metrics created by the node itself, see `Engine::metrics_registry()`
+    #[cfg(feature = "metrics")]
+    pub metrics_registry: prometheus::Registry,
+
     pub on_received: MsgEventSender,
     pub on_completed: MsgEventSender,
     pub on_error: MsgEventSender,
+    pub on_sent: MsgSentEventSender,
 }
 
 #[derive(Debug)]
@@ -131,7 +155,9 @@ pub trait FlowNodeBehavior: Send + Sync + FlowsElement {
         self.get_node().flow.upgrade()?.engine()
     }
 
-    async fn inject_msg(&self, msg: MsgHandle, cancel: CancellationToken) -> crate::Result<()> {
+    async fn inject_msg(&self, mut msg: MsgHandle, cancel: CancellationToken) -> crate::Result<()> {
+        msg.untrack_completion_of(&self.id());
+        msg.begin_hop();
         select! {
             result = self.get_node().msg_tx.send(msg) => result.map_err(|e| e.into()),
             _ = cancel.cancelled() => Err(EdgelinkError::TaskCancelled.into()),
@@ -140,6 +166,7 @@ pub trait FlowNodeBehavior: Send + Sync + FlowsElement {
 
     async fn recv_msg(&self, stop_token: CancellationToken) -> crate::Result<MsgHandle> {
         let msg = self.get_node().msg_rx.recv_msg(stop_token).await?;
+        self.get_node().metrics.inc_received();
         if self.get_node().on_received.receiver_count() > 0 {
             self.get_node().on_received.send(msg.clone())?;
         }
@@ -147,6 +174,7 @@ pub trait FlowNodeBehavior: Send + Sync + FlowsElement {
     }
 
     async fn notify_uow_completed(&self, msg: MsgHandle, cancel: CancellationToken) {
+        msg.end_hop();
         let (node_id, flow) = { (self.id(), self.get_node().flow.upgrade()) };
         if let Some(flow) = flow {
             flow.notify_node_uow_completed(&node_id, msg, cancel).await;
@@ -166,12 +194,40 @@ pub trait FlowNodeBehavior: Send + Sync + FlowsElement {
         }
 
         let port = &self.get_node().ports[envelope.port];
+        self.get_node().metrics.inc_sent();
+
+        if self.get_node().on_sent.receiver_count() > 0 {
+            let event = MsgSentEvent { port: envelope.port, msg: envelope.msg.clone(), at: std::time::Instant::now() };
+            let _ = self.get_node().on_sent.send(event);
+        }
 
         let mut msg_sent = false;
         for wire in port.wires.iter() {
-            let msg_to_send = if msg_sent { envelope.msg.deep_clone(true).await } else { envelope.msg.clone() };
-
-            wire.tx(msg_to_send, cancel.clone()).await?;
+            if self.get_node().ordered {
+                // Sends a copy, so the completion is tracked for this send only
+                let mut msg_to_send = envelope.msg.deep_clone(msg_sent).await;
+                let completed = msg_to_send.track_completion(self.id());
+                if let Some(dropped) = wire.tx(msg_to_send, cancel.clone()).await? {
+                    notify_dropped_completed(self.engine(), wire, dropped, cancel.clone()).await;
+                }
+                select! {
+                    _ = completed => {}
+                    _ = tokio::time::sleep(ORDERED_SEND_TIMEOUT) => {
+                        log::warn!(
+                            "[{}:{}] Timed out waiting for the node '{}' to be done with the ordered message",
+                            self.type_str(),
+                            self.name(),
+                            wire.target_node_id
+                        );
+                    }
+                    _ = cancel.cancelled() => return Err(EdgelinkError::TaskCancelled.into()),
+                }
+            } else {
+                let msg_to_send = if msg_sent { envelope.msg.deep_clone(true).await } else { envelope.msg.clone() };
+                if let Some(dropped) = wire.tx(msg_to_send, cancel.clone()).await? {
+                    notify_dropped_completed(self.engine(), wire, dropped, cancel.clone()).await;
+                }
+            }
             msg_sent = true;
         }
         Ok(())
@@ -190,6 +246,7 @@ pub trait FlowNodeBehavior: Send + Sync + FlowsElement {
     }
 
     async fn report_error(&self, log_message: String, msg: MsgHandle, cancel: CancellationToken) {
+        self.get_node().metrics.inc_errors();
         let handled = if let Some(flow) = self.flow() {
             let node = self.as_any().downcast_ref::<Arc<dyn FlowNodeBehavior>>().unwrap(); // FIXME
             flow.handle_error(node.as_ref(), &log_message, Some(msg), None, cancel).await.unwrap_or(false)
@@ -201,6 +258,16 @@ pub trait FlowNodeBehavior: Send + Sync + FlowsElement {
         }
     }
 
+    /// Reports a fatal error and stops the node, the flow will also be stopped if `runtime.flow.on_fatal` is `"flow"`.
+    fn report_fatal(&self, log_message: &str, stop_token: &CancellationToken) {
+        if let Some(flow) = self.flow() {
+            flow.handle_fatal_error(self.get_node(), log_message);
+        } else {
+            log::error!("[{}:{}] Fatal error! {}", self.type_str(), self.name(), log_message);
+        }
+        stop_token.cancel();
+    }
+
     // events
     fn on_loaded(&self) {}
     async fn on_starting(&self) {}
@@ -259,29 +326,121 @@ where
     T: std::future::Future<Output = crate::Result<()>>,
 {
     match node.recv_msg(cancel.clone()).await {
-        Ok(msg) => {
-            if let Err(ref err) = proc(node, msg.clone()).await {
-                let flow = node.flow().expect(FLOW_STR);
-                let error_message = err.to_string();
-
-                match flow.handle_error(node, &error_message, Some(msg.clone()), None, cancel.clone()).await {
-                    Ok(_) => (),
-                    Err(e) => {
-                        log::error!("Failed to handle error: {:?}", e);
-                    }
-                }
-            }
+        Ok(msg) => handle_uow(node, msg, cancel, proc).await,
+        Err(ref err) => log_recv_error(node, err),
+    }
+}
 
-            // Report the completion
-            node.notify_uow_completed(msg, cancel.clone()).await;
+/// Like `with_uow()`, but up to `concurrency` messages of the node are processed at the same time, each one in its
+/// own task, so the messages may be sent in a different order than they were received.
+///
+/// The node should call `wait_uows_completed()` before its `run()` returns.
+pub async fn with_uow_concurrent<B, F, T>(node: &Arc<B>, cancel: CancellationToken, proc: F)
+where
+    B: FlowNodeBehavior + 'static,
+    F: FnOnce(Arc<B>, MsgHandle) -> T + Send + 'static,
+    T: std::future::Future<Output = crate::Result<()>> + Send + 'static,
+{
+    if node.get_node().concurrency <= 1 {
+        // The serial unit of work
+        return with_uow(node.as_ref(), cancel, |_, msg| proc(node.clone(), msg)).await;
+    }
+
+    // Takes no more messages than we can process
+    let permit = select! {
+        permit = node.get_node().uow_permits.clone().acquire_owned() => permit.expect("The semaphore is never closed"),
+        _ = cancel.cancelled() => return,
+    };
+    match node.recv_msg(cancel.clone()).await {
+        Ok(msg) => {
+            let node = node.clone();
+            tokio::spawn(async move {
+                let proc_node = node.clone();
+                handle_uow(node.as_ref(), msg, cancel, |_, msg| proc(proc_node, msg)).await;
+                drop(permit);
+            });
         }
-        Err(ref err) => {
-            if let Some(EdgelinkError::TaskCancelled) = err.downcast_ref::<EdgelinkError>() {
-                return;
+        Err(ref err) => log_recv_error(node.as_ref(), err),
+    }
+}
+
+/// Waits for the messages still being processed by `with_uow_concurrent()`.
+pub async fn wait_uows_completed<B: FlowNodeBehavior>(node: &B) {
+    let concurrency = node.get_node().concurrency;
+    if concurrency > 1 {
+        let _ = node.get_node().uow_permits.acquire_many(concurrency as u32).await;
+    }
+}
+
+async fn handle_uow<'a, B, F, T>(node: &'a B, msg: MsgHandle, cancel: CancellationToken, proc: F)
+where
+    B: FlowNodeBehavior,
+    F: FnOnce(&'a B, MsgHandle) -> T,
+    T: std::future::Future<Output = crate::Result<()>>,
+{
+    if !check_msg_ttl(node, &msg).await {
+        // The expired msg is done as well, the complete nodes watching this node are still notified
+        node.notify_uow_completed(msg, cancel).await;
+        return;
+    }
+
+    let started_at = std::time::Instant::now();
+    let result = proc(node, msg.clone()).await;
+    node.get_node().metrics.record_latency(started_at.elapsed());
+
+    if let Err(ref err) = result {
+        node.get_node().metrics.inc_errors();
+        let flow = node.flow().expect(FLOW_STR);
+        let error_message = err.to_string();
+
+        match flow.handle_error(node, &error_message, Some(msg.clone()), None, cancel.clone()).await {
+            Ok(_) => (),
+            Err(e) => {
+                log::error!("Failed to handle error: {:?}", e);
             }
+        }
+    }
+
+    // Report the completion
+    node.notify_uow_completed(msg, cancel.clone()).await;
+}
+
+/// Reports the completion of the message dropped by the overflow policy of the wire on behalf of its target node, like
+/// a Node-RED node calls `done()` for the messages it drops.
+async fn notify_dropped_completed(engine: Option<Engine>, wire: &PortWire, msg: MsgHandle, cancel: CancellationToken) {
+    if let Some(target) = engine.and_then(|x| x.find_flow_node_by_id(&wire.target_node_id)) {
+        target.notify_uow_completed(msg, cancel).await;
+    }
+}
+
+fn log_recv_error<B: FlowNodeBehavior>(node: &B, err: &anyhow::Error) {
+    if let Some(EdgelinkError::TaskCancelled) = err.downcast_ref::<EdgelinkError>() {
+        return;
+    }
 
-            log::warn!("[{}:{}] {}", node.type_str(), node.name(), err);
+    log::warn!("[{}:{}] {}", node.type_str(), node.name(), err);
+}
+
+/// Checks the TTL of the msg if the engine enables it, returns `false` if the msg has expired and should be dropped.
+///
+/// The msgs injected to the engine have been stamped by `Engine::inject_msg()`, the ones created by a node start the
+/// TTL at their first hop.
+async fn check_msg_ttl<B: FlowNodeBehavior>(node: &B, msg: &MsgHandle) -> bool {
+    let Some(ttl) = node.engine().and_then(|x| x.msg_ttl()) else {
+        return true;
+    };
+    let expires_at = msg.read().await.expires_at();
+    match expires_at {
+        None => {
+            msg.write().await.set_ttl(ttl);
+            true
+        }
+        Some(x) if x <= tokio::time::Instant::now() => {
+            let msg_id = msg.read().await.id();
+            log::warn!("[{}:{}] Dropped the expired message: {:?}", node.type_str(), node.name(), msg_id);
+            false
         }
+        Some(_) => true,
     }
 }
 
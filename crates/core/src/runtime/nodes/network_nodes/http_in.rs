@@ -0,0 +1,559 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use axum::body::{Body, Bytes};
+use axum::extract::{ConnectInfo, State};
+use axum::http::{HeaderMap, Method, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use serde::Deserialize;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+/// The response completed by the `http response` node.
+#[derive(Debug)]
+pub(super) struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl IntoResponse for HttpResponse {
+    fn into_response(self) -> Response {
+        let mut builder = axum::http::Response::builder().status(self.status);
+        for (name, value) in self.headers.iter() {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        builder.body(Body::from(self.body)).unwrap_or_else(|e| {
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("Bad response of the flow: {}", e)).into_response()
+        })
+    }
+}
+
+/// The requests waiting for the `http response` node, keyed by the random token in `msg.res`
+type PendingResponses = DashMap<u128, oneshot::Sender<HttpResponse>>;
+
+/// The HTTP servers and the pending requests of the `http in` nodes of an engine, see `Engine::shared_state()`.
+#[derive(Debug, Default)]
+pub(super) struct HttpInState {
+    servers: tokio::sync::Mutex<HashMap<String, Arc<HttpInServer>>>,
+    pending_responses: Arc<PendingResponses>,
+}
+
+impl HttpInState {
+    /// Takes the sender of the request by the `msg.res`, the request can be responded only once.
+    pub(super) fn take_response_sender(&self, res: &Variant) -> Option<oneshot::Sender<HttpResponse>> {
+        let token = u128::from_be_bytes(res.as_bytes()?.try_into().ok()?);
+        self.pending_responses.remove(&token).map(|(_, sender)| sender)
+    }
+}
+
+/// The request registered in the `PendingResponses`, it is removed when the request is completed or the client
+/// disconnects, which drops the future of the handler.
+struct PendingResponse {
+    pending_responses: Arc<PendingResponses>,
+    token: u128,
+}
+
+impl PendingResponse {
+    fn register(pending_responses: &Arc<PendingResponses>, sender: oneshot::Sender<HttpResponse>) -> Self {
+        // The token is random, so a flow cannot respond to the requests of the others by guessing the `msg.res`
+        let token = loop {
+            let token = rand::random::<u128>();
+            if let Entry::Vacant(entry) = pending_responses.entry(token) {
+                entry.insert(sender);
+                break token;
+            }
+        };
+        PendingResponse { pending_responses: pending_responses.clone(), token }
+    }
+}
+
+impl Drop for PendingResponse {
+    fn drop(&mut self) {
+        self.pending_responses.remove(&self.token);
+    }
+}
+
+#[derive(Debug)]
+struct HttpRoute {
+    node_id: ElementId,
+
+    /// The method in upper case, `None` matches all methods
+    method: Option<String>,
+
+    /// The segments of the path, a segment like `:id` matches any segment and captures it into `msg.req.params`
+    segments: Vec<String>,
+
+    timeout: Duration,
+    sender: mpsc::Sender<Msg>,
+}
+
+impl HttpRoute {
+    fn matches(&self, method: &Method, path: &str) -> Option<VariantObjectMap> {
+        if self.method.as_deref().is_some_and(|x| x != method.as_str()) {
+            return None;
+        }
+        let segments: Vec<&str> = path.split('/').filter(|x| !x.is_empty()).collect();
+        if segments.len() != self.segments.len() {
+            return None;
+        }
+        let mut params = VariantObjectMap::new();
+        for (pattern, segment) in self.segments.iter().zip(segments) {
+            match pattern.strip_prefix(':') {
+                Some(name) => {
+                    params.insert(name.to_string(), Variant::String(decode_component(segment)));
+                }
+                None if pattern == segment => {}
+                None => return None,
+            }
+        }
+        Some(params)
+    }
+}
+
+/// The HTTP server shared by the `http in` nodes listening on the same address
+#[derive(Debug)]
+struct HttpInServer {
+    local_addr: SocketAddr,
+    routes: RwLock<Vec<HttpRoute>>,
+    pending_responses: Arc<PendingResponses>,
+    cancel: CancellationToken,
+}
+
+/// The key of the server in `HttpInState::servers`, the port `0` binds an ephemeral port for every node.
+fn server_key(listen: &str, node_id: ElementId) -> String {
+    if listen.ends_with(":0") {
+        format!("{}#{}", listen, node_id)
+    } else {
+        listen.to_string()
+    }
+}
+
+impl HttpInServer {
+    /// Adds the route to the server of the address, the server is started if it is not running.
+    async fn add_route(state: &HttpInState, listen: &str, route: HttpRoute) -> crate::Result<()> {
+        let mut servers = state.servers.lock().await;
+        let key = server_key(listen, route.node_id);
+        let server = match servers.get(&key) {
+            Some(server) => server.clone(),
+            None => {
+                let listener = tokio::net::TcpListener::bind(listen)
+                    .await
+                    .with_context(|| format!("Failed to listen on '{}'", listen))?;
+                let server = Arc::new(HttpInServer {
+                    local_addr: listener.local_addr()?,
+                    routes: RwLock::new(Vec::new()),
+                    pending_responses: state.pending_responses.clone(),
+                    cancel: CancellationToken::new(),
+                });
+                let app = axum::Router::new().fallback(handle_request).with_state(server.clone());
+                let shutdown = server.cancel.clone().cancelled_owned();
+                let local_addr = server.local_addr;
+                tokio::spawn(async move {
+                    let app = app.into_make_service_with_connect_info::<SocketAddr>();
+                    if let Err(e) = axum::serve(listener, app).with_graceful_shutdown(shutdown).await {
+                        log::error!("[HTTP_IN] The server on {} failed: {}", local_addr, e);
+                    }
+                });
+                log::info!("[HTTP_IN] Started the server on {}", local_addr);
+                servers.insert(key, server.clone());
+                server
+            }
+        };
+        server.routes.write().expect("The routes lock is poisoned").push(route);
+        Ok(())
+    }
+
+    /// Removes the routes of the node, the server is stopped if it has no routes left.
+    async fn remove_routes(state: &HttpInState, listen: &str, node_id: ElementId) {
+        let mut servers = state.servers.lock().await;
+        let key = server_key(listen, node_id);
+        let is_empty = match servers.get(&key) {
+            Some(server) => {
+                let mut routes = server.routes.write().expect("The routes lock is poisoned");
+                routes.retain(|x| x.node_id != node_id);
+                routes.is_empty()
+            }
+            None => return,
+        };
+        if is_empty {
+            if let Some(server) = servers.remove(&key) {
+                server.cancel.cancel();
+                log::info!("[HTTP_IN] Stopped the server on {}", server.local_addr);
+            }
+        }
+    }
+
+    fn find_route(&self, method: &Method, path: &str) -> Option<(mpsc::Sender<Msg>, Duration, VariantObjectMap)> {
+        let routes = self.routes.read().expect("The routes lock is poisoned");
+        routes.iter().find_map(|x| x.matches(method, path).map(|params| (x.sender.clone(), x.timeout, params)))
+    }
+}
+
+async fn handle_request(
+    State(server): State<Arc<HttpInServer>>,
+    ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let (sender, timeout, params) = match server.find_route(&method, uri.path()) {
+        Some(route) => route,
+        None => return (StatusCode::NOT_FOUND, format!("Cannot {} {}", method, uri.path())).into_response(),
+    };
+    let mut msg = match make_msg(&method, &uri, &headers, params, &body, remote_addr) {
+        Ok(msg) => msg,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+
+    let (res_sender, res_receiver) = oneshot::channel();
+    let pending = PendingResponse::register(&server.pending_responses, res_sender);
+    msg.set("res".to_string(), Variant::Bytes(pending.token.to_be_bytes().to_vec()));
+    if sender.send(msg).await.is_err() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "The flow has been stopped").into_response();
+    }
+
+    match tokio::time::timeout(timeout, res_receiver).await {
+        Ok(Ok(response)) => response.into_response(),
+        Ok(Err(_)) => (StatusCode::INTERNAL_SERVER_ERROR, "The response has been dropped").into_response(),
+        Err(_) => (StatusCode::GATEWAY_TIMEOUT, "The flow did not respond in time").into_response(),
+    }
+}
+
+/// Makes the message of the request, the `msg.req` holds the method, the URL, the headers, the query, the route
+/// parameters and the IP address of the client, and the `msg.payload` is the query for `GET` or the parsed body.
+fn make_msg(
+    method: &Method,
+    uri: &Uri,
+    headers: &HeaderMap,
+    params: VariantObjectMap,
+    body: &Bytes,
+    remote_addr: SocketAddr,
+) -> crate::Result<Msg> {
+    let query = parse_urlencoded(uri.query().unwrap_or_default().as_bytes())?;
+    let mut header_map = VariantObjectMap::new();
+    for (name, value) in headers.iter() {
+        header_map.insert(name.as_str().to_string(), Variant::String(String::from_utf8_lossy(value.as_bytes()).into()));
+    }
+    let content_type = headers
+        .get(axum::http::header::CONTENT_TYPE)
+        .and_then(|x| x.to_str().ok())
+        .map(|x| x.split(';').next().unwrap_or_default().trim().to_ascii_lowercase())
+        .unwrap_or_default();
+
+    let payload = if *method == Method::GET {
+        query.clone()
+    } else if content_type == "application/json" || content_type.ends_with("+json") {
+        serde_json::from_slice::<Variant>(body).context("Bad JSON body")?
+    } else if content_type == "application/x-www-form-urlencoded" {
+        parse_urlencoded(body)?
+    } else if content_type.starts_with("text/") {
+        Variant::String(String::from_utf8_lossy(body).into())
+    } else {
+        Variant::Bytes(body.to_vec())
+    };
+
+    let req = VariantObjectMap::from([
+        ("method".to_string(), Variant::String(method.to_string())),
+        ("url".to_string(), Variant::String(uri.to_string())),
+        ("path".to_string(), Variant::String(uri.path().to_string())),
+        ("headers".to_string(), Variant::Object(header_map)),
+        ("query".to_string(), query),
+        ("params".to_string(), Variant::Object(params)),
+        ("ip".to_string(), Variant::from(remote_addr.ip())),
+    ]);
+    let mut msg = MsgBuilder::new().build()?;
+    msg.set("payload".to_string(), payload);
+    msg.set("req".to_string(), Variant::Object(req));
+    Ok(msg)
+}
+
+/// Parses the `a=1&b=2` into an object, the values of a repeated key are collected into an array.
+fn parse_urlencoded(input: &[u8]) -> crate::Result<Variant> {
+    let pairs: Vec<(String, String)> = serde_urlencoded::from_bytes(input).context("Bad URL encoded data")?;
+    let mut map = VariantObjectMap::new();
+    for (key, value) in pairs {
+        match map.get_mut(&key) {
+            Some(Variant::Array(values)) => values.push(Variant::String(value)),
+            Some(existed) => *existed = Variant::Array(vec![existed.clone(), Variant::String(value)]),
+            None => {
+                map.insert(key, Variant::String(value));
+            }
+        }
+    }
+    Ok(Variant::Object(map))
+}
+
+/// Decodes the percent-encoded path segment, the `+` is kept as is unlike the query.
+fn decode_component(segment: &str) -> String {
+    let mut bytes = Vec::with_capacity(segment.len());
+    let mut i = 0;
+    while i < segment.len() {
+        let decoded = match segment.as_bytes()[i] {
+            b'%' => segment.get(i + 1..i + 3).and_then(|x| u8::from_str_radix(x, 16).ok()),
+            _ => None,
+        };
+        match decoded {
+            Some(byte) => {
+                bytes.push(byte);
+                i += 3;
+            }
+            None => {
+                bytes.push(segment.as_bytes()[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into()
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct HttpInNodeConfig {
+    /// The path like `/users/:id`
+    url: String,
+
+    /// The method in lower case, `use` accepts all methods
+    #[serde(default = "method_default")]
+    method: String,
+}
+
+fn method_default() -> String {
+    "get".to_string()
+}
+
+/// Accepts the HTTP requests and sends them as messages, the `http response` node replies with the `msg.res`.
+#[derive(Debug)]
+#[flow_node("http in")]
+struct HttpInNode {
+    base: FlowNode,
+    config: HttpInNodeConfig,
+}
+
+impl HttpInNode {
+    fn build(
+        _flow: &Flow,
+        base_node: FlowNode,
+        config: &RedFlowNodeConfig,
+    ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let http_config = HttpInNodeConfig::deserialize(&config.rest)?;
+        if http_config.url.trim().is_empty() {
+            return Err(EdgelinkError::BadFlowsJson("The URL of the `http in` node cannot be empty".into()).into());
+        }
+        let node = HttpInNode { base: base_node, config: http_config };
+        Ok(Box::new(node))
+    }
+
+    fn make_route(&self, timeout: Duration, sender: mpsc::Sender<Msg>) -> HttpRoute {
+        let method = self.config.method.trim().to_ascii_uppercase();
+        HttpRoute {
+            node_id: self.id(),
+            method: if method == "USE" { None } else { Some(method) },
+            segments: self.config.url.split('/').filter(|x| !x.is_empty()).map(String::from).collect(),
+            timeout,
+            sender,
+        }
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for HttpInNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        let Some(engine) = self.engine() else {
+            log::error!("[HTTP_IN:{}] The engine has been dropped", self.name());
+            return;
+        };
+        let args = engine.http_args().clone();
+        let state = engine.shared_state::<HttpInState>();
+        drop(engine);
+        let (sender, mut receiver) = mpsc::channel(16);
+        let route = self.make_route(Duration::from_millis(args.timeout_ms), sender);
+        if let Err(e) = HttpInServer::add_route(&state, &args.listen, route).await {
+            log::error!("[HTTP_IN:{}] Failed to serve '{}': {:?}", self.name(), self.config.url, e);
+            stop_token.cancelled().await;
+            return;
+        }
+
+        loop {
+            let msg = tokio::select! {
+                _ = stop_token.cancelled() => break,
+                msg = receiver.recv() => match msg {
+                    Some(msg) => MsgHandle::new(msg),
+                    None => break,
+                },
+            };
+            self.notify_uow_completed(msg.clone(), stop_token.clone()).await;
+            if let Err(e) = self.fan_out_one(Envelope { port: 0, msg }, stop_token.child_token()).await {
+                log::warn!("[HTTP_IN:{}] Failed to send the request: {}", self.name(), e);
+            }
+        }
+
+        HttpInServer::remove_routes(&state, &args.listen, self.id()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn build_engine(flows_json: serde_json::Value, timeout_ms: u64) -> crate::runtime::engine::Engine {
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let cfg = config::Config::builder()
+            .add_source(config::File::from_str(
+                &format!("[runtime.engine.http]\nlisten = \"127.0.0.1:0\"\ntimeout_ms = {}", timeout_ms),
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap();
+        crate::runtime::engine::Engine::with_json(&registry, flows_json, Some(&cfg)).unwrap()
+    }
+
+    /// The base URL of the ephemeral server of the node, waits until it is started.
+    async fn base_url_of(engine: &crate::runtime::engine::Engine, node_id: &str) -> String {
+        let key = server_key("127.0.0.1:0", node_id.parse().unwrap());
+        let state = engine.shared_state::<HttpInState>();
+        for _ in 0..100 {
+            if let Some(server) = state.servers.lock().await.get(&key) {
+                return format!("http://{}", server.local_addr);
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("The server of the node '{}' is not started", node_id);
+    }
+
+    #[tokio::test]
+    async fn test_it_should_respond_with_query_and_params() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "a1", "z": "100", "type": "http in", "url": "/hello", "method": "get", "wires": [["a2"]]},
+            {"id": "a2", "z": "100", "type": "change", "wires": [["a9"]], "rules": [
+                {"t": "set", "p": "payload", "pt": "msg", "to": "req.query.name", "tot": "msg"}
+            ]},
+            {"id": "a3", "z": "100", "type": "http in", "url": "/items/:id", "method": "post", "wires": [["a4"]]},
+            {"id": "a4", "z": "100", "type": "change", "wires": [["a9"]], "rules": [
+                {"t": "set", "p": "payload.id", "pt": "msg", "to": "req.params.id", "tot": "msg"},
+                {"t": "set", "p": "payload.ip", "pt": "msg", "to": "req.ip", "tot": "msg"}
+            ]},
+            {"id": "a5", "z": "100", "type": "http in", "url": "/created", "method": "put", "wires": [["a6"]]},
+            {"id": "a6", "z": "100", "type": "change", "wires": [["a8"]], "rules": [
+                {"t": "set", "p": "headers", "pt": "msg", "to": r#"{"X-Custom": "msg", "x-other": 1}"#, "tot": "json"}
+            ]},
+            {"id": "a8", "z": "100", "type": "http response", "statusCode": "201", "headers": {"x-custom": "node"}},
+            {"id": "a9", "z": "100", "type": "http response", "statusCode": "", "headers": {}}
+        ]);
+        let engine = build_engine(flows_json, 1000);
+        engine.start().await.unwrap();
+        let client = reqwest::Client::new();
+
+        let res = client.get(format!("{}/hello?name=bob", base_url_of(&engine, "a1").await)).send().await.unwrap();
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.text().await.unwrap(), "bob");
+
+        let res = client
+            .post(format!("{}/items/42", base_url_of(&engine, "a3").await))
+            .header("content-type", "application/json")
+            .body(r#"{"name": "apple"}"#)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.headers()["content-type"], "application/json");
+        let body: serde_json::Value = serde_json::from_slice(&res.bytes().await.unwrap()).unwrap();
+        assert_eq!(body, json!({"id": "42", "name": "apple", "ip": "127.0.0.1"}));
+
+        let res = client.get(format!("{}/items/42", base_url_of(&engine, "a3").await)).send().await.unwrap();
+        assert_eq!(res.status(), 404);
+
+        let res =
+            client.put(format!("{}/created", base_url_of(&engine, "a5").await)).body("text").send().await.unwrap();
+        assert_eq!(res.status(), 201);
+        assert_eq!(res.headers()["x-custom"], "node");
+        assert_eq!(res.headers()["x-other"], "1");
+        // The raw body without a known content type is a buffer
+        assert_eq!(res.headers()["content-type"], "application/octet-stream");
+        assert_eq!(res.text().await.unwrap(), "text");
+
+        engine.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_it_should_time_out_without_response() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "b1", "z": "100", "type": "http in", "url": "/nowhere", "method": "use", "wires": [[]]}
+        ]);
+        let engine = build_engine(flows_json, 100);
+        engine.start().await.unwrap();
+
+        let res =
+            reqwest::Client::new().put(format!("{}/nowhere", base_url_of(&engine, "b1").await)).send().await.unwrap();
+        assert_eq!(res.status(), 504);
+
+        engine.stop().await.unwrap();
+        // The server is stopped with the last route
+        let state = engine.shared_state::<HttpInState>();
+        assert!(state.servers.lock().await.get(&server_key("127.0.0.1:0", "b1".parse().unwrap())).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_pending_response_should_be_removed_when_client_disconnects() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "c1", "z": "100", "type": "http in", "url": "/slow", "method": "get", "wires": [[]]}
+        ]);
+        let engine = build_engine(flows_json, 10_000);
+        engine.start().await.unwrap();
+        let state = engine.shared_state::<HttpInState>();
+
+        let client = reqwest::Client::builder().timeout(Duration::from_millis(200)).build().unwrap();
+        let res = client.get(format!("{}/slow", base_url_of(&engine, "c1").await)).send().await;
+        assert!(res.unwrap_err().is_timeout());
+
+        // The handler is dropped with the connection long before the timeout of the flow
+        for _ in 0..100 {
+            if state.pending_responses.is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(state.pending_responses.is_empty());
+
+        engine.stop().await.unwrap();
+    }
+
+    #[test]
+    fn test_route_should_match_method_and_params() {
+        let (sender, _) = mpsc::channel(1);
+        let route = HttpRoute {
+            node_id: ElementId::with_u64(1),
+            method: Some("GET".to_string()),
+            segments: vec!["users".to_string(), ":id".to_string()],
+            timeout: Duration::from_secs(1),
+            sender,
+        };
+        let params = route.matches(&Method::GET, "/users/a%20b+c/").unwrap();
+        assert_eq!(params.get("id"), Some(&Variant::from("a b+c")));
+        assert!(route.matches(&Method::POST, "/users/1").is_none());
+        assert!(route.matches(&Method::GET, "/users").is_none());
+        assert!(route.matches(&Method::GET, "/groups/1").is_none());
+    }
+
+    #[test]
+    fn test_repeated_query_keys_should_be_collected() {
+        let query = parse_urlencoded(b"a=1&b=x%2By&a=2").unwrap();
+        assert_eq!(query, Variant::from(json!({"a": ["1", "2"], "b": "x+y"})));
+    }
+}
@@ -0,0 +1,644 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use reqwest::{redirect, Client, Method, Url};
+use serde::{de, Deserialize, Deserializer};
+
+use super::tls_config::TlsConfigNode;
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use crate::utils;
+use edgelink_macro::*;
+
+/// How the body of the response is decoded into the `msg.payload`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum HttpReturnType {
+    /// A UTF-8 string
+    #[default]
+    #[serde(rename = "txt")]
+    Text,
+
+    /// The raw bytes
+    #[serde(rename = "bin")]
+    Binary,
+
+    /// A parsed JSON value, the string is kept if the body is not a JSON
+    #[serde(rename = "obj")]
+    Object,
+}
+
+/// What to do with the `msg.payload` of the `GET` requests
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum PayloadToQuery {
+    #[default]
+    Ignore,
+
+    /// Appends the properties of the payload to the query string
+    Query,
+
+    /// Sends the payload as the body
+    Body,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum HttpAuthType {
+    #[default]
+    #[serde(rename = "")]
+    None,
+
+    #[serde(rename = "basic")]
+    Basic,
+
+    #[serde(rename = "digest")]
+    Digest,
+
+    /// The `credentials.password` is the token
+    #[serde(rename = "bearer")]
+    Bearer,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct HttpCredentials {
+    #[serde(default)]
+    user: String,
+
+    #[serde(default)]
+    password: String,
+}
+
+/// A header of the request, the `keyType` and the `valueType` are `other` for the literal, `msg` for a message
+/// property, or the literal itself, e.g. `Content-Type`.
+#[derive(Debug, Clone, Deserialize)]
+struct HttpHeaderConfig {
+    #[serde(rename = "keyType")]
+    key_type: String,
+
+    #[serde(rename = "keyValue", default)]
+    key_value: String,
+
+    #[serde(rename = "valueType")]
+    value_type: String,
+
+    #[serde(rename = "valueValue", default)]
+    value_value: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct HttpRequestNodeConfig {
+    /// The method in upper case, `use` takes the `msg.method`
+    #[serde(default = "method_default")]
+    method: String,
+
+    /// The `msg.url` is used if empty
+    #[serde(default)]
+    url: String,
+
+    /// The `tls-config` node
+    #[serde(default, deserialize_with = "json::deser::deser_red_optional_id")]
+    tls: Option<ElementId>,
+
+    #[serde(default, rename = "authType", alias = "auth")]
+    auth: HttpAuthType,
+
+    #[serde(default)]
+    credentials: HttpCredentials,
+
+    /// Sends the responses not in 2xx to the `catch` nodes instead of the output
+    #[serde(default, deserialize_with = "json::deser::deser_bool_or_str")]
+    senderr: bool,
+
+    /// Overrides the headers of the same names in the `msg.headers`
+    #[serde(default)]
+    headers: Vec<HttpHeaderConfig>,
+
+    #[serde(default, deserialize_with = "deser_paytoqs")]
+    paytoqs: PayloadToQuery,
+
+    #[serde(default)]
+    ret: HttpReturnType,
+}
+
+fn method_default() -> String {
+    "GET".to_string()
+}
+
+/// The `paytoqs` was a boolean in the older versions of Node-RED.
+fn deser_paytoqs<'de, D>(deserializer: D) -> Result<PayloadToQuery, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Null | serde_json::Value::Bool(false) => Ok(PayloadToQuery::Ignore),
+        serde_json::Value::Bool(true) => Ok(PayloadToQuery::Query),
+        serde_json::Value::String(s) => match s.as_str() {
+            "" | "ignore" => Ok(PayloadToQuery::Ignore),
+            "query" => Ok(PayloadToQuery::Query),
+            "body" => Ok(PayloadToQuery::Body),
+            _ => Err(de::Error::custom(format!("Unknown `paytoqs`: '{}'", s))),
+        },
+        other => Err(de::Error::custom(format!("Expected a string for `paytoqs`, got: {}", other))),
+    }
+}
+
+/// Sends the HTTP requests and forwards the responses, the status and the headers are set to the `msg.statusCode` and
+/// the `msg.headers`, the `msg.followRedirects` and the `msg.requestTimeout` override the defaults per message.
+#[derive(Debug)]
+#[flow_node("http request")]
+struct HttpRequestNode {
+    base: FlowNode,
+    config: HttpRequestNodeConfig,
+    client: Client,
+
+    /// For the messages with `msg.followRedirects` set to `false`
+    no_redirect_client: Client,
+    timeout: Duration,
+}
+
+impl HttpRequestNode {
+    fn build(flow: &Flow, base_node: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let http_config = HttpRequestNodeConfig::deserialize(&config.rest)?;
+        if http_config.auth == HttpAuthType::Digest {
+            return Err(
+                EdgelinkError::NotSupported("The digest authentication of the `http request` node".into()).into()
+            );
+        }
+
+        let engine = flow.engine().ok_or(EdgelinkError::InvalidOperation("The engine has been released".into()))?;
+        let timeout = Duration::from_millis(engine.http_args().request_timeout_ms);
+        let tls_node = http_config
+            .tls
+            .map(|tls_id| engine.get_global_node(&tls_id))
+            .transpose()
+            .with_context(|| format!("Failed to get the TLS config of the node: id='{}'", base_node.id))?;
+        let tls = match tls_node.as_ref() {
+            Some(tls_node) => Some(
+                tls_node
+                    .as_any()
+                    .downcast_ref::<TlsConfigNode>()
+                    .ok_or(EdgelinkError::BadFlowsJson(format!("Not a 'tls-config' node: {}", tls_node)))?,
+            ),
+            None => None,
+        };
+
+        let client = new_client(tls, engine.tls_insecure(), redirect::Policy::default(), timeout)?;
+        let no_redirect_client = new_client(tls, engine.tls_insecure(), redirect::Policy::none(), timeout)?;
+        let node = HttpRequestNode { base: base_node, config: http_config, client, no_redirect_client, timeout };
+        Ok(Box::new(node))
+    }
+
+    /// Makes the request of the message, and selects the client by the `msg.followRedirects`.
+    fn make_request(&self, msg: &Msg) -> crate::Result<(&Client, reqwest::Request)> {
+        let method = if self.config.method.eq_ignore_ascii_case("use") {
+            msg.get("method").and_then(|x| x.as_str()).unwrap_or("GET").to_ascii_uppercase()
+        } else {
+            self.config.method.to_ascii_uppercase()
+        };
+        let method = Method::from_bytes(method.as_bytes())
+            .map_err(|_| EdgelinkError::InvalidOperation(format!("Bad HTTP method: '{}'", method)))?;
+
+        let url = match self.config.url.trim() {
+            "" => msg
+                .get("url")
+                .and_then(|x| x.as_str())
+                .ok_or(EdgelinkError::InvalidOperation("No URL specified".into()))?,
+            url => url,
+        };
+        let lower_url = url.to_ascii_lowercase();
+        let url = if lower_url.starts_with("http://") || lower_url.starts_with("https://") {
+            url.to_string()
+        } else if self.config.tls.is_some() {
+            format!("https://{}", url)
+        } else {
+            format!("http://{}", url)
+        };
+        let mut url = Url::parse(&url).with_context(|| format!("Bad URL: '{}'", url))?;
+
+        let payload = msg.get("payload");
+        if method == Method::GET && self.config.paytoqs == PayloadToQuery::Query {
+            if let Some(Variant::Object(payload)) = payload {
+                let pairs = form_pairs(payload)?;
+                if !pairs.is_empty() {
+                    url.query_pairs_mut().extend_pairs(pairs);
+                }
+            }
+        }
+
+        let mut headers = HeaderMap::new();
+        if let Some(msg_headers) = msg.get("headers").and_then(|x| x.as_object()) {
+            for (name, value) in msg_headers.iter() {
+                insert_header(&mut headers, name, value)?;
+            }
+        }
+        for header in self.config.headers.iter() {
+            let name = match header.key_type.as_str() {
+                "other" => Some(header.key_value.clone()),
+                "msg" => msg.get_nav_stripped(&header.key_value).and_then(|x| x.as_str()).map(String::from),
+                key_type => Some(key_type.to_string()),
+            };
+            let value = match header.value_type.as_str() {
+                "other" => Some(Variant::String(header.value_value.clone())),
+                "msg" => msg.get_nav_stripped(&header.value_value).cloned(),
+                value_type => Some(Variant::String(value_type.to_string())),
+            };
+            if let (Some(name), Some(value)) = (name.filter(|x| !x.is_empty()), value) {
+                insert_header(&mut headers, &name, &value)?;
+            }
+        }
+
+        let has_body =
+            if method == Method::GET { self.config.paytoqs == PayloadToQuery::Body } else { method != Method::HEAD };
+        let body = match payload {
+            _ if !has_body => None,
+            None | Some(Variant::Null) => None,
+            Some(Variant::String(s)) => Some(s.as_bytes().to_vec()),
+            Some(Variant::Bytes(bytes)) => Some(bytes.clone()),
+            Some(Variant::Number(n)) => Some(n.to_string().into_bytes()),
+            Some(Variant::Object(obj))
+                if headers.get(CONTENT_TYPE).is_some_and(|x| x.as_bytes() == b"application/x-www-form-urlencoded") =>
+            {
+                Some(serde_urlencoded::to_string(form_pairs(obj)?)?.into_bytes())
+            }
+            Some(other) => {
+                headers.entry(CONTENT_TYPE).or_insert(HeaderValue::from_static("application/json"));
+                Some(serde_json::to_vec(other)?)
+            }
+        };
+
+        let client = match msg.get("followRedirects") {
+            Some(Variant::Bool(false)) => &self.no_redirect_client,
+            _ => &self.client,
+        };
+        let timeout = match msg.get("requestTimeout").map(|x| x.as_f64()) {
+            Some(Some(ms)) if ms > 0.0 => utils::time::delay_from_secs_f64(ms / 1000.0).unwrap_or(self.timeout),
+            Some(_) => {
+                log::warn!("[HTTP_REQUEST:{}] Ignored the bad `msg.requestTimeout`", self.name());
+                self.timeout
+            }
+            None => self.timeout,
+        };
+
+        let mut request = client.request(method, url).headers(headers).timeout(timeout);
+        request = match self.config.auth {
+            HttpAuthType::Basic => {
+                request.basic_auth(&self.config.credentials.user, Some(&self.config.credentials.password))
+            }
+            HttpAuthType::Bearer => request.bearer_auth(&self.config.credentials.password),
+            HttpAuthType::None | HttpAuthType::Digest => request,
+        };
+        if let Some(body) = body {
+            request = request.body(body);
+        }
+        Ok((client, request.build()?))
+    }
+
+    async fn do_request(&self, msg: &MsgHandle, cancel: CancellationToken) -> crate::Result<()> {
+        let (client, request) = self.make_request(&*msg.read().await)?;
+        let url = request.url().to_string();
+        let result = async {
+            let response = client.execute(request).await?;
+            let response_url = response.url().to_string();
+            let status = response.status();
+            let headers = response.headers().clone();
+            let body = response.bytes().await?;
+            Ok::<_, reqwest::Error>((response_url, status, headers, body))
+        };
+        let result = tokio::select! {
+            result = result => result,
+            _ = cancel.cancelled() => return Err(EdgelinkError::TaskCancelled.into()),
+        };
+
+        let mut msg_guard = msg.write().await;
+        let (response_url, status, headers, body) = match result {
+            Ok(response) => response,
+            Err(err) => {
+                msg_guard.set("payload".to_string(), Variant::String(format!("{} : {}", err, url)));
+                return Err(err.into());
+            }
+        };
+
+        let mut header_map = VariantObjectMap::new();
+        for name in headers.keys() {
+            let mut values: Vec<Variant> = headers
+                .get_all(name)
+                .iter()
+                .map(|x| Variant::String(String::from_utf8_lossy(x.as_bytes()).into()))
+                .collect();
+            let value = if values.len() == 1 { values.remove(0) } else { Variant::Array(values) };
+            header_map.insert(name.as_str().to_string(), value);
+        }
+        let payload = match self.config.ret {
+            HttpReturnType::Binary => Variant::Bytes(body.to_vec()),
+            HttpReturnType::Text => Variant::String(String::from_utf8_lossy(&body).into()),
+            HttpReturnType::Object => serde_json::from_slice::<Variant>(&body).unwrap_or_else(|_| {
+                log::warn!("[HTTP_REQUEST:{}] The response is not a JSON, kept as a string", self.name());
+                Variant::String(String::from_utf8_lossy(&body).into())
+            }),
+        };
+        msg_guard.set("payload".to_string(), payload);
+        msg_guard.set("statusCode".to_string(), Variant::from(u32::from(status.as_u16())));
+        msg_guard.set("headers".to_string(), Variant::Object(header_map));
+        msg_guard.set("responseUrl".to_string(), Variant::String(response_url));
+
+        if self.config.senderr && !status.is_success() {
+            return Err(EdgelinkError::InvalidOperation(format!("The server responded with: {}", status)).into());
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for HttpRequestNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow_concurrent(&self, cancel.child_token(), |node, msg| async move {
+                node.do_request(&msg, cancel.child_token()).await?;
+                node.fan_out_one(Envelope { port: 0, msg }, cancel.child_token()).await
+            })
+            .await;
+        }
+        wait_uows_completed(self.as_ref()).await;
+    }
+}
+
+fn new_client(
+    tls: Option<&TlsConfigNode>,
+    tls_insecure: bool,
+    redirect: redirect::Policy,
+    connect_timeout: Duration,
+) -> crate::Result<Client> {
+    let mut builder = Client::builder().redirect(redirect).connect_timeout(connect_timeout);
+    if let Some(tls) = tls {
+        if let Some(ca) = tls.ca.as_ref() {
+            builder = builder.add_root_certificate(reqwest::Certificate::from_pem(ca)?);
+        }
+        if let (Some(cert), Some(key)) = (tls.cert.as_ref(), tls.key.as_ref()) {
+            let identity = [cert.as_slice(), b"\n", key.as_slice()].concat();
+            builder = builder.identity(reqwest::Identity::from_pem(&identity)?);
+        }
+    }
+    let tls_insecure = tls_insecure || tls.is_some_and(|x| !x.verify_server_cert);
+    Ok(builder.danger_accept_invalid_certs(tls_insecure).build()?)
+}
+
+fn insert_header(headers: &mut HeaderMap, name: &str, value: &Variant) -> crate::Result<()> {
+    let value = match value {
+        Variant::String(s) => s.clone(),
+        other => other.to_string()?,
+    };
+    let name = HeaderName::from_bytes(name.as_bytes()).with_context(|| format!("Bad header name: '{}'", name))?;
+    let value = HeaderValue::from_str(&value).with_context(|| format!("Bad value of the header '{}'", name))?;
+    headers.insert(name, value);
+    Ok(())
+}
+
+/// The pairs of the query string or the form, the items of an array are repeated with the same key.
+fn form_pairs(obj: &VariantObjectMap) -> crate::Result<Vec<(String, String)>> {
+    let mut pairs = Vec::with_capacity(obj.len());
+    for (key, value) in obj.iter() {
+        let values = match value {
+            Variant::Array(items) => items.iter().collect(),
+            other => vec![other],
+        };
+        for value in values {
+            let value = match value {
+                Variant::String(s) => s.clone(),
+                Variant::Null => String::new(),
+                Variant::Number(_) | Variant::Bool(_) => value.to_string()?,
+                other => serde_json::to_string(other)?,
+            };
+            pairs.push((key.clone(), value));
+        }
+    }
+    Ok(pairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_rustls::rustls;
+    use wiremock::matchers::{body_json, header, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_it_should_get_json_with_query() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/things"))
+            .and(query_param("name", "a b"))
+            .and(query_param("tag", "x"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"id": 7})).insert_header("x-foo", "bar"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/text"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("not a json"))
+            .mount(&server)
+            .await;
+
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "http request", "method": "GET", "ret": "obj", "paytoqs": "query",
+                "url": "", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"},
+            {"id": "3", "z": "100", "type": "catch", "wires": [["2"]]}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"url": format!("{}/things", server.uri()), "payload": {"name": "a b", "tag": ["x"]}}],
+            ["1", {"url": format!("{}/text", server.uri())}],
+        ]))
+        .unwrap();
+        let msgs = engine.run_once_with_inject(2, Duration::from_secs(2), msgs_to_inject).await.unwrap();
+
+        assert_eq!(msgs[0]["payload"], Variant::from(json!({"id": 7})));
+        assert_eq!(msgs[0]["statusCode"], Variant::from(200));
+        assert_eq!(msgs[0].get_nav_stripped("headers['x-foo']"), Some(&Variant::from("bar")));
+        assert_eq!(msgs[1]["payload"], Variant::from("not a json"));
+    }
+
+    #[tokio::test]
+    async fn test_it_should_post_payload_with_headers_and_auth() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/items"))
+            .and(header("x-token", "abc"))
+            .and(header("content-type", "application/json"))
+            .and(header("authorization", "Basic dXNlcjpwYXNz"))
+            .and(body_json(json!({"name": "a"})))
+            .respond_with(ResponseTemplate::new(201).set_body_bytes(vec![1u8, 2, 3]))
+            .mount(&server)
+            .await;
+
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "http request", "method": "use", "ret": "bin",
+                "url": format!("{}/items", server.uri()), "authType": "basic",
+                "credentials": {"user": "user", "password": "pass"},
+                "headers": [{"keyType": "other", "keyValue": "X-Token", "valueType": "msg", "valueValue": "token"}],
+                "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"},
+            {"id": "3", "z": "100", "type": "catch", "wires": [["2"]]}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"method": "post", "token": "abc", "payload": {"name": "a"}}],
+        ]))
+        .unwrap();
+        let msgs = engine.run_once_with_inject(1, Duration::from_secs(2), msgs_to_inject).await.unwrap();
+
+        assert_eq!(msgs[0]["statusCode"], Variant::from(201));
+        assert_eq!(msgs[0]["payload"], Variant::Bytes(vec![1, 2, 3]));
+    }
+
+    #[tokio::test]
+    async fn test_it_should_follow_redirects_unless_disabled() {
+        let server = MockServer::start().await;
+        Mock::given(path("/old"))
+            .respond_with(ResponseTemplate::new(302).insert_header("location", "/new"))
+            .mount(&server)
+            .await;
+        Mock::given(path("/new"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("moved"))
+            .mount(&server)
+            .await;
+
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "http request", "method": "GET", "ret": "txt",
+                "url": format!("{}/old", server.uri()), "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"},
+            {"id": "3", "z": "100", "type": "catch", "wires": [["2"]]}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"payload": ""}],
+            ["1", {"payload": "", "followRedirects": false}],
+        ]))
+        .unwrap();
+        let msgs = engine.run_once_with_inject(2, Duration::from_secs(2), msgs_to_inject).await.unwrap();
+
+        assert_eq!(msgs[0]["payload"], Variant::from("moved"));
+        assert_eq!(msgs[0]["responseUrl"], Variant::from(format!("{}/new", server.uri())));
+        assert_eq!(msgs[1]["statusCode"], Variant::from(302));
+    }
+
+    #[tokio::test]
+    async fn test_errors_should_be_caught() {
+        let server = MockServer::start().await;
+        Mock::given(path("/slow"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(500)))
+            .mount(&server)
+            .await;
+        Mock::given(path("/missing")).respond_with(ResponseTemplate::new(404)).mount(&server).await;
+
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "http request", "method": "GET", "senderr": true, "url": "",
+                "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"},
+            {"id": "3", "z": "100", "type": "catch", "wires": [["2"]]}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"url": format!("{}/slow", server.uri()), "requestTimeout": 100}],
+            ["1", {"url": format!("{}/missing", server.uri())}],
+        ]))
+        .unwrap();
+        let msgs = engine.run_once_with_inject(2, Duration::from_secs(2), msgs_to_inject).await.unwrap();
+
+        assert!(msgs.iter().all(|x| x.get_nav_stripped("error.message").is_some()));
+        assert!(!msgs[0].contains("statusCode"));
+        assert_eq!(msgs[1]["statusCode"], Variant::from(404));
+    }
+
+    /// Serves the HTTPS requests with a self-signed certificate of `localhost`, returns the URL.
+    async fn start_self_signed_server() -> String {
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let key = rustls::pki_types::PrivateKeyDer::Pkcs8(certified.key_pair.serialize_der().into());
+        let config = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![certified.cert.der().clone()], key)
+            .unwrap();
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    if let Ok(mut stream) = acceptor.accept(stream).await {
+                        let mut buf = [0u8; 1024];
+                        let _ = stream.read(&mut buf).await;
+                        let response = b"HTTP/1.1 200 OK\r\ncontent-length: 6\r\nconnection: close\r\n\r\nsecret";
+                        let _ = stream.write_all(response).await;
+                        let _ = stream.shutdown().await;
+                    }
+                });
+            }
+        });
+        format!("https://localhost:{}/", port)
+    }
+
+    #[tokio::test]
+    async fn test_tls_insecure_should_accept_self_signed_certificates() {
+        let url = start_self_signed_server().await;
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "http request", "method": "GET", "ret": "txt", "senderr": true, "url": url,
+                "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"},
+            {"id": "3", "z": "100", "type": "catch", "wires": [["2"]]}
+        ]);
+        let msgs_to_inject = || Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": ""}]])).unwrap();
+
+        // Verified by default
+        let engine = crate::runtime::engine::build_test_engine(flows_json.clone()).unwrap();
+        let msgs = engine.run_once_with_inject(1, Duration::from_secs(2), msgs_to_inject()).await.unwrap();
+        assert!(msgs[0].get_nav_stripped("error.message").is_some());
+        assert!(!msgs[0].contains("statusCode"));
+
+        let cfg = config::Config::builder()
+            .add_source(config::File::from_str("[runtime.engine]\ntls_insecure = true\n", config::FileFormat::Toml))
+            .build()
+            .unwrap();
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let engine = crate::runtime::engine::Engine::with_json(&registry, flows_json, Some(&cfg)).unwrap();
+        assert!(engine.tls_insecure());
+        let msgs = engine.run_once_with_inject(1, Duration::from_secs(2), msgs_to_inject()).await.unwrap();
+        assert_eq!(msgs[0]["statusCode"], Variant::from(200));
+        assert_eq!(msgs[0]["payload"], Variant::from("secret"));
+    }
+
+    #[test]
+    fn test_bad_config_should_fail_to_build() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "http request", "method": "GET", "url": "localhost", "authType": "digest",
+                "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"},
+            {"id": "3", "z": "100", "type": "catch", "wires": [["2"]]}
+        ]);
+        assert!(crate::runtime::engine::build_test_engine(flows_json).is_err());
+
+        let mut flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "http request", "method": "GET", "url": "localhost", "tls": "900",
+                "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"},
+            {"id": "3", "z": "100", "type": "catch", "wires": [["2"]]}
+        ]);
+        flows_json.as_array_mut().unwrap().push(json!({"id": "900", "type": "tls-config", "ca": "/nonexistent.pem"}));
+        assert!(crate::runtime::engine::build_test_engine(flows_json).is_err());
+    }
+}
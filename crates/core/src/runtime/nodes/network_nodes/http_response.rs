@@ -0,0 +1,111 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use super::http_in::{HttpInState, HttpResponse};
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+#[derive(Debug, Clone, Deserialize)]
+struct HttpResponseNodeConfig {
+    /// Overrides the `msg.statusCode` if set
+    #[serde(default, rename = "statusCode", deserialize_with = "json::deser::str_to_option_u16")]
+    status_code: Option<u16>,
+
+    /// Overrides the headers of the same names in the `msg.headers`
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+}
+
+/// Replies to the request of the `http in` node with the `msg.payload`, the request is identified by the `msg.res`.
+#[derive(Debug)]
+#[flow_node("http response")]
+struct HttpResponseNode {
+    base: FlowNode,
+    config: HttpResponseNodeConfig,
+}
+
+impl HttpResponseNode {
+    fn build(
+        _flow: &Flow,
+        base_node: FlowNode,
+        config: &RedFlowNodeConfig,
+    ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let http_config = HttpResponseNodeConfig::deserialize(&config.rest)?;
+        let node = HttpResponseNode { base: base_node, config: http_config };
+        Ok(Box::new(node))
+    }
+
+    fn make_response(&self, msg: &Msg) -> crate::Result<HttpResponse> {
+        let status = match (self.config.status_code, msg.get("statusCode")) {
+            (Some(status), _) => status,
+            (None, Some(status)) => status
+                .as_u64()
+                .and_then(|x| u16::try_from(x).ok())
+                .ok_or(EdgelinkError::InvalidOperation(format!("Bad `msg.statusCode`: {:?}", status)))?,
+            (None, None) => 200,
+        };
+
+        // The header names are case-insensitive
+        let mut headers = BTreeMap::new();
+        if let Some(msg_headers) = msg.get("headers").and_then(|x| x.as_object()) {
+            for (name, value) in msg_headers.iter() {
+                let value = match value {
+                    Variant::String(s) => s.clone(),
+                    other => other.to_string()?,
+                };
+                headers.insert(name.to_ascii_lowercase(), value);
+            }
+        }
+        for (name, value) in self.config.headers.iter() {
+            headers.insert(name.to_ascii_lowercase(), value.clone());
+        }
+
+        let (body, content_type) = match msg.get("payload") {
+            None | Some(Variant::Null) => (Vec::new(), None),
+            Some(Variant::String(s)) => (s.as_bytes().to_vec(), Some("text/html; charset=utf-8")),
+            Some(Variant::Bytes(bytes)) => (bytes.clone(), Some("application/octet-stream")),
+            Some(other) => (serde_json::to_vec(other)?, Some("application/json")),
+        };
+        if let Some(content_type) = content_type {
+            headers.entry("content-type".to_string()).or_insert_with(|| content_type.to_string());
+        }
+
+        Ok(HttpResponse { status, headers: headers.into_iter().collect(), body })
+    }
+
+    async fn respond(&self, msg: MsgHandle) -> crate::Result<()> {
+        let state = self
+            .engine()
+            .map(|x| x.shared_state::<HttpInState>())
+            .ok_or(EdgelinkError::InvalidOperation("The engine has been dropped".into()))?;
+        let msg_guard = msg.read().await;
+        let sender = msg_guard
+            .get("res")
+            .and_then(|x| state.take_response_sender(x))
+            .ok_or(EdgelinkError::InvalidOperation("No request to respond, or it has been responded".into()))?;
+        let response = self.make_response(&msg_guard)?;
+        if sender.send(response).is_err() {
+            log::warn!("[HTTP_RESPONSE:{}] The request has timed out before the response", self.name());
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for HttpResponseNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            with_uow_concurrent(&self, stop_token.child_token(), |node, msg| async move { node.respond(msg).await })
+                .await;
+        }
+        wait_uows_completed(self.as_ref()).await;
+    }
+}
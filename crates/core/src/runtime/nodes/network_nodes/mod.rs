@@ -1,2 +1,38 @@
+#[cfg(feature = "nodes_http")]
+mod http_in;
+
+#[cfg(feature = "nodes_http")]
+mod http_request;
+
+#[cfg(feature = "nodes_http")]
+mod http_response;
+
+#[cfg(feature = "nodes_mqtt")]
+mod mqtt;
+
+#[cfg(any(feature = "nodes_http", feature = "nodes_mqtt"))]
+mod tls_config;
+
+#[cfg(feature = "nodes_tcp")]
+mod tcp;
+
+#[cfg(feature = "nodes_tcp")]
+mod tcp_in;
+
+#[cfg(feature = "nodes_tcp")]
+mod tcp_out;
+
+#[cfg(feature = "nodes_udp")]
+mod udp_in;
+
 #[cfg(feature = "nodes_udp")]
 mod udp_out;
+
+#[cfg(feature = "nodes_websocket")]
+mod websocket;
+
+#[cfg(feature = "nodes_websocket")]
+mod websocket_in;
+
+#[cfg(feature = "nodes_websocket")]
+mod websocket_out;
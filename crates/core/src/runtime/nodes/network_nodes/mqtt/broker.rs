@@ -0,0 +1,615 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rumqttc::v5::mqttbytes::v5 as mqtt5;
+use rumqttc::v5::mqttbytes::QoS as QoS5;
+use rumqttc::{QoS, TlsConfiguration, Transport};
+use serde::Deserialize;
+use tokio::sync::mpsc;
+
+use super::super::tls_config::TlsConfigNode;
+use super::*;
+use crate::runtime::context::ContextScope;
+use crate::runtime::engine::Engine;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+/// The delay before reconnecting after the connection to the broker was lost
+const RECONNECT_DELAY: Duration = Duration::from_secs(15);
+
+const EVENT_CHANNEL_CAPACITY: usize = 64;
+
+#[derive(Debug, Clone, Deserialize)]
+struct Credentials {
+    #[serde(default)]
+    user: String,
+
+    #[serde(default)]
+    password: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct MqttBrokerNodeConfig {
+    broker: String,
+
+    #[serde(default, deserialize_with = "json::deser::str_to_option_usize")]
+    port: Option<usize>,
+
+    #[serde(default)]
+    clientid: String,
+
+    #[serde(default, deserialize_with = "deser_option_bool")]
+    usetls: Option<bool>,
+
+    /// The `tls-config` node
+    #[serde(default, deserialize_with = "json::deser::deser_red_optional_id")]
+    tls: Option<ElementId>,
+
+    /// `4` for MQTT 3.1.1 and `5` for MQTT 5.0
+    #[serde(rename = "protocolVersion", default, deserialize_with = "json::deser::str_to_option_usize")]
+    protocol_version: Option<usize>,
+
+    /// In seconds
+    #[serde(default, deserialize_with = "json::deser::str_to_option_usize")]
+    keepalive: Option<usize>,
+
+    #[serde(default, deserialize_with = "deser_option_bool")]
+    cleansession: Option<bool>,
+
+    /// The session expiry interval of MQTT 5.0 in seconds
+    #[serde(rename = "sessionExpiry", default, deserialize_with = "json::deser::str_to_option_usize")]
+    session_expiry: Option<usize>,
+
+    #[serde(rename = "willTopic", default)]
+    will_topic: String,
+
+    #[serde(rename = "willQos", default, deserialize_with = "deser_qos")]
+    will_qos: Option<u8>,
+
+    #[serde(rename = "willRetain", default, deserialize_with = "deser_option_bool")]
+    will_retain: Option<bool>,
+
+    #[serde(rename = "willPayload", default)]
+    will_payload: String,
+
+    #[serde(default)]
+    credentials: Option<Credentials>,
+}
+
+#[derive(Debug, Clone)]
+enum BrokerOptions {
+    /// The options are boxed since they are large and of much different sizes
+    V4(Box<rumqttc::MqttOptions>),
+    V5(Box<rumqttc::v5::MqttOptions>),
+}
+
+#[derive(Clone)]
+enum MqttClient {
+    V4(rumqttc::AsyncClient),
+    V5(rumqttc::v5::AsyncClient),
+}
+
+#[derive(Debug)]
+pub(super) struct Subscription {
+    pub node_id: ElementId,
+    pub filter: String,
+    pub qos: u8,
+
+    /// MQTT 5.0 only: the no local option
+    pub nl: bool,
+
+    /// MQTT 5.0 only: the retain as published option
+    pub rap: bool,
+
+    /// MQTT 5.0 only: the retain handling option, `0` to `2`
+    pub rh: u8,
+
+    pub sender: mpsc::Sender<MqttMessage>,
+}
+
+struct Connection {
+    client: MqttClient,
+    users: HashSet<ElementId>,
+    cancel: CancellationToken,
+}
+
+/// The connection to the broker shared by the `mqtt in` and `mqtt out` nodes.
+///
+/// The connection is opened by the first node using it and closed after the last one stopped, it reconnects
+/// automatically and restores the subscriptions once the broker acknowledged the connection.
+pub(super) struct MqttBroker {
+    label: String,
+    options: BrokerOptions,
+    connection: Mutex<Option<Connection>>,
+    subscriptions: Arc<Mutex<Vec<Subscription>>>,
+    connected: Arc<AtomicBool>,
+}
+
+#[derive(Debug)]
+#[global_node("mqtt-broker")]
+pub(super) struct MqttBrokerNode {
+    base: GlobalNode,
+    pub broker: Arc<MqttBroker>,
+}
+
+impl std::fmt::Debug for MqttBroker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MqttBroker").field("label", &self.label).field("options", &self.options).finish()
+    }
+}
+
+impl MqttBrokerNode {
+    fn build(engine: &Engine, config: &RedGlobalNodeConfig) -> crate::Result<Box<dyn GlobalNodeBehavior>> {
+        let broker_config = MqttBrokerNodeConfig::deserialize(&config.rest)?;
+        let tls_node = broker_config.tls.map(|tls_id| engine.get_global_node(&tls_id)).transpose()?;
+        let tls = match tls_node.as_ref() {
+            Some(tls_node) => Some(
+                tls_node
+                    .as_any()
+                    .downcast_ref::<TlsConfigNode>()
+                    .ok_or(EdgelinkError::BadFlowsJson(format!("Not a 'tls-config' node: {}", tls_node)))?,
+            ),
+            None => None,
+        };
+        let options = make_options(&broker_config, tls, engine.tls_insecure())?;
+
+        let context =
+            engine.get_context_manager().new_context(&engine.context(), config.id.to_string(), ContextScope::Node);
+        let node = Self {
+            base: GlobalNode {
+                id: config.id,
+                name: config.name.clone(),
+                type_str: "mqtt-broker",
+                ordering: config.ordering,
+                disabled: config.disabled,
+                context,
+            },
+            broker: Arc::new(MqttBroker {
+                label: if config.name.is_empty() { config.id.to_string() } else { config.name.clone() },
+                options,
+                connection: Mutex::new(None),
+                subscriptions: Arc::new(Mutex::new(Vec::new())),
+                connected: Arc::new(AtomicBool::new(false)),
+            }),
+        };
+        Ok(Box::new(node))
+    }
+
+    /// Gets the broker of the `mqtt-broker` node referenced by the `mqtt in` and `mqtt out` nodes.
+    pub fn get_broker(engine: &Engine, id: Option<ElementId>) -> crate::Result<Arc<MqttBroker>> {
+        let id = id.ok_or(EdgelinkError::BadFlowsJson("The MQTT node must have a 'mqtt-broker' node".into()))?;
+        let node = engine.get_global_node(&id)?;
+        let broker_node = node
+            .as_any()
+            .downcast_ref::<MqttBrokerNode>()
+            .ok_or(EdgelinkError::BadFlowsJson(format!("Not a 'mqtt-broker' node: {}", node)))?;
+        Ok(broker_node.broker.clone())
+    }
+}
+
+impl GlobalNodeBehavior for MqttBrokerNode {
+    fn get_node(&self) -> &GlobalNode {
+        &self.base
+    }
+}
+
+impl MqttBroker {
+    /// Opens the connection if it is the first user.
+    pub fn connect(&self, node_id: ElementId) {
+        let mut connection = self.connection.lock().expect("Lock the connection");
+        if let Some(connection) = connection.as_mut() {
+            connection.users.insert(node_id);
+            return;
+        }
+
+        let cancel = CancellationToken::new();
+        let client = match &self.options {
+            BrokerOptions::V4(options) => {
+                let (client, event_loop) = rumqttc::AsyncClient::new(options.as_ref().clone(), EVENT_CHANNEL_CAPACITY);
+                tokio::spawn(run_v4(
+                    self.label.clone(),
+                    client.clone(),
+                    event_loop,
+                    self.subscriptions.clone(),
+                    self.connected.clone(),
+                    cancel.clone(),
+                ));
+                MqttClient::V4(client)
+            }
+            BrokerOptions::V5(options) => {
+                let (client, event_loop) =
+                    rumqttc::v5::AsyncClient::new(options.as_ref().clone(), EVENT_CHANNEL_CAPACITY);
+                tokio::spawn(run_v5(
+                    self.label.clone(),
+                    client.clone(),
+                    event_loop,
+                    self.subscriptions.clone(),
+                    self.connected.clone(),
+                    cancel.clone(),
+                ));
+                MqttClient::V5(client)
+            }
+        };
+        *connection = Some(Connection { client, users: HashSet::from([node_id]), cancel });
+    }
+
+    /// Closes the connection after the last user left.
+    pub fn disconnect(&self, node_id: ElementId) {
+        let mut connection = self.connection.lock().expect("Lock the connection");
+        if let Some(conn) = connection.as_mut() {
+            conn.users.remove(&node_id);
+            if conn.users.is_empty() {
+                conn.cancel.cancel();
+                *connection = None;
+            }
+        }
+    }
+
+    fn client(&self) -> crate::Result<MqttClient> {
+        let connection = self.connection.lock().expect("Lock the connection");
+        connection.as_ref().map(|x| x.client.clone()).ok_or_else(|| {
+            EdgelinkError::InvalidOperation(format!("The MQTT broker '{}' is not connected", self.label)).into()
+        })
+    }
+
+    /// Subscribes the topic filter, the messages received are sent to the sender of the subscription.
+    pub fn subscribe(&self, sub: Subscription) -> crate::Result<()> {
+        let client = self.client()?;
+        let mut subs = self.subscriptions.lock().expect("Lock the subscriptions");
+        // Otherwise it will be subscribed once connected
+        if self.connected.load(Ordering::SeqCst) {
+            try_subscribe(&client, &sub)?;
+        }
+        subs.push(sub);
+        Ok(())
+    }
+
+    /// Removes the subscriptions of the node, the topic filters no longer used are unsubscribed.
+    pub fn unsubscribe(&self, node_id: ElementId) {
+        let client = self.client().ok();
+        let mut subs = self.subscriptions.lock().expect("Lock the subscriptions");
+        let removed: HashSet<String> = subs.iter().filter(|x| x.node_id == node_id).map(|x| x.filter.clone()).collect();
+        subs.retain(|x| x.node_id != node_id);
+        let client = match client {
+            Some(client) if self.connected.load(Ordering::SeqCst) => client,
+            _ => return,
+        };
+        for filter in removed.into_iter().filter(|f| !subs.iter().any(|x| &x.filter == f)) {
+            let res = match &client {
+                MqttClient::V4(client) => client.try_unsubscribe(filter.clone()).map_err(|e| e.to_string()),
+                MqttClient::V5(client) => client.try_unsubscribe(filter.clone()).map_err(|e| e.to_string()),
+            };
+            if let Err(e) = res {
+                log::warn!("[MQTT_BROKER:{}] Failed to unsubscribe '{}': {}", self.label, filter, e);
+            }
+        }
+    }
+
+    /// Publishes a message, the properties are only sent by MQTT 5.0.
+    pub async fn publish(
+        &self,
+        topic: &str,
+        qos: u8,
+        retain: bool,
+        payload: Vec<u8>,
+        props: PublishProperties,
+    ) -> crate::Result<()> {
+        match self.client()? {
+            MqttClient::V4(client) => client.publish(topic, to_qos(qos), retain, payload).await?,
+            MqttClient::V5(client) => {
+                let props = mqtt5::PublishProperties {
+                    message_expiry_interval: props.message_expiry_interval,
+                    response_topic: props.response_topic,
+                    correlation_data: props.correlation_data.map(bytes::Bytes::from),
+                    user_properties: props.user_properties,
+                    content_type: props.content_type,
+                    ..Default::default()
+                };
+                client.publish_with_properties(topic, to_qos5(qos), retain, payload, props).await?
+            }
+        }
+        Ok(())
+    }
+}
+
+fn make_options(
+    config: &MqttBrokerNodeConfig,
+    tls: Option<&TlsConfigNode>,
+    tls_insecure: bool,
+) -> crate::Result<BrokerOptions> {
+    let (mut host, mut use_tls) = (config.broker.trim(), config.usetls.unwrap_or(false));
+    for (scheme, is_tls) in [("mqtt://", false), ("tcp://", false), ("mqtts://", true), ("ssl://", true)] {
+        if let Some(stripped) = host.strip_prefix(scheme) {
+            host = stripped;
+            use_tls |= is_tls;
+        }
+    }
+    if host.is_empty() {
+        return Err(EdgelinkError::BadFlowsJson("The address of the MQTT broker must not be empty".into()).into());
+    }
+    let port = match config.port {
+        Some(port) => u16::try_from(port)
+            .map_err(|_| EdgelinkError::BadFlowsJson(format!("Invalid port of the MQTT broker: {}", port)))?,
+        None if use_tls => 8883,
+        None => 1883,
+    };
+    let client_id = match config.clientid.trim() {
+        "" => format!("edgelink_{}", Msg::generate_id()),
+        id => id.to_string(),
+    };
+    let keep_alive = Duration::from_secs(config.keepalive.unwrap_or(60) as u64);
+    let clean_session = config.cleansession.unwrap_or(true);
+    let credentials = config.credentials.as_ref().filter(|x| !x.user.is_empty());
+
+    let transport = if use_tls {
+        if tls_insecure || tls.is_some_and(|x| !x.verify_server_cert) {
+            log::warn!("[MQTT_BROKER] Skipping the verification of the server certificate is not supported");
+        }
+        let tls_config = match tls.and_then(|x| x.ca.clone()) {
+            Some(ca) => TlsConfiguration::Simple {
+                ca,
+                alpn: None,
+                client_auth: tls.and_then(|x| Some((x.cert.clone()?, x.key.clone()?))),
+            },
+            None => TlsConfiguration::default(),
+        };
+        Some(Transport::tls_with_config(tls_config))
+    } else {
+        None
+    };
+
+    let will = if config.will_topic.is_empty() {
+        None
+    } else {
+        Some((config.will_topic.clone(), config.will_payload.clone(), config.will_qos.unwrap_or(0)))
+    };
+    let will_retain = config.will_retain.unwrap_or(false);
+
+    match config.protocol_version.unwrap_or(4) {
+        4 => {
+            let mut options = rumqttc::MqttOptions::new(client_id, host, port);
+            options.set_keep_alive(keep_alive).set_clean_session(clean_session);
+            if let Some(credentials) = credentials {
+                options.set_credentials(credentials.user.clone(), credentials.password.clone());
+            }
+            if let Some(transport) = transport {
+                options.set_transport(transport);
+            }
+            if let Some((topic, payload, qos)) = will {
+                options.set_last_will(rumqttc::LastWill::new(topic, payload, to_qos(qos), will_retain));
+            }
+            Ok(BrokerOptions::V4(Box::new(options)))
+        }
+        5 => {
+            let mut options = rumqttc::v5::MqttOptions::new(client_id, host, port);
+            options.set_keep_alive(keep_alive).set_clean_start(clean_session);
+            if let Some(expiry) = config.session_expiry {
+                let expiry = u32::try_from(expiry).map_err(|_| {
+                    EdgelinkError::BadFlowsJson(format!("Invalid session expiry of the MQTT broker: {}", expiry))
+                })?;
+                let mut properties = rumqttc::v5::mqttbytes::v5::ConnectProperties::new();
+                properties.session_expiry_interval = Some(expiry);
+                options.set_connect_properties(properties);
+            }
+            if let Some(credentials) = credentials {
+                options.set_credentials(credentials.user.clone(), credentials.password.clone());
+            }
+            if let Some(transport) = transport {
+                options.set_transport(transport);
+            }
+            if let Some((topic, payload, qos)) = will {
+                options.set_last_will(mqtt5::LastWill::new(topic, payload, to_qos5(qos), will_retain, None));
+            }
+            Ok(BrokerOptions::V5(Box::new(options)))
+        }
+        version => Err(EdgelinkError::NotSupported(format!("Unsupported MQTT protocol version: {}", version)).into()),
+    }
+}
+
+fn to_qos(qos: u8) -> QoS {
+    match qos {
+        0 => QoS::AtMostOnce,
+        1 => QoS::AtLeastOnce,
+        _ => QoS::ExactlyOnce,
+    }
+}
+
+fn to_qos5(qos: u8) -> QoS5 {
+    match qos {
+        0 => QoS5::AtMostOnce,
+        1 => QoS5::AtLeastOnce,
+        _ => QoS5::ExactlyOnce,
+    }
+}
+
+fn try_subscribe(client: &MqttClient, sub: &Subscription) -> crate::Result<()> {
+    match client {
+        MqttClient::V4(client) => client.try_subscribe(sub.filter.clone(), to_qos(sub.qos))?,
+        MqttClient::V5(client) => {
+            let mut filter = mqtt5::Filter::new(sub.filter.clone(), to_qos5(sub.qos));
+            filter.nolocal = sub.nl;
+            filter.preserve_retain = sub.rap;
+            filter.retain_forward_rule = match sub.rh {
+                0 => mqtt5::RetainForwardRule::OnEverySubscribe,
+                1 => mqtt5::RetainForwardRule::OnNewSubscribe,
+                _ => mqtt5::RetainForwardRule::Never,
+            };
+            client.try_subscribe_many([filter])?
+        }
+    }
+    Ok(())
+}
+
+/// Sends the message to the matched subscriptions.
+///
+/// The QoS 1 and 2 messages wait for the room of the subscriber, which stops polling the connection and so holds the
+/// broker back, only the QoS 0 messages are dropped if the subscriber falls behind.
+async fn dispatch(label: &str, subscriptions: &Mutex<Vec<Subscription>>, msg: MqttMessage, cancel: &CancellationToken) {
+    let senders: Vec<mpsc::Sender<MqttMessage>> = {
+        let subs = subscriptions.lock().expect("Lock the subscriptions");
+        subs.iter().filter(|x| topic_matches(&x.filter, &msg.topic)).map(|x| x.sender.clone()).collect()
+    };
+    for sender in senders {
+        if msg.qos == 0 {
+            if let Err(e) = sender.try_send(msg.clone()) {
+                log::warn!("[MQTT_BROKER:{}] Dropped the message of the topic '{}': {}", label, msg.topic, e);
+            }
+            continue;
+        }
+        tokio::select! {
+            _ = cancel.cancelled() => return,
+            res = sender.send(msg.clone()) => {
+                if res.is_err() {
+                    log::debug!("[MQTT_BROKER:{}] The subscriber of the topic '{}' is closed", label, msg.topic);
+                }
+            }
+        }
+    }
+}
+
+fn on_connected(label: &str, client: &MqttClient, subscriptions: &Mutex<Vec<Subscription>>, connected: &AtomicBool) {
+    log::info!("[MQTT_BROKER:{}] Connected", label);
+    connected.store(true, Ordering::SeqCst);
+    let subs = subscriptions.lock().expect("Lock the subscriptions");
+    for sub in subs.iter() {
+        if let Err(e) = try_subscribe(client, sub) {
+            log::warn!("[MQTT_BROKER:{}] Failed to subscribe '{}': {}", label, sub.filter, e);
+        }
+    }
+}
+
+/// Waits before reconnecting, returns `false` if cancelled.
+async fn on_connection_error(label: &str, error: &str, connected: &AtomicBool, cancel: &CancellationToken) -> bool {
+    connected.store(false, Ordering::SeqCst);
+    log::warn!("[MQTT_BROKER:{}] Connection error: {}, reconnecting in {:?}", label, error, RECONNECT_DELAY);
+    tokio::select! {
+        _ = cancel.cancelled() => false,
+        _ = tokio::time::sleep(RECONNECT_DELAY) => true,
+    }
+}
+
+async fn run_v4(
+    label: String,
+    client: rumqttc::AsyncClient,
+    mut event_loop: rumqttc::EventLoop,
+    subscriptions: Arc<Mutex<Vec<Subscription>>>,
+    connected: Arc<AtomicBool>,
+    cancel: CancellationToken,
+) {
+    let wrapped_client = MqttClient::V4(client.clone());
+    loop {
+        let event = tokio::select! {
+            _ = cancel.cancelled() => break,
+            event = event_loop.poll() => event,
+        };
+        match event {
+            Ok(rumqttc::Event::Incoming(rumqttc::Packet::ConnAck(_))) => {
+                on_connected(&label, &wrapped_client, &subscriptions, &connected)
+            }
+            Ok(rumqttc::Event::Incoming(rumqttc::Packet::Publish(publish))) => {
+                let msg = MqttMessage {
+                    topic: publish.topic,
+                    payload: publish.payload.to_vec(),
+                    qos: publish.qos as u8,
+                    retain: publish.retain,
+                    properties: VariantObjectMap::new(),
+                };
+                dispatch(&label, &subscriptions, msg, &cancel).await;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                if !on_connection_error(&label, &e.to_string(), &connected, &cancel).await {
+                    break;
+                }
+            }
+        }
+    }
+
+    connected.store(false, Ordering::SeqCst);
+    if client.try_disconnect().is_ok() {
+        let _ = tokio::time::timeout(Duration::from_secs(1), async {
+            while let Ok(event) = event_loop.poll().await {
+                if matches!(event, rumqttc::Event::Outgoing(rumqttc::Outgoing::Disconnect)) {
+                    break;
+                }
+            }
+        })
+        .await;
+    }
+    log::info!("[MQTT_BROKER:{}] Disconnected", label);
+}
+
+async fn run_v5(
+    label: String,
+    client: rumqttc::v5::AsyncClient,
+    mut event_loop: rumqttc::v5::EventLoop,
+    subscriptions: Arc<Mutex<Vec<Subscription>>>,
+    connected: Arc<AtomicBool>,
+    cancel: CancellationToken,
+) {
+    let wrapped_client = MqttClient::V5(client.clone());
+    loop {
+        let event = tokio::select! {
+            _ = cancel.cancelled() => break,
+            event = event_loop.poll() => event,
+        };
+        match event {
+            Ok(rumqttc::v5::Event::Incoming(mqtt5::Packet::ConnAck(_))) => {
+                on_connected(&label, &wrapped_client, &subscriptions, &connected)
+            }
+            Ok(rumqttc::v5::Event::Incoming(mqtt5::Packet::Publish(publish))) => {
+                let msg = MqttMessage {
+                    topic: String::from_utf8_lossy(&publish.topic).into_owned(),
+                    payload: publish.payload.to_vec(),
+                    qos: publish.qos as u8,
+                    retain: publish.retain,
+                    properties: publish.properties.map(properties_to_map).unwrap_or_default(),
+                };
+                dispatch(&label, &subscriptions, msg, &cancel).await;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                if !on_connection_error(&label, &e.to_string(), &connected, &cancel).await {
+                    break;
+                }
+            }
+        }
+    }
+
+    connected.store(false, Ordering::SeqCst);
+    if client.try_disconnect().is_ok() {
+        let _ = tokio::time::timeout(Duration::from_secs(1), async {
+            while let Ok(event) = event_loop.poll().await {
+                if matches!(event, rumqttc::v5::Event::Outgoing(rumqttc::Outgoing::Disconnect)) {
+                    break;
+                }
+            }
+        })
+        .await;
+    }
+    log::info!("[MQTT_BROKER:{}] Disconnected", label);
+}
+
+/// Converts the MQTT 5.0 properties into the message properties named as Node-RED does.
+fn properties_to_map(props: mqtt5::PublishProperties) -> VariantObjectMap {
+    let mut map = VariantObjectMap::new();
+    if let Some(content_type) = props.content_type {
+        map.insert("contentType".to_string(), Variant::String(content_type));
+    }
+    if let Some(response_topic) = props.response_topic {
+        map.insert("responseTopic".to_string(), Variant::String(response_topic));
+    }
+    if let Some(correlation_data) = props.correlation_data {
+        map.insert("correlationData".to_string(), Variant::Bytes(correlation_data.to_vec()));
+    }
+    if let Some(expiry) = props.message_expiry_interval {
+        map.insert("messageExpiryInterval".to_string(), Variant::from(expiry));
+    }
+    if !props.user_properties.is_empty() {
+        let user_props = props.user_properties.into_iter().map(|(k, v)| (k, Variant::String(v))).collect();
+        map.insert("userProperties".to_string(), Variant::Object(user_props));
+    }
+    map
+}
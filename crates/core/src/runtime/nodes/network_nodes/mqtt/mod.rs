@@ -0,0 +1,162 @@
+//! The MQTT nodes, the `mqtt in` and `mqtt out` nodes share the connection of the `mqtt-broker` config node.
+
+use serde::{Deserialize, Deserializer};
+
+use crate::runtime::model::*;
+
+mod broker;
+mod mqtt_in;
+mod mqtt_out;
+
+/// A message received from the broker.
+#[derive(Debug, Clone)]
+pub(super) struct MqttMessage {
+    pub topic: String,
+    pub payload: Vec<u8>,
+    pub qos: u8,
+    pub retain: bool,
+
+    /// The MQTT 5 properties set to the message, e.g. `contentType`
+    pub properties: VariantObjectMap,
+}
+
+/// The MQTT 5 properties of a message to publish, ignored by MQTT 3.1.1.
+#[derive(Debug, Clone, Default)]
+pub(super) struct PublishProperties {
+    pub content_type: Option<String>,
+    pub response_topic: Option<String>,
+    pub correlation_data: Option<Vec<u8>>,
+    pub user_properties: Vec<(String, String)>,
+    pub message_expiry_interval: Option<u32>,
+}
+
+/// Deserializes the QoS of `0`, `1` or `2`, either a number or a string, an empty string means unset.
+fn deser_qos<'de, D>(deserializer: D) -> Result<Option<u8>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match json::deser::str_to_option_usize(deserializer)? {
+        Some(qos) if qos <= 2 => Ok(Some(qos as u8)),
+        Some(qos) => Err(serde::de::Error::custom(format!("The QoS must be 0, 1 or 2, got: {}", qos))),
+        None => Ok(None),
+    }
+}
+
+/// Deserializes the `true` and `false` which may be strings, an empty string means unset.
+fn deser_option_bool<'de, D>(deserializer: D) -> Result<Option<bool>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Bool(b) => Ok(Some(b)),
+        serde_json::Value::String(s) if s.trim().is_empty() => Ok(None),
+        serde_json::Value::String(s) => s.trim().parse::<bool>().map(Some).map_err(serde::de::Error::custom),
+        serde_json::Value::Null => Ok(None),
+        other => Err(serde::de::Error::custom(format!("Expected a boolean, got: {}", other))),
+    }
+}
+
+/// Whether the topic matches the filter with the `+` and `#` wildcards.
+///
+/// The topics starting with `$` are not matched by the wildcards at the first level, and the `$share/{group}/` prefix
+/// of the shared subscriptions is ignored.
+fn topic_matches(filter: &str, topic: &str) -> bool {
+    let filter = match filter.strip_prefix("$share/") {
+        Some(shared) => shared.split_once('/').map_or("", |(_group, filter)| filter),
+        None => filter,
+    };
+    if topic.starts_with('$') && (filter.starts_with('+') || filter.starts_with('#')) {
+        return false;
+    }
+    let mut filter_levels = filter.split('/');
+    let mut topic_levels = topic.split('/');
+    loop {
+        match (filter_levels.next(), topic_levels.next()) {
+            (Some("#"), _) => return true,
+            (Some("+"), Some(_)) => {}
+            (Some(filter_level), Some(topic_level)) if filter_level == topic_level => {}
+            (None, None) => return true,
+            _ => return false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+    use testcontainers_modules::mosquitto::Mosquitto;
+    use testcontainers_modules::testcontainers::runners::AsyncRunner;
+
+    async fn publish_and_subscribe(protocol_version: &str) -> Vec<Msg> {
+        let container = Mosquitto::default().start().await.unwrap();
+        let host = container.get_host().await.unwrap();
+        let port = container.get_host_port_ipv4(1883).await.unwrap();
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "900", "type": "mqtt-broker", "broker": host.to_string(), "port": port.to_string(),
+                "protocolVersion": protocol_version, "keepalive": "30", "cleansession": true},
+            {"id": "1", "z": "100", "type": "mqtt out", "broker": "900", "qos": "1", "retain": "true", "wires": []},
+            {"id": "2", "z": "100", "type": "mqtt in", "broker": "900", "topic": "test/+", "qos": "1",
+                "datatype": "auto-detect", "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"topic": "test/a", "payload": {"value": 1}}],
+            ["1", {"topic": "test/b", "payload": "hello"}],
+        ]))
+        .unwrap();
+        let mut msgs = engine.run_once_with_inject(2, Duration::from_secs(5), msgs_to_inject).await.unwrap();
+        msgs.sort_by(|a, b| a["topic"].as_str().cmp(&b["topic"].as_str()));
+        msgs
+    }
+
+    #[tokio::test]
+    #[ignore = "requires Docker to run the Mosquitto container"]
+    async fn test_it_should_publish_and_subscribe_mqtt_v311() {
+        let msgs = publish_and_subscribe("4").await;
+        assert_eq!(msgs.len(), 2);
+        assert_eq!(msgs[0]["topic"], Variant::from("test/a"));
+        assert_eq!(msgs[0]["payload"], Variant::from(json!({"value": 1})));
+        assert_eq!(msgs[0]["qos"], Variant::from(1));
+        assert_eq!(msgs[1]["topic"], Variant::from("test/b"));
+        assert_eq!(msgs[1]["payload"], Variant::from("hello"));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires Docker to run the Mosquitto container"]
+    async fn test_it_should_publish_and_subscribe_mqtt_v5() {
+        let msgs = publish_and_subscribe("5").await;
+        assert_eq!(msgs.len(), 2);
+        assert_eq!(msgs[0]["payload"], Variant::from(json!({"value": 1})));
+        assert_eq!(msgs[1]["payload"], Variant::from("hello"));
+    }
+
+    #[test]
+    fn test_unsupported_protocol_version_should_fail_to_build() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "900", "type": "mqtt-broker", "broker": "localhost", "protocolVersion": "3"},
+            {"id": "1", "z": "100", "type": "mqtt out", "broker": "900", "wires": []}
+        ]);
+        assert!(crate::runtime::engine::build_test_engine(flows_json).is_err());
+    }
+
+    #[test]
+    fn test_topic_should_match_wildcards() {
+        assert!(topic_matches("a/b", "a/b"));
+        assert!(!topic_matches("a/b", "a/b/c"));
+        assert!(topic_matches("a/+/c", "a/b/c"));
+        assert!(!topic_matches("a/+", "a/b/c"));
+        assert!(topic_matches("a/#", "a"));
+        assert!(topic_matches("a/#", "a/b/c"));
+        assert!(topic_matches("#", "a/b"));
+        assert!(topic_matches("+/+", "/b"));
+        assert!(!topic_matches("#", "$SYS/uptime"));
+        assert!(!topic_matches("+/uptime", "$SYS/uptime"));
+        assert!(topic_matches("$SYS/#", "$SYS/uptime"));
+        assert!(topic_matches("$share/group/a/+", "a/b"));
+    }
+}
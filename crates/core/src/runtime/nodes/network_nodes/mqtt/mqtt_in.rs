@@ -0,0 +1,169 @@
+use std::sync::Arc;
+
+use base64::prelude::*;
+use serde::Deserialize;
+use tokio::sync::mpsc;
+
+use super::broker::{MqttBroker, MqttBrokerNode, Subscription};
+use super::*;
+use crate::runtime::flow::Flow;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+enum MqttInDataType {
+    /// Parses JSON or UTF-8 if possible, otherwise a buffer
+    #[default]
+    #[serde(rename = "auto-detect")]
+    AutoDetect,
+
+    /// UTF-8 if possible, otherwise a buffer
+    #[serde(rename = "auto")]
+    Auto,
+
+    #[serde(rename = "utf8")]
+    Utf8,
+
+    #[serde(rename = "buffer")]
+    Buffer,
+
+    #[serde(rename = "json")]
+    Json,
+
+    #[serde(rename = "base64")]
+    Base64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct MqttInNodeConfig {
+    topic: String,
+
+    #[serde(default, deserialize_with = "deser_qos")]
+    qos: Option<u8>,
+
+    #[serde(default)]
+    datatype: MqttInDataType,
+
+    #[serde(default, deserialize_with = "json::deser::deser_red_optional_id")]
+    broker: Option<ElementId>,
+
+    /// MQTT 5.0 only: do not receive the messages published by this client
+    #[serde(default, deserialize_with = "deser_option_bool")]
+    nl: Option<bool>,
+
+    /// MQTT 5.0 only: keep the retain flag as published
+    #[serde(default, deserialize_with = "deser_option_bool")]
+    rap: Option<bool>,
+
+    /// MQTT 5.0 only: the retain handling, `0` to send the retained messages on every subscription, `1` on new
+    /// subscriptions only and `2` never
+    #[serde(default, deserialize_with = "json::deser::str_to_option_usize")]
+    rh: Option<usize>,
+}
+
+/// Subscribes a topic of the MQTT broker and sends the messages received.
+#[derive(Debug)]
+#[flow_node("mqtt in")]
+struct MqttInNode {
+    base: FlowNode,
+    config: MqttInNodeConfig,
+    broker: Arc<MqttBroker>,
+}
+
+impl MqttInNode {
+    fn build(flow: &Flow, base_node: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let mqtt_config = MqttInNodeConfig::deserialize(&config.rest)?;
+        if mqtt_config.topic.is_empty() {
+            return Err(EdgelinkError::BadFlowsJson("The topic of the 'mqtt in' node must not be empty".into()).into());
+        }
+        if mqtt_config.rh.is_some_and(|x| x > 2) {
+            return Err(EdgelinkError::BadFlowsJson("The retain handling must be 0, 1 or 2".into()).into());
+        }
+        let engine = flow.engine().ok_or(EdgelinkError::InvalidOperation("The engine has been released".into()))?;
+        let broker = MqttBrokerNode::get_broker(&engine, mqtt_config.broker)?;
+        let node = MqttInNode { base: base_node, config: mqtt_config, broker };
+        Ok(Box::new(node))
+    }
+
+    fn make_msg(&self, mqtt_msg: MqttMessage) -> crate::Result<Msg> {
+        let payload = match self.config.datatype {
+            MqttInDataType::Buffer => Variant::Bytes(mqtt_msg.payload),
+            MqttInDataType::Base64 => Variant::String(BASE64_STANDARD.encode(&mqtt_msg.payload)),
+            MqttInDataType::Utf8 => Variant::String(String::from_utf8_lossy(&mqtt_msg.payload).into_owned()),
+            MqttInDataType::Json => {
+                let jv: serde_json::Value = serde_json::from_slice(&mqtt_msg.payload)
+                    .map_err(|e| EdgelinkError::InvalidOperation(format!("Failed to parse the JSON payload: {}", e)))?;
+                Variant::deserialize(jv)?
+            }
+            MqttInDataType::Auto | MqttInDataType::AutoDetect => match String::from_utf8(mqtt_msg.payload) {
+                Ok(text) if self.config.datatype == MqttInDataType::AutoDetect => {
+                    match serde_json::from_str::<serde_json::Value>(&text) {
+                        Ok(jv) if jv.is_object() || jv.is_array() => Variant::deserialize(jv)?,
+                        _ => Variant::String(text),
+                    }
+                }
+                Ok(text) => Variant::String(text),
+                Err(e) => Variant::Bytes(e.into_bytes()),
+            },
+        };
+
+        let mut msg = MsgBuilder::new().build()?;
+        for (key, value) in mqtt_msg.properties.into_iter() {
+            msg.set(key, value);
+        }
+        msg.set("topic".to_string(), Variant::String(mqtt_msg.topic));
+        msg.set("payload".to_string(), payload);
+        msg.set("qos".to_string(), Variant::from(mqtt_msg.qos as u32));
+        msg.set("retain".to_string(), Variant::Bool(mqtt_msg.retain));
+        Ok(msg)
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for MqttInNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        let (sender, mut receiver) = mpsc::channel(64);
+        self.broker.connect(self.id());
+        let sub = Subscription {
+            node_id: self.id(),
+            filter: self.config.topic.clone(),
+            qos: self.config.qos.unwrap_or(2),
+            nl: self.config.nl.unwrap_or(false),
+            rap: self.config.rap.unwrap_or(false),
+            rh: self.config.rh.unwrap_or(0) as u8,
+            sender,
+        };
+        if let Err(e) = self.broker.subscribe(sub) {
+            log::error!("[MQTT_IN:{}] Failed to subscribe '{}': {}", self.name(), self.config.topic, e);
+            stop_token.cancelled().await;
+        }
+
+        loop {
+            let mqtt_msg = tokio::select! {
+                _ = stop_token.cancelled() => break,
+                msg = receiver.recv() => match msg {
+                    Some(msg) => msg,
+                    None => break,
+                },
+            };
+            let msg = match self.make_msg(mqtt_msg) {
+                Ok(msg) => MsgHandle::new(msg),
+                Err(e) => {
+                    log::warn!("[MQTT_IN:{}] {}", self.name(), e);
+                    continue;
+                }
+            };
+            self.notify_uow_completed(msg.clone(), stop_token.clone()).await;
+            if let Err(e) = self.fan_out_one(Envelope { port: 0, msg }, stop_token.child_token()).await {
+                log::warn!("[MQTT_IN:{}] Failed to send the message: {}", self.name(), e);
+            }
+        }
+
+        self.broker.unsubscribe(self.id());
+        self.broker.disconnect(self.id());
+    }
+}
@@ -0,0 +1,136 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use super::broker::{MqttBroker, MqttBrokerNode};
+use super::*;
+use crate::runtime::flow::Flow;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+#[derive(Debug, Clone, Deserialize)]
+struct MqttOutNodeConfig {
+    /// Uses `msg.topic` if empty
+    #[serde(default)]
+    topic: String,
+
+    /// Uses `msg.qos` if unset
+    #[serde(default, deserialize_with = "deser_qos")]
+    qos: Option<u8>,
+
+    /// Uses `msg.retain` if unset
+    #[serde(default, deserialize_with = "deser_option_bool")]
+    retain: Option<bool>,
+
+    #[serde(default, deserialize_with = "json::deser::deser_red_optional_id")]
+    broker: Option<ElementId>,
+
+    /// MQTT 5.0 only, uses `msg.responseTopic` if empty
+    #[serde(rename = "respTopic", default)]
+    resp_topic: String,
+
+    /// MQTT 5.0 only, uses `msg.contentType` if empty
+    #[serde(rename = "contentType", default)]
+    content_type: String,
+
+    /// MQTT 5.0 only, the message expiry interval in seconds, uses `msg.messageExpiryInterval` if unset
+    #[serde(default, deserialize_with = "json::deser::str_to_option_usize")]
+    expiry: Option<usize>,
+}
+
+/// Publishes `msg.payload` to the MQTT broker.
+#[derive(Debug)]
+#[flow_node("mqtt out")]
+struct MqttOutNode {
+    base: FlowNode,
+    config: MqttOutNodeConfig,
+    broker: Arc<MqttBroker>,
+}
+
+impl MqttOutNode {
+    fn build(flow: &Flow, base_node: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let mqtt_config = MqttOutNodeConfig::deserialize(&config.rest)?;
+        let engine = flow.engine().ok_or(EdgelinkError::InvalidOperation("The engine has been released".into()))?;
+        let broker = MqttBrokerNode::get_broker(&engine, mqtt_config.broker)?;
+        let node = MqttOutNode { base: base_node, config: mqtt_config, broker };
+        Ok(Box::new(node))
+    }
+
+    async fn publish(&self, msg: &Msg) -> crate::Result<()> {
+        let topic = match self.config.topic.as_str() {
+            "" => msg.get("topic").and_then(|x| x.as_str()).unwrap_or_default(),
+            topic => topic,
+        };
+        if topic.is_empty() || topic.contains(['+', '#']) {
+            return Err(EdgelinkError::InvalidOperation(format!("Invalid topic to publish: '{}'", topic)).into());
+        }
+        let qos = match self.config.qos {
+            Some(qos) => qos,
+            None => match msg.get("qos") {
+                Some(qos) => qos
+                    .coerce_number()
+                    .and_then(|x| x.as_u64())
+                    .filter(|x| *x <= 2)
+                    .map(|x| x as u8)
+                    .ok_or(EdgelinkError::InvalidOperation(format!("The QoS must be 0, 1 or 2, got: {:?}", qos)))?,
+                None => 0,
+            },
+        };
+        let retain = self.config.retain.or_else(|| msg.get("retain").and_then(|x| x.coerce_bool())).unwrap_or(false);
+
+        let payload = match msg.get("payload") {
+            None | Some(Variant::Null) => Vec::new(),
+            Some(Variant::String(s)) => s.as_bytes().to_vec(),
+            Some(Variant::Bytes(bytes)) => bytes.clone(),
+            Some(value @ (Variant::Number(_) | Variant::Bool(_))) => value.to_string()?.into_bytes(),
+            Some(other) => serde_json::to_vec(other)?,
+        };
+
+        let props = self.make_properties(msg)?;
+        self.broker.publish(topic, qos, retain, payload, props).await
+    }
+
+    fn make_properties(&self, msg: &Msg) -> crate::Result<PublishProperties> {
+        let non_empty = |config_value: &str, prop: &str| match config_value {
+            "" => msg.get(prop).and_then(|x| x.as_str()).filter(|x| !x.is_empty()).map(str::to_string),
+            value => Some(value.to_string()),
+        };
+        let message_expiry_interval = match self.config.expiry {
+            Some(expiry) => Some(expiry as u64),
+            None => msg.get("messageExpiryInterval").and_then(|x| x.coerce_number()).and_then(|x| x.as_u64()),
+        };
+        let user_properties = match msg.get("userProperties") {
+            Some(Variant::Object(props)) => {
+                props.iter().map(|(k, v)| Ok((k.clone(), v.to_string()?))).collect::<crate::Result<Vec<_>>>()?
+            }
+            _ => Vec::new(),
+        };
+        Ok(PublishProperties {
+            content_type: non_empty(&self.config.content_type, "contentType"),
+            response_topic: non_empty(&self.config.resp_topic, "responseTopic"),
+            correlation_data: msg.get("correlationData").and_then(|x| x.to_bytes()),
+            user_properties,
+            message_expiry_interval: message_expiry_interval.map(|x| x.min(u32::MAX as u64) as u32),
+        })
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for MqttOutNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        self.broker.connect(self.id());
+        while !stop_token.is_cancelled() {
+            with_uow_concurrent(&self, stop_token.child_token(), |node, msg| async move {
+                let msg_guard = msg.read().await;
+                node.publish(&msg_guard).await
+            })
+            .await;
+        }
+        wait_uows_completed(self.as_ref()).await;
+        self.broker.disconnect(self.id());
+    }
+}
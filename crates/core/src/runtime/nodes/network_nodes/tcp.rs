@@ -0,0 +1,233 @@
+//! The connections shared by the `tcp in` and `tcp out` nodes, the `tcp out` node in the `reply` mode writes to the
+//! connections of the `tcp in` nodes by `msg._session`.
+
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use base64::prelude::*;
+use dashmap::DashMap;
+use tokio::io::AsyncWriteExt;
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinSet;
+
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+
+/// The first delay before reconnecting, doubled on every failure
+pub(super) const RECONNECT_DELAY_MIN: Duration = Duration::from_secs(1);
+
+pub(super) const RECONNECT_DELAY_MAX: Duration = Duration::from_secs(60);
+
+pub(super) const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub(super) const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// The most bytes buffered for a message of a connection, e.g. a line without the delimiter
+pub(super) const MAX_PENDING_SIZE: usize = 16 * 1024 * 1024;
+
+/// The ID of a TCP connection, exposed as the hex string in `msg._session.id`
+pub(super) type SessionId = u64;
+
+/// The settings of the accepted and connected sockets
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct ConnectionOptions {
+    /// The idle time before sending the keep-alive probes, the keep-alive is disabled if `None`
+    pub keepalive: Option<Duration>,
+
+    /// The maximum count of the accepted connections, unlimited if `None`
+    pub max_connections: Option<usize>,
+}
+
+impl ConnectionOptions {
+    /// Makes the options from the `keepalive` in seconds and `maxConnections` of the node, `0` means disabled.
+    pub fn new(keepalive: Option<f64>, max_connections: Option<usize>) -> crate::Result<Self> {
+        let keepalive = match keepalive {
+            Some(secs) if secs < 0.0 || !secs.is_finite() => {
+                return Err(EdgelinkError::BadFlowsJson(format!("Bad keep-alive of the TCP node: {}", secs)).into())
+            }
+            Some(secs) if secs > 0.0 => Some(Duration::from_secs_f64(secs)),
+            _ => None,
+        };
+        Ok(Self { keepalive, max_connections: max_connections.filter(|x| *x > 0) })
+    }
+
+    pub fn apply(&self, stream: &TcpStream) {
+        if let Some(idle) = self.keepalive {
+            let keepalive = socket2::TcpKeepalive::new().with_time(idle);
+            if let Err(e) = socket2::SockRef::from(stream).set_tcp_keepalive(&keepalive) {
+                log::warn!("[TCP] Failed to set the keep-alive of the connection: {}", e);
+            }
+        }
+    }
+}
+
+/// The writing half of a TCP connection.
+#[derive(Debug)]
+pub(super) struct TcpSession {
+    pub peer: SocketAddr,
+    writer: tokio::sync::Mutex<OwnedWriteHalf>,
+}
+
+impl TcpSession {
+    pub fn new(peer: SocketAddr, writer: OwnedWriteHalf) -> Arc<Self> {
+        Arc::new(Self { peer, writer: tokio::sync::Mutex::new(writer) })
+    }
+
+    pub async fn write(&self, bytes: &[u8]) -> crate::Result<()> {
+        let mut writer = self.writer.lock().await;
+        writer.write_all(bytes).await?;
+        writer.flush().await?;
+        Ok(())
+    }
+
+    /// Closes the writing half, the peer reads the end of the stream.
+    pub async fn shutdown(&self) {
+        let _ = self.writer.lock().await.shutdown().await;
+    }
+}
+
+fn sessions() -> &'static DashMap<SessionId, Arc<TcpSession>> {
+    static SESSIONS: OnceLock<DashMap<SessionId, Arc<TcpSession>>> = OnceLock::new();
+    SESSIONS.get_or_init(DashMap::new)
+}
+
+/// Registers the connection of a `tcp in` node for the replies.
+pub(super) fn add_session(session: Arc<TcpSession>) -> SessionId {
+    static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1);
+    let id = NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed);
+    sessions().insert(id, session);
+    id
+}
+
+pub(super) fn remove_session(id: SessionId) {
+    sessions().remove(&id);
+}
+
+pub(super) fn get_session(id: SessionId) -> Option<Arc<TcpSession>> {
+    sessions().get(&id).map(|x| x.value().clone())
+}
+
+pub(super) fn all_sessions() -> Vec<Arc<TcpSession>> {
+    sessions().iter().map(|x| x.value().clone()).collect()
+}
+
+pub(super) fn make_session_value(id: SessionId) -> Variant {
+    Variant::Object(VariantObjectMap::from([
+        ("type".to_string(), Variant::from("tcp")),
+        ("id".to_string(), Variant::String(format!("{:x}", id))),
+    ]))
+}
+
+/// Gets the session ID in `msg._session` if it is of a TCP connection.
+pub(super) fn session_id_of(msg: &Msg) -> Option<SessionId> {
+    msg.get("_session")
+        .and_then(|x| x.as_object())
+        .filter(|x| x.get("type").and_then(|t| t.as_str()) == Some("tcp"))
+        .and_then(|x| x.get("id"))
+        .and_then(|x| x.as_str())
+        .and_then(|x| SessionId::from_str_radix(x, 16).ok())
+}
+
+/// Converts the payload to the bytes to write, returns `None` if there is nothing to write.
+pub(super) fn payload_to_bytes(payload: Option<&Variant>, base64: bool) -> crate::Result<Option<Vec<u8>>> {
+    let bytes = match payload {
+        None | Some(Variant::Null) => return Ok(None),
+        Some(Variant::Bytes(bytes)) => bytes.clone(),
+        Some(Variant::String(s)) if base64 => BASE64_STANDARD
+            .decode(s.trim())
+            .map_err(|e| EdgelinkError::InvalidOperation(format!("Bad base64 payload: {}", e)))?,
+        Some(Variant::String(s)) => s.as_bytes().to_vec(),
+        Some(value @ (Variant::Number(_) | Variant::Bool(_))) => value.to_string()?.into_bytes(),
+        Some(other) => serde_json::to_vec(other)?,
+    };
+    Ok(Some(bytes))
+}
+
+/// Binds the listener of the `host` and `port`, all interfaces if the host is empty.
+pub(super) async fn bind(host: &str, port: u16) -> crate::Result<TcpListener> {
+    let host = if host.trim().is_empty() { "0.0.0.0" } else { host.trim() };
+    let listener =
+        TcpListener::bind((host, port)).await.with_context(|| format!("Failed to listen on '{}:{}'", host, port))?;
+    Ok(listener)
+}
+
+/// Connects the remote address within the `CONNECT_TIMEOUT`.
+pub(super) async fn connect(host: &str, port: u16, options: &ConnectionOptions) -> crate::Result<TcpStream> {
+    let stream = tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect((host, port)))
+        .await
+        .map_err(|_| EdgelinkError::Timeout)
+        .with_context(|| format!("Timed out connecting '{}:{}'", host, port))?
+        .with_context(|| format!("Failed to connect '{}:{}'", host, port))?;
+    options.apply(&stream);
+    Ok(stream)
+}
+
+/// Accepts the connections until cancelled, the connections over `max_connections` are closed immediately.
+///
+/// Every connection is handled by a task with a child token of `cancel`, the tasks are awaited before returning so
+/// the connections are shut down gracefully.
+pub(super) async fn accept_loop<F, Fut>(
+    label: &str,
+    listener: TcpListener,
+    options: ConnectionOptions,
+    cancel: CancellationToken,
+    handler: F,
+) where
+    F: Fn(TcpStream, SocketAddr, CancellationToken) -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let mut tasks = JoinSet::new();
+    loop {
+        let (stream, peer) = tokio::select! {
+            _ = cancel.cancelled() => break,
+            res = listener.accept() => match res {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    log::warn!("[{}] Failed to accept the connection: {}", label, e);
+                    continue;
+                }
+            },
+        };
+        while tasks.try_join_next().is_some() {}
+        if let Some(max) = options.max_connections.filter(|max| tasks.len() >= *max) {
+            log::warn!("[{}] Refused the connection from {}, the limit of {} connection(s) reached", label, peer, max);
+            continue;
+        }
+        options.apply(&stream);
+        tasks.spawn(handler(stream, peer, cancel.child_token()));
+    }
+    while tasks.join_next().await.is_some() {}
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    pub(in super::super) fn free_port() -> u16 {
+        std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+    }
+
+    /// Connects the port on the localhost, waits until the node is listening.
+    pub(in super::super) async fn connect_with_retry(port: u16) -> TcpStream {
+        for _ in 0..100 {
+            if let Ok(stream) = TcpStream::connect(("127.0.0.1", port)).await {
+                return stream;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("Cannot connect the port {}", port);
+    }
+
+    #[test]
+    fn test_payload_should_convert_to_bytes() {
+        assert_eq!(payload_to_bytes(None, false).unwrap(), None);
+        assert_eq!(payload_to_bytes(Some(&Variant::from("hi")), false).unwrap(), Some(b"hi".to_vec()));
+        assert_eq!(payload_to_bytes(Some(&Variant::from("aGk=")), true).unwrap(), Some(b"hi".to_vec()));
+        assert_eq!(payload_to_bytes(Some(&Variant::from(42)), false).unwrap(), Some(b"42".to_vec()));
+        assert!(payload_to_bytes(Some(&Variant::from("not base64!")), true).is_err());
+    }
+}
@@ -0,0 +1,477 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use base64::prelude::*;
+use serde::Deserialize;
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+
+use super::tcp::{self, ConnectionOptions, TcpSession};
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum TcpInMode {
+    /// Listens on the port and accepts the connections
+    #[default]
+    #[serde(rename = "server")]
+    Server,
+
+    /// Connects the remote host, reconnects if the connection is lost
+    #[serde(rename = "client")]
+    Client,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum TcpDataMode {
+    /// Sends the data as soon as it is received
+    #[default]
+    #[serde(rename = "stream")]
+    Stream,
+
+    /// Sends all the data of the connection once it is closed
+    #[serde(rename = "single")]
+    Single,
+
+    /// Sends the data split by the delimiter
+    #[serde(rename = "delimited")]
+    Delimited,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum TcpDataType {
+    #[default]
+    #[serde(rename = "buffer")]
+    Buffer,
+
+    #[serde(rename = "utf8")]
+    Utf8,
+
+    #[serde(rename = "base64")]
+    Base64,
+
+    /// UTF-8 strings split by the delimiter, without the delimiter and the trailing `\r`
+    #[serde(rename = "lines")]
+    Lines,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct TcpInNodeConfig {
+    #[serde(default)]
+    server: TcpInMode,
+
+    /// The address to listen on in the `server` mode, or the remote host in the `client` mode
+    #[serde(default)]
+    host: String,
+
+    #[serde(default, deserialize_with = "json::deser::str_to_option_u16")]
+    port: Option<u16>,
+
+    #[serde(default)]
+    datamode: TcpDataMode,
+
+    #[serde(default)]
+    datatype: TcpDataType,
+
+    /// The delimiter of the `delimited` mode and the `lines` type with the escapes like `\n`, defaults to `\n`
+    #[serde(default)]
+    newline: String,
+
+    /// Removes the delimiter from the messages of the `delimited` mode
+    #[serde(default, deserialize_with = "json::deser::deser_bool_or_str")]
+    trim: bool,
+
+    #[serde(default)]
+    topic: String,
+
+    /// The idle time in seconds before sending the keep-alive probes, `0` to disable
+    #[serde(default, deserialize_with = "json::deser::str_to_option_f64")]
+    keepalive: Option<f64>,
+
+    /// The maximum count of the connections accepted in the `server` mode, `0` for unlimited
+    #[serde(rename = "maxConnections", default, deserialize_with = "json::deser::str_to_option_usize")]
+    max_connections: Option<usize>,
+}
+
+/// Receives the data of the TCP connections, `msg._session` identifies the connection for the `tcp out` node to reply.
+#[derive(Debug)]
+#[flow_node("tcp in")]
+struct TcpInNode {
+    base: FlowNode,
+    config: TcpInNodeConfig,
+    port: u16,
+    delimiter: Vec<u8>,
+    options: ConnectionOptions,
+}
+
+impl TcpInNode {
+    fn build(
+        _flow: &Flow,
+        base_node: FlowNode,
+        config: &RedFlowNodeConfig,
+    ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let tcp_config = TcpInNodeConfig::deserialize(&config.rest)?;
+        let port =
+            tcp_config.port.ok_or(EdgelinkError::BadFlowsJson("The port of the 'tcp in' node must be set".into()))?;
+        if tcp_config.server == TcpInMode::Client && (tcp_config.host.trim().is_empty() || port == 0) {
+            return Err(EdgelinkError::BadFlowsJson(
+                "The host and port of the 'tcp in' node must be set in the client mode".into(),
+            )
+            .into());
+        }
+        let options = ConnectionOptions::new(tcp_config.keepalive, tcp_config.max_connections)?;
+        let delimiter = parse_delimiter(&tcp_config.newline);
+        let node = TcpInNode { base: base_node, config: tcp_config, port, delimiter, options };
+        Ok(Box::new(node))
+    }
+
+    /// Reads the connection until it is closed or the node stopped.
+    async fn serve_connection(self: Arc<Self>, stream: TcpStream, peer: SocketAddr, cancel: CancellationToken) {
+        let (mut reader, writer) = stream.into_split();
+        let session = TcpSession::new(peer, writer);
+        let session_id = tcp::add_session(session.clone());
+        log::debug!("[TCP_IN:{}] Connected with {}", self.name(), peer);
+
+        let mut buf = vec![0u8; tcp::READ_BUFFER_SIZE];
+        let mut pending = Vec::new();
+        // The bytes of `pending` already searched for the delimiter
+        let mut searched = 0usize;
+        let split = self.config.datamode == TcpDataMode::Delimited || self.config.datatype == TcpDataType::Lines;
+        let mut closed_by_peer = false;
+        loop {
+            let n = tokio::select! {
+                _ = cancel.cancelled() => break,
+                res = reader.read(&mut buf) => match res {
+                    Ok(0) => {
+                        closed_by_peer = true;
+                        break;
+                    }
+                    Ok(n) => n,
+                    Err(e) => {
+                        log::warn!("[TCP_IN:{}] Failed to read from {}: {}", self.name(), peer, e);
+                        break;
+                    }
+                },
+            };
+            pending.extend_from_slice(&buf[..n]);
+            match self.config.datamode {
+                TcpDataMode::Single => {}
+                _ if split => {
+                    // The delimiter may start in the last bytes searched before
+                    let mut from = searched.saturating_sub(self.delimiter.len() - 1);
+                    while let Some(pos) = find_delimiter(&pending[from..], &self.delimiter).map(|x| x + from) {
+                        let mut part: Vec<u8> = pending.drain(..pos + self.delimiter.len()).collect();
+                        if self.config.trim || self.config.datatype == TcpDataType::Lines {
+                            part.truncate(pos);
+                        }
+                        self.emit(part, peer, session_id, &cancel).await;
+                        from = 0;
+                    }
+                    searched = pending.len();
+                }
+                _ => {
+                    // The incomplete UTF-8 character at the end is sent with the next read
+                    let tail = match self.config.datatype {
+                        TcpDataType::Utf8 => pending.split_off(complete_utf8_len(&pending)),
+                        _ => Vec::new(),
+                    };
+                    let data = std::mem::replace(&mut pending, tail);
+                    if !data.is_empty() {
+                        self.emit(data, peer, session_id, &cancel).await;
+                    }
+                }
+            }
+            if pending.len() > tcp::MAX_PENDING_SIZE {
+                log::warn!(
+                    "[TCP_IN:{}] The data from {} exceeds {} bytes, sent without the delimiter",
+                    self.name(),
+                    peer,
+                    tcp::MAX_PENDING_SIZE
+                );
+                self.emit(std::mem::take(&mut pending), peer, session_id, &cancel).await;
+                searched = 0;
+            }
+        }
+
+        // The rest of the data is sent if the peer closed the connection
+        if closed_by_peer && !pending.is_empty() {
+            self.emit(pending, peer, session_id, &cancel).await;
+        }
+        tcp::remove_session(session_id);
+        session.shutdown().await;
+        log::debug!("[TCP_IN:{}] Disconnected from {}", self.name(), peer);
+    }
+
+    fn make_msg(&self, data: Vec<u8>, peer: SocketAddr, session_id: tcp::SessionId) -> crate::Result<Msg> {
+        let payload = match self.config.datatype {
+            TcpDataType::Buffer => Variant::Bytes(data),
+            TcpDataType::Utf8 => Variant::String(String::from_utf8_lossy(&data).into_owned()),
+            TcpDataType::Base64 => Variant::String(BASE64_STANDARD.encode(&data)),
+            TcpDataType::Lines => {
+                let line = String::from_utf8_lossy(&data);
+                Variant::String(line.strip_suffix('\r').unwrap_or(&line).to_string())
+            }
+        };
+        let mut msg = MsgBuilder::new().payload(payload).build()?;
+        if !self.config.topic.is_empty() {
+            msg.set("topic".to_string(), Variant::String(self.config.topic.clone()));
+        }
+        msg.set("ip".to_string(), Variant::from(peer.ip()));
+        msg.set("port".to_string(), Variant::from(peer.port() as u32));
+        msg.set("_session".to_string(), tcp::make_session_value(session_id));
+        Ok(msg)
+    }
+
+    async fn emit(&self, data: Vec<u8>, peer: SocketAddr, session_id: tcp::SessionId, cancel: &CancellationToken) {
+        let msg = match self.make_msg(data, peer, session_id) {
+            Ok(msg) => MsgHandle::new(msg),
+            Err(e) => {
+                log::warn!("[TCP_IN:{}] {}", self.name(), e);
+                return;
+            }
+        };
+        self.notify_uow_completed(msg.clone(), cancel.clone()).await;
+        if let Err(e) = self.fan_out_one(Envelope { port: 0, msg }, cancel.child_token()).await {
+            log::warn!("[TCP_IN:{}] Failed to send the message: {}", self.name(), e);
+        }
+    }
+
+    /// Connects the remote host and reconnects with the exponential backoff until cancelled.
+    async fn run_client(self: Arc<Self>, stop_token: CancellationToken) {
+        let host = self.config.host.trim().to_string();
+        let mut delay = tcp::RECONNECT_DELAY_MIN;
+        loop {
+            let res = tokio::select! {
+                _ = stop_token.cancelled() => break,
+                res = tcp::connect(&host, self.port, &self.options) => res,
+            };
+            match res.and_then(|stream| Ok((stream.peer_addr()?, stream))) {
+                Ok((peer, stream)) => {
+                    delay = tcp::RECONNECT_DELAY_MIN;
+                    self.clone().serve_connection(stream, peer, stop_token.child_token()).await;
+                }
+                Err(e) => log::warn!("[TCP_IN:{}] {}", self.name(), e),
+            }
+            tokio::select! {
+                _ = stop_token.cancelled() => break,
+                _ = tokio::time::sleep(delay) => {}
+            }
+            delay = (delay * 2).min(tcp::RECONNECT_DELAY_MAX);
+        }
+    }
+}
+
+fn find_delimiter(data: &[u8], delimiter: &[u8]) -> Option<usize> {
+    data.windows(delimiter.len()).position(|x| x == delimiter)
+}
+
+/// The length of the data without the incomplete UTF-8 character at the end, the invalid bytes are kept.
+fn complete_utf8_len(data: &[u8]) -> usize {
+    match std::str::from_utf8(data) {
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        _ => data.len(),
+    }
+}
+
+/// Parses the delimiter saved by Node-RED with the escapes, e.g. `\\r\\n`.
+fn parse_delimiter(s: &str) -> Vec<u8> {
+    if s.is_empty() {
+        return b"\n".to_vec();
+    }
+    let mut delimiter = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            delimiter.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => delimiter.push('\n'),
+            Some('r') => delimiter.push('\r'),
+            Some('t') => delimiter.push('\t'),
+            Some('0') => delimiter.push('\0'),
+            Some(other) => delimiter.push(other),
+            None => delimiter.push('\\'),
+        }
+    }
+    delimiter.into_bytes()
+}
+
+#[async_trait]
+impl FlowNodeBehavior for TcpInNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        match self.config.server {
+            TcpInMode::Server => {
+                let listener = match tcp::bind(&self.config.host, self.port).await {
+                    Ok(listener) => listener,
+                    Err(e) => {
+                        log::error!("[TCP_IN:{}] {:#}", self.name(), e);
+                        stop_token.cancelled().await;
+                        return;
+                    }
+                };
+                if let Ok(addr) = listener.local_addr() {
+                    log::info!("[TCP_IN:{}] Listening on {}", self.name(), addr);
+                }
+                let label = format!("TCP_IN:{}", self.name());
+                let node = self.clone();
+                tcp::accept_loop(&label, listener, self.options, stop_token, move |stream, peer, cancel| {
+                    node.clone().serve_connection(stream, peer, cancel)
+                })
+                .await;
+            }
+            TcpInMode::Client => self.clone().run_client(stop_token).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+    use tcp::tests::{connect_with_retry, free_port};
+    use tokio::io::AsyncWriteExt;
+
+    #[test]
+    fn test_it_should_parse_escaped_delimiters() {
+        assert_eq!(parse_delimiter(""), b"\n");
+        assert_eq!(parse_delimiter("\\r\\n"), b"\r\n");
+        assert_eq!(parse_delimiter("||"), b"||");
+        assert_eq!(parse_delimiter("a\\\\"), b"a\\");
+    }
+
+    #[test]
+    fn test_incomplete_utf8_tail_should_be_held_back() {
+        let data = "a\u{4e2d}".as_bytes();
+        assert_eq!(complete_utf8_len(data), data.len());
+        assert_eq!(complete_utf8_len(&data[..2]), 1);
+        assert_eq!(complete_utf8_len(&data[..3]), 1);
+        // The invalid bytes are not held back
+        assert_eq!(complete_utf8_len(&[b'a', 0xff]), 2);
+    }
+
+    #[tokio::test]
+    async fn test_it_should_not_split_utf8_characters_of_the_stream() {
+        let port = free_port();
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "tcp in", "server": "server", "host": "127.0.0.1", "port": port.to_string(),
+                "datamode": "stream", "datatype": "utf8", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let (msgs, _) = tokio::join!(engine.run_once(2, Duration::from_secs(2)), async {
+            let data = "a\u{4e2d}b".as_bytes();
+            let mut stream = connect_with_retry(port).await;
+            stream.write_all(&data[..2]).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            stream.write_all(&data[2..]).await.unwrap();
+            stream
+        });
+        let msgs = msgs.unwrap();
+        let payloads: Vec<&str> = msgs.iter().map(|x| x["payload"].as_str().unwrap()).collect();
+        assert_eq!(payloads, vec!["a", "\u{4e2d}b"]);
+    }
+
+    #[tokio::test]
+    async fn test_it_should_find_the_delimiter_across_reads() {
+        let port = free_port();
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "tcp in", "server": "server", "host": "127.0.0.1", "port": port.to_string(),
+                "datamode": "delimited", "datatype": "utf8", "newline": "\\r\\n", "trim": true, "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let (msgs, _) = tokio::join!(engine.run_once(2, Duration::from_secs(2)), async {
+            let mut stream = connect_with_retry(port).await;
+            for chunk in [&b"ab\r"[..], b"\ncd", b"ef\r", b"\n"] {
+                stream.write_all(chunk).await.unwrap();
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+            stream
+        });
+        let msgs = msgs.unwrap();
+        let payloads: Vec<&str> = msgs.iter().map(|x| x["payload"].as_str().unwrap()).collect();
+        assert_eq!(payloads, vec!["ab", "cdef"]);
+    }
+
+    #[tokio::test]
+    async fn test_it_should_split_the_delimited_data() {
+        let port = free_port();
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "tcp in", "server": "server", "host": "127.0.0.1", "port": port.to_string(),
+                "datamode": "delimited", "datatype": "utf8", "newline": "\\n", "trim": true, "topic": "tcp",
+                "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let (msgs, _) = tokio::join!(engine.run_once(3, Duration::from_secs(2)), async {
+            let mut stream = connect_with_retry(port).await;
+            stream.write_all(b"a\nb").await.unwrap();
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            stream.write_all(b"\nc").await.unwrap();
+            // The rest is sent after the connection is closed
+            stream.shutdown().await.unwrap();
+        });
+        let msgs = msgs.unwrap();
+        let payloads: Vec<&str> = msgs.iter().map(|x| x["payload"].as_str().unwrap()).collect();
+        assert_eq!(payloads, vec!["a", "b", "c"]);
+        assert_eq!(msgs[0]["topic"], "tcp".into());
+        assert_eq!(msgs[0]["ip"], "127.0.0.1".into());
+        assert!(msgs[0]["port"].as_u64().is_some_and(|x| x > 0));
+        assert_eq!(msgs[0].get_nav_stripped("_session.type"), Some(&Variant::from("tcp")));
+    }
+
+    #[tokio::test]
+    async fn test_it_should_send_lines_of_the_stream() {
+        let port = free_port();
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "tcp in", "server": "server", "host": "127.0.0.1", "port": port.to_string(),
+                "datamode": "stream", "datatype": "lines", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let (msgs, _) = tokio::join!(engine.run_once(2, Duration::from_secs(2)), async {
+            let mut stream = connect_with_retry(port).await;
+            stream.write_all(b"x\r\ny\nincomplete").await.unwrap();
+            stream
+        });
+        let msgs = msgs.unwrap();
+        let payloads: Vec<&str> = msgs.iter().map(|x| x["payload"].as_str().unwrap()).collect();
+        assert_eq!(payloads, vec!["x", "y"]);
+    }
+
+    #[tokio::test]
+    async fn test_it_should_refuse_connections_over_the_limit() {
+        let port = free_port();
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "tcp in", "server": "server", "host": "127.0.0.1", "port": port.to_string(),
+                "datamode": "stream", "datatype": "buffer", "maxConnections": "1", "keepalive": "30",
+                "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let (msgs, (_first, refused)) = tokio::join!(engine.run_once(1, Duration::from_secs(2)), async {
+            let mut first = connect_with_retry(port).await;
+            first.write_all(&[1, 2, 3]).await.unwrap();
+            let mut second = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+            let mut buf = [0u8; 8];
+            let read = tokio::time::timeout(Duration::from_secs(1), second.read(&mut buf)).await.unwrap();
+            (first, !matches!(read, Ok(n) if n > 0))
+        });
+        let msgs = msgs.unwrap();
+        assert_eq!(msgs[0]["payload"], Variant::Bytes(vec![1, 2, 3]));
+        assert!(refused);
+    }
+}
@@ -0,0 +1,360 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use serde::Deserialize;
+use tokio::io::AsyncReadExt;
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::net::TcpStream;
+
+use super::tcp::{self, ConnectionOptions, TcpSession};
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum TcpOutMode {
+    /// Listens on the port and sends to all the accepted connections
+    #[serde(rename = "server")]
+    Server,
+
+    /// Connects the remote host and keeps the connection open for the next messages
+    #[default]
+    #[serde(rename = "client")]
+    Client,
+
+    /// Replies to the connection of the `tcp in` node in `msg._session`
+    #[serde(rename = "reply")]
+    Reply,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct TcpOutNodeConfig {
+    #[serde(default)]
+    beserver: TcpOutMode,
+
+    /// The address to listen on in the `server` mode, or the remote host in the `client` mode, `msg.host` is used if
+    /// it is empty
+    #[serde(default)]
+    host: String,
+
+    /// `msg.port` is used if it is unset in the `client` mode
+    #[serde(default, deserialize_with = "json::deser::str_to_option_u16")]
+    port: Option<u16>,
+
+    /// Decodes the string payload as base64 before sending
+    #[serde(default, deserialize_with = "json::deser::deser_bool_or_str")]
+    base64: bool,
+
+    /// Closes the connection after sending each message in the `client` mode
+    #[serde(default, deserialize_with = "json::deser::deser_bool_or_str")]
+    end: bool,
+
+    /// The idle time in seconds before sending the keep-alive probes, `0` to disable
+    #[serde(default, deserialize_with = "json::deser::str_to_option_f64")]
+    keepalive: Option<f64>,
+
+    /// The maximum count of the accepted connections in the `server` mode, or the remote hosts connected in the
+    /// `client` mode, `0` for unlimited
+    #[serde(rename = "maxConnections", default, deserialize_with = "json::deser::str_to_option_usize")]
+    max_connections: Option<usize>,
+}
+
+/// Sends `msg.payload` to the TCP connections, the connections are keyed by the remote address.
+#[derive(Debug)]
+#[flow_node("tcp out")]
+struct TcpOutNode {
+    base: FlowNode,
+    config: TcpOutNodeConfig,
+    options: ConnectionOptions,
+    connections: DashMap<String, Arc<TcpSession>>,
+}
+
+impl TcpOutNode {
+    fn build(
+        _flow: &Flow,
+        base_node: FlowNode,
+        config: &RedFlowNodeConfig,
+    ) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let tcp_config = TcpOutNodeConfig::deserialize(&config.rest)?;
+        if tcp_config.beserver == TcpOutMode::Server && tcp_config.port.is_none() {
+            return Err(EdgelinkError::BadFlowsJson(
+                "The port of the 'tcp out' node must be set in the server mode".into(),
+            )
+            .into());
+        }
+        let options = ConnectionOptions::new(tcp_config.keepalive, tcp_config.max_connections)?;
+        let node = TcpOutNode { base: base_node, config: tcp_config, options, connections: DashMap::new() };
+        Ok(Box::new(node))
+    }
+
+    async fn receive(self: &Arc<Self>, msg: MsgHandle, stop_token: CancellationToken) -> crate::Result<()> {
+        let msg_guard = msg.read().await;
+        let bytes = match tcp::payload_to_bytes(msg_guard.get("payload"), self.config.base64)? {
+            Some(bytes) => bytes,
+            None => return Ok(()),
+        };
+        match self.config.beserver {
+            TcpOutMode::Client => self.send_to_remote(&msg_guard, &bytes, stop_token).await,
+            TcpOutMode::Server => {
+                let sessions: Vec<Arc<TcpSession>> = self.connections.iter().map(|x| x.value().clone()).collect();
+                self.broadcast(&sessions, &bytes).await;
+                Ok(())
+            }
+            TcpOutMode::Reply => {
+                match tcp::session_id_of(&msg_guard) {
+                    Some(id) => match tcp::get_session(id) {
+                        Some(session) => session.write(&bytes).await?,
+                        None => log::warn!("[TCP_OUT:{}] The connection to reply has been closed", self.name()),
+                    },
+                    // Node-RED sends the message without a session to all the connections of the `tcp in` nodes
+                    None => self.broadcast(&tcp::all_sessions(), &bytes).await,
+                }
+                Ok(())
+            }
+        }
+    }
+
+    async fn broadcast(&self, sessions: &[Arc<TcpSession>], bytes: &[u8]) {
+        for session in sessions {
+            if let Err(e) = session.write(bytes).await {
+                log::warn!("[TCP_OUT:{}] Failed to send to {}: {}", self.name(), session.peer, e);
+            }
+        }
+    }
+
+    fn remote_of(&self, msg: &Msg) -> crate::Result<(String, u16)> {
+        let host = match self.config.host.trim() {
+            "" => msg.get("host").and_then(|x| x.as_str()).map(|x| x.trim().to_string()).unwrap_or_default(),
+            host => host.to_string(),
+        };
+        let port = self.config.port.filter(|x| *x != 0).or_else(|| msg.get("port").and_then(|x| x.as_port()));
+        match port {
+            Some(port) if !host.is_empty() => Ok((host, port)),
+            _ => Err(EdgelinkError::InvalidOperation("The host and port to connect are not set".into()).into()),
+        }
+    }
+
+    /// Sends to the remote host by the connection kept open, reconnects once if the connection has been lost.
+    async fn send_to_remote(
+        self: &Arc<Self>,
+        msg: &Msg,
+        bytes: &[u8],
+        stop_token: CancellationToken,
+    ) -> crate::Result<()> {
+        let (host, port) = self.remote_of(msg)?;
+        if self.config.end {
+            let stream = tcp::connect(&host, port, &self.options).await?;
+            let peer = stream.peer_addr()?;
+            let (_, writer) = stream.into_split();
+            let session = TcpSession::new(peer, writer);
+            let res = session.write(bytes).await;
+            session.shutdown().await;
+            return res;
+        }
+
+        let key = format!("{}:{}", host, port);
+        let existed = self.connections.get(&key).map(|x| x.value().clone());
+        if let Some(session) = existed {
+            match session.write(bytes).await {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    log::debug!("[TCP_OUT:{}] Reconnecting '{}' after the failure: {}", self.name(), key, e);
+                    self.connections.remove_if(&key, |_, x| Arc::ptr_eq(x, &session));
+                }
+            }
+        }
+        if self.options.max_connections.is_some_and(|max| self.connections.len() >= max) {
+            return Err(EdgelinkError::InvalidOperation(format!(
+                "Cannot connect '{}', the limit of {} connection(s) reached",
+                key,
+                self.connections.len()
+            ))
+            .into());
+        }
+        let stream = tcp::connect(&host, port, &self.options).await?;
+        let peer = stream.peer_addr()?;
+        let (session, reader) = self.add_connection(key.clone(), stream, peer);
+        let node = self.clone();
+        let watched = session.clone();
+        tokio::spawn(async move { node.watch_connection(key, reader, watched, stop_token).await });
+        session.write(bytes).await
+    }
+
+    /// Keeps the writing half of the connection for the next messages.
+    fn add_connection(&self, key: String, stream: TcpStream, peer: SocketAddr) -> (Arc<TcpSession>, OwnedReadHalf) {
+        let (reader, writer) = stream.into_split();
+        let session = TcpSession::new(peer, writer);
+        self.connections.insert(key, session.clone());
+        (session, reader)
+    }
+
+    /// Discards the incoming data until the connection is closed, then removes the connection.
+    async fn watch_connection(
+        &self,
+        key: String,
+        mut reader: OwnedReadHalf,
+        session: Arc<TcpSession>,
+        cancel: CancellationToken,
+    ) {
+        let mut buf = vec![0u8; tcp::READ_BUFFER_SIZE];
+        loop {
+            tokio::select! {
+                _ = cancel.cancelled() => break,
+                res = reader.read(&mut buf) => match res {
+                    Ok(0) | Err(_) => break,
+                    Ok(_) => {}
+                },
+            }
+        }
+        self.connections.remove_if(&key, |_, x| Arc::ptr_eq(x, &session));
+        session.shutdown().await;
+        log::debug!("[TCP_OUT:{}] Disconnected from {}", self.name(), session.peer);
+    }
+
+    async fn process_msgs(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            let this = self.clone();
+            with_uow(self.as_ref(), cancel.child_token(), |_, msg| async move { this.receive(msg, cancel).await })
+                .await;
+        }
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for TcpOutNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        match self.config.beserver {
+            TcpOutMode::Server => {
+                let port = self.config.port.unwrap_or_default();
+                let listener = match tcp::bind(&self.config.host, port).await {
+                    Ok(listener) => listener,
+                    Err(e) => {
+                        log::error!("[TCP_OUT:{}] {:#}", self.name(), e);
+                        stop_token.cancelled().await;
+                        return;
+                    }
+                };
+                if let Ok(addr) = listener.local_addr() {
+                    log::info!("[TCP_OUT:{}] Listening on {}", self.name(), addr);
+                }
+                let label = format!("TCP_OUT:{}", self.name());
+                let node = self.clone();
+                let accept =
+                    tcp::accept_loop(&label, listener, self.options, stop_token.clone(), |stream, peer, cancel| {
+                        let node = node.clone();
+                        async move {
+                            let (session, reader) = node.add_connection(peer.to_string(), stream, peer);
+                            node.watch_connection(peer.to_string(), reader, session, cancel).await;
+                        }
+                    });
+                tokio::join!(accept, self.clone().process_msgs(stop_token.clone()));
+            }
+            TcpOutMode::Client | TcpOutMode::Reply => self.clone().process_msgs(stop_token.clone()).await,
+        }
+
+        let sessions: Vec<Arc<TcpSession>> = self.connections.iter().map(|x| x.value().clone()).collect();
+        self.connections.clear();
+        for session in sessions {
+            session.shutdown().await;
+        }
+        log::debug!("TcpOutNode process() task has been terminated.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+    use tcp::tests::{connect_with_retry, free_port};
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    async fn inject(engine: &crate::runtime::engine::Engine, msg: serde_json::Value) {
+        let msg = MsgHandle::new(Msg::deserialize(msg).unwrap());
+        engine.inject_msg(&ElementId::with_u64(1), msg, CancellationToken::new()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_it_should_reply_to_the_tcp_in_connection() {
+        let port = free_port();
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "tcp in", "server": "server", "host": "127.0.0.1", "port": port.to_string(),
+                "datamode": "delimited", "datatype": "utf8", "trim": true, "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "tcp out", "beserver": "reply", "wires": []}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        engine.start().await.unwrap();
+        let mut stream = connect_with_retry(port).await;
+        stream.write_all(b"ping\n").await.unwrap();
+        let mut buf = [0u8; 4];
+        tokio::time::timeout(Duration::from_secs(1), stream.read_exact(&mut buf)).await.unwrap().unwrap();
+        assert_eq!(&buf, b"ping");
+
+        // The connection is closed by stopping the `tcp in` node
+        engine.stop().await.unwrap();
+        let read = tokio::time::timeout(Duration::from_secs(1), stream.read(&mut buf)).await.unwrap();
+        assert!(!matches!(read, Ok(n) if n > 0));
+    }
+
+    #[tokio::test]
+    async fn test_it_should_reuse_the_client_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "tcp out", "beserver": "client", "host": "127.0.0.1",
+                "port": port.to_string(), "keepalive": "30", "wires": []}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        engine.start().await.unwrap();
+        inject(&engine, json!({"payload": "a"})).await;
+        inject(&engine, json!({"payload": [98]})).await;
+        inject(&engine, json!({"payload": 1})).await;
+
+        let (mut stream, _) = tokio::time::timeout(Duration::from_secs(1), listener.accept()).await.unwrap().unwrap();
+        let mut buf = [0u8; 6];
+        tokio::time::timeout(Duration::from_secs(1), stream.read_exact(&mut buf)).await.unwrap().unwrap();
+        assert_eq!(&buf, b"a[98]1");
+        assert!(tokio::time::timeout(Duration::from_millis(100), listener.accept()).await.is_err());
+
+        engine.stop().await.unwrap();
+        let mut rest = Vec::new();
+        tokio::time::timeout(Duration::from_secs(1), stream.read_to_end(&mut rest)).await.unwrap().unwrap();
+        assert!(rest.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_it_should_close_the_connection_after_each_msg() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "tcp out", "beserver": "client", "base64": true, "end": true,
+                "wires": []}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        engine.start().await.unwrap();
+        inject(&engine, json!({"host": "127.0.0.1", "port": port, "payload": "aGk="})).await;
+        inject(&engine, json!({"host": "127.0.0.1", "port": port, "payload": "dGhlcmU="})).await;
+
+        let mut received = Vec::new();
+        for _ in 0..2 {
+            let (mut stream, _) =
+                tokio::time::timeout(Duration::from_secs(1), listener.accept()).await.unwrap().unwrap();
+            let mut buf = Vec::new();
+            tokio::time::timeout(Duration::from_secs(1), stream.read_to_end(&mut buf)).await.unwrap().unwrap();
+            received.push(String::from_utf8(buf).unwrap());
+        }
+        engine.stop().await.unwrap();
+        assert_eq!(received, vec!["hi", "there"]);
+    }
+}
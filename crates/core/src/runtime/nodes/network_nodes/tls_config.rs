@@ -0,0 +1,95 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::runtime::context::ContextScope;
+use crate::runtime::engine::Engine;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+#[derive(Debug, Clone, Deserialize)]
+struct TlsConfigNodeConfig {
+    /// The path of the PEM file of the client certificate
+    #[serde(default)]
+    cert: String,
+
+    /// The path of the PEM file of the private key of the client certificate
+    #[serde(default)]
+    key: String,
+
+    /// The path of the PEM file of the CA certificate
+    #[serde(default)]
+    ca: String,
+
+    #[serde(default = "verify_server_cert_default", deserialize_with = "json::deser::deser_bool_or_str")]
+    verifyservercert: bool,
+}
+
+fn verify_server_cert_default() -> bool {
+    true
+}
+
+/// The TLS options shared by the network nodes referencing it, the certificate files are loaded on building.
+#[derive(Debug)]
+#[global_node("tls-config")]
+pub(super) struct TlsConfigNode {
+    base: GlobalNode,
+
+    pub verify_server_cert: bool,
+
+    /// The extra trusted CA certificate in PEM
+    pub ca: Option<Vec<u8>>,
+
+    /// The client certificate and its private key in PEM, they are either both set or both absent
+    pub cert: Option<Vec<u8>>,
+    pub key: Option<Vec<u8>>,
+}
+
+impl TlsConfigNode {
+    fn build(engine: &Engine, config: &RedGlobalNodeConfig) -> crate::Result<Box<dyn GlobalNodeBehavior>> {
+        let tls_config = TlsConfigNodeConfig::deserialize(&config.rest)?;
+        let ca = read_pem(&tls_config.ca)?;
+        let (cert, key) = (read_pem(&tls_config.cert)?, read_pem(&tls_config.key)?);
+        if cert.is_some() != key.is_some() {
+            return Err(EdgelinkError::BadFlowsJson(format!(
+                "The certificate and the private key of the TLS config '{}' must be set together",
+                config.id
+            ))
+            .into());
+        }
+
+        let context =
+            engine.get_context_manager().new_context(&engine.context(), config.id.to_string(), ContextScope::Node);
+        let node = Self {
+            base: GlobalNode {
+                id: config.id,
+                name: config.name.clone(),
+                type_str: "tls-config",
+                ordering: config.ordering,
+                disabled: config.disabled,
+                context,
+            },
+            verify_server_cert: tls_config.verifyservercert,
+            ca,
+            cert,
+            key,
+        };
+        Ok(Box::new(node))
+    }
+}
+
+impl GlobalNodeBehavior for TlsConfigNode {
+    fn get_node(&self) -> &GlobalNode {
+        &self.base
+    }
+}
+
+fn read_pem(path: &str) -> crate::Result<Option<Vec<u8>>> {
+    let path = path.trim();
+    if path.is_empty() {
+        return Ok(None);
+    }
+    let pem = std::fs::read(path).with_context(|| format!("Failed to read the PEM file: '{}'", path))?;
+    Ok(Some(pem))
+}
@@ -0,0 +1,283 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use tokio::net::UdpSocket;
+
+use base64::prelude::*;
+use serde::Deserialize;
+
+use super::udp_out::UdpIpV;
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+/// The largest payload of a UDP datagram
+const DEFAULT_MAX_LEN: usize = 65535;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum UdpDataType {
+    #[default]
+    #[serde(rename = "buffer")]
+    Buffer,
+
+    #[serde(rename = "utf8")]
+    Utf8,
+
+    #[serde(rename = "base64")]
+    Base64,
+}
+
+#[derive(Deserialize, Debug)]
+struct UdpInNodeConfig {
+    /// Local address to listen on, or the interface to join the multicast group
+    #[serde(default, deserialize_with = "crate::runtime::model::json::deser::str_to_ipaddr")]
+    iface: Option<IpAddr>,
+
+    /// Local port
+    #[serde(deserialize_with = "crate::runtime::model::json::deser::str_to_option_u16")]
+    port: Option<u16>,
+
+    #[serde(default)]
+    ipv: UdpIpV,
+
+    /// Joins the multicast `group`
+    #[serde(default, deserialize_with = "crate::runtime::model::json::deser::deser_bool_or_str")]
+    multicast: bool,
+
+    #[serde(default, deserialize_with = "crate::runtime::model::json::deser::str_to_ipaddr")]
+    group: Option<IpAddr>,
+
+    #[serde(default)]
+    datatype: UdpDataType,
+
+    /// The maximum length of the datagrams received, the rest of a longer datagram is discarded
+    #[serde(default, deserialize_with = "crate::runtime::model::json::deser::str_to_option_usize")]
+    maxlen: Option<usize>,
+}
+
+/// Receives the datagrams, the sender is in `msg.ip` and `msg.port`.
+#[derive(Debug)]
+#[flow_node("udp in")]
+struct UdpInNode {
+    base: FlowNode,
+    config: UdpInNodeConfig,
+    port: u16,
+    max_len: usize,
+}
+
+impl UdpInNode {
+    fn build(_flow: &Flow, state: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let udp_config = UdpInNodeConfig::deserialize(&config.rest)?;
+        let port =
+            udp_config.port.ok_or(EdgelinkError::BadFlowsJson("The port of the `udp in` node must be set".into()))?;
+        if udp_config.multicast {
+            match udp_config.group {
+                Some(group) if group.is_multicast() => {}
+                _ => {
+                    return Err(EdgelinkError::BadFlowsJson(format!(
+                        "Bad multicast group of the `udp in` node: {:?}",
+                        udp_config.group
+                    ))
+                    .into())
+                }
+            }
+        }
+        let max_len = match udp_config.maxlen {
+            Some(0) | None => DEFAULT_MAX_LEN,
+            Some(len) => len,
+        };
+
+        let node = UdpInNode { base: state, config: udp_config, port, max_len };
+        Ok(Box::new(node))
+    }
+
+    async fn bind(&self) -> crate::Result<UdpSocket> {
+        let unspecified = match self.config.ipv {
+            UdpIpV::V4 => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            UdpIpV::V6 => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        };
+        // The multicast datagrams are addressed to the group, so the socket cannot be bound to the interface
+        let local_ip = if self.config.multicast { unspecified } else { self.config.iface.unwrap_or(unspecified) };
+        let socket = UdpSocket::bind(SocketAddr::new(local_ip, self.port)).await?;
+        match self.config.group.filter(|_| self.config.multicast) {
+            Some(IpAddr::V4(group)) => {
+                let iface = match self.config.iface {
+                    Some(IpAddr::V4(iface)) => iface,
+                    _ => Ipv4Addr::UNSPECIFIED,
+                };
+                socket.join_multicast_v4(group, iface)?;
+            }
+            Some(IpAddr::V6(group)) => socket.join_multicast_v6(&group, 0)?,
+            None => {}
+        }
+        Ok(socket)
+    }
+
+    fn leave_group(&self, socket: &UdpSocket) {
+        let res = match self.config.group.filter(|_| self.config.multicast) {
+            Some(IpAddr::V4(group)) => {
+                let iface = match self.config.iface {
+                    Some(IpAddr::V4(iface)) => iface,
+                    _ => Ipv4Addr::UNSPECIFIED,
+                };
+                socket.leave_multicast_v4(group, iface)
+            }
+            Some(IpAddr::V6(group)) => socket.leave_multicast_v6(&group, 0),
+            None => Ok(()),
+        };
+        if let Err(e) = res {
+            log::warn!("[UDP_IN:{}] Failed to leave the multicast group: {:?}", self.name(), e);
+        }
+    }
+
+    fn make_msg(&self, data: &[u8], from: SocketAddr) -> crate::Result<Msg> {
+        let payload = match self.config.datatype {
+            UdpDataType::Buffer => Variant::Bytes(data.to_vec()),
+            UdpDataType::Utf8 => Variant::String(String::from_utf8_lossy(data).into_owned()),
+            UdpDataType::Base64 => Variant::String(BASE64_STANDARD.encode(data)),
+        };
+        let mut msg = MsgBuilder::new().payload(payload).build()?;
+        msg.set("fromip".to_string(), Variant::String(from.to_string()));
+        msg.set("ip".to_string(), Variant::from(from.ip()));
+        msg.set("port".to_string(), Variant::from(from.port() as u32));
+        Ok(msg)
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for UdpInNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        let socket = match self.bind().await {
+            Ok(socket) => socket,
+            Err(e) => {
+                log::error!("[UDP_IN:{}] Can not bind the port {}: {:?}", self.name(), self.port, e);
+                stop_token.cancelled().await;
+                return;
+            }
+        };
+
+        let mut buf = vec![0u8; self.max_len];
+        loop {
+            let (len, from) = tokio::select! {
+                _ = stop_token.cancelled() => break,
+                res = socket.recv_from(&mut buf) => match res {
+                    Ok(received) => received,
+                    Err(e) => {
+                        log::warn!("[UDP_IN:{}] Failed to receive: {:?}", self.name(), e);
+                        continue;
+                    }
+                },
+            };
+            let msg = match self.make_msg(&buf[..len], from) {
+                Ok(msg) => MsgHandle::new(msg),
+                Err(e) => {
+                    log::warn!("[UDP_IN:{}] {}", self.name(), e);
+                    continue;
+                }
+            };
+            self.notify_uow_completed(msg.clone(), stop_token.clone()).await;
+            if let Err(e) = self.fan_out_one(Envelope { port: 0, msg }, stop_token.child_token()).await {
+                log::warn!("[UDP_IN:{}] Failed to send the message: {}", self.name(), e);
+            }
+        }
+
+        self.leave_group(&socket);
+        log::debug!("UdpInNode process() task has been terminated.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+
+    fn free_port() -> u16 {
+        std::net::UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+    }
+
+    /// Sends the datagram repeatedly until the engine stopped, the node may not be listening yet.
+    async fn send_until_stopped(sender: &UdpSocket, data: &[u8], to: SocketAddr, stopped: &CancellationToken) {
+        while !stopped.is_cancelled() {
+            let _ = sender.send_to(data, to).await;
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_it_should_receive_and_send_unicast_datagrams() {
+        let in_port = free_port();
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let out_port = receiver.local_addr().unwrap().port();
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "udp in", "iface": "127.0.0.1", "port": in_port.to_string(),
+                "ipv": "udp4", "multicast": "false", "group": "", "datatype": "utf8", "maxlen": "4",
+                "wires": [["2", "3"]]},
+            {"id": "2", "z": "100", "type": "udp out", "addr": "127.0.0.1", "port": out_port.to_string(),
+                "iface": "", "outport": "", "ipv": "udp4", "base64": false, "multicast": "false", "wires": []},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let stopped = CancellationToken::new();
+        let (msgs, _, forwarded) = tokio::join!(
+            async {
+                let msgs = engine.run_once(1, Duration::from_secs(2)).await;
+                stopped.cancel();
+                msgs
+            },
+            send_until_stopped(&sender, b"hello", SocketAddr::from(([127, 0, 0, 1], in_port)), &stopped),
+            async {
+                let mut buf = [0u8; 16];
+                let (len, _) =
+                    tokio::time::timeout(Duration::from_secs(2), receiver.recv_from(&mut buf)).await.unwrap().unwrap();
+                buf[..len].to_vec()
+            }
+        );
+        let msgs = msgs.unwrap();
+        // The datagram is truncated to the `maxlen`
+        assert_eq!(msgs[0]["payload"], "hell".into());
+        assert_eq!(msgs[0]["ip"], "127.0.0.1".into());
+        assert_eq!(msgs[0]["port"], Variant::from(sender.local_addr().unwrap().port() as u32));
+        assert_eq!(forwarded, b"hell");
+    }
+
+    #[tokio::test]
+    async fn test_it_should_receive_multicast_datagrams() {
+        let port = free_port();
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "udp in", "iface": "127.0.0.1", "port": port.to_string(),
+                "ipv": "udp4", "multicast": "true", "group": "239.255.42.99", "datatype": "buffer", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        sender.set_multicast_loop_v4(true).unwrap();
+        let stopped = CancellationToken::new();
+        let (msgs, _) = tokio::join!(
+            async {
+                let msgs = engine.run_once(1, Duration::from_secs(2)).await;
+                stopped.cancel();
+                msgs
+            },
+            send_until_stopped(&sender, &[1, 2, 3], SocketAddr::from(([239, 255, 42, 99], port)), &stopped),
+        );
+        assert_eq!(msgs.unwrap()[0]["payload"], Variant::Bytes(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn test_bad_multicast_group_should_fail_to_build() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "udp in", "port": "0", "multicast": "true", "group": "127.0.0.1",
+                "wires": []}
+        ]);
+        assert!(crate::runtime::engine::build_test_engine(flows_json).is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use std::net::{IpAddr, SocketAddr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::sync::Arc;
 use tokio::net::UdpSocket;
 
@@ -6,11 +6,13 @@ use base64::prelude::*;
 use serde::Deserialize;
 
 use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
 use crate::runtime::nodes::*;
 use edgelink_macro::*;
 
-#[derive(Debug)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 enum UdpMulticast {
+    #[default]
     No,
     Board,
     Multi,
@@ -34,8 +36,9 @@ impl<'de> Deserialize<'de> for UdpMulticast {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
-enum UdpIpV {
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub(super) enum UdpIpV {
+    #[default]
     #[serde(rename = "udp4")]
     V4,
 
@@ -77,36 +80,78 @@ struct UdpOutNodeConfig {
     #[serde(deserialize_with = "crate::runtime::model::json::deser::str_to_option_u16")]
     outport: Option<u16>,
 
+    #[serde(default)]
     ipv: UdpIpV,
 
-    #[serde(default)]
+    /// Uses an IPv6 socket, the same as the `ipv` of `udp6`
+    #[serde(default, deserialize_with = "crate::runtime::model::json::deser::deser_bool_or_str")]
+    ipv6: bool,
+
+    /// Allows sending to the broadcast addresses, the same as the `multicast` of `board`
+    #[serde(default, deserialize_with = "crate::runtime::model::json::deser::deser_bool_or_str")]
+    broadcast: bool,
+
+    /// Decodes the string payload as base64 before sending
+    #[serde(default, deserialize_with = "crate::runtime::model::json::deser::deser_bool_or_str")]
     base64: bool,
-    //multicast: UdpMulticast,
+
+    #[serde(default)]
+    multicast: UdpMulticast,
+}
+
+impl UdpOutNodeConfig {
+    fn is_ipv6(&self) -> bool {
+        self.ipv6 || self.ipv == UdpIpV::V6
+    }
+
+    fn is_broadcast(&self) -> bool {
+        self.broadcast || self.multicast == UdpMulticast::Board
+    }
+
+    /// The local address to send from, any address and port if unset.
+    fn local_addr(&self) -> SocketAddr {
+        let unspecified =
+            if self.is_ipv6() { IpAddr::V6(Ipv6Addr::UNSPECIFIED) } else { IpAddr::V4(Ipv4Addr::UNSPECIFIED) };
+        SocketAddr::new(self.iface.unwrap_or(unspecified), self.outport.unwrap_or(0))
+    }
 }
 
 impl UdpOutNode {
     async fn uow(&self, msg: MsgHandle, socket: &UdpSocket) -> crate::Result<()> {
         let msg_guard = msg.read().await;
         if let Some(payload) = msg_guard.get("payload") {
-            let remote_addr = std::net::SocketAddr::new(
-                self.config.addr.unwrap(), // TODO FIXME
-                self.config.port.unwrap(),
-            );
-
-            if let Some(bytes) = payload.as_bytes() {
-                if self.config.base64 {
-                    let b64_str = BASE64_STANDARD.encode(bytes);
-                    let bytes = b64_str.as_bytes();
-                    socket.send_to(bytes, remote_addr).await?;
-                } else {
-                    socket.send_to(bytes, remote_addr).await?;
+            // The `msg.ip` and `msg.port` are used only if the remote address is not set in the configuration, like the
+            // `tcp out` node, so the messages from the `udp in` node carrying the sender address can be forwarded
+            let ip = self.config.addr.or_else(|| msg_guard.get("ip").and_then(|x| x.as_ip_addr()));
+            let port = self
+                .config
+                .port
+                .filter(|x| is_valid_port(*x))
+                .or_else(|| msg_guard.get("port").and_then(|x| x.as_port()));
+            let remote_addr = match (ip, port) {
+                (Some(ip), Some(port)) => SocketAddr::new(ip, port),
+                _ => {
+                    return Err(EdgelinkError::InvalidOperation(
+                        "The remote IP address or port of the `udp out` node is not set".into(),
+                    )
+                    .into())
                 }
-            }
-            if let Some(bytes) = payload.to_bytes() {
-                socket.send_to(&bytes, remote_addr).await?;
-            } else {
-                log::warn!("Failed to convert payload into bytes");
-            }
+            };
+
+            let bytes = match payload {
+                Variant::String(s) if self.config.base64 => BASE64_STANDARD
+                    .decode(s.trim())
+                    .map_err(|e| EdgelinkError::InvalidOperation(format!("Bad base64 payload: {}", e)))?,
+                _ => match payload.to_bytes() {
+                    Some(bytes) => bytes,
+                    None => {
+                        log::warn!("Failed to convert payload into bytes");
+                        return Ok(());
+                    }
+                },
+            };
+            // UDP is connectionless, so every datagram is sent on its own without any state kept
+            socket.send_to(&bytes, remote_addr).await?;
         }
 
         Ok(())
@@ -120,26 +165,22 @@ impl FlowNodeBehavior for UdpOutNode {
     }
 
     async fn run(self: Arc<Self>, stop_token: CancellationToken) {
-        let local_addr: SocketAddr = match self.config.outport {
-            Some(port) => SocketAddr::new(self.config.iface.unwrap(), port),
-            _ => match self.config.ipv {
-                UdpIpV::V4 => "0.0.0.0:0".parse().unwrap(),
-                UdpIpV::V6 => "[::]:0".parse().unwrap(),
-            },
-        };
-
+        let local_addr = self.config.local_addr();
         match tokio::net::UdpSocket::bind(local_addr).await {
             Ok(socket) => {
+                if let Err(e) = socket.set_broadcast(self.config.is_broadcast()) {
+                    log::warn!("Can not set the broadcast of the socket: {:?}", e);
+                }
                 let socket = Arc::new(socket);
                 while !stop_token.is_cancelled() {
                     let cloned_socket = socket.clone();
 
-                    let node = self.clone();
-                    with_uow(node.as_ref(), stop_token.clone(), |node, msg| async move {
+                    with_uow_concurrent(&self, stop_token.clone(), |node, msg| async move {
                         node.uow(msg, &cloned_socket).await
                     })
                     .await;
                 }
+                wait_uows_completed(self.as_ref()).await;
             }
 
             Err(e) => {
@@ -148,3 +189,28 @@ impl FlowNodeBehavior for UdpOutNode {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_ipv6_and_broadcast_flags_should_override_the_node_red_options() {
+        let config = UdpOutNodeConfig::deserialize(json!({
+            "addr": "", "port": "", "iface": "", "outport": "", "ipv": "udp4", "ipv6": "true", "broadcast": true
+        }))
+        .unwrap();
+        assert!(config.is_ipv6());
+        assert!(config.is_broadcast());
+        assert_eq!(config.local_addr(), "[::]:0".parse().unwrap());
+
+        let config = UdpOutNodeConfig::deserialize(json!({
+            "addr": "", "port": "", "iface": "127.0.0.1", "outport": "4000", "multicast": "board"
+        }))
+        .unwrap();
+        assert!(!config.is_ipv6());
+        assert!(config.is_broadcast());
+        assert_eq!(config.local_addr(), "127.0.0.1:4000".parse().unwrap());
+    }
+}
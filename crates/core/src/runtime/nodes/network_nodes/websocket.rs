@@ -0,0 +1,631 @@
+//! The `websocket-listener` and `websocket-client` config nodes shared by the `websocket in` and `websocket out` nodes.
+
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::Duration;
+
+use dashmap::DashMap;
+use futures_util::{Sink, SinkExt, StreamExt};
+use serde::Deserialize;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+use tokio_tungstenite::WebSocketStream;
+
+use crate::runtime::context::ContextScope;
+use crate::runtime::engine::Engine;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+/// The first delay before reconnecting the `websocket-client`, doubled on every failure
+const RECONNECT_DELAY_MIN: Duration = Duration::from_secs(1);
+
+const RECONNECT_DELAY_MAX: Duration = Duration::from_secs(60);
+
+/// The ID of a WebSocket connection, exposed as the hex string in `msg._session.id`
+pub(super) type ClientId = u64;
+
+type WsSink = Pin<Box<dyn Sink<Message, Error = WsError> + Send>>;
+
+#[derive(Debug, Clone, Deserialize)]
+struct WebSocketListenerNodeConfig {
+    path: String,
+
+    /// Sends and receives the whole message as JSON instead of the payload
+    #[serde(default, deserialize_with = "json::deser::deser_bool_or_str")]
+    wholemsg: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct WebSocketClientNodeConfig {
+    /// The URL to connect, `ws://` or `wss://`
+    path: String,
+
+    #[serde(default, deserialize_with = "json::deser::deser_bool_or_str")]
+    wholemsg: bool,
+}
+
+#[derive(Debug)]
+enum EndpointMode {
+    Server { listen: String, path: String },
+    Client { url: String },
+}
+
+#[derive(Debug, Default)]
+struct EndpointState {
+    users: HashSet<ElementId>,
+
+    /// Cancels the connections, set while the endpoint has users
+    cancel: Option<CancellationToken>,
+}
+
+/// The connections of a `websocket-listener` or a `websocket-client` node.
+///
+/// The listener is served or the client connects once a node uses it, and it is closed after the last node stopped.
+pub(super) struct WebSocketEndpoint {
+    id: ElementId,
+    label: String,
+    mode: EndpointMode,
+    wholemsg: bool,
+    sessions: DashMap<ClientId, Arc<tokio::sync::Mutex<WsSink>>>,
+    receivers: Mutex<Vec<(ElementId, mpsc::Sender<Msg>)>>,
+    state: Mutex<EndpointState>,
+}
+
+impl std::fmt::Debug for WebSocketEndpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebSocketEndpoint")
+            .field("id", &self.id)
+            .field("mode", &self.mode)
+            .field("wholemsg", &self.wholemsg)
+            .field("sessions", &self.sessions.len())
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+#[global_node("websocket-listener")]
+struct WebSocketListenerNode {
+    base: GlobalNode,
+    endpoint: Arc<WebSocketEndpoint>,
+}
+
+impl WebSocketListenerNode {
+    fn build(engine: &Engine, config: &RedGlobalNodeConfig) -> crate::Result<Box<dyn GlobalNodeBehavior>> {
+        let listener_config = WebSocketListenerNodeConfig::deserialize(&config.rest)?;
+        let path = match listener_config.path.trim() {
+            "" => {
+                return Err(EdgelinkError::BadFlowsJson(
+                    "The path of the 'websocket-listener' must not be empty".into(),
+                )
+                .into())
+            }
+            path if path.starts_with('/') => path.to_string(),
+            path => format!("/{}", path),
+        };
+        let mode = EndpointMode::Server { listen: engine.websocket_args().listen.clone(), path };
+        let endpoint = WebSocketEndpoint::new(config, mode, listener_config.wholemsg);
+        let node = Self { base: new_global_node(engine, config, "websocket-listener"), endpoint };
+        Ok(Box::new(node))
+    }
+}
+
+impl GlobalNodeBehavior for WebSocketListenerNode {
+    fn get_node(&self) -> &GlobalNode {
+        &self.base
+    }
+}
+
+#[derive(Debug)]
+#[global_node("websocket-client")]
+struct WebSocketClientNode {
+    base: GlobalNode,
+    endpoint: Arc<WebSocketEndpoint>,
+}
+
+impl WebSocketClientNode {
+    fn build(engine: &Engine, config: &RedGlobalNodeConfig) -> crate::Result<Box<dyn GlobalNodeBehavior>> {
+        let client_config = WebSocketClientNodeConfig::deserialize(&config.rest)?;
+        let url = client_config.path.trim();
+        if !url.starts_with("ws://") && !url.starts_with("wss://") {
+            return Err(EdgelinkError::BadFlowsJson(format!(
+                "The URL of the 'websocket-client' must start with 'ws://' or 'wss://', got: '{}'",
+                url
+            ))
+            .into());
+        }
+        let mode = EndpointMode::Client { url: url.to_string() };
+        let endpoint = WebSocketEndpoint::new(config, mode, client_config.wholemsg);
+        let node = Self { base: new_global_node(engine, config, "websocket-client"), endpoint };
+        Ok(Box::new(node))
+    }
+}
+
+impl GlobalNodeBehavior for WebSocketClientNode {
+    fn get_node(&self) -> &GlobalNode {
+        &self.base
+    }
+}
+
+fn new_global_node(engine: &Engine, config: &RedGlobalNodeConfig, type_str: &'static str) -> GlobalNode {
+    let context =
+        engine.get_context_manager().new_context(&engine.context(), config.id.to_string(), ContextScope::Node);
+    GlobalNode {
+        id: config.id,
+        name: config.name.clone(),
+        type_str,
+        ordering: config.ordering,
+        disabled: config.disabled,
+        context,
+    }
+}
+
+/// Gets the endpoint of the `websocket in` and `websocket out` nodes, either the `server` or the `client` must be set.
+pub(super) fn get_endpoint(
+    engine: &Engine,
+    server: Option<ElementId>,
+    client: Option<ElementId>,
+) -> crate::Result<Arc<WebSocketEndpoint>> {
+    let id = match (server, client) {
+        (Some(id), None) | (None, Some(id)) => id,
+        _ => {
+            return Err(EdgelinkError::BadFlowsJson(
+                "The WebSocket node must have either a 'websocket-listener' or a 'websocket-client'".into(),
+            )
+            .into())
+        }
+    };
+    let node = engine.get_global_node(&id)?;
+    if let Some(listener) = node.as_any().downcast_ref::<WebSocketListenerNode>().filter(|_| server.is_some()) {
+        return Ok(listener.endpoint.clone());
+    }
+    if let Some(client) = node.as_any().downcast_ref::<WebSocketClientNode>().filter(|_| client.is_some()) {
+        return Ok(client.endpoint.clone());
+    }
+    Err(EdgelinkError::BadFlowsJson(format!("Not a WebSocket config node: {}", node)).into())
+}
+
+impl WebSocketEndpoint {
+    fn new(config: &RedGlobalNodeConfig, mode: EndpointMode, wholemsg: bool) -> Arc<Self> {
+        Arc::new(Self {
+            id: config.id,
+            label: if config.name.is_empty() { config.id.to_string() } else { config.name.clone() },
+            mode,
+            wholemsg,
+            sessions: DashMap::new(),
+            receivers: Mutex::new(Vec::new()),
+            state: Mutex::new(EndpointState::default()),
+        })
+    }
+
+    /// Serves the listener or connects the client if the node is the first user.
+    pub async fn open(self: &Arc<Self>, node_id: ElementId) -> crate::Result<()> {
+        let cancel = {
+            let mut state = self.state.lock().expect("Lock the endpoint state");
+            state.users.insert(node_id);
+            if state.cancel.is_some() {
+                return Ok(());
+            }
+            let cancel = CancellationToken::new();
+            state.cancel = Some(cancel.clone());
+            cancel
+        };
+        match &self.mode {
+            EndpointMode::Server { listen, .. } => {
+                if let Err(e) = WebSocketServer::add_endpoint(listen, self.clone()).await {
+                    let mut state = self.state.lock().expect("Lock the endpoint state");
+                    state.users.remove(&node_id);
+                    state.cancel = None;
+                    return Err(e);
+                }
+            }
+            EndpointMode::Client { url } => {
+                tokio::spawn(self.clone().run_client(url.clone(), cancel));
+            }
+        }
+        Ok(())
+    }
+
+    /// Closes the listener or the client after the last user left.
+    pub async fn close(&self, node_id: ElementId) {
+        self.receivers.lock().expect("Lock the receivers").retain(|x| x.0 != node_id);
+        let cancel = {
+            let mut state = self.state.lock().expect("Lock the endpoint state");
+            state.users.remove(&node_id);
+            if !state.users.is_empty() {
+                return;
+            }
+            match state.cancel.take() {
+                Some(cancel) => cancel,
+                None => return,
+            }
+        };
+        if let EndpointMode::Server { listen, .. } = &self.mode {
+            WebSocketServer::remove_endpoint(listen, self).await;
+        }
+        cancel.cancel();
+    }
+
+    /// Adds the receiver of the messages made from the incoming frames.
+    pub fn add_receiver(&self, node_id: ElementId, sender: mpsc::Sender<Msg>) {
+        self.receivers.lock().expect("Lock the receivers").push((node_id, sender));
+    }
+
+    /// Sends the message to the session of `msg._session` if it is of the listener, otherwise to all connections.
+    pub async fn send(&self, msg: &Msg) -> crate::Result<()> {
+        let frame = self.make_frame(msg)?;
+        let session = match self.mode {
+            EndpointMode::Server { .. } => msg
+                .get("_session")
+                .and_then(|x| x.as_object())
+                .filter(|x| x.get("type").and_then(|t| t.as_str()) == Some("websocket"))
+                .and_then(|x| x.get("id"))
+                .and_then(|x| x.as_str())
+                .and_then(|x| ClientId::from_str_radix(x, 16).ok()),
+            EndpointMode::Client { .. } => None,
+        };
+        let sinks: Vec<_> = match session {
+            Some(session) => self.sessions.get(&session).map(|x| x.value().clone()).into_iter().collect(),
+            None => self.sessions.iter().map(|x| x.value().clone()).collect(),
+        };
+        if sinks.is_empty() && matches!(self.mode, EndpointMode::Client { .. }) {
+            return Err(EdgelinkError::InvalidOperation(format!(
+                "The WebSocket client '{}' is not connected",
+                self.label
+            ))
+            .into());
+        }
+        for sink in sinks.into_iter() {
+            if let Err(e) = sink.lock().await.send(frame.clone()).await {
+                log::warn!("[WEBSOCKET:{}] Failed to send the frame: {}", self.label, e);
+            }
+        }
+        Ok(())
+    }
+
+    fn make_frame(&self, msg: &Msg) -> crate::Result<Message> {
+        if self.wholemsg {
+            let mut jv = msg.to_json_value();
+            if let Some(obj) = jv.as_object_mut() {
+                obj.remove("_session");
+            }
+            return Ok(Message::Text(serde_json::to_string(&jv)?));
+        }
+        let frame = match msg.get("payload") {
+            Some(Variant::Bytes(bytes)) => Message::Binary(bytes.clone()),
+            Some(Variant::String(text)) => Message::Text(text.clone()),
+            None | Some(Variant::Null) => Message::Text(String::new()),
+            Some(value @ (Variant::Number(_) | Variant::Bool(_))) => Message::Text(value.to_string()?),
+            Some(other) => Message::Text(serde_json::to_string(other)?),
+        };
+        Ok(frame)
+    }
+
+    fn make_msg(&self, session: ClientId, payload: Variant) -> crate::Result<Msg> {
+        let mut msg = match payload {
+            Variant::String(text) if self.wholemsg => match serde_json::from_str::<serde_json::Value>(&text) {
+                Ok(jv) if jv.is_object() => {
+                    let mut msg = Msg::deserialize(jv)?;
+                    if msg.id().is_none() {
+                        msg.set_id(Msg::generate_id());
+                    }
+                    msg
+                }
+                _ => MsgBuilder::new().payload(text).build()?,
+            },
+            payload => MsgBuilder::new().payload(payload).build()?,
+        };
+        let session = VariantObjectMap::from([
+            ("type".to_string(), Variant::from("websocket")),
+            ("id".to_string(), Variant::String(format!("{:x}", session))),
+        ]);
+        msg.set("_session".to_string(), Variant::Object(session));
+        Ok(msg)
+    }
+
+    async fn dispatch(&self, session: ClientId, payload: Variant) {
+        let msg = match self.make_msg(session, payload) {
+            Ok(msg) => msg,
+            Err(e) => {
+                log::warn!("[WEBSOCKET:{}] Bad message: {}", self.label, e);
+                return;
+            }
+        };
+        let senders: Vec<_> = self.receivers.lock().expect("Lock the receivers").iter().map(|x| x.1.clone()).collect();
+        for sender in senders.into_iter() {
+            // The receiver has been stopped
+            let _ = sender.send(msg.clone()).await;
+        }
+    }
+
+    /// Reads the frames of the connection until it is closed or the endpoint is closed.
+    async fn serve_session<S>(self: Arc<Self>, ws: WebSocketStream<S>, cancel: CancellationToken)
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+        let session = NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed);
+        let (sink, mut stream) = ws.split();
+        let sink: WsSink = Box::pin(sink);
+        self.sessions.insert(session, Arc::new(tokio::sync::Mutex::new(sink)));
+
+        loop {
+            let frame = tokio::select! {
+                _ = cancel.cancelled() => break,
+                frame = stream.next() => frame,
+            };
+            match frame {
+                Some(Ok(Message::Text(text))) => self.dispatch(session, Variant::String(text)).await,
+                Some(Ok(Message::Binary(bytes))) => self.dispatch(session, Variant::Bytes(bytes)).await,
+                Some(Ok(Message::Close(_))) | None => break,
+                // The pings are answered by the stream
+                Some(Ok(_)) => {}
+                Some(Err(e)) => {
+                    log::warn!("[WEBSOCKET:{}] The connection failed: {}", self.label, e);
+                    break;
+                }
+            }
+        }
+
+        if let Some((_, sink)) = self.sessions.remove(&session) {
+            let _ = sink.lock().await.close().await;
+        }
+    }
+
+    /// Connects the URL and reconnects with the exponential backoff until cancelled.
+    async fn run_client(self: Arc<Self>, url: String, cancel: CancellationToken) {
+        let mut delay = RECONNECT_DELAY_MIN;
+        loop {
+            let res = tokio::select! {
+                _ = cancel.cancelled() => break,
+                res = tokio_tungstenite::connect_async(url.as_str()) => res,
+            };
+            match res {
+                Ok((ws, _)) => {
+                    log::info!("[WEBSOCKET:{}] Connected to {}", self.label, url);
+                    delay = RECONNECT_DELAY_MIN;
+                    self.clone().serve_session(ws, cancel.clone()).await;
+                    log::info!("[WEBSOCKET:{}] Disconnected from {}", self.label, url);
+                }
+                Err(e) => log::warn!("[WEBSOCKET:{}] Failed to connect {}: {}", self.label, url, e),
+            }
+            tokio::select! {
+                _ = cancel.cancelled() => break,
+                _ = tokio::time::sleep(delay) => {}
+            }
+            delay = (delay * 2).min(RECONNECT_DELAY_MAX);
+        }
+    }
+}
+
+/// The WebSocket server shared by the `websocket-listener` nodes on the same address, routed by the paths
+#[derive(Debug)]
+struct WebSocketServer {
+    local_addr: SocketAddr,
+    endpoints: RwLock<HashMap<String, Arc<WebSocketEndpoint>>>,
+    cancel: CancellationToken,
+}
+
+fn servers() -> &'static tokio::sync::Mutex<HashMap<String, Arc<WebSocketServer>>> {
+    static SERVERS: OnceLock<tokio::sync::Mutex<HashMap<String, Arc<WebSocketServer>>>> = OnceLock::new();
+    SERVERS.get_or_init(|| tokio::sync::Mutex::new(HashMap::new()))
+}
+
+/// The key of the server in `servers()`, the port `0` binds an ephemeral port for every listener.
+fn server_key(listen: &str, endpoint_id: ElementId) -> String {
+    if listen.ends_with(":0") {
+        format!("{}#{}", listen, endpoint_id)
+    } else {
+        listen.to_string()
+    }
+}
+
+impl WebSocketServer {
+    async fn add_endpoint(listen: &str, endpoint: Arc<WebSocketEndpoint>) -> crate::Result<()> {
+        let path = match &endpoint.mode {
+            EndpointMode::Server { path, .. } => path.clone(),
+            EndpointMode::Client { .. } => {
+                return Err(EdgelinkError::InvalidOperation("Not a WebSocket listener".into()).into())
+            }
+        };
+        let mut servers = servers().lock().await;
+        let key = server_key(listen, endpoint.id);
+        let server = match servers.get(&key) {
+            Some(server) => server.clone(),
+            None => {
+                let listener = tokio::net::TcpListener::bind(listen)
+                    .await
+                    .with_context(|| format!("Failed to listen on '{}'", listen))?;
+                let server = Arc::new(WebSocketServer {
+                    local_addr: listener.local_addr()?,
+                    endpoints: RwLock::new(HashMap::new()),
+                    cancel: CancellationToken::new(),
+                });
+                tokio::spawn(server.clone().accept_loop(listener));
+                log::info!("[WEBSOCKET] Started the server on {}", server.local_addr);
+                servers.insert(key, server.clone());
+                server
+            }
+        };
+        let mut endpoints = server.endpoints.write().expect("The endpoints lock is poisoned");
+        if endpoints.contains_key(&path) {
+            return Err(EdgelinkError::InvalidOperation(format!(
+                "The path '{}' is already listened on '{}'",
+                path, server.local_addr
+            ))
+            .into());
+        }
+        endpoints.insert(path, endpoint);
+        Ok(())
+    }
+
+    /// Removes the endpoint, the server is stopped if it has no endpoints left.
+    async fn remove_endpoint(listen: &str, endpoint: &WebSocketEndpoint) {
+        let mut servers = servers().lock().await;
+        let key = server_key(listen, endpoint.id);
+        let is_empty = match servers.get(&key) {
+            Some(server) => {
+                let mut endpoints = server.endpoints.write().expect("The endpoints lock is poisoned");
+                endpoints.retain(|_, x| x.id != endpoint.id);
+                endpoints.is_empty()
+            }
+            None => return,
+        };
+        if is_empty {
+            if let Some(server) = servers.remove(&key) {
+                server.cancel.cancel();
+                log::info!("[WEBSOCKET] Stopped the server on {}", server.local_addr);
+            }
+        }
+    }
+
+    async fn accept_loop(self: Arc<Self>, listener: tokio::net::TcpListener) {
+        loop {
+            let stream = tokio::select! {
+                _ = self.cancel.cancelled() => break,
+                res = listener.accept() => match res {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        log::warn!("[WEBSOCKET] Failed to accept on {}: {}", self.local_addr, e);
+                        continue;
+                    }
+                },
+            };
+            tokio::spawn(self.clone().handle_connection(stream));
+        }
+    }
+
+    async fn handle_connection(self: Arc<Self>, stream: tokio::net::TcpStream) {
+        let mut endpoint = None;
+        // The large error response is the one required by the handshake of tungstenite
+        #[allow(clippy::result_large_err)]
+        let callback = |req: &Request, res: Response| {
+            let found = self.endpoints.read().expect("The endpoints lock is poisoned").get(req.uri().path()).cloned();
+            match found {
+                Some(found) => {
+                    endpoint = Some(found);
+                    Ok(res)
+                }
+                None => {
+                    let mut res = ErrorResponse::new(Some(format!("Cannot GET {}", req.uri().path())));
+                    *res.status_mut() = StatusCode::NOT_FOUND;
+                    Err(res)
+                }
+            }
+        };
+        let ws = match tokio_tungstenite::accept_hdr_async(stream, callback).await {
+            Ok(ws) => ws,
+            Err(e) => {
+                log::debug!("[WEBSOCKET] Rejected the connection on {}: {}", self.local_addr, e);
+                return;
+            }
+        };
+        let Some(endpoint) = endpoint else { return };
+        let cancel = endpoint.state.lock().expect("Lock the endpoint state").cancel.clone();
+        if let Some(cancel) = cancel {
+            endpoint.serve_session(ws, cancel).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tokio_tungstenite::connect_async;
+
+    fn build_engine(flows_json: serde_json::Value) -> Engine {
+        let registry = crate::runtime::registry::RegistryBuilder::default().build().unwrap();
+        let cfg = config::Config::builder()
+            .add_source(config::File::from_str(
+                "[runtime.engine.websocket]\nlisten = \"127.0.0.1:0\"",
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap();
+        Engine::with_json(&registry, flows_json, Some(&cfg)).unwrap()
+    }
+
+    /// The address of the ephemeral server of the listener, waits until it is started.
+    async fn local_addr_of(listener_id: &str) -> SocketAddr {
+        let key = server_key("127.0.0.1:0", listener_id.parse().unwrap());
+        for _ in 0..100 {
+            if let Some(server) = servers().lock().await.get(&key) {
+                return server.local_addr;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("The server of the listener '{}' is not started", listener_id);
+    }
+
+    #[tokio::test]
+    async fn test_listener_should_echo_to_the_session() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "900", "type": "websocket-listener", "path": "/ws/echo", "wholemsg": "false"},
+            {"id": "1", "z": "100", "type": "websocket in", "server": "900", "client": "", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "websocket out", "server": "900", "client": ""}
+        ]);
+        let engine = build_engine(flows_json);
+        engine.start().await.unwrap();
+        let addr = local_addr_of("900").await;
+
+        let (mut ws1, _) = connect_async(format!("ws://{}/ws/echo", addr)).await.unwrap();
+        let (mut ws2, _) = connect_async(format!("ws://{}/ws/echo", addr)).await.unwrap();
+        ws1.send(Message::Text("hello".into())).await.unwrap();
+        ws2.send(Message::Binary(vec![1, 2, 3])).await.unwrap();
+
+        let timeout = Duration::from_secs(2);
+        let echo1 = tokio::time::timeout(timeout, ws1.next()).await.unwrap().unwrap().unwrap();
+        assert_eq!(echo1, Message::Text("hello".into()));
+        let echo2 = tokio::time::timeout(timeout, ws2.next()).await.unwrap().unwrap().unwrap();
+        assert_eq!(echo2, Message::Binary(vec![1, 2, 3]));
+
+        // An unknown path is rejected
+        assert!(connect_async(format!("ws://{}/ws/unknown", addr)).await.is_err());
+
+        engine.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_client_should_receive_and_send() {
+        let server = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = server.local_addr().unwrap();
+        let server_task = tokio::spawn(async move {
+            let (stream, _) = server.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            ws.send(Message::Text(r#"{"topic": "greeting", "payload": "hi"}"#.into())).await.unwrap();
+            ws.next().await.unwrap().unwrap()
+        });
+
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "901", "type": "websocket-client", "path": format!("ws://{}", addr), "wholemsg": "true"},
+            {"id": "1", "z": "100", "type": "websocket in", "server": "", "client": "901", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "change", "wires": [["3"]], "rules": [
+                {"t": "set", "p": "payload", "pt": "msg", "to": "pong", "tot": "str"},
+                {"t": "set", "p": "session", "pt": "msg", "to": "_session.type", "tot": "msg"}
+            ]},
+            {"id": "3", "z": "100", "type": "websocket out", "server": "", "client": "901"}
+        ]);
+        let engine = build_engine(flows_json);
+        engine.start().await.unwrap();
+
+        let reply = tokio::time::timeout(Duration::from_secs(2), server_task).await.unwrap().unwrap();
+        let reply: serde_json::Value = serde_json::from_str(reply.to_text().unwrap()).unwrap();
+        assert_eq!(reply["payload"], json!("pong"));
+        assert_eq!(reply["topic"], json!("greeting"));
+        assert_eq!(reply["session"], json!("websocket"));
+        assert!(reply.get("_session").is_none());
+        // The `_msgid` is the hex string like Node-RED
+        assert!(reply["_msgid"].as_str().is_some_and(|x| x.len() == 16));
+
+        engine.stop().await.unwrap();
+    }
+}
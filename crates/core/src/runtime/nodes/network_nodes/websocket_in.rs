@@ -0,0 +1,71 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+use tokio::sync::mpsc;
+
+use super::websocket::{get_endpoint, WebSocketEndpoint};
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+#[derive(Debug, Clone, Deserialize)]
+struct WebSocketNodeConfig {
+    /// The `websocket-listener` node
+    #[serde(default, deserialize_with = "json::deser::deser_red_optional_id")]
+    server: Option<ElementId>,
+
+    /// The `websocket-client` node
+    #[serde(default, deserialize_with = "json::deser::deser_red_optional_id")]
+    client: Option<ElementId>,
+}
+
+/// Sends the frames received by the listener or the client, `msg._session` identifies the connection.
+#[derive(Debug)]
+#[flow_node("websocket in")]
+struct WebSocketInNode {
+    base: FlowNode,
+    endpoint: Arc<WebSocketEndpoint>,
+}
+
+impl WebSocketInNode {
+    fn build(flow: &Flow, base_node: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let ws_config = WebSocketNodeConfig::deserialize(&config.rest)?;
+        let engine = flow.engine().ok_or(EdgelinkError::InvalidOperation("The engine has been released".into()))?;
+        let endpoint = get_endpoint(&engine, ws_config.server, ws_config.client)?;
+        let node = WebSocketInNode { base: base_node, endpoint };
+        Ok(Box::new(node))
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for WebSocketInNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        let (sender, mut receiver) = mpsc::channel(16);
+        self.endpoint.add_receiver(self.id(), sender);
+        if let Err(e) = self.endpoint.open(self.id()).await {
+            log::error!("[WEBSOCKET_IN:{}] Failed to open: {:?}", self.name(), e);
+            stop_token.cancelled().await;
+        }
+
+        loop {
+            let msg = tokio::select! {
+                _ = stop_token.cancelled() => break,
+                msg = receiver.recv() => match msg {
+                    Some(msg) => MsgHandle::new(msg),
+                    None => break,
+                },
+            };
+            self.notify_uow_completed(msg.clone(), stop_token.clone()).await;
+            if let Err(e) = self.fan_out_one(Envelope { port: 0, msg }, stop_token.child_token()).await {
+                log::warn!("[WEBSOCKET_IN:{}] Failed to send the message: {}", self.name(), e);
+            }
+        }
+
+        self.endpoint.close(self.id()).await;
+    }
+}
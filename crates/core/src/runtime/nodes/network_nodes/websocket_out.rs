@@ -0,0 +1,62 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use super::websocket::{get_endpoint, WebSocketEndpoint};
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use edgelink_macro::*;
+
+#[derive(Debug, Clone, Deserialize)]
+struct WebSocketNodeConfig {
+    /// The `websocket-listener` node
+    #[serde(default, deserialize_with = "json::deser::deser_red_optional_id")]
+    server: Option<ElementId>,
+
+    /// The `websocket-client` node
+    #[serde(default, deserialize_with = "json::deser::deser_red_optional_id")]
+    client: Option<ElementId>,
+}
+
+/// Sends `msg.payload` by the listener or the client.
+///
+/// The listener replies to the connection of `msg._session` if any, otherwise it broadcasts to all connections.
+#[derive(Debug)]
+#[flow_node("websocket out")]
+struct WebSocketOutNode {
+    base: FlowNode,
+    endpoint: Arc<WebSocketEndpoint>,
+}
+
+impl WebSocketOutNode {
+    fn build(flow: &Flow, base_node: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let ws_config = WebSocketNodeConfig::deserialize(&config.rest)?;
+        let engine = flow.engine().ok_or(EdgelinkError::InvalidOperation("The engine has been released".into()))?;
+        let endpoint = get_endpoint(&engine, ws_config.server, ws_config.client)?;
+        let node = WebSocketOutNode { base: base_node, endpoint };
+        Ok(Box::new(node))
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for WebSocketOutNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        if let Err(e) = self.endpoint.open(self.id()).await {
+            log::error!("[WEBSOCKET_OUT:{}] Failed to open: {:?}", self.name(), e);
+        }
+        while !stop_token.is_cancelled() {
+            with_uow_concurrent(&self, stop_token.child_token(), |node, msg| async move {
+                let msg_guard = msg.read().await;
+                node.endpoint.send(&msg_guard).await
+            })
+            .await;
+        }
+        wait_uows_completed(self.as_ref()).await;
+        self.endpoint.close(self.id()).await;
+    }
+}
@@ -1,77 +1,197 @@
-use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::net::UdpSocket;
 
 use base64::prelude::*;
 use serde::Deserialize;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
 
+use crate::runtime::eval;
 use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
 use crate::runtime::nodes::*;
+use crate::ErrorContext;
 use edgelink_macro::*;
-use validator::{Validate, ValidationError};
 
+/// The `os.EOL` of Node.js appended by the `appendNewline` option
+pub(super) const LINE_ENDING: &str = if cfg!(windows) { "\r\n" } else { "\n" };
 
+/// The encoding of the strings written to or read from the files
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub(super) enum FileEncoding {
+    /// The same as `utf8`
+    #[default]
+    #[serde(rename = "none")]
+    None,
 
+    #[serde(rename = "utf8")]
+    Utf8,
 
+    /// Latin-1, every byte is a character
+    #[serde(rename = "binary")]
+    Binary,
 
-#[derive(Debug)]
-#[flow_node("file")]
-struct FileNode {
-    base: FlowNode,
-    config: FileNodeConfig,
+    #[serde(rename = "base64")]
+    Base64,
 }
 
-impl FileNode {
-    fn build(_flow: &Flow, state: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
-        let udp_config = FileNodeConfig::deserialize(&config.rest)?;
+impl FileEncoding {
+    /// Converts the string to the bytes to write.
+    pub fn encode(&self, s: &str) -> crate::Result<Vec<u8>> {
+        match self {
+            FileEncoding::None | FileEncoding::Utf8 => Ok(s.as_bytes().to_vec()),
+            // The same as `Buffer.from(s, 'binary')` which takes the low byte of every character
+            FileEncoding::Binary => Ok(s.chars().map(|c| c as u32 as u8).collect()),
+            FileEncoding::Base64 => BASE64_STANDARD
+                .decode(s.trim())
+                .map_err(|e| EdgelinkError::InvalidOperation(format!("Bad base64 payload: {}", e)).into()),
+        }
+    }
 
+    /// Converts the bytes read to the string.
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        match self {
+            FileEncoding::None | FileEncoding::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            FileEncoding::Binary => bytes.iter().map(|b| *b as char).collect(),
+            FileEncoding::Base64 => BASE64_STANDARD.encode(bytes),
+        }
+    }
+}
 
+/// The `filename` and `filenameType` of the file nodes.
+#[derive(Debug, Clone)]
+pub(super) struct FilenameProperty {
+    type_: RedPropertyType,
+    value: String,
+}
 
-        let node = FileNode { base: state, config: udp_config };
-        Ok(Box::new(node))
+impl FilenameProperty {
+    /// The old flows without the `filenameType` use `msg.filename` if the `filename` is empty.
+    pub fn new(filename: &str, filename_type: &str) -> crate::Result<Self> {
+        let type_ = match filename_type {
+            "" if filename.is_empty() => RedPropertyType::Msg,
+            "" => RedPropertyType::Str,
+            other => RedPropertyType::deserialize(serde_json::Value::from(other))
+                .map_err(|_| EdgelinkError::BadFlowsJson(format!("Bad `filenameType`: '{}'", other)))?,
+        };
+        let value = match (type_, filename) {
+            (RedPropertyType::Msg, "") => "filename".to_string(),
+            _ => filename.to_string(),
+        };
+        Ok(Self { type_, value })
+    }
+
+    pub async fn evaluate(&self, node: &dyn FlowNodeBehavior, msg: &Msg) -> crate::Result<PathBuf> {
+        let filename = eval::evaluate_node_property(&self.value, self.type_, Some(node), None, Some(msg)).await?;
+        match filename.as_str() {
+            Some(filename) if !filename.trim().is_empty() => Ok(PathBuf::from(filename)),
+            _ => Err(EdgelinkError::InvalidOperation("No filename specified".into()).into()),
+        }
     }
 }
 
-#[derive(Deserialize,Validate, Debug)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum WriteMode {
+    #[default]
+    #[serde(rename = "false")]
+    Append,
+
+    #[serde(rename = "true")]
+    Overwrite,
+
+    #[serde(rename = "delete")]
+    Delete,
+}
+
+#[derive(Debug, Clone, Deserialize)]
 struct FileNodeConfig {
-    #[validate(length(min = 1))]
+    #[serde(default)]
     filename: String,
-    #[serde(rename = "filenameType")]
-    filename_type: Option<String>,
-    #[serde( rename= "appendNewline")]
-    append_new_line: String,
-    #[serde( rename= "overwriteFile")]
-    overwrite_file: String,
+
+    #[serde(default, rename = "filenameType")]
+    filename_type: String,
+
+    /// Appends a newline to every string written, so each message is a line
+    #[serde(
+        default,
+        rename = "appendNewline",
+        deserialize_with = "crate::runtime::model::json::deser::deser_bool_or_str"
+    )]
+    append_newline: bool,
+
+    /// Creates the parent directories of the file if they do not exist
+    #[serde(default, rename = "createDir", deserialize_with = "crate::runtime::model::json::deser::deser_bool_or_str")]
     create_dir: bool,
-    encoding: String,
 
+    #[serde(default, rename = "overwriteFile")]
+    overwrite_file: WriteMode,
+
+    #[serde(default)]
+    encoding: FileEncoding,
+}
+
+/// Writes `msg.payload` to the file, or deletes the file, then sends the message on.
+#[derive(Debug)]
+#[flow_node("file")]
+struct FileNode {
+    base: FlowNode,
+    config: FileNodeConfig,
+    filename: FilenameProperty,
 }
 
 impl FileNode {
-    async fn uow(&self, msg: MsgHandle, socket: &UdpSocket) -> crate::Result<()> {
-        let msg_guard = msg.read().await;
-        if let Some(payload) = msg_guard.get("payload") {
-            let remote_addr = std::net::SocketAddr::new(
-                self.config.addr.unwrap(), // TODO FIXME
-                self.config.port.unwrap(),
-            );
-
-            if let Some(bytes) = payload.as_bytes() {
-                if self.config.base64 {
-                    let b64_str = BASE64_STANDARD.encode(bytes);
-                    let bytes = b64_str.as_bytes();
-                    socket.send_to(bytes, remote_addr).await?;
-                } else {
-                    socket.send_to(bytes, remote_addr).await?;
+    fn build(_flow: &Flow, state: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let file_config = FileNodeConfig::deserialize(&config.rest)?;
+        let filename = FilenameProperty::new(&file_config.filename, &file_config.filename_type)?;
+        let node = FileNode { base: state, config: file_config, filename };
+        Ok(Box::new(node))
+    }
+
+    async fn uow(&self, msg: MsgHandle, cancel: CancellationToken) -> crate::Result<()> {
+        {
+            let msg_guard = msg.read().await;
+            let path = self.filename.evaluate(self, &msg_guard).await?;
+            match self.config.overwrite_file {
+                WriteMode::Delete => fs::remove_file(&path)
+                    .await
+                    .with_context(|| format!("Failed to delete the file '{}'", path.display()))?,
+                mode => {
+                    if let Some(bytes) = self.payload_to_bytes(msg_guard.get("payload"))? {
+                        self.write_file(&path, &bytes, mode == WriteMode::Overwrite)
+                            .await
+                            .with_context(|| format!("Failed to write the file '{}'", path.display()))?;
+                    }
                 }
             }
-            if let Some(bytes) = payload.to_bytes() {
-                socket.send_to(&bytes, remote_addr).await?;
-            } else {
-                log::warn!("Failed to convert payload into bytes");
-            }
         }
+        self.fan_out_one(Envelope { port: 0, msg }, cancel).await
+    }
 
+    /// Converts the payload to the bytes to write, the buffer is written as it is.
+    fn payload_to_bytes(&self, payload: Option<&Variant>) -> crate::Result<Option<Vec<u8>>> {
+        let mut bytes = match payload {
+            None | Some(Variant::Null) => return Ok(None),
+            Some(Variant::Bytes(bytes)) => return Ok(Some(bytes.clone())),
+            Some(Variant::String(s)) => self.config.encoding.encode(s)?,
+            Some(value @ (Variant::Number(_) | Variant::Bool(_))) => value.to_string()?.into_bytes(),
+            Some(other) => serde_json::to_vec(other)?,
+        };
+        if self.config.append_newline {
+            bytes.extend_from_slice(LINE_ENDING.as_bytes());
+        }
+        Ok(Some(bytes))
+    }
+
+    async fn write_file(&self, path: &PathBuf, bytes: &[u8], overwrite: bool) -> crate::Result<()> {
+        if self.config.create_dir {
+            if let Some(dir) = path.parent().filter(|x| !x.as_os_str().is_empty()) {
+                fs::create_dir_all(dir).await?;
+            }
+        }
+        let mut file =
+            fs::OpenOptions::new().create(true).write(true).append(!overwrite).truncate(overwrite).open(path).await?;
+        file.write_all(bytes).await?;
+        file.flush().await?;
         Ok(())
     }
 }
@@ -83,31 +203,81 @@ impl FlowNodeBehavior for FileNode {
     }
 
     async fn run(self: Arc<Self>, stop_token: CancellationToken) {
-        let local_addr: SocketAddr = match self.config.outport {
-            Some(port) => SocketAddr::new(self.config.iface.unwrap(), port),
-            _ => match self.config.ipv {
-                UdpIpV::V4 => "0.0.0.0:0".parse().unwrap(),
-                UdpIpV::V6 => "[::]:0".parse().unwrap(),
-            },
-        };
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow_concurrent(&self, cancel.child_token(), |node, msg| async move { node.uow(msg, cancel).await })
+                .await;
+        }
+        wait_uows_completed(self.as_ref()).await;
+    }
+}
 
-        match tokio::net::UdpSocket::bind(local_addr).await {
-            Ok(socket) => {
-                let socket = Arc::new(socket);
-                while !stop_token.is_cancelled() {
-                    let cloned_socket = socket.clone();
-
-                    let node = self.clone();
-                    with_uow(node.as_ref(), stop_token.clone(), |node, msg| async move {
-                        node.uow(msg, &cloned_socket).await
-                    })
-                        .await;
-                }
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
 
-            Err(e) => {
-                log::error!("Can not bind local address: {:?}", e);
-            }
-        }
+    #[tokio::test]
+    async fn test_it_should_append_lines_to_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sub").join("out.txt");
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "file", "filename": path.to_str().unwrap(), "filenameType": "str",
+                "appendNewline": true, "createDir": true, "overwriteFile": "false", "encoding": "none",
+                "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"payload": "a"}],
+            ["1", {"payload": 1}],
+            ["1", {"payload": {"b": true}}],
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json.clone()).unwrap();
+        let msgs = engine.run_once_with_inject(3, Duration::from_secs_f64(0.5), msgs_to_inject).await.unwrap();
+        assert_eq!(msgs[0]["payload"], "a".into());
+        let expected = ["a", "1", "{\"b\":true}"].map(|x| format!("{}{}", x, LINE_ENDING)).concat();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), expected);
+
+        // Appends to the existing file
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": "c"}]])).unwrap();
+        engine.run_once_with_inject(1, Duration::from_secs_f64(0.5), msgs_to_inject).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), format!("{}c{}", expected, LINE_ENDING));
+    }
+
+    #[tokio::test]
+    async fn test_it_should_overwrite_and_delete_the_file_of_msg_filename() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.txt");
+        std::fs::write(&path, "old content").unwrap();
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "file", "filename": "", "overwriteFile": "true", "encoding": "base64",
+                "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "file", "filename": "", "overwriteFile": "delete", "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"filename": path.to_str().unwrap(), "payload": "aGk="}],
+            ["2", {"filename": path.to_str().unwrap()}],
+        ]))
+        .unwrap();
+
+        // The writing node alone, wired to the test node
+        let mut first_flows_json = json!([flows_json[0], flows_json[1], flows_json[3]]);
+        first_flows_json[1]["wires"] = json!([["3"]]);
+        let engine = crate::runtime::engine::build_test_engine(first_flows_json).unwrap();
+        let first = Vec::from([msgs_to_inject[0].clone()]);
+        engine.run_once_with_inject(1, Duration::from_secs_f64(0.5), first).await.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"hi");
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let second = Vec::from([msgs_to_inject[1].clone()]);
+        engine.run_once_with_inject(1, Duration::from_secs_f64(0.5), second).await.unwrap();
+        assert!(!path.exists());
     }
 }
@@ -0,0 +1,321 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use serde::Deserialize;
+use tokio::fs;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+
+use super::file::{FileEncoding, FilenameProperty};
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use crate::ErrorContext;
+use edgelink_macro::*;
+
+/// The size of the chunks in the `stream` format, the same as the `highWaterMark` of Node.js
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum FileInFormat {
+    /// The whole file as a single buffer
+    #[default]
+    #[serde(rename = "")]
+    Buffer,
+
+    /// The whole file as a single string
+    #[serde(rename = "utf8")]
+    Utf8,
+
+    /// A message of string per line
+    #[serde(rename = "lines")]
+    Lines,
+
+    /// A message of buffer per chunk
+    #[serde(rename = "stream")]
+    Stream,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct FileInNodeConfig {
+    #[serde(default)]
+    filename: String,
+
+    #[serde(default, rename = "filenameType")]
+    filename_type: String,
+
+    #[serde(default)]
+    format: FileInFormat,
+
+    /// Sends the message with the `error` property instead of reporting the error to the `catch` nodes
+    #[serde(default, rename = "sendError", deserialize_with = "crate::runtime::model::json::deser::deser_bool_or_str")]
+    send_error: bool,
+
+    #[serde(default)]
+    encoding: FileEncoding,
+
+    /// Copies all properties of the incoming message to every line or chunk, not only the `topic`
+    #[serde(default, rename = "allProps", deserialize_with = "crate::runtime::model::json::deser::deser_bool_or_str")]
+    all_props: bool,
+}
+
+/// Reads the file to `msg.payload`, the whole file or as a sequence of the lines or chunks.
+#[derive(Debug)]
+#[flow_node("file in")]
+struct FileInNode {
+    base: FlowNode,
+    config: FileInNodeConfig,
+    filename: FilenameProperty,
+}
+
+impl FileInNode {
+    fn build(_flow: &Flow, state: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let file_config = FileInNodeConfig::deserialize(&config.rest)?;
+        let filename = FilenameProperty::new(&file_config.filename, &file_config.filename_type)?;
+        let node = FileInNode { base: state, config: file_config, filename };
+        Ok(Box::new(node))
+    }
+
+    async fn uow(&self, msg: MsgHandle, cancel: CancellationToken) -> crate::Result<()> {
+        let origin = msg.read().await.clone();
+        let res = match self.filename.evaluate(self, &origin).await {
+            Ok(path) => self.read_file(msg.clone(), &origin, &path, cancel.clone()).await,
+            Err(e) => Err(e),
+        };
+        match res {
+            Err(e) if self.config.send_error => {
+                log::warn!("[FILE_IN:{}] {:#}", self.name(), e);
+                let mut err_msg = origin;
+                err_msg.remove("payload");
+                let error = VariantObjectMap::from([("message".to_string(), Variant::String(format!("{:#}", e)))]);
+                err_msg.set("error".to_string(), Variant::Object(error));
+                self.fan_out_one(Envelope { port: 0, msg: MsgHandle::new(err_msg) }, cancel).await
+            }
+            res => res,
+        }
+    }
+
+    async fn read_file(
+        &self,
+        msg: MsgHandle,
+        origin: &Msg,
+        path: &Path,
+        cancel: CancellationToken,
+    ) -> crate::Result<()> {
+        let meta = fs::metadata(path).await.with_context(|| format!("Failed to stat the file '{}'", path.display()))?;
+        if meta.is_dir() {
+            return Err(EdgelinkError::InvalidOperation(format!("'{}' is a directory", path.display())).into());
+        }
+        let filename = Variant::String(path.to_string_lossy().into_owned());
+
+        match self.config.format {
+            FileInFormat::Buffer | FileInFormat::Utf8 => {
+                let bytes =
+                    fs::read(path).await.with_context(|| format!("Failed to read the file '{}'", path.display()))?;
+                let payload = match self.config.format {
+                    FileInFormat::Buffer => Variant::Bytes(bytes),
+                    _ => Variant::String(self.config.encoding.decode(&bytes)),
+                };
+                {
+                    let mut msg_guard = msg.write().await;
+                    msg_guard.set("payload".to_string(), payload);
+                    msg_guard.set("filename".to_string(), filename);
+                }
+                self.fan_out_one(Envelope { port: 0, msg }, cancel).await
+            }
+
+            FileInFormat::Lines => {
+                let file = fs::File::open(path).await?;
+                let mut reader = BufReader::new(file);
+                let seq_id = Msg::generate_id().to_string();
+                let mut line = Vec::new();
+                for index in 0.. {
+                    line.clear();
+                    reader.read_until(b'\n', &mut line).await?;
+                    // Splits the text like `String.prototype.split("\n")`, so the last line may be empty
+                    let is_last = line.last() != Some(&b'\n');
+                    if !is_last {
+                        line.pop();
+                    }
+                    let payload = Variant::String(self.config.encoding.decode(&line));
+                    let parts = self.make_parts(&seq_id, "string", "\n", index, is_last);
+                    let part_msg = self.make_part_msg(origin, payload, &filename, parts)?;
+                    self.fan_out_one(Envelope { port: 0, msg: part_msg }, cancel.clone()).await?;
+                    if is_last {
+                        break;
+                    }
+                }
+                Ok(())
+            }
+
+            FileInFormat::Stream => {
+                let mut file = fs::File::open(path).await?;
+                let seq_id = Msg::generate_id().to_string();
+                // Holds a chunk back to know which one is the last
+                let mut pending: Option<Vec<u8>> = None;
+                let mut index = 0;
+                loop {
+                    let mut chunk = vec![0u8; STREAM_CHUNK_SIZE];
+                    let len = file.read(&mut chunk).await?;
+                    if len == 0 {
+                        break;
+                    }
+                    chunk.truncate(len);
+                    if let Some(prev) = pending.replace(chunk) {
+                        let parts = self.make_parts(&seq_id, "buffer", "", index, false);
+                        let part_msg = self.make_part_msg(origin, Variant::Bytes(prev), &filename, parts)?;
+                        self.fan_out_one(Envelope { port: 0, msg: part_msg }, cancel.clone()).await?;
+                        index += 1;
+                    }
+                }
+                let parts = self.make_parts(&seq_id, "buffer", "", index, true);
+                let payload = Variant::Bytes(pending.unwrap_or_default());
+                let part_msg = self.make_part_msg(origin, payload, &filename, parts)?;
+                self.fan_out_one(Envelope { port: 0, msg: part_msg }, cancel).await
+            }
+        }
+    }
+
+    fn make_parts(&self, seq_id: &str, kind: &str, ch: &str, index: usize, is_last: bool) -> Variant {
+        let mut parts = VariantObjectMap::from([
+            ("id".to_string(), Variant::String(seq_id.to_string())),
+            ("type".to_string(), Variant::from(kind)),
+            ("ch".to_string(), Variant::from(ch)),
+            ("index".to_string(), Variant::from(index as u64)),
+        ]);
+        if is_last {
+            parts.insert("count".to_string(), Variant::from((index + 1) as u64));
+        }
+        Variant::Object(parts)
+    }
+
+    fn make_part_msg(
+        &self,
+        origin: &Msg,
+        payload: Variant,
+        filename: &Variant,
+        parts: Variant,
+    ) -> crate::Result<MsgHandle> {
+        let mut part_msg = if self.config.all_props {
+            let mut part_msg = origin.clone();
+            part_msg.set("payload".to_string(), payload);
+            part_msg.set_id(Msg::generate_id());
+            part_msg
+        } else {
+            let mut part_msg = MsgBuilder::new().payload(payload).build()?;
+            if let Some(topic) = origin.get("topic") {
+                part_msg.set("topic".to_string(), topic.clone());
+            }
+            part_msg
+        };
+        part_msg.set("filename".to_string(), filename.clone());
+        part_msg.set("parts".to_string(), parts);
+        Ok(MsgHandle::new(part_msg))
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for FileInNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow_concurrent(&self, cancel.child_token(), |node, msg| async move { node.uow(msg, cancel).await })
+                .await;
+        }
+        wait_uows_completed(self.as_ref()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_it_should_round_trip_binary_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.bin");
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "file", "filename": "filename", "filenameType": "msg",
+                "appendNewline": true, "overwriteFile": "true", "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "file in", "filename": path.to_str().unwrap(), "filenameType": "str",
+                "format": "", "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let data: Vec<u8> = (0..=255u8).chain([0xff, 0xfe, 0x00, b'\n']).collect();
+        let mut msg = MsgBuilder::new().topic("bin").build().unwrap();
+        msg.set("filename".to_string(), Variant::from(path.to_str().unwrap()));
+        msg.set("payload".to_string(), Variant::Bytes(data.clone()));
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine
+            .run_once_with_inject(1, Duration::from_secs_f64(0.5), vec![(ElementId::with_u64(1), msg)])
+            .await
+            .unwrap();
+        // The buffer is written as it is, without the newline
+        assert_eq!(std::fs::read(&path).unwrap(), data);
+        assert_eq!(msgs[0]["payload"], Variant::Bytes(data));
+        assert_eq!(msgs[0]["topic"], "bin".into());
+        assert_eq!(msgs[0]["filename"], path.to_str().unwrap().into());
+    }
+
+    #[tokio::test]
+    async fn test_it_should_read_lines_as_a_sequence() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lines.txt");
+        std::fs::write(&path, "a\nb\nc").unwrap();
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "file in", "filename": path.to_str().unwrap(), "format": "lines",
+                "encoding": "utf8", "allProps": false, "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject =
+            Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": null, "topic": "t", "foo": 1}]])).unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(3, Duration::from_secs_f64(0.5), msgs_to_inject).await.unwrap();
+        let payloads: Vec<_> = msgs.iter().map(|x| x["payload"].clone()).collect();
+        assert_eq!(payloads, vec!["a".into(), "b".into(), "c".into()]);
+        assert_eq!(msgs[0]["topic"], "t".into());
+        assert!(!msgs[0].contains("foo"));
+        assert_eq!(msgs[2].get_nav_stripped("parts.index"), Some(&Variant::from(2)));
+        assert_eq!(msgs[2].get_nav_stripped("parts.count"), Some(&Variant::from(3)));
+        assert_eq!(msgs[0].get_nav_stripped("parts.count"), None);
+        assert_eq!(msgs[0].get_nav_stripped("parts.id"), msgs[2].get_nav_stripped("parts.id"));
+    }
+
+    #[tokio::test]
+    async fn test_missing_file_should_be_sent_or_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing.txt");
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "file in", "filename": path.to_str().unwrap(), "format": "utf8",
+                "sendError": true, "wires": [["3"]]},
+            {"id": "2", "z": "100", "type": "file in", "filename": path.to_str().unwrap(), "format": "utf8",
+                "sendError": false, "wires": [["3"]]},
+            {"id": "4", "z": "100", "type": "catch", "scope": ["2"], "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"}
+        ]);
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([
+            ["1", {"payload": "x", "topic": "sent"}],
+            ["2", {"payload": "y", "topic": "thrown"}],
+        ]))
+        .unwrap();
+
+        let engine = crate::runtime::engine::build_test_engine(flows_json).unwrap();
+        let msgs = engine.run_once_with_inject(2, Duration::from_secs_f64(0.5), msgs_to_inject).await.unwrap();
+        let sent = msgs.iter().find(|x| x["topic"] == "sent".into()).unwrap();
+        assert!(!sent.contains("payload"));
+        assert!(sent.get_nav_stripped("error.message").is_some());
+        let thrown = msgs.iter().find(|x| x["topic"] == "thrown".into()).unwrap();
+        assert_eq!(thrown["payload"], "y".into());
+        assert!(thrown.get_nav_stripped("error.message").unwrap().as_str().unwrap().contains("missing.txt"));
+    }
+}
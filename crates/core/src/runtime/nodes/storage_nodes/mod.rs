@@ -1,3 +1,5 @@
-// mod file;
+mod file;
+mod file_in;
+mod record;
+mod replay;
 mod watch;
-mod file_in;
\ No newline at end of file
@@ -0,0 +1,161 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use crate::utils::time::unix_now;
+use crate::ErrorContext;
+use edgelink_macro::*;
+
+/// A line of the recording, the message received at the UNIX timestamp in milliseconds
+pub(super) const RECORD_TS_PROPERTY: &str = "ts";
+pub(super) const RECORD_MSG_PROPERTY: &str = "msg";
+
+/// Converts the value to JSON, the buffers are written like `Buffer.toJSON()` so they can be told from the arrays.
+pub(super) fn variant_to_record(value: &Variant) -> JsonValue {
+    match value {
+        Variant::Bytes(bytes) => serde_json::json!({"type": "Buffer", "data": bytes}),
+        Variant::Array(items) => JsonValue::Array(items.iter().map(variant_to_record).collect()),
+        Variant::Object(map) => JsonValue::Object(map.iter().map(|(k, v)| (k.clone(), variant_to_record(v))).collect()),
+        other => JsonValue::from(other),
+    }
+}
+
+/// The reverse of `variant_to_record()`.
+pub(super) fn record_to_variant(value: JsonValue) -> Variant {
+    match value {
+        JsonValue::Object(map) if is_buffer_json(&map) => {
+            let bytes = map["data"].as_array().map(|x| x.iter().filter_map(|b| b.as_u64()).map(|b| b as u8).collect());
+            Variant::Bytes(bytes.unwrap_or_default())
+        }
+        JsonValue::Array(items) => Variant::Array(items.into_iter().map(record_to_variant).collect()),
+        JsonValue::Object(map) => Variant::Object(map.into_iter().map(|(k, v)| (k, record_to_variant(v))).collect()),
+        other => Variant::from(other),
+    }
+}
+
+fn is_buffer_json(map: &serde_json::Map<String, JsonValue>) -> bool {
+    map.len() == 2
+        && map.get("type").and_then(|x| x.as_str()) == Some("Buffer")
+        && map
+            .get("data")
+            .and_then(|x| x.as_array())
+            .is_some_and(|x| x.iter().all(|b| b.as_u64().is_some_and(|b| b <= 255)))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RecordNodeConfig {
+    #[serde(default)]
+    filename: String,
+
+    /// Appends to the existing recording instead of starting a new one when the flows start
+    #[serde(default, deserialize_with = "json::deser::deser_bool_or_str")]
+    append: bool,
+}
+
+/// Records the messages to the file as NDJSON for the `replay` node, the messages are passed through.
+#[derive(Debug)]
+#[flow_node("record")]
+struct RecordNode {
+    base: FlowNode,
+    config: RecordNodeConfig,
+    path: PathBuf,
+    file: Mutex<Option<fs::File>>,
+}
+
+impl RecordNode {
+    fn build(_flow: &Flow, state: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let record_config = RecordNodeConfig::deserialize(&config.rest)?;
+        if record_config.filename.trim().is_empty() {
+            return Err(EdgelinkError::BadFlowsJson("The filename of the record node must be set".into()).into());
+        }
+        let path = PathBuf::from(record_config.filename.trim());
+        let node = RecordNode { base: state, config: record_config, path, file: Mutex::new(None) };
+        Ok(Box::new(node))
+    }
+
+    async fn open(&self) -> crate::Result<fs::File> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(self.config.append)
+            .truncate(!self.config.append)
+            .open(&self.path)
+            .await
+            .with_context(|| format!("Failed to open the recording '{}'", self.path.display()))?;
+        Ok(file)
+    }
+
+    async fn uow(&self, msg: MsgHandle, cancel: CancellationToken) -> crate::Result<()> {
+        let mut line = {
+            let msg_guard = msg.read().await;
+            let record = serde_json::json!({
+                RECORD_TS_PROPERTY: unix_now(),
+                RECORD_MSG_PROPERTY: variant_to_record(msg_guard.as_variant()),
+            });
+            serde_json::to_vec(&record)?
+        };
+        line.push(b'\n');
+        {
+            let mut file = self.file.lock().await;
+            let file = file.as_mut().ok_or(EdgelinkError::InvalidOperation("The recording is not open".into()))?;
+            file.write_all(&line).await.context("Failed to write the recording")?;
+            file.flush().await?;
+        }
+        self.fan_out_one(Envelope { port: 0, msg }, cancel).await
+    }
+}
+
+#[async_trait]
+impl FlowNodeBehavior for RecordNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        match self.open().await {
+            Ok(file) => *self.file.lock().await = Some(file),
+            Err(e) => {
+                log::error!("[RECORD:{}] {:#}", self.name(), e);
+                stop_token.cancelled().await;
+                return;
+            }
+        }
+
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow(self.as_ref(), cancel.child_token(), |node, msg| async move { node.uow(msg, cancel).await }).await;
+        }
+
+        self.file.lock().await.take();
+        log::debug!("RecordNode process() task has been terminated.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_buffers_should_survive_the_record() {
+        let value = Variant::from(json!({"a": [1, "x", null], "b": {"c": true}}));
+        let mut map = value.as_object().unwrap().clone();
+        map.insert("bytes".to_string(), Variant::Bytes(vec![0, 1, 255]));
+        let value = Variant::Object(map);
+
+        let record = variant_to_record(&value);
+        assert_eq!(record["bytes"], json!({"type": "Buffer", "data": [0, 1, 255]}));
+        assert_eq!(record_to_variant(record), value);
+        // Not a buffer
+        let other = json!({"type": "Buffer", "data": [256]});
+        assert_eq!(record_to_variant(other.clone()), Variant::from(other));
+    }
+}
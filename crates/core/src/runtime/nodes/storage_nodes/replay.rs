@@ -0,0 +1,201 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+use tokio::fs;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::time::Instant;
+
+use super::record::{record_to_variant, RECORD_MSG_PROPERTY, RECORD_TS_PROPERTY};
+use crate::runtime::flow::Flow;
+use crate::runtime::model::*;
+use crate::runtime::nodes::*;
+use crate::utils;
+use crate::ErrorContext;
+use edgelink_macro::*;
+
+/// The lowest speed factor, the slower replays would delay the messages for years
+const MIN_SPEED: f64 = 0.001;
+
+#[derive(Debug, Clone, Deserialize)]
+struct ReplayNodeConfig {
+    #[serde(default)]
+    filename: String,
+
+    /// The replay speed factor, `2` replays twice as fast as the messages were recorded
+    #[serde(default, deserialize_with = "json::deser::str_to_option_f64")]
+    speed: Option<f64>,
+}
+
+/// Sends the messages recorded by the `record` node with their original inter-arrival time, every incoming message
+/// starts a replay.
+///
+/// The recording is read line by line, so large recordings are not loaded into memory.
+#[derive(Debug)]
+#[flow_node("replay")]
+struct ReplayNode {
+    base: FlowNode,
+    path: PathBuf,
+    speed: f64,
+}
+
+impl ReplayNode {
+    fn build(_flow: &Flow, state: FlowNode, config: &RedFlowNodeConfig) -> crate::Result<Box<dyn FlowNodeBehavior>> {
+        let replay_config = ReplayNodeConfig::deserialize(&config.rest)?;
+        if replay_config.filename.trim().is_empty() {
+            return Err(EdgelinkError::BadFlowsJson("The filename of the replay node must be set".into()).into());
+        }
+        let speed = replay_config.speed.unwrap_or(1.0);
+        if !speed.is_finite() || speed < MIN_SPEED {
+            return Err(EdgelinkError::BadFlowsJson(format!(
+                "The speed of the replay node must be at least {}: {}",
+                MIN_SPEED, speed
+            ))
+            .into());
+        }
+        let node = ReplayNode { base: state, path: PathBuf::from(replay_config.filename.trim()), speed };
+        Ok(Box::new(node))
+    }
+
+    async fn replay(&self, cancel: CancellationToken) -> crate::Result<()> {
+        let file = fs::File::open(&self.path)
+            .await
+            .with_context(|| format!("Failed to open the recording '{}'", self.path.display()))?;
+        let mut lines = BufReader::new(file).lines();
+        let started = Instant::now();
+        let mut first_ts: Option<i64> = None;
+        let mut line_no = 0;
+        while let Some(line) = lines.next_line().await? {
+            line_no += 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (ts, mut msg) = match parse_record(&line) {
+                Some(record) => record,
+                None => {
+                    // The last line may be truncated if the recording was interrupted
+                    log::warn!("[REPLAY:{}] Skipped the bad record at line {}", self.name(), line_no);
+                    continue;
+                }
+            };
+
+            // Sleeps to the instant relative to the start, so the delays do not accumulate
+            let offset_ms = (ts - *first_ts.get_or_insert(ts)).max(0) as f64 / self.speed;
+            let due = started + utils::time::delay_from_secs_f64(offset_ms / 1000.0).unwrap_or_default();
+            tokio::select! {
+                _ = cancel.cancelled() => return Ok(()),
+                _ = tokio::time::sleep_until(due) => {}
+            }
+
+            msg.set_id(Msg::generate_id());
+            self.fan_out_one(Envelope { port: 0, msg: MsgHandle::new(msg) }, cancel.child_token()).await?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_record(line: &str) -> Option<(i64, Msg)> {
+    let mut record: JsonValue = serde_json::from_str(line).ok()?;
+    let ts = record.get(RECORD_TS_PROPERTY)?.as_i64()?;
+    let body = match record_to_variant(record.get_mut(RECORD_MSG_PROPERTY)?.take()) {
+        Variant::Object(body) => body,
+        _ => return None,
+    };
+    let mut msg = Msg::default();
+    *msg.as_variant_object_mut() = body;
+    Some((ts, msg))
+}
+
+#[async_trait]
+impl FlowNodeBehavior for ReplayNode {
+    fn get_node(&self) -> &FlowNode {
+        &self.base
+    }
+
+    async fn run(self: Arc<Self>, stop_token: CancellationToken) {
+        while !stop_token.is_cancelled() {
+            let cancel = stop_token.clone();
+            with_uow(self.as_ref(), cancel.child_token(), |node, _| async move { node.replay(cancel).await }).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_it_should_replay_the_recorded_msgs_with_their_timing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("recording.ndjson");
+        let record_flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "record", "filename": path.to_str().unwrap(), "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let engine = crate::runtime::engine::build_test_engine(record_flows_json).unwrap();
+        let msgs_to_record = [
+            MsgBuilder::new().payload("a").topic("t").build().unwrap(),
+            MsgBuilder::new().payload(Variant::Bytes(vec![1, 2, 3])).build().unwrap(),
+            MsgBuilder::new().payload(json!({"c": [1, 2]})).build().unwrap(),
+        ];
+        let (recorded, _) = tokio::join!(engine.run_once(3, Duration::from_secs(2)), async {
+            for (i, msg) in msgs_to_record.into_iter().enumerate() {
+                if i > 0 {
+                    tokio::time::sleep(Duration::from_millis(150)).await;
+                }
+                // The node may not be running yet
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                engine
+                    .inject_msg(&ElementId::with_u64(1), MsgHandle::new(msg), CancellationToken::new())
+                    .await
+                    .unwrap();
+            }
+        });
+        assert_eq!(recorded.unwrap().len(), 3);
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 3);
+
+        let replay_flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "replay", "filename": path.to_str().unwrap(), "speed": "2",
+                "wires": [["2"]]},
+            {"id": "2", "z": "100", "type": "test-once"}
+        ]);
+        let recorded_ts: Vec<i64> =
+            std::fs::read_to_string(&path).unwrap().lines().map(|x| parse_record(x).unwrap().0).collect();
+        let recorded_span = Duration::from_millis((recorded_ts[2] - recorded_ts[0]) as u64);
+
+        // The timers of the replay are exact in the paused time
+        tokio::time::pause();
+        let engine = crate::runtime::engine::build_test_engine(replay_flows_json).unwrap();
+        let msgs_to_inject = Vec::<(ElementId, Msg)>::deserialize(json!([["1", {"payload": "go"}]])).unwrap();
+        let started = Instant::now();
+        let msgs = engine.run_once_with_inject(3, Duration::from_secs(2), msgs_to_inject).await.unwrap();
+        let elapsed = started.elapsed();
+        assert_eq!(msgs[0]["payload"], "a".into());
+        assert_eq!(msgs[0]["topic"], "t".into());
+        assert_eq!(msgs[1]["payload"], Variant::Bytes(vec![1, 2, 3]));
+        assert_eq!(msgs[2].get_nav_stripped("payload.c[1]"), Some(&Variant::from(2)));
+        // The recording is replayed twice as fast
+        assert!(elapsed >= recorded_span / 2, "{:?} of {:?}", elapsed, recorded_span);
+        assert!(elapsed < recorded_span / 2 + Duration::from_millis(50), "{:?} of {:?}", elapsed, recorded_span);
+    }
+
+    #[test]
+    fn test_bad_speed_should_fail_to_build() {
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "replay", "filename": "x.ndjson", "speed": "0", "wires": []}
+        ]);
+        assert!(crate::runtime::engine::build_test_engine(flows_json).is_err());
+
+        let flows_json = json!([
+            {"id": "100", "type": "tab"},
+            {"id": "1", "z": "100", "type": "replay", "filename": "x.ndjson", "speed": "1e-300", "wires": []}
+        ]);
+        assert!(crate::runtime::engine::build_test_engine(flows_json).is_err());
+    }
+}
@@ -17,7 +17,7 @@ pub(crate) struct SubflowOutputPort {
     pub index: usize,
     pub instance_node: Option<Weak<dyn FlowNodeBehavior>>,
     pub msg_tx: MsgSender,
-    pub msg_rx: MsgReceiverHolder,
+    pub msg_rx: Arc<MsgReceiverHolder>,
 }
 
 #[derive(Debug)]
@@ -67,7 +67,7 @@ impl SubflowState {
                     index,
                     instance_node: subflow_instance.clone().map(|x| Arc::downgrade(&x)),
                     msg_tx: msg_root_tx.clone(),
-                    msg_rx: MsgReceiverHolder::new(msg_rx),
+                    msg_rx: Arc::new(MsgReceiverHolder::new(msg_rx)),
                 }),
             );
         }
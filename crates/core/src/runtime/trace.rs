@@ -0,0 +1,51 @@
+use std::time::{Instant, SystemTime};
+
+use crate::runtime::model::*;
+use crate::runtime::nodes::FlowNodeBehavior;
+
+/// A step of a traced message, recorded when a node sent a message to one of its output ports.
+#[derive(Debug, Clone)]
+pub struct TraceStep {
+    pub node_id: ElementId,
+    pub node_type: &'static str,
+    pub node_name: String,
+    pub port: usize,
+    /// The IDs of the nodes wired to the port
+    pub targets: Vec<ElementId>,
+    pub msg_id: Option<ElementId>,
+    /// The message sent at this step, to see how the nodes transformed it
+    pub msg: Msg,
+    /// The wall-clock time of the step
+    pub timestamp: SystemTime,
+    /// The monotonic time of the step, for ordering the steps
+    pub at: Instant,
+}
+
+impl TraceStep {
+    pub(crate) async fn new(node: &dyn FlowNodeBehavior, event: &MsgSentEvent) -> Self {
+        let targets = node
+            .get_node()
+            .ports
+            .get(event.port)
+            .map(|p| p.wires.iter().map(|w| w.target_node_id).collect())
+            .unwrap_or_default();
+        let msg = event.msg.read().await.clone();
+        let timestamp = SystemTime::now() - event.at.elapsed();
+        Self {
+            node_id: node.id(),
+            node_type: node.type_str(),
+            node_name: node.name().to_string(),
+            port: event.port,
+            targets,
+            msg_id: msg.id(),
+            msg,
+            timestamp,
+            at: event.at,
+        }
+    }
+
+    /// Returns `true` if the message was fanned out to more than one node at this step.
+    pub fn is_branch(&self) -> bool {
+        self.targets.len() > 1
+    }
+}
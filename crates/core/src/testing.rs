@@ -0,0 +1,238 @@
+//! Utilities to test flows in Rust.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use edgelink_core::testing::FlowTester;
+//! use serde_json::json;
+//!
+//! # async fn example() -> edgelink_core::Result<()> {
+//! let output = FlowTester::new(json!([
+//!         { "id": "100", "type": "tab" },
+//!         { "id": "1", "z": "100", "type": "test-once" }
+//!     ]))
+//!     .inject("1", json!({"payload": "foo"}))
+//!     .expect(1)
+//!     .run()
+//!     .await?;
+//! output.assert_payload_eq(0, "foo");
+//! # Ok(())
+//! # }
+//! ```
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::runtime::engine::Engine;
+use crate::runtime::model::*;
+use crate::runtime::registry::RegistryBuilder;
+use crate::*;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// A builder to load a flows JSON, inject messages and collect the messages arrived at the `test-once` nodes.
+#[derive(Debug)]
+pub struct FlowTester {
+    flows_json: serde_json::Value,
+    config: Option<config::Config>,
+    injections: Vec<(String, serde_json::Value)>,
+    expected: usize,
+    timeout: Duration,
+}
+
+/// The messages received by the `test-once` nodes of a `FlowTester` run, in order of arrival.
+#[derive(Debug)]
+pub struct FlowTestOutput {
+    msgs: Vec<Msg>,
+}
+
+impl FlowTester {
+    pub fn new(flows_json: serde_json::Value) -> Self {
+        Self { flows_json, config: None, injections: Vec::new(), expected: 1, timeout: DEFAULT_TIMEOUT }
+    }
+
+    pub fn with_config(mut self, config: config::Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Injects a message to the node specified by its ID or its name.
+    pub fn inject(mut self, node: &str, msg: serde_json::Value) -> Self {
+        self.injections.push((node.to_string(), msg));
+        self
+    }
+
+    /// Sets the count of messages expected to arrive at the `test-once` nodes.
+    pub fn expect(mut self, count: usize) -> Self {
+        self.expected = count;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub async fn run(self) -> crate::Result<FlowTestOutput> {
+        let registry = RegistryBuilder::default().build()?;
+        let engine = Engine::with_json(&registry, self.flows_json, self.config.as_ref())?;
+
+        let mut msgs_to_inject = Vec::with_capacity(self.injections.len());
+        for (node, msg_json) in self.injections.into_iter() {
+            let node_id = Self::resolve_node_id(&engine, &node)?;
+            let msg = Msg::deserialize(msg_json)
+                .with_context(|| format!("Failed to deserialize the message to inject into node '{}'", node))?;
+            msgs_to_inject.push((node_id, msg));
+        }
+
+        let msgs = engine
+            .run_once_with_inject(self.expected, self.timeout, msgs_to_inject)
+            .await
+            .with_context(|| format!("Failed to receive {} message(s) in {:?}", self.expected, self.timeout))?;
+        Ok(FlowTestOutput { msgs })
+    }
+
+    fn resolve_node_id(engine: &Engine, node: &str) -> crate::Result<ElementId> {
+        if let Some(id) = json::deser::parse_red_id_str(node) {
+            if engine.find_flow_node_by_id(&id).is_some() {
+                return Ok(id);
+            }
+        }
+        match engine.find_flow_node_by_name(node)? {
+            Some(found) => Ok(found.id()),
+            None => Err(EdgelinkError::BadArgument("node"))
+                .with_context(|| format!("Cannot found the flow node by ID or name: '{}'", node)),
+        }
+    }
+}
+
+impl FlowTestOutput {
+    pub fn msgs(&self) -> &[Msg] {
+        &self.msgs
+    }
+
+    pub fn len(&self) -> usize {
+        self.msgs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.msgs.is_empty()
+    }
+
+    /// Gets the message at the index, panics with the count of received messages if it was out of range.
+    pub fn msg(&self, index: usize) -> &Msg {
+        self.msgs.get(index).unwrap_or_else(|| {
+            panic!("FlowTester: no message #{}, only {} message(s) received", index, self.msgs.len())
+        })
+    }
+
+    pub fn assert_count(&self, expected: usize) -> &Self {
+        assert_eq!(
+            self.msgs.len(),
+            expected,
+            "FlowTester: expected {} message(s), but {} received: {:?}",
+            expected,
+            self.msgs.len(),
+            self.msgs
+        );
+        self
+    }
+
+    pub fn assert_payload_eq(&self, index: usize, expected: impl Into<Variant>) -> &Self {
+        self.assert_prop_eq(index, "payload", expected)
+    }
+
+    /// Asserts the property of the message specified by a property expression, e.g. `payload.items[0]`.
+    pub fn assert_prop_eq(&self, index: usize, prop: &str, expected: impl Into<Variant>) -> &Self {
+        let expected = expected.into();
+        let msg = self.msg(index);
+        match msg.get_nav_stripped(prop) {
+            Some(actual) if *actual == expected => self,
+            Some(actual) => panic!(
+                "FlowTester: message #{} property 'msg.{}' mismatched\n  expected: {:?}\n    actual: {:?}",
+                index, prop, expected, actual
+            ),
+            None => panic!(
+                "FlowTester: message #{} has no property 'msg.{}', expected: {:?}\n  message: {:?}",
+                index,
+                prop,
+                expected,
+                msg.as_variant()
+            ),
+        }
+    }
+
+    /// Asserts the property of the message by a predicate.
+    pub fn assert_prop_matches<F>(&self, index: usize, prop: &str, pred: F) -> &Self
+    where
+        F: FnOnce(&Variant) -> bool,
+    {
+        let msg = self.msg(index);
+        match msg.get_nav_stripped(prop) {
+            Some(actual) if pred(actual) => self,
+            Some(actual) => {
+                panic!("FlowTester: message #{} property 'msg.{}' does not match: {:?}", index, prop, actual)
+            }
+            None => panic!("FlowTester: message #{} has no property 'msg.{}': {:?}", index, prop, msg.as_variant()),
+        }
+    }
+
+    pub fn assert_has_prop(&self, index: usize, prop: &str) -> &Self {
+        self.assert_prop_matches(index, prop, |_| true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn make_simple_flows_json() -> serde_json::Value {
+        json!([
+            { "id": "100", "type": "tab", "label": "Flow 1" },
+            { "id": "1", "type": "inject", "z": "100", "name": "", "props": [
+                    { "p": "payload" },
+                    { "p": "topic", "vt": "str" }
+                ],
+                "once": true, "onceDelay": 0, "repeat": "", "topic": "greeting",
+                "payload": "foo", "payloadType": "str",
+                "wires": [ [ "2" ] ]
+            },
+            { "id": "2", "z": "100", "type": "test-once", "name": "sink" }
+        ])
+    }
+
+    #[tokio::test]
+    async fn test_flow_tester_should_collect_the_msgs() {
+        let output = FlowTester::new(make_simple_flows_json()).expect(1).run().await.unwrap();
+        output.assert_count(1).assert_payload_eq(0, "foo").assert_prop_eq(0, "topic", "greeting");
+    }
+
+    #[tokio::test]
+    async fn test_flow_tester_should_inject_msgs_by_node_name() {
+        let output = FlowTester::new(make_simple_flows_json())
+            .inject("sink", json!({"payload": "bar", "nested": {"value": 42}}))
+            .expect(2)
+            .run()
+            .await
+            .unwrap();
+        output.assert_count(2);
+        let bar_index = output.msgs().iter().position(|x| x["payload"] == Variant::from("bar")).unwrap();
+        output
+            .assert_prop_eq(bar_index, "msg.nested.value", 42)
+            .assert_prop_matches(bar_index, "nested", |x| x.is_object());
+    }
+
+    #[tokio::test]
+    async fn test_flow_tester_should_fail_with_unknown_node() {
+        let res = FlowTester::new(make_simple_flows_json()).inject("no-such-node", json!({"payload": 1})).run().await;
+        assert!(res.is_err());
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "property 'msg.payload' mismatched")]
+    async fn test_flow_tester_should_panic_on_mismatched_payload() {
+        let output = FlowTester::new(make_simple_flows_json()).run().await.unwrap();
+        output.assert_payload_eq(0, "bar");
+    }
+}
@@ -1,5 +1,5 @@
 use chrono::prelude::Utc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 pub fn unix_now() -> i64 {
     let now = SystemTime::now();
@@ -17,3 +17,35 @@ pub fn millis_now() -> String {
     let now = Utc::now();
     now.timestamp_millis().to_string()
 }
+
+/// The longest delay of the timers configured by the users, about 24.8 days like the `setTimeout()` of Node.js.
+pub const MAX_DELAY: Duration = Duration::from_millis(i32::MAX as u64);
+
+/// Converts the seconds given by the flows or the messages into a delay, the long ones are clamped to `MAX_DELAY`.
+///
+/// Returns `None` for the negative and the NaN seconds.
+pub fn delay_from_secs_f64(secs: f64) -> Option<Duration> {
+    if secs.is_nan() || secs < 0.0 {
+        None
+    } else if secs >= MAX_DELAY.as_secs_f64() {
+        Some(MAX_DELAY)
+    } else {
+        // `abs()` turns `-0.0` into `0.0`
+        Duration::try_from_secs_f64(secs.abs()).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay_from_secs_f64_should_clamp_and_reject_bad_values() {
+        assert_eq!(delay_from_secs_f64(1.5), Some(Duration::from_millis(1500)));
+        assert_eq!(delay_from_secs_f64(-0.0), Some(Duration::ZERO));
+        assert_eq!(delay_from_secs_f64(1e300), Some(MAX_DELAY));
+        assert_eq!(delay_from_secs_f64(f64::INFINITY), Some(MAX_DELAY));
+        assert_eq!(delay_from_secs_f64(-1.0), None);
+        assert_eq!(delay_from_secs_f64(f64::NAN), None);
+    }
+}
@@ -1,7 +1,7 @@
 extern crate proc_macro;
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{parse_macro_input, DeriveInput, Lit};
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Lit, LitStr};
 
 #[proc_macro_attribute]
 pub fn flow_node(attr: TokenStream, item: TokenStream) -> TokenStream {
@@ -145,3 +145,165 @@ pub fn global_node(attr: TokenStream, item: TokenStream) -> TokenStream {
     }; // quote!
     TokenStream::from(expanded)
 }
+
+/// Derives `RedNodeConfig` for the config struct of a node, the `RedNodeConfig` trait must be in scope.
+///
+/// The fields marked by `#[red_typed]` are the typed properties of Node-RED, their values are coerced according to the
+/// type property named with the `Type` suffix, e.g. `payloadType` of the `payload`, or `#[red_typed("otherType")]`.
+/// The property names respect `#[serde(rename = "...")]` and `#[serde(rename_all = "...")]`, the serde attributes
+/// changing the property names in other ways are compile errors.
+#[proc_macro_derive(RedNodeConfig, attributes(red_typed))]
+pub fn derive_red_node_config(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as DeriveInput);
+    let struct_name = &input.ident;
+    let struct_name_str = struct_name.to_string();
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return syn::Error::new_spanned(
+                    struct_name,
+                    "RedNodeConfig can only be derived for structs with named fields",
+                )
+                .to_compile_error()
+                .into()
+            }
+        },
+        _ => {
+            return syn::Error::new_spanned(struct_name, "RedNodeConfig can only be derived for structs")
+                .to_compile_error()
+                .into()
+        }
+    };
+
+    let rename_all = match parse_rename_all(&input.attrs) {
+        Ok(rename_all) => rename_all,
+        Err(e) => return e.to_compile_error().into(),
+    };
+
+    let mut typed_props = Vec::new();
+    for field in fields {
+        let typed_attr = match field.attrs.iter().find(|attr| attr.path().is_ident("red_typed")) {
+            Some(attr) => attr,
+            None => continue,
+        };
+        let name = field.ident.as_ref().unwrap().to_string().trim_start_matches("r#").to_string();
+        let mut prop = match &rename_all {
+            Some(rule) => rename_field(&rule.value(), &name).unwrap(),
+            None => name,
+        };
+        for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+            let parsed = attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    if let Some(name) = parse_rename(&meta)? {
+                        prop = name.value();
+                    }
+                } else if meta.path.is_ident("flatten")
+                    || meta.path.is_ident("skip")
+                    || meta.path.is_ident("skip_deserializing")
+                {
+                    return Err(meta.error("a `red_typed` field must be deserialized from its own property"));
+                } else if meta.input.peek(syn::Token![=]) {
+                    // e.g. `default = "..."` or `deserialize_with = "..."`
+                    meta.value()?.parse::<syn::Expr>()?;
+                } else if !meta.input.is_empty() && !meta.input.peek(syn::Token![,]) {
+                    return Err(meta.error("unsupported serde attribute of a `red_typed` field"));
+                }
+                Ok(())
+            });
+            if let Err(e) = parsed {
+                return e.to_compile_error().into();
+            }
+        }
+        let type_prop = match &typed_attr.meta {
+            syn::Meta::Path(_) => format!("{}Type", prop),
+            _ => match typed_attr.parse_args::<LitStr>() {
+                Ok(lit) => lit.value(),
+                Err(e) => return e.to_compile_error().into(),
+            },
+        };
+        typed_props.push(quote! { (#prop, #type_prop) });
+    }
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let expanded = quote! {
+        impl #impl_generics RedNodeConfig for #struct_name #ty_generics #where_clause {
+            const NAME: &'static str = #struct_name_str;
+            const TYPED_PROPERTIES: &'static [(&'static str, &'static str)] = &[#(#typed_props),*];
+        }
+    };
+    TokenStream::from(expanded)
+}
+
+/// Parses the `rename_all` rule of the container, e.g. `#[serde(rename_all = "camelCase")]`.
+fn parse_rename_all(attrs: &[syn::Attribute]) -> syn::Result<Option<LitStr>> {
+    let mut rename_all = None;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename_all") {
+                if let Some(rule) = parse_rename(&meta)? {
+                    if rename_field(&rule.value(), "a").is_none() {
+                        return Err(syn::Error::new_spanned(&rule, "unsupported `rename_all` rule"));
+                    }
+                    rename_all = Some(rule);
+                }
+            } else if meta.input.peek(syn::Token![=]) {
+                meta.value()?.parse::<syn::Expr>()?;
+            } else if meta.input.peek(syn::token::Paren) {
+                // e.g. `bound(...)`, the property names are not changed
+                let content;
+                syn::parenthesized!(content in meta.input);
+                content.parse::<proc_macro2::TokenStream>()?;
+            }
+            Ok(())
+        })?;
+    }
+    Ok(rename_all)
+}
+
+/// Parses the deserialized name of `rename = "..."` or `rename(deserialize = "...")`, and the same of `rename_all`.
+fn parse_rename(meta: &syn::meta::ParseNestedMeta) -> syn::Result<Option<LitStr>> {
+    if meta.input.peek(syn::Token![=]) {
+        return Ok(Some(meta.value()?.parse()?));
+    }
+    let mut name = None;
+    meta.parse_nested_meta(|inner| {
+        if inner.path.is_ident("deserialize") {
+            name = Some(inner.value()?.parse()?);
+        } else if inner.path.is_ident("serialize") {
+            inner.value()?.parse::<LitStr>()?;
+        } else {
+            return Err(inner.error("expected `serialize` or `deserialize`"));
+        }
+        Ok(())
+    })?;
+    Ok(name)
+}
+
+/// Renames the snake case field like the `rename_all` rule of serde, `None` if the rule is unknown.
+fn rename_field(rule: &str, field: &str) -> Option<String> {
+    let pascal = || {
+        field
+            .split('_')
+            .map(|word| {
+                let mut chars = word.chars();
+                chars.next().map(|c| c.to_ascii_uppercase().to_string() + chars.as_str()).unwrap_or_default()
+            })
+            .collect::<String>()
+    };
+    let renamed = match rule {
+        "lowercase" | "snake_case" => field.to_string(),
+        "UPPERCASE" | "SCREAMING_SNAKE_CASE" => field.to_ascii_uppercase(),
+        "PascalCase" => pascal(),
+        "camelCase" => {
+            let pascal = pascal();
+            let mut chars = pascal.chars();
+            chars.next().map(|c| c.to_ascii_lowercase().to_string() + chars.as_str()).unwrap_or_default()
+        }
+        "kebab-case" => field.replace('_', "-"),
+        "SCREAMING-KEBAB-CASE" => field.replace('_', "-").to_ascii_uppercase(),
+        _ => return None,
+    };
+    Some(renamed)
+}
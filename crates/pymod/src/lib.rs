@@ -1,4 +1,5 @@
-use edgelink_core::runtime::model::{ElementId, Msg};
+use edgelink_core::runtime::model::{ElementId, EncodedMsgs, Msg};
+use pyo3::types::{PyBytes, PyList, PyTuple};
 use pyo3::{prelude::*, wrap_pyfunction};
 use serde::Deserialize;
 
@@ -73,12 +74,19 @@ fn run_flows_once<'a>(
             .await
             .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("{}", e)))?;
 
-        let result_value = serde_json::to_value(&msgs)
+        // The format is selected by `runtime.engine.output_format` of the `app_cfg`
+        let encoded = engine
+            .encode_msgs(&msgs)
             .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("{}", e)))?;
 
-        Python::with_gil(|py| {
-            let pyo = json::json_value_to_py_object(py, &result_value)?;
-            Ok(pyo.to_object(py))
+        Python::with_gil(|py| match encoded {
+            EncodedMsgs::Json(result_value) => json::json_value_to_py_object(py, &result_value),
+            EncodedMsgs::MsgPack(buf) => Ok(PyBytes::new(py, &buf).to_object(py)),
+            EncodedMsgs::Attachments { msgs, attachments } => {
+                let pyo = json::json_value_to_py_object(py, &msgs)?;
+                let attachments = PyList::new(py, attachments.iter().map(|x| PyBytes::new(py, x)));
+                Ok(PyTuple::new(py, [pyo, attachments.to_object(py)]).to_object(py))
+            }
         })
     })
 }
@@ -34,6 +34,19 @@ pub struct CliArgs {
     #[arg(long, default_value_t = false)]
     pub stdin: bool,
 
+    /// Watch the 'flows.json' file and reload the changed flows without restarting.
+    #[arg(long, default_value_t = false, conflicts_with = "stdin")]
+    pub watch: bool,
+
+    /// Serve the Prometheus metrics at '/metrics' on the address, e.g. '127.0.0.1:9100'.
+    #[cfg(feature = "metrics")]
+    #[arg(long)]
+    pub metrics_addr: Option<std::net::SocketAddr>,
+
+    /// Skip the verification of the server certificates of the outgoing TLS connections, for testing only.
+    #[arg(long, default_value_t = false)]
+    pub tls_insecure: bool,
+
     /// Set the running environment in 'dev' or 'prod', default is `dev`
     #[arg(long)]
     pub env: Option<String>,
@@ -37,6 +37,7 @@ pub struct MsgInjectionEntry {
 struct App {
     _registry: RegistryHandle,
     engine: Engine,
+    elargs: Arc<CliArgs>,
     msgs_to_inject: Mutex<Vec<MsgInjectionEntry>>,
 }
 
@@ -102,7 +103,12 @@ impl App {
             Engine::with_flows_file(&reg, &elargs.flows_path, app_config)?
         };
 
-        Ok(App { _registry: reg, engine, msgs_to_inject: Mutex::new(msgs_to_inject) })
+        Ok(App {
+            _registry: reg,
+            engine,
+            elargs,
+            msgs_to_inject: Mutex::new(msgs_to_inject),
+        })
     }
 
     async fn main_flow_task(self: Arc<Self>, cancel: CancellationToken) -> crate::Result<()> {
@@ -139,11 +145,69 @@ impl App {
         Ok(())
     }
 
+    /// Polls the modified time of the flows file, and reloads the changed flows into the running engine.
+    async fn watch_task(self: Arc<Self>, cancel: CancellationToken) -> crate::Result<()> {
+        let flows_path = self.elargs.flows_path.as_str();
+        log::info!("Watching the flows file: {}", flows_path);
+        let mut last_modified = tokio::fs::metadata(flows_path).await?.modified()?;
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(tokio::time::Duration::from_secs(1)) => {
+                }
+                _ = cancel.cancelled() => {
+                    log::info!("Cancelling the watch task...");
+                    break;
+                }
+            }
+
+            let modified = match tokio::fs::metadata(flows_path).await.and_then(|x| x.modified()) {
+                Ok(modified) => modified,
+                Err(err) => {
+                    // The file may be replaced by the editor at the moment
+                    log::debug!("Failed to get the metadata of the flows file: {}", err);
+                    continue;
+                }
+            };
+            if modified == last_modified {
+                continue;
+            }
+            last_modified = modified;
+
+            log::info!("The flows file has been changed, reloading...");
+            let json = match tokio::fs::read_to_string(flows_path).await {
+                Ok(text) => serde_json::from_str::<serde_json::Value>(&text).map_err(anyhow::Error::from),
+                Err(err) => Err(err.into()),
+            };
+            let result = match json {
+                Ok(json) => self.engine.reload_flows(json).await,
+                Err(err) => Err(err),
+            };
+            if let Err(err) = result {
+                log::error!("Failed to reload the flows file '{}': {}", flows_path, err);
+            }
+        }
+        Ok(())
+    }
+
     pub async fn run(self: Arc<Self>, cancel: CancellationToken) -> crate::Result<()> {
-        let (res1, res2) = tokio::join!(
-            self.clone().main_flow_task(cancel.child_token()),
-            self.clone().idle_task(cancel.child_token())
-        );
+        #[cfg(feature = "metrics")]
+        if let Some(addr) = self.elargs.metrics_addr {
+            let listener = tokio::net::TcpListener::bind(addr).await?;
+            let (engine, metrics_cancel) = (self.engine.clone(), cancel.child_token());
+            tokio::spawn(async move {
+                if let Err(err) = runtime::metrics::serve(listener, engine, metrics_cancel).await {
+                    log::error!("The metrics endpoint stopped: {}", err);
+                }
+            });
+        }
+
+        let (res1, res2) = tokio::join!(self.clone().main_flow_task(cancel.child_token()), async {
+            if self.elargs.watch {
+                self.clone().watch_task(cancel.child_token()).await
+            } else {
+                self.clone().idle_task(cancel.child_token()).await
+            }
+        });
         res1?;
         res2?;
         Ok(())
@@ -182,6 +246,9 @@ fn load_config(cli_args: &CliArgs) -> anyhow::Result<Option<config::Config>> {
             builder = builder
                 .set_override("run_env", run_env)? // override run_env
                 .set_override("node.msg_queue_capacity", 1)?;
+            if cli_args.tls_insecure {
+                builder = builder.set_override("runtime.engine.tls_insecure", true)?;
+            }
             let config = builder.build()?;
             return Ok(Some(config));
         }
@@ -189,6 +256,15 @@ fn load_config(cli_args: &CliArgs) -> anyhow::Result<Option<config::Config>> {
     if cli_args.verbose > 0 {
         eprintln!("The `$EDGELINK_HOME` directory does not exist!");
     }
+    if cli_args.tls_insecure {
+        // The options of the command line still apply without the configuration files
+        let config = config::Config::builder()
+            .set_override("runtime.context.default", "memory")?
+            .set_override("runtime.context.stores.memory.provider", "memory")?
+            .set_override("runtime.engine.tls_insecure", true)?
+            .build()?;
+        return Ok(Some(config));
+    }
     Ok(None)
 }
 
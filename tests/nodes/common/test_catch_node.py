@@ -27,3 +27,37 @@ class TestCatchNode:
         assert msgs[0]["error"]["source"]["type"] == "function"
         assert msgs[0]["error"]["source"]["name"] == "func1"
 
+
+    @pytest.mark.asyncio
+    @pytest.mark.it('should count the catches of the same message')
+    async def test_it_should_count_the_catches_of_the_same_message(self):
+        flows = [
+            {"id": "100", "type": "tab"},  # flow 1
+            {"id": "1", "z": "100", "type": "function", "name":"func1", "func": 'throw new Error("big error");', "wires": []},
+            {"id": "2", "z": "100", "type": "catch", "scope": ["1"], "wires": [["1", "3"]]},
+            {"id": "3", "z": "100", "type": "test-once"},
+        ]
+        injections = [
+            {"nid": "1", "msg": {"payload": "foo"}}
+        ]
+        msgs = await run_flow_with_msgs_ntimes(flows, injections, 2)
+        assert [m["payload"] for m in msgs] == ["foo", "foo"]
+        assert sorted(m["error"]["source"]["count"] for m in msgs) == [1, 2]
+
+    @pytest.mark.asyncio
+    @pytest.mark.it('should drop the original message if keepPayload is false')
+    async def test_it_should_drop_the_original_message_if_keep_payload_is_false(self):
+        flows = [
+            {"id": "100", "type": "tab"},  # flow 1
+            {"id": "1", "z": "100", "type": "function", "name":"func1", "func": 'throw new Error("big error");', "wires": []},
+            {"id": "2", "z": "100", "type": "catch", "keepPayload": False, "wires": [["3"]]},
+            {"id": "3", "z": "100", "type": "test-once"},
+        ]
+        injections = [
+            {"nid": "1", "msg": {"payload": "foo", "topic": "bar"}}
+        ]
+        msgs = await run_flow_with_msgs_ntimes(flows, injections, 1)
+        assert "payload" not in msgs[0]
+        assert "topic" not in msgs[0]
+        assert "_msgid" in msgs[0]
+        assert msgs[0]["error"]["source"]["count"] == 1
@@ -1609,3 +1609,89 @@ class TestChangeNode:
             msgs = await run_flow_with_msgs_ntimes(flows, injections, 1)
             assert msgs[0]["val0"] == "foo"
             assert msgs[0]["val1"] == "bar"
+
+    @pytest.mark.describe('#inc/#dec')
+    class TestIncDec:
+
+        @pytest.mark.asyncio
+        @pytest.mark.it('increments and decrements a message property')
+        async def test_inc_dec_1(self):
+            flows = [
+                {"id": "100", "type": "tab"},  # flow 1
+                {"id": "1", "type": "change", "z": "100", "rules": [
+                    {"t": "inc", "p": "payload", "pt": "msg", "to": "5", "tot": "num"},
+                    {"t": "dec", "p": "count", "pt": "msg", "to": "1.5", "tot": "str"},
+                    {"t": "inc", "p": "missing", "pt": "msg", "to": "3", "tot": "num"}
+                ], "name": "changeNode", "wires": [["2"]]},
+                {"id": "2", "z": "100", "type": "test-once"}
+            ]
+            injections = [
+                {"nid": "1", "msg": {"payload": 10, "count": "4"}},
+            ]
+            msgs = await run_flow_with_msgs_ntimes(flows, injections, 1)
+            assert msgs[0]["payload"] == 15
+            assert msgs[0]["count"] == 2.5
+            assert msgs[0]["missing"] == 3
+
+        @pytest.mark.asyncio
+        @pytest.mark.it('increments a flow context property')
+        async def test_inc_dec_2(self):
+            flows = [
+                {"id": "100", "type": "tab"},  # flow 1
+                {"id": "1", "type": "change", "z": "100", "rules": [
+                    {"t": "inc", "p": "counter", "pt": "flow", "tot": "num"}
+                ], "name": "changeNode", "wires": [["2"]]},
+                {"id": "2", "type": "change", "z": "100", "rules": [
+                    {"t": "set", "p": "payload", "pt": "msg", "to": "counter", "tot": "flow"}
+                ], "name": "changeNode", "wires": [["3"]]},
+                {"id": "3", "z": "100", "type": "test-once"}
+            ]
+            injections = [
+                {"nid": "1", "msg": {"payload": ""}},
+                {"nid": "1", "msg": {"payload": ""}},
+            ]
+            msgs = await run_flow_with_msgs_ntimes(flows, injections, 2)
+            assert sorted(m["payload"] for m in msgs) == [1, 2]
+
+    @pytest.mark.describe('#sequential rules')
+    class TestSequentialRules:
+
+        @pytest.mark.asyncio
+        @pytest.mark.it('applies a rule to the result of the previous rule')
+        async def test_sequential_rules_1(self):
+            flows = [
+                {"id": "100", "type": "tab"},  # flow 1
+                {"id": "1", "type": "change", "z": "100", "rules": [
+                    {"t": "set", "p": "payload", "pt": "msg", "to": "hello world", "tot": "str"},
+                    {"t": "change", "p": "payload", "pt": "msg", "from": "world", "fromt": "str",
+                        "to": "edgelink", "tot": "str"},
+                    {"t": "set", "p": "copy", "pt": "msg", "to": "payload", "tot": "msg"}
+                ], "name": "changeNode", "wires": [["2"]]},
+                {"id": "2", "z": "100", "type": "test-once"}
+            ]
+            injections = [
+                {"nid": "1", "msg": {"payload": "changeMe"}},
+            ]
+            msgs = await run_flow_with_msgs_ntimes(flows, injections, 1)
+            assert msgs[0]["payload"] == "hello edgelink"
+            assert msgs[0]["copy"] == "hello edgelink"
+
+        @pytest.mark.asyncio
+        @pytest.mark.it('sees the context value written by the previous rule')
+        async def test_sequential_rules_2(self):
+            flows = [
+                {"id": "100", "type": "tab"},  # flow 1
+                {"id": "1", "type": "change", "z": "100", "rules": [
+                    {"t": "set", "p": "lastPayload", "pt": "flow", "to": "payload", "tot": "msg"},
+                    {"t": "set", "p": "fromFlow", "pt": "msg", "to": "lastPayload", "tot": "flow"},
+                    {"t": "move", "p": "lastPayload", "pt": "flow", "to": "fromGlobal", "tot": "global"},
+                    {"t": "set", "p": "moved", "pt": "msg", "to": "fromGlobal", "tot": "global"}
+                ], "name": "changeNode", "wires": [["2"]]},
+                {"id": "2", "z": "100", "type": "test-once"}
+            ]
+            injections = [
+                {"nid": "1", "msg": {"payload": "foo"}},
+            ]
+            msgs = await run_flow_with_msgs_ntimes(flows, injections, 1)
+            assert msgs[0]["fromFlow"] == "foo"
+            assert msgs[0]["moved"] == "foo"
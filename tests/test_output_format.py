@@ -0,0 +1,53 @@
+import copy
+import pytest
+import msgpack
+
+from tests import *
+
+
+def _config_with_output_format(output_format: str) -> dict:
+    cfg = copy.deepcopy(TEST_EDGELINLKD_CONFIG)
+    cfg["runtime"]["engine"] = {"output_format": output_format}
+    return cfg
+
+
+_BINARY_FLOWS = [
+    {"id": "0", "type": "tab"},
+    {"id": "1", "z": "0", "type": "function", "wires": [["2"]],
+     "func": "msg.payload = new Uint8Array([0, 1, 254, 255]); msg.nested = [new Uint8Array([7])]; return msg;"},
+    {"id": "2", "z": "0", "type": "test-once"},
+]
+
+
+@pytest.mark.describe('output format of the Python module')
+class TestOutputFormat:
+
+    @pytest.mark.asyncio
+    @pytest.mark.it('should keep buffers as bytes in msgpack')
+    async def test_msgpack_should_keep_buffers(self):
+        encoded = await edgelink.run_flows_once(1, 0.5, _BINARY_FLOWS, [("1", {"topic": "bin"})],
+                                                _config_with_output_format("msgpack"))
+        assert isinstance(encoded, bytes)
+        msgs = msgpack.unpackb(encoded, raw=False)
+        assert len(msgs) == 1
+        assert msgs[0]['payload'] == bytes([0, 1, 254, 255])
+        assert msgs[0]['nested'] == [bytes([7])]
+        assert msgs[0]['topic'] == 'bin'
+        assert isinstance(msgs[0]['_msgid'], str) and len(msgs[0]['_msgid']) == 16
+
+    @pytest.mark.asyncio
+    @pytest.mark.it('should move buffers to the attachments')
+    async def test_attachments_should_replace_buffers(self):
+        msgs, attachments = await edgelink.run_flows_once(1, 0.5, _BINARY_FLOWS, [("1", {"topic": "bin"})],
+                                                          _config_with_output_format("attachments"))
+        payload = msgs[0]['payload']
+        assert payload['type'] == 'Buffer'
+        assert attachments[payload['attachment']] == bytes([0, 1, 254, 255])
+        assert attachments[msgs[0]['nested'][0]['attachment']] == bytes([7])
+
+    @pytest.mark.asyncio
+    @pytest.mark.it('should return arrays of numbers in json by default')
+    async def test_json_should_be_the_default(self):
+        msgs = await edgelink.run_flows_once(1, 0.5, _BINARY_FLOWS, [("1", {"topic": "bin"})],
+                                             TEST_EDGELINLKD_CONFIG)
+        assert msgs[0]['payload'] == [0, 1, 254, 255]